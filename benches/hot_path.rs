@@ -0,0 +1,187 @@
+//! Benchmarks of the per-event hot path
+//!
+//! Run with `cargo bench`. To guard against regressions, save a baseline
+//! before a change and compare against it afterwards:
+//!
+//! ```text
+//! cargo bench -- --save-baseline before
+//! cargo bench -- --baseline before
+//! ```
+//!
+//! Criterion reports each benchmark's change against the baseline and
+//! flags those outside its noise threshold. `scripts/bench_compare.sh`
+//! does both runs against a git ref and fails on any regression, for CI.
+//!
+//! `detect/event_path` follows the daemon's `process_event`: trace,
+//! enrich, detect with state in a store, and store each report.
+//!
+//! The GeoIP lookup benchmark needs a GeoLite2-City database; point
+//! `ODIN_BENCH_GEOIP_DB` at one (it is skipped otherwise).
+
+use std::hint::black_box;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use odin::config::{Config, LineFormat};
+use odin::detection::{DetectionEngine, GeoVelocityTracker, GeoLocation, IdentityContext, LoginRateLimiter};
+use odin::geolocation::GeoIpService;
+use odin::input::{LineParser, LogParser};
+use odin::persistence::{SqliteStateStore, StateStore, StoreHealth};
+use odin::processing::EnrichmentPipeline;
+use odin::telemetry::EventTracer;
+use odin::LogEvent;
+
+/// Users and source addresses the synthetic events are spread over
+const USERS: usize = 1000;
+const ADDRESSES: u32 = 5000;
+
+const SYSLOG_LINE: &str =
+    "<38>Jan 15 10:30:00 web-01 sshd[1234]: Accepted publickey for alice from 203.0.113.5 port 52234 ssh2";
+const FAILED_LINE: &str =
+    "Jan 15 10:30:01 web-01 sshd[1235]: Failed password for invalid user admin from 198.51.100.7 port 40022 ssh2";
+const JSON_LINE: &str =
+    r#"{"timestamp":"2024-01-15T10:30:00Z","user":"alice","ip":"203.0.113.5","event_type":"SSH_LOGIN"}"#;
+
+/// The `i`th synthetic event: users log in from a handful of addresses
+/// each, with every tenth event a failure
+fn synthetic_event(i: usize) -> LogEvent {
+    let user = i % USERS;
+    let address = (user as u32 * 7 + (i / USERS) as u32 % 3) % ADDRESSES;
+    LogEvent::new(
+        1_700_000_000 + i as i64,
+        format!("user{}", user),
+        IpAddr::V4(Ipv4Addr::from(0xC633_6400 + address)),
+        if i.is_multiple_of(10) { "SSH_FAILED" } else { "SSH_LOGIN" },
+    )
+}
+
+fn synthetic_events(count: usize) -> Vec<LogEvent> {
+    (0..count).map(synthetic_event).collect()
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Elements(1));
+    let text = LineParser::default();
+    let json = LineParser::new(LineFormat::Json);
+    group.bench_function("syslog_login", |b| b.iter(|| text.parse(black_box(SYSLOG_LINE)).unwrap()));
+    group.bench_function("syslog_failure", |b| b.iter(|| text.parse(black_box(FAILED_LINE)).unwrap()));
+    group.bench_function("json", |b| b.iter(|| json.parse(black_box(JSON_LINE)).unwrap()));
+    group.finish();
+}
+
+fn bench_detect(c: &mut Criterion) {
+    let events = synthetic_events(10_000);
+    let mut group = c.benchmark_group("detect");
+    group.throughput(Throughput::Elements(events.len() as u64));
+
+    // Every default rule, starting from empty state each iteration
+    let mut config = Config::default().detection;
+    config.enable_attacking_ip = true;
+    group.bench_function("engine", |b| {
+        b.iter_batched(
+            || DetectionEngine::new(&config).unwrap(),
+            |mut engine| {
+                for event in &events {
+                    black_box(engine.evaluate(event));
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    // The daemon's per-event path, with every rule's state in a store
+    let tracer = EventTracer::disabled();
+    let enrichment = EnrichmentPipeline::new();
+    group.bench_function("event_path", |b| {
+        b.iter_batched(
+            || {
+                let store: Arc<dyn StateStore> = Arc::new(SqliteStateStore::in_memory().unwrap());
+                let engine = DetectionEngine::new(&config)
+                    .unwrap()
+                    .with_persistence(store.clone(), Arc::new(StoreHealth::new()));
+                (engine, store)
+            },
+            |(mut engine, store)| {
+                for event in &events {
+                    let trace = tracer.trace_event(event);
+                    let enriched = enrichment.run_traced(event.clone(), &trace);
+                    for report in engine.detect(&enriched, &trace, true).reports {
+                        store.store_anomaly_report(&report).unwrap();
+                    }
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    // IP switch and impossible travel backed by an in-memory store
+    group.bench_function("ip_switch_and_travel_with_store", |b| {
+        b.iter_batched(
+            || {
+                let store: Arc<dyn StateStore> = Arc::new(SqliteStateStore::in_memory().unwrap());
+                (
+                    IdentityContext::with_persistence(store.clone()),
+                    GeoVelocityTracker::with_persistence(900.0, store),
+                )
+            },
+            |(mut context, mut tracker)| {
+                for (i, event) in events.iter().enumerate() {
+                    let location = GeoLocation {
+                        latitude: (i % 180) as f64 - 90.0,
+                        longitude: (i % 360) as f64 - 180.0,
+                    };
+                    black_box(context.check_for_ip_switch(event));
+                    black_box(tracker.check_impossible_travel(event, location));
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn bench_rate_limit(c: &mut Criterion) {
+    let events = synthetic_events(50_000);
+    let mut group = c.benchmark_group("rate_limit");
+    group.throughput(Throughput::Elements(events.len() as u64));
+    group.bench_function("check", |b| {
+        b.iter_batched(
+            || LoginRateLimiter::with_config(300, 10, 20),
+            |mut limiter| {
+                for event in &events {
+                    black_box(limiter.check_rate_limit(event));
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn bench_lookup(c: &mut Criterion) {
+    let Some(path) = std::env::var_os("ODIN_BENCH_GEOIP_DB") else {
+        eprintln!("Skipping geo lookup benchmark: ODIN_BENCH_GEOIP_DB is not set");
+        return;
+    };
+    let service = GeoIpService::new(&path).expect("ODIN_BENCH_GEOIP_DB is a GeoIP database");
+    let addresses: Vec<IpAddr> = ["8.8.8.8", "1.1.1.1", "81.2.69.142", "2001:4860:4860::8888"]
+        .iter()
+        .map(|ip| ip.parse().unwrap())
+        .collect();
+
+    let mut group = c.benchmark_group("geo");
+    group.throughput(Throughput::Elements(addresses.len() as u64));
+    group.bench_function("lookup", |b| {
+        b.iter(|| {
+            for ip in &addresses {
+                black_box(service.lookup_geo(black_box(ip)));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse, bench_detect, bench_rate_limit, bench_lookup);
+criterion_main!(benches);
@@ -0,0 +1,41 @@
+#!/usr/bin/env bash
+# Compare the hot path benchmarks against a git ref and fail on regressions
+#
+# Usage: scripts/bench_compare.sh [BASE_REF] [NOISE_THRESHOLD]
+#
+# Benchmarks BASE_REF (default origin/main) in a temporary worktree, saving
+# a criterion baseline, then benchmarks the working tree against it.
+# Exits non-zero if criterion reports any benchmark as regressed beyond
+# NOISE_THRESHOLD (default 0.05, i.e. 5%).
+set -euo pipefail
+
+base_ref="${1:-origin/main}"
+noise_threshold="${2:-0.05}"
+
+root="$(git rev-parse --show-toplevel)"
+cd "$root"
+
+# Both runs share one target directory so the baseline is found
+export CARGO_TARGET_DIR="${CARGO_TARGET_DIR:-$root/target}"
+
+worktree="$(mktemp -d)"
+cleanup() {
+    git worktree remove --force "$worktree" >/dev/null 2>&1 || true
+}
+trap cleanup EXIT
+
+git worktree add --detach "$worktree" "$base_ref" >/dev/null
+echo "Benchmarking $base_ref ($(git rev-parse --short "$base_ref"))"
+(cd "$worktree" && cargo bench --bench hot_path -- --save-baseline base --noplot)
+
+echo "Benchmarking the working tree against $base_ref"
+log="$(mktemp)"
+cargo bench --bench hot_path -- --baseline base --noise-threshold "$noise_threshold" --noplot | tee "$log"
+
+if grep -q "Performance has regressed" "$log"; then
+    echo
+    echo "Regressed benchmarks:"
+    grep -B 6 "Performance has regressed" "$log" | grep -E "^[a-z_]+/[a-z_]+" || true
+    exit 1
+fi
+echo "No benchmark regressed beyond ${noise_threshold} against $base_ref"
@@ -0,0 +1,309 @@
+//! Per-channel circuit breaker for alert dispatch
+//!
+//! After a number of consecutive failures a channel's circuit opens and
+//! the channel is skipped for a cooldown period. Once the cooldown has
+//! elapsed a single trial request is let through (half-open); success
+//! closes the circuit again, failure re-opens it. Other requests are
+//! skipped while the trial is in flight, so a recovering endpoint isn't
+//! hit with the whole backlog at once.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Current state of a circuit breaker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests flow normally
+    Closed,
+    /// Requests are skipped until the cooldown elapses
+    Open,
+    /// A trial request is allowed to test recovery
+    HalfOpen,
+}
+
+impl std::fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CircuitState::Closed => write!(f, "closed"),
+            CircuitState::Open => write!(f, "open"),
+            CircuitState::HalfOpen => write!(f, "half-open"),
+        }
+    }
+}
+
+/// Circuit breaker for a single alert channel
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    reset_timeout: Duration,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    /// When the half-open trial request was let through
+    probe_started: Option<Instant>,
+    state: CircuitState,
+}
+
+impl CircuitBreaker {
+    /// Create a breaker that opens after `failure_threshold` consecutive
+    /// failures and stays open for `reset_timeout`
+    ///
+    /// A threshold of zero disables the breaker.
+    pub fn new(failure_threshold: u32, reset_timeout: Duration) -> Self {
+        CircuitBreaker {
+            failure_threshold,
+            reset_timeout,
+            consecutive_failures: 0,
+            opened_at: None,
+            probe_started: None,
+            state: CircuitState::Closed,
+        }
+    }
+
+    /// Check whether a request may be sent now
+    ///
+    /// Moves an open circuit to half-open once the cooldown has elapsed.
+    /// While half-open only one trial request is let through at a time; if
+    /// its outcome isn't recorded within another cooldown it is taken as
+    /// lost and a new trial is allowed.
+    pub fn allow_request(&mut self, now: Instant) -> bool {
+        match self.state {
+            CircuitState::Closed => true,
+            CircuitState::HalfOpen => {
+                let probing = self
+                    .probe_started
+                    .is_some_and(|started| now.saturating_duration_since(started) < self.reset_timeout);
+                if probing {
+                    return false;
+                }
+                self.probe_started = Some(now);
+                true
+            }
+            CircuitState::Open => {
+                let elapsed = self
+                    .opened_at
+                    .map(|opened| now.saturating_duration_since(opened))
+                    .unwrap_or_default();
+                if elapsed >= self.reset_timeout {
+                    self.state = CircuitState::HalfOpen;
+                    self.probe_started = Some(now);
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    /// Record a successful request, closing the circuit
+    pub fn record_success(&mut self) {
+        self.consecutive_failures = 0;
+        self.opened_at = None;
+        self.probe_started = None;
+        self.state = CircuitState::Closed;
+    }
+
+    /// Record a failed request
+    ///
+    /// Returns true if this failure opened the circuit.
+    pub fn record_failure(&mut self, now: Instant) -> bool {
+        if self.failure_threshold == 0 {
+            return false;
+        }
+
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        let should_open = match self.state {
+            CircuitState::HalfOpen => true,
+            CircuitState::Closed => self.consecutive_failures >= self.failure_threshold,
+            CircuitState::Open => false,
+        };
+
+        if should_open {
+            self.state = CircuitState::Open;
+            self.opened_at = Some(now);
+            self.probe_started = None;
+        }
+        should_open
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.state
+    }
+
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures
+    }
+}
+
+/// Shared registry of circuit breakers keyed by channel name
+///
+/// Cloning the registry shares the same breakers, so a clone can be kept
+/// for health reporting after the dispatcher has been moved into its task.
+#[derive(Debug, Clone)]
+pub struct CircuitBreakers {
+    breakers: Arc<Mutex<HashMap<String, CircuitBreaker>>>,
+    failure_threshold: u32,
+    reset_timeout: Duration,
+}
+
+impl CircuitBreakers {
+    pub fn new(failure_threshold: u32, reset_timeout: Duration) -> Self {
+        CircuitBreakers {
+            breakers: Arc::new(Mutex::new(HashMap::new())),
+            failure_threshold,
+            reset_timeout,
+        }
+    }
+
+    /// Check whether the channel may be used now
+    pub fn allow_request(&self, channel: &str) -> bool {
+        let mut breakers = self.breakers.lock().unwrap();
+        breakers
+            .entry(channel.to_string())
+            .or_insert_with(|| CircuitBreaker::new(self.failure_threshold, self.reset_timeout))
+            .allow_request(Instant::now())
+    }
+
+    pub fn record_success(&self, channel: &str) {
+        let mut breakers = self.breakers.lock().unwrap();
+        if let Some(breaker) = breakers.get_mut(channel) {
+            if breaker.state() != CircuitState::Closed {
+                log::info!("Alert channel {} recovered, closing circuit", channel);
+            }
+            breaker.record_success();
+        }
+    }
+
+    pub fn record_failure(&self, channel: &str) {
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers
+            .entry(channel.to_string())
+            .or_insert_with(|| CircuitBreaker::new(self.failure_threshold, self.reset_timeout));
+        if breaker.record_failure(Instant::now()) {
+            log::warn!(
+                "Alert channel {} failed {} time(s) in a row, skipping it for {:?}",
+                channel,
+                breaker.consecutive_failures(),
+                self.reset_timeout
+            );
+        }
+    }
+
+    /// Snapshot of every channel's circuit state, sorted by channel name
+    pub fn states(&self) -> Vec<(String, CircuitState)> {
+        let breakers = self.breakers.lock().unwrap();
+        let mut states: Vec<_> = breakers
+            .iter()
+            .map(|(channel, breaker)| (channel.clone(), breaker.state()))
+            .collect();
+        states.sort_by(|a, b| a.0.cmp(&b.0));
+        states
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opens_after_threshold() {
+        let mut breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+        let now = Instant::now();
+
+        assert!(!breaker.record_failure(now));
+        assert!(!breaker.record_failure(now));
+        assert!(breaker.allow_request(now));
+        assert!(breaker.record_failure(now));
+
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.allow_request(now + Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_half_open_then_recovery() {
+        let mut breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+        let now = Instant::now();
+        breaker.record_failure(now);
+        breaker.record_failure(now);
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        // Cooldown elapsed: one trial request is allowed
+        let later = now + Duration::from_secs(61);
+        assert!(breaker.allow_request(later));
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+
+        breaker.record_success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_eq!(breaker.consecutive_failures(), 0);
+    }
+
+    #[test]
+    fn test_half_open_allows_one_probe_at_a_time() {
+        let mut breaker = CircuitBreaker::new(1, Duration::from_secs(60));
+        let now = Instant::now();
+        breaker.record_failure(now);
+
+        let later = now + Duration::from_secs(61);
+        assert!(breaker.allow_request(later));
+        assert!(!breaker.allow_request(later));
+        assert!(!breaker.allow_request(later + Duration::from_secs(30)));
+
+        // A probe whose outcome never arrives is given up on after a cooldown
+        assert!(breaker.allow_request(later + Duration::from_secs(60)));
+        breaker.record_success();
+        assert!(breaker.allow_request(later + Duration::from_secs(61)));
+        assert!(breaker.allow_request(later + Duration::from_secs(61)));
+    }
+
+    #[test]
+    fn test_half_open_failure_reopens() {
+        let mut breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+        let now = Instant::now();
+        breaker.record_failure(now);
+        breaker.record_failure(now);
+
+        let later = now + Duration::from_secs(61);
+        assert!(breaker.allow_request(later));
+        assert!(breaker.record_failure(later));
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.allow_request(later + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_success_resets_failure_count() {
+        let mut breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+        let now = Instant::now();
+        breaker.record_failure(now);
+        breaker.record_success();
+        breaker.record_failure(now);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_zero_threshold_disables() {
+        let mut breaker = CircuitBreaker::new(0, Duration::from_secs(60));
+        let now = Instant::now();
+        for _ in 0..10 {
+            breaker.record_failure(now);
+        }
+        assert!(breaker.allow_request(now));
+    }
+
+    #[test]
+    fn test_registry_states() {
+        let breakers = CircuitBreakers::new(1, Duration::from_secs(60));
+        assert!(breakers.allow_request("slack"));
+        breakers.record_failure("slack");
+        breakers.record_success("discord");
+
+        assert!(!breakers.allow_request("slack"));
+        assert!(breakers.allow_request("discord"));
+        assert_eq!(
+            breakers.states(),
+            vec![
+                ("discord".to_string(), CircuitState::Closed),
+                ("slack".to_string(), CircuitState::Open),
+            ]
+        );
+    }
+}
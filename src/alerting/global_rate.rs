@@ -0,0 +1,148 @@
+//! Global alert rate limit
+//!
+//! A broad attack against many users and IPs produces many distinct
+//! alerts, each legitimate on its own, that together bury whoever is on
+//! call. [`GlobalAlertLimit`] caps the alerts dispatched in any sliding
+//! minute across all keys. Alerts over the cap are counted per rule and
+//! later sent as a single "N additional anomalies suppressed" summary.
+
+use crate::models::AnomalyReport;
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Window the cap applies to
+pub const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Rule name of suppression summaries
+pub const SUMMARY_RULE_NAME: &str = "Alerts Suppressed";
+
+/// Sliding-minute cap on dispatched alerts
+#[derive(Debug)]
+pub struct GlobalAlertLimit {
+    max_per_minute: u32,
+    /// Dispatch times within the last window, oldest first
+    sent: VecDeque<Instant>,
+    /// Suppressed alerts per rule since the last summary
+    suppressed: BTreeMap<String, u64>,
+    /// Highest severity among the suppressed alerts
+    max_severity: u8,
+}
+
+impl GlobalAlertLimit {
+    pub fn new(max_per_minute: u32) -> Self {
+        GlobalAlertLimit {
+            max_per_minute,
+            sent: VecDeque::new(),
+            suppressed: BTreeMap::new(),
+            max_severity: 0,
+        }
+    }
+
+    /// Admit an alert at `now`, or count it as suppressed
+    pub fn admit(&mut self, report: &AnomalyReport, now: Instant) -> bool {
+        while self
+            .sent
+            .front()
+            .is_some_and(|sent| now.saturating_duration_since(*sent) >= RATE_WINDOW)
+        {
+            self.sent.pop_front();
+        }
+        if self.sent.len() < self.max_per_minute as usize {
+            self.sent.push_back(now);
+            return true;
+        }
+        *self.suppressed.entry(report.rule_name.clone()).or_insert(0) += 1;
+        self.max_severity = self.max_severity.max(report.severity);
+        false
+    }
+
+    /// Alerts suppressed since the last summary
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed.values().sum()
+    }
+
+    /// Build a summary of the suppressed alerts and reset the count
+    ///
+    /// Returns None when nothing was suppressed.
+    pub fn take_summary(&mut self) -> Option<AnomalyReport> {
+        let total = self.suppressed();
+        if total == 0 {
+            return None;
+        }
+        let by_rule: Vec<String> = self
+            .suppressed
+            .iter()
+            .map(|(rule, count)| format!("{} ({})", rule, count))
+            .collect();
+        let now = chrono::Utc::now().timestamp();
+        let summary = AnomalyReport::new(
+            self.max_severity,
+            SUMMARY_RULE_NAME,
+            "",
+            now,
+            format!(
+                "{} additional anomalies suppressed by the global alert limit of {}/min: {}",
+                total,
+                self.max_per_minute,
+                by_rule.join(", ")
+            ),
+        )
+        .with_detected_at(now)
+        .with_metadata("suppressed_count", total.to_string());
+        self.suppressed.clear();
+        self.max_severity = 0;
+        Some(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(rule_name: &str, severity: u8) -> AnomalyReport {
+        AnomalyReport {
+            severity,
+            rule_name: rule_name.to_string(),
+            user: "alice".to_string(),
+            detected_ip: "203.0.113.5".to_string(),
+            trusted_ip: String::new(),
+            timestamp: 0,
+            detected_at: 0,
+            description: String::new(),
+            metadata: BTreeMap::new(),
+            confidence: None,
+        }
+    }
+
+    #[test]
+    fn test_window_slides() {
+        let mut limit = GlobalAlertLimit::new(2);
+        let start = Instant::now();
+        assert!(limit.admit(&report("IP Switch", 8), start));
+        assert!(limit.admit(&report("IP Switch", 8), start + Duration::from_secs(30)));
+        assert!(!limit.admit(&report("IP Switch", 8), start + Duration::from_secs(59)));
+
+        // The first alert has left the window
+        assert!(limit.admit(&report("IP Switch", 8), start + Duration::from_secs(60)));
+
+        let summary = limit.take_summary().unwrap();
+        assert_eq!(summary.metadata["suppressed_count"], "1");
+        assert!(limit.take_summary().is_none());
+    }
+
+    #[test]
+    fn test_summary_counts_by_rule() {
+        let mut limit = GlobalAlertLimit::new(1);
+        let now = Instant::now();
+        limit.admit(&report("IP Switch", 8), now);
+        limit.admit(&report("IP Switch", 8), now);
+        limit.admit(&report("Rate Limit Exceeded", 9), now);
+        limit.admit(&report("IP Switch", 7), now);
+
+        let summary = limit.take_summary().unwrap();
+        assert_eq!(summary.rule_name, SUMMARY_RULE_NAME);
+        assert_eq!(summary.severity, 9);
+        assert!(summary.description.starts_with("3 additional anomalies suppressed"));
+        assert!(summary.description.ends_with("IP Switch (2), Rate Limit Exceeded (1)"));
+    }
+}
@@ -0,0 +1,164 @@
+//! Heartbeat (liveness ping)
+//!
+//! Silence from an IDS is ambiguous: nothing happened, or the daemon is
+//! down. The heartbeat posts a small JSON payload to a configured URL
+//! (typically a dead man's switch service) on a fixed interval, so a
+//! missing heartbeat is what raises the alarm. The first ping is sent at
+//! startup.
+//!
+//! Payload fields: `service`, `status`, `timestamp`, `uptime_seconds`,
+//! `interval_seconds`, and `events_processed`, `lines_read` and
+//! `parse_failures` counted since the previous heartbeat.
+
+use super::{request_timeout, AlertError};
+use crate::config::HeartbeatConfig;
+use crate::input::{IngestionSnapshot, IngestionStats};
+use reqwest::Client;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+/// Periodically posts a liveness payload
+pub struct Heartbeat {
+    config: HeartbeatConfig,
+    stats: Arc<IngestionStats>,
+    client: Client,
+    started: Instant,
+}
+
+impl Heartbeat {
+    pub fn new(config: HeartbeatConfig, stats: Arc<IngestionStats>) -> Self {
+        Heartbeat {
+            config,
+            stats,
+            client: Client::new(),
+            started: Instant::now(),
+        }
+    }
+
+    /// Build the payload for counts accumulated since the last heartbeat
+    pub fn payload(&self, recent: &IngestionSnapshot) -> serde_json::Value {
+        serde_json::json!({
+            "service": "odin",
+            "status": "alive",
+            "timestamp": chrono::Utc::now().timestamp(),
+            "uptime_seconds": self.started.elapsed().as_secs(),
+            "interval_seconds": self.config.interval_seconds,
+            "events_processed": recent.lines_parsed,
+            "lines_read": recent.lines_read,
+            "parse_failures": recent.parse_failures,
+        })
+    }
+
+    /// Post one heartbeat
+    pub async fn send(&self, payload: &serde_json::Value) -> Result<(), AlertError> {
+        let response = self
+            .client
+            .post(&self.config.url)
+            .timeout(request_timeout(self.config.timeout_secs))
+            .json(payload)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            log::warn!("Heartbeat endpoint returned non-success status: {}", response.status());
+        }
+        Ok(())
+    }
+
+    /// Send heartbeats forever on the configured interval
+    pub async fn run(self) {
+        let period = Duration::from_secs(self.config.interval_seconds.max(1));
+        let mut ticker = tokio::time::interval(period);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut last = self.stats.snapshot();
+        loop {
+            ticker.tick().await;
+            let now = self.stats.snapshot();
+            let payload = self.payload(&now.since(&last));
+            match self.send(&payload).await {
+                Ok(()) => log::debug!("Heartbeat sent to {}", self.config.url),
+                Err(e) => log::warn!("Failed to send heartbeat: {}", e),
+            }
+            last = now;
+        }
+    }
+
+    /// Run the heartbeat as its own task
+    pub fn spawn(self) -> JoinHandle<()> {
+        log::info!(
+            "Sending heartbeats to {} every {}s",
+            self.config.url,
+            self.config.interval_seconds
+        );
+        tokio::spawn(self.run())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::sync::mpsc;
+
+    /// Accept HTTP requests, answer 200 and pass on their JSON bodies
+    async fn spawn_mock_server() -> (String, mpsc::UnboundedReceiver<serde_json::Value>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/ping", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buf = [0u8; 4096];
+                // Read until the whole JSON body has arrived
+                let body = loop {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    if let Some((_, body)) = text.split_once("\r\n\r\n") {
+                        if let Ok(json) = serde_json::from_str::<serde_json::Value>(body) {
+                            break json;
+                        }
+                    }
+                };
+                socket
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                    .await
+                    .unwrap();
+                let _ = tx.send(body);
+            }
+        });
+        (url, rx)
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_posts_on_interval() {
+        let (url, mut received) = spawn_mock_server().await;
+        let stats = Arc::new(IngestionStats::new());
+        let config = HeartbeatConfig {
+            url,
+            interval_seconds: 1,
+            timeout_secs: Some(5),
+        };
+        let started = Instant::now();
+        let task = Heartbeat::new(config, stats.clone()).spawn();
+
+        // Sent immediately at startup
+        let first = received.recv().await.unwrap();
+        assert_eq!(first["status"], "alive");
+        assert_eq!(first["events_processed"], 0);
+
+        for _ in 0..3 {
+            stats.record_parsed();
+        }
+        stats.record_failure();
+
+        // Then once per interval, with counts since the previous one
+        let second = received.recv().await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(900));
+        assert_eq!(second["events_processed"], 3);
+        assert_eq!(second["parse_failures"], 1);
+        assert_eq!(second["interval_seconds"], 1);
+
+        task.abort();
+    }
+}
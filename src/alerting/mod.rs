@@ -1,13 +1,86 @@
 //! Alerting module for webhook notifications
 //!
 //! This module provides asynchronous alert dispatching to various
-//! notification channels including Slack, Discord, and generic webhooks.
+//! notification channels including Slack, Discord, generic webhooks and
+//! a local Unix socket.
+
+pub mod circuit_breaker;
+pub mod global_rate;
+pub mod heartbeat;
+pub mod text;
+#[cfg(unix)]
+pub mod unix_socket;
+
+pub use circuit_breaker::{CircuitBreaker, CircuitBreakers, CircuitState};
+pub use global_rate::GlobalAlertLimit;
+pub use heartbeat::Heartbeat;
+pub use text::TextTemplate;
+#[cfg(unix)]
+pub use unix_socket::UnixSocketSink;
 
 use crate::config::{AlertConfig, SlackConfig, DiscordConfig, WebhookConfig};
 use crate::models::AnomalyReport;
+use crate::persistence::StateStore;
 use reqwest::Client;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use thiserror::Error;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::JoinHandle;
+
+/// Default per-request timeout for alert channels
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+/// Delay before the first delivery retry, doubled for each further attempt
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Pending alerts redelivered per pass
+const PENDING_BATCH_SIZE: usize = 100;
+
+/// Resolve a channel's configured timeout, falling back to the default
+fn request_timeout(timeout_secs: Option<u64>) -> Duration {
+    Duration::from_secs(timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS))
+}
+
+/// HTTP client shared by the webhook channels, rebuilt periodically when
+/// configured so hostnames are re-resolved
+struct HttpClient {
+    client: Client,
+    built_at: Instant,
+    /// Number of times the client has been rebuilt
+    generation: u64,
+}
+
+/// Build the HTTP client according to the connection settings
+fn build_client(config: &AlertConfig) -> Client {
+    let mut builder = Client::builder();
+    if !config.reuse_connections {
+        // Every request opens a new connection and resolves the host again
+        builder = builder.pool_max_idle_per_host(0);
+    }
+    builder.build().unwrap_or_else(|e| {
+        log::warn!("Failed to build HTTP client ({}), using defaults", e);
+        Client::new()
+    })
+}
+
+/// Hostname attached by reverse DNS enrichment, for alert fields
+fn detected_hostname(report: &AnomalyReport) -> &str {
+    report
+        .metadata
+        .get(crate::geolocation::reverse_dns::HOSTNAME_METADATA_KEY)
+        .map(String::as_str)
+        .unwrap_or("N/A")
+}
+
+/// Report confidence as a percentage, for alert fields
+fn confidence_label(report: &AnomalyReport) -> String {
+    report
+        .confidence
+        .map(|c| format!("{:.0}%", c * 100.0))
+        .unwrap_or_else(|| "N/A".to_string())
+}
 
 /// Errors that can occur during alert dispatch
 #[derive(Error, Debug)]
@@ -18,11 +91,42 @@ pub enum AlertError {
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
 
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
     #[error("Alert channel closed")]
     ChannelClosed,
 
     #[error("Alert queue full")]
     QueueFull,
+
+    #[error("Circuit open for: {0}")]
+    CircuitOpen(String),
+
+    #[error("Timed out after {0:?}")]
+    Timeout(Duration),
+}
+
+/// A configured notification channel
+enum Channel<'a> {
+    Slack(&'a SlackConfig),
+    Discord(&'a DiscordConfig),
+    Webhook(&'a WebhookConfig),
+    #[cfg(unix)]
+    UnixSocket(&'a UnixSocketSink),
+}
+
+impl Channel<'_> {
+    /// Name of the channel's circuit breaker and pending alerts
+    fn name(&self) -> String {
+        match self {
+            Channel::Slack(_) => "slack".to_string(),
+            Channel::Discord(_) => "discord".to_string(),
+            Channel::Webhook(webhook) => format!("webhook:{}", webhook.name),
+            #[cfg(unix)]
+            Channel::UnixSocket(_) => "unix_socket".to_string(),
+        }
+    }
 }
 
 /// Async alert dispatcher
@@ -31,7 +135,19 @@ pub enum AlertError {
 /// notification channels (Slack, Discord, webhooks).
 pub struct AlertDispatcher {
     config: AlertConfig,
-    client: Client,
+    client: Mutex<HttpClient>,
+    breakers: CircuitBreakers,
+    #[cfg(unix)]
+    unix_socket: Option<UnixSocketSink>,
+    /// Store undelivered alerts are persisted to for redelivery
+    pending_store: Option<Arc<dyn StateStore>>,
+    /// Cap on alerts per minute across all keys
+    global_limit: Option<Mutex<GlobalAlertLimit>>,
+    /// Slots for alerts being dispatched at once (`max_concurrent_dispatches`)
+    dispatch_slots: Arc<Semaphore>,
+    /// Background deliveries, and retries of channels an alert couldn't
+    /// be delivered to
+    tasks: Mutex<Vec<JoinHandle<()>>>,
 }
 
 impl AlertDispatcher {
@@ -40,19 +156,79 @@ impl AlertDispatcher {
     /// Returns the dispatcher and a receiver for the alert channel.
     /// The dispatcher should be spawned as a tokio task using `run()`.
     pub fn new(config: AlertConfig) -> (Self, mpsc::Receiver<AnomalyReport>) {
-        let (tx, rx) = mpsc::channel(100);
+        let (_tx, rx) = mpsc::channel(100);
+        let breakers = CircuitBreakers::new(
+            config.circuit_failure_threshold,
+            Duration::from_secs(config.circuit_reset_seconds),
+        );
         let dispatcher = AlertDispatcher {
+            #[cfg(unix)]
+            unix_socket: config
+                .unix_socket
+                .as_ref()
+                .map(|socket| UnixSocketSink::new(socket, &TextTemplate::from_config(&config))),
+            // Timeouts are applied per request so each channel can use its own
+            client: Mutex::new(HttpClient {
+                client: build_client(&config),
+                built_at: Instant::now(),
+                generation: 0,
+            }),
+            global_limit: config
+                .global_alert_rate_per_min
+                .map(|max| Mutex::new(GlobalAlertLimit::new(max))),
+            dispatch_slots: Arc::new(Semaphore::new(config.max_concurrent_dispatches.max(1))),
             config,
-            client: Client::builder()
-                .timeout(std::time::Duration::from_secs(30))
-                .build()
-                .unwrap_or_default(),
+            breakers,
+            pending_store: None,
+            tasks: Mutex::new(Vec::new()),
         };
         // Store the sender in a static or return it separately
         // For now, we'll use a different pattern
         (dispatcher, rx)
     }
 
+    /// Persist alerts that can't be delivered and redeliver them later
+    ///
+    /// Pending alerts are retried when `run` starts and then every
+    /// `pending.retry_interval_seconds`.
+    pub fn with_pending_store(mut self, store: Arc<dyn StateStore>) -> Self {
+        self.pending_store = Some(store);
+        self
+    }
+
+    /// HTTP client for the next request
+    ///
+    /// With `client_refresh_seconds` set, the client (and its pooled
+    /// connections) is replaced once it is older than the interval, so a
+    /// webhook host whose address changed is resolved again.
+    fn client(&self) -> Client {
+        self.client_at(Instant::now())
+    }
+
+    fn client_at(&self, now: Instant) -> Client {
+        let mut http = self.client.lock().unwrap();
+        if let Some(refresh) = self.config.client_refresh_seconds {
+            if now.saturating_duration_since(http.built_at) >= Duration::from_secs(refresh) {
+                http.client = build_client(&self.config);
+                http.built_at = now;
+                http.generation += 1;
+                log::debug!("Rebuilt alert HTTP client (generation {})", http.generation);
+            }
+        }
+        http.client.clone()
+    }
+
+    /// Run a dispatch pass once a dispatch slot is free
+    async fn bounded<T>(&self, request: impl Future<Output = T>) -> T {
+        let _slot = self.dispatch_slots.acquire().await;
+        request.await
+    }
+
+    /// Handle to the per-channel circuit breakers, for health reporting
+    pub fn circuit_breakers(&self) -> CircuitBreakers {
+        self.breakers.clone()
+    }
+
     /// Create a sender for queueing alerts
     pub fn create_channel() -> (mpsc::Sender<AnomalyReport>, mpsc::Receiver<AnomalyReport>) {
         mpsc::channel(100)
@@ -65,72 +241,313 @@ impl AlertDispatcher {
     /// configured notification channels.
     pub async fn run(self, mut rx: mpsc::Receiver<AnomalyReport>) {
         log::info!("Alert dispatcher started");
+        let this = Arc::new(self);
+        this.run_loop(&mut rx).await;
+        this.send_suppressed_summary().await;
+        this.finish_tasks().await;
+        log::info!("Alert dispatcher stopped");
+    }
 
-        while let Some(report) = rx.recv().await {
-            if !self.config.enabled {
-                continue;
-            }
+    /// Receive alerts until the channel closes, delivering each in its
+    /// own task while a dispatch slot is held
+    async fn run_loop(self: &Arc<Self>, rx: &mut mpsc::Receiver<AnomalyReport>) {
+        // Deliver alerts left over from the last run before any new ones
+        let redeliver = self.config.enabled && self.pending_store.is_some();
+        if redeliver {
+            self.retry_pending().await;
+        }
+        let retry_interval = Duration::from_secs(self.config.pending.retry_interval_seconds.max(1));
+        let mut retry_timer =
+            tokio::time::interval_at(tokio::time::Instant::now() + retry_interval, retry_interval);
+        let mut summary_timer = tokio::time::interval_at(
+            tokio::time::Instant::now() + global_rate::RATE_WINDOW,
+            global_rate::RATE_WINDOW,
+        );
+        let summarize = self.global_limit.is_some();
 
-            if report.severity < self.config.min_severity {
-                log::debug!(
-                    "Skipping alert for {} (severity {} < min {})",
-                    report.rule_name,
-                    report.severity,
-                    self.config.min_severity
-                );
-                continue;
+        loop {
+            tokio::select! {
+                report = rx.recv() => match report {
+                    Some(report) => {
+                        // Waiting for a slot holds back the channel, so a
+                        // flood of alerts queues up rather than piling up tasks
+                        let slot = Arc::clone(&self.dispatch_slots).acquire_owned().await.unwrap();
+                        let this = Arc::clone(self);
+                        self.track(tokio::spawn(async move {
+                            this.handle_alert(report).await;
+                            drop(slot);
+                        }));
+                    }
+                    None => break,
+                },
+                _ = retry_timer.tick(), if redeliver => {
+                    self.retry_pending().await;
+                }
+                _ = summary_timer.tick(), if summarize => {
+                    self.send_suppressed_summary().await;
+                }
             }
+        }
+    }
 
-            log::info!(
-                "Dispatching alert: {} (severity {})",
+    /// Filter, dispatch and if necessary persist a single alert
+    async fn handle_alert(self: &Arc<Self>, report: AnomalyReport) {
+        if !self.config.enabled {
+            return;
+        }
+
+        if !self.config.should_alert(&report) {
+            log::debug!(
+                "Skipping alert for {} (severity {} < min {})",
                 report.rule_name,
-                report.severity
+                report.severity,
+                self.config.min_severity
             );
+            return;
+        }
 
-            if let Err(e) = self.dispatch_alert(&report).await {
-                log::error!("Failed to dispatch alert: {}", e);
+        if let Some(limit) = &self.global_limit {
+            if !limit.lock().unwrap().admit(&report, Instant::now()) {
+                log::debug!("Suppressing alert {} over the global alert rate limit", report.rule_name);
+                return;
             }
         }
 
-        log::info!("Alert dispatcher stopped");
+        log::info!(
+            "Dispatching alert: {} (severity {})",
+            report.rule_name,
+            report.severity
+        );
+
+        self.deliver(&report).await;
     }
 
-    /// Dispatch an alert to all configured channels
-    async fn dispatch_alert(&self, report: &AnomalyReport) -> Result<(), AlertError> {
-        let mut errors = Vec::new();
+    /// Dispatch a summary of alerts suppressed by the global rate limit
+    ///
+    /// The summary itself isn't counted against the limit. Returns it, or
+    /// None if nothing was suppressed.
+    pub async fn send_suppressed_summary(self: &Arc<Self>) -> Option<AnomalyReport> {
+        let summary = self.global_limit.as_ref()?.lock().unwrap().take_summary()?;
+        log::warn!("{}", summary.description);
+        self.bounded(self.deliver(&summary)).await;
+        Some(summary)
+    }
 
-        // Send to Slack
-        if let Some(ref slack) = self.config.slack {
-            if let Err(e) = self.send_slack_alert(slack, report).await {
-                log::error!("Slack alert failed: {}", e);
-                errors.push(e);
+    /// Dispatch an alert, handing each channel it failed on to a
+    /// background retry
+    ///
+    /// Open circuits aren't retried since they stay open for much longer
+    /// than the backoff, so the alert is persisted for them right away.
+    async fn deliver(self: &Arc<Self>, report: &AnomalyReport) {
+        for (channel, e) in self.dispatch_to(report, None).await {
+            if self.config.dispatch_retries == 0 || matches!(e, AlertError::CircuitOpen(_)) {
+                log::error!("Failed to dispatch alert to {}: {}", channel, e);
+                self.persist_pending(report, &channel);
+                continue;
             }
+            let this = Arc::clone(self);
+            let report = report.clone();
+            self.track(tokio::spawn(async move { this.retry_channel(&report, channel).await }));
         }
+    }
 
-        // Send to Discord
-        if let Some(ref discord) = self.config.discord {
-            if let Err(e) = self.send_discord_alert(discord, report).await {
-                log::error!("Discord alert failed: {}", e);
-                errors.push(e);
+    /// Keep a background task so it can be awaited on shutdown
+    fn track(&self, task: JoinHandle<()>) {
+        let mut tasks = self.tasks.lock().unwrap();
+        tasks.retain(|task| !task.is_finished());
+        tasks.push(task);
+    }
+
+    /// Retry delivery to a single channel with backoff, persisting the
+    /// alert if it still fails
+    ///
+    /// Runs as its own task so the dispatch loop keeps handling new
+    /// alerts in the meantime, and only the failed channel is retried so
+    /// the others don't get the alert twice.
+    async fn retry_channel(&self, report: &AnomalyReport, channel: String) {
+        let targets = [channel];
+        let mut delay = RETRY_BASE_DELAY;
+        for attempt in 1..=self.config.dispatch_retries {
+            log::debug!("Alert delivery to {} failed, retry {} in {:?}", targets[0], attempt, delay);
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+            match self.bounded(self.dispatch_to(report, Some(&targets))).await.pop() {
+                None => return,
+                Some((_, AlertError::CircuitOpen(_))) => break,
+                Some(_) => {}
             }
         }
+        log::error!("Giving up on alert delivery to {}, retries exhausted", targets[0]);
+        self.persist_pending(report, &targets[0]);
+    }
 
-        // Send to generic webhooks
-        for webhook in &self.config.webhooks {
-            if let Err(e) = self.send_generic_webhook(webhook, report).await {
-                log::error!("Webhook {} failed: {}", webhook.name, e);
-                errors.push(e);
+    /// Wait for outstanding deliveries and retries to finish
+    ///
+    /// Deliveries can start retries while being awaited, so this repeats
+    /// until none are left.
+    async fn finish_tasks(&self) {
+        loop {
+            let tasks = std::mem::take(&mut *self.tasks.lock().unwrap());
+            if tasks.is_empty() {
+                break;
+            }
+            for task in tasks {
+                if let Err(e) = task.await {
+                    log::warn!("Alert delivery task failed: {}", e);
+                }
             }
         }
+    }
 
-        if errors.is_empty() {
-            Ok(())
-        } else {
-            // Return the first error (could be improved to aggregate)
-            Err(errors.remove(0))
+    /// Persist an alert undelivered to `channel` for later redelivery
+    fn persist_pending(&self, report: &AnomalyReport, channel: &str) {
+        let Some(store) = self.pending_store.as_ref().filter(|_| self.config.pending.enabled) else {
+            return;
+        };
+        let now = chrono::Utc::now().timestamp();
+        match store.enqueue_pending_alert(report, Some(channel), now, self.config.pending.max_entries) {
+            Ok(()) => log::info!("Persisted alert {} undelivered to {} for redelivery", report.rule_name, channel),
+            Err(e) => log::error!("Failed to persist undelivered alert: {}", e),
         }
     }
 
+    /// Redeliver persisted alerts, oldest first
+    ///
+    /// Alerts older than `pending.max_age_seconds` are dropped first. Each
+    /// alert is only sent to the channel it is still owed to; once a
+    /// channel fails, its remaining alerts wait for the next pass since
+    /// it is most likely still unavailable. Returns the number of alerts
+    /// delivered.
+    pub async fn retry_pending(&self) -> usize {
+        let Some(store) = self.pending_store.as_ref().filter(|_| self.config.pending.enabled) else {
+            return 0;
+        };
+
+        let cutoff = chrono::Utc::now().timestamp() - self.config.pending.max_age_seconds;
+        match store.expire_pending_alerts(cutoff) {
+            Ok(0) => {}
+            Ok(expired) => log::warn!("Dropped {} alert(s) pending longer than {}s", expired, self.config.pending.max_age_seconds),
+            Err(e) => log::warn!("Failed to expire pending alerts: {}", e),
+        }
+
+        let pending = match store.get_pending_alerts(PENDING_BATCH_SIZE) {
+            Ok(pending) => pending,
+            Err(e) => {
+                log::warn!("Failed to load pending alerts: {}", e);
+                return 0;
+            }
+        };
+
+        let configured: Vec<String> = self.channels().iter().map(Channel::name).collect();
+        let mut unavailable: Vec<String> = Vec::new();
+        let mut delivered = 0;
+        for alert in pending {
+            let targets = match alert.channel {
+                Some(channel) if !configured.contains(&channel) => {
+                    log::warn!("Dropping pending alert {} for unconfigured channel {}", alert.id, channel);
+                    if let Err(e) = store.remove_pending_alert(alert.id) {
+                        log::warn!("Failed to remove pending alert: {}", e);
+                    }
+                    continue;
+                }
+                Some(channel) => vec![channel],
+                None => configured.clone(),
+            };
+            if targets.iter().any(|channel| unavailable.contains(channel)) {
+                continue;
+            }
+
+            let failed = self.bounded(self.dispatch_to(&alert.report, Some(&targets))).await;
+            for (channel, e) in &failed {
+                log::debug!("Pending alert {} still undeliverable to {}: {}", alert.id, channel, e);
+                unavailable.push(channel.clone());
+            }
+            if failed.len() == targets.len() {
+                continue;
+            }
+            // Delivered somewhere; keep it queued only for the channels that failed
+            for (channel, _) in &failed {
+                if let Err(e) = store.enqueue_pending_alert(
+                    &alert.report,
+                    Some(channel),
+                    alert.queued_at,
+                    self.config.pending.max_entries,
+                ) {
+                    log::warn!("Failed to requeue pending alert for {}: {}", channel, e);
+                }
+            }
+            if let Err(e) = store.remove_pending_alert(alert.id) {
+                log::warn!("Failed to remove delivered pending alert: {}", e);
+            }
+            delivered += 1;
+        }
+        if delivered > 0 {
+            log::info!("Redelivered {} pending alert(s)", delivered);
+        }
+        delivered
+    }
+
+    /// Configured notification channels, in dispatch order
+    fn channels(&self) -> Vec<Channel<'_>> {
+        let mut channels = Vec::new();
+        if let Some(ref slack) = self.config.slack {
+            channels.push(Channel::Slack(slack));
+        }
+        if let Some(ref discord) = self.config.discord {
+            channels.push(Channel::Discord(discord));
+        }
+        channels.extend(self.config.webhooks.iter().map(Channel::Webhook));
+        #[cfg(unix)]
+        if let Some(ref sink) = self.unix_socket {
+            channels.push(Channel::UnixSocket(sink));
+        }
+        channels
+    }
+
+    /// Send an alert to a single channel
+    async fn send_to(&self, channel: &Channel<'_>, report: &AnomalyReport) -> Result<(), AlertError> {
+        match channel {
+            Channel::Slack(slack) => self.send_slack_alert(slack, report).await,
+            Channel::Discord(discord) => self.send_discord_alert(discord, report).await,
+            Channel::Webhook(webhook) => self.send_generic_webhook(webhook, report).await,
+            #[cfg(unix)]
+            Channel::UnixSocket(sink) => sink.send(report).await,
+        }
+    }
+
+    /// Dispatch an alert to the named channels, or all configured channels
+    ///
+    /// Channels whose circuit breaker is open are skipped and reported as
+    /// `AlertError::CircuitOpen`. Returns the channels the alert couldn't
+    /// be delivered to.
+    async fn dispatch_to(
+        &self,
+        report: &AnomalyReport,
+        only: Option<&[String]>,
+    ) -> Vec<(String, AlertError)> {
+        let mut failed = Vec::new();
+        for channel in self.channels() {
+            let name = channel.name();
+            if only.is_some_and(|names| !names.contains(&name)) {
+                continue;
+            }
+            if !self.breakers.allow_request(&name) {
+                log::debug!("Skipping {} alert, circuit open", name);
+                failed.push((name.clone(), AlertError::CircuitOpen(name)));
+                continue;
+            }
+            match self.send_to(&channel, report).await {
+                Ok(()) => self.breakers.record_success(&name),
+                Err(e) => {
+                    log::error!("{} alert failed: {}", name, e);
+                    self.breakers.record_failure(&name);
+                    failed.push((name, e));
+                }
+            }
+        }
+        failed
+    }
+
     /// Send an alert to Slack
     async fn send_slack_alert(
         &self,
@@ -162,22 +579,21 @@ impl AlertDispatcher {
                     { "title": "Severity", "value": report.severity.to_string(), "short": true },
                     { "title": "Detected IP", "value": &report.detected_ip, "short": true },
                     { "title": "Trusted IP", "value": if report.trusted_ip.is_empty() { "N/A" } else { &report.trusted_ip }, "short": true },
+                    { "title": "Hostname", "value": detected_hostname(report), "short": true },
+                    { "title": "Confidence", "value": confidence_label(report), "short": true },
                 ],
                 "text": &report.description,
                 "ts": report.timestamp,
             }]
         });
 
-        let response = self
-            .client
+        self.client()
             .post(&config.webhook_url)
+            .timeout(request_timeout(config.timeout_secs))
             .json(&payload)
             .send()
-            .await?;
-
-        if !response.status().is_success() {
-            log::warn!("Slack returned non-success status: {}", response.status());
-        }
+            .await?
+            .error_for_status()?;
 
         Ok(())
     }
@@ -211,6 +627,8 @@ impl AlertDispatcher {
                     { "name": "User", "value": &report.user, "inline": true },
                     { "name": "Severity", "value": format!("{}/10", report.severity), "inline": true },
                     { "name": "Detected IP", "value": &report.detected_ip, "inline": true },
+                    { "name": "Hostname", "value": detected_hostname(report), "inline": true },
+                    { "name": "Confidence", "value": confidence_label(report), "inline": true },
                 ],
                 "timestamp": timestamp,
                 "footer": {
@@ -219,16 +637,13 @@ impl AlertDispatcher {
             }]
         });
 
-        let response = self
-            .client
+        self.client()
             .post(&config.webhook_url)
+            .timeout(request_timeout(config.timeout_secs))
             .json(&payload)
             .send()
-            .await?;
-
-        if !response.status().is_success() {
-            log::warn!("Discord returned non-success status: {}", response.status());
-        }
+            .await?
+            .error_for_status()?;
 
         Ok(())
     }
@@ -242,8 +657,8 @@ impl AlertDispatcher {
         let method = config.method.as_deref().unwrap_or("POST");
 
         let mut request = match method.to_uppercase().as_str() {
-            "PUT" => self.client.put(&config.url),
-            _ => self.client.post(&config.url),
+            "PUT" => self.client().put(&config.url),
+            _ => self.client().post(&config.url),
         };
 
         // Add custom headers
@@ -253,15 +668,12 @@ impl AlertDispatcher {
             }
         }
 
-        let response = request.json(report).send().await?;
-
-        if !response.status().is_success() {
-            log::warn!(
-                "Webhook {} returned non-success status: {}",
-                config.name,
-                response.status()
-            );
-        }
+        request
+            .timeout(request_timeout(config.timeout_secs))
+            .json(report)
+            .send()
+            .await?
+            .error_for_status()?;
 
         Ok(())
     }
@@ -316,6 +728,7 @@ impl AlertQueue {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::BTreeMap;
 
     fn create_test_report() -> AnomalyReport {
         AnomalyReport {
@@ -325,7 +738,10 @@ mod tests {
             detected_ip: "1.2.3.4".to_string(),
             trusted_ip: "5.6.7.8".to_string(),
             timestamp: 1700000000,
+            detected_at: 1700000000,
             description: "Test anomaly detected".to_string(),
+            metadata: BTreeMap::new(),
+            confidence: None,
         }
     }
 
@@ -372,6 +788,7 @@ mod tests {
             slack: None,
             discord: None,
             webhooks: vec![],
+            ..AlertConfig::default()
         };
 
         let (dispatcher, rx) = AlertDispatcher::new(config);
@@ -392,6 +809,7 @@ mod tests {
             slack: None,
             discord: None,
             webhooks: vec![],
+            ..AlertConfig::default()
         };
 
         // Severity 7 should be filtered
@@ -402,9 +820,333 @@ mod tests {
             detected_ip: "1.1.1.1".to_string(),
             trusted_ip: "".to_string(),
             timestamp: 0,
+            detected_at: 0,
             description: "test".to_string(),
+            metadata: BTreeMap::new(),
+            confidence: None,
+        };
+
+        assert!(!config.should_alert(&report));
+    }
+
+    #[test]
+    fn test_always_alert_rules_bypass_min_severity() {
+        let config = AlertConfig {
+            enabled: true,
+            min_severity: 9,
+            always_alert_rules: vec!["Simultaneous Login".to_string()],
+            ..AlertConfig::default()
+        };
+
+        let mut report = create_test_report();
+        report.rule_name = "Simultaneous Login".to_string();
+        report.severity = 5;
+        assert!(config.should_alert(&report));
+
+        // Other rules are still gated
+        report.rule_name = "Rate Limit Exceeded".to_string();
+        assert!(!config.should_alert(&report));
+    }
+
+    #[tokio::test]
+    async fn test_circuit_opens_for_failing_webhook() {
+        // Nothing listens on this port, so every request fails fast
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let config = AlertConfig {
+            enabled: true,
+            webhooks: vec![WebhookConfig {
+                name: "dead".to_string(),
+                url: format!("http://{}/hook", addr),
+                method: None,
+                headers: None,
+                timeout_secs: Some(1),
+            }],
+            circuit_failure_threshold: 2,
+            circuit_reset_seconds: 3600,
+            ..AlertConfig::default()
+        };
+        let (dispatcher, _rx) = AlertDispatcher::new(config);
+        let breakers = dispatcher.circuit_breakers();
+        let report = create_test_report();
+
+        assert_eq!(dispatcher.dispatch_to(&report, None).await.len(), 1);
+        assert_eq!(dispatcher.dispatch_to(&report, None).await.len(), 1);
+        assert_eq!(
+            breakers.states(),
+            vec![("webhook:dead".to_string(), CircuitState::Open)]
+        );
+
+        // While open, the channel is skipped entirely
+        match dispatcher.dispatch_to(&report, None).await.as_slice() {
+            [(channel, AlertError::CircuitOpen(_))] => assert_eq!(channel, "webhook:dead"),
+            other => panic!("Expected open circuit, got {:?}", other),
+        }
+    }
+
+    /// Accept HTTP requests, answer 200 and count them
+    async fn spawn_ok_server() -> (std::net::SocketAddr, Arc<std::sync::atomic::AtomicUsize>) {
+        spawn_server(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n").await
+    }
+
+    /// Accept HTTP requests, answer with `response` and count them
+    async fn spawn_server(response: &'static [u8]) -> (std::net::SocketAddr, Arc<std::sync::atomic::AtomicUsize>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let mut buf = [0u8; 4096];
+                let _ = socket.read(&mut buf).await;
+                let _ = socket.write_all(response).await;
+            }
+        });
+        (addr, requests)
+    }
+
+    #[tokio::test]
+    async fn test_error_status_fails_delivery() {
+        let (addr, requests) =
+            spawn_server(b"HTTP/1.1 500 Internal Server Error\r\ncontent-length: 0\r\nconnection: close\r\n\r\n").await;
+        let store: Arc<dyn StateStore> = Arc::new(crate::persistence::SqliteStateStore::in_memory().unwrap());
+        let mut config = webhook_config(format!("http://{}/hook", addr));
+        config.circuit_failure_threshold = 1;
+        let (dispatcher, _rx) = AlertDispatcher::new(config);
+        let dispatcher = Arc::new(dispatcher.with_pending_store(store.clone()));
+
+        match dispatcher.send_generic_webhook(&dispatcher.config.webhooks[0], &create_test_report()).await {
+            Err(AlertError::Http(e)) => assert_eq!(e.status(), Some(reqwest::StatusCode::INTERNAL_SERVER_ERROR)),
+            other => panic!("Expected HTTP status error, got {:?}", other),
+        }
+
+        // The rejected alert opens the circuit and is kept for redelivery
+        dispatcher.handle_alert(create_test_report()).await;
+        assert_eq!(requests.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert_eq!(
+            dispatcher.breakers.states(),
+            vec![("webhook:hook".to_string(), CircuitState::Open)]
+        );
+        assert_eq!(store.get_pending_alerts(10).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_dispatches_bounded() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Answers each request after a delay, tracking the most in flight
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let served = Arc::new(AtomicUsize::new(0));
+        let (current, max, done) = (in_flight.clone(), max_in_flight.clone(), served.clone());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let (current, max, done) = (current.clone(), max.clone(), done.clone());
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    let _ = socket.read(&mut buf).await;
+                    max.fetch_max(current.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    current.fetch_sub(1, Ordering::SeqCst);
+                    done.fetch_add(1, Ordering::SeqCst);
+                    let _ = socket
+                        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                        .await;
+                });
+            }
+        });
+
+        let mut config = webhook_config(format!("http://{}/hook", addr));
+        config.max_concurrent_dispatches = 2;
+        config.reuse_connections = false;
+        let (dispatcher, _rx) = AlertDispatcher::new(config);
+        let (tx, rx) = AlertDispatcher::create_channel();
+        for _ in 0..6 {
+            tx.send(create_test_report()).await.unwrap();
+        }
+        drop(tx);
+
+        // Alerts are delivered two at a time, and all before run returns
+        dispatcher.run(rx).await;
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+        assert_eq!(served.load(Ordering::SeqCst), 6);
+    }
+
+    fn webhook_config(url: String) -> AlertConfig {
+        AlertConfig {
+            enabled: true,
+            min_severity: 1,
+            webhooks: vec![WebhookConfig {
+                name: "hook".to_string(),
+                url,
+                method: None,
+                headers: None,
+                timeout_secs: Some(1),
+            }],
+            dispatch_retries: 0,
+            ..AlertConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_alert_persisted_and_redelivered() {
+        let store: Arc<dyn StateStore> = Arc::new(crate::persistence::SqliteStateStore::in_memory().unwrap());
+
+        // First run: the webhook is down, so the alert is persisted
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let dead = listener.local_addr().unwrap();
+        drop(listener);
+        let (dispatcher, _rx) = AlertDispatcher::new(webhook_config(format!("http://{}/hook", dead)));
+        let dispatcher = Arc::new(dispatcher.with_pending_store(store.clone()));
+        dispatcher.handle_alert(create_test_report()).await;
+        let pending = store.get_pending_alerts(10).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].report.rule_name, "Test Rule");
+
+        // Next run: the webhook is back and the alert is delivered on startup
+        let (addr, requests) = spawn_ok_server().await;
+        let (dispatcher, _rx) = AlertDispatcher::new(webhook_config(format!("http://{}/hook", addr)));
+        let dispatcher = dispatcher.with_pending_store(store.clone());
+        let (tx, rx) = AlertDispatcher::create_channel();
+        drop(tx);
+        dispatcher.run(rx).await;
+
+        assert_eq!(requests.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert!(store.get_pending_alerts(10).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_only_failed_channel_retried_and_queued() {
+        let store: Arc<dyn StateStore> = Arc::new(crate::persistence::SqliteStateStore::in_memory().unwrap());
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let dead = listener.local_addr().unwrap();
+        drop(listener);
+        let (addr, requests) = spawn_ok_server().await;
+
+        let mut config = webhook_config(format!("http://{}/hook", addr));
+        config.dispatch_retries = 2;
+        config.webhooks.push(WebhookConfig {
+            name: "dead".to_string(),
+            url: format!("http://{}/hook", dead),
+            method: None,
+            headers: None,
+            timeout_secs: Some(1),
+        });
+        let (dispatcher, _rx) = AlertDispatcher::new(config);
+        let dispatcher = Arc::new(dispatcher.with_pending_store(store.clone()));
+        dispatcher.handle_alert(create_test_report()).await;
+
+        // The failed channel is retried in the background
+        assert!(store.get_pending_alerts(10).unwrap().is_empty());
+        dispatcher.finish_tasks().await;
+
+        // The working webhook got the alert once, despite the retries
+        assert_eq!(requests.load(std::sync::atomic::Ordering::SeqCst), 1);
+        let pending = store.get_pending_alerts(10).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].channel.as_deref(), Some("webhook:dead"));
+
+        // Redelivery only targets the channel that missed it
+        assert_eq!(dispatcher.retry_pending().await, 0);
+        assert_eq!(requests.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(store.get_pending_alerts(10).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_global_rate_limit_collapses_flood_into_summary() {
+        let (addr, requests) = spawn_ok_server().await;
+        let mut config = webhook_config(format!("http://{}/hook", addr));
+        config.global_alert_rate_per_min = Some(20);
+        let dispatcher = Arc::new(AlertDispatcher::new(config).0);
+
+        // 500 distinct alerts within a minute
+        for i in 0..500 {
+            let mut report = create_test_report();
+            report.user = format!("user{}", i);
+            report.detected_ip = format!("10.0.{}.{}", i / 256, i % 256);
+            dispatcher.handle_alert(report).await;
+        }
+        assert_eq!(requests.load(std::sync::atomic::Ordering::SeqCst), 20);
+
+        let summary = dispatcher.send_suppressed_summary().await.unwrap();
+        assert_eq!(summary.rule_name, global_rate::SUMMARY_RULE_NAME);
+        assert_eq!(summary.metadata["suppressed_count"], "480");
+        assert!(summary.description.starts_with("480 additional anomalies suppressed"));
+        assert_eq!(requests.load(std::sync::atomic::Ordering::SeqCst), 21);
+
+        // Nothing left to summarize
+        assert!(dispatcher.send_suppressed_summary().await.is_none());
+    }
+
+    #[test]
+    fn test_client_rebuilt_after_refresh_interval() {
+        let config = AlertConfig {
+            client_refresh_seconds: Some(300),
+            ..AlertConfig::default()
+        };
+        let (dispatcher, _rx) = AlertDispatcher::new(config);
+        let start = dispatcher.client.lock().unwrap().built_at;
+        let generation = || dispatcher.client.lock().unwrap().generation;
+
+        dispatcher.client_at(start + Duration::from_secs(299));
+        assert_eq!(generation(), 0);
+
+        dispatcher.client_at(start + Duration::from_secs(300));
+        assert_eq!(generation(), 1);
+        assert_eq!(dispatcher.client.lock().unwrap().built_at, start + Duration::from_secs(300));
+
+        // Without an interval the client is kept for the daemon's lifetime
+        let (dispatcher, _rx) = AlertDispatcher::new(AlertConfig::default());
+        dispatcher.client_at(Instant::now() + Duration::from_secs(86400));
+        assert_eq!(dispatcher.client.lock().unwrap().generation, 0);
+    }
+
+    #[test]
+    fn test_request_timeout_default() {
+        assert_eq!(request_timeout(None), Duration::from_secs(30));
+        assert_eq!(request_timeout(Some(5)), Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_webhook_timeout_triggers() {
+        // Mock server that accepts connections but never responds
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_secs(30)).await;
+                    drop(socket);
+                });
+            }
+        });
+
+        let webhook = WebhookConfig {
+            name: "slow".to_string(),
+            url: format!("http://{}/hook", addr),
+            method: None,
+            headers: None,
+            timeout_secs: Some(1),
         };
+        let (dispatcher, _rx) = AlertDispatcher::new(AlertConfig::default());
+
+        let start = std::time::Instant::now();
+        let result = dispatcher
+            .send_generic_webhook(&webhook, &create_test_report())
+            .await;
 
-        assert!(report.severity < config.min_severity);
+        match result {
+            Err(AlertError::Http(e)) => assert!(e.is_timeout()),
+            other => panic!("Expected timeout error, got {:?}", other),
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
     }
 }
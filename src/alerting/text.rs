@@ -0,0 +1,148 @@
+//! Plain-text alert formatting
+//!
+//! Channels that carry a plain message rather than structured JSON render
+//! each report through a template, so operators choose the wording and
+//! which fields appear. Placeholders are `{user}`, `{rule}`, `{severity}`,
+//! `{ip}`, `{description}` and `{timestamp}`; anything else is copied
+//! through unchanged.
+
+use crate::config::AlertConfig;
+use crate::models::AnomalyReport;
+use std::fmt::Write;
+
+/// Renders reports through a plain-text template
+#[derive(Debug, Clone)]
+pub struct TextTemplate {
+    template: String,
+    timestamp_format: String,
+}
+
+impl TextTemplate {
+    /// Create a template, rendering `{timestamp}` with a chrono strftime format
+    pub fn new(template: impl Into<String>, timestamp_format: impl Into<String>) -> Self {
+        TextTemplate {
+            template: template.into(),
+            timestamp_format: timestamp_format.into(),
+        }
+    }
+
+    /// The template configured for alerting
+    pub fn from_config(config: &AlertConfig) -> Self {
+        TextTemplate::new(config.text_template.clone(), config.text_timestamp_format.clone())
+    }
+
+    /// Render a report
+    ///
+    /// The template is scanned once, so placeholders inside substituted
+    /// values (a username of "{ip}") are copied through literally.
+    pub fn render(&self, report: &AnomalyReport) -> String {
+        let mut rendered = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+        while let Some(start) = rest.find('{') {
+            rendered.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let field = rest
+                .find('}')
+                .and_then(|end| Some((end, self.field(&rest[..=end], report)?)));
+            match field {
+                Some((end, value)) => {
+                    rendered.push_str(&value);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    rendered.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        rendered.push_str(rest);
+        rendered
+    }
+
+    /// The value a placeholder stands for, or None if it isn't one
+    fn field(&self, placeholder: &str, report: &AnomalyReport) -> Option<String> {
+        let value = match placeholder {
+            "{user}" => report.user.clone(),
+            "{rule}" => report.rule_name.clone(),
+            "{severity}" => report.severity.to_string(),
+            "{ip}" => report.detected_ip.clone(),
+            "{description}" => report.description.clone(),
+            "{timestamp}" => self.timestamp(report.timestamp),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// Format a timestamp, falling back to epoch seconds when it is out of
+    /// range or the format is invalid
+    fn timestamp(&self, timestamp: i64) -> String {
+        let Some(dt) = chrono::DateTime::from_timestamp(timestamp, 0) else {
+            return timestamp.to_string();
+        };
+        let mut formatted = String::new();
+        match write!(formatted, "{}", dt.format(&self.timestamp_format)) {
+            Ok(()) => formatted,
+            Err(_) => {
+                log::warn!("Invalid alert timestamp format {:?}", self.timestamp_format);
+                timestamp.to_string()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn create_test_report() -> AnomalyReport {
+        AnomalyReport {
+            severity: 8,
+            rule_name: "Impossible Travel".to_string(),
+            user: "alice".to_string(),
+            detected_ip: "203.0.113.5".to_string(),
+            trusted_ip: String::new(),
+            timestamp: 1700000000,
+            detected_at: 1700000000,
+            description: "Login from two continents".to_string(),
+            metadata: BTreeMap::new(),
+            confidence: None,
+        }
+    }
+
+    #[test]
+    fn test_custom_template_rendered() {
+        let template = TextTemplate::new(
+            "{timestamp} [{severity}] {rule}: {user} from {ip} ({description}) {unknown}",
+            "%d/%m/%Y %H:%M",
+        );
+        assert_eq!(
+            template.render(&create_test_report()),
+            "14/11/2023 22:13 [8] Impossible Travel: alice from 203.0.113.5 (Login from two continents) {unknown}"
+        );
+    }
+
+    #[test]
+    fn test_placeholders_in_values_not_expanded() {
+        let report = AnomalyReport {
+            user: "{ip}".to_string(),
+            description: "user {description} tried {{rule}}".to_string(),
+            ..create_test_report()
+        };
+        let template = TextTemplate::new("{user} from {ip}: {description} {{rule}} {user", "%H:%M");
+        assert_eq!(
+            template.render(&report),
+            "{ip} from 203.0.113.5: user {description} tried {{rule}} {Impossible Travel} {user"
+        );
+    }
+
+    #[test]
+    fn test_default_template_and_invalid_format() {
+        let rendered = TextTemplate::from_config(&AlertConfig::default()).render(&create_test_report());
+        assert!(rendered.contains("alice"));
+        assert!(rendered.contains("2023-11-14 22:13:20"));
+
+        let template = TextTemplate::new("at {timestamp}", "%Q");
+        assert_eq!(template.render(&create_test_report()), "at 1700000000");
+    }
+}
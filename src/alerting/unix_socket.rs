@@ -0,0 +1,158 @@
+//! Unix domain socket alert channel
+//!
+//! Writes each report as a line of JSON, or of text rendered through the
+//! alerting text template, to a local socket, for sidecar collectors that
+//! would rather not receive alerts over HTTP. The
+//! connection is kept open between alerts and re-established on failure,
+//! so the collector may start after the daemon.
+
+use super::text::TextTemplate;
+use super::{request_timeout, AlertError};
+use crate::config::{AlertFormat, UnixSocketConfig};
+use crate::models::AnomalyReport;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::UnixStream;
+use tokio::sync::Mutex;
+
+/// Newline-delimited report writer to a Unix socket
+pub struct UnixSocketSink {
+    path: PathBuf,
+    /// Template for text lines; None writes JSON
+    text: Option<TextTemplate>,
+    /// Longest a send may take, connecting included
+    timeout: Duration,
+    stream: Mutex<Option<UnixStream>>,
+}
+
+impl UnixSocketSink {
+    /// Create a sink; `template` renders reports when the format is text
+    pub fn new(config: &UnixSocketConfig, template: &TextTemplate) -> Self {
+        UnixSocketSink {
+            path: config.path.clone(),
+            text: (config.format == AlertFormat::Text).then(|| template.clone()),
+            timeout: request_timeout(config.timeout_secs),
+            stream: Mutex::new(None),
+        }
+    }
+
+    /// Send a report, reconnecting once if the existing connection broke
+    ///
+    /// Fails if the socket doesn't exist or refuses the connection; the
+    /// next alert tries again. A collector that stops reading fails the
+    /// send once the timeout passes, and its connection is dropped so the
+    /// next alert starts on a fresh one rather than after a partial line.
+    pub async fn send(&self, report: &AnomalyReport) -> Result<(), AlertError> {
+        let mut line = match &self.text {
+            Some(template) => template.render(report).into_bytes(),
+            None => serde_json::to_vec(report)?,
+        };
+        line.push(b'\n');
+
+        let mut stream = self.stream.lock().await;
+        match tokio::time::timeout(self.timeout, self.write_line(&mut stream, &line)).await {
+            Ok(result) => result,
+            Err(_) => {
+                log::debug!("Unix socket {:?} write timed out, dropping the connection", self.path);
+                *stream = None;
+                Err(AlertError::Timeout(self.timeout))
+            }
+        }
+    }
+
+    async fn write_line(&self, stream: &mut Option<UnixStream>, line: &[u8]) -> Result<(), AlertError> {
+        if let Some(existing) = stream.as_mut() {
+            if existing.write_all(line).await.is_ok() {
+                return Ok(());
+            }
+            log::debug!("Unix socket {:?} connection lost, reconnecting", self.path);
+            *stream = None;
+        }
+
+        let mut fresh = UnixStream::connect(&self.path).await?;
+        fresh.write_all(line).await?;
+        *stream = Some(fresh);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+    use tokio::net::UnixListener;
+
+    fn create_test_report() -> AnomalyReport {
+        AnomalyReport {
+            severity: 9,
+            rule_name: "Test Rule".to_string(),
+            user: "alice".to_string(),
+            detected_ip: "1.2.3.4".to_string(),
+            trusted_ip: String::new(),
+            timestamp: 1700000000,
+            detected_at: 1700000000,
+            description: "Test anomaly".to_string(),
+            metadata: BTreeMap::new(),
+            confidence: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reports_delivered_as_json_lines() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("alerts.sock");
+        let config = UnixSocketConfig {
+            path: path.clone(),
+            format: AlertFormat::Json,
+            timeout_secs: None,
+        };
+        let sink = UnixSocketSink::new(&config, &TextTemplate::from_config(&Default::default()));
+
+        // The collector isn't listening yet
+        assert!(sink.send(&create_test_report()).await.is_err());
+
+        let listener = UnixListener::bind(&path).unwrap();
+        sink.send(&create_test_report()).await.unwrap();
+        sink.send(&create_test_report()).await.unwrap();
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        for _ in 0..2 {
+            let line = lines.next_line().await.unwrap().unwrap();
+            let report: AnomalyReport = serde_json::from_str(&line).unwrap();
+            assert_eq!(report.rule_name, "Test Rule");
+            assert_eq!(report.user, "alice");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stalled_collector_times_out() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("alerts.sock");
+        let config = UnixSocketConfig {
+            path: path.clone(),
+            format: AlertFormat::Json,
+            timeout_secs: Some(1),
+        };
+        let sink = UnixSocketSink::new(&config, &TextTemplate::from_config(&Default::default()));
+
+        // A collector that accepts but never reads fills the socket buffer
+        let listener = UnixListener::bind(&path).unwrap();
+        let report = AnomalyReport {
+            description: "x".repeat(64 * 1024),
+            ..create_test_report()
+        };
+        let mut result = Ok(());
+        for _ in 0..64 {
+            result = sink.send(&report).await;
+            if result.is_err() {
+                break;
+            }
+        }
+        assert!(matches!(result, Err(AlertError::Timeout(_))));
+        assert!(sink.stream.lock().await.is_none());
+        drop(listener);
+    }
+}
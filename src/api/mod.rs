@@ -0,0 +1,260 @@
+//! Read-only HTTP API
+//!
+//! A minimal HTTP/1.1 server so integrations (e.g. a firewall-sync
+//! script) can query daemon state. Each connection serves one request.
+//!
+//! Routes:
+//! - `GET /lockouts`: currently active lockouts as a JSON array
+//! - `GET /metrics`: ingestion counters and alert channel circuit states
+//!   (whichever are attached)
+//! - `GET /maintenance`: whether maintenance mode is on (when attached)
+
+use crate::alerting::CircuitBreakers;
+use crate::detection::MaintenanceMode;
+use crate::input::IngestionStats;
+use crate::persistence::{run_blocking, StateStore};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Largest request head accepted, in bytes
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// Errors that can occur while running the API server
+#[derive(Error, Debug)]
+pub enum ApiError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// HTTP API server backed by the state store
+pub struct ApiServer {
+    listener: TcpListener,
+    store: Arc<dyn StateStore>,
+    stats: Option<Arc<IngestionStats>>,
+    breakers: Option<CircuitBreakers>,
+    maintenance: Option<MaintenanceMode>,
+}
+
+impl ApiServer {
+    /// Bind the API server to an address
+    pub async fn bind(address: &str, store: Arc<dyn StateStore>) -> Result<Self, ApiError> {
+        let listener = TcpListener::bind(address).await?;
+        Ok(ApiServer { listener, store, stats: None, breakers: None, maintenance: None })
+    }
+
+    /// Serve ingestion counters on `GET /metrics`
+    pub fn with_ingestion_stats(mut self, stats: Arc<IngestionStats>) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    /// Serve each alert channel's circuit state on `GET /metrics`
+    pub fn with_circuit_breakers(mut self, breakers: CircuitBreakers) -> Self {
+        self.breakers = Some(breakers);
+        self
+    }
+
+    /// Serve the maintenance mode state on `GET /maintenance`
+    pub fn with_maintenance_mode(mut self, mode: MaintenanceMode) -> Self {
+        self.maintenance = Some(mode);
+        self
+    }
+
+    /// Address the server is listening on
+    pub fn local_addr(&self) -> Result<SocketAddr, ApiError> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Accept and serve connections until the task is cancelled
+    pub async fn run(self) -> Result<(), ApiError> {
+        log::info!("API listening on {}", self.listener.local_addr()?);
+        loop {
+            let (stream, peer) = self.listener.accept().await?;
+            let store = self.store.clone();
+            let stats = self.stats.clone();
+            let breakers = self.breakers.clone();
+            let maintenance = self.maintenance.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle_connection(stream, store, stats, breakers, maintenance).await {
+                    log::debug!("API connection from {} failed: {}", peer, e);
+                }
+            });
+        }
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    store: Arc<dyn StateStore>,
+    stats: Option<Arc<IngestionStats>>,
+    breakers: Option<CircuitBreakers>,
+    maintenance: Option<MaintenanceMode>,
+) -> Result<(), ApiError> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 || buf.len() + n > MAX_REQUEST_SIZE {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    let head = String::from_utf8_lossy(&buf);
+    let mut parts = head.lines().next().unwrap_or("").split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("");
+
+    let (status, body) = route(
+        method,
+        path,
+        store.as_ref(),
+        stats.as_deref(),
+        breakers.as_ref(),
+        maintenance.as_ref(),
+    );
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Produce the status line and JSON body for a request
+fn route(
+    method: &str,
+    path: &str,
+    store: &dyn StateStore,
+    stats: Option<&IngestionStats>,
+    breakers: Option<&CircuitBreakers>,
+    maintenance: Option<&MaintenanceMode>,
+) -> (&'static str, String) {
+    let path = path.split('?').next().unwrap_or(path);
+    match (method, path) {
+        ("GET", "/lockouts") => {
+            let now = chrono::Utc::now().timestamp();
+            match run_blocking(|| store.get_active_lockouts(now)) {
+                Ok(lockouts) => match serde_json::to_string(&lockouts) {
+                    Ok(json) => ("200 OK", json),
+                    Err(e) => ("500 Internal Server Error", error_body(&e.to_string())),
+                },
+                Err(e) => ("500 Internal Server Error", error_body(&e.to_string())),
+            }
+        }
+        ("GET", "/metrics") if stats.is_some() || breakers.is_some() => {
+            let mut body = serde_json::Map::new();
+            if let Some(stats) = stats {
+                let snapshot = stats.snapshot();
+                body.insert(
+                    "ingestion".to_string(),
+                    serde_json::json!({
+                        "lines_read": snapshot.lines_read,
+                        "lines_parsed": snapshot.lines_parsed,
+                        "lines_skipped": snapshot.lines_skipped,
+                        "parse_failures": snapshot.parse_failures,
+                        "failure_ratio": snapshot.failure_ratio(),
+                    }),
+                );
+            }
+            if let Some(breakers) = breakers {
+                // Channels appear once they have been used
+                let channels: serde_json::Map<String, serde_json::Value> = breakers
+                    .states()
+                    .into_iter()
+                    .map(|(channel, state)| (channel, state.to_string().into()))
+                    .collect();
+                body.insert("alert_channels".to_string(), channels.into());
+            }
+            ("200 OK", serde_json::Value::Object(body).to_string())
+        }
+        ("GET", "/metrics") => ("404 Not Found", error_body("not found")),
+        ("GET", "/maintenance") => match maintenance {
+            Some(mode) => ("200 OK", serde_json::json!({ "active": mode.is_active() }).to_string()),
+            None => ("404 Not Found", error_body("not found")),
+        },
+        (_, "/lockouts") | (_, "/metrics") | (_, "/maintenance") => {
+            ("405 Method Not Allowed", error_body("method not allowed"))
+        }
+        _ => ("404 Not Found", error_body("not found")),
+    }
+}
+
+fn error_body(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Lockout, LockoutKind};
+    use crate::persistence::SqliteStateStore;
+
+    async fn request(addr: SocketAddr, raw: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(raw.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_get_lockouts_returns_active_only() {
+        let store = Arc::new(SqliteStateStore::in_memory().unwrap());
+        let now = chrono::Utc::now().timestamp();
+        for (subject, expires_at) in [("10.0.0.1", now + 600), ("10.0.0.2", now - 600)] {
+            store
+                .add_lockout(&Lockout {
+                    kind: LockoutKind::Ip,
+                    subject: subject.to_string(),
+                    reason: "brute force".to_string(),
+                    locked_at: now - 1200,
+                    expires_at,
+                })
+                .unwrap();
+        }
+
+        let server = ApiServer::bind("127.0.0.1:0", store).await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.run());
+
+        let response = request(addr, "GET /lockouts HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        let lockouts: Vec<Lockout> = serde_json::from_str(body).unwrap();
+        assert_eq!(lockouts.len(), 1);
+        assert_eq!(lockouts[0].subject, "10.0.0.1");
+
+        let response = request(addr, "GET /nope HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 404"));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_include_circuit_states() {
+        let store = Arc::new(SqliteStateStore::in_memory().unwrap());
+        let breakers = CircuitBreakers::new(2, std::time::Duration::from_secs(60));
+        breakers.record_failure("webhook");
+        breakers.record_failure("webhook");
+        breakers.record_failure("discord");
+
+        let server = ApiServer::bind("127.0.0.1:0", store)
+            .await
+            .unwrap()
+            .with_circuit_breakers(breakers);
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.run());
+
+        let response = request(addr, "GET /metrics HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        let body: serde_json::Value = serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(body["alert_channels"]["webhook"], "open");
+        assert_eq!(body["alert_channels"]["discord"], "closed");
+        assert!(body.get("ingestion").is_none());
+    }
+}
@@ -2,6 +2,7 @@ use std::path::PathBuf;
 use structopt::StructOpt;
 
 use odin::config::Config;
+use odin::persistence::{parse_age, run_maintenance, seed_baselines, Baselines, SqliteStateStore};
 
 /// Intrusion Detection System (ISDS) Command Line Interface
 #[derive(StructOpt, Debug)]
@@ -9,9 +10,10 @@ use odin::config::Config;
 pub enum Cli {
     /// Run the daemon
     Daemon {
-        /// Path to configuration file
-        #[structopt(short, long, default_value = "config.toml")]
-        config: PathBuf,
+        /// Path to configuration file; repeat to layer overrides, later
+        /// files taking precedence
+        #[structopt(short, long, default_value = "config.toml", number_of_values = 1)]
+        config: Vec<PathBuf>,
     },
     /// Generate a default configuration file
     Config {
@@ -28,6 +30,30 @@ pub enum Cli {
         #[structopt(short, long, default_value = "10")]
         lines: usize,
     },
+    /// Prune and compact the state database
+    Maintenance {
+        /// Path to the state database
+        #[structopt(long)]
+        db: PathBuf,
+        /// Remove tracking data older than this age (e.g. 12h, 7d)
+        #[structopt(long)]
+        prune_before: Option<String>,
+        /// Rebuild the database file to reclaim free space
+        #[structopt(long)]
+        compact: bool,
+    },
+    /// Load per-user baselines (trusted IP, location) into the state database
+    Seed {
+        /// Path to the state database (created if missing)
+        #[structopt(long)]
+        db: PathBuf,
+        /// JSON baselines file
+        #[structopt(long)]
+        from: PathBuf,
+        /// Replace baselines of users that already have stored state
+        #[structopt(long)]
+        overwrite: bool,
+    },
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -38,15 +64,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             println!("Starting ISDS daemon with config: {:?}", config);
             // In a real implementation, this would start the daemon
             // For now, just show that the config file exists
-            if config.exists() {
-                let _config = Config::from_file(&config)?;
-                println!("Configuration loaded successfully");
-                println!("Use 'isds-daemon' binary to run the daemon");
-            } else {
-                eprintln!("Configuration file not found: {:?}", config);
+            if let Some(missing) = config.iter().find(|path| !path.exists()) {
+                eprintln!("Configuration file not found: {:?}", missing);
                 eprintln!("Run 'isds config' to generate a default configuration");
                 std::process::exit(1);
             }
+            let _config = Config::from_files(&config)?;
+            println!("Configuration loaded successfully");
+            println!("Use 'isds-daemon' binary to run the daemon");
         }
         Cli::Config { output } => {
             let config = Config::default();
@@ -75,6 +100,45 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 );
             }
         }
+        Cli::Maintenance { db, prune_before, compact } => {
+            if !db.exists() {
+                eprintln!("Database not found: {:?}", db);
+                std::process::exit(1);
+            }
+
+            let cutoff = match prune_before {
+                Some(age) => Some(chrono::Utc::now().timestamp() - parse_age(&age)?),
+                None => None,
+            };
+
+            let summary = match run_maintenance(&db, cutoff, compact) {
+                Ok(summary) => summary,
+                Err(e) if e.is_locked() => {
+                    eprintln!("Database {:?} is locked, is the daemon running? ({})", db, e);
+                    std::process::exit(1);
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            println!("Rows removed: {}", summary.rows_removed);
+            println!("Size before: {} bytes", summary.size_before);
+            println!("Size after:  {} bytes", summary.size_after);
+        }
+        Cli::Seed { db, from, overwrite } => {
+            let baselines = Baselines::from_file(&from)?;
+            let store = match SqliteStateStore::new(&db) {
+                Ok(store) => store,
+                Err(e) if e.is_locked() => {
+                    eprintln!("Database {:?} is locked, is the daemon running? ({})", db, e);
+                    std::process::exit(1);
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            let summary = seed_baselines(&store, &baselines, overwrite)?;
+            println!("Users seeded: {}", summary.seeded);
+            println!("Users skipped (already known): {}", summary.skipped);
+        }
     }
 
     Ok(())
@@ -7,17 +7,34 @@ use std::path::PathBuf;
 use std::sync::Arc;
 use std::env;
 
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinSet;
 use tokio::time::{interval, Duration};
 
-use odin::config::Config;
-use odin::detection::{IdentityContext, GeoVelocityTracker, LoginRateLimiter};
+use odin::config::{Config, DetectionRule, EnrichmentStage, InputConfig, ProcessingMode, SourceSpec};
+use odin::detection::{
+    DetectionEngine, HostingAsnDetector, LastSeen, MaintenanceMode, RiskCorrelator, SeverityEscalator, UserReportThrottle,
+};
 use odin::models::{LogEvent, AnomalyReport};
-use odin::input::{AsyncFileTailer, AsyncSyslogListener};
-use odin::output::{OutputHandler, OutputFormat};
-use odin::geolocation::GeoIpService;
-use odin::persistence::SqliteStateStore;
-use odin::alerting::{AlertDispatcher, AlertQueue};
+use odin::input::{
+    AsyncFifoReader, AsyncFileTailer, AsyncStdinReader, AsyncSyslogListener, AsyncTcpSyslogListener, ClockGuard, EventAgeFilter, EventClassifier, EventDeduplicator, EventReorderBuffer, EventSampler,
+    IngestionSnapshot, IngestionStats, LineParser, UsernameNormalizer, CLOCK_STEP,
+};
+use odin::output::{OutputSinks, SuppressionAudit};
+use odin::geolocation::{
+    open_geo_service, AsnLookup, AsnService, DnsResolver, GeoLookup, ReverseDnsEnricher,
+};
+use odin::persistence::{
+    expand_database_path, is_templated, run_blocking, seed_baselines, AsyncStateStore, Baselines, ReportStores, SqliteStateStore,
+    StateStore, StoreHealth,
+};
+use odin::alerting::{AlertDispatcher, AlertQueue, CircuitState, Heartbeat};
+use odin::processing::{EnrichedEvent, EnrichmentPipeline, PipelineClock, WorkerPool};
+use odin::api::ApiServer;
+use odin::telemetry::{EventTrace, EventTracer};
+
+/// Events buffered per input source before they join the shared channel
+const SOURCE_CHANNEL_CAPACITY: usize = 100;
 
 /// Main daemon entry point
 #[tokio::main]
@@ -29,32 +46,68 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     log::info!("Starting ISDS Daemon (async)...");
 
+    // Parse command line: [config.toml] [--config FILE]... [--stdin] [--explain] [--replay]
+    let args: Vec<String> = env::args().skip(1).collect();
+    let read_stdin = args.iter().any(|a| a == "--stdin");
+    let explain = args.iter().any(|a| a == "--explain");
+    let replay = args.iter().any(|a| a == "--replay");
+
+    // Config files in the order given; later files override earlier ones
+    let mut config_paths = Vec::new();
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        if arg == "--config" {
+            let path = rest.next().ok_or("--config requires a path")?;
+            config_paths.push(PathBuf::from(path));
+        } else if !arg.starts_with("--") {
+            config_paths.push(PathBuf::from(arg));
+        }
+    }
+
+    if config_paths.is_empty() {
+        config_paths.push(PathBuf::from("config.toml"));
+    }
+
     // Load configuration
-    let config_path = env::args()
-        .nth(1)
-        .map(PathBuf::from)
-        .unwrap_or_else(|| PathBuf::from("config.toml"));
-
-    let config = if config_path.exists() {
-        log::info!("Loading configuration from {:?}", config_path);
-        Config::from_file(&config_path)?
-    } else {
-        log::warn!("Config file not found at {:?}, using defaults", config_path);
-        Config::default()
+    let mut config = match config_paths.as_slice() {
+        [config_path] if !config_path.exists() => {
+            log::warn!("Config file not found at {:?}, using defaults", config_path);
+            Config::default()
+        }
+        _ => {
+            log::info!("Loading configuration from {:?}", config_paths);
+            Config::from_files(&config_paths)?
+        }
     };
 
+    if read_stdin {
+        config.input.source_type = "stdin".to_string();
+        config.input.sources.clear();
+        config.input.exit_on_eof = true;
+    }
+    if explain {
+        config.detection.explain = true;
+    }
+    if replay {
+        config.detection.processing_mode = ProcessingMode::Replay;
+    }
+
     // Initialize persistence
-    let state_store = if config.persistence.enabled {
-        let db_path = config
-            .persistence
-            .database_path
-            .as_deref()
-            .unwrap_or(std::path::Path::new("odin_state.db"));
+    let db_template = config
+        .persistence
+        .database_path
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("odin_state.db"));
+    let mut db_path = expand_database_path(&db_template, chrono::Utc::now().date_naive());
+    let db_rollover = config.persistence.rollover && is_templated(&db_template);
 
-        match SqliteStateStore::new(db_path) {
+    let state_store = if config.persistence.enabled {
+        match SqliteStateStore::new(&db_path) {
             Ok(store) => {
                 log::info!("Persistence initialized at {:?}", db_path);
-                Some(Arc::new(store))
+                Some(Arc::new(
+                    store.with_location_dedup(config.persistence.dedup_locations),
+                ))
             }
             Err(e) => {
                 log::error!("Failed to initialize persistence: {}", e);
@@ -67,22 +120,78 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         None
     };
 
+    if let (Some(store), Some(seed_file)) = (&state_store, &config.persistence.seed_file) {
+        match Baselines::from_file(seed_file)
+            .and_then(|baselines| seed_baselines(store.as_ref(), &baselines, false))
+        {
+            Ok(summary) => log::info!(
+                "Seeded {} user baseline(s) from {:?} ({} already known)",
+                summary.seeded,
+                seed_file,
+                summary.skipped
+            ),
+            Err(e) => log::warn!("Failed to seed baselines from {:?}: {}", seed_file, e),
+        }
+    }
+
+    // Counters shared by the input sources
+    let ingestion_stats = Arc::new(IngestionStats::new());
+
+    // Maintenance mode: rules keep learning but no reports are produced
+    let maintenance = match &config.detection.maintenance_control_file {
+        Some(path) => MaintenanceMode::new().with_control_file(path.clone()),
+        None => MaintenanceMode::new(),
+    };
+    maintenance.poll_control_file();
+    if maintenance.is_active() {
+        log::warn!("Starting in maintenance mode: reports are suppressed");
+    }
+
     // Initialize geolocation service
-    let geo_service = if config.detection.geo_location.enabled {
+    let geo = &config.detection.geo_location;
+    let geo_service = match open_geo_service(geo).await {
+        Ok(Some(service)) => {
+            log::info!("GeoIP service initialized from {:?}", geo.database_path);
+            if geo.anonymize_prefix_v4.is_some() || geo.anonymize_prefix_v6.is_some() {
+                log::info!(
+                    "GeoIP lookups anonymized (IPv4 /{}, IPv6 /{})",
+                    geo.anonymize_prefix_v4.unwrap_or(32),
+                    geo.anonymize_prefix_v6.unwrap_or(128)
+                );
+            }
+            Some(service.with_anonymization(geo.anonymize_prefix_v4, geo.anonymize_prefix_v6))
+        }
+        Ok(None) => None,
+        Err(e) => {
+            log::error!("GeoIP database unavailable: {}", e);
+            return Err(format!(
+                "GeoIP database unavailable ({}); set detection.geo_location.on_missing_database = \"warn\" \
+                 to start without geolocation",
+                e
+            )
+            .into());
+        }
+    };
+
+    // Initialize the ASN service, shared by enrichment and hosting provider detection
+    let hosting_asn_enabled = config.detection.rule_enabled(DetectionRule::HostingAsn);
+    let asn_service: Option<Arc<dyn AsnLookup>> = if hosting_asn_enabled
+        || config.detection.enrichment_stages.contains(&EnrichmentStage::Asn)
+    {
         config
             .detection
-            .geo_location
+            .hosting_asn
             .database_path
             .as_ref()
             .and_then(|path| {
-                match GeoIpService::new(path) {
+                match AsnService::new(path) {
                     Ok(service) => {
-                        log::info!("GeoIP service initialized from {:?}", path);
-                        Some(service)
+                        log::info!("ASN service initialized from {:?}", path);
+                        Some(Arc::new(service) as Arc<dyn AsnLookup>)
                     }
                     Err(e) => {
-                        log::warn!("Failed to initialize GeoIP service: {}", e);
-                        log::warn!("Geo-velocity detection will be disabled");
+                        log::warn!("Failed to initialize ASN service: {}", e);
+                        log::warn!("Hosting provider detection and ASN enrichment will be disabled");
                         None
                     }
                 }
@@ -90,11 +199,81 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     } else {
         None
     };
+    let hosting_asn_detector = asn_service
+        .clone()
+        .filter(|_| hosting_asn_enabled)
+        .map(|lookup| HostingAsnDetector::new(&config.detection.hosting_asn, lookup));
+
+    let reverse_dns_enricher = if config.detection.reverse_dns.enabled {
+        match DnsResolver::from_system_conf() {
+            Ok(resolver) => {
+                log::info!(
+                    "Reverse DNS enrichment enabled (timeout: {}ms)",
+                    config.detection.reverse_dns.timeout_ms
+                );
+                if !config.detection.enrichment_stages.contains(&EnrichmentStage::ReverseDns) {
+                    log::warn!("Reverse DNS is enabled but \"reverse_dns\" is not in detection.enrichment_stages");
+                }
+                Some(Arc::new(ReverseDnsEnricher::new(&config.detection.reverse_dns, Arc::new(resolver))))
+            }
+            Err(e) => {
+                log::error!("Reverse DNS enrichment disabled, failed to read the system DNS configuration: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let enrichment = EnrichmentPipeline::from_config(
+        &config.detection.enrichment_stages,
+        geo_service.clone().map(|geo| Arc::new(geo) as Arc<dyn GeoLookup>),
+        asn_service,
+        reverse_dns_enricher,
+    );
+    if !enrichment.is_empty() {
+        log::info!("Enrichment stages: {:?}", config.detection.enrichment_stages);
+    }
+
+    // Trace event processing to an OpenTelemetry collector
+    let tracer = EventTracer::from_config(&config.telemetry)?;
+    if tracer.is_enabled() {
+        log::info!("Exporting traces to {}", config.telemetry.otlp_endpoint);
+    }
 
     // Initialize alerting
     let (alert_tx, alert_rx) = AlertDispatcher::create_channel();
     let alert_queue = AlertQueue::new(alert_tx);
-    let alert_dispatcher = AlertDispatcher::new(config.alerting.clone()).0;
+    let mut alert_dispatcher = AlertDispatcher::new(config.alerting.clone()).0;
+    if let Some(store) = state_store.as_ref().filter(|_| config.alerting.pending.enabled) {
+        alert_dispatcher = alert_dispatcher.with_pending_store(store.clone());
+    }
+    let alert_breakers = alert_dispatcher.circuit_breakers();
+
+    // Start the HTTP API
+    if config.api.enabled {
+        match state_store {
+            Some(ref store) => {
+                let store: Arc<dyn StateStore> = store.clone();
+                match ApiServer::bind(&config.api.bind_address, store).await {
+                    Ok(server) => {
+                        let server = server
+                            .with_ingestion_stats(ingestion_stats.clone())
+                            .with_circuit_breakers(alert_breakers.clone())
+                            .with_maintenance_mode(maintenance.clone());
+                        tokio::spawn(async move {
+                            if let Err(e) = server.run().await {
+                                log::error!("API server error: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        log::error!("Failed to start API on {}: {}", config.api.bind_address, e);
+                    }
+                }
+            }
+            None => log::warn!("API enabled but persistence is unavailable, not starting it"),
+        }
+    }
 
     // Spawn alert dispatcher task
     tokio::spawn(async move {
@@ -106,144 +285,385 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             "Alerting enabled (min severity: {})",
             config.alerting.min_severity
         );
+        if !config.alerting.always_alert_rules.is_empty() {
+            log::info!(
+                "Always alerting for rules: {}",
+                config.alerting.always_alert_rules.join(", ")
+            );
+        }
+        if config.alerting.pending.enabled && state_store.is_none() {
+            log::warn!("Persistence is unavailable, undelivered alerts will not be retried after restart");
+        }
     }
 
-    // Initialize output handler
-    let output_format = OutputFormat::from_str(&config.output.format);
-    let output_handler = Arc::new(tokio::sync::Mutex::new(
-        OutputHandler::new(output_format, config.output.file_path.clone())?
-    ));
-    log::info!("Output handler initialized (format: {})", config.output.format);
+    // Liveness pings run independently of alerting
+    if let Some(heartbeat) = &config.alerting.heartbeat {
+        Heartbeat::new(heartbeat.clone(), ingestion_stats.clone()).spawn();
+    }
 
-    // Initialize detection components
-    let identity_context = Arc::new(tokio::sync::Mutex::new(
-        if let Some(ref store) = state_store {
-            IdentityContext::with_persistence(store.clone())
-        } else {
-            IdentityContext::new()
-        }
-    ));
-
-    let geo_velocity_tracker = Arc::new(tokio::sync::Mutex::new(
-        if let Some(ref store) = state_store {
-            GeoVelocityTracker::with_persistence(
-                config.detection.geo_velocity.max_velocity_kmh,
-                store.clone(),
-            )
-        } else {
-            GeoVelocityTracker::with_max_velocity(config.detection.geo_velocity.max_velocity_kmh)
-        }
-    ));
-
-    let rate_limiter = Arc::new(tokio::sync::Mutex::new(
-        if let Some(ref store) = state_store {
-            LoginRateLimiter::with_persistence(
-                config.detection.rate_limit.window_seconds,
-                config.detection.rate_limit.max_user_attempts,
-                config.detection.rate_limit.max_ip_attempts,
-                store.clone(),
-            )
-        } else {
-            LoginRateLimiter::with_config(
-                config.detection.rate_limit.window_seconds,
-                config.detection.rate_limit.max_user_attempts,
-                config.detection.rate_limit.max_ip_attempts,
-            )
+    // Initialize output sinks
+    let output_handler = Arc::new(tokio::sync::Mutex::new(OutputSinks::from_config(&config.output)?));
+
+    // Flush buffered output periodically even when no new reports arrive
+    if config.output.flush_interval_ms > 0 {
+        let output_handler = output_handler.clone();
+        let mut flush_interval = interval(Duration::from_millis(config.output.flush_interval_ms));
+        tokio::spawn(async move {
+            loop {
+                flush_interval.tick().await;
+                if let Err(e) = output_handler.lock().await.flush_if_due() {
+                    log::warn!("Failed to flush output: {}", e);
+                }
+            }
+        });
+    }
+    for sink in config.output.sink_specs() {
+        log::info!("Output sink initialized (format: {}, min severity: {})", sink.format, sink.min_severity);
+    }
+
+    // Reports are resolved through the reverse DNS stage's resolver, off the event path
+    let reverse_dns = enrichment.reverse_dns().cloned().map(ReportEnrichment::new);
+    let escalator = if config.detection.escalation.escalation_step > 0 {
+        log::info!(
+            "Severity escalation enabled (+{} per repeat within {}s, cap {})",
+            config.detection.escalation.escalation_step,
+            config.detection.escalation.window_seconds,
+            config.detection.escalation.escalation_cap
+        );
+        Some(Arc::new(std::sync::Mutex::new(
+            SeverityEscalator::new(&config.detection.escalation)
+                .with_max_tracked(config.detection.max_tracked_entries),
+        )))
+    } else {
+        None
+    };
+    let risk_correlator = if config.detection.risk.enabled {
+        log::info!(
+            "Risk correlation enabled (window: {}s, min score: {})",
+            config.detection.risk.correlation_window_seconds,
+            config.detection.risk.min_score
+        );
+        Some(Arc::new(std::sync::Mutex::new(
+            RiskCorrelator::new(&config.detection.risk)
+                .with_max_tracked(config.detection.max_tracked_entries),
+        )))
+    } else {
+        None
+    };
+    // Store errors during detection fall back to in-memory state and are
+    // counted here
+    let store_health = Arc::new(StoreHealth::new());
+    let report_throttle = config.detection.min_report_interval_per_user_seconds.map(|interval| {
+        log::info!("Emitting at most one report per user every {}s unless more severe", interval);
+        let mut throttle = UserReportThrottle::new(interval)
+            .with_max_tracked(config.detection.max_tracked_entries)
+            .with_store_health(store_health.clone());
+        if let Some(store) = state_store.as_ref().filter(|_| config.detection.persist_report_throttle) {
+            throttle = throttle.with_persistence(store.clone());
         }
-    ));
+        Arc::new(std::sync::Mutex::new(throttle))
+    });
+    let mut report_stores = ReportStores::new(state_store.clone().map(|store| store as Arc<dyn StateStore>))
+        .with_min_severity(config.persistence.min_severity_to_store);
+    for sink in &config.persistence.report_sinks {
+        match SqliteStateStore::new(&sink.database_path) {
+            Ok(store) => {
+                log::info!("Storing a copy of each report in {:?}", sink.database_path);
+                report_stores = report_stores.with_sink(
+                    sink.database_path.display().to_string(),
+                    Arc::new(store),
+                    sink.min_severity,
+                );
+            }
+            Err(e) => log::error!("Failed to open report sink {:?}: {}", sink.database_path, e),
+        }
+    }
+    let report_handler = ReportHandler {
+        output_handler: output_handler.clone(),
+        alert_queue: alert_queue.clone(),
+        state_store: state_store.clone().map(|store| AsyncStateStore::new(store)),
+        report_stores: Arc::new(report_stores),
+        reverse_dns,
+        escalator: escalator.clone(),
+        risk_correlator: risk_correlator.clone(),
+        maintenance: maintenance.clone(),
+    };
+
+    // Initialize detection components
+    let mut sampler = EventSampler::new(&config.input.sample_rates);
+    let geo_available = geo_service.is_some();
+    let asn_available = hosting_asn_detector.is_some();
+    let mut engine = DetectionEngine::new(&config.detection)?
+        .with_event_weights(sampler.scale_weights(&config.detection.rate_limit.event_weights))
+        .with_unknown_user_policy(config.input.unknown_user)
+        .with_maintenance_mode(maintenance.clone());
+    if let Some(ref store) = state_store {
+        engine = engine.with_persistence(store.clone(), store_health.clone());
+    }
+    if let Some(geo_service) = geo_service {
+        engine = engine.with_geo_service(geo_service);
+    }
+    if let Some(detector) = hosting_asn_detector {
+        engine = engine.with_hosting_asn(detector);
+    }
+    let engine = Arc::new(tokio::sync::Mutex::new(engine));
 
     log::info!("Detection rules initialized:");
-    log::info!("  - IP switch detection: {}", config.detection.enable_ip_switch);
+    if config.detection.explain {
+        log::info!("  - Explain mode enabled");
+    }
+    log::info!("  - IP switch detection: {}", config.detection.rule_enabled(DetectionRule::IpSwitch));
     log::info!("  - Geo velocity detection: {} (GeoIP: {})",
-        config.detection.enable_geo_velocity,
-        geo_service.is_some()
+        config.detection.rule_enabled(DetectionRule::GeoVelocity),
+        geo_available
+    );
+    log::info!("  - Hosting provider detection: {} (ASN DB: {})",
+        config.detection.rule_enabled(DetectionRule::HostingAsn),
+        asn_available
+    );
+    log::info!("  - Attacking IP detection: {} (window: {}s, min failed users: {})",
+        config.detection.rule_enabled(DetectionRule::AttackingIp),
+        config.detection.attacking_ip.window_seconds,
+        config.detection.attacking_ip.min_failed_users
+    );
+    log::info!("  - Success cluster detection: {} (window: {}s, min users: {})",
+        config.detection.rule_enabled(DetectionRule::SuccessCluster),
+        config.detection.success_cluster.window_seconds,
+        config.detection.success_cluster.min_users
+    );
+    log::info!("  - Off-hours detection: {} ({:02}:00-{:02}:00, default timezone: {})",
+        config.detection.rule_enabled(DetectionRule::OffHours),
+        config.detection.off_hours.start_hour,
+        config.detection.off_hours.end_hour,
+        config.detection.off_hours.default_timezone
+    );
+    log::info!("  - Home region detection: {} (home: {}, {}, radius: {} km)",
+        config.detection.rule_enabled(DetectionRule::HomeRegion),
+        config.detection.home_region.latitude,
+        config.detection.home_region.longitude,
+        config.detection.home_region.radius_km
+    );
+    log::info!("  - Login hour pattern detection: {} (min history: {}, max hour share: {})",
+        config.detection.rule_enabled(DetectionRule::HourPattern),
+        config.detection.hour_pattern.min_history,
+        config.detection.hour_pattern.max_hour_share
+    );
+    log::info!("  - First seen user detection: {}",
+        config.detection.rule_enabled(DetectionRule::FirstSeen)
+    );
+    log::info!("  - Login during lockout detection: {} (severity: {})",
+        config.detection.rule_enabled(DetectionRule::Lockout),
+        config.detection.lockout.severity
     );
     log::info!("  - Rate limiting: {} (window: {}s, max user: {}, max IP: {})",
-        config.detection.enable_rate_limiting,
+        config.detection.rule_enabled(DetectionRule::RateLimit),
         config.detection.rate_limit.window_seconds,
         config.detection.rate_limit.max_user_attempts,
         config.detection.rate_limit.max_ip_attempts
     );
 
-    // Create event channel
-    let (event_tx, mut event_rx) = mpsc::channel::<LogEvent>(1000);
+    // Create event channel, carrying each event's source index
+    let (event_tx, mut event_rx) = mpsc::channel::<(usize, LogEvent)>(1000);
 
-    // Spawn input source task
-    match config.input.source_type.as_str() {
-        "file" => {
-            if let Some(ref path) = config.input.file_path {
-                let path = path.clone();
-                let tx = event_tx.clone();
-                tokio::spawn(async move {
-                    let mut tailer = AsyncFileTailer::new(path.clone());
-                    if let Err(e) = tailer.run(tx).await {
-                        log::error!("File tailer error: {}", e);
-                    }
-                });
-                log::info!("Monitoring log file: {:?}", config.input.file_path);
-            } else {
-                log::warn!("File source type selected but no file path configured");
-            }
-        }
-        "syslog" => {
-            if let Some(ref address) = config.input.syslog_address {
-                let addr = address.clone();
-                let tx = event_tx.clone();
-                tokio::spawn(async move {
-                    match AsyncSyslogListener::new(&addr).await {
-                        Ok(mut listener) => {
-                            if let Err(e) = listener.run(tx).await {
-                                log::error!("Syslog listener error: {}", e);
-                            }
-                        }
-                        Err(e) => {
-                            log::error!("Failed to create syslog listener: {}", e);
-                        }
-                    }
-                });
-                log::info!("Listening on syslog: {}", address);
-            } else {
-                log::warn!("Syslog source type selected but no address configured");
-            }
-        }
-        _ => {
-            log::warn!("Unknown input source type: {}", config.input.source_type);
+    // Spawn a task per input source, each parsing with its own format
+    let classifier = EventClassifier::from_config(&config.input)
+        .with_lockout_events(config.detection.rule_enabled(DetectionRule::Lockout));
+    for (source_id, source) in config.input.source_specs().into_iter().enumerate() {
+        let parser = LineParser::from_source(&source, classifier.clone())?
+            .with_host_extraction(config.input.extract_host);
+        spawn_source(
+            &source,
+            parser,
+            tag_source(source_id, event_tx.clone()),
+            ingestion_stats.clone(),
+            &config.input,
+        );
+    }
+
+    let normalizer = UsernameNormalizer::new(config.input.username_normalization.clone());
+    if normalizer.is_enabled() {
+        log::info!("Username normalization: {:?}", config.input.username_normalization);
+    }
+
+    if let Some(ref types) = config.input.process_event_types {
+        log::info!("Processing only event types: {}", types.join(", "));
+    }
+    if sampler.is_enabled() {
+        log::info!("Sampling event types: {:?}", config.input.sample_rates);
+    }
+    // Old events are expected when replaying, so their age isn't checked
+    let replaying = config.detection.processing_mode == ProcessingMode::Replay;
+    if replaying && config.input.max_event_age_seconds.is_some() {
+        log::warn!("max_event_age_seconds is ignored in replay mode");
+    }
+    let max_event_age = config.input.max_event_age_seconds.filter(|_| !replaying);
+    if let Some(max_age) = max_event_age {
+        log::info!("Dropping events older than {}s", max_age);
+    }
+    let mut age_filter = EventAgeFilter::new(max_event_age);
+    let mut deduplicator = EventDeduplicator::new(config.input.dedup_window_seconds);
+    if let Some(window) = config.input.dedup_window_seconds {
+        log::info!("Dropping repeated events within {}s across sources", window);
+    }
+    let mut suppression_audit = match config.input.suppression_audit_file {
+        Some(ref path) => {
+            log::info!("Recording suppressed events to {:?}", path);
+            Some(SuppressionAudit::open(path)?)
         }
+        None => None,
+    };
+    let mut reorder = EventReorderBuffer::new(config.input.reorder_delay_seconds)
+        .with_max_events(config.input.reorder_max_events);
+    if let Some(delay) = config.input.reorder_delay_seconds {
+        log::info!("Holding events {}s to process them in timestamp order", delay);
     }
 
     // Drop the original sender so the channel closes when tasks complete
     drop(event_tx);
 
+    let clock = Arc::new(PipelineClock::new(config.detection.processing_mode));
+    if clock.mode() == ProcessingMode::Replay {
+        log::info!("Replay mode: cooldowns and pruning follow event timestamps");
+    }
+
+    let clock_guard = config.input.clock_guard.enabled.then(|| {
+        Arc::new(std::sync::Mutex::new(
+            ClockGuard::new(&config.input.clock_guard).with_timestamp_jumps(clock.mode() != ProcessingMode::Replay),
+        ))
+    });
+
+    let processor = Arc::new(EventProcessor {
+        config: config.clone(),
+        engine: engine.clone(),
+        enrichment,
+        tracer: tracer.clone(),
+        report_handler: report_handler.clone(),
+        report_throttle: report_throttle.clone(),
+        store_health: store_health.clone(),
+        clock_guard: clock_guard.clone(),
+        clock: clock.clone(),
+    });
+
+    // Process events on a worker pool sharded by user (ordered per user
+    // only), or inline. The workers share the engine lock, so only the
+    // work around detection runs concurrently
+    let worker_pool = if config.detection.processing_workers > 1 {
+        let processor = processor.clone();
+        log::info!("Processing events on {} workers (detection serialized)", config.detection.processing_workers);
+        Some(WorkerPool::spawn(config.detection.processing_workers, move |event| {
+            let processor = processor.clone();
+            async move { processor.process(&event).await }
+        }))
+    } else {
+        None
+    };
+
     // Setup graceful shutdown
     log::info!("Daemon running. Press Ctrl+C to stop.");
 
     // Periodic maintenance interval (every 60 seconds)
     let mut maintenance_interval = interval(Duration::from_secs(60));
+    let mut control_file_interval = interval(Duration::from_secs(1));
+    let mut reorder_interval = interval(Duration::from_secs(1));
+    let mut maintenance_signal = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined1())?;
+    let mut config_dump_signal = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined2())?;
+    let mut last_ingestion = ingestion_stats.snapshot();
+    let mut last_store_errors = 0;
 
     // Main event loop
+    let mut input_open = true;
     loop {
         tokio::select! {
             // Process incoming events
-            Some(event) = event_rx.recv() => {
-                process_event(
-                    &event,
-                    &config,
-                    &identity_context,
-                    &geo_velocity_tracker,
-                    &rate_limiter,
-                    &output_handler,
-                    geo_service.as_ref(),
-                    &alert_queue,
-                    state_store.as_ref(),
-                ).await;
+            received = event_rx.recv(), if input_open => {
+                let Some((source_id, mut event)) = received else {
+                    // All input sources have finished
+                    input_open = false;
+                    if config.input.exit_on_eof {
+                        log::info!("Input closed, shutting down");
+                        break;
+                    }
+                    log::warn!("Input closed, waiting for shutdown signal");
+                    continue;
+                };
+
+                if !age_filter.should_process(&event, chrono::Utc::now().timestamp()) {
+                    log::trace!("Dropping stale event (timestamp: {}, user: {})", event.timestamp, event.user);
+                    continue;
+                }
+                if !deduplicator.should_process(&event, source_id, chrono::Utc::now().timestamp()) {
+                    log::trace!("Dropping duplicate event (timestamp: {}, user: {})", event.timestamp, event.user);
+                    continue;
+                }
+                clock.observe(&event);
+                if let Some(guard) = &clock_guard {
+                    let jump = guard.lock().unwrap().observe(&event, clock.received_at(&event));
+                    if let Some(jump) = jump {
+                        match jump.skew_seconds {
+                            Some(skew) => log::warn!(
+                                "Event timestamps jumped by {}s, suspending time-sensitive rules for {}s",
+                                skew,
+                                config.input.clock_guard.suspend_seconds
+                            ),
+                            None => log::warn!(
+                                "Clock adjustment logged, suspending time-sensitive rules for {}s",
+                                config.input.clock_guard.suspend_seconds
+                            ),
+                        }
+                    }
+                    if event.event_type == CLOCK_STEP {
+                        continue;
+                    }
+                }
+
+                if let Some(reason) = config.input.suppression_reason(&event) {
+                    log::trace!("Skipping event (type: {}, user: {}): {}", event.event_type, event.user, reason);
+                    if let Some(audit) = suppression_audit.as_mut() {
+                        if let Err(e) = audit.record(&event, &reason) {
+                            log::warn!("Failed to record suppressed event: {}", e);
+                        }
+                    }
+                    continue;
+                }
+                if !sampler.should_process(&event) {
+                    continue;
+                }
+                normalizer.apply(&mut event);
+
+                for event in reorder.push(event, chrono::Utc::now().timestamp()) {
+                    dispatch(&worker_pool, &processor, event).await;
+                }
+            }
+
+            // Release reordered events that have been held long enough
+            _ = reorder_interval.tick(), if !reorder.is_empty() => {
+                for event in reorder.release(chrono::Utc::now().timestamp()) {
+                    dispatch(&worker_pool, &processor, event).await;
+                }
             }
 
             // Periodic maintenance
             _ = maintenance_interval.tick() => {
+                // Switch to the current day's database if the date changed
+                if let Some(store) = state_store.as_ref().filter(|_| db_rollover) {
+                    let current = expand_database_path(&db_template, chrono::Utc::now().date_naive());
+                    if current != db_path {
+                        match store.reopen(&current) {
+                            Ok(()) => {
+                                log::info!("Rolled over persistence to {:?}", current);
+                                db_path = current;
+                            }
+                            Err(e) => {
+                                log::warn!("Failed to roll over database to {:?}: {}", current, e);
+                            }
+                        }
+                    }
+                }
+
                 // Prune old data from persistence
-                if let Some(ref store) = state_store {
-                    let cutoff = chrono::Utc::now().timestamp() - 86400; // 24 hours
-                    match store.prune_old_data(cutoff) {
+                if let Some((store, now)) = state_store.as_ref().zip(clock.now()) {
+                    let cutoff = now - 86400; // 24 hours
+                    match AsyncStateStore::new(store.clone()).prune_old_data(cutoff).await {
                         Ok(count) => {
                             if count > 0 {
                                 log::debug!("Pruned {} old records from database", count);
@@ -255,9 +675,99 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     }
                 }
 
-                // Prune in-memory caches
-                let now = chrono::Utc::now().timestamp();
-                rate_limiter.lock().await.prune_stale(now);
+                // Report the parse-failure rate over the last interval
+                let ingestion = ingestion_stats.snapshot();
+                let recent = ingestion.since(&last_ingestion);
+                last_ingestion = ingestion;
+                if recent.lines_read > 0 {
+                    log::info!(
+                        "Ingestion: {} line(s) read, {} skipped, {} failed to parse ({:.1}%)",
+                        recent.lines_read,
+                        recent.lines_skipped,
+                        recent.parse_failures,
+                        recent.failure_ratio() * 100.0
+                    );
+                }
+                if let Some(report) = parse_failure_report(&recent, config.input.parse_failure_alert_ratio) {
+                    report_handler.handle(report).await;
+                }
+
+                let store_errors = store_health.errors();
+                if store_errors > last_store_errors {
+                    log::warn!(
+                        "{} persistence error(s) during detection in the last interval, fell back to in-memory state",
+                        store_errors - last_store_errors
+                    );
+                    last_store_errors = store_errors;
+                }
+
+                let stale = age_filter.take_dropped();
+                if stale > 0 {
+                    log::warn!("Dropped {} stale event(s) in the last interval", stale);
+                }
+                let duplicates = deduplicator.take_dropped();
+                if duplicates > 0 {
+                    log::info!("Dropped {} duplicate event(s) in the last interval", duplicates);
+                }
+                let sampled = sampler.take_dropped();
+                if sampled > 0 {
+                    log::info!("Sampled out {} event(s) in the last interval", sampled);
+                }
+
+                // Report alert channels that are currently being skipped
+                for (channel, state) in alert_breakers.states() {
+                    if state != CircuitState::Closed {
+                        log::warn!("Alert channel {} circuit is {}", channel, state);
+                    }
+                }
+
+                // Prune in-memory caches (in replay mode, once events have
+                // established the replayed time)
+                if let Some(now) = clock.now() {
+                    let resolved = engine.lock().await.prune_stale(now);
+                    if let Some(escalator) = &escalator {
+                        escalator.lock().unwrap().prune_stale(now);
+                    }
+                    if let Some(correlator) = &risk_correlator {
+                        correlator.lock().unwrap().prune_stale(now);
+                    }
+                    if let Some(throttle) = &report_throttle {
+                        run_blocking(|| throttle.lock().unwrap().prune_stale(now));
+                    }
+                    for report in resolved {
+                        report_handler.handle(report).await;
+                    }
+                }
+            }
+
+            // SIGUSR1 toggles maintenance mode
+            _ = maintenance_signal.recv() => {
+                if maintenance.toggle() {
+                    log::warn!("Maintenance mode on: rules keep learning, reports are suppressed");
+                } else if maintenance.is_active() {
+                    log::warn!("Maintenance flag cleared, but the control file still exists");
+                } else {
+                    log::warn!("Maintenance mode off: detection resumed");
+                }
+            }
+
+            // SIGUSR2 logs the effective configuration
+            _ = config_dump_signal.recv() => {
+                match config.to_redacted_toml() {
+                    Ok(dump) => log::info!("Effective configuration (secrets redacted):\n{}", dump),
+                    Err(e) => log::warn!("Failed to dump configuration: {}", e),
+                }
+            }
+
+            // Watch the maintenance control file
+            _ = control_file_interval.tick(), if maintenance.control_file().is_some() => {
+                if maintenance.poll_control_file() {
+                    if maintenance.is_active() {
+                        log::warn!("Maintenance control file present: reports are suppressed");
+                    } else {
+                        log::warn!("Maintenance mode off: detection resumed");
+                    }
+                }
             }
 
             // Shutdown signal
@@ -268,53 +778,415 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    // Process events still held for reordering
+    for event in reorder.flush() {
+        dispatch(&worker_pool, &processor, event).await;
+    }
+
+    // Let queued events finish before flushing
+    if let Some(pool) = worker_pool {
+        pool.shutdown().await;
+    }
+
+    // Report bursts that were still being merged, like any other report
+    let bursts = engine.lock().await.drain_bursts();
+    for report in bursts {
+        report_handler.handle(report).await;
+    }
+
+    // Emit reports still waiting on a reverse DNS lookup
+    if let Some(enrichment) = &report_handler.reverse_dns {
+        enrichment.drain().await;
+    }
+
     // Flush output before exit
     if let Err(e) = output_handler.lock().await.flush() {
         log::error!("Failed to flush output: {}", e);
     }
+    tracer.shutdown();
 
     log::info!("ISDS Daemon stopped");
     Ok(())
 }
 
-/// Process a single log event through all detection rules
-async fn process_event(
-    event: &LogEvent,
-    config: &Config,
-    identity_context: &Arc<tokio::sync::Mutex<IdentityContext>>,
-    geo_velocity_tracker: &Arc<tokio::sync::Mutex<GeoVelocityTracker>>,
-    rate_limiter: &Arc<tokio::sync::Mutex<LoginRateLimiter>>,
-    output_handler: &Arc<tokio::sync::Mutex<OutputHandler>>,
-    geo_service: Option<&GeoIpService>,
-    alert_queue: &AlertQueue,
-    state_store: Option<&Arc<SqliteStateStore>>,
+/// Hand an event to its worker, or process it inline
+async fn dispatch(worker_pool: &Option<WorkerPool>, processor: &EventProcessor, event: LogEvent) {
+    match worker_pool {
+        Some(pool) => pool.submit(event).await,
+        None => processor.process(&event).await,
+    }
+}
+
+/// Detection state and sinks shared by every event worker
+struct EventProcessor {
+    config: Config,
+    engine: Arc<tokio::sync::Mutex<DetectionEngine>>,
+    enrichment: EnrichmentPipeline,
+    tracer: EventTracer,
+    report_handler: ReportHandler,
+    /// At most one report per user per interval, unless more severe
+    report_throttle: Option<Arc<std::sync::Mutex<UserReportThrottle>>>,
+    /// Counts store errors, which skip the last-seen annotation
+    store_health: Arc<StoreHealth>,
+    clock_guard: Option<Arc<std::sync::Mutex<ClockGuard>>>,
+    clock: Arc<PipelineClock>,
+}
+
+impl EventProcessor {
+    /// Process a single log event through all detection rules
+    async fn process(&self, event: &LogEvent) {
+        let now = self.clock.received_at(event);
+        let time_rules = !self
+            .clock_guard
+            .as_ref()
+            .is_some_and(|guard| guard.lock().unwrap().time_rules_suspended(now));
+        let trace = self.tracer.trace_event(event);
+        let enriched = self.enrichment.run_traced(event.clone(), &trace);
+        self.process_event(&enriched, &trace, now, time_rules).await;
+    }
+
+    /// Run an enriched event through all detection rules received at `now`
+    async fn process_event(&self, enriched: &EnrichedEvent, trace: &EventTrace, now: i64, time_rules: bool) {
+        let config = &self.config;
+        let report_handler = &self.report_handler;
+        let event = &enriched.event;
+        log::debug!(
+            "Processing event: user={}, ip={}, type={}",
+            event.user,
+            event.ip_address,
+            event.event_type
+        );
+
+        // Read when the user was last seen before the IP switch rule records this event
+        let last_seen = match report_handler.state_store.as_ref() {
+            Some(store) if config.detection.enrich_last_seen && config.input.unknown_user.user_rules_apply(event) => {
+                let user = event.user.clone();
+                store
+                    .run(move |store| LastSeen::lookup(store, &user))
+                    .await
+                    .map_err(|e| self.store_health.record("look up last seen time", &e))
+                    .ok()
+            }
+            _ => None,
+        };
+
+        // Stateful rules may query the store, so they run off the async workers
+        let detection = {
+            let mut engine = self.engine.lock().await;
+            run_blocking(|| engine.detect(enriched, trace, time_rules))
+        };
+
+        if let Some(report) = detection.geo_degraded {
+            report_handler.handle(report).await;
+        }
+        for mut report in detection.reports {
+            if let Some(last_seen) = last_seen.filter(|_| report.user == event.user) {
+                last_seen.annotate(&mut report);
+            }
+            // A persisted throttle reads and writes cooldowns in the store
+            let throttled = self
+                .report_throttle
+                .as_ref()
+                .is_some_and(|throttle| !run_blocking(|| throttle.lock().unwrap().allow(&report, now)));
+            if throttled {
+                log::debug!(
+                    "Throttled {} (severity {}) for user={}",
+                    report.rule_name,
+                    report.severity,
+                    report.user
+                );
+                continue;
+            }
+            let _span = trace.span("report", &report.rule_name);
+            report_handler.handle(report).await;
+        }
+    }
+}
+
+/// Channel for one source's events, forwarded to `tx` tagged with the
+/// source's index so the deduplicator can tell copies from another source
+/// apart from repeats within one
+fn tag_source(source_id: usize, tx: mpsc::Sender<(usize, LogEvent)>) -> mpsc::Sender<LogEvent> {
+    let (source_tx, mut source_rx) = mpsc::channel(SOURCE_CHANNEL_CAPACITY);
+    tokio::spawn(async move {
+        while let Some(event) = source_rx.recv().await {
+            if tx.send((source_id, event)).await.is_err() {
+                break;
+            }
+        }
+    });
+    source_tx
+}
+
+/// Start reading an input source, sending its events down the channel
+fn spawn_source(
+    source: &SourceSpec,
+    parser: LineParser,
+    tx: mpsc::Sender<LogEvent>,
+    stats: Arc<IngestionStats>,
+    input: &InputConfig,
 ) {
-    log::debug!(
-        "Processing event: user={}, ip={}, type={}",
-        event.user,
-        event.ip_address,
-        event.event_type
-    );
+    let rotation_check_interval = Duration::from_millis(input.rotation_check_interval_ms);
+    let syslog_buffer_size = input.syslog_buffer_size;
+    let syslog_max_connections = input.syslog_max_connections;
+    let syslog_idle_timeout = Duration::from_secs(input.syslog_idle_timeout_seconds);
+    match source.source_type.as_str() {
+        "file" => {
+            if let Some(ref path) = source.file_path {
+                let path = path.clone();
+                tokio::spawn(async move {
+                    let mut tailer = AsyncFileTailer::new(path.clone())
+                        .with_parser(parser)
+                        .with_stats(stats)
+                        .with_rotation_check_interval(rotation_check_interval);
+                    if let Err(e) = tailer.run(tx).await {
+                        log::error!("File tailer error: {}", e);
+                    }
+                });
+                log::info!("Monitoring log file: {:?} ({:?} lines)", source.file_path, source.format);
+            } else {
+                log::warn!("File source type selected but no file path configured");
+            }
+        }
+        "fifo" => {
+            if let Some(ref path) = source.file_path {
+                let path = path.clone();
+                tokio::spawn(async move {
+                    let mut reader = AsyncFifoReader::new(path)
+                        .with_parser(parser)
+                        .with_stats(stats);
+                    if let Err(e) = reader.run(tx).await {
+                        log::error!("FIFO reader error: {}", e);
+                    }
+                });
+                log::info!("Reading named pipe: {:?} ({:?} lines)", source.file_path, source.format);
+            } else {
+                log::warn!("FIFO source type selected but no file path configured");
+            }
+        }
+        "syslog" => {
+            if let Some(ref address) = source.syslog_address {
+                let addr = address.clone();
+                tokio::spawn(async move {
+                    match AsyncSyslogListener::new(&addr).await {
+                        Ok(listener) => {
+                            let mut listener = listener
+                                .with_parser(parser)
+                                .with_stats(stats)
+                                .with_buffer_size(syslog_buffer_size);
+                            if let Err(e) = listener.run(tx).await {
+                                log::error!("Syslog listener error: {}", e);
+                            }
+                        }
+                        Err(e) => {
+                            log::error!("Failed to create syslog listener: {}", e);
+                        }
+                    }
+                });
+                log::info!("Listening on syslog: {} ({:?} lines)", address, source.format);
+            } else {
+                log::warn!("Syslog source type selected but no address configured");
+            }
+        }
+        "syslog-tcp" => {
+            if let Some(ref address) = source.syslog_address {
+                let addr = address.clone();
+                tokio::spawn(async move {
+                    match AsyncTcpSyslogListener::new(&addr).await {
+                        Ok(listener) => {
+                            let mut listener = listener
+                                .with_parser(parser)
+                                .with_stats(stats)
+                                .with_max_connections(syslog_max_connections)
+                                .with_idle_timeout(syslog_idle_timeout);
+                            if let Err(e) = listener.run(tx).await {
+                                log::error!("TCP syslog listener error: {}", e);
+                            }
+                        }
+                        Err(e) => {
+                            log::error!("Failed to create TCP syslog listener: {}", e);
+                        }
+                    }
+                });
+                log::info!("Listening on TCP syslog: {} ({:?} lines)", address, source.format);
+            } else {
+                log::warn!("TCP syslog source type selected but no address configured");
+            }
+        }
+        "stdin" => {
+            tokio::spawn(async move {
+                let mut reader = AsyncStdinReader::new()
+                    .with_parser(parser)
+                    .with_stats(stats);
+                if let Err(e) = reader.run(tx).await {
+                    log::error!("Stdin reader error: {}", e);
+                }
+            });
+            log::info!("Reading log lines from stdin ({:?} lines)", source.format);
+        }
+        _ => {
+            log::warn!("Unknown input source type: {}", source.source_type);
+        }
+    }
+}
+
+/// Fewest lines in an interval before the parse-failure ratio is trusted
+const MIN_LINES_FOR_PARSE_ALERT: u64 = 20;
+
+/// Build a self-alert when too many lines in an interval failed to parse
+fn parse_failure_report(recent: &IngestionSnapshot, threshold: Option<f64>) -> Option<AnomalyReport> {
+    let threshold = threshold?;
+    if recent.lines_considered() < MIN_LINES_FOR_PARSE_ALERT || recent.failure_ratio() <= threshold {
+        return None;
+    }
 
-    // Helper to handle anomaly reports
-    let handle_report = |report: AnomalyReport| async {
+    let now = chrono::Utc::now().timestamp();
+    Some(AnomalyReport::new(
+        6,
+        "Ingestion Parse Failures High",
+        "",
+        now,
+        format!(
+            "{} of {} auth log line(s) ({:.1}%) failed to parse in the last interval, above the {:.1}% threshold. \
+             The log format may have changed.",
+            recent.parse_failures,
+            recent.lines_considered(),
+            recent.failure_ratio() * 100.0,
+            threshold * 100.0
+        ),
+    )
+    .with_detected_at(now))
+}
+
+/// Reverse DNS enrichment of reports, off the event path
+///
+/// Lookups run concurrently in a tracked task set, but each report waits
+/// for the one queued before it, so reports are emitted in the order they
+/// were raised. The set is drained on shutdown.
+#[derive(Clone)]
+struct ReportEnrichment {
+    enricher: Arc<ReverseDnsEnricher>,
+    queue: Arc<std::sync::Mutex<EnrichmentQueue>>,
+}
+
+struct EnrichmentQueue {
+    tasks: JoinSet<()>,
+    /// Completes once the most recently queued report has been emitted
+    last_emitted: Option<oneshot::Receiver<()>>,
+}
+
+impl ReportEnrichment {
+    fn new(enricher: Arc<ReverseDnsEnricher>) -> Self {
+        ReportEnrichment {
+            enricher,
+            queue: Arc::new(std::sync::Mutex::new(EnrichmentQueue {
+                tasks: JoinSet::new(),
+                last_emitted: None,
+            })),
+        }
+    }
+
+    /// Enrich a report in the background, then emit it through `handler`
+    fn spawn(&self, mut report: AnomalyReport, handler: ReportHandler) {
+        let enricher = self.enricher.clone();
+        let (emitted_tx, emitted_rx) = oneshot::channel();
+        let mut queue = self.queue.lock().unwrap();
+        // Reap finished tasks so the set only holds reports in flight
+        while let Some(result) = queue.tasks.try_join_next() {
+            log_enrichment_result(result);
+        }
+        let previous = queue.last_emitted.replace(emitted_rx);
+        queue.tasks.spawn(async move {
+            enricher.enrich(&mut report).await;
+            if let Some(previous) = previous {
+                // A dropped sender (the previous task panicked) also means go ahead
+                let _ = previous.await;
+            }
+            handler.emit(report).await;
+            let _ = emitted_tx.send(());
+        });
+    }
+
+    /// Wait for every queued report to be emitted
+    async fn drain(&self) {
+        let mut tasks = std::mem::take(&mut self.queue.lock().unwrap().tasks);
+        while let Some(result) = tasks.join_next().await {
+            log_enrichment_result(result);
+        }
+    }
+}
+
+fn log_enrichment_result(result: Result<(), tokio::task::JoinError>) {
+    if let Err(e) = result {
+        log::error!("Report enrichment task failed: {}", e);
+    }
+}
+
+/// Writes, persists and alerts on anomaly reports
+#[derive(Clone)]
+struct ReportHandler {
+    output_handler: Arc<tokio::sync::Mutex<OutputSinks>>,
+    alert_queue: AlertQueue,
+    state_store: Option<AsyncStateStore>,
+    /// Primary store plus report sinks, written for every report
+    report_stores: Arc<ReportStores>,
+    reverse_dns: Option<ReportEnrichment>,
+    escalator: Option<Arc<std::sync::Mutex<SeverityEscalator>>>,
+    risk_correlator: Option<Arc<std::sync::Mutex<RiskCorrelator>>>,
+    maintenance: MaintenanceMode,
+}
+
+impl ReportHandler {
+    /// Handle a single anomaly report
+    ///
+    /// With reverse DNS enabled the report is enriched and emitted from a
+    /// tracked background task, so a slow lookup never holds up event
+    /// processing.
+    async fn handle(&self, mut report: AnomalyReport) {
+        if self.maintenance.is_active() {
+            log::debug!(
+                "Maintenance mode: suppressed {} for user={}",
+                report.rule_name,
+                report.user
+            );
+            return;
+        }
+        if let Some(escalator) = &self.escalator {
+            escalator.lock().unwrap().apply(&mut report);
+        }
+        // A report that tips the user's combined risk over is followed by the combined report
+        let combined = self
+            .risk_correlator
+            .as_ref()
+            .and_then(|correlator| correlator.lock().unwrap().add(&report))
+            .map(|assessment| assessment.to_report());
+        for report in std::iter::once(report).chain(combined) {
+            match &self.reverse_dns {
+                Some(enrichment) => enrichment.spawn(report, self.clone()),
+                None => self.emit(report).await,
+            }
+        }
+    }
+
+    /// Write, persist and alert on a single anomaly report
+    async fn emit(&self, report: AnomalyReport) {
         // Write to output
         {
-            let mut out = output_handler.lock().await;
-            if let Err(e) = out.write_report(&report) {
-                log::error!("Failed to write report: {}", e);
+            let mut out = self.output_handler.lock().await;
+            match out.write_report(&report) {
+                Ok(()) => {}
+                Err(e) if e.is_fatal() => log::error!("Failed to write report: {}", e),
+                Err(e) => log::warn!("Failed to write report, will retry output: {}", e),
             }
         }
 
-        // Store in persistence
-        if let Some(store) = state_store {
-            if let Err(e) = store.store_anomaly_report(&report) {
-                log::warn!("Failed to store anomaly report: {}", e);
-            }
+        // Store in persistence and any report sinks
+        for (store, e) in self.report_stores.store_anomaly_report(&report).await {
+            log::warn!("Failed to store anomaly report in {}: {}", store, e);
         }
 
         // Queue alert
-        alert_queue.queue_alert(report.clone());
+        self.alert_queue.queue_alert(report.clone());
 
         // Log warning
         log::warn!(
@@ -324,33 +1196,5 @@ async fn process_event(
             report.user,
             report.description
         );
-    };
-
-    // Check for IP switching
-    if config.detection.enable_ip_switch {
-        let mut ctx = identity_context.lock().await;
-        if let Some(report) = ctx.check_for_ip_switch(event) {
-            handle_report(report).await;
-        }
-    }
-
-    // Check for impossible travel (requires geo location lookup)
-    if config.detection.enable_geo_velocity {
-        if let Some(geo) = geo_service {
-            if let Some(location) = geo.lookup_optional(&event.ip_address) {
-                let mut tracker = geo_velocity_tracker.lock().await;
-                if let Some(report) = tracker.check_impossible_travel(event, location) {
-                    handle_report(report).await;
-                }
-            }
-        }
-    }
-
-    // Check for rate limiting violations
-    if config.detection.enable_rate_limiting {
-        let mut limiter = rate_limiter.lock().await;
-        for report in limiter.check_rate_limit(event) {
-            handle_report(report).await;
-        }
     }
 }
@@ -1,3 +1,7 @@
+use crate::detection::bounded_map::DEFAULT_MAX_TRACKED_ENTRIES;
+use crate::input::classify::{SSH_FAILED, SSH_FAILED_PREFIX};
+use crate::models::{AnomalyReport, LogEvent};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -17,17 +21,426 @@ pub struct Config {
     /// Alerting configuration
     #[serde(default)]
     pub alerting: AlertConfig,
+    /// HTTP API configuration
+    #[serde(default)]
+    pub api: ApiConfig,
+    /// OpenTelemetry tracing configuration
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
 }
 
+/// Input source types the daemon can read from
+pub const SOURCE_TYPES: &[&str] = &["file", "fifo", "syslog", "syslog-tcp", "stdin"];
+
 /// Input source configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InputConfig {
-    /// Type of input source: "file" or "syslog"
+    /// Type of input source: "file", "fifo", "syslog", "syslog-tcp" or "stdin"
+    pub source_type: String,
+    /// Path to log file or named pipe (if source_type is "file" or "fifo")
+    pub file_path: Option<PathBuf>,
+    /// Syslog bind address (if source_type is "syslog" or "syslog-tcp")
+    pub syslog_address: Option<String>,
+    /// Format of the source's lines (when no `sources` are listed)
+    #[serde(default)]
+    pub log_format: LineFormat,
+    /// Only process events with these event types (all types if unset).
+    /// `SSH_FAILED` also covers the detailed `SSH_FAILED_*` types.
+    #[serde(default)]
+    pub process_event_types: Option<Vec<String>>,
+    /// Append a JSON line per event kept from detection by
+    /// `process_event_types` or the `unknown_user` policy to this file,
+    /// naming the setting responsible. Off by default for volume.
+    #[serde(default)]
+    pub suppression_audit_file: Option<PathBuf>,
+    /// Timestamp formats tried in order when parsing lines; built-in names
+    /// (rfc3339, iso8601, syslog, clf, epoch_millis, epoch_seconds) or
+    /// strftime patterns. Defaults to all built-ins.
+    #[serde(default)]
+    pub timestamp_formats: Option<Vec<String>>,
+    /// Stop the daemon once the input reaches EOF (stdin source)
+    #[serde(default)]
+    pub exit_on_eof: bool,
+    /// How often tailed files are checked for rotation (replaced or
+    /// truncated) while idle, in milliseconds
+    #[serde(default = "default_rotation_check_interval_ms")]
+    pub rotation_check_interval_ms: u64,
+    /// Largest UDP syslog message received whole, in bytes; longer ones
+    /// are dropped with a warning and the buffer grows to the largest
+    /// datagram
+    #[serde(default = "default_syslog_buffer_size")]
+    pub syslog_buffer_size: usize,
+    /// Most TCP syslog connections open at once; further ones are closed
+    /// on accept
+    #[serde(default = "default_syslog_max_connections")]
+    pub syslog_max_connections: usize,
+    /// Close TCP syslog connections that send nothing for this long
+    #[serde(default = "default_syslog_idle_timeout_seconds")]
+    pub syslog_idle_timeout_seconds: u64,
+    /// Username normalization applied at ingestion
+    #[serde(default)]
+    pub username_normalization: UsernameNormalizationConfig,
+    /// Raise an "Ingestion Parse Failures High" alert when the share of
+    /// unparseable lines in a maintenance interval exceeds this ratio
+    /// (0.0-1.0). Disabled when unset.
+    #[serde(default)]
+    pub parse_failure_alert_ratio: Option<f64>,
+    /// What to do with events whose username couldn't be extracted
+    #[serde(default)]
+    pub unknown_user: UnknownUserPolicy,
+    /// Classify sshd failures into finer event types (SSH_FAILED_PASSWORD,
+    /// SSH_FAILED_MAX_AUTH, ...) instead of a single SSH_FAILED
+    #[serde(default)]
+    pub detailed_failure_types: bool,
+    /// Attach the host named in each text line's syslog header (BSD or
+    /// RFC 5424) to the event and its reports
+    #[serde(default = "default_extract_host")]
+    pub extract_host: bool,
+    /// Phrase to event type mappings, checked before the built-in ones
+    #[serde(default)]
+    pub event_type_mappings: Vec<EventTypeMapping>,
+    /// Process only 1 in N events of these types, scaling their rate
+    /// limit weight by N. Failed and successful logins are never sampled.
+    #[serde(default)]
+    pub sample_rates: HashMap<String, u32>,
+    /// Suspend time-sensitive rules when the host clock is stepped
+    #[serde(default)]
+    pub clock_guard: ClockGuardConfig,
+    /// Drop events timestamped more than this many seconds ago before
+    /// detection, e.g. while a backlog drains (ignored in replay mode)
+    #[serde(default)]
+    pub max_event_age_seconds: Option<i64>,
+    /// Drop an event identical (user, IP, host, timestamp and type) to one
+    /// received from a different source within this many seconds. For
+    /// logs that are tailed from more than one copy; repeats within one
+    /// source are kept.
+    #[serde(default)]
+    pub dedup_window_seconds: Option<i64>,
+    /// Hold events for this many seconds and pass them to detection in
+    /// timestamp order, for sources that interleave slightly out of order
+    #[serde(default)]
+    pub reorder_delay_seconds: Option<i64>,
+    /// Most events held for reordering; the earliest are released early
+    /// past this
+    #[serde(default = "default_reorder_max_events")]
+    pub reorder_max_events: usize,
+    /// Sources read at the same time, each with its own line format.
+    /// When set, these replace the single `source_type` source.
+    #[serde(default)]
+    pub sources: Vec<SourceSpec>,
+}
+
+fn default_extract_host() -> bool {
+    true
+}
+
+fn default_rotation_check_interval_ms() -> u64 {
+    crate::input::file_tailer::DEFAULT_ROTATION_CHECK_INTERVAL.as_millis() as u64
+}
+
+fn default_syslog_buffer_size() -> usize {
+    crate::input::syslog_listener::DEFAULT_BUFFER_SIZE
+}
+
+fn default_syslog_max_connections() -> usize {
+    crate::input::tcp_syslog::DEFAULT_MAX_CONNECTIONS
+}
+
+fn default_syslog_idle_timeout_seconds() -> u64 {
+    crate::input::tcp_syslog::DEFAULT_IDLE_TIMEOUT.as_secs()
+}
+
+fn default_reorder_max_events() -> usize {
+    crate::input::reorder::DEFAULT_MAX_REORDER_EVENTS
+}
+
+/// A single input source
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceSpec {
+    /// Type of input source: "file", "fifo", "syslog", "syslog-tcp" or "stdin"
     pub source_type: String,
-    /// Path to log file (if source_type is "file")
+    /// Path to log file or named pipe (if source_type is "file" or "fifo")
+    #[serde(default)]
     pub file_path: Option<PathBuf>,
-    /// Syslog bind address (if source_type is "syslog")
+    /// Syslog bind address (if source_type is "syslog" or "syslog-tcp")
+    #[serde(default)]
     pub syslog_address: Option<String>,
+    /// Format of the source's lines
+    #[serde(default)]
+    pub format: LineFormat,
+    /// Timestamp formats tried in order for this source (all built-ins
+    /// if unset)
+    #[serde(default)]
+    pub timestamp_formats: Option<Vec<String>>,
+    /// Keys read from lines of a "json" or "logfmt" source
+    #[serde(default)]
+    pub fields: FieldsConfig,
+}
+
+impl InputConfig {
+    /// The configured sources, falling back to the single
+    /// `source_type` source (in `log_format`) when no `sources` are listed
+    pub fn source_specs(&self) -> Vec<SourceSpec> {
+        if !self.sources.is_empty() {
+            return self.sources.clone();
+        }
+        vec![SourceSpec {
+            source_type: self.source_type.clone(),
+            file_path: self.file_path.clone(),
+            syslog_address: self.syslog_address.clone(),
+            format: self.log_format,
+            timestamp_formats: self.timestamp_formats.clone(),
+            fields: FieldsConfig::default(),
+        }]
+    }
+
+    /// Reject settings the input pipeline can't work with
+    pub fn validate(&self) -> Result<(), String> {
+        for source in self.source_specs() {
+            if !SOURCE_TYPES.contains(&source.source_type.as_str()) {
+                return Err(format!(
+                    "Unknown input source type {:?}, expected one of {}",
+                    source.source_type,
+                    SOURCE_TYPES.join(", ")
+                ));
+            }
+        }
+        if let Some(max_age) = self.max_event_age_seconds.filter(|&age| age < 0) {
+            return Err(format!("Maximum event age {} seconds must not be negative", max_age));
+        }
+        Ok(())
+    }
+}
+
+/// Format of an input source's lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineFormat {
+    /// Free-form auth log messages (auth.log, BSD syslog)
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+    /// Whitespace-separated `key=value` pairs (logfmt, journald exports)
+    Logfmt,
+    /// nginx/Apache access log lines (common or combined log format)
+    NginxAccess,
+}
+
+/// Keys of a structured (JSON or logfmt) line holding each event field
+///
+/// Unset keys fall back to the format's defaults: `timestamp`, `user`,
+/// `ip`, `event_type` and `message` for JSON; `time`, `user`, `src_ip`,
+/// `result` and `msg` for logfmt. A numeric timestamp is taken as epoch
+/// seconds; anything else is parsed with the source's timestamp formats.
+/// An event type of `fail`/`failure` reads as `SSH_FAILED` and
+/// `success`/`ok` as `SSH_LOGIN`; without an event type key the message
+/// (or the whole line) is classified like a text line.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct FieldsConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Clock jump handling
+///
+/// A step is noticed from time daemon log lines (chrony, ntpd,
+/// timesyncd) or, outside replay mode, from one host's event timestamps
+/// suddenly moving relative to the time they're received. Impossible
+/// travel, rate limiting and attacking IP detection then skip events for
+/// `suspend_seconds`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClockGuardConfig {
+    /// Enable clock jump detection
+    #[serde(default)]
+    pub enabled: bool,
+    /// Treat a change of more than this many seconds in the offset between
+    /// event and receive time as a clock step
+    #[serde(default = "default_max_clock_jump_seconds")]
+    pub max_jump_seconds: i64,
+    /// Seconds time-sensitive rules stay suspended after a step
+    #[serde(default = "default_clock_suspend_seconds")]
+    pub suspend_seconds: i64,
+}
+
+fn default_max_clock_jump_seconds() -> i64 {
+    300
+}
+
+fn default_clock_suspend_seconds() -> i64 {
+    600
+}
+
+impl Default for ClockGuardConfig {
+    fn default() -> Self {
+        ClockGuardConfig {
+            enabled: false,
+            max_jump_seconds: default_max_clock_jump_seconds(),
+            suspend_seconds: default_clock_suspend_seconds(),
+        }
+    }
+}
+
+/// Assigns an event type to lines containing a phrase
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventTypeMapping {
+    /// Substring the log line must contain
+    pub pattern: String,
+    /// Event type given to matching lines
+    pub event_type: String,
+}
+
+/// Handling of events whose username couldn't be extracted
+///
+/// Such events all share the `"unknown"` pseudo-user, so running per-user
+/// rules on them links unrelated logins together.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnknownUserPolicy {
+    /// Drop the events before detection
+    Drop,
+    /// Run only IP-keyed rules on them
+    #[default]
+    IpOnly,
+    /// Treat "unknown" like any other username
+    Process,
+}
+
+/// Where the processing pipeline takes "now" from
+///
+/// Live processing uses the wall clock, held back by however late the
+/// latest event arrived. Replaying old logs uses the latest event
+/// timestamp instead, so cooldowns, burst merging and pruning follow the
+/// replayed timeline rather than the time of the replay.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessingMode {
+    /// Events arrive as they happen
+    #[default]
+    Live,
+    /// Events are historical logs fed through the pipeline
+    Replay,
+}
+
+impl UnknownUserPolicy {
+    /// Whether per-user rules should run for an event
+    pub fn user_rules_apply(&self, event: &LogEvent) -> bool {
+        *self == UnknownUserPolicy::Process || !event.has_unknown_user()
+    }
+}
+
+/// A detection rule, as named in `detection.rule_order`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DetectionRule {
+    IpSwitch,
+    GeoVelocity,
+    HostingAsn,
+    OffHours,
+    HourPattern,
+    HomeRegion,
+    AttackingIp,
+    SuccessCluster,
+    RateLimit,
+    FirstSeen,
+    Lockout,
+}
+
+impl DetectionRule {
+    /// Name of the rule as written in configuration
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DetectionRule::IpSwitch => "ip_switch",
+            DetectionRule::GeoVelocity => "geo_velocity",
+            DetectionRule::HostingAsn => "hosting_asn",
+            DetectionRule::OffHours => "off_hours",
+            DetectionRule::HourPattern => "hour_pattern",
+            DetectionRule::HomeRegion => "home_region",
+            DetectionRule::AttackingIp => "attacking_ip",
+            DetectionRule::SuccessCluster => "success_cluster",
+            DetectionRule::RateLimit => "rate_limit",
+            DetectionRule::FirstSeen => "first_seen",
+            DetectionRule::Lockout => "lockout",
+        }
+    }
+
+    /// Order rules run in unless configured otherwise
+    pub const DEFAULT_ORDER: [DetectionRule; 11] = [
+        DetectionRule::IpSwitch,
+        DetectionRule::GeoVelocity,
+        DetectionRule::HostingAsn,
+        DetectionRule::OffHours,
+        DetectionRule::HourPattern,
+        DetectionRule::HomeRegion,
+        DetectionRule::AttackingIp,
+        DetectionRule::SuccessCluster,
+        DetectionRule::RateLimit,
+        DetectionRule::FirstSeen,
+        DetectionRule::Lockout,
+    ];
+}
+
+/// An event enrichment stage, as named in `detection.enrichment_stages`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EnrichmentStage {
+    /// City, country, coordinates and timezone (needs GeoIP)
+    Geo,
+    /// Autonomous system (needs the ASN database)
+    Asn,
+    /// Hostnames of the source and reported addresses (needs
+    /// `reverse_dns.enabled`)
+    ReverseDns,
+}
+
+/// Username normalization configuration
+///
+/// All transformations are disabled by default.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsernameNormalizationConfig {
+    /// Convert usernames to lowercase
+    #[serde(default)]
+    pub lowercase: bool,
+    /// Strip a trailing `@domain` or Kerberos realm
+    #[serde(default)]
+    pub strip_domain: bool,
+    /// Strip a leading `DOMAIN\` prefix
+    #[serde(default)]
+    pub strip_domain_prefix: bool,
+}
+
+impl InputConfig {
+    /// Check whether an event should be passed on to detection
+    pub fn should_process(&self, event: &LogEvent) -> bool {
+        self.suppression_reason(event).is_none()
+    }
+
+    /// Which setting keeps an event from detection, if any
+    pub fn suppression_reason(&self, event: &LogEvent) -> Option<String> {
+        if self.unknown_user == UnknownUserPolicy::Drop && event.has_unknown_user() {
+            return Some("unknown_user = \"drop\"".to_string());
+        }
+        match &self.process_event_types {
+            Some(types) if !types.iter().any(|t| lists_event_type(t, &event.event_type)) => Some(format!(
+                "process_event_types {:?} doesn't list {}",
+                types, event.event_type
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Whether a `process_event_types` entry admits an event type
+fn lists_event_type(entry: &str, event_type: &str) -> bool {
+    entry == event_type || (entry == SSH_FAILED && event_type.starts_with(SSH_FAILED_PREFIX))
 }
 
 /// Detection rules configuration
@@ -39,6 +452,26 @@ pub struct DetectionConfig {
     pub enable_geo_velocity: bool,
     /// Enable rate limiting detection
     pub enable_rate_limiting: bool,
+    /// Enable hosting provider (datacenter ASN) login detection
+    #[serde(default)]
+    pub enable_hosting_asn: bool,
+    /// Enable detection of successful logins from IPs that recently failed
+    /// against several other users
+    #[serde(default)]
+    pub enable_attacking_ip: bool,
+    /// Enable detection of successful logins outside business hours
+    #[serde(default)]
+    pub enable_off_hours: bool,
+    /// Enable detection of logins geolocated far from a home location
+    #[serde(default)]
+    pub enable_home_region: bool,
+    /// Enable detection of logins in hours that are rare for the user,
+    /// based on a learned histogram of their login hours
+    #[serde(default)]
+    pub enable_hour_pattern: bool,
+    /// Sudden IP switch configuration
+    #[serde(default)]
+    pub ip_switch: IpSwitchConfig,
     /// Rate limiting configuration
     pub rate_limit: RateLimitConfig,
     /// Geo velocity configuration
@@ -46,6 +479,158 @@ pub struct DetectionConfig {
     /// Geolocation configuration
     #[serde(default)]
     pub geo_location: GeoLocationConfig,
+    /// Hosting provider ASN configuration
+    #[serde(default)]
+    pub hosting_asn: HostingAsnConfig,
+    /// Reverse DNS enrichment of detected IPs
+    #[serde(default)]
+    pub reverse_dns: ReverseDnsConfig,
+    /// Attacking IP detection configuration
+    #[serde(default)]
+    pub attacking_ip: AttackingIpConfig,
+    /// Success cluster detection configuration
+    #[serde(default)]
+    pub success_cluster: SuccessClusterConfig,
+    /// Off-hours login configuration
+    #[serde(default)]
+    pub off_hours: OffHoursConfig,
+    /// Home region configuration
+    #[serde(default)]
+    pub home_region: HomeRegionConfig,
+    /// Login hour pattern configuration
+    #[serde(default)]
+    pub hour_pattern: HourPatternConfig,
+    /// First seen user configuration
+    #[serde(default)]
+    pub first_seen: FirstSeenConfig,
+    /// Login during lockout configuration
+    #[serde(default)]
+    pub lockout: LockoutConfig,
+    /// Severity escalation for reports repeating within a window
+    #[serde(default)]
+    pub escalation: EscalationConfig,
+    /// Combining a user's reports within a window into one risk score
+    #[serde(default)]
+    pub risk: RiskConfig,
+    /// Severity thresholds for inline allow/challenge/deny decisions
+    #[serde(default)]
+    pub decision: DecisionConfig,
+    /// Attach the user's previous event time to reports (needs
+    /// persistence and the IP switch rule, which records it)
+    #[serde(default)]
+    pub enrich_last_seen: bool,
+    /// Order rules are evaluated in; unlisted rules run afterwards in the
+    /// default order
+    #[serde(default)]
+    pub rule_order: Vec<DetectionRule>,
+    /// Rules to turn on regardless of their `enable_*` flag
+    #[serde(default)]
+    pub enabled_rules: Vec<DetectionRule>,
+    /// Rules to turn off regardless of their `enable_*` flag or
+    /// `enabled_rules`
+    #[serde(default)]
+    pub disabled_rules: Vec<DetectionRule>,
+    /// Skip the remaining rules for an event once a report at or above
+    /// this severity fires. Skipped rules don't update their state (e.g.
+    /// the user's trusted IP) for that event.
+    #[serde(default)]
+    pub short_circuit_severity: Option<u8>,
+    /// Emit at most this many reports per event, most severe first; the
+    /// rest are summarized on the top report (1 = one combined report)
+    #[serde(default)]
+    pub max_reports_per_event: Option<usize>,
+    /// After a report for a user, suppress their further reports from any
+    /// rule for this many seconds unless more severe (rules keep learning).
+    /// Reports about an IP or subnet are never suppressed.
+    #[serde(default)]
+    pub min_report_interval_per_user_seconds: Option<i64>,
+    /// Keep per-user report cooldowns in the persistence database so they
+    /// survive restarts
+    #[serde(default)]
+    pub persist_report_throttle: bool,
+    /// Suppress all reports (while rules keep learning) whenever this file
+    /// exists; SIGUSR1 toggles the same maintenance mode
+    #[serde(default)]
+    pub maintenance_control_file: Option<PathBuf>,
+    /// Log why each event did or didn't trigger each rule (verbose)
+    #[serde(default)]
+    pub explain: bool,
+    /// Record the raising rule's effective parameters (window, limit,
+    /// velocity, ...) in each report's metadata
+    #[serde(default)]
+    pub include_rule_parameters: bool,
+    /// Maximum users/IPs tracked in memory per detection map
+    #[serde(default = "default_max_tracked_entries")]
+    pub max_tracked_entries: Option<usize>,
+    /// Number of event workers; events for the same user always go to the
+    /// same worker (1 = process inline). Detection stays serialized on the
+    /// shared engine, so only enrichment and output overlap. Only per-user
+    /// order is kept, so IP-keyed rules may see events from different
+    /// users out of log order
+    #[serde(default = "default_processing_workers")]
+    pub processing_workers: usize,
+    /// Whether "now" is the wall clock (live) or the event stream (replay)
+    #[serde(default)]
+    pub processing_mode: ProcessingMode,
+    /// Enrichment stages run on each event, in order, before detection
+    #[serde(default = "default_enrichment_stages")]
+    pub enrichment_stages: Vec<EnrichmentStage>,
+}
+
+impl DetectionConfig {
+    /// Whether a rule runs
+    ///
+    /// `disabled_rules` wins over `enabled_rules`, which wins over the
+    /// rule's legacy `enable_*` flag. Rules added since `enabled_rules`
+    /// have no flag and only run when listed there.
+    pub fn rule_enabled(&self, rule: DetectionRule) -> bool {
+        if self.disabled_rules.contains(&rule) {
+            return false;
+        }
+        if self.enabled_rules.contains(&rule) {
+            return true;
+        }
+        match rule {
+            DetectionRule::IpSwitch => self.enable_ip_switch,
+            DetectionRule::GeoVelocity => self.enable_geo_velocity,
+            DetectionRule::HostingAsn => self.enable_hosting_asn,
+            DetectionRule::OffHours => self.enable_off_hours,
+            DetectionRule::HourPattern => self.enable_hour_pattern,
+            DetectionRule::HomeRegion => self.enable_home_region,
+            DetectionRule::AttackingIp => self.enable_attacking_ip,
+            DetectionRule::RateLimit => self.enable_rate_limiting,
+            DetectionRule::SuccessCluster | DetectionRule::FirstSeen | DetectionRule::Lockout => false,
+        }
+    }
+
+    /// Rules in evaluation order: `rule_order` first, then the rest in
+    /// the default order
+    pub fn effective_rule_order(&self) -> Vec<DetectionRule> {
+        let mut order: Vec<DetectionRule> = Vec::with_capacity(DetectionRule::DEFAULT_ORDER.len());
+        for rule in self.rule_order.iter().chain(DetectionRule::DEFAULT_ORDER.iter()) {
+            if !order.contains(rule) {
+                order.push(*rule);
+            }
+        }
+        order
+    }
+
+    /// Whether a report of this severity stops evaluation of the event
+    pub fn short_circuits(&self, severity: u8) -> bool {
+        self.short_circuit_severity.is_some_and(|threshold| severity >= threshold)
+    }
+}
+
+fn default_max_tracked_entries() -> Option<usize> {
+    Some(DEFAULT_MAX_TRACKED_ENTRIES)
+}
+
+fn default_processing_workers() -> usize {
+    1
+}
+
+fn default_enrichment_stages() -> Vec<EnrichmentStage> {
+    vec![EnrichmentStage::Geo, EnrichmentStage::Asn, EnrichmentStage::ReverseDns]
 }
 
 /// Geolocation configuration for IP-to-location lookups
@@ -55,6 +640,52 @@ pub struct GeoLocationConfig {
     pub enabled: bool,
     /// Path to MaxMind GeoLite2-City.mmdb database file
     pub database_path: Option<PathBuf>,
+    /// Truncate IPv4 addresses to this prefix length before lookup
+    /// (e.g. 24 zeroes the last octet; unset = full address)
+    #[serde(default)]
+    pub anonymize_prefix_v4: Option<u8>,
+    /// Truncate IPv6 addresses to this prefix length before lookup
+    #[serde(default)]
+    pub anonymize_prefix_v6: Option<u8>,
+    /// Add the detected IP's city and country to report metadata
+    #[serde(default)]
+    pub enrich_reports: bool,
+    /// What to do when the database file is missing at startup
+    #[serde(default)]
+    pub on_missing_database: MissingDatabasePolicy,
+    /// URL of an uncompressed .mmdb file fetched by the `download` policy
+    #[serde(default)]
+    pub download_url: Option<String>,
+    /// Account ID sent as the basic auth user when downloading
+    #[serde(default)]
+    pub download_account_id: Option<String>,
+    /// License key sent as the basic auth password when downloading
+    #[serde(default)]
+    pub download_license_key: Option<String>,
+    /// Consecutive failed lookups before geo detection is reported as
+    /// degraded (0 never reports)
+    #[serde(default = "default_degraded_after_failures")]
+    pub degraded_after_failures: u32,
+    /// Also raise a report when geo detection becomes degraded
+    #[serde(default)]
+    pub alert_when_degraded: bool,
+}
+
+fn default_degraded_after_failures() -> u32 {
+    20
+}
+
+/// Startup behavior when the GeoIP database file is missing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingDatabasePolicy {
+    /// Log a warning and run without geolocation
+    #[default]
+    Warn,
+    /// Refuse to start
+    Fail,
+    /// Fetch the database from `download_url`, failing if that doesn't work
+    Download,
 }
 
 impl Default for GeoLocationConfig {
@@ -62,6 +693,510 @@ impl Default for GeoLocationConfig {
         GeoLocationConfig {
             enabled: true,
             database_path: Some(PathBuf::from("GeoLite2-City.mmdb")),
+            anonymize_prefix_v4: None,
+            anonymize_prefix_v6: None,
+            enrich_reports: false,
+            on_missing_database: MissingDatabasePolicy::Warn,
+            download_url: None,
+            download_account_id: None,
+            download_license_key: None,
+            degraded_after_failures: default_degraded_after_failures(),
+            alert_when_degraded: false,
+        }
+    }
+}
+
+/// Hosting provider ASN detection configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostingAsnConfig {
+    /// Path to MaxMind GeoLite2-ASN.mmdb database file
+    pub database_path: Option<PathBuf>,
+    /// Autonomous system numbers treated as hosting/datacenter
+    #[serde(default)]
+    pub flagged_asns: Vec<u32>,
+    /// Case-insensitive organization name substrings treated as hosting
+    #[serde(default)]
+    pub flagged_organizations: Vec<String>,
+}
+
+impl Default for HostingAsnConfig {
+    fn default() -> Self {
+        HostingAsnConfig {
+            database_path: Some(PathBuf::from("GeoLite2-ASN.mmdb")),
+            // AWS, DigitalOcean, Hetzner, OVH, Linode, Vultr
+            flagged_asns: vec![16509, 14618, 14061, 24940, 16276, 63949, 20473],
+            flagged_organizations: vec!["hosting".to_string(), "datacenter".to_string()],
+        }
+    }
+}
+
+/// Reverse DNS (PTR) enrichment configuration
+///
+/// PTR records are controlled by whoever owns the IP range, so lookups are
+/// bounded by a timeout, cached, and disabled by default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReverseDnsConfig {
+    /// Attach the detected IP's hostname to reports
+    #[serde(default)]
+    pub enabled: bool,
+    /// Give up on a lookup after this many milliseconds
+    #[serde(default = "default_reverse_dns_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Maximum number of cached lookups
+    #[serde(default = "default_reverse_dns_cache_size")]
+    pub cache_size: usize,
+    /// How long an IP without a PTR record is remembered, in seconds
+    ///
+    /// Failed and timed-out lookups are not cached and are retried.
+    #[serde(default = "default_reverse_dns_negative_ttl_seconds")]
+    pub negative_ttl_seconds: u64,
+}
+
+fn default_reverse_dns_timeout_ms() -> u64 {
+    500
+}
+
+fn default_reverse_dns_cache_size() -> usize {
+    10_000
+}
+
+fn default_reverse_dns_negative_ttl_seconds() -> u64 {
+    3600
+}
+
+impl Default for ReverseDnsConfig {
+    fn default() -> Self {
+        ReverseDnsConfig {
+            enabled: false,
+            timeout_ms: default_reverse_dns_timeout_ms(),
+            cache_size: default_reverse_dns_cache_size(),
+            negative_ttl_seconds: default_reverse_dns_negative_ttl_seconds(),
+        }
+    }
+}
+
+/// Configuration for successful logins from IPs with multi-user failures
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttackingIpConfig {
+    /// How far back failed attempts are remembered, in seconds
+    pub window_seconds: i64,
+    /// Distinct other users an IP must have failed against
+    pub min_failed_users: usize,
+    /// How failed usernames are remembered per IP: "exact" (up to
+    /// `max_users_per_ip`) or "approximate" (fixed-size estimate)
+    #[serde(default)]
+    pub user_tracking: UserTrackingMode,
+    /// Usernames remembered per IP in exact mode; the oldest are
+    /// forgotten beyond this
+    #[serde(default = "default_max_users_per_ip")]
+    pub max_users_per_ip: usize,
+}
+
+/// How distinct usernames are tracked per IP
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserTrackingMode {
+    /// Exact set, capped at a maximum size
+    #[default]
+    Exact,
+    /// HyperLogLog estimate with fixed memory
+    Approximate,
+}
+
+fn default_max_users_per_ip() -> usize {
+    1000
+}
+
+impl Default for AttackingIpConfig {
+    fn default() -> Self {
+        AttackingIpConfig {
+            window_seconds: 3600,
+            min_failed_users: 3,
+            user_tracking: UserTrackingMode::Exact,
+            max_users_per_ip: default_max_users_per_ip(),
+        }
+    }
+}
+
+/// Configuration for IPs successfully logging in as many users
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuccessClusterConfig {
+    /// How far back successful logins are remembered, in seconds
+    #[serde(default = "default_success_cluster_window")]
+    pub window_seconds: i64,
+    /// Distinct users an IP must log in as within the window (at least 2)
+    #[serde(default = "default_success_cluster_min_users")]
+    pub min_users: usize,
+    /// Severity of a success cluster report
+    #[serde(default = "default_success_cluster_severity")]
+    pub severity: u8,
+    /// Usernames remembered per IP; the oldest are forgotten beyond this
+    #[serde(default = "default_max_users_per_ip")]
+    pub max_users_per_ip: usize,
+}
+
+fn default_success_cluster_window() -> i64 {
+    600
+}
+
+fn default_success_cluster_min_users() -> usize {
+    5
+}
+
+fn default_success_cluster_severity() -> u8 {
+    9
+}
+
+impl Default for SuccessClusterConfig {
+    fn default() -> Self {
+        SuccessClusterConfig {
+            window_seconds: default_success_cluster_window(),
+            min_users: default_success_cluster_min_users(),
+            severity: default_success_cluster_severity(),
+            max_users_per_ip: default_max_users_per_ip(),
+        }
+    }
+}
+
+/// Severity thresholds mapping reports to an inline decision
+///
+/// The highest report severity for an event decides the outcome: at or
+/// above `deny_severity` denies, at or above `challenge_severity`
+/// challenges, anything lower allows.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecisionConfig {
+    /// Lowest severity that requires a challenge (e.g. MFA)
+    #[serde(default = "default_challenge_severity")]
+    pub challenge_severity: u8,
+    /// Lowest severity that denies the login outright
+    #[serde(default = "default_deny_severity")]
+    pub deny_severity: u8,
+}
+
+fn default_challenge_severity() -> u8 {
+    5
+}
+
+fn default_deny_severity() -> u8 {
+    8
+}
+
+impl Default for DecisionConfig {
+    fn default() -> Self {
+        DecisionConfig {
+            challenge_severity: default_challenge_severity(),
+            deny_severity: default_deny_severity(),
+        }
+    }
+}
+
+/// Severity escalation for repeated reports
+///
+/// Each earlier report for the same rule and IP (or user) within the
+/// window adds `escalation_step` to the severity, up to `escalation_cap`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EscalationConfig {
+    /// How far back earlier reports count as repeats, in seconds
+    #[serde(default = "default_escalation_window_seconds")]
+    pub window_seconds: i64,
+    /// Severity added per earlier report (0 disables escalation)
+    #[serde(default)]
+    pub escalation_step: u8,
+    /// Highest severity escalation can reach
+    #[serde(default = "default_escalation_cap")]
+    pub escalation_cap: u8,
+}
+
+fn default_escalation_window_seconds() -> i64 {
+    6 * 3600
+}
+
+fn default_escalation_cap() -> u8 {
+    10
+}
+
+impl Default for EscalationConfig {
+    fn default() -> Self {
+        EscalationConfig {
+            window_seconds: default_escalation_window_seconds(),
+            escalation_step: 0,
+            escalation_cap: default_escalation_cap(),
+        }
+    }
+}
+
+/// Multi-signal risk correlation
+///
+/// Reports about the same user are collected as risk factors. Factors
+/// within `correlation_window_seconds` of each other combine into one
+/// score; once it reaches `min_score` a combined report is raised and the
+/// user's factors start over. Factors older than the window drop out, so
+/// weak signals hours apart never add up.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RiskConfig {
+    /// Combine per-user reports into risk assessments
+    #[serde(default)]
+    pub enabled: bool,
+    /// How close together factors must be to combine, in seconds
+    #[serde(default = "default_correlation_window_seconds")]
+    pub correlation_window_seconds: i64,
+    /// Combined score (0-10) that raises a report
+    #[serde(default = "default_min_risk_score")]
+    pub min_score: f64,
+}
+
+fn default_correlation_window_seconds() -> i64 {
+    15 * 60
+}
+
+fn default_min_risk_score() -> f64 {
+    8.0
+}
+
+impl Default for RiskConfig {
+    fn default() -> Self {
+        RiskConfig {
+            enabled: false,
+            correlation_window_seconds: default_correlation_window_seconds(),
+            min_score: default_min_risk_score(),
+        }
+    }
+}
+
+/// Off-hours login configuration
+///
+/// A login's local time is computed in the first available timezone of:
+/// the user's override, the IP's geolocated timezone, `default_timezone`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OffHoursConfig {
+    /// Start of business hours (local hour, 0-23, inclusive)
+    pub start_hour: u32,
+    /// End of business hours (local hour, 0-23, exclusive); may be less
+    /// than `start_hour` for windows spanning midnight
+    pub end_hour: u32,
+    /// IANA timezone used when no other timezone is known
+    #[serde(default = "default_timezone")]
+    pub default_timezone: String,
+    /// Per-user IANA timezone overrides
+    #[serde(default)]
+    pub user_timezones: HashMap<String, String>,
+}
+
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+impl Default for OffHoursConfig {
+    fn default() -> Self {
+        OffHoursConfig {
+            start_hour: 8,
+            end_hour: 18,
+            default_timezone: default_timezone(),
+            user_timezones: HashMap::new(),
+        }
+    }
+}
+
+/// Sudden IP switch rule configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpSwitchConfig {
+    /// Severity of a switch when the distance between the IPs is unknown
+    #[serde(default = "default_ip_switch_severity")]
+    pub severity: u8,
+    /// Scale severity with the distance between the old and new IP's
+    /// locations (needs GeoIP)
+    #[serde(default)]
+    pub scale_by_distance: bool,
+    /// Severity of a switch between IPs at the same location
+    #[serde(default = "default_ip_switch_min_severity")]
+    pub min_severity: u8,
+    /// Severity of a switch at or beyond `max_severity_distance_km`
+    #[serde(default = "default_ip_switch_max_severity")]
+    pub max_severity: u8,
+    /// Distance in km at which a switch reaches `max_severity`
+    #[serde(default = "default_ip_switch_max_severity_distance_km")]
+    pub max_severity_distance_km: f64,
+    /// Only successful logins are checked against, and update, the
+    /// user's trusted IP; failures are left to the rate limiter
+    #[serde(default)]
+    pub successful_logins_only: bool,
+    /// Remember this many of each user's most recent IPs; returning to
+    /// one of them isn't a switch (0 remembers only the last IP). An IP
+    /// that raised a switch is remembered once a later successful login
+    /// from it confirms it
+    #[serde(default)]
+    pub known_ips_per_user: usize,
+    /// Report a return to a remembered IP as an informational
+    /// "Returned to Known IP"
+    #[serde(default)]
+    pub report_returns: bool,
+    /// Severity of a "Returned to Known IP" report
+    #[serde(default = "default_ip_switch_return_severity")]
+    pub return_severity: u8,
+}
+
+fn default_ip_switch_severity() -> u8 {
+    8
+}
+
+fn default_ip_switch_min_severity() -> u8 {
+    4
+}
+
+fn default_ip_switch_max_severity() -> u8 {
+    9
+}
+
+fn default_ip_switch_max_severity_distance_km() -> f64 {
+    5000.0
+}
+
+fn default_ip_switch_return_severity() -> u8 {
+    2
+}
+
+impl Default for IpSwitchConfig {
+    fn default() -> Self {
+        IpSwitchConfig {
+            severity: default_ip_switch_severity(),
+            scale_by_distance: false,
+            min_severity: default_ip_switch_min_severity(),
+            max_severity: default_ip_switch_max_severity(),
+            max_severity_distance_km: default_ip_switch_max_severity_distance_km(),
+            successful_logins_only: false,
+            known_ips_per_user: 0,
+            report_returns: false,
+            return_severity: default_ip_switch_return_severity(),
+        }
+    }
+}
+
+/// Learned login hour pattern configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HourPatternConfig {
+    /// Logins a user needs before their pattern is trusted
+    #[serde(default = "default_hour_min_history")]
+    pub min_history: u32,
+    /// Logins in an hour holding at most this share (0.0-1.0) of the
+    /// user's history are flagged
+    #[serde(default = "default_max_hour_share")]
+    pub max_hour_share: f64,
+    /// Adjacent hours on either side counted towards an hour's share
+    #[serde(default = "default_neighbor_hours")]
+    pub neighbor_hours: u32,
+}
+
+fn default_hour_min_history() -> u32 {
+    20
+}
+
+fn default_max_hour_share() -> f64 {
+    0.02
+}
+
+fn default_neighbor_hours() -> u32 {
+    1
+}
+
+impl Default for HourPatternConfig {
+    fn default() -> Self {
+        HourPatternConfig {
+            min_history: default_hour_min_history(),
+            max_hour_share: default_max_hour_share(),
+            neighbor_hours: default_neighbor_hours(),
+        }
+    }
+}
+
+/// Home location for the outside-home-region rule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HomeRegionConfig {
+    /// Latitude of the home location in degrees
+    pub latitude: f64,
+    /// Longitude of the home location in degrees
+    pub longitude: f64,
+    /// Logins geolocated farther than this many km from home are flagged
+    #[serde(default = "default_home_radius_km")]
+    pub radius_km: f64,
+}
+
+fn default_home_radius_km() -> f64 {
+    500.0
+}
+
+impl Default for HomeRegionConfig {
+    fn default() -> Self {
+        HomeRegionConfig {
+            latitude: 0.0,
+            longitude: 0.0,
+            radius_km: default_home_radius_km(),
+        }
+    }
+}
+
+/// Merge `overlay` into `base` following the rules of [`Config::from_files`]
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(table)) => merge_tables(existing, table),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// First seen user configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FirstSeenConfig {
+    /// Severity of a first seen user report
+    #[serde(default = "default_first_seen_severity")]
+    pub severity: u8,
+    /// Start of a window (e.g. a bulk onboarding) in which new users are
+    /// remembered without a report; open-ended when unset
+    #[serde(default)]
+    pub suppress_from: Option<DateTime<Utc>>,
+    /// End (exclusive) of the suppression window; open-ended when unset
+    #[serde(default)]
+    pub suppress_until: Option<DateTime<Utc>>,
+}
+
+fn default_first_seen_severity() -> u8 {
+    3
+}
+
+impl Default for FirstSeenConfig {
+    fn default() -> Self {
+        FirstSeenConfig {
+            severity: default_first_seen_severity(),
+            suppress_from: None,
+            suppress_until: None,
+        }
+    }
+}
+
+/// Login during lockout configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockoutConfig {
+    /// Severity of a login during lockout report
+    #[serde(default = "default_lockout_severity")]
+    pub severity: u8,
+    /// Treat a lock as lifted this long after it was reported, for
+    /// systems that unlock silently (e.g. pam_faillock's `unlock_time`);
+    /// locks last until an unlock event when unset
+    #[serde(default)]
+    pub lock_duration_seconds: Option<i64>,
+}
+
+fn default_lockout_severity() -> u8 {
+    10
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        LockoutConfig {
+            severity: default_lockout_severity(),
+            lock_duration_seconds: None,
         }
     }
 }
@@ -69,12 +1204,76 @@ impl Default for GeoLocationConfig {
 /// Rate limiting configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RateLimitConfig {
-    /// Time window in seconds
+    /// Time window in seconds (must be positive)
     pub window_seconds: i64,
-    /// Maximum login attempts per user within window
+    /// Maximum login attempts per user within window (0 disables the limit)
     pub max_user_attempts: usize,
-    /// Maximum login attempts per IP within window
+    /// Maximum login attempts per IP within window (0 disables the limit)
     pub max_ip_attempts: usize,
+    /// Emit a low-severity report when an exceeded limit clears
+    #[serde(default)]
+    pub alert_on_resolve: bool,
+    /// How many attempts an event of each type counts as (default 1; 0
+    /// for `HTTP_REQUEST` and `HTTP_LOGIN`), e.g. `{ SSH_FAILED_MAX_AUTH = 3 }`
+    #[serde(default)]
+    pub event_weights: HashMap<String, usize>,
+    /// Merge over-limit reports for the same user or IP within this many
+    /// seconds into one report with an attempt timeline (disabled if unset).
+    /// Daemon only; inline decisions need every report immediately.
+    #[serde(default)]
+    pub merge_window_seconds: Option<i64>,
+    /// Emit a merged report early once it covers this many attempts
+    #[serde(default)]
+    pub merge_max_count: Option<usize>,
+    /// Also count attempts per IPv4 subnet of this prefix length (e.g. 24,
+    /// at most 32)
+    #[serde(default)]
+    pub subnet_prefix: Option<u8>,
+    /// Prefix length IPv6 sources are counted by when subnet aggregation
+    /// is enabled (at most 128)
+    #[serde(default = "default_ipv6_subnet_prefix")]
+    pub ipv6_subnet_prefix: u8,
+    /// Maximum attempts per subnet within window; subnet aggregation is
+    /// enabled when this and `subnet_prefix` are both set
+    #[serde(default)]
+    pub max_subnet_attempts: Option<usize>,
+}
+
+fn default_ipv6_subnet_prefix() -> u8 {
+    crate::detection::rate_limiter::DEFAULT_IPV6_SUBNET_PREFIX
+}
+
+impl RateLimitConfig {
+    /// Reject settings the sliding windows can't work with
+    pub fn validate(&self) -> Result<(), String> {
+        if self.window_seconds <= 0 {
+            return Err(format!("Rate limit window {} seconds must be positive", self.window_seconds));
+        }
+        if let Some(merge_window) = self.merge_window_seconds.filter(|&w| w <= 0) {
+            return Err(format!("Rate limit merge window {} seconds must be positive", merge_window));
+        }
+        if let Some(prefix) = self.subnet_prefix.filter(|&p| p > 32) {
+            return Err(format!("Rate limit subnet prefix /{} is longer than an IPv4 address", prefix));
+        }
+        if self.ipv6_subnet_prefix > 128 {
+            return Err(format!(
+                "Rate limit IPv6 subnet prefix /{} is longer than an IPv6 address",
+                self.ipv6_subnet_prefix
+            ));
+        }
+        // Event types match exactly, so rows differing only in case or
+        // spacing would leave one of them silently unused
+        let mut seen = HashMap::new();
+        for event_type in self.event_weights.keys() {
+            if let Some(other) = seen.insert(event_type.trim().to_uppercase(), event_type) {
+                return Err(format!(
+                    "Rate limit event_weights lists {:?} and {:?}, which name the same event type",
+                    other, event_type
+                ));
+            }
+        }
+        Ok(())
+    }
 }
 
 /// Geo velocity configuration
@@ -82,32 +1281,258 @@ pub struct RateLimitConfig {
 pub struct GeoVelocityConfig {
     /// Maximum plausible travel speed in km/h
     pub max_velocity_kmh: f64,
+    /// Ignore a user's new location if it arrives within this many seconds
+    /// of the last recorded one and is nearby (0 records every location)
+    #[serde(default)]
+    pub min_location_interval_seconds: i64,
+    /// Only flag travel when both lookups have an accuracy radius of at
+    /// most this many km (unset flags regardless of accuracy)
+    #[serde(default)]
+    pub max_accuracy_radius_km: Option<u16>,
+    /// Skip evaluation when a login comes from the same IP as the user's
+    /// last recorded location, whatever coordinates it resolves to now
+    #[serde(default = "default_skip_same_ip")]
+    pub skip_same_ip: bool,
+    /// Only successful logins are checked against, and update, the
+    /// user's last location; a failed attempt doesn't mean the user was
+    /// there
+    #[serde(default)]
+    pub successful_logins_only: bool,
+}
+
+fn default_skip_same_ip() -> bool {
+    true
 }
 
 /// Output configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OutputConfig {
-    /// Output format: "json", "jsonl", or "console"
+    /// Output format: "json", "jsonl", "syslog", "console", "console-compact" or "ecs"
     pub format: String,
-    /// Output file path (if format is not "console")
+    /// Output file path (ignored for the console formats)
     pub file_path: Option<PathBuf>,
+    /// Mapping from report severity (1-10) to syslog level (0-7) used by
+    /// the "syslog" format
+    #[serde(default = "default_syslog_levels")]
+    pub syslog_levels: Vec<SyslogLevelMapping>,
+    /// Buffer output and flush at most every N milliseconds (0 flushes
+    /// after every report)
+    #[serde(default)]
+    pub flush_interval_ms: u64,
+    /// Additional sinks, each with its own format, destination and filter.
+    /// When set, these replace the single `format`/`file_path` sink.
+    #[serde(default)]
+    pub sinks: Vec<SinkSpec>,
+}
+
+/// A single output destination
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SinkSpec {
+    /// Output format: "json", "jsonl", "syslog", "console", "console-compact" or "ecs"
+    pub format: String,
+    /// Output file path (ignored for the console formats)
+    #[serde(default)]
+    pub file_path: Option<PathBuf>,
+    /// Only write reports with at least this severity
+    #[serde(default)]
+    pub min_severity: u8,
+    /// Only write reports from these rules (all rules if unset)
+    #[serde(default)]
+    pub rules: Option<Vec<String>>,
+}
+
+impl SinkSpec {
+    /// Check whether a report passes this sink's filter
+    pub fn accepts(&self, report: &AnomalyReport) -> bool {
+        report.severity >= self.min_severity
+            && self
+                .rules
+                .as_ref()
+                .is_none_or(|rules| rules.iter().any(|r| r == &report.rule_name))
+    }
+}
+
+impl OutputConfig {
+    /// The configured sinks, falling back to the single `format`/`file_path`
+    /// sink when no `sinks` are listed
+    pub fn sink_specs(&self) -> Vec<SinkSpec> {
+        if !self.sinks.is_empty() {
+            return self.sinks.clone();
+        }
+        vec![SinkSpec {
+            format: self.format.clone(),
+            file_path: self.file_path.clone(),
+            min_severity: 0,
+            rules: None,
+        }]
+    }
+}
+
+/// Reports with at least `min_severity` are emitted at syslog `level`
+///
+/// The entry with the highest matching `min_severity` wins.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SyslogLevelMapping {
+    pub min_severity: u8,
+    /// Syslog level: 0 (emerg) to 7 (debug)
+    pub level: u8,
+}
+
+fn default_syslog_levels() -> Vec<SyslogLevelMapping> {
+    [(9, 2), (7, 3), (5, 4), (3, 5), (1, 6)]
+        .into_iter()
+        .map(|(min_severity, level)| SyslogLevelMapping { min_severity, level })
+        .collect()
+}
+
+/// Persistence configuration for state storage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersistenceConfig {
+    /// Enable persistent state storage
+    pub enabled: bool,
+    /// Path to SQLite database file; may contain `{YYYY}`, `{MM}` and
+    /// `{DD}` placeholders that are expanded with the current UTC date
+    pub database_path: Option<PathBuf>,
+    /// Switch to a new database file when a templated path's date changes
+    #[serde(default)]
+    pub rollover: bool,
+    /// Update the timestamp of a user's latest stored location instead of
+    /// inserting a new row when they're seen at the same coordinates again
+    #[serde(default)]
+    pub dedup_locations: bool,
+    /// Only store anomaly reports at or above this severity (0 stores
+    /// everything); output and alerting are unaffected
+    #[serde(default)]
+    pub min_severity_to_store: u8,
+    /// JSON file of per-user baselines loaded at startup; users that
+    /// already have stored state are left untouched
+    #[serde(default)]
+    pub seed_file: Option<PathBuf>,
+    /// Extra databases that receive a copy of every anomaly report (and
+    /// nothing else), each written independently of the others
+    #[serde(default)]
+    pub report_sinks: Vec<ReportSinkSpec>,
+}
+
+/// A database that only stores anomaly reports
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportSinkSpec {
+    /// Path to the SQLite database file
+    pub database_path: PathBuf,
+    /// Only store reports with at least this severity
+    #[serde(default)]
+    pub min_severity: u8,
+}
+
+impl PersistenceConfig {
+    /// Reject report sinks that would write to the primary database
+    pub fn validate(&self) -> Result<(), String> {
+        let Some(primary) = self.database_path.as_ref().filter(|_| self.enabled) else {
+            return Ok(());
+        };
+        let canonical = |path: &PathBuf| path.canonicalize().unwrap_or_else(|_| path.clone());
+        for sink in &self.report_sinks {
+            if canonical(&sink.database_path) == canonical(primary) {
+                return Err(format!(
+                    "Report sink {} is the primary database; sinks must be separate databases",
+                    sink.database_path.display()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for PersistenceConfig {
+    fn default() -> Self {
+        PersistenceConfig {
+            enabled: true,
+            database_path: Some(PathBuf::from("odin_state.db")),
+            rollover: false,
+            dedup_locations: false,
+            min_severity_to_store: 0,
+            seed_file: None,
+            report_sinks: Vec::new(),
+        }
+    }
+}
+
+/// Read-only HTTP API configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiConfig {
+    /// Enable the HTTP API (requires persistence)
+    pub enabled: bool,
+    /// Address to listen on
+    pub bind_address: String,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        ApiConfig {
+            enabled: false,
+            bind_address: "127.0.0.1:8088".to_string(),
+        }
+    }
 }
 
-/// Persistence configuration for state storage
+/// OpenTelemetry tracing configuration
+///
+/// Each processed event becomes a trace with a span per enrichment stage,
+/// rule and output step, exported over OTLP/HTTP. Needs the `otel` build
+/// feature.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PersistenceConfig {
-    /// Enable persistent state storage
+pub struct TelemetryConfig {
+    /// Export traces of event processing
+    #[serde(default)]
     pub enabled: bool,
-    /// Path to SQLite database file
-    pub database_path: Option<PathBuf>,
+    /// OTLP/HTTP traces endpoint of the collector
+    #[serde(default = "default_otlp_endpoint")]
+    pub otlp_endpoint: String,
+    /// `service.name` reported with the traces
+    #[serde(default = "default_telemetry_service_name")]
+    pub service_name: String,
+    /// Share of events traced (0.0-1.0)
+    #[serde(default = "default_telemetry_sample_ratio")]
+    pub sample_ratio: f64,
+    /// Record each event's user and source IP on its root span
+    ///
+    /// Off by default: both are personal data, and collectors tend to keep
+    /// traces longer and with wider access than the detection database.
+    #[serde(default)]
+    pub record_identities: bool,
 }
 
-impl Default for PersistenceConfig {
+fn default_otlp_endpoint() -> String {
+    "http://localhost:4318/v1/traces".to_string()
+}
+
+fn default_telemetry_service_name() -> String {
+    "odin".to_string()
+}
+
+fn default_telemetry_sample_ratio() -> f64 {
+    1.0
+}
+
+impl Default for TelemetryConfig {
     fn default() -> Self {
-        PersistenceConfig {
-            enabled: true,
-            database_path: Some(PathBuf::from("odin_state.db")),
+        TelemetryConfig {
+            enabled: false,
+            otlp_endpoint: default_otlp_endpoint(),
+            service_name: default_telemetry_service_name(),
+            sample_ratio: default_telemetry_sample_ratio(),
+            record_identities: false,
+        }
+    }
+}
+
+impl TelemetryConfig {
+    /// Reject a sample ratio that isn't a share of events
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.sample_ratio) {
+            return Err(format!("Telemetry sample ratio {} must be between 0.0 and 1.0", self.sample_ratio));
         }
+        Ok(())
     }
 }
 
@@ -125,6 +1550,127 @@ pub struct AlertConfig {
     /// Generic webhook configurations
     #[serde(default)]
     pub webhooks: Vec<WebhookConfig>,
+    /// Local Unix socket receiving newline-delimited JSON reports
+    #[serde(default)]
+    pub unix_socket: Option<UnixSocketConfig>,
+    /// Periodic liveness ping, sent even when alerting is disabled
+    #[serde(default)]
+    pub heartbeat: Option<HeartbeatConfig>,
+    /// Rules that always alert, regardless of `min_severity`
+    #[serde(default)]
+    pub always_alert_rules: Vec<String>,
+    /// Most alerts dispatched per minute across all rules, users and IPs;
+    /// alerts beyond this are collapsed into a periodic summary
+    #[serde(default)]
+    pub global_alert_rate_per_min: Option<u32>,
+    /// Consecutive failures before a channel's circuit opens (0 disables)
+    #[serde(default = "default_circuit_failure_threshold")]
+    pub circuit_failure_threshold: u32,
+    /// Seconds a channel is skipped before a recovery attempt
+    #[serde(default = "default_circuit_reset_seconds")]
+    pub circuit_reset_seconds: u64,
+    /// Extra delivery attempts before an alert counts as undelivered
+    #[serde(default = "default_dispatch_retries")]
+    pub dispatch_retries: u32,
+    /// Persistence and redelivery of undelivered alerts
+    #[serde(default)]
+    pub pending: PendingAlertConfig,
+    /// Keep idle connections to webhook hosts open between alerts; disable
+    /// where internal DNS is volatile so every alert resolves the host
+    #[serde(default = "default_reuse_connections")]
+    pub reuse_connections: bool,
+    /// Rebuild the HTTP client (dropping cached connections) after this many
+    /// seconds so hostnames are re-resolved after a failover
+    #[serde(default)]
+    pub client_refresh_seconds: Option<u64>,
+    /// Most alerts being dispatched at once; further alerts wait in the
+    /// queue for a free slot
+    #[serde(default = "default_max_concurrent_dispatches")]
+    pub max_concurrent_dispatches: usize,
+    /// Message body for plain-text channels; placeholders are `{user}`,
+    /// `{rule}`, `{severity}`, `{ip}`, `{description}` and `{timestamp}`
+    #[serde(default = "default_text_template")]
+    pub text_template: String,
+    /// strftime format of `{timestamp}` in the text template (UTC)
+    #[serde(default = "default_text_timestamp_format")]
+    pub text_timestamp_format: String,
+}
+
+fn default_text_template() -> String {
+    "[{severity}/10] {rule}: {user} from {ip} at {timestamp} - {description}".to_string()
+}
+
+fn default_text_timestamp_format() -> String {
+    "%Y-%m-%d %H:%M:%S UTC".to_string()
+}
+
+fn default_circuit_failure_threshold() -> u32 {
+    5
+}
+
+fn default_circuit_reset_seconds() -> u64 {
+    60
+}
+
+fn default_dispatch_retries() -> u32 {
+    2
+}
+
+fn default_reuse_connections() -> bool {
+    true
+}
+
+fn default_max_concurrent_dispatches() -> usize {
+    8
+}
+
+/// Undelivered alert persistence configuration
+///
+/// Alerts that still fail after `dispatch_retries` are stored in the
+/// persistence database and redelivered on startup and periodically,
+/// giving at-least-once delivery across restarts. A channel that already
+/// received an alert may receive it again when another channel failed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingAlertConfig {
+    /// Persist undelivered alerts (requires persistence)
+    #[serde(default = "default_pending_enabled")]
+    pub enabled: bool,
+    /// Seconds between redelivery attempts
+    #[serde(default = "default_pending_retry_interval_seconds")]
+    pub retry_interval_seconds: u64,
+    /// Give up on alerts that have been pending this many seconds
+    #[serde(default = "default_pending_max_age_seconds")]
+    pub max_age_seconds: i64,
+    /// Maximum number of pending alerts kept (oldest dropped first)
+    #[serde(default = "default_pending_max_entries")]
+    pub max_entries: usize,
+}
+
+fn default_pending_enabled() -> bool {
+    true
+}
+
+fn default_pending_retry_interval_seconds() -> u64 {
+    60
+}
+
+fn default_pending_max_age_seconds() -> i64 {
+    24 * 3600
+}
+
+fn default_pending_max_entries() -> usize {
+    1000
+}
+
+impl Default for PendingAlertConfig {
+    fn default() -> Self {
+        PendingAlertConfig {
+            enabled: default_pending_enabled(),
+            retry_interval_seconds: default_pending_retry_interval_seconds(),
+            max_age_seconds: default_pending_max_age_seconds(),
+            max_entries: default_pending_max_entries(),
+        }
+    }
 }
 
 impl Default for AlertConfig {
@@ -135,10 +1681,33 @@ impl Default for AlertConfig {
             slack: None,
             discord: None,
             webhooks: Vec::new(),
+            unix_socket: None,
+            heartbeat: None,
+            always_alert_rules: Vec::new(),
+            global_alert_rate_per_min: None,
+            circuit_failure_threshold: default_circuit_failure_threshold(),
+            circuit_reset_seconds: default_circuit_reset_seconds(),
+            dispatch_retries: default_dispatch_retries(),
+            pending: PendingAlertConfig::default(),
+            reuse_connections: default_reuse_connections(),
+            client_refresh_seconds: None,
+            max_concurrent_dispatches: default_max_concurrent_dispatches(),
+            text_template: default_text_template(),
+            text_timestamp_format: default_text_timestamp_format(),
         }
     }
 }
 
+impl AlertConfig {
+    /// Check whether a report passes the severity gate
+    ///
+    /// Rules listed in `always_alert_rules` bypass the threshold entirely.
+    pub fn should_alert(&self, report: &AnomalyReport) -> bool {
+        report.severity >= self.min_severity
+            || self.always_alert_rules.iter().any(|r| r == &report.rule_name)
+    }
+}
+
 /// Slack webhook configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SlackConfig {
@@ -148,6 +1717,9 @@ pub struct SlackConfig {
     pub channel: Option<String>,
     /// Username for the bot (optional)
     pub username: Option<String>,
+    /// Request timeout in seconds (defaults to 30)
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
 }
 
 /// Discord webhook configuration
@@ -157,6 +1729,9 @@ pub struct DiscordConfig {
     pub webhook_url: String,
     /// Username for the bot (optional)
     pub username: Option<String>,
+    /// Request timeout in seconds (defaults to 30)
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
 }
 
 /// Generic webhook configuration
@@ -170,6 +1745,53 @@ pub struct WebhookConfig {
     pub method: Option<String>,
     /// Custom headers to include
     pub headers: Option<HashMap<String, String>>,
+    /// Request timeout in seconds (defaults to 30)
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+/// Heartbeat (liveness ping) configuration
+///
+/// Point `url` at a dead man's switch service so that missing heartbeats,
+/// rather than silence, tell the team the daemon is down.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeartbeatConfig {
+    /// Endpoint the heartbeat is POSTed to
+    pub url: String,
+    /// Seconds between heartbeats
+    #[serde(default = "default_heartbeat_interval_seconds")]
+    pub interval_seconds: u64,
+    /// Request timeout in seconds
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+fn default_heartbeat_interval_seconds() -> u64 {
+    300
+}
+
+/// Unix domain socket alert channel configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnixSocketConfig {
+    /// Path of the socket to connect to
+    pub path: PathBuf,
+    /// How each report is written
+    #[serde(default)]
+    pub format: AlertFormat,
+    /// Write timeout in seconds
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+/// Encoding of reports sent to a line-oriented alert channel
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertFormat {
+    /// One JSON object per line
+    #[default]
+    Json,
+    /// The alerting text template
+    Text,
 }
 
 impl Default for Config {
@@ -179,44 +1801,1120 @@ impl Default for Config {
                 source_type: "file".to_string(),
                 file_path: Some(PathBuf::from("/var/log/auth.log")),
                 syslog_address: None,
+                log_format: LineFormat::Text,
+                process_event_types: None,
+                suppression_audit_file: None,
+                timestamp_formats: None,
+                exit_on_eof: false,
+                rotation_check_interval_ms: default_rotation_check_interval_ms(),
+                syslog_buffer_size: default_syslog_buffer_size(),
+                syslog_max_connections: default_syslog_max_connections(),
+                syslog_idle_timeout_seconds: default_syslog_idle_timeout_seconds(),
+                username_normalization: UsernameNormalizationConfig::default(),
+                parse_failure_alert_ratio: None,
+                unknown_user: UnknownUserPolicy::default(),
+                detailed_failure_types: false,
+                extract_host: default_extract_host(),
+                event_type_mappings: Vec::new(),
+                sample_rates: HashMap::new(),
+                clock_guard: ClockGuardConfig::default(),
+                max_event_age_seconds: None,
+                dedup_window_seconds: None,
+                reorder_delay_seconds: None,
+                reorder_max_events: default_reorder_max_events(),
+                sources: Vec::new(),
             },
             detection: DetectionConfig {
                 enable_ip_switch: true,
                 enable_geo_velocity: true,
                 enable_rate_limiting: true,
+                enable_hosting_asn: false,
+                enable_attacking_ip: false,
+                enable_off_hours: false,
+                enable_home_region: false,
+                enable_hour_pattern: false,
+                ip_switch: IpSwitchConfig::default(),
                 rate_limit: RateLimitConfig {
                     window_seconds: 300,
                     max_user_attempts: 10,
                     max_ip_attempts: 20,
+                    alert_on_resolve: false,
+                    event_weights: HashMap::new(),
+                    merge_window_seconds: None,
+                    merge_max_count: None,
+                    subnet_prefix: None,
+                    ipv6_subnet_prefix: default_ipv6_subnet_prefix(),
+                    max_subnet_attempts: None,
                 },
                 geo_velocity: GeoVelocityConfig {
                     max_velocity_kmh: 900.0,
+                    min_location_interval_seconds: 0,
+                    max_accuracy_radius_km: None,
+                    skip_same_ip: default_skip_same_ip(),
+                    successful_logins_only: false,
                 },
                 geo_location: GeoLocationConfig::default(),
+                hosting_asn: HostingAsnConfig::default(),
+                reverse_dns: ReverseDnsConfig::default(),
+                attacking_ip: AttackingIpConfig::default(),
+                success_cluster: SuccessClusterConfig::default(),
+                off_hours: OffHoursConfig::default(),
+                home_region: HomeRegionConfig::default(),
+                hour_pattern: HourPatternConfig::default(),
+                first_seen: FirstSeenConfig::default(),
+                lockout: LockoutConfig::default(),
+                escalation: EscalationConfig::default(),
+                risk: RiskConfig::default(),
+                decision: DecisionConfig::default(),
+                enrich_last_seen: false,
+                rule_order: Vec::new(),
+                enabled_rules: Vec::new(),
+                disabled_rules: Vec::new(),
+                short_circuit_severity: None,
+                max_reports_per_event: None,
+                min_report_interval_per_user_seconds: None,
+                persist_report_throttle: false,
+                maintenance_control_file: None,
+                explain: false,
+                include_rule_parameters: false,
+                max_tracked_entries: default_max_tracked_entries(),
+                processing_workers: default_processing_workers(),
+                processing_mode: ProcessingMode::default(),
+                enrichment_stages: default_enrichment_stages(),
             },
             output: OutputConfig {
                 format: "json".to_string(),
                 file_path: Some(PathBuf::from("anomalies.jsonl")),
+                syslog_levels: default_syslog_levels(),
+                flush_interval_ms: 0,
+                sinks: Vec::new(),
             },
             persistence: PersistenceConfig::default(),
             alerting: AlertConfig::default(),
+            api: ApiConfig::default(),
+            telemetry: TelemetryConfig::default(),
         }
     }
 }
 
+/// Placeholder for secret values in [`Config::redacted`]
+pub const REDACTED: &str = "<redacted>";
+
 impl Config {
     /// Load configuration from a file
     pub fn from_file(path: &PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
         let contents = std::fs::read_to_string(path)?;
         let config: Config = toml::from_str(&contents)?;
+        config.validate()?;
         Ok(config)
     }
 
+    /// Load configuration from several files, later files overriding
+    /// earlier ones
+    ///
+    /// The files are merged as TOML before being read as a configuration,
+    /// so a required field may come from any of them and an override only
+    /// needs the keys it changes. Merge rules:
+    ///
+    /// - Tables (`[section]`, inline tables) merge key by key, recursively.
+    /// - Any other value set in a later file replaces the earlier one.
+    /// - Arrays, including arrays of tables such as `[[alerting.webhooks]]`,
+    ///   are replaced as a whole, never appended: an override listing one
+    ///   webhook ends up with exactly that webhook.
+    /// - A key missing from a later file keeps its earlier value. TOML has
+    ///   no null, so an optional field set by an earlier file can't be
+    ///   unset by a later one.
+    /// - A table replacing a non-table (or the reverse) replaces it.
+    pub fn from_files(paths: &[PathBuf]) -> Result<Self, Box<dyn std::error::Error>> {
+        if paths.is_empty() {
+            return Err("no configuration files given".into());
+        }
+        let mut merged = toml::Table::new();
+        for path in paths {
+            let contents = std::fs::read_to_string(path)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            let table: toml::Table = toml::from_str(&contents)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            merge_tables(&mut merged, table);
+        }
+        let config = Config::deserialize(toml::Value::Table(merged))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Reject values that parse but can't work
+    pub fn validate(&self) -> Result<(), String> {
+        self.input.validate()?;
+        self.detection.rate_limit.validate()?;
+        self.telemetry.validate()?;
+        self.persistence.validate()
+    }
+
+    /// A copy safe to log, with secrets (webhook and heartbeat URLs,
+    /// webhook header values, the GeoIP license key) replaced by
+    /// [`REDACTED`]
+    pub fn redacted(&self) -> Config {
+        let mut config = self.clone();
+        let alerting = &mut config.alerting;
+        if let Some(slack) = &mut alerting.slack {
+            slack.webhook_url = REDACTED.to_string();
+        }
+        if let Some(discord) = &mut alerting.discord {
+            discord.webhook_url = REDACTED.to_string();
+        }
+        for webhook in &mut alerting.webhooks {
+            webhook.url = REDACTED.to_string();
+            for value in webhook.headers.iter_mut().flat_map(|headers| headers.values_mut()) {
+                *value = REDACTED.to_string();
+            }
+        }
+        if let Some(heartbeat) = &mut alerting.heartbeat {
+            heartbeat.url = REDACTED.to_string();
+        }
+        if let Some(key) = &mut config.detection.geo_location.download_license_key {
+            *key = REDACTED.to_string();
+        }
+        config
+    }
+
+    /// The configuration as TOML with secrets redacted, for dumping the
+    /// effective settings of a running daemon
+    pub fn to_redacted_toml(&self) -> Result<String, toml::ser::Error> {
+        toml::to_string(&self.redacted())
+    }
+
     /// Save configuration to a file
+    ///
+    /// The file is written with [`Config::to_documented_toml`], so every
+    /// field carries a comment and unset optional sections appear as
+    /// commented-out examples.
     pub fn to_file(&self, path: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
-        let contents = toml::to_string_pretty(self)?;
+        let contents = self.to_documented_toml()?;
         std::fs::write(path, contents)?;
         Ok(())
     }
+
+    /// Render the configuration as commented TOML
+    ///
+    /// Unlike `toml::to_string_pretty`, each field is preceded by a short
+    /// description and optional sections that aren't configured (alert
+    /// channels, unset paths) are included commented out with placeholder
+    /// values, so users can discover them.
+    pub fn to_documented_toml(&self) -> Result<String, toml::ser::Error> {
+        let mut w = TemplateWriter::default();
+        w.comment("Odin intrusion detection configuration");
+
+        let input = &self.input;
+        w.section("input", None);
+        w.field("Input source: \"file\", \"fifo\", \"syslog\", \"syslog-tcp\" or \"stdin\"", "source_type", &input.source_type)?;
+        w.optional("Log file to tail, or named pipe to read (file and fifo sources)", "file_path", input.file_path.as_ref(), "\"/var/log/auth.log\"")?;
+        w.optional("Address to receive syslog on (UDP for syslog, TCP for syslog-tcp sources)", "syslog_address", input.syslog_address.as_ref(), "\"0.0.0.0:514\"")?;
+        w.field("Line format: \"text\", \"json\", \"logfmt\" or \"nginx_access\"", "log_format", &input.log_format)?;
+        w.optional("Only process these event types", "process_event_types", input.process_event_types.as_ref(), "[\"SSH_LOGIN\", \"SSH_FAILED\"]")?;
+        w.optional("Record events kept from detection by process_event_types or unknown_user to this file, with the reason", "suppression_audit_file", input.suppression_audit_file.as_ref(), "\"/var/log/odin/suppressed.jsonl\"")?;
+        w.optional("Timestamp formats tried in order (built-in names or strftime patterns)", "timestamp_formats", input.timestamp_formats.as_ref(), "[\"rfc3339\", \"syslog\"]")?;
+        w.field("Stop the daemon at end of input (stdin source)", "exit_on_eof", &input.exit_on_eof)?;
+        w.field("Check tailed files for rotation this often while idle (milliseconds)", "rotation_check_interval_ms", &input.rotation_check_interval_ms)?;
+        w.field("Largest UDP syslog message received whole, in bytes (longer ones are dropped)", "syslog_buffer_size", &input.syslog_buffer_size)?;
+        w.field("Most TCP syslog connections open at once", "syslog_max_connections", &input.syslog_max_connections)?;
+        w.field("Close TCP syslog connections idle for this long (seconds)", "syslog_idle_timeout_seconds", &input.syslog_idle_timeout_seconds)?;
+        w.optional("Alert when this share of lines (0.0-1.0) fails to parse", "parse_failure_alert_ratio", input.parse_failure_alert_ratio.as_ref(), "0.5")?;
+        w.field("Events without a username: \"drop\", \"ip_only\" or \"process\"", "unknown_user", &input.unknown_user)?;
+        w.field("Split sshd failures into SSH_FAILED_PASSWORD, SSH_FAILED_MAX_AUTH, ...", "detailed_failure_types", &input.detailed_failure_types)?;
+        w.field("Read the originating host from syslog headers into events and reports", "extract_host", &input.extract_host)?;
+        w.optional("Drop events older than this many seconds before detection (ignored in replay mode)", "max_event_age_seconds", input.max_event_age_seconds.as_ref(), "3600")?;
+        w.optional("Drop copies of an event (same user, IP, host, timestamp and type) received from another source within this many seconds", "dedup_window_seconds", input.dedup_window_seconds.as_ref(), "60")?;
+        w.optional("Hold events this many seconds and pass them to detection in timestamp order", "reorder_delay_seconds", input.reorder_delay_seconds.as_ref(), "2")?;
+        w.field("Most events held for reordering", "reorder_max_events", &input.reorder_max_events)?;
+        if input.sample_rates.is_empty() {
+            w.example("Process only 1 in N events of these types (logins and failures are never sampled)", "sample_rates", "{ SSH_DISCONNECT = 10 }");
+        } else {
+            w.field("Process only 1 in N events of these types (logins and failures are never sampled)", "sample_rates", &input.sample_rates)?;
+        }
+        w.section("input.username_normalization", Some("Username normalization applied at ingestion"));
+        let norm = &input.username_normalization;
+        w.field("Convert usernames to lowercase", "lowercase", &norm.lowercase)?;
+        w.field("Strip a trailing @domain or Kerberos realm", "strip_domain", &norm.strip_domain)?;
+        w.field("Strip a leading DOMAIN\\ prefix", "strip_domain_prefix", &norm.strip_domain_prefix)?;
+        if input.event_type_mappings.is_empty() {
+            w.commented_section(
+                "[input.event_type_mappings]",
+                "Custom event types for lines containing a phrase (repeat the table for each mapping)",
+                &[
+                    "pattern = \"Failed password for invalid user\"",
+                    "event_type = \"SSH_FAILED_INVALID_USER\"",
+                ],
+            );
+        }
+        for mapping in &input.event_type_mappings {
+            w.section("[input.event_type_mappings]", Some("Custom event type mapping"));
+            w.field("Phrase the line must contain", "pattern", &mapping.pattern)?;
+            w.field("Event type for matching lines", "event_type", &mapping.event_type)?;
+        }
+
+        if input.sources.is_empty() {
+            w.commented_section(
+                "[input.sources]",
+                "Sources read together, replacing source_type above (repeat the table for each source)",
+                &[
+                    "source_type = \"file\"",
+                    "file_path = \"/var/log/app/auth.jsonl\"",
+                    "format = \"json\"",
+                ],
+            );
+        }
+        for source in &input.sources {
+            w.section("[input.sources]", Some("Input source"));
+            w.field("Input source: \"file\", \"fifo\", \"syslog\", \"syslog-tcp\" or \"stdin\"", "source_type", &source.source_type)?;
+            w.optional("Log file to tail, or named pipe to read (file and fifo sources)", "file_path", source.file_path.as_ref(), "\"/var/log/auth.log\"")?;
+            w.optional("Address to receive syslog on (UDP for syslog, TCP for syslog-tcp sources)", "syslog_address", source.syslog_address.as_ref(), "\"0.0.0.0:514\"")?;
+            w.field("Line format: \"text\", \"json\", \"logfmt\" or \"nginx_access\"", "format", &source.format)?;
+            w.optional("Timestamp formats tried in order (built-in names or strftime patterns)", "timestamp_formats", source.timestamp_formats.as_ref(), "[\"rfc3339\", \"syslog\"]")?;
+            if matches!(source.format, LineFormat::Json | LineFormat::Logfmt) {
+                let doc = "Keys holding each event field in JSON or logfmt lines (unset keys use the format's defaults)";
+                if source.fields == FieldsConfig::default() {
+                    w.example(doc, "fields", "{ user = \"username\", ip = \"client_ip\" }");
+                } else {
+                    w.field(doc, "fields", &source.fields)?;
+                }
+            }
+        }
+
+        w.section("input.clock_guard", Some("Suspend time-sensitive rules when the host clock is stepped"));
+        let clock = &input.clock_guard;
+        w.field("Detect clock steps from time daemon lines and timestamp jumps", "enabled", &clock.enabled)?;
+        w.field("Offset change in seconds treated as a clock step", "max_jump_seconds", &clock.max_jump_seconds)?;
+        w.field("Seconds time-sensitive rules stay suspended after a step", "suspend_seconds", &clock.suspend_seconds)?;
+
+        let detection = &self.detection;
+        w.section("detection", None);
+        w.field("Flag a user's login from a new IP", "enable_ip_switch", &detection.enable_ip_switch)?;
+        w.field("Flag physically impossible travel between logins (needs GeoIP)", "enable_geo_velocity", &detection.enable_geo_velocity)?;
+        w.field("Flag too many login attempts per user or IP", "enable_rate_limiting", &detection.enable_rate_limiting)?;
+        w.field("Flag logins from hosting providers (needs the ASN database)", "enable_hosting_asn", &detection.enable_hosting_asn)?;
+        w.field("Flag successful logins from IPs failing against other users", "enable_attacking_ip", &detection.enable_attacking_ip)?;
+        w.field("Flag logins outside business hours", "enable_off_hours", &detection.enable_off_hours)?;
+        w.field("Flag logins far from the home location (needs GeoIP)", "enable_home_region", &detection.enable_home_region)?;
+        w.field("Flag logins in hours that are rare for the user", "enable_hour_pattern", &detection.enable_hour_pattern)?;
+        w.field("Attach the user's previous event time to reports (needs persistence)", "enrich_last_seen", &detection.enrich_last_seen)?;
+        if detection.rule_order.is_empty() {
+            w.example("Order rules are evaluated in (unlisted rules follow in the default order)", "rule_order", "[\"attacking_ip\", \"ip_switch\", \"rate_limit\"]");
+        } else {
+            w.field("Order rules are evaluated in (unlisted rules follow in the default order)", "rule_order", &detection.rule_order)?;
+        }
+        if detection.enabled_rules.is_empty() {
+            w.example("Rules to turn on regardless of their enable_* flag (success_cluster, first_seen and lockout only run when listed)", "enabled_rules", "[\"hour_pattern\", \"first_seen\"]");
+        } else {
+            w.field("Rules to turn on regardless of their enable_* flag (success_cluster, first_seen and lockout only run when listed)", "enabled_rules", &detection.enabled_rules)?;
+        }
+        if detection.disabled_rules.is_empty() {
+            w.example("Rules to turn off (wins over enabled_rules and enable_* flags)", "disabled_rules", "[\"rate_limit\"]");
+        } else {
+            w.field("Rules to turn off (wins over enabled_rules and enable_* flags)", "disabled_rules", &detection.disabled_rules)?;
+        }
+        w.optional("Skip remaining rules once a report reaches this severity (skipped rules don't learn from the event)", "short_circuit_severity", detection.short_circuit_severity.as_ref(), "9")?;
+        w.optional("Emit at most this many reports per event (1 = one combined report)", "max_reports_per_event", detection.max_reports_per_event.as_ref(), "2")?;
+        w.optional("Emit at most one report per user per this many seconds, unless more severe", "min_report_interval_per_user_seconds", detection.min_report_interval_per_user_seconds.as_ref(), "3600")?;
+        w.field("Keep per-user report cooldowns in the database across restarts", "persist_report_throttle", &detection.persist_report_throttle)?;
+        w.optional("Suppress all reports while this file exists (maintenance mode)", "maintenance_control_file", detection.maintenance_control_file.as_ref(), "\"/run/odin/maintenance\"")?;
+        w.field("Log why each event did or didn't trigger each rule", "explain", &detection.explain)?;
+        w.field("Record the raising rule's effective parameters in report metadata", "include_rule_parameters", &detection.include_rule_parameters)?;
+        w.optional("Maximum users/IPs tracked in memory per detection map", "max_tracked_entries", detection.max_tracked_entries.as_ref(), "100000")?;
+        w.field("Event workers (1 = inline); detection stays serialized, only enrichment and output overlap, and only per-user order is kept", "processing_workers", &detection.processing_workers)?;
+        w.field("Time source: \"live\" (wall clock) or \"replay\" (event timestamps)", "processing_mode", &detection.processing_mode)?;
+        w.field("Enrichment stages run on each event before detection, in order (\"geo\", \"asn\", \"reverse_dns\")", "enrichment_stages", &detection.enrichment_stages)?;
+
+        w.section("detection.ip_switch", None);
+        let ip_switch = &detection.ip_switch;
+        w.field("Severity when the distance between the IPs is unknown", "severity", &ip_switch.severity)?;
+        w.field("Scale severity with the distance between the IPs (needs GeoIP)", "scale_by_distance", &ip_switch.scale_by_distance)?;
+        w.field("Severity of a switch within the same location", "min_severity", &ip_switch.min_severity)?;
+        w.field("Severity of a switch at or beyond max_severity_distance_km", "max_severity", &ip_switch.max_severity)?;
+        w.field("Distance in km at which a switch reaches max_severity", "max_severity_distance_km", &ip_switch.max_severity_distance_km)?;
+        w.field("Only successful logins are checked and update the trusted IP", "successful_logins_only", &ip_switch.successful_logins_only)?;
+        w.field("Remember this many recent confirmed IPs per user; returning to one isn't a switch (0 for only the last)", "known_ips_per_user", &ip_switch.known_ips_per_user)?;
+        w.field("Report returns to a remembered IP as \"Returned to Known IP\"", "report_returns", &ip_switch.report_returns)?;
+        w.field("Severity of a \"Returned to Known IP\" report", "return_severity", &ip_switch.return_severity)?;
+
+        w.section("detection.rate_limit", None);
+        let rate = &detection.rate_limit;
+        w.field("Time window in seconds", "window_seconds", &rate.window_seconds)?;
+        w.field("Maximum attempts per user within the window (0 disables)", "max_user_attempts", &rate.max_user_attempts)?;
+        w.field("Maximum attempts per IP within the window (0 disables)", "max_ip_attempts", &rate.max_ip_attempts)?;
+        w.field("Report when an exceeded limit clears", "alert_on_resolve", &rate.alert_on_resolve)?;
+        if rate.event_weights.is_empty() {
+            w.example("Attempts each event type counts as (default 1)", "event_weights", "{ SSH_FAILED_MAX_AUTH = 3, SSH_FAILED_CONNECTION_CLOSED = 1 }");
+        } else {
+            w.field("Attempts each event type counts as (default 1)", "event_weights", &rate.event_weights)?;
+        }
+        w.optional("Merge a burst's over-limit reports within this many seconds into one", "merge_window_seconds", rate.merge_window_seconds.as_ref(), "60")?;
+        w.optional("Report a merged burst early once it reaches this many attempts", "merge_max_count", rate.merge_max_count.as_ref(), "100")?;
+        w.optional("Also count attempts per IPv4 subnet of this prefix length", "subnet_prefix", rate.subnet_prefix.as_ref(), "24")?;
+        w.field("Prefix length IPv6 sources are counted by when subnet_prefix is set", "ipv6_subnet_prefix", &rate.ipv6_subnet_prefix)?;
+        w.optional("Maximum attempts per subnet within the window (needs subnet_prefix)", "max_subnet_attempts", rate.max_subnet_attempts.as_ref(), "50")?;
+
+        w.section("detection.geo_velocity", None);
+        let velocity = &detection.geo_velocity;
+        w.field("Maximum plausible travel speed in km/h", "max_velocity_kmh", &velocity.max_velocity_kmh)?;
+        w.field("Ignore nearby locations seen within this many seconds", "min_location_interval_seconds", &velocity.min_location_interval_seconds)?;
+        w.optional("Only flag travel when lookups are accurate to this many km", "max_accuracy_radius_km", velocity.max_accuracy_radius_km.as_ref(), "100")?;
+        w.field("Skip logins from the same IP as the previous location (ignores lookup jitter)", "skip_same_ip", &velocity.skip_same_ip)?;
+        w.field("Only successful logins are checked and update the last location", "successful_logins_only", &velocity.successful_logins_only)?;
+
+        w.section("detection.geo_location", Some("MaxMind GeoLite2 City database used for geolocation"));
+        let geo = &detection.geo_location;
+        w.field("Enable geolocation lookups", "enabled", &geo.enabled)?;
+        w.optional("Path to GeoLite2-City.mmdb", "database_path", geo.database_path.as_ref(), "\"/usr/share/GeoIP/GeoLite2-City.mmdb\"")?;
+        w.optional("Truncate IPv4 addresses to this prefix before lookup (privacy)", "anonymize_prefix_v4", geo.anonymize_prefix_v4.as_ref(), "24")?;
+        w.optional("Truncate IPv6 addresses to this prefix before lookup (privacy)", "anonymize_prefix_v6", geo.anonymize_prefix_v6.as_ref(), "48")?;
+        w.field("Add the detected IP's city and country to report metadata", "enrich_reports", &geo.enrich_reports)?;
+        w.field("Missing database at startup: \"warn\", \"fail\" or \"download\"", "on_missing_database", &geo.on_missing_database)?;
+        w.optional("Uncompressed .mmdb fetched by the download policy", "download_url", geo.download_url.as_ref(), "\"https://mirror.example.com/GeoLite2-City.mmdb\"")?;
+        w.optional("Basic auth user for the download", "download_account_id", geo.download_account_id.as_ref(), "\"123456\"")?;
+        w.optional("Basic auth password for the download", "download_license_key", geo.download_license_key.as_ref(), "\"your-license-key\"")?;
+        w.field("Consecutive failed lookups before geo detection is reported degraded (0 never)", "degraded_after_failures", &geo.degraded_after_failures)?;
+        w.field("Also raise a report when geo detection becomes degraded", "alert_when_degraded", &geo.alert_when_degraded)?;
+
+        w.section("detection.hosting_asn", None);
+        let asn = &detection.hosting_asn;
+        w.optional("Path to GeoLite2-ASN.mmdb", "database_path", asn.database_path.as_ref(), "\"/usr/share/GeoIP/GeoLite2-ASN.mmdb\"")?;
+        w.field("Autonomous system numbers treated as hosting", "flagged_asns", &asn.flagged_asns)?;
+        w.field("Organization name substrings treated as hosting", "flagged_organizations", &asn.flagged_organizations)?;
+
+        w.section("detection.reverse_dns", Some("Hostname (PTR) enrichment of detected IPs"));
+        let rdns = &detection.reverse_dns;
+        w.field("Attach the detected IP's hostname to reports", "enabled", &rdns.enabled)?;
+        w.field("Lookup timeout in milliseconds", "timeout_ms", &rdns.timeout_ms)?;
+        w.field("Maximum number of cached lookups", "cache_size", &rdns.cache_size)?;
+        w.field("Seconds an IP without a PTR record is remembered", "negative_ttl_seconds", &rdns.negative_ttl_seconds)?;
+
+        w.section("detection.attacking_ip", None);
+        let attacking = &detection.attacking_ip;
+        w.field("How long failed attempts are remembered, in seconds", "window_seconds", &attacking.window_seconds)?;
+        w.field("Distinct other users an IP must have failed against", "min_failed_users", &attacking.min_failed_users)?;
+        w.field("Failed usernames per IP: \"exact\" (capped) or \"approximate\" (fixed-size estimate)", "user_tracking", &attacking.user_tracking)?;
+        w.field("Usernames remembered per IP in exact mode", "max_users_per_ip", &attacking.max_users_per_ip)?;
+
+        w.section("detection.success_cluster", Some("One IP successfully logging in as many users (credential stuffing with valid credentials)"));
+        let cluster = &detection.success_cluster;
+        w.field("How long successful logins are remembered, in seconds", "window_seconds", &cluster.window_seconds)?;
+        w.field("Distinct users an IP must log in as within the window", "min_users", &cluster.min_users)?;
+        w.field("Severity of a success cluster report", "severity", &cluster.severity)?;
+        w.field("Usernames remembered per IP", "max_users_per_ip", &cluster.max_users_per_ip)?;
+
+        w.section("detection.off_hours", None);
+        let off_hours = &detection.off_hours;
+        w.field("Start of business hours (local hour, inclusive)", "start_hour", &off_hours.start_hour)?;
+        w.field("End of business hours (local hour, exclusive)", "end_hour", &off_hours.end_hour)?;
+        w.field("IANA timezone used when no other is known", "default_timezone", &off_hours.default_timezone)?;
+        if off_hours.user_timezones.is_empty() {
+            w.example("Per-user timezone overrides", "user_timezones", "{ alice = \"Europe/Berlin\" }");
+        } else {
+            w.field("Per-user timezone overrides", "user_timezones", &off_hours.user_timezones)?;
+        }
+
+        w.section("detection.home_region", None);
+        let home = &detection.home_region;
+        w.field("Latitude of the home location", "latitude", &home.latitude)?;
+        w.field("Longitude of the home location", "longitude", &home.longitude)?;
+        w.field("Flag logins farther than this many km from home", "radius_km", &home.radius_km)?;
+
+        w.section("detection.hour_pattern", Some("Per-user login hour histogram (UTC), learned from successful logins"));
+        let hour_pattern = &detection.hour_pattern;
+        w.field("Logins needed before a user's pattern is used", "min_history", &hour_pattern.min_history)?;
+        w.field("Flag hours holding at most this share of the user's logins", "max_hour_share", &hour_pattern.max_hour_share)?;
+        w.field("Adjacent hours counted towards an hour's share", "neighbor_hours", &hour_pattern.neighbor_hours)?;
+
+        w.section("detection.first_seen", Some("First successful login of a never seen user (novelty is remembered in persistence)"));
+        let first_seen = &detection.first_seen;
+        w.field("Severity of a first seen user report", "severity", &first_seen.severity)?;
+        w.optional("Remember new users without reporting them from this time (e.g. bulk onboarding)", "suppress_from", first_seen.suppress_from.as_ref(), "\"2026-01-05T00:00:00Z\"")?;
+        w.optional("End of that window (exclusive)", "suppress_until", first_seen.suppress_until.as_ref(), "\"2026-01-12T00:00:00Z\"")?;
+
+        w.section("detection.lockout", Some("Successful logins while an external system (e.g. pam_faillock) has the account locked"));
+        let lockout = &detection.lockout;
+        w.field("Severity of a login during lockout report", "severity", &lockout.severity)?;
+        w.optional("Treat a lock as lifted after this many seconds (for systems that unlock silently)", "lock_duration_seconds", lockout.lock_duration_seconds.as_ref(), "600")?;
+
+        w.section("detection.escalation", Some("Raise the severity of reports that keep repeating"));
+        let escalation = &detection.escalation;
+        w.field("How far back earlier reports count as repeats, in seconds", "window_seconds", &escalation.window_seconds)?;
+        w.field("Severity added per earlier report for the same rule and IP/user (0 disables)", "escalation_step", &escalation.escalation_step)?;
+        w.field("Highest severity escalation can reach", "escalation_cap", &escalation.escalation_cap)?;
+
+        w.section("detection.risk", Some("Combine a user's reports within a window into one risk score"));
+        let risk = &detection.risk;
+        w.field("Raise a combined report when a user's signals add up", "enabled", &risk.enabled)?;
+        w.field("How close together signals must be to combine, in seconds", "correlation_window_seconds", &risk.correlation_window_seconds)?;
+        w.field("Combined score (0-10) that raises a report", "min_score", &risk.min_score)?;
+
+        w.section("detection.decision", Some("Severity thresholds for inline allow/challenge/deny decisions"));
+        let decision = &detection.decision;
+        w.field("Lowest severity that requires a challenge", "challenge_severity", &decision.challenge_severity)?;
+        w.field("Lowest severity that denies the login", "deny_severity", &decision.deny_severity)?;
+
+        let output = &self.output;
+        w.section("output", None);
+        w.field("Output format: \"json\", \"jsonl\", \"syslog\", \"console\", \"console-compact\" or \"ecs\"", "format", &output.format)?;
+        w.optional("Output file (ignored for console)", "file_path", output.file_path.as_ref(), "\"anomalies.jsonl\"")?;
+        w.field("Flush buffered output at most every N ms (0 = every report)", "flush_interval_ms", &output.flush_interval_ms)?;
+        if output.sinks.is_empty() {
+            w.commented_section(
+                "[output.sinks]",
+                "Extra sinks replacing format/file_path above (repeat the table for each sink)",
+                &[
+                    "format = \"console\"",
+                    "min_severity = 5",
+                    "rules = [\"Impossible Travel\"]",
+                ],
+            );
+        }
+        for sink in &output.sinks {
+            w.section("[output.sinks]", Some("Output sink"));
+            w.field("Output format", "format", &sink.format)?;
+            w.optional("Output file (ignored for console)", "file_path", sink.file_path.as_ref(), "\"anomalies.jsonl\"")?;
+            w.field("Only write reports with at least this severity", "min_severity", &sink.min_severity)?;
+            w.optional("Only write reports from these rules", "rules", sink.rules.as_ref(), "[\"Impossible Travel\"]")?;
+        }
+        for (i, mapping) in output.syslog_levels.iter().enumerate() {
+            let doc = (i == 0).then_some("Severity to syslog level mapping for the syslog format");
+            w.section("[output.syslog_levels]", doc);
+            w.field("", "min_severity", &mapping.min_severity)?;
+            w.field("", "level", &mapping.level)?;
+        }
+
+        let persistence = &self.persistence;
+        w.section("persistence", None);
+        w.field("Persist detection state across restarts", "enabled", &persistence.enabled)?;
+        w.optional("SQLite database path; may contain {YYYY}, {MM} and {DD}", "database_path", persistence.database_path.as_ref(), "\"odin_state.db\"")?;
+        w.field("Switch to a new file when a templated path's date changes", "rollover", &persistence.rollover)?;
+        w.field("Refresh a repeated location instead of storing it again", "dedup_locations", &persistence.dedup_locations)?;
+        w.field("Only store reports at or above this severity (0 = all)", "min_severity_to_store", &persistence.min_severity_to_store)?;
+        w.optional("JSON file of user baselines loaded at startup", "seed_file", persistence.seed_file.as_ref(), "\"baselines.json\"")?;
+        if persistence.report_sinks.is_empty() {
+            w.commented_section(
+                "[persistence.report_sinks]",
+                "Extra database storing only a copy of each report (repeat the table for each sink)",
+                &["database_path = \"/srv/odin/reports.db\"", "min_severity = 5"],
+            );
+        }
+        for sink in &persistence.report_sinks {
+            w.section("[persistence.report_sinks]", Some("Report sink"));
+            w.field("SQLite database receiving a copy of each report", "database_path", &sink.database_path)?;
+            w.field("Only store reports with at least this severity", "min_severity", &sink.min_severity)?;
+        }
+
+        let alerting = &self.alerting;
+        w.section("alerting", None);
+        w.field("Send alerts to the channels below", "enabled", &alerting.enabled)?;
+        w.field("Minimum severity (1-10) that alerts", "min_severity", &alerting.min_severity)?;
+        w.field("Rules that always alert regardless of severity", "always_alert_rules", &alerting.always_alert_rules)?;
+        w.optional("Most alerts per minute; the rest are summarized once a minute", "global_alert_rate_per_min", alerting.global_alert_rate_per_min.as_ref(), "30")?;
+        w.field("Consecutive failures before a channel is skipped (0 disables)", "circuit_failure_threshold", &alerting.circuit_failure_threshold)?;
+        w.field("Seconds a failing channel is skipped", "circuit_reset_seconds", &alerting.circuit_reset_seconds)?;
+        w.field("Extra delivery attempts before an alert counts as undelivered", "dispatch_retries", &alerting.dispatch_retries)?;
+        w.field("Keep idle connections to webhook hosts open between alerts", "reuse_connections", &alerting.reuse_connections)?;
+        w.optional("Rebuild the HTTP client after this many seconds to re-resolve hosts", "client_refresh_seconds", alerting.client_refresh_seconds.as_ref(), "300")?;
+        w.field("Most alerts being dispatched at once", "max_concurrent_dispatches", &alerting.max_concurrent_dispatches)?;
+        w.field(
+            "Message body for plain-text channels ({user}, {rule}, {severity}, {ip}, {description}, {timestamp})",
+            "text_template",
+            &alerting.text_template,
+        )?;
+        w.field("strftime format of {timestamp} in the text template (UTC)", "text_timestamp_format", &alerting.text_timestamp_format)?;
+
+        w.section("alerting.pending", Some("Undelivered alerts kept in the persistence database and redelivered"));
+        let pending = &alerting.pending;
+        w.field("Persist undelivered alerts (requires persistence)", "enabled", &pending.enabled)?;
+        w.field("Seconds between redelivery attempts", "retry_interval_seconds", &pending.retry_interval_seconds)?;
+        w.field("Give up after an alert has been pending this many seconds", "max_age_seconds", &pending.max_age_seconds)?;
+        w.field("Maximum number of pending alerts kept", "max_entries", &pending.max_entries)?;
+
+        match &alerting.slack {
+            Some(slack) => {
+                w.section("alerting.slack", Some("Slack incoming webhook"));
+                w.field("Webhook URL", "webhook_url", &slack.webhook_url)?;
+                w.optional("Channel override", "channel", slack.channel.as_ref(), "\"#security\"")?;
+                w.optional("Bot username", "username", slack.username.as_ref(), "\"Odin\"")?;
+                w.optional("Request timeout in seconds", "timeout_secs", slack.timeout_secs.as_ref(), "30")?;
+            }
+            None => w.commented_section(
+                "alerting.slack",
+                "Slack incoming webhook",
+                &[
+                    "webhook_url = \"https://hooks.slack.com/services/T000/B000/XXXX\"",
+                    "channel = \"#security\"",
+                    "username = \"Odin\"",
+                ],
+            ),
+        }
+
+        match &alerting.discord {
+            Some(discord) => {
+                w.section("alerting.discord", Some("Discord webhook"));
+                w.field("Webhook URL", "webhook_url", &discord.webhook_url)?;
+                w.optional("Bot username", "username", discord.username.as_ref(), "\"Odin\"")?;
+                w.optional("Request timeout in seconds", "timeout_secs", discord.timeout_secs.as_ref(), "30")?;
+            }
+            None => w.commented_section(
+                "alerting.discord",
+                "Discord webhook",
+                &[
+                    "webhook_url = \"https://discord.com/api/webhooks/000/XXXX\"",
+                    "username = \"Odin\"",
+                ],
+            ),
+        }
+
+        if alerting.webhooks.is_empty() {
+            w.commented_section(
+                "[alerting.webhooks]",
+                "Generic JSON webhooks (repeat the table for each endpoint)",
+                &[
+                    "name = \"siem\"",
+                    "url = \"https://siem.example.com/ingest\"",
+                    "method = \"POST\"",
+                    "headers = { Authorization = \"Bearer <token>\" }",
+                ],
+            );
+        }
+        for webhook in &alerting.webhooks {
+            w.section("[alerting.webhooks]", Some("Generic JSON webhook"));
+            w.field("Name used in logs", "name", &webhook.name)?;
+            w.field("Endpoint URL", "url", &webhook.url)?;
+            w.optional("HTTP method (POST or PUT)", "method", webhook.method.as_ref(), "\"POST\"")?;
+            w.optional("Extra request headers", "headers", webhook.headers.as_ref(), "{ Authorization = \"Bearer <token>\" }")?;
+            w.optional("Request timeout in seconds", "timeout_secs", webhook.timeout_secs.as_ref(), "30")?;
+        }
+
+        match &alerting.unix_socket {
+            Some(socket) => {
+                w.section("alerting.unix_socket", Some("Local Unix socket receiving one report per line"));
+                w.field("Socket path", "path", &socket.path)?;
+                w.field("Line format: \"json\", or \"text\" for the text template", "format", &socket.format)?;
+                w.optional("Write timeout in seconds", "timeout_secs", socket.timeout_secs.as_ref(), "30")?;
+            }
+            None => w.commented_section(
+                "alerting.unix_socket",
+                "Local Unix socket receiving one report per line",
+                &["path = \"/run/odin/alerts.sock\"", "format = \"json\""],
+            ),
+        }
+
+        match &alerting.heartbeat {
+            Some(heartbeat) => {
+                w.section("alerting.heartbeat", Some("Periodic liveness ping (sent even when alerting is disabled)"));
+                w.field("Endpoint the heartbeat is POSTed to", "url", &heartbeat.url)?;
+                w.field("Seconds between heartbeats", "interval_seconds", &heartbeat.interval_seconds)?;
+                w.optional("Request timeout in seconds", "timeout_secs", heartbeat.timeout_secs.as_ref(), "30")?;
+            }
+            None => w.commented_section(
+                "alerting.heartbeat",
+                "Periodic liveness ping (sent even when alerting is disabled)",
+                &["url = \"https://hc-ping.example.com/<uuid>\"", "interval_seconds = 300"],
+            ),
+        }
+
+        let api = &self.api;
+        w.section("api", Some("Read-only HTTP API (requires persistence)"));
+        w.field("Serve the API", "enabled", &api.enabled)?;
+        w.field("Address to listen on", "bind_address", &api.bind_address)?;
+
+        let telemetry = &self.telemetry;
+        w.section("telemetry", Some("OpenTelemetry traces of event processing (needs the otel build feature)"));
+        w.field("Export a trace per processed event", "enabled", &telemetry.enabled)?;
+        w.field("OTLP/HTTP traces endpoint of the collector", "otlp_endpoint", &telemetry.otlp_endpoint)?;
+        w.field("service.name reported with the traces", "service_name", &telemetry.service_name)?;
+        w.field("Share of events traced (0.0-1.0)", "sample_ratio", &telemetry.sample_ratio)?;
+        w.field("Record each event's user and source IP on its trace (personal data)", "record_identities", &telemetry.record_identities)?;
+
+        Ok(w.out)
+    }
+}
+
+/// Builds commented TOML for [`Config::to_documented_toml`]
+#[derive(Default)]
+struct TemplateWriter {
+    out: String,
+}
+
+impl TemplateWriter {
+    fn comment(&mut self, text: &str) {
+        self.out.push_str("# ");
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn section(&mut self, name: &str, doc: Option<&str>) {
+        self.out.push('\n');
+        if let Some(doc) = doc {
+            self.comment(doc);
+        }
+        self.out.push_str(&format!("[{}]\n", name));
+    }
+
+    /// An optional section that isn't configured, shown commented out
+    fn commented_section(&mut self, name: &str, doc: &str, lines: &[&str]) {
+        self.out.push('\n');
+        self.comment(doc);
+        self.comment(&format!("[{}]", name));
+        for line in lines {
+            self.comment(line);
+        }
+    }
+
+    fn field<T: Serialize + ?Sized>(&mut self, doc: &str, key: &str, value: &T) -> Result<(), toml::ser::Error> {
+        if !doc.is_empty() {
+            self.comment(doc);
+        }
+        let rendered = toml::Value::try_from(value)?;
+        self.out.push_str(&format!("{} = {}\n", key, rendered));
+        Ok(())
+    }
+
+    /// A field that may be unset; unset fields are shown commented out
+    /// with an example value
+    fn optional<T: Serialize>(
+        &mut self,
+        doc: &str,
+        key: &str,
+        value: Option<&T>,
+        example: &str,
+    ) -> Result<(), toml::ser::Error> {
+        match value {
+            Some(value) => self.field(doc, key, value),
+            None => {
+                self.example(doc, key, example);
+                Ok(())
+            }
+        }
+    }
+
+    fn example(&mut self, doc: &str, key: &str, example: &str) {
+        self.comment(doc);
+        self.comment(&format!("{} = {}", key, example));
+    }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::classify::SSH_FAILED_MAX_AUTH;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    fn create_event(user: &str, event_type: &str) -> LogEvent {
+        LogEvent {
+            timestamp: 1700000000,
+            user: user.to_string(),
+            ip_address: IpAddr::from_str("1.1.1.1").unwrap(),
+            event_type: event_type.to_string(),
+            host: None,
+        }
+    }
+
+    #[test]
+    fn test_process_event_types_whitelist() {
+        let mut config = Config::default();
+        config.input.process_event_types =
+            Some(vec!["SSH_LOGIN".to_string(), "SSH_FAILED".to_string()]);
+
+        let events = [
+            create_event("alice", "SSH_LOGIN"),
+            create_event("bob", "UNKNOWN"),
+            create_event("carol", "SSH_FAILED"),
+            create_event("dave", "SUDO"),
+            create_event("erin", SSH_FAILED_MAX_AUTH),
+        ];
+
+        let processed: Vec<&str> = events
+            .iter()
+            .filter(|e| config.input.should_process(e))
+            .map(|e| e.user.as_str())
+            .collect();
+
+        assert_eq!(processed, vec!["alice", "carol", "erin"]);
+    }
+
+    #[test]
+    fn test_unknown_source_type_rejected() {
+        let mut config = Config::default();
+        config.input.sources.push(SourceSpec {
+            source_type: "journald".to_string(),
+            file_path: None,
+            syslog_address: None,
+            format: LineFormat::Text,
+            timestamp_formats: None,
+            fields: FieldsConfig::default(),
+        });
+        assert!(config.validate().unwrap_err().contains("Unknown input source type \"journald\""));
+
+        config.input.sources[0].source_type = "stdin".to_string();
+        assert!(config.validate().is_ok());
+
+        config.input.sources.clear();
+        config.input.source_type = "udp".to_string();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_negative_max_event_age_rejected() {
+        let mut config = Config::default();
+        config.input.max_event_age_seconds = Some(-1);
+        assert!(config.validate().unwrap_err().contains("must not be negative"));
+
+        config.input.max_event_age_seconds = Some(0);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_duplicate_event_weights_rejected() {
+        let mut config = Config::default();
+        config.detection.rate_limit.event_weights =
+            HashMap::from([(SSH_FAILED_MAX_AUTH.to_string(), 3), ("ssh_failed_max_auth ".to_string(), 1)]);
+        let err = config.validate().unwrap_err();
+        assert!(err.contains("name the same event type"), "{}", err);
+
+        config.detection.rate_limit.event_weights.remove("ssh_failed_max_auth ");
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_documented_toml_round_trips() {
+        let defaults = Config::default();
+        let rendered = defaults.to_documented_toml().unwrap();
+
+        let parsed: Config = toml::from_str(&rendered).unwrap();
+        assert_eq!(
+            toml::Value::try_from(&parsed).unwrap(),
+            toml::Value::try_from(&defaults).unwrap()
+        );
+
+        for section in ["# [alerting.slack]", "# [alerting.discord]", "# [[alerting.webhooks]]", "[detection.geo_location]"] {
+            assert!(rendered.contains(section), "missing {}", section);
+        }
+    }
+
+    /// Uncomment every example value and section of a rendered template
+    fn uncomment_examples(rendered: &str) -> String {
+        let example = regex::Regex::new(r"^# ([a-z0-9_]+ = .*|\[.*\])$").unwrap();
+        rendered
+            .lines()
+            .map(|line| example.captures(line).map_or(line, |caps| caps.get(1).unwrap().as_str()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Check that every field of a config (as JSON, where unset options
+    /// are null) was written to its rendered template
+    fn assert_fields_rendered(path: &str, expected: &serde_json::Value, rendered: &toml::Value) {
+        match expected {
+            serde_json::Value::Null => panic!("{} is still unset; give it an example in to_documented_toml", path),
+            serde_json::Value::Object(fields) => {
+                for (key, value) in fields {
+                    let field = format!("{}.{}", path, key);
+                    let Some(rendered) = rendered.get(key) else {
+                        panic!("{} is missing from to_documented_toml", field);
+                    };
+                    assert_fields_rendered(&field, value, rendered);
+                }
+            }
+            serde_json::Value::Array(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    assert_fields_rendered(&format!("{}[{}]", path, i), item, &rendered[i]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn test_fully_populated_documented_toml_round_trips() {
+        // Fill in every optional field and section from its example; some
+        // examples (e.g. a channel's timeout) only appear once the
+        // section around them is set
+        let mut config = Config::default();
+        for _ in 0..3 {
+            config = toml::from_str(&uncomment_examples(&config.to_documented_toml().unwrap())).unwrap();
+        }
+        let rendered = config.to_documented_toml().unwrap();
+        let parsed: toml::Value = toml::from_str(&rendered).unwrap();
+        assert_fields_rendered("config", &serde_json::to_value(&config).unwrap(), &parsed);
+
+        assert_eq!(parsed, toml::Value::try_from(&config).unwrap());
+    }
+
+    #[test]
+    fn test_documented_toml_renders_configured_channels() {
+        let mut config = Config::default();
+        config.alerting.slack = Some(SlackConfig {
+            webhook_url: "https://hooks.slack.com/services/T/B/X".to_string(),
+            channel: None,
+            username: None,
+            timeout_secs: Some(10),
+        });
+        config.alerting.webhooks.push(WebhookConfig {
+            name: "siem".to_string(),
+            url: "https://siem.example.com".to_string(),
+            method: None,
+            headers: Some(HashMap::from([("X-Token".to_string(), "abc".to_string())])),
+            timeout_secs: None,
+        });
+        config.detection.off_hours.user_timezones.insert("alice".to_string(), "Asia/Tokyo".to_string());
+        config.input.sources.push(SourceSpec {
+            source_type: "file".to_string(),
+            file_path: Some(PathBuf::from("/var/log/app/auth.jsonl")),
+            syslog_address: None,
+            format: LineFormat::Json,
+            timestamp_formats: None,
+            fields: FieldsConfig {
+                user: Some("username".to_string()),
+                ..FieldsConfig::default()
+            },
+        });
+
+        let parsed: Config = toml::from_str(&config.to_documented_toml().unwrap()).unwrap();
+        assert_eq!(parsed.alerting.slack.unwrap().timeout_secs, Some(10));
+        assert_eq!(parsed.alerting.webhooks[0].headers.as_ref().unwrap()["X-Token"], "abc");
+        assert_eq!(parsed.detection.off_hours.user_timezones["alice"], "Asia/Tokyo");
+        assert_eq!(parsed.input.source_specs()[0].format, LineFormat::Json);
+        assert_eq!(parsed.input.source_specs()[0].fields.user.as_deref(), Some("username"));
+    }
+
+    #[test]
+    fn test_from_files_merges_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("base.toml");
+        Config::default().to_file(&base).unwrap();
+        let host = dir.path().join("host.toml");
+        std::fs::write(
+            &host,
+            r#"
+[input]
+file_path = "/var/log/secure"
+log_format = "nginx_access"
+
+[detection]
+enable_geo_velocity = false
+rule_order = ["rate_limit"]
+
+[detection.rate_limit]
+max_user_attempts = 3
+
+[alerting]
+enabled = true
+min_severity = 5
+
+[[alerting.webhooks]]
+name = "host"
+url = "https://siem.example.com/host"
+"#,
+        )
+        .unwrap();
+
+        let config = Config::from_files(&[base.clone(), host]).unwrap();
+        let defaults = Config::default();
+        // Overridden scalars, including inside nested tables
+        assert_eq!(config.input.file_path, Some(PathBuf::from("/var/log/secure")));
+        assert_eq!(config.input.source_specs()[0].format, LineFormat::NginxAccess);
+        assert!(!config.detection.enable_geo_velocity);
+        assert_eq!(config.detection.rate_limit.max_user_attempts, 3);
+        assert!(config.alerting.enabled);
+        assert_eq!(config.alerting.min_severity, 5);
+        // Untouched siblings keep the base values
+        assert_eq!(config.detection.rate_limit.window_seconds, defaults.detection.rate_limit.window_seconds);
+        assert_eq!(config.detection.enable_ip_switch, defaults.detection.enable_ip_switch);
+        assert_eq!(config.output.format, defaults.output.format);
+        // Arrays are replaced
+        assert_eq!(config.detection.rule_order, vec![DetectionRule::RateLimit]);
+        assert_eq!(config.alerting.webhooks.len(), 1);
+        assert_eq!(config.alerting.webhooks[0].name, "host");
+
+        // A single file reads as from_file does
+        let single = Config::from_files(&[base]).unwrap();
+        assert_eq!(toml::Value::try_from(&single).unwrap(), toml::Value::try_from(&defaults).unwrap());
+    }
+
+    #[test]
+    fn test_from_files_replaces_arrays_of_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("base.toml");
+        let mut config = Config::default();
+        config.alerting.webhooks = vec![
+            WebhookConfig {
+                name: "a".to_string(),
+                url: "https://a.example.com".to_string(),
+                method: None,
+                headers: None,
+                timeout_secs: None,
+            };
+            2
+        ];
+        config.to_file(&base).unwrap();
+        let host = dir.path().join("host.toml");
+        std::fs::write(&host, "[alerting]\nwebhooks = []\n").unwrap();
+
+        assert_eq!(Config::from_files(std::slice::from_ref(&base)).unwrap().alerting.webhooks.len(), 2);
+        assert!(Config::from_files(&[base, host]).unwrap().alerting.webhooks.is_empty());
+    }
+
+    #[test]
+    fn test_from_files_errors_name_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let bad = dir.path().join("bad.toml");
+        std::fs::write(&bad, "[detection\n").unwrap();
+        let error = Config::from_files(&[bad]).unwrap_err().to_string();
+        assert!(error.contains("bad.toml"), "{}", error);
+        assert!(Config::from_files(&[]).is_err());
+    }
+
+    #[test]
+    fn test_non_positive_rate_limit_window_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        for window in [0, -300] {
+            let mut config = Config::default();
+            config.detection.rate_limit.window_seconds = window;
+            config.to_file(&path).unwrap();
+
+            let error = Config::from_file(&path).unwrap_err().to_string();
+            assert!(error.contains("must be positive"), "{}", error);
+            assert!(Config::from_files(std::slice::from_ref(&path)).is_err());
+            assert!(crate::detection::DetectionEngine::new(&config.detection).is_err());
+        }
+    }
+
+    #[test]
+    fn test_report_sink_on_primary_database_rejected() {
+        let mut config = Config::default();
+        config.persistence.report_sinks.push(ReportSinkSpec {
+            database_path: PathBuf::from("odin_state.db"),
+            min_severity: 0,
+        });
+        assert!(config.validate().unwrap_err().contains("primary database"));
+
+        config.persistence.report_sinks[0].database_path = PathBuf::from("reports.db");
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_oversized_subnet_prefix_rejected() {
+        let mut config = Config::default();
+        config.detection.rate_limit.subnet_prefix = Some(33);
+        assert!(config.validate().unwrap_err().contains("/33"));
+
+        config.detection.rate_limit.subnet_prefix = Some(32);
+        config.detection.rate_limit.ipv6_subnet_prefix = 129;
+        assert!(config.validate().unwrap_err().contains("/129"));
+
+        config.detection.rate_limit.ipv6_subnet_prefix = 128;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_telemetry_sample_ratio_bounded() {
+        let mut config = Config::default();
+        config.telemetry.sample_ratio = 1.5;
+        assert!(config.validate().unwrap_err().contains("sample ratio"));
+
+        config.telemetry.sample_ratio = 0.1;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_redacted_toml_hides_secrets() {
+        let mut config = Config::default();
+        config.alerting.slack = Some(SlackConfig {
+            webhook_url: "https://hooks.slack.com/services/T/B/SECRET1".to_string(),
+            channel: Some("#security".to_string()),
+            username: None,
+            timeout_secs: None,
+        });
+        config.alerting.webhooks = vec![WebhookConfig {
+            name: "siem".to_string(),
+            url: "https://siem.example.com/ingest?token=SECRET2".to_string(),
+            method: None,
+            headers: Some(HashMap::from([("Authorization".to_string(), "Bearer SECRET3".to_string())])),
+            timeout_secs: None,
+        }];
+        config.detection.geo_location.download_license_key = Some("SECRET4".to_string());
+        config.detection.rate_limit.max_user_attempts = 7;
+
+        let dump = config.to_redacted_toml().unwrap();
+        assert!(!dump.contains("SECRET"), "{}", dump);
+        assert!(dump.contains(REDACTED));
+
+        // Everything else is dumped as configured
+        let parsed: Config = toml::from_str(&dump).unwrap();
+        assert_eq!(parsed.detection.rate_limit.max_user_attempts, 7);
+        assert_eq!(parsed.alerting.slack.unwrap().channel.as_deref(), Some("#security"));
+        assert_eq!(parsed.alerting.webhooks[0].name, "siem");
+        assert_eq!(parsed.alerting.webhooks[0].headers.as_ref().unwrap()["Authorization"], REDACTED);
+        assert_eq!(config.alerting.webhooks[0].url, "https://siem.example.com/ingest?token=SECRET2");
+    }
+
+    #[test]
+    fn test_unknown_user_policy() {
+        let mut config = Config::default();
+        let event = create_event("unknown", "SSH_FAILED");
+        assert!(config.input.should_process(&event));
+        assert!(!config.input.unknown_user.user_rules_apply(&event));
+        assert!(config.input.unknown_user.user_rules_apply(&create_event("alice", "SSH_FAILED")));
+
+        config.input.unknown_user = UnknownUserPolicy::Drop;
+        assert!(!config.input.should_process(&event));
+
+        config.input.unknown_user = UnknownUserPolicy::Process;
+        assert!(config.input.unknown_user.user_rules_apply(&event));
+    }
+
+    #[test]
+    fn test_rules_toggled_by_name() {
+        let mut detection: DetectionConfig = toml::from_str::<Config>(
+            &Config::default().to_documented_toml().unwrap().replace(
+                "[detection]\n",
+                "[detection]\nenabled_rules = [\"hour_pattern\"]\ndisabled_rules = [\"ip_switch\"]\n",
+            ),
+        )
+        .unwrap()
+        .detection;
+        assert!(!detection.rule_enabled(DetectionRule::IpSwitch));
+        assert!(detection.rule_enabled(DetectionRule::HourPattern));
+        assert_eq!(detection.rule_enabled(DetectionRule::RateLimit), detection.enable_rate_limiting);
+
+        // Disabling wins over enabling
+        detection.enabled_rules.push(DetectionRule::IpSwitch);
+        assert!(!detection.rule_enabled(DetectionRule::IpSwitch));
+    }
+
+    #[test]
+    fn test_flagless_rules_enabled_by_name_only() {
+        let mut detection = Config::default().detection;
+        let flagless = [DetectionRule::SuccessCluster, DetectionRule::FirstSeen, DetectionRule::Lockout];
+        for rule in flagless {
+            assert!(!detection.rule_enabled(rule));
+        }
+
+        detection.enabled_rules.extend(flagless);
+        for rule in flagless {
+            assert!(detection.rule_enabled(rule));
+        }
+    }
+
+    #[test]
+    fn test_unknown_rule_name_rejected() {
+        let rendered = Config::default()
+            .to_documented_toml()
+            .unwrap()
+            .replace("[detection]\n", "[detection]\ndisabled_rules = [\"ip_swtich\"]\n");
+        let error = toml::from_str::<Config>(&rendered).unwrap_err().to_string();
+        assert!(error.contains("ip_swtich"), "{}", error);
+    }
+
+    #[test]
+    fn test_no_whitelist_processes_everything() {
+        let config = Config::default();
+        assert!(config.input.should_process(&create_event("alice", "UNKNOWN")));
+    }
+}
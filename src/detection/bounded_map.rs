@@ -0,0 +1,220 @@
+//! Size-bounded map with least-recently-seen eviction
+//!
+//! Used by the detection components to keep per-user and per-IP state
+//! from growing without bound (e.g. during a password spray hitting
+//! millions of usernames).
+
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+/// Default cap on tracked entries per map
+pub const DEFAULT_MAX_TRACKED_ENTRIES: usize = 100_000;
+
+/// A HashMap that evicts the least-recently-seen entry when full
+///
+/// Inserting or updating an entry marks it as seen; plain lookups do not.
+#[derive(Debug, Clone)]
+pub struct BoundedMap<K, V> {
+    /// Key -> (value, last-seen tick)
+    entries: HashMap<K, (V, u64)>,
+    /// Last-seen tick -> key, oldest first
+    order: BTreeMap<u64, K>,
+    tick: u64,
+    capacity: Option<usize>,
+    /// Name used in eviction log messages
+    name: &'static str,
+}
+
+impl<K: Hash + Eq + Clone, V> BoundedMap<K, V> {
+    /// Create a map holding at most `capacity` entries (unbounded if None)
+    ///
+    /// A capacity of zero is treated as one so the current entry is kept.
+    pub fn new(name: &'static str, capacity: Option<usize>) -> Self {
+        BoundedMap {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            capacity: capacity.map(|c| c.max(1)),
+            name,
+        }
+    }
+
+    /// Change the capacity, evicting entries if the map is now over it
+    pub fn set_capacity(&mut self, capacity: Option<usize>) {
+        self.capacity = capacity.map(|c| c.max(1));
+        self.evict_to_capacity();
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.entries.get(key).map(|(value, _)| value)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.entries.contains_key(key)
+    }
+
+    /// Insert or replace a value, marking the key as most recently seen
+    pub fn insert(&mut self, key: K, value: V) {
+        let tick = self.next_tick();
+        if let Some((_, old_tick)) = self.entries.insert(key.clone(), (value, tick)) {
+            self.order.remove(&old_tick);
+        }
+        self.order.insert(tick, key);
+        self.evict_to_capacity();
+    }
+
+    /// Get a mutable reference to a value, inserting it if missing,
+    /// and mark the key as most recently seen
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, default: F) -> &mut V {
+        let tick = self.next_tick();
+        match self.entries.get_mut(&key) {
+            Some((_, old_tick)) => {
+                self.order.remove(old_tick);
+                *old_tick = tick;
+            }
+            None => {
+                self.entries.insert(key.clone(), (default(), tick));
+            }
+        }
+        self.order.insert(tick, key.clone());
+        self.evict_to_capacity();
+        // The key was just marked most recent, so it is never the one evicted
+        &mut self.entries.get_mut(&key).expect("entry just inserted").0
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (value, tick) = self.entries.remove(key)?;
+        self.order.remove(&tick);
+        Some(value)
+    }
+
+    /// Keep only the entries for which the predicate returns true
+    pub fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut keep: F) {
+        let order = &mut self.order;
+        self.entries.retain(|key, (value, tick)| {
+            let kept = keep(key, value);
+            if !kept {
+                order.remove(tick);
+            }
+            kept
+        });
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries.iter().map(|(key, (value, _))| (key, value))
+    }
+
+    /// Remove and return every entry
+    pub fn drain(&mut self) -> impl Iterator<Item = (K, V)> + '_ {
+        self.order.clear();
+        self.entries.drain().map(|(key, (value, _))| (key, value))
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn evict_to_capacity(&mut self) {
+        let Some(capacity) = self.capacity else {
+            return;
+        };
+        while self.entries.len() > capacity {
+            match self.order.pop_first() {
+                Some((_, key)) => {
+                    self.entries.remove(&key);
+                    log::debug!(
+                        "Evicted least recently seen entry from {} (cap {})",
+                        self.name,
+                        capacity
+                    );
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_size_stays_bounded() {
+        let mut map = BoundedMap::new("test", Some(3));
+        for i in 0..10 {
+            map.insert(i, i * 10);
+        }
+
+        assert_eq!(map.len(), 3);
+        // The most recent entries survive
+        assert_eq!(map.get(&7), Some(&70));
+        assert_eq!(map.get(&8), Some(&80));
+        assert_eq!(map.get(&9), Some(&90));
+        assert!(map.get(&0).is_none());
+    }
+
+    #[test]
+    fn test_update_refreshes_recency() {
+        let mut map = BoundedMap::new("test", Some(2));
+        map.insert("a", 1);
+        map.insert("b", 2);
+        *map.get_or_insert_with("a", || 0) += 1;
+        map.insert("c", 3);
+
+        assert_eq!(map.get("a"), Some(&2));
+        assert!(map.get("b").is_none());
+        assert_eq!(map.get("c"), Some(&3));
+    }
+
+    #[test]
+    fn test_retain_and_remove_keep_order_consistent() {
+        let mut map = BoundedMap::new("test", Some(3));
+        map.insert(1, "one");
+        map.insert(2, "two");
+        map.insert(3, "three");
+        map.retain(|k, _| *k != 1);
+        map.remove(&2);
+        map.insert(4, "four");
+        map.insert(5, "five");
+
+        assert_eq!(map.len(), 3);
+        assert!(map.contains_key(&3));
+        assert!(map.contains_key(&5));
+    }
+
+    #[test]
+    fn test_unbounded() {
+        let mut map = BoundedMap::new("test", None);
+        for i in 0..1000 {
+            map.insert(i, ());
+        }
+        assert_eq!(map.len(), 1000);
+    }
+}
@@ -2,27 +2,56 @@
 //!
 //! Tracks user IP addresses and detects when a user logs in from
 //! a different IP than previously seen.
-
-use std::collections::HashMap;
+//!
+//! With `known_ips_per_user` set, each user's most recent confirmed IPs
+//! are also remembered, so a user moving back and forth between two
+//! addresses (home and VPN, say) is reported once rather than on every
+//! leg. An IP that raised a switch is only remembered once a later
+//! successful login from it confirms it, so an attacker alternating with
+//! the real user keeps being reported. A return can optionally be
+//! reported at a low, informational severity.
+
+use std::collections::VecDeque;
 use std::net::IpAddr;
 use std::sync::Arc;
+use crate::config::IpSwitchConfig;
 use crate::models::{LogEvent, AnomalyReport};
-use crate::persistence::StateStore;
+use crate::persistence::{StateStore, StoreHealth};
+use super::bounded_map::{BoundedMap, DEFAULT_MAX_TRACKED_ENTRIES};
+use super::explain_outcome;
+use super::rule_geo_velocity::{haversine_distance, GeoLocation};
+
+/// Rule name of the report raised when a user returns to a known IP
+pub const RETURNED_RULE_NAME: &str = "Returned to Known IP";
 
 /// Context for tracking user identities and detecting IP switches
 pub struct IdentityContext {
     /// In-memory cache of user -> last known IP
-    last_known_ip: HashMap<String, IpAddr>,
+    last_known_ip: BoundedMap<String, IpAddr>,
+    /// User -> their most recent IPs, oldest first (`known_ips_per_user`)
+    known_ips: BoundedMap<String, VecDeque<IpAddr>>,
     /// Optional persistence backend
     store: Option<Arc<dyn StateStore>>,
+    /// Counts store errors fallen back from
+    store_health: Arc<StoreHealth>,
+    /// Severity, and how it scales with the distance between the IPs
+    config: IpSwitchConfig,
+    /// Record why each check did or didn't trigger
+    explain: bool,
+    last_explanation: Option<String>,
 }
 
 impl IdentityContext {
     /// Create a new identity context (in-memory only)
     pub fn new() -> Self {
         IdentityContext {
-            last_known_ip: HashMap::new(),
+            last_known_ip: BoundedMap::new("last_known_ip", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            known_ips: BoundedMap::new("known_ips", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
             store: None,
+            store_health: Arc::new(StoreHealth::new()),
+            config: IpSwitchConfig::default(),
+            explain: false,
+            last_explanation: None,
         }
     }
 
@@ -34,16 +63,88 @@ impl IdentityContext {
     /// - Use the in-memory cache for fast lookups
     pub fn with_persistence(store: Arc<dyn StateStore>) -> Self {
         IdentityContext {
-            last_known_ip: HashMap::new(),
+            last_known_ip: BoundedMap::new("last_known_ip", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            known_ips: BoundedMap::new("known_ips", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
             store: Some(store),
+            store_health: Arc::new(StoreHealth::new()),
+            config: IpSwitchConfig::default(),
+            explain: false,
+            last_explanation: None,
         }
     }
 
+    /// Limit the number of users tracked in memory (None for unbounded)
+    ///
+    /// When full, the least recently seen user is evicted.
+    pub fn with_max_tracked(mut self, max_entries: Option<usize>) -> Self {
+        self.last_known_ip.set_capacity(max_entries);
+        self.known_ips.set_capacity(max_entries);
+        self
+    }
+
+    /// Count store errors, which fall back to in-memory state, in a
+    /// shared counter
+    pub fn with_store_health(mut self, health: Arc<StoreHealth>) -> Self {
+        self.store_health = health;
+        self
+    }
+
+    /// Set the report severity and optional distance-based scaling
+    pub fn with_config(mut self, config: &IpSwitchConfig) -> Self {
+        self.config = config.clone();
+        self
+    }
+
+    /// Record an explanation of each check, readable via `last_explanation()`
+    pub fn with_explain(mut self, enabled: bool) -> Self {
+        self.explain = enabled;
+        self
+    }
+
+    /// Explanation of the most recent check (explain mode only)
+    pub fn last_explanation(&self) -> Option<&str> {
+        self.last_explanation.as_deref()
+    }
+
+    /// Number of users currently tracked in memory
+    pub fn tracked_users(&self) -> usize {
+        self.last_known_ip.len()
+    }
+
     /// Check if the user has switched IP addresses
     ///
     /// Returns an anomaly report if the user is logging in from a different
     /// IP than their last known IP address.
     pub fn check_for_ip_switch(&mut self, event: &LogEvent) -> Option<AnomalyReport> {
+        self.check_for_ip_switch_with_geo(event, |_| None)
+    }
+
+    /// Check for an IP switch, locating both IPs with `locate`
+    ///
+    /// With `scale_by_distance` enabled and both IPs located, severity
+    /// scales with the distance between them; otherwise the fixed
+    /// severity is used.
+    pub fn check_for_ip_switch_with_geo<F>(&mut self, event: &LogEvent, locate: F) -> Option<AnomalyReport>
+    where
+        F: Fn(&IpAddr) -> Option<GeoLocation>,
+    {
+        if self.config.successful_logins_only && !event.is_successful_login() {
+            if self.explain {
+                self.last_explanation = Some(format!(
+                    "Sudden IP Switch: event type {} is not a successful login -> skipped",
+                    event.event_type
+                ));
+            }
+            return None;
+        }
+        // Lock/unlock and clock lines name no address; they aren't a location
+        if event.ip_address.is_unspecified() {
+            if self.explain {
+                self.last_explanation = Some("Sudden IP Switch: event has no source address -> skipped".to_string());
+            }
+            return None;
+        }
+
         // First check in-memory cache
         let cached_ip = self.last_known_ip.get(&event.user).copied();
 
@@ -60,7 +161,7 @@ impl IdentityContext {
                         }
                         Ok(None) => None,
                         Err(e) => {
-                            log::warn!("Failed to get user IP from persistence: {}", e);
+                            self.store_health.record("get user IP", &e);
                             None
                         }
                     }
@@ -70,43 +171,175 @@ impl IdentityContext {
             }
         };
 
+        // A return to one of the user's remembered IPs isn't a switch
+        if trusted_ip.is_some_and(|ip| ip != event.ip_address) {
+            self.load_known_ips(&event.user);
+        }
+        let returned = match trusted_ip {
+            Some(ip) if ip != event.ip_address => self
+                .known_ips
+                .get(&event.user)
+                .is_some_and(|known| known.contains(&event.ip_address)),
+            _ => false,
+        };
+
+        let distance_km = match trusted_ip {
+            Some(ip) if ip != event.ip_address && !returned && self.config.scale_by_distance => locate(&ip)
+                .zip(locate(&event.ip_address))
+                .map(|(from, to)| haversine_distance(from, to)),
+            _ => None,
+        };
+
         // Generate report if IP changed
         let report = match trusted_ip {
             None => None,
             Some(ip) if ip == event.ip_address => None,
-            Some(trusted_ip) => Some(AnomalyReport {
-                severity: 8,
-                rule_name: "Sudden IP Switch".to_string(),
-                user: event.user.clone(),
-                detected_ip: event.ip_address.to_string(),
-                trusted_ip: trusted_ip.to_string(),
-                timestamp: event.timestamp,
-                description: format!(
-                    "User '{}' switched from trusted IP {} to new IP {}.",
-                    event.user, trusted_ip, event.ip_address
+            Some(_) if returned && !self.config.report_returns => None,
+            Some(trusted_ip) if returned => Some(
+                AnomalyReport::new(
+                    self.config.return_severity,
+                    RETURNED_RULE_NAME,
+                    event.user.clone(),
+                    event.timestamp,
+                    format!(
+                        "User '{}' returned from {} to previously seen IP {}.",
+                        event.user, trusted_ip, event.ip_address
+                    ),
+                )
+                .with_detected_ip(event.ip_address.to_string())
+                .with_trusted_ip(trusted_ip.to_string()),
+            ),
+            Some(trusted_ip) => {
+                let report = AnomalyReport::new(
+                    distance_km
+                        .map(|km| self.distance_severity(km))
+                        .unwrap_or(self.config.severity),
+                    "Sudden IP Switch",
+                    event.user.clone(),
+                    event.timestamp,
+                    match distance_km {
+                        Some(km) => format!(
+                            "User '{}' switched from trusted IP {} to new IP {} ({:.0} km apart).",
+                            event.user, trusted_ip, event.ip_address, km
+                        ),
+                        None => format!(
+                            "User '{}' switched from trusted IP {} to new IP {}.",
+                            event.user, trusted_ip, event.ip_address
+                        ),
+                    },
+                )
+                .with_detected_ip(event.ip_address.to_string())
+                .with_trusted_ip(trusted_ip.to_string());
+                Some(match distance_km {
+                    Some(km) => report.with_metadata("switch_distance_km", format!("{:.0}", km)),
+                    None => report,
+                })
+            }
+        };
+
+        if self.explain {
+            self.last_explanation = Some(match trusted_ip {
+                None => format!(
+                    "Sudden IP Switch: no known IP for '{}' -> not triggered",
+                    event.user
+                ),
+                Some(ip) if returned => format!(
+                    "Sudden IP Switch: current IP {} is one of '{}''s known IPs (last {}) -> not triggered",
+                    event.ip_address, event.user, ip
+                ),
+                Some(ip) => format!(
+                    "Sudden IP Switch: trusted IP {} vs current IP {} -> {}",
+                    ip,
+                    event.ip_address,
+                    explain_outcome(report.is_some())
                 ),
-            }),
+            });
+        }
+
+        // An IP that just raised a switch isn't known-good until a later
+        // successful login from it confirms it
+        let confirmed = match trusted_ip {
+            Some(ip) if ip != event.ip_address => returned,
+            _ => event.is_successful_login(),
         };
+        if confirmed {
+            self.remember_ip(&event.user, event.ip_address, event.timestamp);
+        }
 
         // Update both cache and persistence
         self.last_known_ip.insert(event.user.clone(), event.ip_address);
         if let Some(ref store) = self.store {
             if let Err(e) = store.set_user_last_ip(&event.user, &event.ip_address, event.timestamp) {
-                log::warn!("Failed to persist user IP: {}", e);
+                self.store_health.record("store user IP", &e);
             }
         }
 
         report
     }
 
+    /// Populate the user's remembered IPs from persistence if not cached
+    fn load_known_ips(&mut self, user: &str) {
+        let limit = self.config.known_ips_per_user;
+        if limit == 0 || self.known_ips.contains_key(user) {
+            return;
+        }
+        let Some(ref store) = self.store else {
+            return;
+        };
+        match store.get_user_known_ips(user) {
+            Ok(ips) => {
+                let skip = ips.len().saturating_sub(limit);
+                self.known_ips.insert(user.to_string(), ips.into_iter().skip(skip).collect());
+            }
+            Err(e) => self.store_health.record("get user known IPs", &e),
+        }
+    }
+
+    /// Add an IP to the user's remembered IPs, dropping the oldest past
+    /// `known_ips_per_user`
+    fn remember_ip(&mut self, user: &str, ip: IpAddr, timestamp: i64) {
+        let limit = self.config.known_ips_per_user;
+        if limit == 0 {
+            return;
+        }
+        self.load_known_ips(user);
+        let known = self.known_ips.get_or_insert_with(user.to_string(), VecDeque::new);
+        known.retain(|known_ip| *known_ip != ip);
+        known.push_back(ip);
+        while known.len() > limit {
+            known.pop_front();
+        }
+        if let Some(ref store) = self.store {
+            if let Err(e) = store.add_user_known_ip(user, &ip, timestamp, limit) {
+                self.store_health.record("store user known IP", &e);
+            }
+        }
+    }
+
+    /// Severity for a switch between IPs `distance_km` apart, rising
+    /// linearly from `min_severity` to `max_severity` at
+    /// `max_severity_distance_km`
+    fn distance_severity(&self, distance_km: f64) -> u8 {
+        let min = self.config.min_severity.min(self.config.max_severity);
+        let max = self.config.max_severity;
+        let scale = if self.config.max_severity_distance_km > 0.0 {
+            (distance_km / self.config.max_severity_distance_km).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        min + (f64::from(max - min) * scale).round() as u8
+    }
+
     /// Clear tracking data for a specific user
     pub fn clear_user(&mut self, user: &str) {
         self.last_known_ip.remove(user);
+        self.known_ips.remove(user);
     }
 
     /// Clear all tracking data (in-memory only)
     pub fn clear_all(&mut self) {
         self.last_known_ip.clear();
+        self.known_ips.clear();
     }
 
     /// Get the last known IP for a user
@@ -132,6 +365,7 @@ mod tests {
             user: user.to_string(),
             ip_address: IpAddr::from_str(ip).unwrap(),
             event_type: "SSH_LOGIN".to_string(),
+            host: None,
         }
     }
 
@@ -173,6 +407,133 @@ mod tests {
         assert!(report.description.contains("alice"));
     }
 
+    #[test]
+    fn test_failures_ignored_when_successful_logins_only() {
+        let config = IpSwitchConfig {
+            successful_logins_only: true,
+            ..IpSwitchConfig::default()
+        };
+        let mut context = IdentityContext::new().with_config(&config);
+        let failure = |user: &str, ip: &str, timestamp: i64| LogEvent {
+            event_type: "SSH_FAILED".to_string(),
+            ..create_event(user, ip, timestamp)
+        };
+
+        context.check_for_ip_switch(&create_event("alice", "1.1.1.1", 1700000000));
+        assert!(context.check_for_ip_switch(&failure("alice", "2.2.2.2", 1700000005)).is_none());
+        assert_eq!(context.get_last_ip("alice"), Some(IpAddr::from_str("1.1.1.1").unwrap()));
+        assert!(context.check_for_ip_switch(&create_event("alice", "1.1.1.1", 1700000010)).is_none());
+
+        // A failure for a new user doesn't set their trusted IP either
+        context.check_for_ip_switch(&failure("bob", "3.3.3.3", 1700000015));
+        assert_eq!(context.get_last_ip("bob"), None);
+    }
+
+    #[test]
+    fn test_switch_severity_scales_with_distance() {
+        let config = IpSwitchConfig {
+            scale_by_distance: true,
+            ..IpSwitchConfig::default()
+        };
+        let mut context = IdentityContext::new().with_config(&config);
+        let locate = |ip: &IpAddr| match ip.to_string().as_str() {
+            // Two Berlin ISPs and a Sydney one
+            "1.1.1.1" => Some(GeoLocation { latitude: 52.5200, longitude: 13.4050 }),
+            "2.2.2.2" => Some(GeoLocation { latitude: 52.4800, longitude: 13.3500 }),
+            "3.3.3.3" => Some(GeoLocation { latitude: -33.8688, longitude: 151.2093 }),
+            _ => None,
+        };
+
+        context.check_for_ip_switch_with_geo(&create_event("alice", "1.1.1.1", 1700000000), locate);
+        let local = context
+            .check_for_ip_switch_with_geo(&create_event("alice", "2.2.2.2", 1700000005), locate)
+            .unwrap();
+        let intercontinental = context
+            .check_for_ip_switch_with_geo(&create_event("alice", "3.3.3.3", 1700000010), locate)
+            .unwrap();
+
+        assert_eq!(local.severity, config.min_severity);
+        assert_eq!(intercontinental.severity, config.max_severity);
+        assert!(local.severity < intercontinental.severity);
+        assert_eq!(local.metadata["switch_distance_km"], "6");
+
+        // Without a location for the new IP the fixed severity applies
+        let unknown = context
+            .check_for_ip_switch_with_geo(&create_event("alice", "4.4.4.4", 1700000015), locate)
+            .unwrap();
+        assert_eq!(unknown.severity, config.severity);
+        assert!(unknown.metadata.is_empty());
+    }
+
+    #[test]
+    fn test_return_to_known_ip_not_realerted() {
+        let config = IpSwitchConfig {
+            known_ips_per_user: 2,
+            ..IpSwitchConfig::default()
+        };
+        let mut context = IdentityContext::new().with_config(&config);
+        assert!(context.check_for_ip_switch(&create_event("alice", "1.1.1.1", 1000)).is_none());
+
+        // Switching away is reported, coming back isn't
+        let away = context.check_for_ip_switch(&create_event("alice", "2.2.2.2", 1100)).unwrap();
+        assert_eq!(away.rule_name, "Sudden IP Switch");
+        assert!(context.check_for_ip_switch(&create_event("alice", "1.1.1.1", 1200)).is_none());
+
+        // The switched-to IP isn't known until a login from it confirms it,
+        // so alternating with an unconfirmed IP keeps being reported
+        assert!(context.check_for_ip_switch(&create_event("alice", "2.2.2.2", 1300)).is_some());
+        assert!(context.check_for_ip_switch(&create_event("alice", "2.2.2.2", 1350)).is_none());
+        assert!(context.check_for_ip_switch(&create_event("alice", "1.1.1.1", 1400)).is_none());
+        assert!(context.check_for_ip_switch(&create_event("alice", "2.2.2.2", 1450)).is_none());
+
+        // Failures from a switched-to IP don't confirm it
+        context.check_for_ip_switch(&create_event("alice", "3.3.3.3", 1500)).unwrap();
+        context.check_for_ip_switch(&LogEvent {
+            event_type: "SSH_FAILED".to_string(),
+            ..create_event("alice", "3.3.3.3", 1510)
+        });
+        assert!(context.check_for_ip_switch(&create_event("alice", "2.2.2.2", 1520)).is_none());
+        assert!(context.check_for_ip_switch(&create_event("alice", "3.3.3.3", 1530)).is_some());
+
+        // A third confirmed IP pushes out the oldest remembered one
+        assert!(context.check_for_ip_switch(&create_event("alice", "3.3.3.3", 1540)).is_none());
+        assert!(context.check_for_ip_switch(&create_event("alice", "1.1.1.1", 1600)).is_some());
+
+        // Returns can be reported at a low severity instead
+        let config = IpSwitchConfig {
+            known_ips_per_user: 2,
+            report_returns: true,
+            ..IpSwitchConfig::default()
+        };
+        let mut context = IdentityContext::new().with_config(&config);
+        context.check_for_ip_switch(&create_event("bob", "1.1.1.1", 1000));
+        context.check_for_ip_switch(&create_event("bob", "2.2.2.2", 1100));
+        context.check_for_ip_switch(&create_event("bob", "2.2.2.2", 1150));
+        let back = context.check_for_ip_switch(&create_event("bob", "1.1.1.1", 1200)).unwrap();
+        assert_eq!(back.rule_name, "Returned to Known IP");
+        assert_eq!(back.severity, config.return_severity);
+        assert_eq!(back.trusted_ip, "2.2.2.2");
+    }
+
+    #[test]
+    fn test_known_ips_survive_restart() {
+        let config = IpSwitchConfig {
+            known_ips_per_user: 2,
+            ..IpSwitchConfig::default()
+        };
+        let store: Arc<dyn StateStore> = Arc::new(crate::persistence::SqliteStateStore::in_memory().unwrap());
+        let mut context = IdentityContext::with_persistence(store.clone()).with_config(&config);
+        context.check_for_ip_switch(&create_event("alice", "1.1.1.1", 1000));
+        context.check_for_ip_switch(&create_event("alice", "2.2.2.2", 1100)).unwrap();
+        context.check_for_ip_switch(&create_event("alice", "2.2.2.2", 1200));
+
+        // A fresh context still knows both IPs
+        let mut context = IdentityContext::with_persistence(store).with_config(&config);
+        assert!(context.check_for_ip_switch(&create_event("alice", "1.1.1.1", 1300)).is_none());
+        assert!(context.check_for_ip_switch(&create_event("alice", "2.2.2.2", 1400)).is_none());
+        assert!(context.check_for_ip_switch(&create_event("alice", "3.3.3.3", 1500)).is_some());
+    }
+
     #[test]
     fn test_different_users_independent() {
         let mut context = IdentityContext::new();
@@ -217,6 +578,36 @@ mod tests {
         assert!(context.get_last_ip("bob").is_none());
     }
 
+    #[test]
+    fn test_max_tracked_users() {
+        let mut context = IdentityContext::new().with_max_tracked(Some(10));
+
+        for i in 0..50 {
+            let event = create_event(&format!("user{}", i), "1.1.1.1", 1700000000 + i);
+            context.check_for_ip_switch(&event);
+        }
+
+        assert_eq!(context.tracked_users(), 10);
+        assert!(context.get_last_ip("user49").is_some());
+        assert!(context.get_last_ip("user40").is_some());
+        assert!(context.get_last_ip("user0").is_none());
+    }
+
+    #[test]
+    fn test_explain_mode() {
+        let mut context = IdentityContext::new().with_explain(true);
+
+        context.check_for_ip_switch(&create_event("alice", "1.1.1.1", 1700000000));
+        assert!(context.last_explanation().unwrap().contains("no known IP"));
+
+        context.check_for_ip_switch(&create_event("alice", "2.2.2.2", 1700000005));
+        let explanation = context.last_explanation().unwrap();
+        assert!(explanation.contains("1.1.1.1"));
+        assert!(explanation.ends_with("-> triggered"));
+
+        assert!(IdentityContext::new().last_explanation().is_none());
+    }
+
     #[test]
     fn test_ipv6_support() {
         let mut context = IdentityContext::new();
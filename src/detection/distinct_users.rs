@@ -0,0 +1,239 @@
+//! Bounded tracking of distinct usernames per source
+//!
+//! A spray against millions of usernames from one IP would otherwise make
+//! the set of usernames remembered for that IP grow without limit.
+//! [`DistinctUsers`] keeps it bounded in one of two ways:
+//!
+//! - **Exact** remembers up to `cap` usernames with their latest failure
+//!   time, forgetting the oldest beyond that. Counts are exact below the
+//!   cap and saturate at it, which is harmless as long as the cap is
+//!   above the rule's threshold.
+//! - **Approximate** feeds usernames into [`HyperLogLog`] sketches, one
+//!   per quarter of the window, so memory is fixed (4 KiB per IP) however
+//!   many usernames arrive. The estimate has a standard error of about
+//!   1.6%, the window edge is only accurate to a quarter window, and a
+//!   username can't be excluded from a sketch, so a user's own earlier
+//!   failures may count towards their login (at most one extra).
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+
+/// Register index bits; 2^12 registers give ~1.6% standard error
+const HLL_PRECISION: u32 = 12;
+const HLL_REGISTERS: usize = 1 << HLL_PRECISION;
+
+/// Sketches kept per window in approximate mode
+const APPROXIMATE_BUCKETS: i64 = 4;
+
+/// HyperLogLog distinct-count sketch
+#[derive(Debug, Clone)]
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        HyperLogLog { registers: vec![0; HLL_REGISTERS] }
+    }
+}
+
+impl HyperLogLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) {
+        let mut hasher = DefaultHasher::new();
+        item.hash(&mut hasher);
+        let hash = hasher.finish();
+        let index = (hash >> (64 - HLL_PRECISION)) as usize;
+        // Leading zeros of the remaining bits, plus one
+        let rest = (hash << HLL_PRECISION) | (1 << (HLL_PRECISION - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        if rank > self.registers[index] {
+            self.registers[index] = rank;
+        }
+    }
+
+    /// Fold another sketch into this one (union)
+    pub fn merge(&mut self, other: &HyperLogLog) {
+        for (mine, theirs) in self.registers.iter_mut().zip(&other.registers) {
+            *mine = (*mine).max(*theirs);
+        }
+    }
+
+    /// Estimated number of distinct items inserted
+    pub fn estimate(&self) -> f64 {
+        let m = HLL_REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|&r| 2f64.powi(-i32::from(r))).sum();
+        let raw = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        if raw <= 2.5 * m && zeros > 0 {
+            // Linear counting is more accurate for small cardinalities
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        }
+    }
+}
+
+/// Distinct usernames seen from one source within a sliding window
+#[derive(Debug, Clone)]
+pub enum DistinctUsers {
+    /// Username -> latest timestamp, at most `cap` entries
+    Exact { users: HashMap<String, i64>, cap: usize },
+    /// (bucket start, sketch), oldest first
+    Approximate { buckets: VecDeque<(i64, HyperLogLog)>, bucket_seconds: i64 },
+}
+
+impl DistinctUsers {
+    pub fn exact(cap: usize) -> Self {
+        DistinctUsers::Exact { users: HashMap::new(), cap: cap.max(1) }
+    }
+
+    pub fn approximate(window_seconds: i64) -> Self {
+        DistinctUsers::Approximate {
+            buckets: VecDeque::new(),
+            bucket_seconds: (window_seconds / APPROXIMATE_BUCKETS).max(1),
+        }
+    }
+
+    /// Record a username at `timestamp`
+    pub fn record(&mut self, user: &str, timestamp: i64) {
+        match self {
+            DistinctUsers::Exact { users, cap } => {
+                if !users.contains_key(user) && users.len() >= *cap {
+                    let oldest = users.iter().min_by_key(|(_, ts)| **ts).map(|(u, _)| u.clone());
+                    if let Some(oldest) = oldest {
+                        users.remove(&oldest);
+                    }
+                }
+                let latest = users.entry(user.to_string()).or_insert(timestamp);
+                *latest = (*latest).max(timestamp);
+            }
+            DistinctUsers::Approximate { buckets, bucket_seconds } => {
+                let start = timestamp - timestamp.rem_euclid(*bucket_seconds);
+                match buckets.iter_mut().find(|(bucket_start, _)| *bucket_start == start) {
+                    Some((_, sketch)) => sketch.insert(user),
+                    None => {
+                        let mut sketch = HyperLogLog::new();
+                        sketch.insert(user);
+                        buckets.push_back((start, sketch));
+                        buckets.make_contiguous().sort_by_key(|(bucket_start, _)| *bucket_start);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Forget entries older than `window_start`
+    pub fn prune(&mut self, window_start: i64) {
+        match self {
+            DistinctUsers::Exact { users, .. } => users.retain(|_, ts| *ts >= window_start),
+            DistinctUsers::Approximate { buckets, bucket_seconds } => {
+                while buckets.front().is_some_and(|(start, _)| start + *bucket_seconds <= window_start) {
+                    buckets.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Distinct usernames since `window_start`, other than `excluding`
+    ///
+    /// Approximate mode can't exclude a username; see the module docs.
+    pub fn count_excluding(&self, excluding: &str, window_start: i64) -> usize {
+        match self {
+            DistinctUsers::Exact { users, .. } => users
+                .iter()
+                .filter(|(user, ts)| **ts >= window_start && user.as_str() != excluding)
+                .count(),
+            DistinctUsers::Approximate { buckets, bucket_seconds } => {
+                let mut union = HyperLogLog::new();
+                for (_, sketch) in buckets.iter().filter(|(start, _)| start + *bucket_seconds > window_start) {
+                    union.merge(sketch);
+                }
+                union.estimate().round() as usize
+            }
+        }
+    }
+
+    /// Usernames since `window_start` other than `excluding`, sorted
+    /// (empty in approximate mode)
+    pub fn users_excluding(&self, excluding: &str, window_start: i64) -> Vec<&str> {
+        let DistinctUsers::Exact { users, .. } = self else {
+            return Vec::new();
+        };
+        let mut names: Vec<&str> = users
+            .iter()
+            .filter(|(user, ts)| **ts >= window_start && user.as_str() != excluding)
+            .map(|(user, _)| user.as_str())
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            DistinctUsers::Exact { users, .. } => users.is_empty(),
+            DistinctUsers::Approximate { buckets, .. } => buckets.is_empty(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hyperloglog_accurate_at_high_cardinality() {
+        for n in [1_000usize, 100_000, 1_000_000] {
+            let mut sketch = HyperLogLog::new();
+            for i in 0..n {
+                sketch.insert(&format!("user{}", i));
+            }
+            let error = (sketch.estimate() - n as f64).abs() / n as f64;
+            assert!(error < 0.05, "{} distinct estimated as {} ({:.1}% off)", n, sketch.estimate(), error * 100.0);
+        }
+    }
+
+    #[test]
+    fn test_exact_below_cap() {
+        let mut users = DistinctUsers::exact(100);
+        for i in 0..50 {
+            users.record(&format!("user{}", i), 1000 + i);
+            // Repeats don't count twice
+            users.record(&format!("user{}", i), 1000 + i);
+        }
+        assert_eq!(users.count_excluding("nobody", 0), 50);
+        assert_eq!(users.count_excluding("user0", 0), 49);
+        assert_eq!(users.count_excluding("nobody", 1040), 10);
+    }
+
+    #[test]
+    fn test_exact_saturates_at_cap_keeping_newest() {
+        let mut users = DistinctUsers::exact(10);
+        for i in 0..10_000 {
+            users.record(&format!("user{}", i), i);
+        }
+        assert_eq!(users.count_excluding("nobody", 0), 10);
+        assert_eq!(users.users_excluding("nobody", 0)[0], "user9990");
+    }
+
+    #[test]
+    fn test_approximate_spray_within_tolerance_and_window() {
+        let mut users = DistinctUsers::approximate(3600);
+        for i in 0..200_000i64 {
+            users.record(&format!("user{}", i), 10_000 + i % 3600);
+        }
+        let estimate = users.count_excluding("nobody", 10_000) as f64;
+        assert!((estimate - 200_000.0).abs() / 200_000.0 < 0.05, "estimated {}", estimate);
+        assert!(users.users_excluding("nobody", 10_000).is_empty());
+
+        // Buckets age out once the window has passed
+        users.prune(10_000 + 7200);
+        assert!(users.is_empty());
+        assert_eq!(users.count_excluding("nobody", 10_000 + 7200), 0);
+    }
+}
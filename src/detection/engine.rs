@@ -0,0 +1,896 @@
+//! Inline detection facade
+//!
+//! The daemon reports anomalies after the fact; embedders such as an auth
+//! service instead want a verdict for a login as it happens. The
+//! [`DetectionEngine`] runs the enabled rules synchronously against a
+//! single event and maps the most severe report to allow, challenge or
+//! deny using the configured thresholds.
+//!
+//! The daemon runs its rules through the same engine, via
+//! [`DetectionEngine::detect`], so both see the same rule set.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::config::{DecisionConfig, DetectionConfig, DetectionRule, UnknownUserPolicy};
+use crate::geolocation::{EventGeo, GeoHealth, GeoIpService, GeoLookup};
+use crate::models::{AnomalyReport, LogEvent};
+use crate::persistence::{StateStore, StoreHealth};
+use crate::processing::{EnrichedEvent, Enrichment};
+use crate::telemetry::{EventTrace, EventTracer};
+use super::{
+    annotate_parameters, cap_reports, run_rule, AttackingIpDetector, SuccessClusterDetector, FirstSeenDetector, GeoVelocityTracker, HostingAsnDetector,
+    IdentityContext, HomeRegionDetector, HourPatternDetector, LockoutDetector, LoginRateLimiter, MaintenanceMode,
+    OffHoursDetector,
+};
+
+/// Outcome for a single login
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    Challenge,
+    Deny,
+}
+
+impl std::fmt::Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Verdict::Allow => write!(f, "allow"),
+            Verdict::Challenge => write!(f, "challenge"),
+            Verdict::Deny => write!(f, "deny"),
+        }
+    }
+}
+
+/// A verdict together with the reports that produced it
+#[derive(Debug, Clone)]
+pub struct Decision {
+    pub verdict: Verdict,
+    /// Every report raised for the event, most severe first
+    pub reports: Vec<AnomalyReport>,
+}
+
+impl Decision {
+    /// Map reports to a verdict using the configured thresholds
+    pub fn from_reports(mut reports: Vec<AnomalyReport>, thresholds: &DecisionConfig) -> Self {
+        reports.sort_by_key(|report| std::cmp::Reverse(report.severity));
+        let verdict = match reports.first().map(|report| report.severity) {
+            Some(severity) if severity >= thresholds.deny_severity => Verdict::Deny,
+            Some(severity) if severity >= thresholds.challenge_severity => Verdict::Challenge,
+            _ => Verdict::Allow,
+        };
+        Decision { verdict, reports }
+    }
+
+    /// Highest severity among the reports (None when nothing triggered)
+    pub fn max_severity(&self) -> Option<u8> {
+        self.reports.first().map(|report| report.severity)
+    }
+}
+
+/// Reports raised for one event, before they are mapped to a verdict
+#[derive(Debug, Clone, Default)]
+pub struct Detection {
+    /// Rule reports, capped and annotated, in rule order
+    pub reports: Vec<AnomalyReport>,
+    /// Raised when this event's failed geolocation lookup marked
+    /// geolocation as degraded (with `alert_when_degraded`)
+    pub geo_degraded: Option<AnomalyReport>,
+}
+
+/// Runs the enabled detection rules against events and returns decisions
+pub struct DetectionEngine {
+    config: DetectionConfig,
+    identity_context: IdentityContext,
+    geo_velocity_tracker: GeoVelocityTracker,
+    rate_limiter: LoginRateLimiter,
+    attacking_ip_detector: AttackingIpDetector,
+    success_cluster_detector: SuccessClusterDetector,
+    hour_pattern_detector: HourPatternDetector,
+    first_seen_detector: FirstSeenDetector,
+    lockout_detector: LockoutDetector,
+    off_hours_detector: Option<OffHoursDetector>,
+    home_region_detector: Option<HomeRegionDetector>,
+    geo_service: Option<GeoIpService>,
+    geo_health: GeoHealth,
+    hosting_asn_detector: Option<HostingAsnDetector>,
+    /// Backend of the stateful rules, and where its errors are counted
+    store: Option<Arc<dyn StateStore>>,
+    store_health: Arc<StoreHealth>,
+    /// Rate limit weights per event type (the configured ones by default)
+    event_weights: HashMap<String, usize>,
+    unknown_user: UnknownUserPolicy,
+    maintenance: MaintenanceMode,
+    tracer: EventTracer,
+}
+
+impl DetectionEngine {
+    /// Build in-memory detectors from the detection configuration
+    ///
+    /// Fails if the off-hours rule is enabled with an invalid timezone or
+    /// hour range, or the home region rule with invalid coordinates.
+    pub fn new(config: &DetectionConfig) -> Result<Self, String> {
+        config.rate_limit.validate()?;
+        let off_hours_detector = if config.rule_enabled(DetectionRule::OffHours) {
+            Some(OffHoursDetector::new(&config.off_hours)?)
+        } else {
+            None
+        };
+        let home_region_detector = if config.rule_enabled(DetectionRule::HomeRegion) {
+            Some(HomeRegionDetector::new(&config.home_region)?)
+        } else {
+            None
+        };
+
+        let store_health = Arc::new(StoreHealth::new());
+        let event_weights = config.rate_limit.event_weights.clone();
+        Ok(DetectionEngine {
+            config: config.clone(),
+            identity_context: identity_context(config, None, &store_health),
+            geo_velocity_tracker: geo_velocity_tracker(config, None, &store_health),
+            rate_limiter: rate_limiter(config, None, &store_health, event_weights.clone()),
+            attacking_ip_detector: AttackingIpDetector::new(&config.attacking_ip)
+                .with_max_tracked(config.max_tracked_entries)
+                .with_explain(config.explain),
+            success_cluster_detector: SuccessClusterDetector::new(&config.success_cluster)
+                .with_max_tracked(config.max_tracked_entries)
+                .with_explain(config.explain),
+            hour_pattern_detector: HourPatternDetector::new(&config.hour_pattern)
+                .with_max_tracked(config.max_tracked_entries)
+                .with_explain(config.explain),
+            first_seen_detector: first_seen_detector(config, None, &store_health),
+            lockout_detector: lockout_detector(config, None, &store_health),
+            off_hours_detector,
+            home_region_detector,
+            geo_service: None,
+            geo_health: GeoHealth::new(config.geo_location.degraded_after_failures)
+                .with_alert(config.geo_location.alert_when_degraded),
+            hosting_asn_detector: None,
+            store: None,
+            store_health,
+            event_weights,
+            unknown_user: UnknownUserPolicy::default(),
+            maintenance: MaintenanceMode::new(),
+            tracer: EventTracer::disabled(),
+        })
+    }
+
+    /// Keep the stateful rules' state in `store`, counting store errors
+    /// (which fall back to in-memory state) in `health`
+    ///
+    /// Replaces any state the rules have learned so far.
+    pub fn with_persistence(mut self, store: Arc<dyn StateStore>, health: Arc<StoreHealth>) -> Self {
+        self.store = Some(store);
+        self.store_health = health;
+        self.identity_context = identity_context(&self.config, self.store.clone(), &self.store_health);
+        self.geo_velocity_tracker = geo_velocity_tracker(&self.config, self.store.clone(), &self.store_health);
+        self.rate_limiter = rate_limiter(&self.config, self.store.clone(), &self.store_health, self.event_weights.clone());
+        self.first_seen_detector = first_seen_detector(&self.config, self.store.clone(), &self.store_health);
+        self.lockout_detector = lockout_detector(&self.config, self.store.clone(), &self.store_health);
+        self
+    }
+
+    /// Weigh events towards the rate limits with `weights` instead of
+    /// the configured `event_weights` (e.g. scaled up for sampling)
+    ///
+    /// Replaces any state the rate limiter has learned so far.
+    pub fn with_event_weights(mut self, weights: HashMap<String, usize>) -> Self {
+        self.event_weights = weights;
+        self.rate_limiter = rate_limiter(&self.config, self.store.clone(), &self.store_health, self.event_weights.clone());
+        self
+    }
+
+    /// Use a GeoIP database for impossible travel, home region and
+    /// off-hours timezones
+    pub fn with_geo_service(mut self, geo_service: GeoIpService) -> Self {
+        self.geo_service = Some(geo_service);
+        self
+    }
+
+    /// Enable hosting provider detection with the given detector
+    pub fn with_hosting_asn(mut self, detector: HostingAsnDetector) -> Self {
+        self.hosting_asn_detector = Some(detector);
+        self
+    }
+
+    /// How to treat events whose username couldn't be extracted
+    ///
+    /// With `Drop` such events are always allowed without touching any
+    /// rule state.
+    pub fn with_unknown_user_policy(mut self, policy: UnknownUserPolicy) -> Self {
+        self.unknown_user = policy;
+        self
+    }
+
+    /// Suppress every report while `mode` is active
+    ///
+    /// Rules still run and update their state, so decisions after
+    /// maintenance are made against current baselines.
+    pub fn with_maintenance_mode(mut self, mode: MaintenanceMode) -> Self {
+        self.maintenance = mode;
+        self
+    }
+
+    /// Record a trace of each evaluation, with a span per rule
+    pub fn with_tracer(mut self, tracer: EventTracer) -> Self {
+        self.tracer = tracer;
+        self
+    }
+
+    /// Run the enabled rules against an event and decide on it
+    ///
+    /// Rule state is updated as in the daemon, so failures fed through
+    /// `evaluate` count towards later decisions. Rules skipped by
+    /// `short_circuit_severity` don't update their state for the event.
+    pub fn evaluate(&mut self, event: &LogEvent) -> Decision {
+        let trace = self.tracer.trace_event(event);
+        let mut detection = self.run_rules(event, None, &trace, true);
+        if self.maintenance.is_active() {
+            detection.reports.clear();
+        }
+        Decision::from_reports(detection.reports, &self.config.decision)
+    }
+
+    /// Run the enabled rules against an event enrichment already looked
+    /// up, returning its reports
+    ///
+    /// Reports are returned during maintenance too, for the caller to
+    /// suppress. With `time_rules` false (the clock just stepped), rules
+    /// that depend on event timing are skipped.
+    pub fn detect(&mut self, enriched: &EnrichedEvent, trace: &EventTrace, time_rules: bool) -> Detection {
+        self.run_rules(&enriched.event, Some(enriched), trace, time_rules)
+    }
+
+    /// Drop state older than the rules' windows at `now`, returning
+    /// reports for rate limits that cleared and bursts that ended
+    pub fn prune_stale(&mut self, now: i64) -> Vec<AnomalyReport> {
+        self.rate_limiter.prune_stale(now);
+        let mut reports = self.rate_limiter.check_resolved(now);
+        reports.extend(self.rate_limiter.flush_bursts(now));
+        self.attacking_ip_detector.prune_stale(now);
+        self.success_cluster_detector.prune_stale(now);
+        reports
+    }
+
+    /// Reports for rate limit bursts still being merged, for shutdown
+    pub fn drain_bursts(&mut self) -> Vec<AnomalyReport> {
+        self.rate_limiter.drain_bursts()
+    }
+
+    /// Whether a rule is on: enabled in config, or for rules whose
+    /// detector is only built when configured, attached
+    fn rule_active(&self, rule: DetectionRule) -> bool {
+        match rule {
+            DetectionRule::HostingAsn => {
+                self.hosting_asn_detector.is_some() && !self.config.disabled_rules.contains(&rule)
+            }
+            DetectionRule::OffHours => self.off_hours_detector.is_some(),
+            DetectionRule::HomeRegion => self.home_region_detector.is_some(),
+            _ => self.config.rule_enabled(rule),
+        }
+    }
+
+    fn run_rules(
+        &mut self,
+        event: &LogEvent,
+        enriched: Option<&EnrichedEvent>,
+        trace: &EventTrace,
+        time_rules: bool,
+    ) -> Detection {
+        let explain = self.config.explain;
+        let mut reports = Vec::new();
+        if self.unknown_user == UnknownUserPolicy::Drop && event.has_unknown_user() {
+            return Detection::default();
+        }
+        // Events without a username only go to IP-keyed rules unless configured otherwise
+        let user_rules = self.unknown_user.user_rules_apply(event);
+        if !user_rules && explain {
+            log::info!("[explain] Username unknown -> per-user rules skipped");
+        }
+        if !time_rules && explain {
+            log::info!("[explain] Clock recently stepped -> time-sensitive rules skipped");
+        }
+        let maintenance = self.maintenance.is_active();
+        // Every geo-aware rule and enrichment shares one lookup of the
+        // event's IP, made on first use (by the geo stage when there is one)
+        let mut event_geo = EventGeo::new(
+            event.ip_address,
+            self.geo_service.as_ref().map(|geo| geo as &dyn GeoLookup),
+        );
+        let enriched_geo = enriched.filter(|enriched| enriched.has_geo());
+        let staged_geo = || enriched_geo.and_then(|enriched| enriched.geo().found().cloned()).unwrap_or_default();
+        if enriched_geo.is_some() {
+            event_geo = event_geo.with_source(&staged_geo);
+        }
+
+        for rule in self.config.effective_rule_order() {
+            // Disabled rules get no span, so traces show only what ran
+            if !self.rule_active(rule) {
+                continue;
+            }
+            let before = reports.len();
+            let mut span = trace.span("rule", rule.as_str());
+            match rule {
+                DetectionRule::IpSwitch => {
+                    if self.config.rule_enabled(DetectionRule::IpSwitch) && user_rules {
+                        let ctx = &mut self.identity_context;
+                        let locate = |ip: &std::net::IpAddr| event_geo.locate(ip);
+                        reports.extend(
+                            run_rule("IP Switch", event, || ctx.check_for_ip_switch_with_geo(event, locate)).flatten(),
+                        );
+                        log_explanation(ctx.last_explanation());
+                    }
+                }
+                DetectionRule::GeoVelocity => {
+                    if self.config.rule_enabled(DetectionRule::GeoVelocity) && user_rules && time_rules {
+                        if let Some((location, accuracy)) = event_geo.location_with_accuracy() {
+                            let tracker = &mut self.geo_velocity_tracker;
+                            reports.extend(
+                                run_rule("Impossible Travel", event, || {
+                                    tracker.check_impossible_travel_with_accuracy(event, location, accuracy)
+                                })
+                                .flatten(),
+                            );
+                            log_explanation(tracker.last_explanation());
+                        } else if explain {
+                            log::info!(
+                                "[explain] Impossible Travel: no location for {} -> skipped",
+                                event.ip_address
+                            );
+                        }
+                    }
+                }
+                DetectionRule::HostingAsn => {
+                    // Attaching a detector enables the rule; only `disabled_rules` overrides that
+                    let disabled = self.config.disabled_rules.contains(&DetectionRule::HostingAsn);
+                    if let Some(detector) = self.hosting_asn_detector.as_ref().filter(|_| !disabled) {
+                        // A miss from the ASN stage is final; only look up when no stage ran
+                        let asn = match enriched.map(|enriched| enriched.asn()) {
+                            Some(Enrichment::Found(asn)) => Some(asn.clone()),
+                            Some(Enrichment::NotFound) => None,
+                            Some(Enrichment::NotRun) | None => detector.lookup_asn(event),
+                        };
+                        if explain {
+                            log::info!("[explain] {}", detector.explain_with_asn(event, asn.clone()));
+                        }
+                        reports.extend(
+                            run_rule("Hosting Provider", event, || detector.check_login_with_asn(event, asn)).flatten(),
+                        );
+                    }
+                }
+                DetectionRule::OffHours => {
+                    if let Some(detector) = self.off_hours_detector.as_ref().filter(|_| user_rules) {
+                        let geo_timezone = event_geo.city_info().and_then(|info| info.timezone.clone());
+                        if explain {
+                            log::info!("[explain] {}", detector.explain(event, geo_timezone.as_deref()));
+                        }
+                        reports.extend(
+                            run_rule("Off Hours", event, || detector.check_login(event, geo_timezone.as_deref()))
+                                .flatten(),
+                        );
+                    }
+                }
+                DetectionRule::HourPattern => {
+                    if self.config.rule_enabled(DetectionRule::HourPattern) && user_rules {
+                        let detector = &mut self.hour_pattern_detector;
+                        reports.extend(run_rule("Hour Pattern", event, || detector.check_login(event)).flatten());
+                        log_explanation(detector.last_explanation());
+                    }
+                }
+                DetectionRule::HomeRegion => {
+                    if let Some(detector) = &self.home_region_detector {
+                        let location = event_geo.location();
+                        if explain {
+                            log::info!("[explain] {}", detector.explain(event, location));
+                        }
+                        if let Some(location) = location {
+                            reports.extend(
+                                run_rule("Home Region", event, || detector.check_login(event, location)).flatten(),
+                            );
+                        }
+                    }
+                }
+                DetectionRule::AttackingIp => {
+                    if self.config.rule_enabled(DetectionRule::AttackingIp) && time_rules {
+                        let detector = &mut self.attacking_ip_detector;
+                        reports.extend(run_rule("Attacking IP", event, || detector.check_event(event)).flatten());
+                        log_explanation(detector.last_explanation());
+                    }
+                }
+                DetectionRule::SuccessCluster => {
+                    if self.config.rule_enabled(DetectionRule::SuccessCluster) && time_rules {
+                        let detector = &mut self.success_cluster_detector;
+                        reports.extend(run_rule("Success Cluster", event, || detector.check_event(event)).flatten());
+                        log_explanation(detector.last_explanation());
+                    }
+                }
+                DetectionRule::RateLimit => {
+                    if self.config.rule_enabled(DetectionRule::RateLimit) && time_rules {
+                        let limiter = &mut self.rate_limiter;
+                        reports.extend(
+                            run_rule("Rate Limit", event, || {
+                                if user_rules {
+                                    limiter.check_rate_limit(event)
+                                } else {
+                                    limiter.check_ip_rate_limit(event)
+                                }
+                            })
+                            .unwrap_or_default(),
+                        );
+                        log_explanation(limiter.last_explanation());
+                    }
+                }
+                DetectionRule::FirstSeen => {
+                    if self.config.rule_enabled(DetectionRule::FirstSeen) && user_rules {
+                        let detector = &mut self.first_seen_detector;
+                        reports.extend(run_rule("First Seen User", event, || detector.check_login(event)).flatten());
+                        log_explanation(detector.last_explanation());
+                    }
+                }
+                DetectionRule::Lockout => {
+                    if self.config.rule_enabled(DetectionRule::Lockout) && user_rules {
+                        let detector = &mut self.lockout_detector;
+                        reports.extend(run_rule("Login During Lockout", event, || detector.check_event(event)).flatten());
+                        log_explanation(detector.last_explanation());
+                    }
+                }
+            }
+            annotate_parameters(&self.config, rule, &mut reports[before..]);
+            span.record_reports(reports.len() - before);
+
+            // Stop at a severe report; later rules don't see (or learn from) this event.
+            // In maintenance every rule runs so all baselines stay current
+            let severe = reports[before..]
+                .iter()
+                .find(|report| self.config.short_circuits(report.severity))
+                .filter(|_| !maintenance);
+            if let Some(report) = severe {
+                log::debug!(
+                    "{} (severity {}) short-circuited the remaining rules for user={}",
+                    report.rule_name,
+                    report.severity,
+                    event.user
+                );
+                break;
+            }
+        }
+
+        // A run of failed lookups leaves the geo rules above blind
+        let geo_degraded = event_geo
+            .lookup_failed()
+            .filter(|_| self.geo_service.is_some())
+            .and_then(|failed| self.geo_health.record(failed, event.timestamp));
+
+        let mut reports = cap_reports(reports, self.config.max_reports_per_event);
+        reports.iter_mut().for_each(|report| event.annotate_report(report));
+        if self.config.geo_location.enrich_reports {
+            reports.iter_mut().for_each(|report| event_geo.enrich(report));
+        }
+        Detection { reports, geo_degraded }
+    }
+}
+
+/// Log a rule's explanation of its last check (explain mode only)
+fn log_explanation(explanation: Option<&str>) {
+    if let Some(explanation) = explanation {
+        log::info!("[explain] {}", explanation);
+    }
+}
+
+fn identity_context(
+    config: &DetectionConfig,
+    store: Option<Arc<dyn StateStore>>,
+    health: &Arc<StoreHealth>,
+) -> IdentityContext {
+    match store {
+        Some(store) => IdentityContext::with_persistence(store),
+        None => IdentityContext::new(),
+    }
+    .with_config(&config.ip_switch)
+    .with_store_health(health.clone())
+    .with_max_tracked(config.max_tracked_entries)
+    .with_explain(config.explain)
+}
+
+fn geo_velocity_tracker(
+    config: &DetectionConfig,
+    store: Option<Arc<dyn StateStore>>,
+    health: &Arc<StoreHealth>,
+) -> GeoVelocityTracker {
+    let max_velocity = config.geo_velocity.max_velocity_kmh;
+    match store {
+        Some(store) => GeoVelocityTracker::with_persistence(max_velocity, store),
+        None => GeoVelocityTracker::with_max_velocity(max_velocity),
+    }
+    .with_min_location_interval(config.geo_velocity.min_location_interval_seconds)
+    .with_max_accuracy_radius(config.geo_velocity.max_accuracy_radius_km)
+    .with_skip_same_ip(config.geo_velocity.skip_same_ip)
+    .with_successful_logins_only(config.geo_velocity.successful_logins_only)
+    .with_store_health(health.clone())
+    .with_max_tracked(config.max_tracked_entries)
+    .with_explain(config.explain)
+}
+
+fn rate_limiter(
+    config: &DetectionConfig,
+    store: Option<Arc<dyn StateStore>>,
+    health: &Arc<StoreHealth>,
+    event_weights: HashMap<String, usize>,
+) -> LoginRateLimiter {
+    let rate = &config.rate_limit;
+    match store {
+        Some(store) => LoginRateLimiter::with_persistence(
+            rate.window_seconds,
+            rate.max_user_attempts,
+            rate.max_ip_attempts,
+            store,
+        ),
+        None => LoginRateLimiter::with_config(rate.window_seconds, rate.max_user_attempts, rate.max_ip_attempts),
+    }
+    .with_alert_on_resolve(rate.alert_on_resolve)
+    .with_event_weights(event_weights)
+    .with_merge_window(rate.merge_window_seconds, rate.merge_max_count)
+    .with_subnet_limit(rate.subnet_prefix, rate.max_subnet_attempts)
+    .with_ipv6_subnet_prefix(rate.ipv6_subnet_prefix)
+    .with_store_health(health.clone())
+    .with_max_tracked(config.max_tracked_entries)
+    .with_explain(config.explain)
+    .with_rule_parameters(config.include_rule_parameters)
+}
+
+fn first_seen_detector(
+    config: &DetectionConfig,
+    store: Option<Arc<dyn StateStore>>,
+    health: &Arc<StoreHealth>,
+) -> FirstSeenDetector {
+    let detector = FirstSeenDetector::new(&config.first_seen);
+    match store {
+        Some(store) => detector.with_persistence(store),
+        None => detector,
+    }
+    .with_store_health(health.clone())
+    .with_max_tracked(config.max_tracked_entries)
+    .with_explain(config.explain)
+}
+
+fn lockout_detector(
+    config: &DetectionConfig,
+    store: Option<Arc<dyn StateStore>>,
+    health: &Arc<StoreHealth>,
+) -> LockoutDetector {
+    let detector = LockoutDetector::new(&config.lockout);
+    match store {
+        Some(store) => detector.with_persistence(store),
+        None => detector,
+    }
+    .with_store_health(health.clone())
+    .with_max_tracked(config.max_tracked_entries)
+    .with_explain(config.explain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::config::Config;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    fn create_event(user: &str, ip: &str, event_type: &str, timestamp: i64) -> LogEvent {
+        LogEvent {
+            timestamp,
+            user: user.to_string(),
+            ip_address: IpAddr::from_str(ip).unwrap(),
+            event_type: event_type.to_string(),
+            host: None,
+        }
+    }
+
+    fn create_report(severity: u8) -> AnomalyReport {
+        AnomalyReport {
+            severity,
+            rule_name: "Test".to_string(),
+            user: "alice".to_string(),
+            detected_ip: "10.0.0.1".to_string(),
+            trusted_ip: String::new(),
+            timestamp: 0,
+            detected_at: 0,
+            description: String::new(),
+            metadata: BTreeMap::new(),
+            confidence: None,
+        }
+    }
+
+    fn detection_config() -> DetectionConfig {
+        let mut config = Config::default().detection;
+        config.enable_attacking_ip = true;
+        config
+    }
+
+    #[test]
+    fn test_thresholds_map_to_verdicts() {
+        let thresholds = DecisionConfig::default();
+        assert_eq!(Decision::from_reports(vec![], &thresholds).verdict, Verdict::Allow);
+        assert_eq!(Decision::from_reports(vec![create_report(3)], &thresholds).verdict, Verdict::Allow);
+        assert_eq!(Decision::from_reports(vec![create_report(5)], &thresholds).verdict, Verdict::Challenge);
+
+        let decision = Decision::from_reports(vec![create_report(3), create_report(9)], &thresholds);
+        assert_eq!(decision.verdict, Verdict::Deny);
+        assert_eq!(decision.max_severity(), Some(9));
+        assert_eq!(decision.reports.len(), 2);
+    }
+
+    #[test]
+    fn test_first_login_is_allowed() {
+        let mut engine = DetectionEngine::new(&detection_config()).unwrap();
+        let decision = engine.evaluate(&create_event("alice", "10.0.0.1", "SSH_LOGIN", 1000));
+        assert_eq!(decision.verdict, Verdict::Allow);
+        assert!(decision.reports.is_empty());
+    }
+
+    #[test]
+    fn test_ip_switch_is_denied() {
+        let mut engine = DetectionEngine::new(&detection_config()).unwrap();
+        engine.evaluate(&create_event("alice", "10.0.0.1", "SSH_LOGIN", 1000));
+        let decision = engine.evaluate(&create_event("alice", "10.0.0.2", "SSH_LOGIN", 1060));
+        assert_eq!(decision.verdict, Verdict::Deny);
+        assert_eq!(decision.reports[0].severity, 8);
+    }
+
+    #[test]
+    fn test_log_host_reaches_reports() {
+        use crate::input::LogParser;
+
+        let parser = crate::input::LineParser::default();
+        let mut engine = DetectionEngine::new(&detection_config()).unwrap();
+        engine.evaluate(
+            &parser.parse("<38>1 2024-01-15T10:30:00Z web-01 sshd 1 - - Accepted password for alice from 10.0.0.1").unwrap(),
+        );
+        let decision = engine.evaluate(
+            &parser.parse("Jan 15 10:31:00 web-02 sshd[7]: Accepted password for alice from 10.0.0.2 port 22").unwrap(),
+        );
+        assert_eq!(decision.reports[0].metadata[crate::models::LOG_HOST_METADATA_KEY], "web-02");
+    }
+
+    #[test]
+    fn test_attacking_ip_is_denied_and_thresholds_are_configurable() {
+        let mut config = detection_config();
+        config.enable_ip_switch = false;
+        config.decision.deny_severity = 10;
+        let mut engine = DetectionEngine::new(&config).unwrap();
+        for (i, user) in ["bob", "carol", "dave"].iter().enumerate() {
+            engine.evaluate(&create_event(user, "203.0.113.5", "SSH_FAILED", 1000 + i as i64));
+        }
+
+        // Severity 9 is below the raised deny threshold
+        let decision = engine.evaluate(&create_event("alice", "203.0.113.5", "SSH_LOGIN", 1010));
+        assert_eq!(decision.verdict, Verdict::Challenge);
+        assert_eq!(decision.reports[0].rule_name, "Successful Login From Attacking IP");
+    }
+
+    #[test]
+    fn test_unknown_user_only_counts_towards_ip_rules() {
+        let mut config = detection_config();
+        config.rate_limit.max_user_attempts = 2;
+        config.rate_limit.max_ip_attempts = 3;
+        let mut engine = DetectionEngine::new(&config).unwrap();
+
+        // Unrelated unknown-user events from different IPs aren't an IP switch
+        engine.evaluate(&create_event("unknown", "10.0.0.1", "SSH_LOGIN", 1000));
+        let decision = engine.evaluate(&create_event("unknown", "10.0.0.2", "SSH_LOGIN", 1001));
+        assert!(decision.reports.is_empty());
+
+        // ...but still count towards the per-IP rate limit
+        let mut rules = Vec::new();
+        for i in 0..5 {
+            let decision = engine.evaluate(&create_event("unknown", "10.0.0.3", "SSH_FAILED", 1010 + i));
+            rules.extend(decision.reports.into_iter().map(|r| r.rule_name));
+        }
+        assert_eq!(rules, vec!["IP Rate Limit Exceeded"]);
+    }
+
+    #[test]
+    fn test_unknown_user_dropped() {
+        let mut engine = DetectionEngine::new(&detection_config())
+            .unwrap()
+            .with_unknown_user_policy(UnknownUserPolicy::Drop);
+        for i in 0..30 {
+            let decision = engine.evaluate(&create_event("unknown", "10.0.0.3", "SSH_FAILED", 1000 + i));
+            assert_eq!(decision.verdict, Verdict::Allow);
+            assert!(decision.reports.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_reports_per_event_capped() {
+        let mut config = detection_config();
+        config.enable_off_hours = true;
+        config.rate_limit.max_ip_attempts = 2;
+
+        let evaluate_attack = |config: &DetectionConfig| {
+            let mut engine = DetectionEngine::new(config).unwrap();
+            engine.evaluate(&create_event("alice", "10.0.0.1", "SSH_LOGIN", 900));
+            for (i, user) in ["bob", "carol", "dave"].iter().enumerate() {
+                engine.evaluate(&create_event(user, "203.0.113.5", "SSH_FAILED", 1000 + i as i64));
+            }
+            // IP switch, attacking IP, off-hours (00:16 UTC) and IP rate limit
+            engine.evaluate(&create_event("alice", "203.0.113.5", "SSH_LOGIN", 1010))
+        };
+
+        assert_eq!(evaluate_attack(&config).reports.len(), 4);
+
+        config.max_reports_per_event = Some(2);
+        let decision = evaluate_attack(&config);
+        assert_eq!(decision.reports.len(), 2);
+        assert_eq!(decision.verdict, Verdict::Deny);
+        assert_eq!(decision.reports[0].rule_name, "Successful Login From Attacking IP");
+        assert_eq!(decision.reports[0].metadata[crate::detection::report_cap::SUPPRESSED_COUNT_KEY], "2");
+    }
+
+    #[test]
+    fn test_short_circuit_skips_later_rules_and_their_state() {
+        let mut config = detection_config();
+        config.rule_order = vec![DetectionRule::AttackingIp];
+        config.short_circuit_severity = Some(9);
+        let mut engine = DetectionEngine::new(&config).unwrap();
+        engine.evaluate(&create_event("alice", "10.0.0.1", "SSH_LOGIN", 900));
+        for (i, user) in ["bob", "carol", "dave"].iter().enumerate() {
+            engine.evaluate(&create_event(user, "203.0.113.5", "SSH_FAILED", 1000 + i as i64));
+        }
+
+        // Attacking IP runs first and stops the IP switch rule
+        let decision = engine.evaluate(&create_event("alice", "203.0.113.5", "SSH_LOGIN", 1010));
+        let rules: Vec<_> = decision.reports.iter().map(|r| r.rule_name.as_str()).collect();
+        assert_eq!(rules, vec!["Successful Login From Attacking IP"]);
+
+        // The skipped rule never learned the new IP, so once the failures
+        // age out the same login is still a switch from the old one
+        let decision = engine.evaluate(&create_event("alice", "203.0.113.5", "SSH_LOGIN", 1010 + 3700));
+        assert_eq!(decision.reports.len(), 1);
+        assert_eq!(decision.reports[0].rule_name, "Sudden IP Switch");
+        assert_eq!(decision.reports[0].trusted_ip, "10.0.0.1");
+    }
+
+    #[test]
+    fn test_rule_disabled_by_name_does_not_fire() {
+        let mut config = detection_config();
+        config.disabled_rules = vec![DetectionRule::IpSwitch];
+        let mut engine = DetectionEngine::new(&config).unwrap();
+        engine.evaluate(&create_event("alice", "10.0.0.1", "SSH_LOGIN", 900));
+        for (i, user) in ["bob", "carol", "dave"].iter().enumerate() {
+            engine.evaluate(&create_event(user, "203.0.113.5", "SSH_FAILED", 1000 + i as i64));
+        }
+
+        // Both rules would fire; only attacking IP does
+        let decision = engine.evaluate(&create_event("alice", "203.0.113.5", "SSH_LOGIN", 1010));
+        let rules: Vec<_> = decision.reports.iter().map(|r| r.rule_name.as_str()).collect();
+        assert_eq!(rules, vec!["Successful Login From Attacking IP"]);
+    }
+
+    #[test]
+    fn test_first_seen_user_reported_once() {
+        let mut config = detection_config();
+        config.enabled_rules = vec![DetectionRule::FirstSeen];
+        let mut engine = DetectionEngine::new(&config).unwrap();
+
+        let decision = engine.evaluate(&create_event("alice", "10.0.0.1", "SSH_LOGIN", 1000));
+        let rules: Vec<_> = decision.reports.iter().map(|r| r.rule_name.as_str()).collect();
+        assert_eq!(rules, vec!["First Seen User"]);
+        assert_eq!(decision.verdict, Verdict::Allow);
+
+        let decision = engine.evaluate(&create_event("alice", "10.0.0.1", "SSH_LOGIN", 2000));
+        assert!(decision.reports.is_empty());
+    }
+
+    #[test]
+    fn test_login_during_lockout_denied() {
+        let mut config = detection_config();
+        config.enabled_rules = vec![DetectionRule::Lockout];
+        let mut engine = DetectionEngine::new(&config).unwrap();
+
+        engine.evaluate(&create_event("alice", "10.0.0.1", "ACCOUNT_LOCKED", 1000));
+        let decision = engine.evaluate(&create_event("alice", "10.0.0.1", "SSH_LOGIN", 1010));
+        let rules: Vec<_> = decision.reports.iter().map(|r| r.rule_name.as_str()).collect();
+        assert_eq!(rules, vec!["Login During Lockout"]);
+        assert_eq!(decision.verdict, Verdict::Deny);
+
+        engine.evaluate(&create_event("alice", "10.0.0.1", "ACCOUNT_UNLOCKED", 1100));
+        let decision = engine.evaluate(&create_event("alice", "10.0.0.1", "SSH_LOGIN", 1110));
+        assert!(decision.reports.is_empty());
+    }
+
+    #[test]
+    fn test_lockout_from_raw_lines() {
+        use crate::input::LogParser;
+
+        let mut config = detection_config();
+        config.enabled_rules = vec![DetectionRule::Lockout];
+        let mut engine = DetectionEngine::new(&config).unwrap();
+        let parser = crate::input::LineParser::default()
+            .with_classifier(crate::input::EventClassifier::default().with_lockout_events(true));
+        let mut evaluate = |line: &str| engine.evaluate(&parser.parse(line).unwrap());
+
+        evaluate("Jan 15 10:30:00 host sshd[1]: pam_faillock(sshd:auth): Consecutive login failures for user alice account temporarily locked");
+        let decision = evaluate("Jan 15 10:30:10 host sshd[2]: Accepted password for alice from 10.0.0.1 port 22 ssh2");
+        let rules: Vec<_> = decision.reports.iter().map(|r| r.rule_name.as_str()).collect();
+        assert_eq!(rules, vec!["Login During Lockout"]);
+        assert_eq!(decision.reports[0].user, "alice");
+
+        // A failure naming a lockout phrase stays a failure
+        let decision = evaluate("Jan 15 10:30:20 host sshd[3]: Failed password for account locked from 10.0.0.2 port 22 ssh2");
+        assert!(decision.reports.is_empty());
+        let decision = evaluate("Jan 15 10:30:30 host sshd[4]: Accepted password for bob from 10.0.0.2 port 22 ssh2");
+        assert!(decision.reports.is_empty());
+
+        evaluate("Jan 15 10:31:00 host idm: Account unlocked for alice by admin");
+        let decision = evaluate("Jan 15 10:31:10 host sshd[5]: Accepted password for alice from 10.0.0.1 port 22 ssh2");
+        assert!(decision.reports.is_empty());
+    }
+
+    #[test]
+    fn test_maintenance_mode_learns_without_reporting() {
+        let mode = MaintenanceMode::new();
+        let mut engine = DetectionEngine::new(&detection_config())
+            .unwrap()
+            .with_maintenance_mode(mode.clone());
+        engine.evaluate(&create_event("alice", "10.0.0.1", "SSH_LOGIN", 1000));
+
+        mode.set(true);
+        let decision = engine.evaluate(&create_event("alice", "10.0.0.2", "SSH_LOGIN", 2000));
+        assert!(decision.reports.is_empty());
+        assert_eq!(decision.verdict, Verdict::Allow);
+        for i in 0..10 {
+            let decision = engine.evaluate(&create_event("alice", "10.0.0.2", "SSH_FAILED", 2100 + i));
+            assert!(decision.reports.is_empty());
+        }
+        // Baselines advanced: the login and failures were counted...
+        assert_eq!(engine.rate_limiter.get_user_attempt_count("alice"), 11);
+
+        // ...and the new IP became trusted, so detection resumes against it
+        mode.set(false);
+        let decision = engine.evaluate(&create_event("alice", "10.0.0.3", "SSH_LOGIN", 2200));
+        let switch = decision.reports.iter().find(|r| r.rule_name == "Sudden IP Switch").unwrap();
+        assert_eq!(switch.trusted_ip, "10.0.0.2");
+    }
+
+    #[test]
+    fn test_detect_skips_time_rules_and_leaves_maintenance_to_caller() {
+        let mut config = detection_config();
+        config.rate_limit.max_user_attempts = 2;
+        let mode = MaintenanceMode::new();
+        let mut engine = DetectionEngine::new(&config)
+            .unwrap()
+            .with_maintenance_mode(mode.clone());
+        let trace = EventTracer::disabled().trace_event(&create_event("alice", "10.0.0.1", "SSH_FAILED", 1000));
+        let failure = |timestamp| EnrichedEvent::new(create_event("alice", "10.0.0.1", "SSH_FAILED", timestamp));
+
+        // With the clock just stepped, failures don't count towards the limit
+        for i in 0..5 {
+            assert!(engine.detect(&failure(1000 + i), &trace, false).reports.is_empty());
+        }
+
+        // Reports raised in maintenance are returned for the caller to suppress
+        mode.set(true);
+        let mut rules = Vec::new();
+        for i in 0..4 {
+            let detection = engine.detect(&failure(1010 + i), &trace, true);
+            rules.extend(detection.reports.into_iter().map(|r| r.rule_name));
+        }
+        assert_eq!(rules, vec!["User Rate Limit Exceeded"]);
+    }
+
+    #[test]
+    fn test_invalid_off_hours_config_rejected() {
+        let mut config = detection_config();
+        config.enable_off_hours = true;
+        config.off_hours.default_timezone = "Nowhere".to_string();
+        assert!(DetectionEngine::new(&config).is_err());
+    }
+
+    #[test]
+    fn test_invalid_home_region_config_rejected() {
+        let mut config = detection_config();
+        config.enable_home_region = true;
+        config.home_region.radius_km = -1.0;
+        assert!(DetectionEngine::new(&config).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+//! Severity escalation for repeated anomalies
+//!
+//! An IP or user that keeps tripping the same rule is more worrying than
+//! a one-off, so each repeat of a (rule, IP) or (rule, user) pair within
+//! the window raises the report's severity by a fixed step, up to a cap.
+
+use crate::config::EscalationConfig;
+use crate::models::AnomalyReport;
+use super::bounded_map::{BoundedMap, DEFAULT_MAX_TRACKED_ENTRIES};
+
+/// Metadata key for how many earlier reports the escalation counted
+pub const REPEAT_COUNT_METADATA_KEY: &str = "repeat_count";
+/// Metadata key for the severity before escalation
+pub const ESCALATED_FROM_METADATA_KEY: &str = "escalated_from";
+
+/// Raises the severity of reports that repeat within a window
+pub struct SeverityEscalator {
+    window_seconds: i64,
+    step: u8,
+    cap: u8,
+    /// (rule, "ip:<addr>" or "user:<name>") -> timestamps of earlier reports
+    repeats: BoundedMap<(String, String), Vec<i64>>,
+}
+
+impl SeverityEscalator {
+    /// Create an escalator from configuration
+    pub fn new(config: &EscalationConfig) -> Self {
+        SeverityEscalator {
+            window_seconds: config.window_seconds,
+            step: config.escalation_step,
+            cap: config.escalation_cap.min(10),
+            repeats: BoundedMap::new("escalation_repeats", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+        }
+    }
+
+    /// Limit the number of (rule, subject) pairs tracked (None for unbounded)
+    pub fn with_max_tracked(mut self, max_entries: Option<usize>) -> Self {
+        self.repeats.set_capacity(max_entries);
+        self
+    }
+
+    /// Record a report and escalate its severity if it is a repeat
+    ///
+    /// The repeat count is the higher of the counts for the report's IP
+    /// and its user, so both a persistent source and a persistently
+    /// targeted account escalate.
+    pub fn apply(&mut self, report: &mut AnomalyReport) {
+        let mut keys = vec![(report.rule_name.clone(), format!("user:{}", report.user))];
+        if !report.detected_ip.is_empty() {
+            keys.push((report.rule_name.clone(), format!("ip:{}", report.detected_ip)));
+        }
+
+        let window_start = report.timestamp - self.window_seconds;
+        let mut repeats = 0;
+        for key in keys {
+            let history = self.repeats.get_or_insert_with(key, Vec::new);
+            history.retain(|&ts| ts > window_start);
+            repeats = repeats.max(history.len());
+            history.push(report.timestamp);
+        }
+
+        if repeats == 0 || self.step == 0 {
+            return;
+        }
+        let raised = (report.severity as usize + repeats * self.step as usize).min(self.cap as usize) as u8;
+        if raised > report.severity {
+            report.metadata.insert(ESCALATED_FROM_METADATA_KEY.to_string(), report.severity.to_string());
+            report.severity = raised;
+        }
+        report.metadata.insert(REPEAT_COUNT_METADATA_KEY.to_string(), repeats.to_string());
+    }
+
+    /// Forget reports older than the window
+    pub fn prune_stale(&mut self, now: i64) {
+        let window_start = now - self.window_seconds;
+        self.repeats.retain(|_, history| {
+            history.retain(|&ts| ts > window_start);
+            !history.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn create_report(ip: &str, user: &str, timestamp: i64) -> AnomalyReport {
+        AnomalyReport {
+            severity: 6,
+            rule_name: "Login From Hosting Provider".to_string(),
+            user: user.to_string(),
+            detected_ip: ip.to_string(),
+            trusted_ip: String::new(),
+            timestamp,
+            detected_at: timestamp,
+            description: "test".to_string(),
+            metadata: BTreeMap::new(),
+            confidence: None,
+        }
+    }
+
+    fn create_escalator() -> SeverityEscalator {
+        SeverityEscalator::new(&EscalationConfig {
+            window_seconds: 3600,
+            escalation_step: 1,
+            escalation_cap: 9,
+        })
+    }
+
+    #[test]
+    fn test_third_repeat_escalates() {
+        let mut escalator = create_escalator();
+        let mut severities = Vec::new();
+        for (i, user) in ["alice", "bob", "carol", "dave", "erin"].iter().enumerate() {
+            let mut report = create_report("203.0.113.5", user, 1700000000 + i as i64 * 600);
+            escalator.apply(&mut report);
+            severities.push(report.severity);
+        }
+        // Escalation stops at the cap
+        assert_eq!(severities, vec![6, 7, 8, 9, 9]);
+    }
+
+    #[test]
+    fn test_repeats_outside_window_not_counted() {
+        let mut escalator = create_escalator();
+        let mut first = create_report("203.0.113.5", "alice", 1700000000);
+        escalator.apply(&mut first);
+
+        let mut later = create_report("203.0.113.5", "alice", 1700000000 + 3600);
+        escalator.apply(&mut later);
+        assert_eq!(later.severity, 6);
+        assert!(!later.metadata.contains_key(REPEAT_COUNT_METADATA_KEY));
+
+        // Other rules keep their own count
+        let mut other = create_report("203.0.113.5", "alice", 1700000000 + 3601);
+        other.rule_name = "Off-Hours Login".to_string();
+        escalator.apply(&mut other);
+        assert_eq!(other.severity, 6);
+    }
+}
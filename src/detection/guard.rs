@@ -0,0 +1,71 @@
+//! Panic isolation for detection rules
+//!
+//! A rule that panics on an unexpected event should cost that one event,
+//! not the daemon. Rules are run through [`run_rule`], which catches the
+//! unwind, logs the offending event and lets processing continue.
+
+use crate::models::LogEvent;
+use std::panic::{self, AssertUnwindSafe};
+
+/// Run a single rule against an event, catching any panic
+///
+/// Returns `None` if the rule panicked. Rule state touched before the
+/// panic is left as-is; the next event proceeds normally.
+pub fn run_rule<T, F: FnOnce() -> T>(rule: &str, event: &LogEvent, check: F) -> Option<T> {
+    match panic::catch_unwind(AssertUnwindSafe(check)) {
+        Ok(result) => Some(result),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            log::error!(
+                "Rule {} panicked on event (user={}, ip={}, type={}, timestamp={}): {}",
+                rule,
+                event.user,
+                event.ip_address,
+                event.event_type,
+                event.timestamp,
+                message
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    fn create_event(user: &str) -> LogEvent {
+        LogEvent {
+            timestamp: 1700000000,
+            user: user.to_string(),
+            ip_address: IpAddr::from_str("1.1.1.1").unwrap(),
+            event_type: "SSH_LOGIN".to_string(),
+            host: None,
+        }
+    }
+
+    #[test]
+    fn test_panicking_rule_does_not_stop_processing() {
+        let mut processed = Vec::new();
+        for user in ["alice", "boom", "bob"] {
+            let event = create_event(user);
+            let result = run_rule("Exploding Rule", &event, || {
+                if event.user == "boom" {
+                    panic!("malformed event");
+                }
+                event.user.clone()
+            });
+            if let Some(user) = result {
+                processed.push(user);
+            }
+        }
+
+        assert_eq!(processed, vec!["alice", "bob"]);
+    }
+}
@@ -0,0 +1,101 @@
+//! Last-seen enrichment
+//!
+//! Attaches when the user was previously seen to reports, so an alert
+//! reader can tell a login after three days of silence from one thirty
+//! seconds after the last. The previous time comes from the state
+//! store's last-IP record and must be read before the IP switch rule
+//! overwrites it with the current event.
+
+use crate::models::AnomalyReport;
+use crate::persistence::{PersistenceError, StateStore};
+
+/// Metadata key for the previous event's timestamp (or "never")
+pub const LAST_SEEN_METADATA_KEY: &str = "last_seen_at";
+/// Metadata key for the seconds between the previous and current event
+pub const SECONDS_SINCE_LAST_SEEN_METADATA_KEY: &str = "seconds_since_last_seen";
+
+/// When a user was seen before the current event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LastSeen {
+    /// No earlier event is recorded for the user
+    Never,
+    /// Unix timestamp of the user's previous event
+    At(i64),
+}
+
+impl LastSeen {
+    /// Look up a user's previous event time in the store
+    pub fn lookup(store: &dyn StateStore, user: &str) -> Result<Self, PersistenceError> {
+        Ok(match store.get_user_last_ip(user)? {
+            Some((_ip, timestamp)) => LastSeen::At(timestamp),
+            None => LastSeen::Never,
+        })
+    }
+
+    /// Record the previous event time and delta in a report's metadata
+    pub fn annotate(&self, report: &mut AnomalyReport) {
+        match *self {
+            LastSeen::Never => {
+                report.metadata.insert(LAST_SEEN_METADATA_KEY.to_string(), "never".to_string());
+            }
+            LastSeen::At(timestamp) => {
+                report.metadata.insert(LAST_SEEN_METADATA_KEY.to_string(), timestamp.to_string());
+                report.metadata.insert(
+                    SECONDS_SINCE_LAST_SEEN_METADATA_KEY.to_string(),
+                    (report.timestamp - timestamp).to_string(),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::net::IpAddr;
+    use crate::persistence::SqliteStateStore;
+
+    fn create_report(user: &str, timestamp: i64) -> AnomalyReport {
+        AnomalyReport {
+            severity: 7,
+            rule_name: "User Rate Limit Exceeded".to_string(),
+            user: user.to_string(),
+            detected_ip: "10.0.0.2".to_string(),
+            trusted_ip: String::new(),
+            timestamp,
+            detected_at: timestamp,
+            description: "test".to_string(),
+            metadata: BTreeMap::new(),
+            confidence: None,
+        }
+    }
+
+    #[test]
+    fn test_delta_from_prior_record() {
+        let store = SqliteStateStore::in_memory().unwrap();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        store.set_user_last_ip("alice", &ip, 1700000000).unwrap();
+
+        let last_seen = LastSeen::lookup(&store, "alice").unwrap();
+        assert_eq!(last_seen, LastSeen::At(1700000000));
+
+        // Three days later
+        let mut report = create_report("alice", 1700000000 + 3 * 86400);
+        last_seen.annotate(&mut report);
+        assert_eq!(report.metadata[LAST_SEEN_METADATA_KEY], "1700000000");
+        assert_eq!(report.metadata[SECONDS_SINCE_LAST_SEEN_METADATA_KEY], "259200");
+    }
+
+    #[test]
+    fn test_first_seen_user() {
+        let store = SqliteStateStore::in_memory().unwrap();
+        let last_seen = LastSeen::lookup(&store, "bob").unwrap();
+        assert_eq!(last_seen, LastSeen::Never);
+
+        let mut report = create_report("bob", 1700000000);
+        last_seen.annotate(&mut report);
+        assert_eq!(report.metadata[LAST_SEEN_METADATA_KEY], "never");
+        assert!(!report.metadata.contains_key(SECONDS_SINCE_LAST_SEEN_METADATA_KEY));
+    }
+}
@@ -0,0 +1,105 @@
+//! Maintenance mode
+//!
+//! During planned administrative work operators want detection to keep
+//! learning (trusted IPs, locations, attempt counters) without producing
+//! any reports or alerts. Unlike a dry run, which still writes reports,
+//! maintenance mode produces nothing; once it ends, detection resumes
+//! against baselines that are current.
+//!
+//! The mode is on while either the runtime flag is set (toggled with
+//! SIGUSR1 in the daemon) or the configured control file exists.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Shared, runtime-togglable maintenance switch
+///
+/// Clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct MaintenanceMode {
+    /// Set at runtime (signal)
+    flag: Arc<AtomicBool>,
+    /// Whether the control file existed when last polled
+    file_present: Arc<AtomicBool>,
+    control_file: Option<PathBuf>,
+}
+
+impl MaintenanceMode {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also enter maintenance mode while `path` exists
+    ///
+    /// The file is only checked by [`poll_control_file`](Self::poll_control_file).
+    pub fn with_control_file(mut self, path: PathBuf) -> Self {
+        self.control_file = Some(path);
+        self
+    }
+
+    /// The configured control file, if any
+    pub fn control_file(&self) -> Option<&Path> {
+        self.control_file.as_deref()
+    }
+
+    /// Whether reports are currently suppressed
+    pub fn is_active(&self) -> bool {
+        self.flag.load(Ordering::SeqCst) || self.file_present.load(Ordering::SeqCst)
+    }
+
+    /// Set the runtime flag, returning whether the mode was active before
+    pub fn set(&self, enabled: bool) -> bool {
+        let was_active = self.is_active();
+        self.flag.store(enabled, Ordering::SeqCst);
+        was_active
+    }
+
+    /// Flip the runtime flag, returning its new value
+    pub fn toggle(&self) -> bool {
+        !self.flag.fetch_xor(true, Ordering::SeqCst)
+    }
+
+    /// Re-check the control file, returning true if that changed the mode
+    pub fn poll_control_file(&self) -> bool {
+        let Some(path) = &self.control_file else {
+            return false;
+        };
+        let was_active = self.is_active();
+        self.file_present.store(path.exists(), Ordering::SeqCst);
+        was_active != self.is_active()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flag_and_clones_share_state() {
+        let mode = MaintenanceMode::new();
+        let shared = mode.clone();
+        assert!(!shared.is_active());
+        assert!(mode.toggle());
+        assert!(shared.is_active());
+        assert!(shared.set(false));
+        assert!(!mode.is_active());
+    }
+
+    #[test]
+    fn test_control_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("maintenance");
+        let mode = MaintenanceMode::new().with_control_file(path.clone());
+
+        assert!(!mode.poll_control_file());
+        std::fs::write(&path, "").unwrap();
+        assert!(!mode.is_active(), "file is only noticed when polled");
+        assert!(mode.poll_control_file());
+        assert!(mode.is_active());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(mode.poll_control_file());
+        assert!(!mode.is_active());
+    }
+}
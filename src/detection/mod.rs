@@ -1,7 +1,53 @@
+pub mod bounded_map;
 pub mod context;
+pub mod distinct_users;
+pub mod engine;
+pub mod escalation;
+pub mod guard;
+pub mod last_seen;
+pub mod maintenance_mode;
 pub mod rule_geo_velocity;
 pub mod rate_limiter;
+pub mod report_cap;
+pub mod report_throttle;
+pub mod risk;
+pub mod rule_hosting_asn;
+pub mod rule_attacking_ip;
+pub mod rule_success_cluster;
+pub mod rule_off_hours;
+pub mod rule_home_region;
+pub mod rule_hour_pattern;
+pub mod rule_first_seen;
+pub mod rule_lockout;
+pub mod rule_parameters;
 
 pub use context::IdentityContext;
-pub use rule_geo_velocity::{GeoLocation, GeoVelocityTracker};
+pub use distinct_users::{DistinctUsers, HyperLogLog};
+pub use engine::{Decision, DetectionEngine, Verdict};
+pub use escalation::SeverityEscalator;
+pub use guard::run_rule;
+pub use last_seen::LastSeen;
+pub use maintenance_mode::MaintenanceMode;
+pub use rule_geo_velocity::{GeoLocation, GeoVelocityTracker, InvalidCoordinates};
 pub use rate_limiter::LoginRateLimiter;
+pub use report_cap::cap_reports;
+pub use report_throttle::UserReportThrottle;
+pub use risk::{RiskAssessment, RiskCorrelator};
+pub use rule_hosting_asn::HostingAsnDetector;
+pub use rule_attacking_ip::AttackingIpDetector;
+pub use rule_success_cluster::SuccessClusterDetector;
+pub use rule_off_hours::OffHoursDetector;
+pub use rule_home_region::HomeRegionDetector;
+pub use rule_hour_pattern::HourPatternDetector;
+pub use rule_first_seen::FirstSeenDetector;
+pub use rule_lockout::LockoutDetector;
+pub use rule_parameters::annotate_parameters;
+
+/// Describe a rule outcome for explain-mode traces
+pub(crate) fn explain_outcome(triggered: bool) -> &'static str {
+    if triggered {
+        "triggered"
+    } else {
+        "not triggered"
+    }
+}
@@ -3,59 +3,209 @@
 //! Tracks login attempt rates per user and per IP address to detect
 //! brute force attacks and credential stuffing.
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::sync::Arc;
+use crate::input::classify::{HTTP_LOGIN, HTTP_REQUEST};
 use crate::models::{LogEvent, AnomalyReport};
-use crate::persistence::StateStore;
+use crate::persistence::{StateStore, StoreHealth};
+use super::bounded_map::{BoundedMap, DEFAULT_MAX_TRACKED_ENTRIES};
+use super::explain_outcome;
 
 /// Sliding window entry for tracking login attempts
 #[derive(Debug, Clone)]
 struct WindowEntry {
-    timestamps: Vec<i64>,
+    /// (timestamp, weight) of each recorded event
+    attempts: Vec<(i64, usize)>,
 }
 
 impl WindowEntry {
     fn new() -> Self {
-        WindowEntry { timestamps: Vec::new() }
+        WindowEntry { attempts: Vec::new() }
     }
 
-    /// Add a timestamp and prune old entries outside the window
-    fn add_and_prune(&mut self, timestamp: i64, window_seconds: i64) {
+    /// Prune old entries outside the window ending at `timestamp`
+    fn prune(&mut self, timestamp: i64, window_seconds: i64) {
         let cutoff = timestamp - window_seconds;
-        self.timestamps.retain(|&t| t > cutoff);
-        self.timestamps.push(timestamp);
+        self.attempts.retain(|&(t, _)| t > cutoff);
+    }
+
+    /// Add an event counting as `weight` attempts
+    fn add(&mut self, timestamp: i64, weight: usize) {
+        self.attempts.push((timestamp, weight));
     }
 
     fn count(&self) -> usize {
-        self.timestamps.len()
+        self.attempts.iter().map(|&(_, weight)| weight).sum()
+    }
+}
+
+/// Event types that count as no attempts unless weighted: web page and
+/// asset loads, which a single visit makes dozens of
+const UNWEIGHTED_EVENTS: &[&str] = &[HTTP_REQUEST, HTTP_LOGIN];
+
+/// Rule name of the report raised when an exceeded limit clears
+pub const CLEARED_RULE_NAME: &str = "Rate Limit Condition Cleared";
+
+/// Metadata key for the subnet of a "Subnet Rate Limit Exceeded" report
+pub const SUBNET_METADATA_KEY: &str = "subnet";
+
+/// Default prefix length IPv6 sources are aggregated by: one end site
+pub const DEFAULT_IPV6_SUBNET_PREFIX: u8 = 64;
+
+/// The subnet containing an address, in CIDR form, of `v4_prefix` bits
+/// for IPv4 and `v6_prefix` bits for IPv6
+fn subnet_of(ip: &IpAddr, v4_prefix: u8, v6_prefix: u8) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let prefix = v4_prefix.min(32);
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            format!("{}/{}", Ipv4Addr::from(u32::from(*ip) & mask), prefix)
+        }
+        IpAddr::V6(ip) => {
+            let prefix = v6_prefix.min(128);
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            format!("{}/{}", Ipv6Addr::from(u128::from(*ip) & mask), prefix)
+        }
+    }
+}
+
+/// Whether `count` attempts break a limit of `max`; a zero limit is disabled
+fn exceeds(count: usize, max: usize) -> bool {
+    max > 0 && count > max
+}
+
+/// Metadata key for the first attempt of a merged burst
+pub const BURST_FIRST_METADATA_KEY: &str = "burst_first_attempt";
+/// Metadata key for the last attempt of a merged burst
+pub const BURST_LAST_METADATA_KEY: &str = "burst_last_attempt";
+/// Metadata key for the number of over-limit attempts in a merged burst
+pub const BURST_COUNT_METADATA_KEY: &str = "burst_count";
+/// Metadata key for the distinct source IPs of a merged burst
+pub const BURST_IPS_METADATA_KEY: &str = "burst_distinct_ips";
+
+/// Over-limit attempts accumulated into a single report
+#[derive(Debug, Clone)]
+struct Burst {
+    /// Most recent report, carrying the highest severity seen
+    report: AnomalyReport,
+    first: i64,
+    last: i64,
+    count: usize,
+    ips: BTreeSet<String>,
+}
+
+impl Burst {
+    fn new(report: AnomalyReport) -> Self {
+        Burst {
+            first: report.timestamp,
+            last: report.timestamp,
+            count: 1,
+            ips: BTreeSet::from([report.detected_ip.clone()]),
+            report,
+        }
+    }
+
+    fn add(&mut self, report: AnomalyReport) {
+        self.last = report.timestamp;
+        self.count += 1;
+        self.ips.insert(report.detected_ip.clone());
+        let severity = self.report.severity.max(report.severity);
+        self.report = report;
+        self.report.severity = severity;
+    }
+
+    /// Summarize the burst's timeline into its report
+    fn into_report(self) -> AnomalyReport {
+        let mut report = self.report;
+        report.description = format!(
+            "{} Burst of {} over-limit attempts between {} and {} from {} IP(s).",
+            report.description,
+            self.count,
+            self.first,
+            self.last,
+            self.ips.len()
+        );
+        report.metadata.insert(BURST_FIRST_METADATA_KEY.to_string(), self.first.to_string());
+        report.metadata.insert(BURST_LAST_METADATA_KEY.to_string(), self.last.to_string());
+        report.metadata.insert(BURST_COUNT_METADATA_KEY.to_string(), self.count.to_string());
+        report.metadata.insert(
+            BURST_IPS_METADATA_KEY.to_string(),
+            self.ips.into_iter().collect::<Vec<_>>().join(","),
+        );
+        report
     }
 }
 
 /// Tracks login attempt rates to detect brute force attacks
 pub struct LoginRateLimiter {
     /// Maps (user OR ip) -> window entry (in-memory cache)
-    per_user_attempts: HashMap<String, WindowEntry>,
-    per_ip_attempts: HashMap<String, WindowEntry>,
+    per_user_attempts: BoundedMap<String, WindowEntry>,
+    per_ip_attempts: BoundedMap<String, WindowEntry>,
+    /// Maps subnet -> window entry (in memory only)
+    per_subnet_attempts: BoundedMap<String, WindowEntry>,
     /// Time window in seconds (default: 300 = 5 minutes)
     window_seconds: i64,
     /// Max attempts per user within window
     max_user_attempts: usize,
     /// Max attempts per IP within window
     max_ip_attempts: usize,
+    /// (IPv4 prefix length, max attempts) of the per-subnet limit, if enabled
+    subnet_limit: Option<(u8, usize)>,
+    /// Prefix length IPv6 sources are aggregated by under the subnet limit
+    ipv6_subnet_prefix: u8,
     /// Optional persistence backend
     store: Option<Arc<dyn StateStore>>,
+    /// Counts store errors fallen back from
+    store_health: Arc<StoreHealth>,
+    /// Record why each check did or didn't trigger
+    explain: bool,
+    last_explanation: Option<String>,
+    /// Attach the limits a report was raised under to its metadata
+    include_parameters: bool,
+    /// Emit a report when an exceeded limit clears
+    alert_on_resolve: bool,
+    /// Users currently over their limit -> (last exceeded timestamp, last IP)
+    exceeded_users: BoundedMap<String, (i64, String)>,
+    /// IPs currently over their limit -> (last exceeded timestamp, last user)
+    exceeded_ips: BoundedMap<String, (i64, String)>,
+    /// Subnets currently over their limit -> (last exceeded timestamp, last user)
+    exceeded_subnets: BoundedMap<String, (i64, String)>,
+    /// Attempts an event of each type counts as (types not listed count once)
+    event_weights: HashMap<String, usize>,
+    /// Merge over-limit reports within this many seconds into one
+    merge_window: Option<i64>,
+    /// Emit a merged report early once it covers this many attempts
+    merge_max_count: Option<usize>,
+    /// Open bursts keyed by "user:<name>", "ip:<address>" or "subnet:<cidr>"
+    bursts: BoundedMap<String, Burst>,
 }
 
 impl LoginRateLimiter {
     /// Create a new rate limiter with default thresholds
     pub fn new() -> Self {
         LoginRateLimiter {
-            per_user_attempts: HashMap::new(),
-            per_ip_attempts: HashMap::new(),
+            per_user_attempts: BoundedMap::new("per_user_attempts", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            per_ip_attempts: BoundedMap::new("per_ip_attempts", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            per_subnet_attempts: BoundedMap::new("per_subnet_attempts", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
             window_seconds: 300,
             max_user_attempts: 10,
             max_ip_attempts: 20,
+            subnet_limit: None,
+            ipv6_subnet_prefix: DEFAULT_IPV6_SUBNET_PREFIX,
             store: None,
+            store_health: Arc::new(StoreHealth::new()),
+            explain: false,
+            last_explanation: None,
+            include_parameters: false,
+            alert_on_resolve: false,
+            exceeded_users: BoundedMap::new("exceeded_users", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            exceeded_ips: BoundedMap::new("exceeded_ips", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            exceeded_subnets: BoundedMap::new("exceeded_subnets", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            event_weights: HashMap::new(),
+            merge_window: None,
+            merge_max_count: None,
+            bursts: BoundedMap::new("rate_limit_bursts", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
         }
     }
 
@@ -66,12 +216,27 @@ impl LoginRateLimiter {
         max_ip_attempts: usize,
     ) -> Self {
         LoginRateLimiter {
-            per_user_attempts: HashMap::new(),
-            per_ip_attempts: HashMap::new(),
+            per_user_attempts: BoundedMap::new("per_user_attempts", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            per_ip_attempts: BoundedMap::new("per_ip_attempts", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            per_subnet_attempts: BoundedMap::new("per_subnet_attempts", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
             window_seconds,
             max_user_attempts,
             max_ip_attempts,
+            subnet_limit: None,
+            ipv6_subnet_prefix: DEFAULT_IPV6_SUBNET_PREFIX,
             store: None,
+            store_health: Arc::new(StoreHealth::new()),
+            explain: false,
+            last_explanation: None,
+            include_parameters: false,
+            alert_on_resolve: false,
+            exceeded_users: BoundedMap::new("exceeded_users", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            exceeded_ips: BoundedMap::new("exceeded_ips", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            exceeded_subnets: BoundedMap::new("exceeded_subnets", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            event_weights: HashMap::new(),
+            merge_window: None,
+            merge_max_count: None,
+            bursts: BoundedMap::new("rate_limit_bursts", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
         }
     }
 
@@ -83,46 +248,188 @@ impl LoginRateLimiter {
         store: Arc<dyn StateStore>,
     ) -> Self {
         LoginRateLimiter {
-            per_user_attempts: HashMap::new(),
-            per_ip_attempts: HashMap::new(),
+            per_user_attempts: BoundedMap::new("per_user_attempts", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            per_ip_attempts: BoundedMap::new("per_ip_attempts", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            per_subnet_attempts: BoundedMap::new("per_subnet_attempts", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
             window_seconds,
             max_user_attempts,
             max_ip_attempts,
+            subnet_limit: None,
+            ipv6_subnet_prefix: DEFAULT_IPV6_SUBNET_PREFIX,
             store: Some(store),
+            store_health: Arc::new(StoreHealth::new()),
+            explain: false,
+            last_explanation: None,
+            include_parameters: false,
+            alert_on_resolve: false,
+            exceeded_users: BoundedMap::new("exceeded_users", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            exceeded_ips: BoundedMap::new("exceeded_ips", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            exceeded_subnets: BoundedMap::new("exceeded_subnets", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            event_weights: HashMap::new(),
+            merge_window: None,
+            merge_max_count: None,
+            bursts: BoundedMap::new("rate_limit_bursts", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+        }
+    }
+
+    /// Limit the number of users, IPs, subnets and open bursts tracked in
+    /// memory (None for unbounded)
+    ///
+    /// Each map is capped separately; when full, the least recently seen
+    /// entry is evicted.
+    pub fn with_max_tracked(mut self, max_entries: Option<usize>) -> Self {
+        self.per_user_attempts.set_capacity(max_entries);
+        self.per_ip_attempts.set_capacity(max_entries);
+        self.per_subnet_attempts.set_capacity(max_entries);
+        self.exceeded_users.set_capacity(max_entries);
+        self.exceeded_ips.set_capacity(max_entries);
+        self.exceeded_subnets.set_capacity(max_entries);
+        self.bursts.set_capacity(max_entries);
+        self
+    }
+
+    /// Also limit attempts per IPv4 subnet of `prefix` bits, and per IPv6
+    /// subnet of the IPv6 prefix, so a distributed attack from one network
+    /// is caught even when no single IP exceeds its limit (disabled unless
+    /// both are set)
+    pub fn with_subnet_limit(mut self, prefix: Option<u8>, max_attempts: Option<usize>) -> Self {
+        self.subnet_limit = prefix.zip(max_attempts);
+        self
+    }
+
+    /// Prefix length IPv6 sources are aggregated by under the subnet limit
+    /// (default /64)
+    pub fn with_ipv6_subnet_prefix(mut self, prefix: u8) -> Self {
+        self.ipv6_subnet_prefix = prefix;
+        self
+    }
+
+    /// Count store errors, which fall back to in-memory state, in a
+    /// shared counter
+    pub fn with_store_health(mut self, health: Arc<StoreHealth>) -> Self {
+        self.store_health = health;
+        self
+    }
+
+    /// Record an explanation of each check, readable via `last_explanation()`
+    pub fn with_explain(mut self, enabled: bool) -> Self {
+        self.explain = enabled;
+        self
+    }
+
+    /// Explanation of the most recent check (explain mode only)
+    pub fn last_explanation(&self) -> Option<&str> {
+        self.last_explanation.as_deref()
+    }
+
+    /// Record the limits each report was raised under (`window_seconds`,
+    /// `max_attempts`, and `subnet_prefix` and `event_weights` where they
+    /// apply) in its metadata
+    pub fn with_rule_parameters(mut self, enabled: bool) -> Self {
+        self.include_parameters = enabled;
+        self
+    }
+
+    /// Enable "Rate Limit Condition Cleared" reports
+    ///
+    /// A limit is considered cleared once a full window has passed
+    /// without the user or IP exceeding it again.
+    pub fn with_alert_on_resolve(mut self, enabled: bool) -> Self {
+        self.alert_on_resolve = enabled;
+        self
+    }
+
+    /// Count events of some types as several attempts
+    ///
+    /// Lets a "maximum authentication attempts exceeded" line weigh more
+    /// than a single closed connection. Types not listed count once,
+    /// except web requests other than rejected ones, which count as none;
+    /// a weight of 0 ignores the type.
+    pub fn with_event_weights(mut self, weights: HashMap<String, usize>) -> Self {
+        self.event_weights = weights;
+        self
+    }
+
+    /// Summarize bursts into one report instead of one per attempt
+    ///
+    /// Over-limit attempts for the same user or IP are accumulated from
+    /// the first one for `window_seconds`, then emitted as a single report
+    /// whose metadata carries the first and last attempt, the count and
+    /// the distinct source IPs. A burst is emitted early once it reaches
+    /// `max_count` attempts. Unlike a cooldown nothing is dropped.
+    pub fn with_merge_window(mut self, window_seconds: Option<i64>, max_count: Option<usize>) -> Self {
+        self.merge_window = window_seconds;
+        self.merge_max_count = max_count;
+        self
+    }
+
+    /// Attempts an event counts as
+    fn event_weight(&self, event: &LogEvent) -> usize {
+        match self.event_weights.get(&event.event_type) {
+            Some(&weight) => weight,
+            None if UNWEIGHTED_EVENTS.contains(&event.event_type.as_str()) => 0,
+            None => 1,
         }
     }
 
     /// Check for rate limit violations (returns up to 2 reports if both limits exceeded)
     pub fn check_rate_limit(&mut self, event: &LogEvent) -> Vec<AnomalyReport> {
+        self.check_limits(event, true)
+    }
+
+    /// Check only the per-IP limit, leaving per-user tracking untouched
+    ///
+    /// Used for events whose username couldn't be extracted, which would
+    /// otherwise all pile up under one pseudo-user.
+    pub fn check_ip_rate_limit(&mut self, event: &LogEvent) -> Vec<AnomalyReport> {
+        self.check_limits(event, false)
+    }
+
+    fn check_limits(&mut self, event: &LogEvent, track_user: bool) -> Vec<AnomalyReport> {
         let mut reports = Vec::new();
         let window_start = event.timestamp - self.window_seconds;
+        let weight = self.event_weight(event);
+        if weight == 0 {
+            return reports;
+        }
 
-        // Record the login attempt to persistence first
+        // Record the login attempt to persistence first; if that fails the
+        // store's counts would miss it, so count from memory instead
+        let mut use_store = self.store.is_some();
         if let Some(ref store) = self.store {
-            if let Err(e) = store.add_login_attempt(&event.user, &event.ip_address, event.timestamp) {
-                log::warn!("Failed to persist login attempt: {}", e);
+            if let Err(e) = store.add_login_attempt(&event.user, &event.ip_address, event.timestamp, weight) {
+                self.store_health.record("store login attempt", &e);
+                use_store = false;
             }
         }
 
-        // Get user attempt count
-        let user_count = self.get_user_attempt_count_internal(&event.user, event.timestamp);
-
-        // Track per-user attempts in memory
-        let user_entry = self
-            .per_user_attempts
-            .entry(event.user.clone())
-            .or_insert_with(WindowEntry::new);
-        user_entry.add_and_prune(event.timestamp, self.window_seconds);
-
-        if user_count > self.max_user_attempts {
-            reports.push(AnomalyReport {
-                severity: Self::calculate_severity(user_count, self.max_user_attempts),
-                rule_name: "User Rate Limit Exceeded".to_string(),
-                user: event.user.clone(),
-                detected_ip: event.ip_address.to_string(),
-                trusted_ip: String::new(),
-                timestamp: event.timestamp,
-                description: format!(
+        // Get user attempt count, then track per-user attempts in memory
+        let user_count = if track_user {
+            self.per_user_attempts
+                .get_or_insert_with(event.user.clone(), WindowEntry::new)
+                .prune(event.timestamp, self.window_seconds);
+            let count = self.get_user_attempt_count_internal(&event.user, event.timestamp, use_store);
+            self.per_user_attempts
+                .get_or_insert_with(event.user.clone(), WindowEntry::new)
+                .add(event.timestamp, weight);
+            count
+        } else {
+            0
+        };
+
+        if track_user && exceeds(user_count, self.max_user_attempts) {
+            if self.alert_on_resolve {
+                self.exceeded_users.insert(
+                    event.user.clone(),
+                    (event.timestamp, event.ip_address.to_string()),
+                );
+            }
+            let report = AnomalyReport::new(
+                Self::calculate_severity(user_count, self.max_user_attempts),
+                "User Rate Limit Exceeded",
+                event.user.clone(),
+                event.timestamp,
+                format!(
                     "User '{}' has {} login attempts in the last {} seconds (threshold: {}). \
                      Possible credential stuffing or brute force attack.",
                     event.user,
@@ -130,29 +437,33 @@ impl LoginRateLimiter {
                     self.window_seconds,
                     self.max_user_attempts
                 ),
-            });
+            )
+            .with_detected_ip(event.ip_address.to_string());
+            let report = self.with_parameters(report, self.max_user_attempts, None);
+            reports.extend(self.merge_report(format!("user:{}", event.user), report));
         }
 
-        // Get IP attempt count
+        // Get IP attempt count, then track per-IP attempts in memory
         let ip_str = event.ip_address.to_string();
-        let ip_count = self.get_ip_attempt_count_internal(&ip_str, window_start);
-
-        // Track per-IP attempts in memory
-        let ip_entry = self
-            .per_ip_attempts
-            .entry(ip_str.clone())
-            .or_insert_with(WindowEntry::new);
-        ip_entry.add_and_prune(event.timestamp, self.window_seconds);
-
-        if ip_count > self.max_ip_attempts {
-            reports.push(AnomalyReport {
-                severity: Self::calculate_severity(ip_count, self.max_ip_attempts),
-                rule_name: "IP Rate Limit Exceeded".to_string(),
-                user: event.user.clone(),
-                detected_ip: ip_str,
-                trusted_ip: String::new(),
-                timestamp: event.timestamp,
-                description: format!(
+        self.per_ip_attempts
+            .get_or_insert_with(ip_str.clone(), WindowEntry::new)
+            .prune(event.timestamp, self.window_seconds);
+        let ip_count = self.get_ip_attempt_count_internal(&event.ip_address, window_start, use_store);
+        self.per_ip_attempts
+            .get_or_insert_with(ip_str.clone(), WindowEntry::new)
+            .add(event.timestamp, weight);
+
+        if exceeds(ip_count, self.max_ip_attempts) {
+            if self.alert_on_resolve {
+                self.exceeded_ips
+                    .insert(ip_str.clone(), (event.timestamp, event.user.clone()));
+            }
+            let report = AnomalyReport::new(
+                Self::calculate_severity(ip_count, self.max_ip_attempts),
+                "IP Rate Limit Exceeded",
+                event.user.clone(),
+                event.timestamp,
+                format!(
                     "IP {} has {} login attempts in the last {} seconds (threshold: {}). \
                      Possible distributed attack or compromised host.",
                     event.ip_address,
@@ -160,20 +471,245 @@ impl LoginRateLimiter {
                     self.window_seconds,
                     self.max_ip_attempts
                 ),
-            });
+            )
+            .with_detected_ip(ip_str.clone());
+            let report = self.with_parameters(report, self.max_ip_attempts, None);
+            reports.extend(self.merge_report(format!("ip:{}", ip_str), report));
+        }
+
+        if let Some((prefix, max_attempts)) = self.subnet_limit {
+            // Get subnet attempt count, then track this attempt, as for users and IPs
+            let subnet = subnet_of(&event.ip_address, prefix, self.ipv6_subnet_prefix);
+            let subnet_entry = self
+                .per_subnet_attempts
+                .get_or_insert_with(subnet.clone(), WindowEntry::new);
+            subnet_entry.prune(event.timestamp, self.window_seconds);
+            let subnet_count = subnet_entry.count();
+            subnet_entry.add(event.timestamp, weight);
+
+            if exceeds(subnet_count, max_attempts) {
+                if self.alert_on_resolve {
+                    self.exceeded_subnets
+                        .insert(subnet.clone(), (event.timestamp, event.user.clone()));
+                }
+                let report = AnomalyReport::new(
+                    Self::calculate_severity(subnet_count, max_attempts),
+                    "Subnet Rate Limit Exceeded",
+                    event.user.clone(),
+                    event.timestamp,
+                    format!(
+                        "Subnet {} has {} login attempts in the last {} seconds (threshold: {}). \
+                         Possible distributed attack from a single network.",
+                        subnet,
+                        subnet_count,
+                        self.window_seconds,
+                        max_attempts
+                    ),
+                )
+                .with_detected_ip(ip_str.clone())
+                .with_metadata(SUBNET_METADATA_KEY, subnet.clone());
+                let report = self.with_parameters(report, max_attempts, Some(&subnet));
+                reports.extend(self.merge_report(format!("subnet:{}", subnet), report));
+            }
+        }
+
+        if self.explain {
+            let user_part = if track_user {
+                format!(
+                    "user '{}' {}/{} attempts -> {}",
+                    event.user,
+                    user_count,
+                    self.max_user_attempts,
+                    explain_outcome(exceeds(user_count, self.max_user_attempts))
+                )
+            } else {
+                format!("user '{}' not tracked", event.user)
+            };
+            self.last_explanation = Some(format!(
+                "Rate Limit: {}; IP {} {}/{} attempts -> {} ({}s window)",
+                user_part,
+                event.ip_address,
+                ip_count,
+                self.max_ip_attempts,
+                explain_outcome(exceeds(ip_count, self.max_ip_attempts)),
+                self.window_seconds
+            ));
+        }
+
+        reports
+    }
+
+    /// Add a report to its burst, returning any report due now
+    ///
+    /// Without a merge window the report is returned unchanged.
+    fn merge_report(&mut self, key: String, report: AnomalyReport) -> Option<AnomalyReport> {
+        if self.merge_window.is_none() {
+            return Some(report);
+        }
+        let burst = match self.bursts.remove(&key) {
+            Some(mut burst) => {
+                burst.add(report);
+                burst
+            }
+            None => Burst::new(report),
+        };
+        self.bursts.insert(key.clone(), burst);
+        self.take_full_burst(&key)
+    }
+
+    fn take_full_burst(&mut self, key: &str) -> Option<AnomalyReport> {
+        let max_count = self.merge_max_count?;
+        if self.bursts.get(key)?.count < max_count {
+            return None;
+        }
+        self.bursts.remove(key).map(Burst::into_report)
+    }
+
+    /// Emit merged reports for bursts whose window has closed
+    ///
+    /// Not checked per event: call this periodically (the engine does
+    /// from `prune_stale`) so bursts are reported, including after the
+    /// attack stops entirely.
+    pub fn flush_bursts(&mut self, current_timestamp: i64) -> Vec<AnomalyReport> {
+        let Some(window) = self.merge_window else {
+            return Vec::new();
+        };
+        let closed: Vec<String> = self
+            .bursts
+            .iter()
+            .filter(|(_, burst)| current_timestamp >= burst.first + window)
+            .map(|(key, _)| key.clone())
+            .collect();
+        closed
+            .iter()
+            .filter_map(|key| self.bursts.remove(key))
+            .map(Burst::into_report)
+            .collect()
+    }
+
+    /// Emit all open bursts regardless of their window (e.g. on shutdown)
+    pub fn drain_bursts(&mut self) -> Vec<AnomalyReport> {
+        self.bursts.drain().map(|(_, burst)| burst.into_report()).collect()
+    }
+
+    /// Emit reports for users, IPs and subnets whose exceeded limit has cleared
+    ///
+    /// Not checked per event: call this periodically (the engine does
+    /// from `prune_stale`) so conditions clear, including after the attack
+    /// stops entirely.
+    pub fn check_resolved(&mut self, current_timestamp: i64) -> Vec<AnomalyReport> {
+        let mut reports = Vec::new();
+        if !self.alert_on_resolve {
+            return reports;
+        }
+
+        let cutoff = current_timestamp - self.window_seconds;
+
+        let cleared_users: Vec<(String, (i64, String))> = self
+            .exceeded_users
+            .iter()
+            .filter(|(_, (last, _))| *last <= cutoff)
+            .map(|(user, data)| (user.clone(), data.clone()))
+            .collect();
+        for (user, (last, ip)) in cleared_users {
+            self.exceeded_users.remove(&user);
+            let report = AnomalyReport::new(
+                3,
+                CLEARED_RULE_NAME,
+                user.clone(),
+                current_timestamp,
+                format!(
+                    "User '{}' has stayed below the rate limit ({} attempts per {} seconds) \
+                     since {}.",
+                    user, self.max_user_attempts, self.window_seconds, last
+                ),
+            )
+            .with_detected_ip(ip);
+            reports.push(self.with_parameters(report, self.max_user_attempts, None));
+        }
+
+        let cleared_ips: Vec<(String, (i64, String))> = self
+            .exceeded_ips
+            .iter()
+            .filter(|(_, (last, _))| *last <= cutoff)
+            .map(|(ip, data)| (ip.clone(), data.clone()))
+            .collect();
+        for (ip, (last, user)) in cleared_ips {
+            self.exceeded_ips.remove(&ip);
+            let report = AnomalyReport::new(
+                3,
+                CLEARED_RULE_NAME,
+                user,
+                current_timestamp,
+                format!(
+                    "IP {} has stayed below the rate limit ({} attempts per {} seconds) \
+                     since {}.",
+                    ip, self.max_ip_attempts, self.window_seconds, last
+                ),
+            )
+            .with_detected_ip(ip.clone());
+            reports.push(self.with_parameters(report, self.max_ip_attempts, None));
+        }
+
+        let cleared_subnets: Vec<(String, (i64, String))> = self
+            .exceeded_subnets
+            .iter()
+            .filter(|(_, (last, _))| *last <= cutoff)
+            .map(|(subnet, data)| (subnet.clone(), data.clone()))
+            .collect();
+        let max_subnet_attempts = self.subnet_limit.map_or(0, |(_, max)| max);
+        for (subnet, (last, user)) in cleared_subnets {
+            self.exceeded_subnets.remove(&subnet);
+            let report = AnomalyReport::new(
+                3,
+                CLEARED_RULE_NAME,
+                user,
+                current_timestamp,
+                format!(
+                    "Subnet {} has stayed below the rate limit ({} attempts per {} seconds) \
+                     since {}.",
+                    subnet, max_subnet_attempts, self.window_seconds, last
+                ),
+            )
+            .with_metadata(SUBNET_METADATA_KEY, subnet.clone());
+            reports.push(self.with_parameters(report, max_subnet_attempts, Some(&subnet)));
         }
 
         reports
     }
 
+    /// Attach the limits a report was raised under, if enabled
+    fn with_parameters(&self, report: AnomalyReport, max_attempts: usize, subnet: Option<&str>) -> AnomalyReport {
+        if !self.include_parameters {
+            return report;
+        }
+        let mut report = report
+            .with_metadata("window_seconds", self.window_seconds.to_string())
+            .with_metadata("max_attempts", max_attempts.to_string());
+        if let Some((_, prefix)) = subnet.and_then(|subnet| subnet.rsplit_once('/')) {
+            report = report.with_metadata("subnet_prefix", prefix);
+        }
+        if !self.event_weights.is_empty() {
+            let mut weights: Vec<String> = self
+                .event_weights
+                .iter()
+                .map(|(event_type, weight)| format!("{}={}", event_type, weight))
+                .collect();
+            weights.sort();
+            report = report.with_metadata("event_weights", weights.join(","));
+        }
+        report
+    }
+
     /// Get current attempt count for a user (checks both cache and persistence)
-    fn get_user_attempt_count_internal(&self, user: &str, current_timestamp: i64) -> usize {
+    fn get_user_attempt_count_internal(&self, user: &str, current_timestamp: i64, use_store: bool) -> usize {
         let window_start = current_timestamp - self.window_seconds;
 
         // Try persistence first for accurate count
-        if let Some(ref store) = self.store {
-            if let Ok(count) = store.get_user_attempt_count(user, window_start) {
-                return count;
+        if let Some(store) = self.store.as_ref().filter(|_| use_store) {
+            match store.get_user_attempt_count(user, window_start) {
+                Ok(count) => return count,
+                Err(e) => self.store_health.record("count user attempts", &e),
             }
         }
 
@@ -185,17 +721,18 @@ impl LoginRateLimiter {
     }
 
     /// Get current attempt count for an IP (checks both cache and persistence)
-    fn get_ip_attempt_count_internal(&self, ip: &str, window_start: i64) -> usize {
+    fn get_ip_attempt_count_internal(&self, ip: &IpAddr, window_start: i64, use_store: bool) -> usize {
         // Try persistence first for accurate count
-        if let Some(ref store) = self.store {
-            if let Ok(count) = store.get_ip_attempt_count(ip, window_start) {
-                return count;
+        if let Some(store) = self.store.as_ref().filter(|_| use_store) {
+            match store.get_ip_attempt_count(ip, window_start) {
+                Ok(count) => return count,
+                Err(e) => self.store_health.record("count IP attempts", &e),
             }
         }
 
         // Fall back to in-memory cache
         self.per_ip_attempts
-            .get(ip)
+            .get(&ip.to_string())
             .map(|e| e.count())
             .unwrap_or(0)
     }
@@ -209,9 +746,11 @@ impl LoginRateLimiter {
     }
 
     /// Get current attempt count for an IP (public interface)
-    pub fn get_ip_attempt_count(&self, ip: &str) -> usize {
+    ///
+    /// Any spelling of the address finds the same count.
+    pub fn get_ip_attempt_count(&self, ip: &IpAddr) -> usize {
         self.per_ip_attempts
-            .get(ip)
+            .get(&ip.to_string())
             .map(|e| e.count())
             .unwrap_or(0)
     }
@@ -233,6 +772,10 @@ impl LoginRateLimiter {
     pub fn clear_all(&mut self) {
         self.per_user_attempts.clear();
         self.per_ip_attempts.clear();
+        self.per_subnet_attempts.clear();
+        self.exceeded_users.clear();
+        self.exceeded_ips.clear();
+        self.exceeded_subnets.clear();
     }
 
     /// Prune stale entries older than the window
@@ -240,13 +783,18 @@ impl LoginRateLimiter {
         let cutoff = current_timestamp - self.window_seconds;
 
         self.per_user_attempts.retain(|_, entry| {
-            entry.timestamps.retain(|&t| t > cutoff);
-            !entry.timestamps.is_empty()
+            entry.attempts.retain(|&(t, _)| t > cutoff);
+            !entry.attempts.is_empty()
         });
 
         self.per_ip_attempts.retain(|_, entry| {
-            entry.timestamps.retain(|&t| t > cutoff);
-            !entry.timestamps.is_empty()
+            entry.attempts.retain(|&(t, _)| t > cutoff);
+            !entry.attempts.is_empty()
+        });
+
+        self.per_subnet_attempts.retain(|_, entry| {
+            entry.attempts.retain(|&(t, _)| t > cutoff);
+            !entry.attempts.is_empty()
         });
     }
 }
@@ -260,6 +808,7 @@ impl Default for LoginRateLimiter {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::input::classify::{SSH_FAILED_CONNECTION_CLOSED, SSH_FAILED_MAX_AUTH, SSH_FAILED_PASSWORD};
     use std::net::IpAddr;
     use std::str::FromStr;
 
@@ -269,6 +818,7 @@ mod tests {
             user: user.to_string(),
             ip_address: IpAddr::from_str(ip).unwrap(),
             event_type: "LOGIN".to_string(),
+            host: None,
         }
     }
 
@@ -293,13 +843,25 @@ mod tests {
             let event = create_event("attacker", 1700000000 + i, "1.1.1.1");
             let reports = limiter.check_rate_limit(&event);
 
-            if i >= 3 {
+            if i >= 4 {
                 assert!(!reports.is_empty(), "Should trigger after threshold");
                 assert!(reports[0].rule_name.contains("User Rate"));
             }
         }
     }
 
+    #[test]
+    fn test_zero_max_disables_limit() {
+        let mut limiter = LoginRateLimiter::with_config(300, 0, 3);
+
+        // No user report however many attempts, while the IP limit still applies
+        for i in 0..5 {
+            let reports = limiter.check_rate_limit(&create_event("alice", 1700000000 + i, "1.1.1.1"));
+            assert!(reports.iter().all(|r| !r.rule_name.contains("User Rate")));
+            assert_eq!(reports.iter().any(|r| r.rule_name.contains("IP Rate")), i >= 4);
+        }
+    }
+
     #[test]
     fn test_ip_rate_exceeded() {
         let mut limiter = LoginRateLimiter::with_config(300, 100, 3);
@@ -309,13 +871,255 @@ mod tests {
             let event = create_event(&format!("user{}", i), 1700000000 + i as i64, "10.0.0.1");
             let reports = limiter.check_rate_limit(&event);
 
-            if i >= 3 {
+            if i >= 4 {
                 assert!(!reports.is_empty(), "Should trigger after IP threshold");
                 assert!(reports.iter().any(|r| r.rule_name.contains("IP Rate")));
             }
         }
     }
 
+    #[test]
+    fn test_ipv6_spellings_share_one_key() {
+        let store: Arc<dyn StateStore> = Arc::new(crate::persistence::SqliteStateStore::in_memory().unwrap());
+        for mut limiter in [
+            LoginRateLimiter::with_config(300, 100, 3),
+            LoginRateLimiter::with_persistence(300, 100, 3, store),
+        ] {
+            let spellings = ["2001:db8::1", "2001:0db8:0000:0000:0000:0000:0000:0001", "2001:DB8:0::1"];
+            let mut reports = Vec::new();
+            for i in 0..5 {
+                let event = create_event(&format!("user{}", i), 1700000000 + i as i64, spellings[i % 3]);
+                reports.extend(limiter.check_rate_limit(&event));
+            }
+            assert!(reports.iter().any(|r| r.rule_name == "IP Rate Limit Exceeded" && r.detected_ip == "2001:db8::1"));
+            for spelling in spellings {
+                assert_eq!(limiter.get_ip_attempt_count(&IpAddr::from_str(spelling).unwrap()), 5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_subnet_rate_exceeded() {
+        let mut limiter = LoginRateLimiter::with_config(300, 100, 5).with_subnet_limit(Some(24), Some(20));
+
+        // One attempt each from 30 IPs in the same /24: no IP is over its limit
+        let mut reports = Vec::new();
+        for i in 0..30 {
+            let event = create_event(&format!("user{}", i), 1700000000 + i, &format!("203.0.113.{}", i + 1));
+            reports.extend(limiter.check_rate_limit(&event));
+        }
+
+        assert!(reports.iter().all(|r| r.rule_name == "Subnet Rate Limit Exceeded"));
+        assert_eq!(reports.len(), 9);
+        assert_eq!(reports[0].metadata[SUBNET_METADATA_KEY], "203.0.113.0/24");
+        assert!(reports[0].description.contains("21 login attempts"));
+
+        // A neighbouring network is counted separately
+        assert!(limiter.check_rate_limit(&create_event("user0", 1700000100, "203.0.114.1")).is_empty());
+    }
+
+    #[test]
+    fn test_subnet_limit_boundary_matches_ip_limit() {
+        let mut limiter = LoginRateLimiter::with_config(300, 100, 3).with_subnet_limit(Some(32), Some(3));
+
+        // A /32 subnet is a single IP, so both limits trip on the same attempt
+        for i in 0..6 {
+            let reports = limiter.check_rate_limit(&create_event(&format!("user{}", i), 1700000000 + i, "203.0.113.5"));
+            let ip = reports.iter().any(|r| r.rule_name == "IP Rate Limit Exceeded");
+            let subnet = reports.iter().any(|r| r.rule_name == "Subnet Rate Limit Exceeded");
+            assert_eq!(ip, i >= 4);
+            assert_eq!(subnet, ip, "attempt {}", i + 1);
+        }
+    }
+
+    #[test]
+    fn test_subnet_of() {
+        let ip = IpAddr::from_str("198.51.100.77").unwrap();
+        assert_eq!(subnet_of(&ip, 24, 64), "198.51.100.0/24");
+        assert_eq!(subnet_of(&ip, 16, 64), "198.51.0.0/16");
+        assert_eq!(subnet_of(&ip, 32, 64), "198.51.100.77/32");
+        assert_eq!(subnet_of(&ip, 0, 64), "0.0.0.0/0");
+
+        let ip = IpAddr::from_str("2001:db8:1:2:aaaa::1").unwrap();
+        assert_eq!(subnet_of(&ip, 24, 64), "2001:db8:1:2::/64");
+        assert_eq!(subnet_of(&ip, 24, 48), "2001:db8:1::/48");
+        assert_eq!(subnet_of(&ip, 24, 128), "2001:db8:1:2:aaaa::1/128");
+    }
+
+    #[test]
+    fn test_ipv6_subnet_rate_exceeded() {
+        let mut limiter = LoginRateLimiter::with_config(300, 100, 5).with_subnet_limit(Some(24), Some(20));
+
+        // An attacker rotating through addresses in one /64
+        let mut reports = Vec::new();
+        for i in 0..25 {
+            let event = create_event("root", 1700000000 + i, &format!("2001:db8:0:7::{:x}", i + 1));
+            reports.extend(limiter.check_rate_limit(&event));
+        }
+
+        let subnet_reports: Vec<_> = reports.iter().filter(|r| r.rule_name == "Subnet Rate Limit Exceeded").collect();
+        assert_eq!(subnet_reports.len(), 4);
+        assert_eq!(subnet_reports[0].metadata[SUBNET_METADATA_KEY], "2001:db8:0:7::/64");
+
+        // The next /64 is another network
+        assert!(limiter.check_ip_rate_limit(&create_event("root", 1700000100, "2001:db8:0:8::1")).is_empty());
+    }
+
+    #[test]
+    fn test_subnet_resolve() {
+        let mut limiter = LoginRateLimiter::with_config(60, 100, 100)
+            .with_subnet_limit(Some(24), Some(3))
+            .with_alert_on_resolve(true);
+        for i in 0..5 {
+            limiter.check_rate_limit(&create_event(&format!("user{}", i), 1000 + i, &format!("203.0.113.{}", i + 1)));
+        }
+
+        assert!(limiter.check_resolved(1030).is_empty());
+        let resolved = limiter.check_resolved(1100);
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].rule_name, CLEARED_RULE_NAME);
+        assert_eq!(resolved[0].metadata[SUBNET_METADATA_KEY], "203.0.113.0/24");
+        assert!(limiter.check_resolved(1200).is_empty());
+    }
+
+    #[test]
+    fn test_exceeded_and_burst_state_bounded() {
+        let mut limiter = LoginRateLimiter::with_config(60, 1, 100)
+            .with_alert_on_resolve(true)
+            .with_merge_window(Some(600), None)
+            .with_max_tracked(Some(2));
+        // A spray over many accounts, each exceeding its limit once
+        for i in 0..10 {
+            let user = format!("user{}", i);
+            for _ in 0..3 {
+                limiter.check_rate_limit(&create_event(&user, 1000 + i, "203.0.113.5"));
+            }
+        }
+
+        // Only the two most recent users are still tracked
+        assert_eq!(limiter.check_resolved(2000).len(), 2);
+        assert_eq!(limiter.drain_bursts().len(), 2);
+    }
+
+    #[test]
+    fn test_ip_only_check_skips_user_tracking() {
+        let mut limiter = LoginRateLimiter::with_config(300, 2, 3);
+
+        let mut triggered = Vec::new();
+        for i in 0..6 {
+            let event = create_event("unknown", 1700000000 + i, "10.0.0.1");
+            triggered.extend(limiter.check_ip_rate_limit(&event));
+        }
+
+        assert_eq!(limiter.get_user_attempt_count("unknown"), 0);
+        assert!(triggered.iter().all(|r| r.rule_name == "IP Rate Limit Exceeded"));
+        assert_eq!(triggered.len(), 2);
+    }
+
+    #[test]
+    fn test_event_weights() {
+        let weights = HashMap::from([
+            (SSH_FAILED_MAX_AUTH.to_string(), 3),
+            (SSH_FAILED_CONNECTION_CLOSED.to_string(), 0),
+        ]);
+        let mut limiter = LoginRateLimiter::with_config(300, 4, 100).with_event_weights(weights);
+
+        let mut event = create_event("root", 1700000000, "203.0.113.5");
+        event.event_type = SSH_FAILED_CONNECTION_CLOSED.to_string();
+        assert!(limiter.check_rate_limit(&event).is_empty());
+        assert_eq!(limiter.get_user_attempt_count("root"), 0);
+
+        event.event_type = SSH_FAILED_PASSWORD.to_string();
+        assert!(limiter.check_rate_limit(&event).is_empty());
+
+        // A max-auth line counts as three attempts
+        event.event_type = SSH_FAILED_MAX_AUTH.to_string();
+        assert!(limiter.check_rate_limit(&event).is_empty());
+        assert_eq!(limiter.get_user_attempt_count("root"), 4);
+        assert!(limiter.check_rate_limit(&event).is_empty());
+        assert_eq!(limiter.get_user_attempt_count("root"), 7);
+        let reports = limiter.check_rate_limit(&event);
+        assert_eq!(reports[0].rule_name, "User Rate Limit Exceeded");
+    }
+
+    #[test]
+    fn test_weighted_event_recorded_once() {
+        let store = Arc::new(crate::persistence::SqliteStateStore::in_memory().unwrap());
+        let weights = HashMap::from([("LOGIN".to_string(), 1000)]);
+        let mut limiter = LoginRateLimiter::with_persistence(300, 5000, 5000, store.clone()).with_event_weights(weights);
+
+        limiter.check_rate_limit(&create_event("alice", 1700000000, "203.0.113.5"));
+        limiter.check_rate_limit(&create_event("alice", 1700000001, "203.0.113.5"));
+
+        // One row and one window entry per event, each carrying its weight
+        assert_eq!(store.get_user_attempts_in_window("alice", 0).unwrap().len(), 2);
+        assert_eq!(store.get_user_attempt_count("alice", 0).unwrap(), 2000);
+        assert_eq!(limiter.per_user_attempts.get("alice").unwrap().attempts.len(), 2);
+        assert_eq!(limiter.get_user_attempt_count("alice"), 2000);
+    }
+
+    #[test]
+    fn test_web_requests_count_only_when_rejected() {
+        use crate::input::classify::HTTP_AUTH_FAILED;
+
+        let mut limiter = LoginRateLimiter::with_config(300, 100, 5);
+        let mut event = create_event("alice", 1700000000, "203.0.113.5");
+
+        // One page load with its assets
+        for event_type in [HTTP_REQUEST, HTTP_LOGIN] {
+            event.event_type = event_type.to_string();
+            for _ in 0..20 {
+                assert!(limiter.check_rate_limit(&event).is_empty());
+            }
+        }
+        assert_eq!(limiter.get_user_attempt_count("alice"), 0);
+
+        event.event_type = HTTP_AUTH_FAILED.to_string();
+        let reports: Vec<_> = (0..7).flat_map(|_| limiter.check_rate_limit(&event)).collect();
+        assert_eq!(reports[0].rule_name, "IP Rate Limit Exceeded");
+    }
+
+    #[test]
+    fn test_burst_merged_into_one_report() {
+        let mut limiter = LoginRateLimiter::with_config(300, 4, 100).with_merge_window(Some(60), None);
+
+        let mut reports = Vec::new();
+        for i in 0..20 {
+            let ip = format!("10.0.0.{}", i % 3);
+            reports.extend(limiter.check_rate_limit(&create_event("alice", 1700000000 + i, &ip)));
+        }
+        assert!(reports.is_empty(), "Burst should be held until the window closes");
+
+        // The window closes 60s after the first over-limit attempt
+        reports.extend(limiter.flush_bursts(1700000005 + 59));
+        assert!(reports.is_empty());
+        reports.extend(limiter.flush_bursts(1700000005 + 60));
+
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(report.rule_name, "User Rate Limit Exceeded");
+        assert_eq!(report.metadata[BURST_FIRST_METADATA_KEY], "1700000005");
+        assert_eq!(report.metadata[BURST_LAST_METADATA_KEY], "1700000019");
+        assert_eq!(report.metadata[BURST_COUNT_METADATA_KEY], "15");
+        assert_eq!(report.metadata[BURST_IPS_METADATA_KEY], "10.0.0.0,10.0.0.1,10.0.0.2");
+        // Severity reflects the peak of the burst (19 earlier attempts vs 4 allowed)
+        assert_eq!(report.severity, 9);
+    }
+
+    #[test]
+    fn test_burst_emitted_at_max_count() {
+        let mut limiter = LoginRateLimiter::with_config(300, 1, 100).with_merge_window(Some(3600), Some(5));
+
+        let mut reports = Vec::new();
+        for i in 0..13 {
+            reports.extend(limiter.check_rate_limit(&create_event("bob", 1700000000 + i, "10.0.0.1")));
+        }
+        // 11 over-limit attempts: two full bursts of 5, one still open
+        assert_eq!(reports.len(), 2);
+        assert!(reports.iter().all(|r| r.metadata[BURST_COUNT_METADATA_KEY] == "5"));
+        assert_eq!(limiter.drain_bursts()[0].metadata[BURST_COUNT_METADATA_KEY], "1");
+    }
+
     #[test]
     fn test_window_expiry() {
         let mut limiter = LoginRateLimiter::with_config(60, 3, 100);
@@ -340,14 +1144,14 @@ mod tests {
     fn test_both_limits_exceeded() {
         let mut limiter = LoginRateLimiter::with_config(300, 2, 2);
 
-        // First two logins from same user and IP
-        for i in 0..2 {
+        // First three logins from same user and IP
+        for i in 0..3 {
             let event = create_event("target", 1700000000 + i, "5.5.5.5");
             limiter.check_rate_limit(&event);
         }
 
-        // Third login triggers both limits
-        let event = create_event("target", 1700000002, "5.5.5.5");
+        // Fourth login triggers both limits
+        let event = create_event("target", 1700000003, "5.5.5.5");
         let reports = limiter.check_rate_limit(&event);
 
         assert_eq!(reports.len(), 2, "Should trigger both user and IP limits");
@@ -381,6 +1185,77 @@ mod tests {
         assert_eq!(limiter.get_user_attempt_count("user1"), 0);
     }
 
+    #[test]
+    fn test_alert_on_resolve() {
+        let mut limiter = LoginRateLimiter::with_config(60, 2, 100).with_alert_on_resolve(true);
+
+        // Exceed the user limit
+        let mut triggered = Vec::new();
+        for i in 0..4 {
+            triggered.extend(limiter.check_rate_limit(&create_event("bob", 1000 + i, "1.1.1.1")));
+        }
+        assert!(triggered.iter().any(|r| r.rule_name == "User Rate Limit Exceeded"));
+
+        // Not yet cleared within the window
+        assert!(limiter.check_resolved(1030).is_empty());
+
+        // Later events don't clear it; that's left to the periodic check
+        assert!(limiter.check_rate_limit(&create_event("alice", 1100, "2.2.2.2")).is_empty());
+
+        // A full quiet window later the condition clears exactly once
+        let resolved = limiter.check_resolved(1100);
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].rule_name, "Rate Limit Condition Cleared");
+        assert_eq!(resolved[0].user, "bob");
+        assert!(resolved[0].severity < 7);
+        assert!(limiter.check_resolved(1200).is_empty());
+    }
+
+    #[test]
+    fn test_no_resolve_reports_by_default() {
+        let mut limiter = LoginRateLimiter::with_config(60, 2, 100);
+        for i in 0..3 {
+            limiter.check_rate_limit(&create_event("bob", 1000 + i, "1.1.1.1"));
+        }
+
+        let reports = limiter.check_rate_limit(&create_event("bob", 2000, "1.1.1.1"));
+        assert!(reports.is_empty());
+    }
+
+    #[test]
+    fn test_explain_just_under_limit() {
+        let mut limiter = LoginRateLimiter::with_config(300, 3, 10).with_explain(true);
+
+        let mut reports = Vec::new();
+        for i in 0..3 {
+            reports.extend(limiter.check_rate_limit(&create_event("carol", 1000 + i, "7.7.7.7")));
+        }
+
+        assert!(reports.is_empty());
+        assert_eq!(
+            limiter.last_explanation().unwrap(),
+            "Rate Limit: user 'carol' 2/3 attempts -> not triggered; \
+             IP 7.7.7.7 2/10 attempts -> not triggered (300s window)"
+        );
+    }
+
+    #[test]
+    fn test_max_tracked_entries() {
+        let mut limiter = LoginRateLimiter::with_config(300, 10, 10).with_max_tracked(Some(5));
+
+        // Password spray: many users from many IPs
+        for i in 0..100 {
+            let event = create_event(&format!("user{}", i), 1000 + i, &format!("10.0.0.{}", i));
+            limiter.check_rate_limit(&event);
+        }
+
+        assert_eq!(limiter.per_user_attempts.len(), 5);
+        assert_eq!(limiter.per_ip_attempts.len(), 5);
+        assert_eq!(limiter.get_user_attempt_count("user99"), 1);
+        assert_eq!(limiter.get_ip_attempt_count(&IpAddr::from_str("10.0.0.95").unwrap()), 1);
+        assert_eq!(limiter.get_user_attempt_count("user0"), 0);
+    }
+
     #[test]
     fn test_clear_all() {
         let mut limiter = LoginRateLimiter::with_config(300, 10, 10);
@@ -392,6 +1267,6 @@ mod tests {
 
         assert_eq!(limiter.get_user_attempt_count("user1"), 0);
         assert_eq!(limiter.get_user_attempt_count("user2"), 0);
-        assert_eq!(limiter.get_ip_attempt_count("1.1.1.1"), 0);
+        assert_eq!(limiter.get_ip_attempt_count(&IpAddr::from_str("1.1.1.1").unwrap()), 0);
     }
 }
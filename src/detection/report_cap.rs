@@ -0,0 +1,86 @@
+//! Per-event report cap
+//!
+//! One event can trip several rules at once (IP switch, impossible travel,
+//! off-hours, rate limits), producing a burst of correlated reports for a
+//! single moment. [`cap_reports`] keeps only the most severe ones and
+//! records what was dropped on the top report, so alert volume is bounded
+//! at the source without losing the fact that other rules fired. A cap of
+//! 1 yields a single combined report per event.
+
+use crate::models::AnomalyReport;
+
+/// Metadata key holding how many reports were dropped by the cap
+pub const SUPPRESSED_COUNT_KEY: &str = "suppressed_reports";
+/// Metadata key listing the rules (and severities) of dropped reports
+pub const SUPPRESSED_RULES_KEY: &str = "suppressed_rules";
+
+/// Keep at most `cap` reports, most severe first
+///
+/// Reports of equal severity keep their rule order. Dropped reports are
+/// summarized in the metadata of the most severe kept report. `None`
+/// returns the reports unchanged.
+pub fn cap_reports(mut reports: Vec<AnomalyReport>, cap: Option<usize>) -> Vec<AnomalyReport> {
+    let Some(cap) = cap.map(|cap| cap.max(1)) else {
+        return reports;
+    };
+    if reports.len() <= cap {
+        return reports;
+    }
+
+    reports.sort_by_key(|report| std::cmp::Reverse(report.severity));
+    let suppressed = reports.split_off(cap);
+    let rules = suppressed
+        .iter()
+        .map(|report| format!("{} ({})", report.rule_name, report.severity))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let top = &mut reports[0];
+    top.metadata.insert(SUPPRESSED_COUNT_KEY.to_string(), suppressed.len().to_string());
+    top.metadata.insert(SUPPRESSED_RULES_KEY.to_string(), rules);
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn create_report(rule_name: &str, severity: u8) -> AnomalyReport {
+        AnomalyReport {
+            severity,
+            rule_name: rule_name.to_string(),
+            user: "alice".to_string(),
+            detected_ip: "203.0.113.5".to_string(),
+            trusted_ip: String::new(),
+            timestamp: 1700000000,
+            detected_at: 1700000000,
+            description: "test".to_string(),
+            metadata: BTreeMap::new(),
+            confidence: None,
+        }
+    }
+
+    #[test]
+    fn test_cap_keeps_most_severe_and_summarizes() {
+        let reports = vec![
+            create_report("Off-Hours Login", 5),
+            create_report("Sudden IP Switch", 8),
+            create_report("Rate Limit Exceeded", 7),
+            create_report("Successful Login From Attacking IP", 9),
+        ];
+
+        let capped = cap_reports(reports.clone(), Some(2));
+        assert_eq!(capped.len(), 2);
+        assert_eq!(capped[0].rule_name, "Successful Login From Attacking IP");
+        assert_eq!(capped[1].rule_name, "Sudden IP Switch");
+        assert_eq!(capped[0].metadata[SUPPRESSED_COUNT_KEY], "2");
+        assert_eq!(
+            capped[0].metadata[SUPPRESSED_RULES_KEY],
+            "Rate Limit Exceeded (7), Off-Hours Login (5)"
+        );
+
+        assert_eq!(cap_reports(reports.clone(), None).len(), 4);
+        assert!(cap_reports(reports, Some(4)).iter().all(|r| r.metadata.is_empty()));
+    }
+}
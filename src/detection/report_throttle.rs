@@ -0,0 +1,253 @@
+//! Per-user report throttle
+//!
+//! A chronically noisy user (a roaming laptop, a shared jump account) can
+//! trip one rule after another all day. With a minimum interval set,
+//! [`UserReportThrottle`] lets one report per user through and suppresses
+//! that user's further reports, from any rule, until the interval has
+//! passed. A report more severe than the one let through is still
+//! emitted and restarts the interval, so throttling never hides the
+//! worst finding. Rules keep updating their state either way; only the
+//! output is throttled.
+//!
+//! Reports about an IP rather than the user ([`IP_SCOPED_RULES`]) name
+//! whichever user the triggering event happened to carry, so they are
+//! never throttled: a new attack must not hide behind an unrelated report
+//! for the same user. Intervals run on the pipeline clock, the same one
+//! `prune_stale` is given.
+//!
+//! With persistence, each user's cooldown is also written to the store
+//! and read back for users not in memory, so a restart during an ongoing
+//! attack doesn't let a fresh flood of reports through. Those store calls
+//! block, so async callers go through `run_blocking`, like the rules.
+
+use super::bounded_map::{BoundedMap, DEFAULT_MAX_TRACKED_ENTRIES};
+use crate::models::{AnomalyReport, UNKNOWN_USER};
+use crate::persistence::{StateStore, StoreHealth};
+use std::sync::Arc;
+
+/// Prefix of the throttle's keys in the store's dedup state
+const STORE_KEY_PREFIX: &str = "report_throttle:user:";
+
+/// Rules whose reports are about an IP or subnet, not the user they name
+pub const IP_SCOPED_RULES: &[&str] = &[
+    "IP Rate Limit Exceeded",
+    "Subnet Rate Limit Exceeded",
+    "Successful Login From Attacking IP",
+    "Credential Stuffing Success Cluster",
+];
+
+/// The report that started a user's current interval
+#[derive(Debug, Clone, Copy)]
+struct LastReport {
+    /// Pipeline time the report was let through at
+    timestamp: i64,
+    severity: u8,
+}
+
+/// Lets at most one report per user through per interval
+pub struct UserReportThrottle {
+    interval_seconds: i64,
+    /// Maps user -> report that started their interval
+    last_reports: BoundedMap<String, LastReport>,
+    /// Optional persistence of cooldowns across restarts
+    store: Option<Arc<dyn StateStore>>,
+    /// Counts store errors fallen back from
+    store_health: Arc<StoreHealth>,
+}
+
+impl UserReportThrottle {
+    pub fn new(interval_seconds: i64) -> Self {
+        UserReportThrottle {
+            interval_seconds,
+            last_reports: BoundedMap::new("throttled_users", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            store: None,
+            store_health: Arc::new(StoreHealth::new()),
+        }
+    }
+
+    /// Persist cooldowns so they survive restarts
+    pub fn with_persistence(mut self, store: Arc<dyn StateStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Count store errors, which fall back to in-memory state, in a
+    /// shared counter
+    pub fn with_store_health(mut self, health: Arc<StoreHealth>) -> Self {
+        self.store_health = health;
+        self
+    }
+
+    /// Limit the number of users tracked (None for unbounded)
+    pub fn with_max_tracked(mut self, max_entries: Option<usize>) -> Self {
+        self.last_reports.set_capacity(max_entries);
+        self
+    }
+
+    /// Whether a report should be emitted at pipeline time `now`,
+    /// recording it if so
+    ///
+    /// Reports without a user, or from an IP-scoped rule, are never
+    /// throttled.
+    pub fn allow(&mut self, report: &AnomalyReport, now: i64) -> bool {
+        let unnamed = report.user.is_empty() || report.user == UNKNOWN_USER;
+        if unnamed || IP_SCOPED_RULES.contains(&report.rule_name.as_str()) {
+            return true;
+        }
+        let last = match self.last_reports.get(&report.user) {
+            Some(last) => Some(*last),
+            None => self.load(&report.user),
+        };
+        let allowed = match last {
+            Some(last) => {
+                now >= last.timestamp + self.interval_seconds || report.severity > last.severity
+            }
+            None => true,
+        };
+        if allowed {
+            self.last_reports.insert(
+                report.user.clone(),
+                LastReport {
+                    timestamp: now,
+                    severity: report.severity,
+                },
+            );
+            if let Some(ref store) = self.store {
+                let key = format!("{}{}", STORE_KEY_PREFIX, report.user);
+                if let Err(e) = store.set_dedup_state(&key, now + self.interval_seconds, report.severity) {
+                    self.store_health.record("store report cooldown", &e);
+                }
+            }
+        }
+        allowed
+    }
+
+    /// A user's persisted cooldown, as the report that started it
+    fn load(&self, user: &str) -> Option<LastReport> {
+        let store = self.store.as_ref()?;
+        match store.get_dedup_state(&format!("{}{}", STORE_KEY_PREFIX, user)) {
+            Ok(state) => state.map(|(cooldown_until, severity)| LastReport {
+                timestamp: cooldown_until - self.interval_seconds,
+                severity,
+            }),
+            Err(e) => {
+                self.store_health.record("get report cooldown", &e);
+                None
+            }
+        }
+    }
+
+    /// Forget users whose interval has passed by pipeline time `now`
+    pub fn prune_stale(&mut self, now: i64) {
+        let interval = self.interval_seconds;
+        self.last_reports.retain(|_, last| now < last.timestamp + interval);
+        if let Some(ref store) = self.store {
+            if let Err(e) = store.prune_dedup_state(now) {
+                self.store_health.record("prune report cooldowns", &e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn create_report(user: &str, severity: u8) -> AnomalyReport {
+        AnomalyReport {
+            severity,
+            rule_name: "Test".to_string(),
+            user: user.to_string(),
+            detected_ip: "203.0.113.5".to_string(),
+            trusted_ip: String::new(),
+            timestamp: 1700000000,
+            detected_at: 1700000000,
+            description: "test".to_string(),
+            metadata: BTreeMap::new(),
+            confidence: None,
+        }
+    }
+
+    #[test]
+    fn test_second_report_within_interval_suppressed() {
+        let mut throttle = UserReportThrottle::new(3600);
+        assert!(throttle.allow(&create_report("alice", 6), 1000));
+        assert!(!throttle.allow(&create_report("alice", 6), 1500));
+        assert!(!throttle.allow(&create_report("alice", 3), 4599));
+
+        // Other users, and alice once the interval has passed, aren't throttled
+        assert!(throttle.allow(&create_report("bob", 6), 1500));
+        assert!(throttle.allow(&create_report("alice", 3), 4600));
+    }
+
+    #[test]
+    fn test_more_severe_report_restarts_interval() {
+        let mut throttle = UserReportThrottle::new(3600);
+        assert!(throttle.allow(&create_report("alice", 5), 1000));
+        assert!(throttle.allow(&create_report("alice", 9), 1200));
+        assert!(!throttle.allow(&create_report("alice", 8), 4700));
+        assert!(throttle.allow(&create_report("alice", 8), 4800));
+
+        throttle.prune_stale(100000);
+        assert!(throttle.allow(&create_report("alice", 1), 100000));
+    }
+
+    #[test]
+    fn test_suppression_survives_restart() {
+        let store: Arc<dyn StateStore> = Arc::new(crate::persistence::SqliteStateStore::in_memory().unwrap());
+        let mut throttle = UserReportThrottle::new(3600).with_persistence(store.clone());
+        assert!(throttle.allow(&create_report("alice", 6), 1000));
+        assert!(!throttle.allow(&create_report("alice", 6), 1500));
+
+        // A new instance over the same store still suppresses alice
+        let mut restarted = UserReportThrottle::new(3600).with_persistence(store.clone());
+        assert!(!restarted.allow(&create_report("alice", 6), 2000));
+        assert!(restarted.allow(&create_report("alice", 7), 2100));
+        assert!(restarted.allow(&create_report("bob", 6), 2000));
+
+        // Expired cooldowns are pruned from the store
+        restarted.prune_stale(10000);
+        assert_eq!(store.get_dedup_state("report_throttle:user:alice").unwrap(), None);
+        assert!(UserReportThrottle::new(3600)
+            .with_persistence(store)
+            .allow(&create_report("alice", 1), 10000));
+    }
+
+    #[test]
+    fn test_ip_scoped_and_unknown_user_reports_not_throttled() {
+        let mut throttle = UserReportThrottle::new(3600);
+        assert!(throttle.allow(&create_report("alice", 6), 1000));
+
+        // An attack from an IP is reported even though alice was just reported
+        let ip_report = AnomalyReport {
+            rule_name: "IP Rate Limit Exceeded".to_string(),
+            ..create_report("alice", 5)
+        };
+        assert!(throttle.allow(&ip_report, 1100));
+        assert!(throttle.allow(&ip_report, 1200));
+
+        // Reports for users the parser couldn't name don't share a bucket
+        assert!(throttle.allow(&create_report(UNKNOWN_USER, 5), 1100));
+        assert!(throttle.allow(&create_report(UNKNOWN_USER, 5), 1200));
+
+        assert!(!throttle.allow(&create_report("alice", 6), 1300));
+    }
+
+    #[test]
+    fn test_interval_runs_on_pipeline_clock() {
+        let mut throttle = UserReportThrottle::new(3600);
+        // A report for an old event still starts the interval at `now`
+        let old_event = AnomalyReport {
+            timestamp: 10,
+            ..create_report("alice", 6)
+        };
+        assert!(throttle.allow(&old_event, 50_000));
+
+        // and pruning on the same clock keeps it until the interval passes
+        throttle.prune_stale(50_100);
+        assert!(!throttle.allow(&create_report("alice", 6), 50_200));
+        throttle.prune_stale(53_600);
+        assert!(throttle.allow(&create_report("alice", 6), 53_600));
+    }
+}
@@ -0,0 +1,267 @@
+//! Multi-signal risk correlation
+//!
+//! Some rules fire on signals that are weak on their own: an off-hours
+//! login, a first-seen user, a hosting provider address. Several of them
+//! for the same user in quick succession are far more telling than any
+//! one. [`RiskCorrelator`] collects each user's reports as risk factors
+//! and scores the factors that fall within the correlation window of
+//! each other; once the score is high enough the factors are raised as a
+//! single [`RiskAssessment`] and the user starts over.
+//!
+//! Factors combine like independent probabilities: each severity is read
+//! as a chance out of 10, and the score is the chance that at least one
+//! of them is real. Two severity 5 factors score 7.5, three score 8.75.
+//! A rule that keeps firing is one signal, not several: each rule counts
+//! once, at the highest severity it reported within the window.
+//! Reports that a condition has ended, and reports for users the parser
+//! couldn't name, are not risk factors.
+
+use crate::config::RiskConfig;
+use crate::models::{AnomalyReport, UNKNOWN_USER};
+use super::bounded_map::{BoundedMap, DEFAULT_MAX_TRACKED_ENTRIES};
+use super::context::RETURNED_RULE_NAME;
+use super::rate_limiter::CLEARED_RULE_NAME;
+
+/// Rule name of reports raised from a risk assessment
+pub const COMBINED_RISK_RULE_NAME: &str = "Combined Risk";
+
+/// Factors needed before a score is raised; one report is already a report
+const MIN_FACTORS: usize = 2;
+
+/// Rules whose reports never count as risk factors: combined reports
+/// themselves, and reports that a condition has ended
+const NON_FACTOR_RULES: &[&str] = &[COMBINED_RISK_RULE_NAME, CLEARED_RULE_NAME, RETURNED_RULE_NAME];
+
+/// One report counted towards a user's risk
+#[derive(Debug, Clone, PartialEq)]
+pub struct RiskFactor {
+    pub rule_name: String,
+    pub severity: u8,
+    pub detected_ip: String,
+    pub timestamp: i64,
+}
+
+/// Risk factors for one user that combined past the threshold
+#[derive(Debug, Clone)]
+pub struct RiskAssessment {
+    pub user: String,
+    /// Factors in the order they were reported
+    pub factors: Vec<RiskFactor>,
+    /// Combined score from 0 to 10
+    pub score: f64,
+}
+
+impl RiskAssessment {
+    /// Render the assessment as a report, timed at its latest factor
+    pub fn to_report(&self) -> AnomalyReport {
+        let latest = self.factors.last().expect("assessments have factors");
+        let first = self.factors.first().expect("assessments have factors");
+        let signals: Vec<String> = self
+            .factors
+            .iter()
+            .map(|factor| format!("{} ({})", factor.rule_name, factor.severity))
+            .collect();
+        AnomalyReport::new(
+            (self.score.round() as u8).clamp(1, 10),
+            COMBINED_RISK_RULE_NAME,
+            self.user.clone(),
+            latest.timestamp,
+            format!(
+                "{} signals for user '{}' within {}s combine to a risk score of {:.1}: {}",
+                self.factors.len(),
+                self.user,
+                latest.timestamp - first.timestamp,
+                self.score,
+                signals.join(", ")
+            ),
+        )
+        .with_detected_ip(latest.detected_ip.clone())
+        .with_metadata("risk_score", format!("{:.2}", self.score))
+        .with_metadata("factor_count", self.factors.len().to_string())
+    }
+}
+
+/// Combined score of a set of factors
+fn combined_score(factors: &[RiskFactor]) -> f64 {
+    let benign: f64 = factors
+        .iter()
+        .map(|factor| 1.0 - f64::from(factor.severity.min(10)) / 10.0)
+        .product();
+    10.0 * (1.0 - benign)
+}
+
+/// Accumulates per-user risk factors within a correlation window
+pub struct RiskCorrelator {
+    window_seconds: i64,
+    min_score: f64,
+    /// Maps user -> factors within the window, one per rule, oldest first
+    factors: BoundedMap<String, Vec<RiskFactor>>,
+}
+
+impl RiskCorrelator {
+    pub fn new(config: &RiskConfig) -> Self {
+        RiskCorrelator {
+            window_seconds: config.correlation_window_seconds,
+            min_score: config.min_score,
+            factors: BoundedMap::new("risk_factors", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+        }
+    }
+
+    /// Limit the number of users tracked (None for unbounded)
+    pub fn with_max_tracked(mut self, max_entries: Option<usize>) -> Self {
+        self.factors.set_capacity(max_entries);
+        self
+    }
+
+    /// Count a report towards its user's risk
+    ///
+    /// Returns an assessment when the factors within the window reach the
+    /// minimum score; the user's factors are then reset. A repeat of a
+    /// rule replaces its earlier factor, keeping the higher severity.
+    /// Reports without a known user, combined reports and reports that a
+    /// condition has cleared are not counted.
+    pub fn add(&mut self, report: &AnomalyReport) -> Option<RiskAssessment> {
+        let unnamed = report.user.is_empty() || report.user == UNKNOWN_USER;
+        if unnamed || NON_FACTOR_RULES.contains(&report.rule_name.as_str()) {
+            return None;
+        }
+        let window_start = report.timestamp - self.window_seconds;
+        let factors = self.factors.get_or_insert_with(report.user.clone(), Vec::new);
+        factors.retain(|factor| factor.timestamp > window_start);
+        let mut severity = report.severity;
+        if let Some(pos) = factors.iter().position(|factor| factor.rule_name == report.rule_name) {
+            severity = severity.max(factors.remove(pos).severity);
+        }
+        factors.push(RiskFactor {
+            rule_name: report.rule_name.clone(),
+            severity,
+            detected_ip: report.detected_ip.clone(),
+            timestamp: report.timestamp,
+        });
+
+        let score = combined_score(factors);
+        if factors.len() < MIN_FACTORS || score < self.min_score {
+            return None;
+        }
+        let factors = self.factors.remove(&report.user).unwrap_or_default();
+        Some(RiskAssessment {
+            user: report.user.clone(),
+            factors,
+            score,
+        })
+    }
+
+    /// Forget factors older than the window
+    pub fn prune_stale(&mut self, now: i64) {
+        let window_start = now - self.window_seconds;
+        self.factors.retain(|_, factors| {
+            factors.retain(|factor| factor.timestamp > window_start);
+            !factors.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn create_report(rule_name: &str, severity: u8, timestamp: i64) -> AnomalyReport {
+        AnomalyReport {
+            severity,
+            rule_name: rule_name.to_string(),
+            user: "alice".to_string(),
+            detected_ip: "203.0.113.5".to_string(),
+            trusted_ip: String::new(),
+            timestamp,
+            detected_at: timestamp,
+            description: "test".to_string(),
+            metadata: BTreeMap::new(),
+            confidence: None,
+        }
+    }
+
+    fn create_correlator() -> RiskCorrelator {
+        RiskCorrelator::new(&RiskConfig {
+            enabled: true,
+            correlation_window_seconds: 900,
+            min_score: 8.0,
+        })
+    }
+
+    #[test]
+    fn test_factors_within_window_combine() {
+        let mut correlator = create_correlator();
+        assert!(correlator.add(&create_report("Off Hours Login", 5, 1000)).is_none());
+        assert!(correlator.add(&create_report("First Seen User", 5, 1300)).is_none());
+        let assessment = correlator.add(&create_report("Login From Hosting Provider", 6, 1600)).unwrap();
+
+        assert_eq!(assessment.factors.len(), 3);
+        assert!((assessment.score - 9.0).abs() < 1e-9);
+        let report = assessment.to_report();
+        assert_eq!(report.rule_name, COMBINED_RISK_RULE_NAME);
+        assert_eq!(report.severity, 9);
+        assert_eq!(report.timestamp, 1600);
+        assert_eq!(report.metadata["factor_count"], "3");
+
+        // Raised factors are reset
+        assert!(correlator.add(&create_report("Off Hours Login", 5, 1700)).is_none());
+    }
+
+    #[test]
+    fn test_factors_outside_window_do_not_combine() {
+        let mut correlator = create_correlator();
+        // Each is within the window of its neighbour, never of both others
+        assert!(correlator.add(&create_report("Off Hours Login", 5, 1000)).is_none());
+        assert!(correlator.add(&create_report("First Seen User", 5, 1800)).is_none());
+        assert!(correlator.add(&create_report("Login From Hosting Provider", 5, 2600)).is_none());
+
+        // A day later nothing from before counts
+        assert!(correlator.add(&create_report("Off Hours Login", 5, 90000)).is_none());
+        correlator.prune_stale(100000);
+        assert!(correlator.add(&create_report("First Seen User", 5, 100000)).is_none());
+    }
+
+    #[test]
+    fn test_users_and_combined_reports_kept_apart() {
+        let mut correlator = create_correlator();
+        let mut bob = create_report("First Seen User", 9, 1010);
+        bob.user = "bob".to_string();
+        assert!(correlator.add(&create_report("Off Hours Login", 9, 1000)).is_none());
+        assert!(correlator.add(&bob).is_none());
+        assert!(correlator.add(&create_report(COMBINED_RISK_RULE_NAME, 9, 1020)).is_none());
+        assert!(correlator.add(&create_report("Hosting", 9, 1030)).is_some());
+    }
+
+    #[test]
+    fn test_repeated_rule_counts_once_at_max_severity() {
+        let mut correlator = create_correlator();
+        // A brute force keeps raising the same rule; that alone never combines
+        for (i, severity) in [7, 9, 6].into_iter().enumerate() {
+            assert!(correlator
+                .add(&create_report("User Rate Limit Exceeded", severity, 1000 + i as i64 * 10))
+                .is_none());
+        }
+
+        // A second rule combines with the highest severity seen
+        let assessment = correlator.add(&create_report("First Seen User", 3, 1100)).unwrap();
+        assert_eq!(assessment.factors.len(), 2);
+        assert_eq!(assessment.factors[0].severity, 9);
+        assert_eq!(assessment.factors[0].timestamp, 1020);
+        assert!((assessment.score - 9.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cleared_and_unknown_user_reports_not_counted() {
+        let mut correlator = create_correlator();
+        assert!(correlator.add(&create_report("Off Hours Login", 7, 1000)).is_none());
+        assert!(correlator.add(&create_report(CLEARED_RULE_NAME, 7, 1010)).is_none());
+        assert!(correlator.add(&create_report(RETURNED_RULE_NAME, 7, 1020)).is_none());
+
+        let mut unknown = create_report("First Seen User", 9, 1030);
+        unknown.user = UNKNOWN_USER.to_string();
+        assert!(correlator.add(&unknown).is_none());
+        assert!(correlator.add(&unknown).is_none());
+        assert!(!correlator.factors.contains_key(&UNKNOWN_USER.to_string()));
+    }
+}
@@ -0,0 +1,231 @@
+//! Successful login from an attacking IP
+//!
+//! An IP that fails against several different accounts and then logs in
+//! successfully to another one is a strong sign of credential stuffing or
+//! lateral movement. Unlike per-user brute force detection, failures are
+//! correlated across users at the IP level.
+
+use std::net::IpAddr;
+use crate::config::{AttackingIpConfig, UserTrackingMode};
+use crate::input::classify::SSH_LOGIN;
+use crate::models::{LogEvent, AnomalyReport};
+use super::bounded_map::{BoundedMap, DEFAULT_MAX_TRACKED_ENTRIES};
+use super::distinct_users::DistinctUsers;
+use super::explain_outcome;
+
+/// Failed usernames listed in a report description
+const MAX_LISTED_USERS: usize = 10;
+
+/// Tracks per-IP failures across users and flags subsequent successes
+pub struct AttackingIpDetector {
+    /// Maps IP -> usernames it recently failed against
+    failures: BoundedMap<IpAddr, DistinctUsers>,
+    window_seconds: i64,
+    min_failed_users: usize,
+    user_tracking: UserTrackingMode,
+    max_users_per_ip: usize,
+    /// Record why each check did or didn't trigger
+    explain: bool,
+    last_explanation: Option<String>,
+}
+
+impl AttackingIpDetector {
+    pub fn new(config: &AttackingIpConfig) -> Self {
+        AttackingIpDetector {
+            failures: BoundedMap::new("ip_failures", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            window_seconds: config.window_seconds,
+            min_failed_users: config.min_failed_users.max(1),
+            user_tracking: config.user_tracking,
+            max_users_per_ip: config.max_users_per_ip,
+            explain: false,
+            last_explanation: None,
+        }
+    }
+
+    /// Limit the number of IPs tracked in memory (None for unbounded)
+    pub fn with_max_tracked(mut self, max_entries: Option<usize>) -> Self {
+        self.failures.set_capacity(max_entries);
+        self
+    }
+
+    /// Record an explanation of each check, readable via `last_explanation()`
+    pub fn with_explain(mut self, enabled: bool) -> Self {
+        self.explain = enabled;
+        self
+    }
+
+    /// Explanation of the most recent check (explain mode only)
+    pub fn last_explanation(&self) -> Option<&str> {
+        self.last_explanation.as_deref()
+    }
+
+    /// Number of IPs with failures currently tracked
+    pub fn tracked_ips(&self) -> usize {
+        self.failures.len()
+    }
+
+    /// Record failures and check successful logins
+    pub fn check_event(&mut self, event: &LogEvent) -> Option<AnomalyReport> {
+        let window_start = event.timestamp - self.window_seconds;
+
+        match event.event_type.as_str() {
+            _ if event.is_failed_login() => {
+                let (mode, cap, window) = (self.user_tracking, self.max_users_per_ip, self.window_seconds);
+                let users = self.failures.get_or_insert_with(event.ip_address, || match mode {
+                    UserTrackingMode::Exact => DistinctUsers::exact(cap),
+                    UserTrackingMode::Approximate => DistinctUsers::approximate(window),
+                });
+                users.prune(window_start);
+                users.record(&event.user, event.timestamp);
+                if self.explain {
+                    self.last_explanation = Some(format!(
+                        "Attacking IP: recorded failure for '{}' from {} -> not triggered",
+                        event.user, event.ip_address
+                    ));
+                }
+                None
+            }
+            SSH_LOGIN => {
+                let users = self.failures.get(&event.ip_address);
+                let failed_count = users.map_or(0, |users| users.count_excluding(&event.user, window_start));
+                let triggered = failed_count >= self.min_failed_users;
+
+                if self.explain {
+                    self.last_explanation = Some(format!(
+                        "Attacking IP: {} failed against {}/{} other users in {}s -> {}",
+                        event.ip_address,
+                        failed_count,
+                        self.min_failed_users,
+                        self.window_seconds,
+                        explain_outcome(triggered)
+                    ));
+                }
+
+                if !triggered {
+                    return None;
+                }
+
+                let names = users.map(|users| users.users_excluding(&event.user, window_start)).unwrap_or_default();
+                let listed = match names.len() {
+                    0 => "usernames not retained".to_string(),
+                    n if n > MAX_LISTED_USERS => {
+                        format!("{}, and {} more", names[..MAX_LISTED_USERS].join(", "), n - MAX_LISTED_USERS)
+                    }
+                    _ => names.join(", "),
+                };
+                let approximate = if self.user_tracking == UserTrackingMode::Approximate { "an estimated " } else { "" };
+                Some(AnomalyReport::new(
+                    9,
+                    "Successful Login From Attacking IP",
+                    event.user.clone(),
+                    event.timestamp,
+                    format!(
+                        "User '{}' logged in from {} after it failed against {}{} other users \
+                         within {}s ({}). Possible credential stuffing or lateral movement.",
+                        event.user,
+                        event.ip_address,
+                        approximate,
+                        failed_count,
+                        self.window_seconds,
+                        listed
+                    ),
+                )
+                .with_detected_ip(event.ip_address.to_string()))
+            }
+            _ => {
+                if self.explain {
+                    self.last_explanation = Some(format!(
+                        "Attacking IP: event type {} not tracked -> not triggered",
+                        event.event_type
+                    ));
+                }
+                None
+            }
+        }
+    }
+
+    /// Drop failures older than the window
+    pub fn prune_stale(&mut self, now: i64) {
+        let window_start = now - self.window_seconds;
+        self.failures.retain(|_, users| {
+            users.prune(window_start);
+            !users.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn create_event(user: &str, ip: &str, event_type: &str, timestamp: i64) -> LogEvent {
+        LogEvent {
+            timestamp,
+            user: user.to_string(),
+            ip_address: IpAddr::from_str(ip).unwrap(),
+            event_type: event_type.to_string(),
+            host: None,
+        }
+    }
+
+    #[test]
+    fn test_success_after_failures_against_other_users() {
+        let mut detector = AttackingIpDetector::new(&AttackingIpConfig::default());
+        for (i, user) in ["alice", "bob", "carol"].iter().enumerate() {
+            let event = create_event(user, "203.0.113.5", "SSH_FAILED", 1000 + i as i64);
+            assert!(detector.check_event(&event).is_none());
+        }
+
+        let report = detector
+            .check_event(&create_event("dave", "203.0.113.5", "SSH_LOGIN", 1010))
+            .unwrap();
+        assert_eq!(report.rule_name, "Successful Login From Attacking IP");
+        assert_eq!(report.user, "dave");
+        assert!(report.description.contains("alice, bob, carol"));
+
+        // A different IP is unaffected
+        assert!(detector
+            .check_event(&create_event("dave", "198.51.100.1", "SSH_LOGIN", 1011))
+            .is_none());
+    }
+
+    #[test]
+    fn test_spray_tracked_within_bounds() {
+        for mode in [UserTrackingMode::Exact, UserTrackingMode::Approximate] {
+            let config = AttackingIpConfig {
+                user_tracking: mode,
+                max_users_per_ip: 100,
+                ..AttackingIpConfig::default()
+            };
+            let mut detector = AttackingIpDetector::new(&config);
+            for i in 0..20_000 {
+                detector.check_event(&create_event(&format!("user{}", i), "203.0.113.5", "SSH_FAILED", 1000 + i / 10));
+            }
+            let report = detector
+                .check_event(&create_event("dave", "203.0.113.5", "SSH_LOGIN", 3000))
+                .unwrap();
+            match mode {
+                UserTrackingMode::Exact => assert!(report.description.contains("against 100 other users")),
+                UserTrackingMode::Approximate => assert!(report.description.contains("an estimated")),
+            }
+        }
+    }
+
+    #[test]
+    fn test_not_triggered_below_threshold_or_outside_window() {
+        let mut detector = AttackingIpDetector::new(&AttackingIpConfig::default());
+        detector.check_event(&create_event("alice", "203.0.113.5", "SSH_FAILED", 1000));
+        detector.check_event(&create_event("bob", "203.0.113.5", "SSH_FAILED", 1001));
+        // Failures against the user who then succeeds don't count
+        detector.check_event(&create_event("dave", "203.0.113.5", "SSH_FAILED", 1002));
+        assert!(detector
+            .check_event(&create_event("dave", "203.0.113.5", "SSH_LOGIN", 1003))
+            .is_none());
+
+        detector.check_event(&create_event("carol", "203.0.113.5", "SSH_FAILED", 1004));
+        assert!(detector
+            .check_event(&create_event("dave", "203.0.113.5", "SSH_LOGIN", 1004 + 7200))
+            .is_none());
+    }
+}
@@ -0,0 +1,214 @@
+//! First seen user detection
+//!
+//! In some environments any successful login by a username never seen
+//! before is worth a look: a freshly created account, or a guessed
+//! username that worked. This rule raises a low-severity report the first
+//! time a user logs in successfully, and never again for that user.
+//!
+//! Novelty comes from the state store's known users, which are never
+//! pruned, so restarts don't make everyone new again. Without persistence
+//! users are remembered in memory only, up to `max_tracked_entries`; a
+//! user evicted from memory would be reported a second time.
+//!
+//! Users first seen inside the configured suppression window (e.g. a bulk
+//! onboarding) are remembered without a report.
+
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use crate::config::FirstSeenConfig;
+use crate::input::classify::SSH_LOGIN;
+use crate::models::{LogEvent, AnomalyReport};
+use crate::persistence::{StateStore, StoreHealth};
+use super::bounded_map::{BoundedMap, DEFAULT_MAX_TRACKED_ENTRIES};
+use super::explain_outcome;
+
+/// Reports the first successful login of each user
+pub struct FirstSeenDetector {
+    /// Maps user -> first seen timestamp, for users already known
+    known: BoundedMap<String, i64>,
+    /// Optional persistence backend holding every known user
+    store: Option<Arc<dyn StateStore>>,
+    /// Counts store errors fallen back from
+    store_health: Arc<StoreHealth>,
+    severity: u8,
+    suppress_from: Option<DateTime<Utc>>,
+    suppress_until: Option<DateTime<Utc>>,
+    /// Record why each check did or didn't trigger
+    explain: bool,
+    last_explanation: Option<String>,
+}
+
+impl FirstSeenDetector {
+    pub fn new(config: &FirstSeenConfig) -> Self {
+        FirstSeenDetector {
+            known: BoundedMap::new("first_seen_users", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            store: None,
+            store_health: Arc::new(StoreHealth::new()),
+            severity: config.severity,
+            suppress_from: config.suppress_from,
+            suppress_until: config.suppress_until,
+            explain: false,
+            last_explanation: None,
+        }
+    }
+
+    /// Look up and record known users in a persistence backend
+    pub fn with_persistence(mut self, store: Arc<dyn StateStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Limit the number of users cached in memory (None for unbounded)
+    pub fn with_max_tracked(mut self, max_entries: Option<usize>) -> Self {
+        self.known.set_capacity(max_entries);
+        self
+    }
+
+    /// Count store errors, which fall back to in-memory state, in a
+    /// shared counter
+    pub fn with_store_health(mut self, health: Arc<StoreHealth>) -> Self {
+        self.store_health = health;
+        self
+    }
+
+    /// Record an explanation of each check, readable via `last_explanation()`
+    pub fn with_explain(mut self, enabled: bool) -> Self {
+        self.explain = enabled;
+        self
+    }
+
+    /// Explanation of the most recent check (explain mode only)
+    pub fn last_explanation(&self) -> Option<&str> {
+        self.last_explanation.as_deref()
+    }
+
+    /// Whether a timestamp falls inside the suppression window
+    fn suppressed(&self, timestamp: i64) -> bool {
+        if self.suppress_from.is_none() && self.suppress_until.is_none() {
+            return false;
+        }
+        self.suppress_from.is_none_or(|from| timestamp >= from.timestamp())
+            && self.suppress_until.is_none_or(|until| timestamp < until.timestamp())
+    }
+
+    /// Remember a user, returning true if they had never been seen
+    ///
+    /// If the store fails the user is treated as known and not cached, so
+    /// a flaky database can't make every existing user "first seen"; the
+    /// next login asks the store again.
+    fn record(&mut self, event: &LogEvent) -> bool {
+        if self.known.contains_key(&event.user) {
+            return false;
+        }
+        let new = match &self.store {
+            Some(store) => match store.record_user_seen(&event.user, event.timestamp) {
+                Ok(new) => new,
+                Err(e) => {
+                    self.store_health.record("record known user", &e);
+                    return false;
+                }
+            },
+            None => true,
+        };
+        self.known.insert(event.user.clone(), event.timestamp);
+        new
+    }
+
+    /// Check a successful login, reporting it if the user is new
+    pub fn check_login(&mut self, event: &LogEvent) -> Option<AnomalyReport> {
+        if event.event_type != SSH_LOGIN {
+            if self.explain {
+                self.last_explanation = Some(format!(
+                    "First Seen User: event type {} is not a successful login -> not triggered",
+                    event.event_type
+                ));
+            }
+            return None;
+        }
+
+        let new = self.record(event);
+        let suppressed = new && self.suppressed(event.timestamp);
+        if self.explain {
+            self.last_explanation = Some(if suppressed {
+                format!("First Seen User: '{}' is new but inside the suppression window -> not triggered", event.user)
+            } else {
+                format!(
+                    "First Seen User: '{}' {} -> {}",
+                    event.user,
+                    if new { "has never been seen" } else { "is already known" },
+                    explain_outcome(new)
+                )
+            });
+        }
+        if !new || suppressed {
+            return None;
+        }
+
+        Some(AnomalyReport::new(
+            self.severity,
+            "First Seen User",
+            event.user.clone(),
+            event.timestamp,
+            format!(
+                "First successful login by previously unseen user '{}' from {}.",
+                event.user, event.ip_address
+            ),
+        )
+        .with_detected_ip(event.ip_address.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::classify::SSH_FAILED_PASSWORD;
+    use crate::persistence::SqliteStateStore;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    fn create_event(user: &str, event_type: &str, timestamp: i64) -> LogEvent {
+        LogEvent {
+            timestamp,
+            user: user.to_string(),
+            ip_address: IpAddr::from_str("203.0.113.5").unwrap(),
+            event_type: event_type.to_string(),
+            host: None,
+        }
+    }
+
+    #[test]
+    fn test_fires_once_per_user() {
+        let store: Arc<dyn StateStore> = Arc::new(SqliteStateStore::in_memory().unwrap());
+        let mut detector = FirstSeenDetector::new(&FirstSeenConfig::default()).with_persistence(store.clone());
+
+        // Failures don't make a user known
+        assert!(detector.check_login(&create_event("alice", SSH_FAILED_PASSWORD, 1000)).is_none());
+
+        let report = detector.check_login(&create_event("alice", "SSH_LOGIN", 1010)).unwrap();
+        assert_eq!(report.rule_name, "First Seen User");
+        assert_eq!(report.severity, 3);
+        assert!(detector.check_login(&create_event("alice", "SSH_LOGIN", 2000)).is_none());
+
+        // Not even after a restart, since the store remembers the user
+        let mut restarted = FirstSeenDetector::new(&FirstSeenConfig::default()).with_persistence(store);
+        assert!(restarted.check_login(&create_event("alice", "SSH_LOGIN", 3000)).is_none());
+        assert!(restarted.check_login(&create_event("bob", "SSH_LOGIN", 3000)).is_some());
+    }
+
+    #[test]
+    fn test_suppression_window() {
+        let config = FirstSeenConfig {
+            suppress_from: DateTime::from_timestamp(1000, 0),
+            suppress_until: DateTime::from_timestamp(2000, 0),
+            ..FirstSeenConfig::default()
+        };
+        let mut detector = FirstSeenDetector::new(&config);
+
+        // Onboarded inside the window: learned silently, never reported later
+        assert!(detector.check_login(&create_event("alice", "SSH_LOGIN", 1500)).is_none());
+        assert!(detector.check_login(&create_event("alice", "SSH_LOGIN", 2500)).is_none());
+
+        assert!(detector.check_login(&create_event("bob", "SSH_LOGIN", 500)).is_some());
+        assert!(detector.check_login(&create_event("carol", "SSH_LOGIN", 2000)).is_some());
+    }
+}
@@ -1,57 +1,239 @@
-use std::collections::HashMap;
+use std::net::IpAddr;
 use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use crate::models::{LogEvent, AnomalyReport};
-use crate::persistence::StateStore;
+use crate::persistence::{StateStore, StoreHealth};
+use super::bounded_map::{BoundedMap, DEFAULT_MAX_TRACKED_ENTRIES};
+use super::explain_outcome;
+
+/// Locations further apart than this are always recorded, even within
+/// the minimum location interval
+const MIN_LOCATION_CHANGE_KM: f64 = 50.0;
 
 /// Geographic coordinates for IP location
-#[derive(Debug, Clone, Copy)]
+///
+/// Deserialization validates the coordinates, so a corrupt stored row or
+/// a typo in a file fails to load instead of skewing distance math.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawGeoLocation")]
 pub struct GeoLocation {
     pub latitude: f64,
     pub longitude: f64,
 }
 
+/// Coordinates outside latitude -90..=90 or longitude -180..=180
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("({latitude}, {longitude}) is not a valid latitude/longitude")]
+pub struct InvalidCoordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl GeoLocation {
+    /// Create a location, rejecting out-of-range or NaN coordinates
+    pub fn new(latitude: f64, longitude: f64) -> Result<Self, InvalidCoordinates> {
+        if (-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude) {
+            Ok(GeoLocation { latitude, longitude })
+        } else {
+            Err(InvalidCoordinates { latitude, longitude })
+        }
+    }
+}
+
+/// Unvalidated form of [`GeoLocation`] used during deserialization
+#[derive(Deserialize)]
+struct RawGeoLocation {
+    latitude: f64,
+    longitude: f64,
+}
+
+impl TryFrom<RawGeoLocation> for GeoLocation {
+    type Error = InvalidCoordinates;
+
+    fn try_from(raw: RawGeoLocation) -> Result<Self, Self::Error> {
+        GeoLocation::new(raw.latitude, raw.longitude)
+    }
+}
+
+/// A user's last recorded login location
+#[derive(Debug, Clone, Copy)]
+struct LastLocation {
+    timestamp: i64,
+    location: GeoLocation,
+    /// Unknown for locations restored from persistence
+    accuracy_radius_km: Option<u16>,
+    ip: IpAddr,
+}
+
 /// Tracks user login locations and timestamps for velocity analysis
 pub struct GeoVelocityTracker {
-    /// Maps user -> (last_timestamp, last_location) (in-memory cache)
-    user_locations: HashMap<String, (i64, GeoLocation)>,
+    /// Maps user -> last recorded location (in-memory cache)
+    user_locations: BoundedMap<String, LastLocation>,
     /// Maximum plausible travel speed in km/h (default: 900 km/h for commercial flight)
     max_velocity_kmh: f64,
     /// Optional persistence backend
     store: Option<Arc<dyn StateStore>>,
+    /// Counts store errors fallen back from
+    store_health: Arc<StoreHealth>,
+    /// Minimum seconds between recorded locations for a user (0 = no limit)
+    min_location_interval: i64,
+    /// Only flag travel when both lookups are at least this accurate
+    max_accuracy_radius_km: Option<u16>,
+    /// Skip evaluation when the login comes from the last recorded IP
+    skip_same_ip: bool,
+    /// Ignore events other than successful logins
+    successful_logins_only: bool,
+    /// Record why each check did or didn't trigger
+    explain: bool,
+    last_explanation: Option<String>,
 }
 
 impl GeoVelocityTracker {
     pub fn new() -> Self {
         GeoVelocityTracker {
-            user_locations: HashMap::new(),
+            user_locations: BoundedMap::new("user_locations", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
             max_velocity_kmh: 900.0,
             store: None,
+            store_health: Arc::new(StoreHealth::new()),
+            min_location_interval: 0,
+            max_accuracy_radius_km: None,
+            skip_same_ip: true,
+            successful_logins_only: false,
+            explain: false,
+            last_explanation: None,
         }
     }
 
     pub fn with_max_velocity(max_velocity_kmh: f64) -> Self {
         GeoVelocityTracker {
-            user_locations: HashMap::new(),
+            user_locations: BoundedMap::new("user_locations", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
             max_velocity_kmh,
             store: None,
+            store_health: Arc::new(StoreHealth::new()),
+            min_location_interval: 0,
+            max_accuracy_radius_km: None,
+            skip_same_ip: true,
+            successful_logins_only: false,
+            explain: false,
+            last_explanation: None,
         }
     }
 
     /// Create a tracker with persistence support
     pub fn with_persistence(max_velocity_kmh: f64, store: Arc<dyn StateStore>) -> Self {
         GeoVelocityTracker {
-            user_locations: HashMap::new(),
+            user_locations: BoundedMap::new("user_locations", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
             max_velocity_kmh,
             store: Some(store),
+            store_health: Arc::new(StoreHealth::new()),
+            min_location_interval: 0,
+            max_accuracy_radius_km: None,
+            skip_same_ip: true,
+            successful_logins_only: false,
+            explain: false,
+            last_explanation: None,
         }
     }
 
+    /// Limit the number of users tracked in memory (None for unbounded)
+    ///
+    /// When full, the least recently seen user is evicted.
+    pub fn with_max_tracked(mut self, max_entries: Option<usize>) -> Self {
+        self.user_locations.set_capacity(max_entries);
+        self
+    }
+
+    /// Count store errors, which fall back to in-memory state, in a
+    /// shared counter
+    pub fn with_store_health(mut self, health: Arc<StoreHealth>) -> Self {
+        self.store_health = health;
+        self
+    }
+
+    /// Skip recording a new location within `seconds` of the last recorded
+    /// one unless it is more than 50 km away
+    ///
+    /// Double-logged events otherwise add redundant rows to the location
+    /// history and add noise to velocity calculations.
+    pub fn with_min_location_interval(mut self, seconds: i64) -> Self {
+        self.min_location_interval = seconds;
+        self
+    }
+
+    /// Only flag travel when both endpoints' accuracy radius is at most
+    /// `radius_km`
+    ///
+    /// Lookups with a larger or unknown radius (including locations
+    /// restored from persistence) are treated as low confidence and
+    /// suppress the alert.
+    pub fn with_max_accuracy_radius(mut self, radius_km: Option<u16>) -> Self {
+        self.max_accuracy_radius_km = radius_km;
+        self
+    }
+
+    /// Skip velocity evaluation when a login comes from the same IP as the
+    /// user's last recorded location (on by default)
+    ///
+    /// The same address can't have moved, but repeated lookups of it may
+    /// resolve to slightly different coordinates, or different ones after
+    /// a database update, which would otherwise look like travel.
+    pub fn with_skip_same_ip(mut self, enabled: bool) -> Self {
+        self.skip_same_ip = enabled;
+        self
+    }
+
+    /// Only check, and record locations from, successful logins
+    ///
+    /// A failed attempt from far away doesn't mean the user was there, so
+    /// it shouldn't move their baseline location.
+    pub fn with_successful_logins_only(mut self, enabled: bool) -> Self {
+        self.successful_logins_only = enabled;
+        self
+    }
+
+    /// Record an explanation of each check, readable via `last_explanation()`
+    pub fn with_explain(mut self, enabled: bool) -> Self {
+        self.explain = enabled;
+        self
+    }
+
+    /// Explanation of the most recent check (explain mode only)
+    pub fn last_explanation(&self) -> Option<&str> {
+        self.last_explanation.as_deref()
+    }
+
+    /// Number of users currently tracked in memory
+    pub fn tracked_users(&self) -> usize {
+        self.user_locations.len()
+    }
+
     /// Check if the user's travel between logins is physically impossible
     pub fn check_impossible_travel(
         &mut self,
         event: &LogEvent,
         current_location: GeoLocation,
     ) -> Option<AnomalyReport> {
+        self.check_impossible_travel_with_accuracy(event, current_location, None)
+    }
+
+    /// Check travel using a location with a known accuracy radius in km
+    pub fn check_impossible_travel_with_accuracy(
+        &mut self,
+        event: &LogEvent,
+        current_location: GeoLocation,
+        accuracy_radius_km: Option<u16>,
+    ) -> Option<AnomalyReport> {
+        if self.successful_logins_only && !event.is_successful_login() {
+            if self.explain {
+                self.last_explanation = Some(format!(
+                    "Impossible Travel: event type {} is not a successful login -> skipped",
+                    event.event_type
+                ));
+            }
+            return None;
+        }
+
         // First check in-memory cache
         let cached_location = self.user_locations.get(&event.user).copied();
 
@@ -61,14 +243,15 @@ impl GeoVelocityTracker {
             None => {
                 if let Some(ref store) = self.store {
                     match store.get_user_last_location(&event.user) {
-                        Ok(Some((ts, loc))) => {
-                            // Populate cache from persistence
-                            self.user_locations.insert(event.user.clone(), (ts, loc));
-                            Some((ts, loc))
+                        Ok(Some((timestamp, location, ip))) => {
+                            // Populate cache from persistence (accuracy isn't stored)
+                            let last = LastLocation { timestamp, location, accuracy_radius_km: None, ip };
+                            self.user_locations.insert(event.user.clone(), last);
+                            Some(last)
                         }
                         Ok(None) => None,
                         Err(e) => {
-                            log::warn!("Failed to get user location from persistence: {}", e);
+                            self.store_health.record("get user location", &e);
                             None
                         }
                     }
@@ -79,31 +262,91 @@ impl GeoVelocityTracker {
         };
 
         let result = match last_location_data {
-            None => None,
-            Some((last_timestamp, last_location)) => {
+            None => {
+                if self.explain {
+                    self.last_explanation = Some(format!(
+                        "Impossible Travel: no previous location for '{}' -> not triggered",
+                        event.user
+                    ));
+                }
+                None
+            }
+            Some(last) if self.skip_same_ip && last.ip == event.ip_address => {
+                if self.explain {
+                    self.last_explanation = Some(format!(
+                        "Impossible Travel: same IP {} as the previous location -> not triggered",
+                        event.ip_address
+                    ));
+                }
+                None
+            }
+            Some(last)
+                if !self.is_high_confidence(last.accuracy_radius_km)
+                    || !self.is_high_confidence(accuracy_radius_km) =>
+            {
+                if self.explain {
+                    self.last_explanation = Some(format!(
+                        "Impossible Travel: low-confidence location (accuracy {} / {} km, max {} km) -> not triggered",
+                        Self::format_accuracy(last.accuracy_radius_km),
+                        Self::format_accuracy(accuracy_radius_km),
+                        Self::format_accuracy(self.max_accuracy_radius_km)
+                    ));
+                }
+                None
+            }
+            Some(LastLocation {
+                timestamp: last_timestamp,
+                location: last_location,
+                accuracy_radius_km: last_accuracy,
+                ..
+            }) => {
+                let confidence = Self::travel_confidence(
+                    haversine_distance(last_location, current_location),
+                    last_accuracy,
+                    accuracy_radius_km,
+                );
                 let time_diff_hours = (event.timestamp - last_timestamp) as f64 / 3600.0;
 
                 // Avoid division by zero for near-simultaneous logins
                 if time_diff_hours < 0.001 {
-                    return Some(self.create_simultaneous_login_report(
+                    if self.explain {
+                        self.last_explanation = Some(format!(
+                            "Impossible Travel: logins {:.1} km apart within {} second(s) -> triggered",
+                            haversine_distance(last_location, current_location),
+                            event.timestamp - last_timestamp
+                        ));
+                    }
+                    let mut report = self.create_simultaneous_login_report(
                         event,
                         &last_location,
                         &current_location,
-                    ));
+                    );
+                    report.confidence = confidence;
+                    return Some(report);
                 }
 
                 let distance_km = haversine_distance(last_location, current_location);
                 let velocity_kmh = distance_km / time_diff_hours;
+                let triggered = velocity_kmh > self.max_velocity_kmh;
+
+                if self.explain {
+                    self.last_explanation = Some(format!(
+                        "Impossible Travel: {:.1} km in {:.2} h = {:.0} km/h vs max {:.0} km/h -> {}",
+                        distance_km,
+                        time_diff_hours,
+                        velocity_kmh,
+                        self.max_velocity_kmh,
+                        explain_outcome(triggered)
+                    ));
+                }
 
-                if velocity_kmh > self.max_velocity_kmh {
-                    Some(AnomalyReport {
-                        severity: Self::calculate_severity(velocity_kmh, self.max_velocity_kmh),
-                        rule_name: "Impossible Travel Velocity".to_string(),
-                        user: event.user.clone(),
-                        detected_ip: event.ip_address.to_string(),
-                        trusted_ip: String::new(), // N/A for geo-velocity
-                        timestamp: event.timestamp,
-                        description: format!(
+                if triggered {
+                    Some(AnomalyReport::new(
+                        Self::calculate_severity(velocity_kmh, self.max_velocity_kmh),
+                        "Impossible Travel Velocity",
+                        event.user.clone(),
+                        event.timestamp,
+                        format!(
                             "User '{}' traveled {:.1} km in {:.2} hours ({:.0} km/h). \
                              Max plausible speed: {:.0} km/h. Previous location: ({:.4}, {:.4}), \
                              Current location: ({:.4}, {:.4}).",
@@ -117,16 +360,35 @@ impl GeoVelocityTracker {
                             current_location.latitude,
                             current_location.longitude
                         ),
-                    })
+                    )
+                    .with_detected_ip(event.ip_address.to_string())
+                    .with_confidence(confidence))
                 } else {
                     None
                 }
             }
         };
 
+        // Keep the previous record for repeated nearby events
+        if let Some(last) = last_location_data {
+            if event.timestamp - last.timestamp < self.min_location_interval
+                && haversine_distance(last.location, current_location) <= MIN_LOCATION_CHANGE_KM
+            {
+                log::trace!("Skipping location update for '{}' within minimum interval", event.user);
+                return result;
+            }
+        }
+
         // Update both cache and persistence
-        self.user_locations
-            .insert(event.user.clone(), (event.timestamp, current_location));
+        self.user_locations.insert(
+            event.user.clone(),
+            LastLocation {
+                timestamp: event.timestamp,
+                location: current_location,
+                accuracy_radius_km,
+                ip: event.ip_address,
+            },
+        );
 
         if let Some(ref store) = self.store {
             if let Err(e) = store.add_user_location(
@@ -135,7 +397,7 @@ impl GeoVelocityTracker {
                 &current_location,
                 &event.ip_address,
             ) {
-                log::warn!("Failed to persist user location: {}", e);
+                self.store_health.record("store user location", &e);
             }
         }
 
@@ -149,14 +411,12 @@ impl GeoVelocityTracker {
         current_location: &GeoLocation,
     ) -> AnomalyReport {
         let distance_km = haversine_distance(*last_location, *current_location);
-        AnomalyReport {
-            severity: 10,
-            rule_name: "Simultaneous Multi-Location Login".to_string(),
-            user: event.user.clone(),
-            detected_ip: event.ip_address.to_string(),
-            trusted_ip: String::new(),
-            timestamp: event.timestamp,
-            description: format!(
+        AnomalyReport::new(
+            10,
+            "Simultaneous Multi-Location Login",
+            event.user.clone(),
+            event.timestamp,
+            format!(
                 "User '{}' logged in from two locations {:.1} km apart within seconds. \
                  Locations: ({:.4}, {:.4}) and ({:.4}, {:.4}). Likely credential compromise.",
                 event.user,
@@ -166,7 +426,33 @@ impl GeoVelocityTracker {
                 current_location.latitude,
                 current_location.longitude
             ),
+        )
+        .with_detected_ip(event.ip_address.to_string())
+    }
+
+    /// Confidence in a travel report from the endpoints' accuracy radii
+    ///
+    /// The share of the distance not covered by the combined radii: two
+    /// 50 km radii over 1000 km give 0.9, radii as large as the distance
+    /// give 0. Unknown when either radius is.
+    fn travel_confidence(distance_km: f64, last_accuracy: Option<u16>, accuracy: Option<u16>) -> Option<f64> {
+        let uncertainty_km = f64::from(last_accuracy?) + f64::from(accuracy?);
+        if distance_km <= 0.0 {
+            return Some(0.0);
         }
+        Some((1.0 - uncertainty_km / distance_km).clamp(0.0, 1.0))
+    }
+
+    /// Whether a lookup's accuracy radius satisfies the configured maximum
+    fn is_high_confidence(&self, accuracy_radius_km: Option<u16>) -> bool {
+        match self.max_accuracy_radius_km {
+            Some(max) => accuracy_radius_km.is_some_and(|radius| radius <= max),
+            None => true,
+        }
+    }
+
+    fn format_accuracy(radius_km: Option<u16>) -> String {
+        radius_km.map_or_else(|| "unknown".to_string(), |r| r.to_string())
     }
 
     fn calculate_severity(actual_velocity: f64, max_velocity: f64) -> u8 {
@@ -201,7 +487,7 @@ impl Default for GeoVelocityTracker {
 
 /// Calculate the great-circle distance between two points using the Haversine formula
 /// Returns distance in kilometers
-fn haversine_distance(loc1: GeoLocation, loc2: GeoLocation) -> f64 {
+pub(crate) fn haversine_distance(loc1: GeoLocation, loc2: GeoLocation) -> f64 {
     const EARTH_RADIUS_KM: f64 = 6371.0;
 
     let lat1_rad = loc1.latitude.to_radians();
@@ -228,9 +514,26 @@ mod tests {
             user: user.to_string(),
             ip_address: IpAddr::from_str(ip).unwrap(),
             event_type: "LOGIN".to_string(),
+            host: None,
         }
     }
 
+    #[test]
+    fn test_out_of_range_coordinates_rejected() {
+        assert!(GeoLocation::new(40.7128, -74.0060).is_ok());
+        assert!(GeoLocation::new(-90.0, 180.0).is_ok());
+        assert_eq!(
+            GeoLocation::new(200.0, 0.0),
+            Err(InvalidCoordinates { latitude: 200.0, longitude: 0.0 })
+        );
+        assert!(GeoLocation::new(0.0, -180.5).is_err());
+        assert!(GeoLocation::new(f64::NAN, 0.0).is_err());
+
+        let parsed: Result<GeoLocation, _> = serde_json::from_str(r#"{"latitude": 200.0, "longitude": 0.0}"#);
+        let err = parsed.unwrap_err().to_string();
+        assert!(err.contains("not a valid latitude/longitude"), "{}", err);
+    }
+
     #[test]
     fn test_haversine_distance() {
         // New York to Los Angeles: ~3944 km
@@ -255,6 +558,101 @@ mod tests {
         assert!(tracker.check_impossible_travel(&event2, la).is_none());
     }
 
+    #[test]
+    fn test_min_location_interval_skips_repeated_records() {
+        let store: Arc<dyn StateStore> =
+            Arc::new(crate::persistence::SqliteStateStore::in_memory().unwrap());
+        let mut tracker = GeoVelocityTracker::with_persistence(900.0, store.clone())
+            .with_min_location_interval(60);
+        let nyc = GeoLocation { latitude: 40.7128, longitude: -74.0060 };
+
+        for offset in [0, 10, 20] {
+            let event = create_event("bob", 1700000000 + offset, "1.1.1.1");
+            assert!(tracker.check_impossible_travel(&event, nyc).is_none());
+        }
+        let (last_ts, _, _) = store.get_user_last_location("bob").unwrap().unwrap();
+        assert_eq!(last_ts, 1700000000);
+
+        // Once the interval has passed the location is recorded again
+        let event = create_event("bob", 1700000000 + 90, "1.1.1.1");
+        tracker.check_impossible_travel(&event, nyc);
+        let (last_ts, _, _) = store.get_user_last_location("bob").unwrap().unwrap();
+        assert_eq!(last_ts, 1700000090);
+    }
+
+    #[test]
+    fn test_min_location_interval_records_distant_location() {
+        let mut tracker = GeoVelocityTracker::new().with_min_location_interval(60);
+        let nyc = GeoLocation { latitude: 40.7128, longitude: -74.0060 };
+        let boston = GeoLocation { latitude: 42.3601, longitude: -71.0589 };
+
+        tracker.check_impossible_travel(&create_event("bob", 1700000000, "1.1.1.1"), nyc);
+        tracker.check_impossible_travel(&create_event("bob", 1700000030, "2.2.2.2"), boston);
+        assert_eq!(tracker.user_locations.get("bob").unwrap().timestamp, 1700000030);
+    }
+
+    #[test]
+    fn test_low_confidence_endpoint_suppresses_alert() {
+        let mut tracker = GeoVelocityTracker::new().with_max_accuracy_radius(Some(100));
+        let nyc = GeoLocation { latitude: 40.7128, longitude: -74.0060 };
+        let london = GeoLocation { latitude: 51.5074, longitude: -0.1278 };
+
+        let event1 = create_event("alice", 1700000000, "1.1.1.1");
+        let event2 = create_event("alice", 1700000000 + 3600, "2.2.2.2");
+        assert!(tracker.check_impossible_travel_with_accuracy(&event1, nyc, Some(20)).is_none());
+        // London is 1000 km uncertain, so the jump isn't trusted
+        assert!(tracker
+            .check_impossible_travel_with_accuracy(&event2, london, Some(1000))
+            .is_none());
+
+        // Both endpoints precise: flagged
+        let event3 = create_event("alice", 1700000000 + 7200, "3.3.3.3");
+        let event4 = create_event("alice", 1700000000 + 10800, "4.4.4.4");
+        assert!(tracker
+            .check_impossible_travel_with_accuracy(&event3, london, Some(50))
+            .is_none());
+        assert!(tracker
+            .check_impossible_travel_with_accuracy(&event4, nyc, Some(50))
+            .is_some());
+    }
+
+    #[test]
+    fn test_large_accuracy_radius_lowers_confidence() {
+        let nyc = GeoLocation { latitude: 40.7128, longitude: -74.0060 };
+        let london = GeoLocation { latitude: 51.5074, longitude: -0.1278 };
+        let travel = |accuracy: Option<u16>| {
+            let mut tracker = GeoVelocityTracker::new();
+            tracker.check_impossible_travel_with_accuracy(&create_event("alice", 1700000000, "1.1.1.1"), nyc, accuracy);
+            tracker
+                .check_impossible_travel_with_accuracy(&create_event("alice", 1700003600, "2.2.2.2"), london, accuracy)
+                .unwrap()
+                .confidence
+        };
+
+        let precise = travel(Some(10)).unwrap();
+        let coarse = travel(Some(1000)).unwrap();
+        assert!(precise > 0.99);
+        assert!(coarse < 0.7);
+        assert!(coarse < precise);
+        assert_eq!(travel(None), None);
+    }
+
+    #[test]
+    fn test_unknown_accuracy_is_low_confidence() {
+        let mut tracker = GeoVelocityTracker::new().with_max_accuracy_radius(Some(100));
+        let nyc = GeoLocation { latitude: 40.7128, longitude: -74.0060 };
+        let london = GeoLocation { latitude: 51.5074, longitude: -0.1278 };
+
+        tracker.check_impossible_travel(&create_event("alice", 1700000000, "1.1.1.1"), nyc);
+        assert!(tracker
+            .check_impossible_travel_with_accuracy(
+                &create_event("alice", 1700003600, "2.2.2.2"),
+                london,
+                Some(10)
+            )
+            .is_none());
+    }
+
     #[test]
     fn test_impossible_travel() {
         let mut tracker = GeoVelocityTracker::new();
@@ -275,6 +673,69 @@ mod tests {
         assert!(report.description.contains("alice"));
     }
 
+    #[test]
+    fn test_same_ip_skips_evaluation_despite_jitter() {
+        let mut tracker = GeoVelocityTracker::new();
+        let nyc = GeoLocation { latitude: 40.7128, longitude: -74.0060 };
+        // The same address resolved 80 km away on the next lookup
+        let jittered = GeoLocation { latitude: 41.4, longitude: -74.3 };
+
+        tracker.check_impossible_travel(&create_event("alice", 1700000000, "1.1.1.1"), nyc);
+        assert!(tracker
+            .check_impossible_travel(&create_event("alice", 1700000001, "1.1.1.1"), jittered)
+            .is_none());
+        assert!(tracker
+            .check_impossible_travel(&create_event("alice", 1700000060, "1.1.1.1"), nyc)
+            .is_none());
+
+        // Without the short-circuit the jitter reads as a simultaneous login
+        let mut tracker = GeoVelocityTracker::new().with_skip_same_ip(false);
+        tracker.check_impossible_travel(&create_event("alice", 1700000000, "1.1.1.1"), nyc);
+        assert!(tracker
+            .check_impossible_travel(&create_event("alice", 1700000001, "1.1.1.1"), jittered)
+            .is_some());
+    }
+
+    #[test]
+    fn test_failures_ignored_when_successful_logins_only() {
+        let mut tracker = GeoVelocityTracker::new().with_successful_logins_only(true);
+        let nyc = GeoLocation { latitude: 40.7128, longitude: -74.0060 };
+        let tokyo = GeoLocation { latitude: 35.6762, longitude: 139.6503 };
+        let login = |timestamp: i64, ip: &str| LogEvent {
+            event_type: "SSH_LOGIN".to_string(),
+            ..create_event("alice", timestamp, ip)
+        };
+        let failure = |timestamp: i64, ip: &str| LogEvent {
+            event_type: "SSH_FAILED".to_string(),
+            ..create_event("alice", timestamp, ip)
+        };
+
+        tracker.check_impossible_travel(&login(1700000000, "1.1.1.1"), nyc);
+        // An attacker's failed attempt from Tokyo neither alerts nor moves alice
+        assert!(tracker.check_impossible_travel(&failure(1700000600, "2.2.2.2"), tokyo).is_none());
+        assert!(tracker.check_impossible_travel(&login(1700001200, "3.3.3.3"), nyc).is_none());
+        assert!(tracker.check_impossible_travel(&login(1700001800, "2.2.2.2"), tokyo).is_some());
+    }
+
+    #[test]
+    fn test_same_ip_restored_from_persistence() {
+        let store: Arc<dyn StateStore> =
+            Arc::new(crate::persistence::SqliteStateStore::in_memory().unwrap());
+        let nyc = GeoLocation { latitude: 40.7128, longitude: -74.0060 };
+        let london = GeoLocation { latitude: 51.5074, longitude: -0.1278 };
+        GeoVelocityTracker::with_persistence(900.0, store.clone())
+            .check_impossible_travel(&create_event("alice", 1700000000, "1.1.1.1"), nyc);
+
+        // After a restart (e.g. with an updated GeoIP database) the IP moved
+        let mut tracker = GeoVelocityTracker::with_persistence(900.0, store);
+        assert!(tracker
+            .check_impossible_travel(&create_event("alice", 1700003600, "1.1.1.1"), london)
+            .is_none());
+        assert!(tracker
+            .check_impossible_travel(&create_event("alice", 1700003660, "2.2.2.2"), nyc)
+            .is_some());
+    }
+
     #[test]
     fn test_simultaneous_login() {
         let mut tracker = GeoVelocityTracker::new();
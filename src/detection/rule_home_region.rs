@@ -0,0 +1,154 @@
+//! Home region login detection
+//!
+//! Organisations whose users all work from one area can pin a home
+//! location and flag any successful login that geolocates farther away
+//! than the configured radius, without needing a login history.
+
+use crate::config::HomeRegionConfig;
+use crate::input::classify::SSH_LOGIN;
+use crate::models::{LogEvent, AnomalyReport};
+use super::explain_outcome;
+use super::rule_geo_velocity::{haversine_distance, GeoLocation};
+
+/// Flags successful logins resolved outside a radius around home
+pub struct HomeRegionDetector {
+    home: GeoLocation,
+    radius_km: f64,
+}
+
+impl HomeRegionDetector {
+    /// Create a detector, validating the home coordinates and radius
+    pub fn new(config: &HomeRegionConfig) -> Result<Self, String> {
+        let home = GeoLocation::new(config.latitude, config.longitude)
+            .map_err(|e| format!("Home location {}", e))?;
+        if config.radius_km.is_nan() || config.radius_km <= 0.0 {
+            return Err(format!("Home region radius {} km must be positive", config.radius_km));
+        }
+
+        Ok(HomeRegionDetector {
+            home,
+            radius_km: config.radius_km,
+        })
+    }
+
+    /// Distance from the home location in km
+    pub fn distance_from_home(&self, location: GeoLocation) -> f64 {
+        haversine_distance(self.home, location)
+    }
+
+    /// Explain how a login event would be evaluated (explain mode)
+    pub fn explain(&self, event: &LogEvent, location: Option<GeoLocation>) -> String {
+        if event.event_type != SSH_LOGIN {
+            return format!(
+                "Home Region: event type {} is not a successful login -> not triggered",
+                event.event_type
+            );
+        }
+        match location {
+            Some(location) => {
+                let distance = self.distance_from_home(location);
+                format!(
+                    "Home Region: {} is {:.0} km from home vs radius {:.0} km -> {}",
+                    event.ip_address,
+                    distance,
+                    self.radius_km,
+                    explain_outcome(distance > self.radius_km)
+                )
+            }
+            None => format!(
+                "Home Region: no location found for {} -> not triggered",
+                event.ip_address
+            ),
+        }
+    }
+
+    /// Check a login event, returning a report if it resolved outside the home region
+    pub fn check_login(&self, event: &LogEvent, location: GeoLocation) -> Option<AnomalyReport> {
+        if event.event_type != SSH_LOGIN {
+            return None;
+        }
+
+        let distance = self.distance_from_home(location);
+        if distance <= self.radius_km {
+            return None;
+        }
+
+        Some(AnomalyReport::new(
+            6,
+            "Login Outside Home Region",
+            event.user.clone(),
+            event.timestamp,
+            format!(
+                "User '{}' logged in from {} ({:.4}, {:.4}), {:.0} km from home, outside the {:.0} km home region.",
+                event.user, event.ip_address, location.latitude, location.longitude, distance, self.radius_km
+            ),
+        )
+        .with_detected_ip(event.ip_address.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    const BERLIN: GeoLocation = GeoLocation { latitude: 52.5200, longitude: 13.4050 };
+
+    fn create_event(event_type: &str) -> LogEvent {
+        LogEvent {
+            timestamp: 1700000000,
+            user: "alice".to_string(),
+            ip_address: IpAddr::from_str("1.1.1.1").unwrap(),
+            event_type: event_type.to_string(),
+            host: None,
+        }
+    }
+
+    fn create_detector() -> HomeRegionDetector {
+        // Berlin with a 300 km radius
+        let config = HomeRegionConfig {
+            latitude: BERLIN.latitude,
+            longitude: BERLIN.longitude,
+            radius_km: 300.0,
+        };
+        HomeRegionDetector::new(&config).unwrap()
+    }
+
+    #[test]
+    fn test_login_inside_radius() {
+        let detector = create_detector();
+        // Hamburg, ~255 km from Berlin
+        let hamburg = GeoLocation { latitude: 53.5511, longitude: 9.9937 };
+        assert!(detector.check_login(&create_event("SSH_LOGIN"), hamburg).is_none());
+        assert!(detector.check_login(&create_event("SSH_LOGIN"), BERLIN).is_none());
+    }
+
+    #[test]
+    fn test_login_outside_radius() {
+        let detector = create_detector();
+        // Munich, ~505 km from Berlin
+        let munich = GeoLocation { latitude: 48.1351, longitude: 11.5820 };
+        let report = detector.check_login(&create_event("SSH_LOGIN"), munich).unwrap();
+        assert_eq!(report.rule_name, "Login Outside Home Region");
+        assert_eq!(report.severity, 6);
+
+        // Failed attempts from far away are left to the other rules
+        assert!(detector.check_login(&create_event("SSH_FAILED"), munich).is_none());
+    }
+
+    #[test]
+    fn test_invalid_config_rejected() {
+        let config = HomeRegionConfig {
+            latitude: 95.0,
+            ..HomeRegionConfig::default()
+        };
+        assert!(HomeRegionDetector::new(&config).is_err());
+
+        let config = HomeRegionConfig {
+            radius_km: 0.0,
+            ..HomeRegionConfig::default()
+        };
+        assert!(HomeRegionDetector::new(&config).is_err());
+    }
+}
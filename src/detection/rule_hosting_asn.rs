@@ -0,0 +1,207 @@
+//! Hosting provider login detection
+//!
+//! Interactive user logins rarely originate from datacenter networks, so
+//! a successful login from a hosting/VPS ASN is worth flagging.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use crate::config::HostingAsnConfig;
+use crate::geolocation::asn::{AsnInfo, AsnLookup};
+use crate::input::classify::SSH_LOGIN;
+use crate::models::{LogEvent, AnomalyReport};
+use super::explain_outcome;
+
+/// Flags successful logins from ASNs categorized as hosting/datacenter
+pub struct HostingAsnDetector {
+    lookup: Arc<dyn AsnLookup>,
+    /// Flagged autonomous system numbers
+    flagged_asns: HashSet<u32>,
+    /// Lowercased organization substrings that mark an AS as hosting
+    flagged_organizations: Vec<String>,
+}
+
+impl HostingAsnDetector {
+    /// Create a detector from configuration and an ASN lookup backend
+    pub fn new(config: &HostingAsnConfig, lookup: Arc<dyn AsnLookup>) -> Self {
+        HostingAsnDetector {
+            lookup,
+            flagged_asns: config.flagged_asns.iter().copied().collect(),
+            flagged_organizations: config
+                .flagged_organizations
+                .iter()
+                .map(|o| o.to_lowercase())
+                .collect(),
+        }
+    }
+
+    /// Check whether an AS is categorized as hosting/datacenter
+    pub fn is_flagged(&self, asn: &AsnInfo) -> bool {
+        if self.flagged_asns.contains(&asn.number) {
+            return true;
+        }
+        match &asn.organization {
+            Some(org) => {
+                let org = org.to_lowercase();
+                self.flagged_organizations.iter().any(|f| org.contains(f.as_str()))
+            }
+            None => false,
+        }
+    }
+
+    /// Look up the AS of an event's source address
+    pub fn lookup_asn(&self, event: &LogEvent) -> Option<AsnInfo> {
+        self.lookup.lookup_asn(&event.ip_address)
+    }
+
+    /// Explain how a login event would be evaluated (explain mode)
+    pub fn explain(&self, event: &LogEvent) -> String {
+        self.explain_with_asn(event, self.lookup_asn(event))
+    }
+
+    /// Explain the evaluation of a login whose AS is already known
+    pub fn explain_with_asn(&self, event: &LogEvent, asn: Option<AsnInfo>) -> String {
+        if event.event_type != SSH_LOGIN {
+            return format!(
+                "Hosting Provider: event type {} is not a successful login -> not triggered",
+                event.event_type
+            );
+        }
+        match asn {
+            Some(asn) => format!(
+                "Hosting Provider: {} is AS{} ({}), flagged: {} -> {}",
+                event.ip_address,
+                asn.number,
+                asn.organization.as_deref().unwrap_or("unknown organization"),
+                self.is_flagged(&asn),
+                explain_outcome(self.is_flagged(&asn))
+            ),
+            None => format!(
+                "Hosting Provider: no ASN found for {} -> not triggered",
+                event.ip_address
+            ),
+        }
+    }
+
+    /// Check a login event, returning a report if it came from a flagged ASN
+    ///
+    /// Only successful logins are considered; failed attempts from
+    /// datacenters are common background noise.
+    pub fn check_login(&self, event: &LogEvent) -> Option<AnomalyReport> {
+        if event.event_type != SSH_LOGIN {
+            return None;
+        }
+        self.check_login_with_asn(event, self.lookup_asn(event))
+    }
+
+    /// Check a login event whose AS is already known (e.g. enriched)
+    pub fn check_login_with_asn(&self, event: &LogEvent, asn: Option<AsnInfo>) -> Option<AnomalyReport> {
+        if event.event_type != SSH_LOGIN {
+            return None;
+        }
+
+        let asn = asn?;
+        if !self.is_flagged(&asn) {
+            return None;
+        }
+
+        Some(AnomalyReport::new(
+            6,
+            "Login From Hosting Provider",
+            event.user.clone(),
+            event.timestamp,
+            format!(
+                "User '{}' logged in from {} on AS{} ({}), a hosting/datacenter network.",
+                event.user,
+                event.ip_address,
+                asn.number,
+                asn.organization.as_deref().unwrap_or("unknown organization")
+            ),
+        )
+        .with_detected_ip(event.ip_address.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    struct MockAsnLookup;
+
+    impl AsnLookup for MockAsnLookup {
+        fn lookup_asn(&self, ip: &IpAddr) -> Option<AsnInfo> {
+            match ip.to_string().as_str() {
+                "203.0.113.10" => Some(AsnInfo {
+                    number: 14061,
+                    organization: Some("DIGITALOCEAN-ASN".to_string()),
+                }),
+                "198.51.100.20" => Some(AsnInfo {
+                    number: 64500,
+                    organization: Some("Example Cloud Hosting Ltd".to_string()),
+                }),
+                "192.0.2.30" => Some(AsnInfo {
+                    number: 64501,
+                    organization: Some("Residential Broadband".to_string()),
+                }),
+                _ => None,
+            }
+        }
+    }
+
+    fn create_detector() -> HostingAsnDetector {
+        let config = HostingAsnConfig {
+            flagged_asns: vec![14061],
+            flagged_organizations: vec!["Hosting".to_string()],
+            ..HostingAsnConfig::default()
+        };
+        HostingAsnDetector::new(&config, Arc::new(MockAsnLookup))
+    }
+
+    fn create_event(ip: &str, event_type: &str) -> LogEvent {
+        LogEvent {
+            timestamp: 1700000000,
+            user: "alice".to_string(),
+            ip_address: IpAddr::from_str(ip).unwrap(),
+            event_type: event_type.to_string(),
+            host: None,
+        }
+    }
+
+    #[test]
+    fn test_flagged_asn_fires() {
+        let detector = create_detector();
+        let report = detector.check_login(&create_event("203.0.113.10", "SSH_LOGIN"));
+
+        let report = report.expect("Login from flagged ASN should be reported");
+        assert_eq!(report.rule_name, "Login From Hosting Provider");
+        assert!(report.description.contains("AS14061"));
+    }
+
+    #[test]
+    fn test_flagged_organization_fires() {
+        let detector = create_detector();
+        assert!(detector.check_login(&create_event("198.51.100.20", "SSH_LOGIN")).is_some());
+    }
+
+    #[test]
+    fn test_residential_and_unknown_ignored() {
+        let detector = create_detector();
+        assert!(detector.check_login(&create_event("192.0.2.30", "SSH_LOGIN")).is_none());
+        assert!(detector.check_login(&create_event("10.0.0.1", "SSH_LOGIN")).is_none());
+    }
+
+    #[test]
+    fn test_explain() {
+        let detector = create_detector();
+        let explanation = detector.explain(&create_event("192.0.2.30", "SSH_LOGIN"));
+        assert!(explanation.contains("AS64501"));
+        assert!(explanation.ends_with("flagged: false -> not triggered"));
+    }
+
+    #[test]
+    fn test_failed_logins_ignored() {
+        let detector = create_detector();
+        assert!(detector.check_login(&create_event("203.0.113.10", "SSH_FAILED")).is_none());
+    }
+}
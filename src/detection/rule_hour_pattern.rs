@@ -0,0 +1,210 @@
+//! Time-of-day pattern break detection
+//!
+//! Learns a histogram of the UTC hours each user logs in at and flags
+//! logins in hours that are rare for that user. Unlike the off-hours rule
+//! this needs no configured window: a user who always logs in 9-5 is
+//! flagged at 3am, while a night-shift user is not. Users with too little
+//! history are skipped.
+
+use chrono::{TimeZone, Timelike, Utc};
+use crate::config::HourPatternConfig;
+use crate::input::classify::SSH_LOGIN;
+use crate::models::{LogEvent, AnomalyReport};
+use super::bounded_map::{BoundedMap, DEFAULT_MAX_TRACKED_ENTRIES};
+use super::explain_outcome;
+
+/// Severity of a login in an hour the user has never been seen in
+const MAX_SEVERITY: u8 = 7;
+/// Severity of a login in an hour right at the rarity threshold
+const MIN_SEVERITY: u8 = 4;
+
+/// Logins per UTC hour of day
+pub type HourHistogram = [u32; 24];
+
+/// Flags logins in hours that are unusual for the user
+pub struct HourPatternDetector {
+    /// Maps user -> learned login hour histogram
+    histograms: BoundedMap<String, HourHistogram>,
+    min_history: u32,
+    max_hour_share: f64,
+    neighbor_hours: u32,
+    /// Record why each check did or didn't trigger
+    explain: bool,
+    last_explanation: Option<String>,
+}
+
+impl HourPatternDetector {
+    pub fn new(config: &HourPatternConfig) -> Self {
+        HourPatternDetector {
+            histograms: BoundedMap::new("hour_histograms", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            min_history: config.min_history.max(1),
+            max_hour_share: config.max_hour_share,
+            neighbor_hours: config.neighbor_hours.min(11),
+            explain: false,
+            last_explanation: None,
+        }
+    }
+
+    /// Limit the number of users tracked in memory (None for unbounded)
+    pub fn with_max_tracked(mut self, max_entries: Option<usize>) -> Self {
+        self.histograms.set_capacity(max_entries);
+        self
+    }
+
+    /// Record an explanation of each check, readable via `last_explanation()`
+    pub fn with_explain(mut self, enabled: bool) -> Self {
+        self.explain = enabled;
+        self
+    }
+
+    /// Explanation of the most recent check (explain mode only)
+    pub fn last_explanation(&self) -> Option<&str> {
+        self.last_explanation.as_deref()
+    }
+
+    /// Number of users with a learned histogram
+    pub fn tracked_users(&self) -> usize {
+        self.histograms.len()
+    }
+
+    /// Replace a user's histogram, e.g. with one learned elsewhere
+    pub fn seed_histogram(&mut self, user: &str, histogram: HourHistogram) {
+        self.histograms.insert(user.to_string(), histogram);
+    }
+
+    /// A user's learned histogram, if any
+    pub fn histogram(&self, user: &str) -> Option<&HourHistogram> {
+        self.histograms.get(user)
+    }
+
+    /// Share of a user's logins in `hour`, counting `neighbor_hours` on
+    /// either side so logins just outside the usual window aren't flagged
+    fn hour_share(&self, histogram: &HourHistogram, total: u32, hour: u32) -> f64 {
+        let spread = self.neighbor_hours as i32;
+        let count: u32 = (-spread..=spread)
+            .map(|offset| histogram[(hour as i32 + offset).rem_euclid(24) as usize])
+            .sum();
+        f64::from(count) / f64::from(total)
+    }
+
+    /// Severity scaled from the threshold (least unusual) to never seen
+    fn severity(&self, share: f64) -> u8 {
+        if self.max_hour_share <= 0.0 {
+            return MAX_SEVERITY;
+        }
+        let rarity = (1.0 - share / self.max_hour_share).clamp(0.0, 1.0);
+        MIN_SEVERITY + (f64::from(MAX_SEVERITY - MIN_SEVERITY) * rarity).round() as u8
+    }
+
+    /// Check a successful login against the user's histogram, then learn it
+    pub fn check_login(&mut self, event: &LogEvent) -> Option<AnomalyReport> {
+        if event.event_type != SSH_LOGIN {
+            if self.explain {
+                self.last_explanation = Some(format!(
+                    "Hour Pattern: event type {} is not a successful login -> not triggered",
+                    event.event_type
+                ));
+            }
+            return None;
+        }
+        let hour = Utc.timestamp_opt(event.timestamp, 0).single()?.hour();
+
+        let histogram = self.histograms.get(&event.user).copied().unwrap_or([0; 24]);
+        let total: u32 = histogram.iter().sum();
+        let report = if total < self.min_history {
+            if self.explain {
+                self.last_explanation = Some(format!(
+                    "Hour Pattern: '{}' has {}/{} logins of history -> not triggered",
+                    event.user, total, self.min_history
+                ));
+            }
+            None
+        } else {
+            let share = self.hour_share(&histogram, total, hour);
+            let triggered = share <= self.max_hour_share;
+            if self.explain {
+                self.last_explanation = Some(format!(
+                    "Hour Pattern: {:02}:00 UTC has {:.1}% of '{}''s {} logins vs threshold {:.1}% -> {}",
+                    hour,
+                    share * 100.0,
+                    event.user,
+                    total,
+                    self.max_hour_share * 100.0,
+                    explain_outcome(triggered)
+                ));
+            }
+            triggered.then(|| AnomalyReport::new(
+                self.severity(share),
+                "Unusual Login Hour",
+                event.user.clone(),
+                event.timestamp,
+                format!(
+                    "User '{}' logged in at {:02}:00 UTC, an hour holding {:.1}% of their {} previous logins.",
+                    event.user,
+                    hour,
+                    share * 100.0,
+                    total
+                ),
+            )
+            .with_detected_ip(event.ip_address.to_string()))
+        };
+
+        self.histograms.get_or_insert_with(event.user.clone(), || [0; 24])[hour as usize] += 1;
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    /// 2023-11-14 00:00:00 UTC
+    const MIDNIGHT: i64 = 1699920000;
+
+    fn create_login(user: &str, hour: i64) -> LogEvent {
+        LogEvent {
+            timestamp: MIDNIGHT + hour * 3600 + 600,
+            user: user.to_string(),
+            ip_address: IpAddr::from_str("203.0.113.5").unwrap(),
+            event_type: "SSH_LOGIN".to_string(),
+            host: None,
+        }
+    }
+
+    fn nine_to_five() -> HourHistogram {
+        let mut histogram = [0; 24];
+        for count in &mut histogram[9..17] {
+            *count = 10;
+        }
+        histogram
+    }
+
+    #[test]
+    fn test_login_outside_learned_hours_flagged() {
+        let mut detector = HourPatternDetector::new(&HourPatternConfig::default());
+        detector.seed_histogram("alice", nine_to_five());
+
+        let report = detector.check_login(&create_login("alice", 3)).expect("3am login flagged");
+        assert_eq!(report.rule_name, "Unusual Login Hour");
+        assert_eq!(report.severity, MAX_SEVERITY);
+
+        assert!(detector.check_login(&create_login("alice", 14)).is_none());
+        // Adjacent to the usual window
+        assert!(detector.check_login(&create_login("alice", 8)).is_none());
+        assert_eq!(detector.histogram("alice").unwrap()[3], 1);
+    }
+
+    #[test]
+    fn test_insufficient_history_skipped() {
+        let mut detector = HourPatternDetector::new(&HourPatternConfig::default());
+        let mut histogram = [0; 24];
+        histogram[10] = 5;
+        detector.seed_histogram("bob", histogram);
+
+        assert!(detector.check_login(&create_login("bob", 3)).is_none());
+        assert!(detector.check_login(&create_login("carol", 3)).is_none());
+        assert_eq!(detector.histogram("carol").unwrap()[3], 1);
+    }
+}
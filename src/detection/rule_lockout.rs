@@ -0,0 +1,241 @@
+//! Login during lockout detection
+//!
+//! Systems such as pam_faillock or a directory server lock an account
+//! after repeated failures. Once locked, a successful login should be
+//! impossible; one that happens anyway points at a bypass (another auth
+//! path, a stolen session, a tampered PAM stack). This rule follows the
+//! `ACCOUNT_LOCKED` / `ACCOUNT_UNLOCKED` events those systems log and
+//! reports a successful login while the user is locked.
+//!
+//! With persistence the locks are also saved as user lockouts, so they
+//! can be listed on the API's `GET /lockouts`.
+
+use std::sync::Arc;
+use chrono::DateTime;
+use crate::config::LockoutConfig;
+use crate::input::classify::{ACCOUNT_LOCKED, ACCOUNT_UNLOCKED, SSH_LOGIN};
+use crate::models::{LogEvent, AnomalyReport, Lockout, LockoutKind};
+use crate::persistence::{StateStore, StoreHealth};
+use super::bounded_map::{BoundedMap, DEFAULT_MAX_TRACKED_ENTRIES};
+use super::explain_outcome;
+
+/// Reports successful logins to accounts an external system has locked
+pub struct LockoutDetector {
+    /// Maps user -> time their account was locked
+    locked: BoundedMap<String, i64>,
+    /// Optional persistence backend the locks are saved to
+    store: Option<Arc<dyn StateStore>>,
+    /// Counts store errors
+    store_health: Arc<StoreHealth>,
+    severity: u8,
+    lock_duration_seconds: Option<i64>,
+    /// Record why each check did or didn't trigger
+    explain: bool,
+    last_explanation: Option<String>,
+}
+
+impl LockoutDetector {
+    pub fn new(config: &LockoutConfig) -> Self {
+        LockoutDetector {
+            locked: BoundedMap::new("locked_users", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            store: None,
+            store_health: Arc::new(StoreHealth::new()),
+            severity: config.severity,
+            lock_duration_seconds: config.lock_duration_seconds,
+            explain: false,
+            last_explanation: None,
+        }
+    }
+
+    /// Save locks and unlocks as user lockouts in a persistence backend
+    pub fn with_persistence(mut self, store: Arc<dyn StateStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Count store errors in a shared counter
+    pub fn with_store_health(mut self, health: Arc<StoreHealth>) -> Self {
+        self.store_health = health;
+        self
+    }
+
+    /// Limit the number of locked users tracked (None for unbounded)
+    pub fn with_max_tracked(mut self, max_entries: Option<usize>) -> Self {
+        self.locked.set_capacity(max_entries);
+        self
+    }
+
+    /// Record an explanation of each check, readable via `last_explanation()`
+    pub fn with_explain(mut self, enabled: bool) -> Self {
+        self.explain = enabled;
+        self
+    }
+
+    /// Explanation of the most recent check (explain mode only)
+    pub fn last_explanation(&self) -> Option<&str> {
+        self.last_explanation.as_deref()
+    }
+
+    /// When the user's account was locked, if it still is at `timestamp`
+    pub fn locked_since(&self, user: &str, timestamp: i64) -> Option<i64> {
+        let locked_at = *self.locked.get(user)?;
+        let expired = self
+            .lock_duration_seconds
+            .is_some_and(|duration| timestamp >= locked_at + duration);
+        (timestamp >= locked_at && !expired).then_some(locked_at)
+    }
+
+    /// Track lock and unlock events, and check successful logins against them
+    pub fn check_event(&mut self, event: &LogEvent) -> Option<AnomalyReport> {
+        match event.event_type.as_str() {
+            ACCOUNT_LOCKED => {
+                self.locked.insert(event.user.clone(), event.timestamp);
+                let expires_at = self
+                    .lock_duration_seconds
+                    .map_or(i64::MAX, |duration| event.timestamp.saturating_add(duration));
+                self.save_lockout(event, event.timestamp, expires_at);
+                self.explain_with(|| format!("Login During Lockout: '{}' locked -> not triggered", event.user));
+                return None;
+            }
+            ACCOUNT_UNLOCKED => {
+                // The saved lockout (possibly from before a restart) now
+                // expires at the unlock
+                let locked_at = self.locked.remove(&event.user).unwrap_or(event.timestamp);
+                self.save_lockout(event, locked_at, event.timestamp);
+                self.explain_with(|| format!("Login During Lockout: '{}' unlocked -> not triggered", event.user));
+                return None;
+            }
+            SSH_LOGIN => {}
+            other => {
+                self.explain_with(|| {
+                    format!("Login During Lockout: event type {} is not a successful login -> not triggered", other)
+                });
+                return None;
+            }
+        }
+
+        let locked_at = self.locked_since(&event.user, event.timestamp);
+        self.explain_with(|| {
+            format!(
+                "Login During Lockout: '{}' {} -> {}",
+                event.user,
+                if locked_at.is_some() { "is locked" } else { "is not locked" },
+                explain_outcome(locked_at.is_some())
+            )
+        });
+        let locked_at = locked_at?;
+
+        let since = DateTime::from_timestamp(locked_at, 0)
+            .map(|dt| dt.to_rfc3339())
+            .unwrap_or_else(|| locked_at.to_string());
+        let report = AnomalyReport::new(
+            self.severity,
+            "Login During Lockout",
+            event.user.clone(),
+            event.timestamp,
+            format!(
+                "User '{}' logged in successfully from {} while their account has been locked since {}.",
+                event.user, event.ip_address, since
+            ),
+        )
+        .with_detected_ip(event.ip_address.to_string())
+        .with_metadata("locked_at", locked_at.to_string());
+        Some(report)
+    }
+
+    /// Save the user's lockout, replacing any earlier one
+    fn save_lockout(&self, event: &LogEvent, locked_at: i64, expires_at: i64) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        let lockout = Lockout {
+            kind: LockoutKind::User,
+            subject: event.user.clone(),
+            reason: "Account locked by the authentication system".to_string(),
+            locked_at,
+            expires_at,
+        };
+        if let Err(e) = store.add_lockout(&lockout) {
+            self.store_health.record("save lockout", &e);
+        }
+    }
+
+    fn explain_with(&mut self, explanation: impl FnOnce() -> String) {
+        if self.explain {
+            self.last_explanation = Some(explanation());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    fn create_event(user: &str, event_type: &str, timestamp: i64) -> LogEvent {
+        LogEvent {
+            timestamp,
+            user: user.to_string(),
+            ip_address: IpAddr::from_str("203.0.113.5").unwrap(),
+            event_type: event_type.to_string(),
+            host: None,
+        }
+    }
+
+    #[test]
+    fn test_login_while_locked_reported() {
+        let mut detector = LockoutDetector::new(&LockoutConfig::default());
+        assert!(detector.check_event(&create_event("alice", SSH_LOGIN, 900)).is_none());
+
+        assert!(detector.check_event(&create_event("alice", ACCOUNT_LOCKED, 1000)).is_none());
+        let report = detector.check_event(&create_event("alice", SSH_LOGIN, 1010)).unwrap();
+        assert_eq!(report.rule_name, "Login During Lockout");
+        assert_eq!(report.severity, 10);
+        assert_eq!(report.metadata["locked_at"], "1000");
+
+        // Other users aren't affected, and the unlock ends the window
+        assert!(detector.check_event(&create_event("bob", SSH_LOGIN, 1020)).is_none());
+        assert!(detector.check_event(&create_event("alice", ACCOUNT_UNLOCKED, 1100)).is_none());
+        assert!(detector.check_event(&create_event("alice", SSH_LOGIN, 1110)).is_none());
+    }
+
+    #[test]
+    fn test_lock_duration() {
+        let config = LockoutConfig {
+            lock_duration_seconds: Some(600),
+            ..LockoutConfig::default()
+        };
+        let mut detector = LockoutDetector::new(&config);
+        detector.check_event(&create_event("alice", ACCOUNT_LOCKED, 1000));
+
+        // A login that was logged before the lock isn't inside it
+        assert!(detector.check_event(&create_event("alice", SSH_LOGIN, 990)).is_none());
+        assert!(detector.check_event(&create_event("alice", SSH_LOGIN, 1599)).is_some());
+        assert!(detector.check_event(&create_event("alice", SSH_LOGIN, 1600)).is_none());
+    }
+
+    #[test]
+    fn test_locks_saved_as_lockouts() {
+        let store = Arc::new(crate::persistence::SqliteStateStore::in_memory().unwrap());
+        let config = LockoutConfig {
+            lock_duration_seconds: Some(600),
+            ..LockoutConfig::default()
+        };
+        let mut detector = LockoutDetector::new(&config).with_persistence(store.clone());
+
+        detector.check_event(&create_event("alice", ACCOUNT_LOCKED, 1000));
+        detector.check_event(&create_event("bob", ACCOUNT_LOCKED, 1100));
+        let active = store.get_active_lockouts(1200).unwrap();
+        assert_eq!(active.len(), 2);
+        assert_eq!(active[0].kind, LockoutKind::User);
+        assert_eq!(active[0].subject, "alice");
+        assert_eq!((active[0].locked_at, active[0].expires_at), (1000, 1600));
+
+        // An unlock ends the lockout before its duration
+        detector.check_event(&create_event("bob", ACCOUNT_UNLOCKED, 1150));
+        let active = store.get_active_lockouts(1200).unwrap();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].subject, "alice");
+    }
+}
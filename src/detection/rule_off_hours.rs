@@ -0,0 +1,231 @@
+//! Off-hours login detection
+//!
+//! Flags successful logins outside business hours in the user's local
+//! time. The timezone is resolved in priority order: per-user override,
+//! then the geolocated timezone of the source IP, then a global default,
+//! so the rule works without geolocation.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use chrono::{TimeZone, Timelike};
+use chrono_tz::Tz;
+use crate::config::OffHoursConfig;
+use crate::input::classify::SSH_LOGIN;
+use crate::models::{LogEvent, AnomalyReport};
+use super::explain_outcome;
+
+/// Where a login's timezone came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimezoneSource {
+    UserOverride,
+    Geolocation,
+    Default,
+}
+
+impl std::fmt::Display for TimezoneSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimezoneSource::UserOverride => write!(f, "user override"),
+            TimezoneSource::Geolocation => write!(f, "geolocation"),
+            TimezoneSource::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// Flags successful logins outside configured business hours
+pub struct OffHoursDetector {
+    start_hour: u32,
+    end_hour: u32,
+    default_timezone: Tz,
+    user_timezones: HashMap<String, Tz>,
+}
+
+impl OffHoursDetector {
+    /// Create a detector, validating all configured timezones
+    pub fn new(config: &OffHoursConfig) -> Result<Self, String> {
+        if config.start_hour > 23 || config.end_hour > 23 {
+            return Err(format!(
+                "Off-hours window {}-{} must use hours 0-23",
+                config.start_hour, config.end_hour
+            ));
+        }
+
+        let default_timezone = parse_timezone(&config.default_timezone)?;
+        let user_timezones = config
+            .user_timezones
+            .iter()
+            .map(|(user, tz)| Ok((user.clone(), parse_timezone(tz)?)))
+            .collect::<Result<HashMap<_, _>, String>>()?;
+
+        Ok(OffHoursDetector {
+            start_hour: config.start_hour,
+            end_hour: config.end_hour,
+            default_timezone,
+            user_timezones,
+        })
+    }
+
+    /// Resolve the timezone for a user's login
+    ///
+    /// Unrecognised geolocation timezones fall through to the default.
+    pub fn resolve_timezone(&self, user: &str, geo_timezone: Option<&str>) -> (Tz, TimezoneSource) {
+        if let Some(tz) = self.user_timezones.get(user) {
+            return (*tz, TimezoneSource::UserOverride);
+        }
+        if let Some(tz) = geo_timezone.and_then(|name| Tz::from_str(name).ok()) {
+            return (tz, TimezoneSource::Geolocation);
+        }
+        (self.default_timezone, TimezoneSource::Default)
+    }
+
+    /// Whether a local hour falls inside business hours
+    pub fn is_business_hour(&self, hour: u32) -> bool {
+        if self.start_hour <= self.end_hour {
+            hour >= self.start_hour && hour < self.end_hour
+        } else {
+            hour >= self.start_hour || hour < self.end_hour
+        }
+    }
+
+    /// Explain how a login event would be evaluated (explain mode)
+    pub fn explain(&self, event: &LogEvent, geo_timezone: Option<&str>) -> String {
+        if event.event_type != SSH_LOGIN {
+            return format!(
+                "Off Hours: event type {} is not a successful login -> not triggered",
+                event.event_type
+            );
+        }
+        let (tz, source) = self.resolve_timezone(&event.user, geo_timezone);
+        match Self::local_hour(event.timestamp, tz) {
+            Some(hour) => format!(
+                "Off Hours: local hour {} in {} ({}) vs business hours {}-{} -> {}",
+                hour,
+                tz,
+                source,
+                self.start_hour,
+                self.end_hour,
+                explain_outcome(!self.is_business_hour(hour))
+            ),
+            None => "Off Hours: invalid timestamp -> not triggered".to_string(),
+        }
+    }
+
+    /// Check a login event, returning a report if it was outside business hours
+    pub fn check_login(&self, event: &LogEvent, geo_timezone: Option<&str>) -> Option<AnomalyReport> {
+        if event.event_type != SSH_LOGIN {
+            return None;
+        }
+
+        let (tz, source) = self.resolve_timezone(&event.user, geo_timezone);
+        let hour = Self::local_hour(event.timestamp, tz)?;
+        if self.is_business_hour(hour) {
+            return None;
+        }
+
+        Some(AnomalyReport::new(
+            5,
+            "Off-Hours Login",
+            event.user.clone(),
+            event.timestamp,
+            format!(
+                "User '{}' logged in at {:02}:00 local time ({}, from {}), outside business hours {:02}:00-{:02}:00.",
+                event.user, hour, tz, source, self.start_hour, self.end_hour
+            ),
+        )
+        .with_detected_ip(event.ip_address.to_string()))
+    }
+
+    fn local_hour(timestamp: i64, tz: Tz) -> Option<u32> {
+        tz.timestamp_opt(timestamp, 0).single().map(|dt| dt.hour())
+    }
+}
+
+fn parse_timezone(name: &str) -> Result<Tz, String> {
+    Tz::from_str(name).map_err(|_| format!("Unknown timezone: {}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+
+    // 2023-11-14 22:13:20 UTC
+    const TIMESTAMP: i64 = 1700000000;
+
+    fn create_event(user: &str) -> LogEvent {
+        LogEvent {
+            timestamp: TIMESTAMP,
+            user: user.to_string(),
+            ip_address: IpAddr::from_str("1.1.1.1").unwrap(),
+            event_type: "SSH_LOGIN".to_string(),
+            host: None,
+        }
+    }
+
+    fn create_detector() -> OffHoursDetector {
+        let mut config = OffHoursConfig::default();
+        config
+            .user_timezones
+            .insert("alice".to_string(), "Asia/Tokyo".to_string());
+        OffHoursDetector::new(&config).unwrap()
+    }
+
+    #[test]
+    fn test_user_override_wins() {
+        let detector = create_detector();
+        let (tz, source) = detector.resolve_timezone("alice", Some("America/New_York"));
+        assert_eq!(tz, chrono_tz::Asia::Tokyo);
+        assert_eq!(source, TimezoneSource::UserOverride);
+        // 07:13 in Tokyo, before business hours
+        assert!(detector.check_login(&create_event("alice"), None).is_some());
+    }
+
+    #[test]
+    fn test_geolocation_timezone_used_without_override() {
+        let detector = create_detector();
+        let (tz, source) = detector.resolve_timezone("bob", Some("America/Los_Angeles"));
+        assert_eq!(tz, chrono_tz::America::Los_Angeles);
+        assert_eq!(source, TimezoneSource::Geolocation);
+        // 14:13 in Los Angeles, within business hours
+        assert!(detector.check_login(&create_event("bob"), Some("America/Los_Angeles")).is_none());
+    }
+
+    #[test]
+    fn test_default_timezone_fallback() {
+        let detector = create_detector();
+        assert_eq!(detector.resolve_timezone("bob", None).1, TimezoneSource::Default);
+        assert_eq!(
+            detector.resolve_timezone("bob", Some("Not/AZone")).1,
+            TimezoneSource::Default
+        );
+        // 22:13 UTC
+        let report = detector.check_login(&create_event("bob"), None).unwrap();
+        assert_eq!(report.rule_name, "Off-Hours Login");
+    }
+
+    #[test]
+    fn test_invalid_timezones_rejected() {
+        let mut config = OffHoursConfig {
+            default_timezone: "Mars/Olympus".to_string(),
+            ..OffHoursConfig::default()
+        };
+        assert!(OffHoursDetector::new(&config).is_err());
+
+        config.default_timezone = "UTC".to_string();
+        config.user_timezones.insert("bob".to_string(), "Nowhere".to_string());
+        assert!(OffHoursDetector::new(&config).is_err());
+    }
+
+    #[test]
+    fn test_window_spanning_midnight() {
+        let config = OffHoursConfig {
+            start_hour: 22,
+            end_hour: 6,
+            ..OffHoursConfig::default()
+        };
+        let detector = OffHoursDetector::new(&config).unwrap();
+        assert!(detector.is_business_hour(23));
+        assert!(detector.is_business_hour(3));
+        assert!(!detector.is_business_hour(12));
+    }
+}
@@ -0,0 +1,201 @@
+//! Effective rule parameters on reports
+//!
+//! Thresholds change with config edits, so a stored report alone doesn't
+//! say what limit it broke. With `detection.include_rule_parameters` set,
+//! each report carries the parameters of the rule that raised it (e.g.
+//! `max_velocity_kmh` for geo velocity) in its metadata, keeping
+//! historical reports self-describing. Keys a rule already set are left
+//! alone; the rate limiter, whose limit depends on the report, sets its
+//! own.
+
+use crate::config::{DetectionConfig, DetectionRule};
+use crate::models::AnomalyReport;
+
+/// Parameters in effect for `rule`
+pub fn rule_parameters(config: &DetectionConfig, rule: DetectionRule) -> Vec<(&'static str, String)> {
+    match rule {
+        DetectionRule::IpSwitch => {
+            let ip_switch = &config.ip_switch;
+            vec![
+                ("severity", ip_switch.severity.to_string()),
+                ("scale_by_distance", ip_switch.scale_by_distance.to_string()),
+                ("successful_logins_only", ip_switch.successful_logins_only.to_string()),
+            ]
+        }
+        DetectionRule::GeoVelocity => {
+            let geo = &config.geo_velocity;
+            let mut parameters = vec![
+                ("max_velocity_kmh", geo.max_velocity_kmh.to_string()),
+                ("min_location_interval_seconds", geo.min_location_interval_seconds.to_string()),
+            ];
+            if let Some(radius) = geo.max_accuracy_radius_km {
+                parameters.push(("max_accuracy_radius_km", radius.to_string()));
+            }
+            parameters
+        }
+        DetectionRule::HostingAsn => vec![
+            ("flagged_asns", config.hosting_asn.flagged_asns.len().to_string()),
+            ("flagged_organizations", config.hosting_asn.flagged_organizations.len().to_string()),
+        ],
+        DetectionRule::OffHours => {
+            let off_hours = &config.off_hours;
+            vec![
+                ("start_hour", off_hours.start_hour.to_string()),
+                ("end_hour", off_hours.end_hour.to_string()),
+                ("default_timezone", off_hours.default_timezone.clone()),
+            ]
+        }
+        DetectionRule::HourPattern => {
+            let pattern = &config.hour_pattern;
+            vec![
+                ("min_history", pattern.min_history.to_string()),
+                ("max_hour_share", pattern.max_hour_share.to_string()),
+                ("neighbor_hours", pattern.neighbor_hours.to_string()),
+            ]
+        }
+        DetectionRule::HomeRegion => {
+            let home = &config.home_region;
+            vec![
+                ("home_latitude", home.latitude.to_string()),
+                ("home_longitude", home.longitude.to_string()),
+                ("radius_km", home.radius_km.to_string()),
+            ]
+        }
+        DetectionRule::AttackingIp => vec![
+            ("window_seconds", config.attacking_ip.window_seconds.to_string()),
+            ("min_failed_users", config.attacking_ip.min_failed_users.to_string()),
+        ],
+        DetectionRule::SuccessCluster => vec![
+            ("window_seconds", config.success_cluster.window_seconds.to_string()),
+            ("min_users", config.success_cluster.min_users.to_string()),
+        ],
+        // Which limit applies depends on the report, so the rate limiter
+        // attaches its own when it builds them
+        DetectionRule::RateLimit => Vec::new(),
+        DetectionRule::FirstSeen => vec![("severity", config.first_seen.severity.to_string())],
+        DetectionRule::Lockout => {
+            let mut parameters = vec![("severity", config.lockout.severity.to_string())];
+            if let Some(duration) = config.lockout.lock_duration_seconds {
+                parameters.push(("lock_duration_seconds", duration.to_string()));
+            }
+            parameters
+        }
+    }
+}
+
+/// Attach the rule's parameters to the reports it raised, if configured
+pub fn annotate_parameters(config: &DetectionConfig, rule: DetectionRule, reports: &mut [AnomalyReport]) {
+    if !config.include_rule_parameters {
+        return;
+    }
+    let parameters = rule_parameters(config, rule);
+    for report in reports {
+        for (key, value) in &parameters {
+            report.metadata.entry(key.to_string()).or_insert_with(|| value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::detection::rate_limiter::CLEARED_RULE_NAME;
+    use crate::detection::{DetectionEngine, GeoLocation, GeoVelocityTracker};
+    use crate::input::classify::SSH_FAILED;
+    use std::collections::HashMap;
+    use crate::models::LogEvent;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    fn create_event(user: &str, ip: &str, timestamp: i64) -> LogEvent {
+        LogEvent {
+            timestamp,
+            user: user.to_string(),
+            ip_address: IpAddr::from_str(ip).unwrap(),
+            event_type: "SSH_FAILED".to_string(),
+            host: None,
+        }
+    }
+
+    #[test]
+    fn test_rate_limit_reports_carry_parameters() {
+        let mut config = Config::default().detection;
+        config.enable_ip_switch = false;
+        config.rate_limit.window_seconds = 120;
+        config.rate_limit.max_user_attempts = 2;
+        config.rate_limit.max_ip_attempts = 100;
+        config.include_rule_parameters = true;
+        let mut engine = DetectionEngine::new(&config).unwrap();
+
+        let mut decision = None;
+        for i in 0..4 {
+            decision = Some(engine.evaluate(&create_event("alice", "203.0.113.5", 1000 + i)));
+        }
+        let report = &decision.unwrap().reports[0];
+        assert_eq!(report.rule_name, "User Rate Limit Exceeded");
+        assert_eq!(report.metadata["window_seconds"], "120");
+        assert_eq!(report.metadata["max_attempts"], "2");
+
+        // Off by default
+        config.include_rule_parameters = false;
+        let mut engine = DetectionEngine::new(&config).unwrap();
+        for i in 0..3 {
+            engine.evaluate(&create_event("alice", "203.0.113.5", 1000 + i));
+        }
+        let decision = engine.evaluate(&create_event("alice", "203.0.113.5", 1003));
+        assert!(!decision.reports[0].metadata.contains_key("max_attempts"));
+    }
+
+    #[test]
+    fn test_subnet_and_cleared_reports_carry_their_own_limits() {
+        let mut config = Config::default().detection;
+        config.enable_ip_switch = false;
+        config.include_rule_parameters = true;
+        config.rate_limit.window_seconds = 60;
+        config.rate_limit.max_user_attempts = 100;
+        config.rate_limit.max_ip_attempts = 100;
+        config.rate_limit.subnet_prefix = Some(24);
+        config.rate_limit.max_subnet_attempts = Some(3);
+        config.rate_limit.alert_on_resolve = true;
+        config.rate_limit.event_weights = HashMap::from([(SSH_FAILED.to_string(), 1)]);
+        let mut engine = DetectionEngine::new(&config).unwrap();
+
+        let mut reports = Vec::new();
+        for i in 0..5 {
+            let ip = format!("203.0.113.{}", i + 1);
+            reports.extend(engine.evaluate(&create_event(&format!("user{}", i), &ip, 1000 + i)).reports);
+        }
+        let subnet = reports.iter().find(|r| r.rule_name == "Subnet Rate Limit Exceeded").unwrap();
+        assert_eq!(subnet.metadata["max_attempts"], "3");
+        assert_eq!(subnet.metadata["subnet_prefix"], "24");
+        assert_eq!(subnet.metadata["event_weights"], "SSH_FAILED=1");
+
+        let cleared = engine.prune_stale(2000);
+        assert_eq!(cleared[0].rule_name, CLEARED_RULE_NAME);
+        assert_eq!(cleared[0].metadata["max_attempts"], "3");
+        assert_eq!(cleared[0].metadata["window_seconds"], "60");
+    }
+
+    #[test]
+    fn test_geo_velocity_reports_carry_parameters() {
+        let mut config = Config::default().detection;
+        config.include_rule_parameters = true;
+        config.geo_velocity.max_velocity_kmh = 750.0;
+        config.geo_velocity.max_accuracy_radius_km = Some(100);
+
+        let mut tracker = GeoVelocityTracker::with_max_velocity(config.geo_velocity.max_velocity_kmh);
+        let nyc = GeoLocation { latitude: 40.7128, longitude: -74.0060 };
+        let london = GeoLocation { latitude: 51.5074, longitude: -0.1278 };
+        tracker.check_impossible_travel(&create_event("bob", "1.1.1.1", 1700000000), nyc);
+        let report = tracker
+            .check_impossible_travel(&create_event("bob", "2.2.2.2", 1700003600), london)
+            .unwrap();
+
+        let mut reports = vec![report];
+        annotate_parameters(&config, DetectionRule::GeoVelocity, &mut reports);
+        assert_eq!(reports[0].metadata["max_velocity_kmh"], "750");
+        assert_eq!(reports[0].metadata["max_accuracy_radius_km"], "100");
+        assert!(reports[0].metadata.contains_key("min_location_interval_seconds"));
+    }
+}
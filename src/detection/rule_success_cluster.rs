@@ -0,0 +1,207 @@
+//! Credential stuffing success cluster
+//!
+//! Stuffing with a leaked credential list produces a few successes among
+//! many failures, and a careful attacker spreads the failures thinly
+//! enough to stay under rate limits. The successes still cluster: one IP
+//! logging in to several different accounts within minutes is rare for
+//! anything but a bastion or NAT gateway. This rule counts the distinct
+//! users each IP has successfully logged in as within a window and
+//! reports the IP once the count reaches the threshold, at most once per
+//! window.
+
+use std::net::IpAddr;
+use crate::config::SuccessClusterConfig;
+use crate::models::{LogEvent, AnomalyReport};
+use super::bounded_map::{BoundedMap, DEFAULT_MAX_TRACKED_ENTRIES};
+use super::distinct_users::DistinctUsers;
+use super::explain_outcome;
+
+/// Usernames listed in a report description
+const MAX_LISTED_USERS: usize = 10;
+
+/// Recent successes from one IP
+struct IpSuccesses {
+    users: DistinctUsers,
+    /// When the IP was last reported, to report once per window
+    reported_at: Option<i64>,
+}
+
+/// Tracks per-IP successful logins across users and flags clusters
+pub struct SuccessClusterDetector {
+    /// Maps IP -> users it recently logged in as
+    successes: BoundedMap<IpAddr, IpSuccesses>,
+    window_seconds: i64,
+    min_users: usize,
+    severity: u8,
+    max_users_per_ip: usize,
+    /// Record why each check did or didn't trigger
+    explain: bool,
+    last_explanation: Option<String>,
+}
+
+impl SuccessClusterDetector {
+    pub fn new(config: &SuccessClusterConfig) -> Self {
+        SuccessClusterDetector {
+            successes: BoundedMap::new("ip_successes", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            window_seconds: config.window_seconds,
+            min_users: config.min_users.max(2),
+            severity: config.severity,
+            max_users_per_ip: config.max_users_per_ip,
+            explain: false,
+            last_explanation: None,
+        }
+    }
+
+    /// Limit the number of IPs tracked in memory (None for unbounded)
+    pub fn with_max_tracked(mut self, max_entries: Option<usize>) -> Self {
+        self.successes.set_capacity(max_entries);
+        self
+    }
+
+    /// Record an explanation of each check, readable via `last_explanation()`
+    pub fn with_explain(mut self, enabled: bool) -> Self {
+        self.explain = enabled;
+        self
+    }
+
+    /// Explanation of the most recent check (explain mode only)
+    pub fn last_explanation(&self) -> Option<&str> {
+        self.last_explanation.as_deref()
+    }
+
+    /// Record a successful login and check its IP for a cluster
+    ///
+    /// Events without a username can't be told apart and are ignored.
+    pub fn check_event(&mut self, event: &LogEvent) -> Option<AnomalyReport> {
+        if !event.is_successful_login() || event.has_unknown_user() {
+            if self.explain {
+                self.last_explanation = Some(format!(
+                    "Success Cluster: {} event for '{}' not tracked -> not triggered",
+                    event.event_type, event.user
+                ));
+            }
+            return None;
+        }
+
+        let window_start = event.timestamp - self.window_seconds;
+        let cap = self.max_users_per_ip;
+        let ip = self.successes.get_or_insert_with(event.ip_address, || IpSuccesses {
+            users: DistinctUsers::exact(cap),
+            reported_at: None,
+        });
+        ip.users.prune(window_start);
+        ip.users.record(&event.user, event.timestamp);
+
+        let user_count = ip.users.count_excluding(&event.user, window_start) + 1;
+        let recently_reported = ip.reported_at.is_some_and(|reported_at| reported_at > window_start);
+        let triggered = user_count >= self.min_users && !recently_reported;
+
+        if self.explain {
+            self.last_explanation = Some(format!(
+                "Success Cluster: {} logged in as {}/{} distinct users in {}s{} -> {}",
+                event.ip_address,
+                user_count,
+                self.min_users,
+                self.window_seconds,
+                if recently_reported { " (already reported)" } else { "" },
+                explain_outcome(triggered)
+            ));
+        }
+
+        if !triggered {
+            return None;
+        }
+        ip.reported_at = Some(event.timestamp);
+
+        let mut names = ip.users.users_excluding("", window_start);
+        let listed = if names.len() > MAX_LISTED_USERS {
+            let more = names.len() - MAX_LISTED_USERS;
+            names.truncate(MAX_LISTED_USERS);
+            format!("{}, and {} more", names.join(", "), more)
+        } else {
+            names.join(", ")
+        };
+        Some(AnomalyReport::new(
+            self.severity,
+            "Credential Stuffing Success Cluster",
+            event.user.clone(),
+            event.timestamp,
+            format!(
+                "{} successfully logged in as {} distinct users within {}s ({}). \
+                 Possible credential stuffing with valid credentials.",
+                event.ip_address, user_count, self.window_seconds, listed
+            ),
+        )
+        .with_detected_ip(event.ip_address.to_string()))
+    }
+
+    /// Drop successes older than the window
+    pub fn prune_stale(&mut self, now: i64) {
+        let window_start = now - self.window_seconds;
+        self.successes.retain(|_, ip| {
+            ip.users.prune(window_start);
+            !ip.users.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn create_event(user: &str, ip: &str, event_type: &str, timestamp: i64) -> LogEvent {
+        LogEvent {
+            timestamp,
+            user: user.to_string(),
+            ip_address: IpAddr::from_str(ip).unwrap(),
+            event_type: event_type.to_string(),
+            host: None,
+        }
+    }
+
+    #[test]
+    fn test_many_users_succeeding_from_one_ip() {
+        let mut detector = SuccessClusterDetector::new(&SuccessClusterConfig::default());
+        let users = ["alice", "bob", "carol", "dave", "erin", "frank", "grace", "heidi"];
+        let mut reports = Vec::new();
+        for (i, user) in users.iter().enumerate() {
+            // A failure in between doesn't count
+            detector.check_event(&create_event("mallory", "203.0.113.5", "SSH_FAILED", 1000 + i as i64 * 30));
+            reports.extend(detector.check_event(&create_event(user, "203.0.113.5", "SSH_LOGIN", 1010 + i as i64 * 30)));
+        }
+
+        // Reported once, when the fifth distinct user succeeded
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].rule_name, "Credential Stuffing Success Cluster");
+        assert_eq!(reports[0].user, "erin");
+        assert_eq!(reports[0].severity, 9);
+        assert!(reports[0].description.contains("5 distinct users"));
+        assert!(reports[0].description.contains("alice, bob, carol, dave, erin"));
+
+        // Other IPs are unaffected
+        assert!(detector
+            .check_event(&create_event("alice", "198.51.100.1", "SSH_LOGIN", 1300))
+            .is_none());
+    }
+
+    #[test]
+    fn test_not_triggered_by_repeats_or_outside_window() {
+        let mut detector = SuccessClusterDetector::new(&SuccessClusterConfig::default());
+        // One user logging in repeatedly is a single user
+        for i in 0..10 {
+            assert!(detector
+                .check_event(&create_event("alice", "203.0.113.5", "SSH_LOGIN", 1000 + i))
+                .is_none());
+        }
+        // Successes spread wider than the window never cluster
+        for (i, user) in ["bob", "carol", "dave", "erin", "frank"].iter().enumerate() {
+            assert!(detector
+                .check_event(&create_event(user, "198.51.100.1", "SSH_LOGIN", 1000 + i as i64 * 700))
+                .is_none());
+        }
+
+        detector.prune_stale(100_000);
+        assert!(detector.successes.is_empty());
+    }
+}
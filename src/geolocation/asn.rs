@@ -0,0 +1,93 @@
+//! ASN lookups using the MaxMind GeoLite2-ASN database
+//!
+//! Resolves an IP address to the autonomous system that announces it,
+//! which lets rules tell hosting/datacenter networks from residential ISPs.
+
+use maxminddb::{geoip2, Reader};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::GeoError;
+
+/// Autonomous system information for an IP address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsnInfo {
+    /// Autonomous system number
+    pub number: u32,
+    /// Organization that owns the AS (e.g. "DIGITALOCEAN-ASN")
+    pub organization: Option<String>,
+}
+
+/// Trait for ASN lookup backends
+///
+/// Implemented by [`AsnService`]; rules take a trait object so lookups
+/// can be mocked in tests.
+pub trait AsnLookup: Send + Sync {
+    /// Look up the AS for an IP address, returning None if unknown
+    fn lookup_asn(&self, ip: &IpAddr) -> Option<AsnInfo>;
+}
+
+/// ASN lookup service using MaxMind GeoLite2-ASN database
+pub struct AsnService {
+    reader: Arc<Reader<Vec<u8>>>,
+}
+
+impl AsnService {
+    /// Create a new ASN service from a MaxMind database file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database file cannot be opened or is invalid.
+    pub fn new<P: AsRef<Path>>(db_path: P) -> Result<Self, GeoError> {
+        let path = db_path.as_ref();
+        if !path.exists() {
+            return Err(GeoError::FileNotFound(path.display().to_string()));
+        }
+
+        let reader = Reader::open_readfile(path)?;
+        Ok(AsnService {
+            reader: Arc::new(reader),
+        })
+    }
+
+    /// Look up the autonomous system of an IP address
+    pub fn lookup(&self, ip: &IpAddr) -> Result<AsnInfo, GeoError> {
+        let asn: geoip2::Asn = self.reader.lookup(*ip).map_err(|e| {
+            match e {
+                maxminddb::MaxMindDBError::AddressNotFoundError(_) => GeoError::NotFound,
+                other => GeoError::DatabaseOpen(other),
+            }
+        })?;
+
+        Ok(AsnInfo {
+            number: asn.autonomous_system_number.ok_or(GeoError::NotFound)?,
+            organization: asn.autonomous_system_organization.map(String::from),
+        })
+    }
+}
+
+impl AsnLookup for AsnService {
+    fn lookup_asn(&self, ip: &IpAddr) -> Option<AsnInfo> {
+        self.lookup(ip).ok()
+    }
+}
+
+impl Clone for AsnService {
+    fn clone(&self) -> Self {
+        AsnService {
+            reader: Arc::clone(&self.reader),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_not_found() {
+        let result = AsnService::new("nonexistent-asn.mmdb");
+        assert!(matches!(result, Err(GeoError::FileNotFound(_))));
+    }
+}
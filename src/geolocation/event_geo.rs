@@ -0,0 +1,224 @@
+//! Per-event geolocation cache
+//!
+//! Several rules want to know where an event came from: impossible travel
+//! needs coordinates and the accuracy radius, off-hours the timezone,
+//! home region the coordinates, and report enrichment the city and
+//! country. [`EventGeo`] looks the event's address up once, on first use,
+//! and serves all of them from that single database traversal.
+
+use super::CityInfo;
+use crate::detection::GeoLocation;
+use crate::models::AnomalyReport;
+use std::net::IpAddr;
+use std::sync::OnceLock;
+
+/// Report metadata key for the detected IP's "City, Country"
+pub const LOCATION_METADATA_KEY: &str = "detected_location";
+
+/// Report metadata key for the detected IP's ISO country code
+pub const COUNTRY_METADATA_KEY: &str = "detected_country";
+
+/// Everything known about one address, from a single lookup
+#[derive(Debug, Clone, Default)]
+pub struct IpGeo {
+    /// Coordinates, when the record has them
+    pub location: Option<GeoLocation>,
+    /// City, country, timezone and accuracy details
+    pub city: Option<CityInfo>,
+    /// The backend failed, as opposed to not knowing the address
+    pub failed: bool,
+}
+
+impl IpGeo {
+    /// Accuracy radius of the location in kilometers
+    pub fn accuracy_radius(&self) -> Option<u16> {
+        self.city.as_ref().and_then(|city| city.accuracy_radius)
+    }
+}
+
+/// Trait for city lookup backends
+///
+/// Implemented by [`GeoIpService`](super::GeoIpService); [`EventGeo`]
+/// takes a trait object so lookups can be counted in tests.
+pub trait GeoLookup: Send + Sync {
+    /// Look up coordinates and city information for an address
+    fn lookup_geo(&self, ip: &IpAddr) -> IpGeo;
+}
+
+/// Geolocation of one event's source address, looked up at most once
+pub struct EventGeo<'a> {
+    ip: IpAddr,
+    lookup: Option<&'a dyn GeoLookup>,
+    /// Where the event address's result comes from instead of `lookup`
+    source: Option<&'a (dyn Fn() -> IpGeo + 'a)>,
+    cached: OnceLock<IpGeo>,
+}
+
+impl<'a> EventGeo<'a> {
+    /// Create a cache for `ip`; without a backend every lookup is empty
+    pub fn new(ip: IpAddr, lookup: Option<&'a dyn GeoLookup>) -> Self {
+        EventGeo {
+            ip,
+            lookup,
+            source: None,
+            cached: OnceLock::new(),
+        }
+    }
+
+    /// Take the event address's result from `source`, e.g. the enrichment
+    /// pipeline's geo stage, called on first use instead of the backend
+    pub fn with_source(mut self, source: &'a (dyn Fn() -> IpGeo + 'a)) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// The event address's lookup result
+    pub fn get(&self) -> &IpGeo {
+        self.cached.get_or_init(|| match self.source {
+            Some(source) => source(),
+            None => self
+                .lookup
+                .map(|lookup| lookup.lookup_geo(&self.ip))
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Coordinates of the event address
+    pub fn location(&self) -> Option<GeoLocation> {
+        self.get().location
+    }
+
+    /// Coordinates and accuracy radius of the event address
+    pub fn location_with_accuracy(&self) -> Option<(GeoLocation, Option<u16>)> {
+        let geo = self.get();
+        geo.location.map(|location| (location, geo.accuracy_radius()))
+    }
+
+    /// Whether the event address's lookup failed, or None if it wasn't
+    /// looked up
+    pub fn lookup_failed(&self) -> Option<bool> {
+        self.cached.get().map(|geo| geo.failed)
+    }
+
+    /// City information of the event address
+    pub fn city_info(&self) -> Option<&CityInfo> {
+        self.get().city.as_ref()
+    }
+
+    /// Coordinates of any address; the event's own comes from the cache
+    pub fn locate(&self, ip: &IpAddr) -> Option<GeoLocation> {
+        if *ip == self.ip {
+            return self.location();
+        }
+        self.lookup.and_then(|lookup| lookup.lookup_geo(ip).location)
+    }
+
+    /// Attach the event address's city and country to a report about it
+    ///
+    /// Reports about another address (e.g. a rate limit on an attacking
+    /// IP) are left alone.
+    pub fn enrich(&self, report: &mut AnomalyReport) {
+        if report.detected_ip != self.ip.to_string() {
+            return;
+        }
+        let Some(city) = self.city_info() else {
+            return;
+        };
+        report
+            .metadata
+            .insert(LOCATION_METADATA_KEY.to_string(), city.display_location());
+        if let Some(code) = &city.country_code {
+            report.metadata.insert(COUNTRY_METADATA_KEY.to_string(), code.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Returns a fixed London record and counts lookups
+    #[derive(Default)]
+    struct CountingLookup {
+        lookups: AtomicUsize,
+    }
+
+    impl GeoLookup for CountingLookup {
+        fn lookup_geo(&self, _ip: &IpAddr) -> IpGeo {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            IpGeo {
+                location: Some(GeoLocation { latitude: 51.5074, longitude: -0.1278 }),
+                city: Some(CityInfo {
+                    city_name: Some("London".to_string()),
+                    country_name: Some("United Kingdom".to_string()),
+                    country_code: Some("GB".to_string()),
+                    latitude: 51.5074,
+                    longitude: -0.1278,
+                    timezone: Some("Europe/London".to_string()),
+                    accuracy_radius: Some(20),
+                }),
+                failed: false,
+            }
+        }
+    }
+
+    fn report(detected_ip: &str) -> AnomalyReport {
+        AnomalyReport {
+            severity: 8,
+            rule_name: "Impossible Travel Velocity".to_string(),
+            user: "alice".to_string(),
+            detected_ip: detected_ip.to_string(),
+            trusted_ip: String::new(),
+            timestamp: 1700000000,
+            detected_at: 1700000000,
+            description: "Test".to_string(),
+            metadata: BTreeMap::new(),
+            confidence: None,
+        }
+    }
+
+    #[test]
+    fn test_one_lookup_per_event() {
+        let backend = CountingLookup::default();
+        let ip: IpAddr = "203.0.113.5".parse().unwrap();
+        let geo = EventGeo::new(ip, Some(&backend));
+
+        // Velocity, off-hours, home region and enrichment all ask
+        let (_, accuracy) = geo.location_with_accuracy().unwrap();
+        assert_eq!(accuracy, Some(20));
+        assert_eq!(geo.city_info().unwrap().timezone.as_deref(), Some("Europe/London"));
+        assert!(geo.location().is_some());
+        assert!(geo.locate(&ip).is_some());
+        let mut report = report("203.0.113.5");
+        geo.enrich(&mut report);
+
+        assert_eq!(backend.lookups.load(Ordering::SeqCst), 1);
+        assert_eq!(report.metadata[LOCATION_METADATA_KEY], "London, United Kingdom");
+        assert_eq!(report.metadata[COUNTRY_METADATA_KEY], "GB");
+    }
+
+    #[test]
+    fn test_other_addresses_and_reports() {
+        let backend = CountingLookup::default();
+        let geo = EventGeo::new("203.0.113.5".parse().unwrap(), Some(&backend));
+
+        // A trusted IP isn't the event's address, so it's a separate lookup
+        assert!(geo.locate(&"198.51.100.7".parse().unwrap()).is_some());
+        assert_eq!(backend.lookups.load(Ordering::SeqCst), 1);
+
+        let mut report = report("198.51.100.7");
+        geo.enrich(&mut report);
+        assert!(report.metadata.is_empty());
+    }
+
+    #[test]
+    fn test_without_backend() {
+        let geo = EventGeo::new("203.0.113.5".parse().unwrap(), None);
+        assert!(geo.location().is_none());
+        let mut report = report("203.0.113.5");
+        geo.enrich(&mut report);
+        assert!(report.metadata.is_empty());
+    }
+}
@@ -0,0 +1,148 @@
+//! Geolocation failure tracking
+//!
+//! A lookup that fails (a corrupt or unreadable database, or an
+//! unreachable provider) leaves geo rules with nothing to compare, so they
+//! stay quiet rather than erroring. One failure is usually transient, but
+//! a run of them is a blind spot: [`GeoHealth`] counts consecutive failed
+//! lookups and, once they reach a threshold, logs an error and optionally
+//! raises a report that geo detection is degraded. An address the
+//! database doesn't know isn't a failure.
+
+use crate::models::AnomalyReport;
+
+/// Counts consecutive failed lookups
+#[derive(Debug, Default)]
+pub struct GeoHealth {
+    /// Failures in a row before reporting degradation (0 never reports)
+    threshold: u32,
+    alert: bool,
+    consecutive_failures: u32,
+    degraded: bool,
+}
+
+impl GeoHealth {
+    pub fn new(threshold: u32) -> Self {
+        GeoHealth {
+            threshold,
+            ..GeoHealth::default()
+        }
+    }
+
+    /// Raise a report when geo detection becomes degraded
+    pub fn with_alert(mut self, enabled: bool) -> Self {
+        self.alert = enabled;
+        self
+    }
+
+    /// Whether the failure threshold has been reached without a success since
+    pub fn is_degraded(&self) -> bool {
+        self.degraded
+    }
+
+    /// Record the outcome of the lookup for an event at `timestamp`
+    ///
+    /// Returns the degradation report, when configured, for the failure
+    /// that reaches the threshold.
+    pub fn record(&mut self, failed: bool, timestamp: i64) -> Option<AnomalyReport> {
+        if !failed {
+            if self.degraded {
+                log::warn!(
+                    "Geolocation recovered after {} consecutive failed lookups",
+                    self.consecutive_failures
+                );
+            }
+            self.consecutive_failures = 0;
+            self.degraded = false;
+            return None;
+        }
+
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        if self.threshold == 0 || self.degraded || self.consecutive_failures < self.threshold {
+            return None;
+        }
+        self.degraded = true;
+        log::error!(
+            "Geolocation degraded: {} consecutive lookups failed, geo rules are not detecting",
+            self.consecutive_failures
+        );
+        if !self.alert {
+            return None;
+        }
+        Some(AnomalyReport::new(
+            6,
+            "Geolocation Degraded",
+            "",
+            timestamp,
+            format!(
+                "{} consecutive geolocation lookups failed. Impossible travel, home region and other \
+                 geo rules can't detect until lookups succeed again.",
+                self.consecutive_failures
+            ),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geolocation::{EventGeo, GeoLookup, IpGeo};
+    use std::net::IpAddr;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Fails every lookup until switched back
+    struct FlakyLookup {
+        failing: AtomicBool,
+    }
+
+    impl GeoLookup for FlakyLookup {
+        fn lookup_geo(&self, _ip: &IpAddr) -> IpGeo {
+            IpGeo {
+                failed: self.failing.load(Ordering::SeqCst),
+                ..IpGeo::default()
+            }
+        }
+    }
+
+    fn lookup_failed(backend: &FlakyLookup) -> bool {
+        let geo = EventGeo::new("203.0.113.5".parse().unwrap(), Some(backend));
+        geo.location();
+        geo.lookup_failed().unwrap()
+    }
+
+    #[test]
+    fn test_sustained_failures_raise_one_alert() {
+        let backend = FlakyLookup { failing: AtomicBool::new(true) };
+        let mut health = GeoHealth::new(3).with_alert(true);
+
+        assert!(health.record(lookup_failed(&backend), 1000).is_none());
+        assert!(health.record(lookup_failed(&backend), 1001).is_none());
+        let report = health.record(lookup_failed(&backend), 1002).unwrap();
+        assert_eq!(report.rule_name, "Geolocation Degraded");
+        assert_eq!(report.timestamp, 1002);
+        assert!(health.is_degraded());
+        assert!(health.record(lookup_failed(&backend), 1003).is_none());
+
+        // A success resets the run
+        backend.failing.store(false, Ordering::SeqCst);
+        assert!(health.record(lookup_failed(&backend), 1004).is_none());
+        assert!(!health.is_degraded());
+        backend.failing.store(true, Ordering::SeqCst);
+        assert!(health.record(lookup_failed(&backend), 1005).is_none());
+        assert!(health.record(lookup_failed(&backend), 1006).is_none());
+        assert!(health.record(lookup_failed(&backend), 1007).is_some());
+    }
+
+    #[test]
+    fn test_degradation_without_alert_or_threshold() {
+        let mut health = GeoHealth::new(2);
+        assert!(health.record(true, 1000).is_none());
+        assert!(health.record(true, 1001).is_none());
+        assert!(health.is_degraded());
+
+        let mut health = GeoHealth::new(0).with_alert(true);
+        for now in 0..100 {
+            assert!(health.record(true, now).is_none());
+        }
+        assert!(!health.is_degraded());
+    }
+}
@@ -4,8 +4,20 @@
 //! GeoLite2-City database. Users must download the database file separately
 //! from MaxMind (free with registration).
 
+pub mod asn;
+pub mod event_geo;
+pub mod health;
+pub mod provision;
+pub mod reverse_dns;
+
+pub use asn::{AsnInfo, AsnLookup, AsnService};
+pub use event_geo::{EventGeo, GeoLookup, IpGeo};
+pub use health::GeoHealth;
+pub use provision::open_geo_service;
+pub use reverse_dns::{DnsResolver, LookupFuture, PtrLookup, ReverseDnsEnricher, ReverseResolver};
+
 use maxminddb::{geoip2, Reader};
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::path::Path;
 use std::sync::Arc;
 use thiserror::Error;
@@ -26,6 +38,12 @@ pub enum GeoError {
 
     #[error("Database file not found: {0}")]
     FileNotFound(String),
+
+    #[error("GeoIP database download failed: {0}")]
+    Download(String),
+
+    #[error("GeoIP database download requested but no download_url is configured")]
+    DownloadNotConfigured,
 }
 
 /// GeoIP lookup service using MaxMind GeoLite2-City database
@@ -48,6 +66,35 @@ pub enum GeoError {
 /// ```
 pub struct GeoIpService {
     reader: Arc<Reader<Vec<u8>>>,
+    /// Prefix lengths IPv4/IPv6 addresses are truncated to before lookup
+    anonymize_prefix_v4: Option<u8>,
+    anonymize_prefix_v6: Option<u8>,
+}
+
+/// Zero the bits of an IP address beyond a prefix length
+///
+/// IPv4 addresses are truncated to `prefix_v4` bits and IPv6 addresses to
+/// `prefix_v6` bits; `None` leaves that family untouched. Prefixes longer
+/// than the address are clamped.
+pub fn anonymize_ip(ip: IpAddr, prefix_v4: Option<u8>, prefix_v6: Option<u8>) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) => match prefix_v4 {
+            Some(prefix) => {
+                let bits = u32::from(v4);
+                let mask = u32::MAX.checked_shl(32 - u32::from(prefix.min(32))).unwrap_or(0);
+                IpAddr::V4(Ipv4Addr::from(bits & mask))
+            }
+            None => ip,
+        },
+        IpAddr::V6(v6) => match prefix_v6 {
+            Some(prefix) => {
+                let bits = u128::from(v6);
+                let mask = u128::MAX.checked_shl(128 - u32::from(prefix.min(128))).unwrap_or(0);
+                IpAddr::V6(Ipv6Addr::from(bits & mask))
+            }
+            None => ip,
+        },
+    }
 }
 
 impl GeoIpService {
@@ -69,9 +116,26 @@ impl GeoIpService {
         let reader = Reader::open_readfile(path)?;
         Ok(GeoIpService {
             reader: Arc::new(reader),
+            anonymize_prefix_v4: None,
+            anonymize_prefix_v6: None,
         })
     }
 
+    /// Truncate addresses to the given prefix lengths before every lookup
+    ///
+    /// Trades location precision for never handing the full address to
+    /// the database; `None` keeps that family at full precision.
+    pub fn with_anonymization(mut self, prefix_v4: Option<u8>, prefix_v6: Option<u8>) -> Self {
+        self.anonymize_prefix_v4 = prefix_v4;
+        self.anonymize_prefix_v6 = prefix_v6;
+        self
+    }
+
+    /// The address actually looked up for `ip`, after anonymization
+    pub fn lookup_address(&self, ip: &IpAddr) -> IpAddr {
+        anonymize_ip(*ip, self.anonymize_prefix_v4, self.anonymize_prefix_v6)
+    }
+
     /// Look up the geographic location of an IP address
     ///
     /// # Arguments
@@ -83,7 +147,7 @@ impl GeoIpService {
     /// Returns `Ok(GeoLocation)` with latitude and longitude if found,
     /// or an error if the IP is not in the database or has no location data.
     pub fn lookup(&self, ip: &IpAddr) -> Result<GeoLocation, GeoError> {
-        let city: geoip2::City = self.reader.lookup(*ip).map_err(|e| {
+        let city: geoip2::City = self.reader.lookup(self.lookup_address(ip)).map_err(|e| {
             match e {
                 maxminddb::MaxMindDBError::AddressNotFoundError(_) => GeoError::NotFound,
                 other => GeoError::DatabaseOpen(other),
@@ -116,6 +180,21 @@ impl GeoIpService {
         self.lookup(ip).ok()
     }
 
+    /// Look up an IP address along with its accuracy radius in kilometers
+    ///
+    /// Returns `None` if the IP can't be located.
+    pub fn lookup_with_accuracy(&self, ip: &IpAddr) -> Option<(GeoLocation, Option<u16>)> {
+        let city: geoip2::City = self.reader.lookup(self.lookup_address(ip)).ok()?;
+        let location = city.location?;
+        Some((
+            GeoLocation {
+                latitude: location.latitude?,
+                longitude: location.longitude?,
+            },
+            location.accuracy_radius,
+        ))
+    }
+
     /// Check if an IP address is in the database
     pub fn contains(&self, ip: &IpAddr) -> bool {
         self.lookup(ip).is_ok()
@@ -125,25 +204,60 @@ impl GeoIpService {
     ///
     /// Returns the full city record including country, city name, etc.
     pub fn lookup_city_info(&self, ip: &IpAddr) -> Result<CityInfo, GeoError> {
-        let city: geoip2::City = self.reader.lookup(*ip).map_err(|e| {
+        let city: geoip2::City = self.reader.lookup(self.lookup_address(ip)).map_err(|e| {
             match e {
                 maxminddb::MaxMindDBError::AddressNotFoundError(_) => GeoError::NotFound,
                 other => GeoError::DatabaseOpen(other),
             }
         })?;
+        Self::city_info(city).ok_or(GeoError::NoLocation)
+    }
 
-        let location = city.location.ok_or(GeoError::NoLocation)?;
+    /// Look up coordinates and city information with a single traversal
+    ///
+    /// Unknown addresses give an empty [`IpGeo`].
+    pub fn lookup_geo(&self, ip: &IpAddr) -> IpGeo {
+        let city = match self.reader.lookup::<geoip2::City>(self.lookup_address(ip)) {
+            Ok(city) => city,
+            Err(maxminddb::MaxMindDBError::AddressNotFoundError(_)) => return IpGeo::default(),
+            Err(e) => {
+                log::debug!("GeoIP lookup of {} failed: {}", ip, e);
+                return IpGeo {
+                    failed: true,
+                    ..IpGeo::default()
+                };
+            }
+        };
+        let location = city
+            .location
+            .as_ref()
+            .and_then(|location| Some(GeoLocation {
+                latitude: location.latitude?,
+                longitude: location.longitude?,
+            }));
+        IpGeo {
+            location,
+            city: Self::city_info(city),
+            failed: false,
+        }
+    }
 
-        Ok(CityInfo {
+    /// City information from a database record, if it has a location
+    fn city_info(city: geoip2::City) -> Option<CityInfo> {
+        let location = city.location?;
+
+        Some(CityInfo {
             city_name: city.city
                 .and_then(|c| c.names)
                 .and_then(|n| n.get("en").copied())
                 .map(String::from),
             country_name: city.country
-                .and_then(|c| c.names)
+                .as_ref()
+                .and_then(|c| c.names.as_ref())
                 .and_then(|n| n.get("en").copied())
                 .map(String::from),
             country_code: city.country
+                .as_ref()
                 .and_then(|c| c.iso_code)
                 .map(String::from),
             latitude: location.latitude.unwrap_or(0.0),
@@ -154,10 +268,18 @@ impl GeoIpService {
     }
 }
 
+impl GeoLookup for GeoIpService {
+    fn lookup_geo(&self, ip: &IpAddr) -> IpGeo {
+        GeoIpService::lookup_geo(self, ip)
+    }
+}
+
 impl Clone for GeoIpService {
     fn clone(&self) -> Self {
         GeoIpService {
             reader: Arc::clone(&self.reader),
+            anonymize_prefix_v4: self.anonymize_prefix_v4,
+            anonymize_prefix_v6: self.anonymize_prefix_v6,
         }
     }
 }
@@ -276,6 +398,67 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_anonymize_ip() {
+        let v4 = IpAddr::from_str("203.0.113.77").unwrap();
+        assert_eq!(anonymize_ip(v4, Some(24), None), IpAddr::from_str("203.0.113.0").unwrap());
+        assert_eq!(anonymize_ip(v4, Some(16), None), IpAddr::from_str("203.0.0.0").unwrap());
+        assert_eq!(anonymize_ip(v4, Some(0), None), IpAddr::from_str("0.0.0.0").unwrap());
+        assert_eq!(anonymize_ip(v4, Some(40), None), v4);
+        assert_eq!(anonymize_ip(v4, None, Some(48)), v4);
+
+        let v6 = IpAddr::from_str("2001:db8:abcd:12:3456::1").unwrap();
+        assert_eq!(anonymize_ip(v6, Some(24), Some(48)), IpAddr::from_str("2001:db8:abcd::").unwrap());
+        assert_eq!(anonymize_ip(v6, Some(24), None), v6);
+    }
+
+    #[test]
+    fn test_anonymized_lookup_uses_masked_ip() {
+        if let Some(service) = get_test_service() {
+            let service = service.with_anonymization(Some(24), Some(48));
+            let ip = IpAddr::from_str("8.8.8.8").unwrap();
+            let masked = IpAddr::from_str("8.8.8.0").unwrap();
+            assert_eq!(service.lookup_address(&ip), masked);
+
+            // Every address in the /24 resolves exactly like the network address
+            let neighbour = IpAddr::from_str("8.8.8.200").unwrap();
+            let full = get_test_service().unwrap();
+            assert_eq!(
+                service.lookup_optional(&neighbour).map(|l| (l.latitude, l.longitude)),
+                full.lookup_optional(&masked).map(|l| (l.latitude, l.longitude))
+            );
+        }
+    }
+
+    #[test]
+    fn test_velocity_at_reduced_precision() {
+        use crate::detection::GeoVelocityTracker;
+
+        if let Some(service) = get_test_service() {
+            let service = service.with_anonymization(Some(16), Some(32));
+            let first = IpAddr::from_str("8.8.8.8").unwrap();
+            let second = IpAddr::from_str("1.1.1.1").unwrap();
+            let (Some(a), Some(b)) = (service.lookup_optional(&first), service.lookup_optional(&second)) else {
+                return;
+            };
+            if crate::detection::rule_geo_velocity::haversine_distance(a, b) < 2000.0 {
+                return;
+            }
+
+            // Coarse locations still expose travel that is physically impossible
+            let mut tracker = GeoVelocityTracker::with_max_velocity(1000.0);
+            let login = |ip: IpAddr, timestamp: i64| crate::models::LogEvent {
+                timestamp,
+                user: "alice".to_string(),
+                ip_address: ip,
+                event_type: "SSH_LOGIN".to_string(),
+                host: None,
+            };
+            assert!(tracker.check_impossible_travel(&login(first, 1000), a).is_none());
+            assert!(tracker.check_impossible_travel(&login(second, 1060), b).is_some());
+        }
+    }
+
     #[test]
     fn test_clone() {
         if let Some(service) = get_test_service() {
@@ -0,0 +1,254 @@
+//! Reverse DNS enrichment
+//!
+//! Resolves the PTR record of a report's detected IP and attaches it as
+//! `detected_hostname` metadata. Lookups are asynchronous and bounded by a
+//! timeout. Hostnames are cached, and IPs without a PTR record are
+//! remembered for `negative_ttl_seconds`, so a slow or attacker-controlled
+//! nameserver is hit at most once per IP per interval. Failed and timed-out
+//! lookups are not cached and are retried on the next report.
+
+use crate::config::ReverseDnsConfig;
+use crate::detection::bounded_map::BoundedMap;
+use crate::models::AnomalyReport;
+use hickory_resolver::error::{ResolveError, ResolveErrorKind};
+use hickory_resolver::TokioAsyncResolver;
+use std::future::Future;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Metadata key the resolved hostname is stored under
+pub const HOSTNAME_METADATA_KEY: &str = "detected_hostname";
+
+/// Outcome of a PTR lookup
+#[derive(Debug, Clone, PartialEq)]
+pub enum PtrLookup {
+    /// The IP's hostname
+    Found(String),
+    /// The IP has no PTR record
+    NoRecord,
+    /// The lookup failed (e.g. the nameserver was unreachable)
+    Failed(String),
+}
+
+/// A pending PTR lookup
+pub type LookupFuture<'a> = Pin<Box<dyn Future<Output = PtrLookup> + Send + 'a>>;
+
+/// Asynchronous PTR lookup
+pub trait ReverseResolver: Send + Sync {
+    /// Resolve an IP to a hostname
+    fn reverse_lookup(&self, ip: IpAddr) -> LookupFuture<'_>;
+}
+
+/// Resolver using the system's nameservers (from /etc/resolv.conf)
+pub struct DnsResolver {
+    resolver: TokioAsyncResolver,
+}
+
+impl DnsResolver {
+    /// Create a resolver from the system's DNS configuration
+    pub fn from_system_conf() -> Result<Self, ResolveError> {
+        Ok(DnsResolver {
+            resolver: TokioAsyncResolver::tokio_from_system_conf()?,
+        })
+    }
+}
+
+impl ReverseResolver for DnsResolver {
+    fn reverse_lookup(&self, ip: IpAddr) -> LookupFuture<'_> {
+        Box::pin(async move {
+            match self.resolver.reverse_lookup(ip).await {
+                Ok(lookup) => match lookup.iter().next() {
+                    Some(name) => PtrLookup::Found(name.0.to_utf8().trim_end_matches('.').to_string()),
+                    None => PtrLookup::NoRecord,
+                },
+                Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => PtrLookup::NoRecord,
+                Err(e) => PtrLookup::Failed(e.to_string()),
+            }
+        })
+    }
+}
+
+/// A cached lookup: a hostname, or no PTR record until `expires_at`
+struct CachedLookup {
+    hostname: Option<String>,
+    expires_at: Option<Instant>,
+}
+
+/// Attaches cached, timeout-bounded PTR lookups to reports
+pub struct ReverseDnsEnricher {
+    resolver: Arc<dyn ReverseResolver>,
+    timeout: Duration,
+    negative_ttl: Duration,
+    cache: Mutex<BoundedMap<IpAddr, CachedLookup>>,
+}
+
+impl ReverseDnsEnricher {
+    /// Create an enricher looking hostnames up through `resolver`
+    pub fn new(config: &ReverseDnsConfig, resolver: Arc<dyn ReverseResolver>) -> Self {
+        ReverseDnsEnricher {
+            resolver,
+            timeout: Duration::from_millis(config.timeout_ms),
+            negative_ttl: Duration::from_secs(config.negative_ttl_seconds),
+            cache: Mutex::new(BoundedMap::new("reverse_dns", Some(config.cache_size))),
+        }
+    }
+
+    /// Resolve an IP's hostname, consulting the cache first
+    pub async fn resolve(&self, ip: IpAddr) -> Option<String> {
+        if let Some(cached) = self.cache.lock().unwrap().get(&ip) {
+            if cached.expires_at.is_none_or(|expires_at| expires_at > Instant::now()) {
+                return cached.hostname.clone();
+            }
+        }
+
+        let cached = match tokio::time::timeout(self.timeout, self.resolver.reverse_lookup(ip)).await {
+            Ok(PtrLookup::Found(hostname)) => CachedLookup {
+                hostname: Some(hostname),
+                expires_at: None,
+            },
+            Ok(PtrLookup::NoRecord) => CachedLookup {
+                hostname: None,
+                expires_at: Some(Instant::now() + self.negative_ttl),
+            },
+            Ok(PtrLookup::Failed(e)) => {
+                log::debug!("Reverse DNS lookup for {} failed: {}", ip, e);
+                return None;
+            }
+            Err(_) => {
+                log::debug!("Reverse DNS lookup for {} timed out after {:?}", ip, self.timeout);
+                return None;
+            }
+        };
+
+        let hostname = cached.hostname.clone();
+        self.cache.lock().unwrap().insert(ip, cached);
+        hostname
+    }
+
+    /// Attach the detected IP's hostname to a report, if it resolves
+    pub async fn enrich(&self, report: &mut AnomalyReport) {
+        let Ok(ip) = report.detected_ip.parse::<IpAddr>() else {
+            return;
+        };
+        if let Some(hostname) = self.resolve(ip).await {
+            report.metadata.insert(HOSTNAME_METADATA_KEY.to_string(), hostname);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct MockResolver {
+        outcome: PtrLookup,
+        delay: Duration,
+        calls: AtomicUsize,
+    }
+
+    impl MockResolver {
+        fn new(outcome: PtrLookup, delay: Duration) -> Arc<Self> {
+            Arc::new(MockResolver {
+                outcome,
+                delay,
+                calls: AtomicUsize::new(0),
+            })
+        }
+
+        fn calls(&self) -> usize {
+            self.calls.load(Ordering::SeqCst)
+        }
+    }
+
+    impl ReverseResolver for MockResolver {
+        fn reverse_lookup(&self, _ip: IpAddr) -> LookupFuture<'_> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                tokio::time::sleep(self.delay).await;
+                self.outcome.clone()
+            })
+        }
+    }
+
+    fn create_enricher(resolver: Arc<MockResolver>, negative_ttl_seconds: u64) -> ReverseDnsEnricher {
+        let config = ReverseDnsConfig {
+            enabled: true,
+            timeout_ms: 50,
+            cache_size: 16,
+            negative_ttl_seconds,
+        };
+        ReverseDnsEnricher::new(&config, resolver)
+    }
+
+    fn create_report() -> AnomalyReport {
+        AnomalyReport {
+            severity: 8,
+            rule_name: "IP Switch".to_string(),
+            user: "alice".to_string(),
+            detected_ip: "203.0.113.5".to_string(),
+            trusted_ip: String::new(),
+            timestamp: 1700000000,
+            detected_at: 1700000000,
+            description: "test".to_string(),
+            metadata: BTreeMap::new(),
+            confidence: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hostname_attached_and_cached() {
+        let resolver = MockResolver::new(PtrLookup::Found("host.example.com".to_string()), Duration::ZERO);
+        let enricher = create_enricher(resolver.clone(), 3600);
+
+        let mut report = create_report();
+        enricher.enrich(&mut report).await;
+        assert_eq!(report.metadata[HOSTNAME_METADATA_KEY], "host.example.com");
+
+        enricher.enrich(&mut create_report()).await;
+        assert_eq!(resolver.calls(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_timeouts_and_failures_are_retried() {
+        let resolver = MockResolver::new(PtrLookup::Found("slow.example.com".to_string()), Duration::from_millis(500));
+        let enricher = create_enricher(resolver.clone(), 3600);
+
+        let mut report = create_report();
+        let started = std::time::Instant::now();
+        enricher.enrich(&mut report).await;
+        assert!(started.elapsed() < Duration::from_millis(400));
+        assert!(report.metadata.is_empty());
+
+        // A timeout is not remembered as "no hostname"
+        enricher.enrich(&mut create_report()).await;
+        assert_eq!(resolver.calls(), 2);
+
+        let resolver = MockResolver::new(PtrLookup::Failed("connection refused".to_string()), Duration::ZERO);
+        let enricher = create_enricher(resolver.clone(), 3600);
+        enricher.enrich(&mut create_report()).await;
+        enricher.enrich(&mut create_report()).await;
+        assert_eq!(resolver.calls(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_missing_record_cached_until_ttl() {
+        let resolver = MockResolver::new(PtrLookup::NoRecord, Duration::ZERO);
+        let enricher = create_enricher(resolver.clone(), 3600);
+        let mut report = create_report();
+        enricher.enrich(&mut report).await;
+        enricher.enrich(&mut report).await;
+        assert!(report.metadata.is_empty());
+        assert_eq!(resolver.calls(), 1);
+
+        // An expired negative entry is looked up again
+        let resolver = MockResolver::new(PtrLookup::NoRecord, Duration::ZERO);
+        let enricher = create_enricher(resolver.clone(), 0);
+        enricher.enrich(&mut create_report()).await;
+        enricher.enrich(&mut create_report()).await;
+        assert_eq!(resolver.calls(), 2);
+    }
+}
@@ -0,0 +1,89 @@
+//! Stale event filtering
+//!
+//! When a backlog or slow queue drains, events can reach detection hours
+//! after they happened; alerting on them in real time is noise. With a
+//! maximum age set, [`EventAgeFilter`] drops events whose timestamp is
+//! further behind "now" than that. Replay mode, where old events are the
+//! point, doesn't use it.
+
+use crate::models::LogEvent;
+
+/// Drops events older than a maximum age
+#[derive(Debug, Default)]
+pub struct EventAgeFilter {
+    max_age_seconds: Option<i64>,
+    /// Events dropped since the last `take_dropped`
+    dropped: u64,
+}
+
+impl EventAgeFilter {
+    /// Create a filter (no filtering when `max_age_seconds` is None)
+    pub fn new(max_age_seconds: Option<i64>) -> Self {
+        EventAgeFilter {
+            max_age_seconds,
+            dropped: 0,
+        }
+    }
+
+    /// Whether a maximum age is set
+    pub fn is_enabled(&self) -> bool {
+        self.max_age_seconds.is_some()
+    }
+
+    /// Whether an event is recent enough at `now` to be processed
+    pub fn should_process(&mut self, event: &LogEvent, now: i64) -> bool {
+        let Some(max_age) = self.max_age_seconds else {
+            return true;
+        };
+        if now - event.timestamp <= max_age {
+            return true;
+        }
+        self.dropped += 1;
+        false
+    }
+
+    /// Events dropped since the last call, resetting the count
+    pub fn take_dropped(&mut self) -> u64 {
+        std::mem::take(&mut self.dropped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::classify::SSH_FAILED;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    fn create_event(timestamp: i64) -> LogEvent {
+        LogEvent {
+            timestamp,
+            user: "alice".to_string(),
+            ip_address: IpAddr::from_str("203.0.113.5").unwrap(),
+            event_type: SSH_FAILED.to_string(),
+            host: None,
+        }
+    }
+
+    #[test]
+    fn test_drops_events_older_than_max_age() {
+        let now = chrono::Utc::now().timestamp();
+        let mut filter = EventAgeFilter::new(Some(3600));
+
+        assert!(!filter.should_process(&create_event(now - 2 * 3600), now));
+        assert!(filter.should_process(&create_event(now - 60), now));
+        assert!(filter.should_process(&create_event(now - 3600), now));
+        // Events stamped slightly ahead of the local clock are kept
+        assert!(filter.should_process(&create_event(now + 5), now));
+
+        assert_eq!(filter.take_dropped(), 1);
+        assert_eq!(filter.take_dropped(), 0);
+    }
+
+    #[test]
+    fn test_disabled_keeps_everything() {
+        let mut filter = EventAgeFilter::new(None);
+        assert!(!filter.is_enabled());
+        assert!(filter.should_process(&create_event(0), chrono::Utc::now().timestamp()));
+    }
+}
@@ -0,0 +1,285 @@
+//! Event type classification
+//!
+//! Maps a log line to an event type by phrase. By default sshd failures
+//! all become `SSH_FAILED`; with detailed failure types enabled the
+//! common sshd phrasings get their own `SSH_FAILED_*` types so rules can
+//! weigh e.g. "maximum authentication attempts exceeded" above a single
+//! "Connection closed". User mappings are checked before the built-ins.
+//! Clock adjustment lines from time daemons can optionally be classified
+//! as `CLOCK_STEP` for the clock guard, and account lockouts reported by
+//! other systems as `ACCOUNT_LOCKED` / `ACCOUNT_UNLOCKED`. Both suspend or
+//! redirect detection, so they only count when logged by the program
+//! that owns them (chronyd, pam_faillock, ...), never from phrases that
+//! happen to appear in a username, and sshd's own events win first.
+
+use crate::config::{EventTypeMapping, InputConfig};
+use super::clock::{CLOCK_PROGRAMS, CLOCK_STEP, CLOCK_STEP_PHRASES};
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Successful authentication
+pub const SSH_LOGIN: &str = "SSH_LOGIN";
+/// Failure that doesn't match a more specific phrasing
+pub const SSH_FAILED: &str = "SSH_FAILED";
+/// Prefix shared by the detailed failure types below
+pub const SSH_FAILED_PREFIX: &str = "SSH_FAILED_";
+/// "Failed password for ..."
+pub const SSH_FAILED_PASSWORD: &str = "SSH_FAILED_PASSWORD";
+/// "Failed publickey for ..."
+pub const SSH_FAILED_PUBLICKEY: &str = "SSH_FAILED_PUBLICKEY";
+/// "Invalid user ... from ..."
+pub const SSH_FAILED_INVALID_USER: &str = "SSH_FAILED_INVALID_USER";
+/// "maximum authentication attempts exceeded for ..."
+pub const SSH_FAILED_MAX_AUTH: &str = "SSH_FAILED_MAX_AUTH";
+/// "Connection closed by authenticating user ..." (gave up mid-auth)
+pub const SSH_FAILED_CONNECTION_CLOSED: &str = "SSH_FAILED_CONNECTION_CLOSED";
+/// An external system (e.g. pam_faillock) locked the account
+pub const ACCOUNT_LOCKED: &str = "ACCOUNT_LOCKED";
+/// An external system unlocked the account
+pub const ACCOUNT_UNLOCKED: &str = "ACCOUNT_UNLOCKED";
+/// A request in a web server access log
+pub const HTTP_REQUEST: &str = "HTTP_REQUEST";
+/// A web request rejected as unauthenticated or forbidden (401/403)
+pub const HTTP_AUTH_FAILED: &str = "HTTP_AUTH_FAILED";
+/// A successful web request by an authenticated user
+pub const HTTP_LOGIN: &str = "HTTP_LOGIN";
+/// Line that isn't an authentication event
+pub const UNKNOWN_EVENT: &str = "UNKNOWN";
+
+/// Lowercased phrases of account unlocks, checked before locks
+const UNLOCK_PHRASES: &[&str] = &["account unlocked", "account was unlocked"];
+
+/// Lowercased phrases of account lockouts
+const LOCK_PHRASES: &[&str] = &["account temporarily locked", "account locked", "account was locked", "locked out"];
+
+/// Programs whose lock and unlock lines are trusted
+const LOCKOUT_PROGRAMS: &[&str] = &["pam_faillock", "faillock", "idm", "ipa", "sssd"];
+
+/// PAM modules log through the calling program (`sshd[1]: pam_faillock(sshd:auth): ...`)
+const LOCKOUT_MODULE_PREFIX: &str = "pam_faillock(";
+
+/// `<pri>1 timestamp host app procid msgid ` (RFC 5424)
+fn rfc5424_tag() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"^<\d{1,3}>\d{1,2} \S+ \S+ (\S+) \S+ \S+ ").unwrap())
+}
+
+/// The first `tag[pid]: ` or `tag: ` on the line (BSD syslog, journal)
+fn bsd_tag() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"([A-Za-z0-9_.-]+)(?:\[\d+\])?: ").unwrap())
+}
+
+/// The program that logged a line and its message, if the line has a
+/// syslog tag
+fn program_and_message(line: &str) -> Option<(&str, &str)> {
+    let caps = rfc5424_tag().captures(line).or_else(|| bsd_tag().captures(line))?;
+    let program = caps.get(1)?.as_str();
+    let message = &line[caps.get(0)?.end()..];
+    Some((program, message))
+}
+
+/// Detailed sshd failure phrasings, most specific first
+const DETAILED_FAILURES: &[(&str, &str)] = &[
+    ("maximum authentication attempts exceeded", SSH_FAILED_MAX_AUTH),
+    ("Failed password", SSH_FAILED_PASSWORD),
+    ("Failed publickey", SSH_FAILED_PUBLICKEY),
+    ("Invalid user", SSH_FAILED_INVALID_USER),
+    ("Connection closed by authenticating user", SSH_FAILED_CONNECTION_CLOSED),
+    ("Disconnected from authenticating user", SSH_FAILED_CONNECTION_CLOSED),
+];
+
+/// Assigns event types to log lines
+#[derive(Debug, Clone, Default)]
+pub struct EventClassifier {
+    /// User-configured (phrase, event type) pairs, checked in order
+    mappings: Vec<(String, String)>,
+    /// Split sshd failures into `SSH_FAILED_*` types
+    detailed_failures: bool,
+    /// Recognise time daemon clock adjustments as `CLOCK_STEP`
+    clock_events: bool,
+    /// Recognise account lockouts as `ACCOUNT_LOCKED` / `ACCOUNT_UNLOCKED`
+    lockout_events: bool,
+}
+
+impl EventClassifier {
+    /// Create a classifier from the input configuration
+    pub fn from_config(config: &InputConfig) -> Self {
+        EventClassifier::default()
+            .with_detailed_failures(config.detailed_failure_types)
+            .with_mappings(&config.event_type_mappings)
+            .with_clock_events(config.clock_guard.enabled)
+    }
+
+    /// Split sshd failures into finer `SSH_FAILED_*` event types
+    pub fn with_detailed_failures(mut self, enabled: bool) -> Self {
+        self.detailed_failures = enabled;
+        self
+    }
+
+    /// Classify chrony/ntpd/timesyncd clock adjustments as `CLOCK_STEP`
+    pub fn with_clock_events(mut self, enabled: bool) -> Self {
+        self.clock_events = enabled;
+        self
+    }
+
+    /// Classify account lock and unlock lines as `ACCOUNT_LOCKED` /
+    /// `ACCOUNT_UNLOCKED`
+    pub fn with_lockout_events(mut self, enabled: bool) -> Self {
+        self.lockout_events = enabled;
+        self
+    }
+
+    /// Add phrase mappings checked before the built-in classification
+    pub fn with_mappings(mut self, mappings: &[EventTypeMapping]) -> Self {
+        self.mappings.extend(
+            mappings
+                .iter()
+                .map(|m| (m.pattern.clone(), m.event_type.clone())),
+        );
+        self
+    }
+
+    /// Classify a log line
+    pub fn classify(&self, line: &str) -> String {
+        if let Some((_, event_type)) = self.mappings.iter().find(|(pattern, _)| line.contains(pattern.as_str())) {
+            return event_type.clone();
+        }
+
+        if line.contains("Accepted") || line.contains("Successful") {
+            return SSH_LOGIN.to_string();
+        }
+        if self.detailed_failures {
+            if let Some((_, event_type)) = DETAILED_FAILURES.iter().find(|(phrase, _)| line.contains(phrase)) {
+                return event_type.to_string();
+            }
+        }
+        if line.contains("Failed") || line.contains("Invalid") {
+            return SSH_FAILED.to_string();
+        }
+
+        let Some((program, message)) = program_and_message(line) else {
+            return UNKNOWN_EVENT.to_string();
+        };
+        if self.clock_events
+            && CLOCK_PROGRAMS.contains(&program)
+            && CLOCK_STEP_PHRASES.iter().any(|phrase| message.contains(phrase))
+        {
+            return CLOCK_STEP.to_string();
+        }
+        if self.lockout_events && (LOCKOUT_PROGRAMS.contains(&program) || message.starts_with(LOCKOUT_MODULE_PREFIX)) {
+            let lower = message.to_lowercase();
+            if UNLOCK_PHRASES.iter().any(|phrase| lower.contains(phrase)) {
+                return ACCOUNT_UNLOCKED.to_string();
+            }
+            if LOCK_PHRASES.iter().any(|phrase| lower.contains(phrase)) {
+                return ACCOUNT_LOCKED.to_string();
+            }
+        }
+        UNKNOWN_EVENT.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINES: &[(&str, &str)] = &[
+        (
+            "Jan 1 12:00:00 host sshd[1]: Failed password for root from 203.0.113.5 port 22 ssh2",
+            SSH_FAILED_PASSWORD,
+        ),
+        (
+            "Jan 1 12:00:00 host sshd[1]: Failed password for invalid user admin from 203.0.113.5 port 22 ssh2",
+            SSH_FAILED_PASSWORD,
+        ),
+        (
+            "Jan 1 12:00:00 host sshd[1]: Failed publickey for alice from 203.0.113.5 port 22 ssh2",
+            SSH_FAILED_PUBLICKEY,
+        ),
+        (
+            "Jan 1 12:00:00 host sshd[1]: Invalid user admin from 203.0.113.5 port 22",
+            SSH_FAILED_INVALID_USER,
+        ),
+        (
+            "Jan 1 12:00:00 host sshd[1]: error: maximum authentication attempts exceeded for root from 203.0.113.5 port 22 ssh2 [preauth]",
+            SSH_FAILED_MAX_AUTH,
+        ),
+        (
+            "Jan 1 12:00:00 host sshd[1]: Connection closed by authenticating user root 203.0.113.5 port 22 [preauth]",
+            SSH_FAILED_CONNECTION_CLOSED,
+        ),
+        (
+            "Jan 1 12:00:00 host sshd[1]: Accepted publickey for alice from 192.168.1.100 port 22 ssh2",
+            SSH_LOGIN,
+        ),
+        (
+            "Jan 1 12:00:00 host sshd[1]: Received disconnect from 192.168.1.100 port 22:11: disconnected by user",
+            UNKNOWN_EVENT,
+        ),
+    ];
+
+    #[test]
+    fn test_detailed_sshd_failures() {
+        let classifier = EventClassifier::default().with_detailed_failures(true);
+        for (line, expected) in LINES {
+            assert_eq!(classifier.classify(line), *expected, "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_coarse_failures_by_default() {
+        let classifier = EventClassifier::default();
+        assert_eq!(classifier.classify(LINES[0].0), SSH_FAILED);
+        assert_eq!(classifier.classify(LINES[3].0), SSH_FAILED);
+        assert_eq!(classifier.classify(LINES[6].0), SSH_LOGIN);
+    }
+
+    #[test]
+    fn test_clock_adjustments_classified() {
+        let line = "Jan 1 12:00:00 host chronyd[512]: System clock wrong by -86400.012 seconds, adjustment started";
+        assert_eq!(EventClassifier::default().classify(line), UNKNOWN_EVENT);
+        assert_eq!(EventClassifier::default().with_clock_events(true).classify(line), CLOCK_STEP);
+        let timesyncd = "<30>1 2024-01-15T10:30:00Z host systemd-timesyncd 412 - - Time has been changed";
+        assert_eq!(EventClassifier::default().with_clock_events(true).classify(timesyncd), CLOCK_STEP);
+    }
+
+    #[test]
+    fn test_clock_phrases_only_from_time_daemons() {
+        let classifier = EventClassifier::default().with_clock_events(true);
+        let spoofed = "Jan 1 12:00:00 host sshd[1]: Failed password for time reset from 203.0.113.5 port 22";
+        assert_eq!(classifier.classify(spoofed), SSH_FAILED);
+        let other_program = "Jan 1 12:00:00 host app[7]: user said System clock wrong by 5 seconds";
+        assert_eq!(classifier.classify(other_program), UNKNOWN_EVENT);
+    }
+
+    #[test]
+    fn test_lockouts_classified() {
+        let locked = "Jan 1 12:00:00 host sshd[1]: pam_faillock(sshd:auth): Consecutive login failures for user alice account temporarily locked";
+        let unlocked = "Jan 1 12:10:00 host idm: Account unlocked for alice by admin";
+        assert_eq!(EventClassifier::default().classify(locked), UNKNOWN_EVENT);
+
+        let classifier = EventClassifier::default().with_lockout_events(true);
+        assert_eq!(classifier.classify(locked), ACCOUNT_LOCKED);
+        assert_eq!(classifier.classify(unlocked), ACCOUNT_UNLOCKED);
+        assert_eq!(classifier.classify(LINES[6].0), SSH_LOGIN);
+
+        // Lockout phrases elsewhere are left alone
+        let spoofed = "Jan 1 12:00:00 host sshd[1]: Failed password for account locked from 203.0.113.5 port 22";
+        assert_eq!(classifier.classify(spoofed), SSH_FAILED);
+        let other_program = "Jan 1 12:00:00 host app[7]: account locked for alice";
+        assert_eq!(classifier.classify(other_program), UNKNOWN_EVENT);
+    }
+
+    #[test]
+    fn test_mappings_take_precedence() {
+        let classifier = EventClassifier::default()
+            .with_detailed_failures(true)
+            .with_mappings(&[EventTypeMapping {
+                pattern: "Failed password for invalid user".to_string(),
+                event_type: "SSH_SPRAY".to_string(),
+            }]);
+        assert_eq!(classifier.classify(LINES[1].0), "SSH_SPRAY");
+        assert_eq!(classifier.classify(LINES[0].0), SSH_FAILED_PASSWORD);
+    }
+}
@@ -0,0 +1,239 @@
+//! Clock jump detection
+//!
+//! A host whose clock is stepped (NTP/chrony correction, manual change)
+//! logs events whose timestamps leap forward or backward, which wrecks
+//! window and velocity math: a backward step makes two logins look
+//! simultaneous. [`ClockGuard`] notices steps, either from clock
+//! adjustment lines (classified as `CLOCK_STEP`) or from a sudden change
+//! in the offset between event timestamps and the time they were
+//! received, and suspends time-sensitive rules for a grace period so
+//! nothing is learned from or compared against the skewed timestamps.
+//!
+//! The offset is tracked per logging host, since hosts' clocks differ and
+//! a merged stream would otherwise look like it jumps between them. A
+//! backlog catching up moves the offset forward too, but towards receive
+//! time, so a forward move only counts once events are stamped ahead of
+//! it. In replay mode receive time means nothing and the offset check is
+//! off; adjustment lines still count.
+
+use crate::config::ClockGuardConfig;
+use crate::detection::bounded_map::{BoundedMap, DEFAULT_MAX_TRACKED_ENTRIES};
+use crate::models::LogEvent;
+
+/// Event type of chrony/ntpd/timesyncd clock adjustment lines
+pub const CLOCK_STEP: &str = "CLOCK_STEP";
+
+/// Programs whose clock adjustment lines are trusted
+pub(crate) const CLOCK_PROGRAMS: &[&str] = &["chronyd", "ntpd", "systemd-timesyncd", "systemd"];
+
+/// Phrases logged when a time daemon steps the system clock
+pub(crate) const CLOCK_STEP_PHRASES: &[&str] = &[
+    // chronyd
+    "System clock wrong by",
+    "System clock was stepped by",
+    // ntpd
+    "step time server",
+    "time reset",
+    // systemd-timesyncd / systemd
+    "Time has been changed",
+    "Clock change detected",
+];
+
+/// How a clock jump was noticed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockJumpSource {
+    /// A time daemon logged a clock adjustment
+    AdjustmentLine,
+    /// Event timestamps moved relative to the time they were received
+    TimestampJump,
+}
+
+/// A detected clock jump
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockJump {
+    pub source: ClockJumpSource,
+    /// Change in event-time offset in seconds (positive = forward);
+    /// None for adjustment lines
+    pub skew_seconds: Option<i64>,
+}
+
+/// Tracks event timestamps against receive time and flags clock steps
+pub struct ClockGuard {
+    max_jump_seconds: i64,
+    suspend_seconds: i64,
+    /// Whether offset changes are checked at all
+    timestamp_jumps: bool,
+    /// Maps host ("" when unknown) -> offset (event timestamp - received
+    /// at) of its previous event
+    last_offsets: BoundedMap<String, i64>,
+    /// Time-sensitive rules are suspended until this receive time
+    suspended_until: Option<i64>,
+}
+
+impl ClockGuard {
+    pub fn new(config: &ClockGuardConfig) -> Self {
+        ClockGuard {
+            max_jump_seconds: config.max_jump_seconds,
+            suspend_seconds: config.suspend_seconds,
+            timestamp_jumps: true,
+            last_offsets: BoundedMap::new("clock_offsets", Some(DEFAULT_MAX_TRACKED_ENTRIES)),
+            suspended_until: None,
+        }
+    }
+
+    /// Whether to detect steps from event timestamps (off in replay
+    /// mode, where events aren't received in real time)
+    pub fn with_timestamp_jumps(mut self, enabled: bool) -> Self {
+        self.timestamp_jumps = enabled;
+        self
+    }
+
+    /// Observe an event received at `received_at` (wall-clock seconds)
+    ///
+    /// Returns the jump if this event revealed one; time-sensitive rules
+    /// are then suspended for `suspend_seconds` of receive time.
+    pub fn observe(&mut self, event: &LogEvent, received_at: i64) -> Option<ClockJump> {
+        let jump = if event.event_type == CLOCK_STEP {
+            Some(ClockJump {
+                source: ClockJumpSource::AdjustmentLine,
+                skew_seconds: None,
+            })
+        } else if self.timestamp_jumps {
+            let offset = event.timestamp - received_at;
+            let host = event.host.clone().unwrap_or_default();
+            let last = self.last_offsets.get(&host).copied();
+            self.last_offsets.insert(host, offset);
+            let max_jump = self.max_jump_seconds;
+            last.map(|last| offset - last)
+                // Catching up on a backlog moves forward towards receive time
+                .filter(|skew| *skew < -max_jump || (*skew > max_jump && offset > max_jump))
+                .map(|skew| ClockJump {
+                    source: ClockJumpSource::TimestampJump,
+                    skew_seconds: Some(skew),
+                })
+        } else {
+            None
+        };
+
+        if jump.is_some() {
+            self.suspended_until = Some(received_at + self.suspend_seconds);
+        }
+        jump
+    }
+
+    /// Whether time-sensitive rules should skip events received at `now`
+    pub fn time_rules_suspended(&self, now: i64) -> bool {
+        self.suspended_until.is_some_and(|until| now < until)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detection::{GeoLocation, GeoVelocityTracker};
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    const LONDON: GeoLocation = GeoLocation { latitude: 51.5074, longitude: -0.1278 };
+    const NYC: GeoLocation = GeoLocation { latitude: 40.7128, longitude: -74.0060 };
+    const DAY: i64 = 86400;
+
+    fn create_event(event_type: &str, timestamp: i64) -> LogEvent {
+        LogEvent {
+            timestamp,
+            user: "alice".to_string(),
+            ip_address: IpAddr::from_str("203.0.113.5").unwrap(),
+            event_type: event_type.to_string(),
+            host: None,
+        }
+    }
+
+    fn on_host(host: &str, timestamp: i64) -> LogEvent {
+        LogEvent {
+            host: Some(host.to_string()),
+            ..create_event("SSH_LOGIN", timestamp)
+        }
+    }
+
+    /// (event, received at, location) in arrival order: the clock jumps a
+    /// day ahead, chrony steps it back, and alice flies to New York
+    fn jump_sequence() -> Vec<(LogEvent, i64, Option<GeoLocation>)> {
+        vec![
+            (create_event("SSH_LOGIN", 1000), 1000, Some(LONDON)),
+            (create_event("SSH_LOGIN", 1400 + DAY), 1400, Some(LONDON)),
+            (create_event(CLOCK_STEP, 1500), 1500, None),
+            (create_event("SSH_LOGIN", 1500 + 8 * 3600), 1500 + 8 * 3600, Some(NYC)),
+        ]
+    }
+
+    fn travel_reports(guard: Option<&mut ClockGuard>) -> usize {
+        let mut guard = guard;
+        // Every event shares one IP, so same-IP logins have to be evaluated
+        let mut tracker = GeoVelocityTracker::with_max_velocity(900.0).with_skip_same_ip(false);
+        let mut reports = 0;
+        for (event, received_at, location) in jump_sequence() {
+            if let Some(guard) = guard.as_deref_mut() {
+                guard.observe(&event, received_at);
+                if guard.time_rules_suspended(received_at) {
+                    continue;
+                }
+            }
+            if let Some(location) = location {
+                reports += tracker.check_impossible_travel(&event, location).into_iter().count();
+            }
+        }
+        reports
+    }
+
+    #[test]
+    fn test_velocity_not_triggered_across_jump() {
+        // Unguarded, the skewed London login makes New York look earlier
+        assert_eq!(travel_reports(None), 1);
+
+        let mut guard = ClockGuard::new(&ClockGuardConfig::default());
+        assert_eq!(travel_reports(Some(&mut guard)), 0);
+    }
+
+    #[test]
+    fn test_jump_detection() {
+        let mut guard = ClockGuard::new(&ClockGuardConfig::default());
+        assert_eq!(guard.observe(&create_event("SSH_LOGIN", 1000), 1000), None);
+        // A quiet hour isn't a jump: timestamps track receive time
+        assert_eq!(guard.observe(&create_event("SSH_LOGIN", 4600), 4600), None);
+        assert!(!guard.time_rules_suspended(4600));
+
+        let jump = guard.observe(&create_event("SSH_LOGIN", 4700 + DAY), 4700).unwrap();
+        assert_eq!(jump.source, ClockJumpSource::TimestampJump);
+        assert_eq!(jump.skew_seconds, Some(DAY));
+        assert!(guard.time_rules_suspended(4800));
+        assert!(!guard.time_rules_suspended(4700 + ClockGuardConfig::default().suspend_seconds));
+
+        let jump = guard.observe(&create_event(CLOCK_STEP, 9000), 9000).unwrap();
+        assert_eq!(jump.source, ClockJumpSource::AdjustmentLine);
+    }
+
+    #[test]
+    fn test_backlogs_and_skewed_hosts_are_not_jumps() {
+        let mut guard = ClockGuard::new(&ClockGuardConfig::default());
+        // A five-hour backlog read in ten seconds
+        for i in 0..30 {
+            assert_eq!(guard.observe(&on_host("web-01", 100_000 - 5 * 3600 + i * 600), 100_000 + i / 3), None);
+        }
+        // Two hosts, one with its clock twenty minutes slow, interleaved
+        for i in 0..10 {
+            assert_eq!(guard.observe(&on_host("web-01", 101_000 + i), 101_000 + i), None);
+            assert_eq!(guard.observe(&on_host("db-01", 101_000 + i - 1200), 101_000 + i), None);
+        }
+        // ...but one host's clock stepping back still is
+        let jump = guard.observe(&on_host("db-01", 101_010 - 2 * DAY), 101_010).unwrap();
+        assert_eq!(jump.skew_seconds, Some(-2 * DAY + 1200));
+    }
+
+    #[test]
+    fn test_replay_only_trusts_adjustment_lines() {
+        let mut guard = ClockGuard::new(&ClockGuardConfig::default()).with_timestamp_jumps(false);
+        assert_eq!(guard.observe(&create_event("SSH_LOGIN", 1000), 5000), None);
+        assert_eq!(guard.observe(&create_event("SSH_LOGIN", 1000 + DAY), 5001), None);
+        assert!(guard.observe(&create_event(CLOCK_STEP, 1000 + DAY), 5002).is_some());
+    }
+}
@@ -0,0 +1,187 @@
+//! Cross-source event de-duplication
+//!
+//! A log shipped to two places (say a local file and its NFS copy) and
+//! tailed from both yields every event twice, doubling rate limit counts
+//! and baselines. With a window set, [`EventDeduplicator`] remembers a
+//! hash of each event's user, IP, host, timestamp and type along with the
+//! source it first came from, and drops an exact repeat that arrives from
+//! a different source within the window. Repeats from the same source
+//! are real events (several failures in one second) and are kept.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use crate::models::LogEvent;
+
+/// Drops exact duplicate events arriving from another source within a window
+#[derive(Debug, Default)]
+pub struct EventDeduplicator {
+    window_seconds: Option<i64>,
+    /// Hashes of events seen within the window -> source first seen from
+    seen: HashMap<u64, usize>,
+    /// Hashes with when they were first seen, oldest first
+    order: VecDeque<(i64, u64)>,
+    /// Events dropped since the last `take_dropped`
+    dropped: u64,
+}
+
+impl EventDeduplicator {
+    /// Create a deduplicator (no de-duplication when `window_seconds` is None)
+    pub fn new(window_seconds: Option<i64>) -> Self {
+        EventDeduplicator {
+            window_seconds,
+            ..EventDeduplicator::default()
+        }
+    }
+
+    /// Whether a window is set
+    pub fn is_enabled(&self) -> bool {
+        self.window_seconds.is_some()
+    }
+
+    /// Whether an event received from `source` at `now` isn't a copy of
+    /// one another source delivered within the window
+    pub fn should_process(&mut self, event: &LogEvent, source: usize, now: i64) -> bool {
+        let Some(window) = self.window_seconds else {
+            return true;
+        };
+        self.expire(now - window);
+
+        let hash = event_hash(event);
+        match self.seen.get(&hash) {
+            Some(&first_source) if first_source != source => {
+                self.dropped += 1;
+                false
+            }
+            Some(_) => true,
+            None => {
+                self.seen.insert(hash, source);
+                self.order.push_back((now, hash));
+                true
+            }
+        }
+    }
+
+    /// Number of events currently remembered
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+
+    /// Events dropped since the last call, resetting the count
+    pub fn take_dropped(&mut self) -> u64 {
+        std::mem::take(&mut self.dropped)
+    }
+
+    /// Forget events first seen at or before `cutoff`
+    fn expire(&mut self, cutoff: i64) {
+        while let Some(&(seen_at, hash)) = self.order.front() {
+            if seen_at > cutoff {
+                break;
+            }
+            self.order.pop_front();
+            self.seen.remove(&hash);
+        }
+    }
+}
+
+/// Hash of the fields that identify an event
+fn event_hash(event: &LogEvent) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    event.user.hash(&mut hasher);
+    event.ip_address.hash(&mut hasher);
+    event.host.hash(&mut hasher);
+    event.timestamp.hash(&mut hasher);
+    event.event_type.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::classify::SSH_FAILED;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    const LOCAL: usize = 0;
+    const NFS_COPY: usize = 1;
+
+    fn create_event(user: &str, timestamp: i64) -> LogEvent {
+        LogEvent {
+            timestamp,
+            user: user.to_string(),
+            ip_address: IpAddr::from_str("203.0.113.5").unwrap(),
+            event_type: SSH_FAILED.to_string(),
+            host: None,
+        }
+    }
+
+    #[test]
+    fn test_copies_from_two_sources_processed_once() {
+        let mut dedup = EventDeduplicator::new(Some(60));
+        let local = [create_event("alice", 1000), create_event("alice", 1001), create_event("bob", 1001)];
+        let nfs_copy = local.clone();
+
+        // The copy lags behind the local file by a few events
+        let mut processed = Vec::new();
+        let arrivals = [
+            (LOCAL, &local[0]),
+            (LOCAL, &local[1]),
+            (NFS_COPY, &nfs_copy[0]),
+            (LOCAL, &local[2]),
+            (NFS_COPY, &nfs_copy[1]),
+            (NFS_COPY, &nfs_copy[2]),
+        ];
+        for (now, (source, event)) in (5000..).zip(arrivals) {
+            if dedup.should_process(event, source, now) {
+                processed.push(event.clone());
+            }
+        }
+
+        assert_eq!(processed.len(), 3);
+        assert_eq!(processed[0].user, "alice");
+        assert_eq!(processed[2].user, "bob");
+        assert_eq!(dedup.take_dropped(), 3);
+        assert_eq!(dedup.take_dropped(), 0);
+    }
+
+    #[test]
+    fn test_repeats_within_one_source_kept() {
+        let mut dedup = EventDeduplicator::new(Some(60));
+        // Three failures in the same second from one source are three attempts
+        for now in 5000..5003 {
+            assert!(dedup.should_process(&create_event("alice", 1000), LOCAL, now));
+        }
+        assert!(!dedup.should_process(&create_event("alice", 1000), NFS_COPY, 5003));
+
+        // The same login on two hosts isn't a copy
+        let mut other_host = create_event("alice", 1000);
+        other_host.host = Some("web-02".to_string());
+        assert!(dedup.should_process(&other_host, NFS_COPY, 5004));
+        assert_eq!(dedup.take_dropped(), 1);
+    }
+
+    #[test]
+    fn test_repeat_after_window_processed() {
+        let mut dedup = EventDeduplicator::new(Some(60));
+        assert!(dedup.should_process(&create_event("alice", 1000), LOCAL, 5000));
+        assert!(!dedup.should_process(&create_event("alice", 1000), NFS_COPY, 5059));
+        assert!(dedup.should_process(&create_event("alice", 1000), NFS_COPY, 5061));
+
+        // Expired entries are forgotten
+        assert!(dedup.should_process(&create_event("bob", 1000), LOCAL, 6000));
+        assert_eq!(dedup.len(), 1);
+    }
+
+    #[test]
+    fn test_disabled_keeps_everything() {
+        let mut dedup = EventDeduplicator::new(None);
+        assert!(!dedup.is_enabled());
+        assert!(dedup.should_process(&create_event("alice", 1000), LOCAL, 5000));
+        assert!(dedup.should_process(&create_event("alice", 1000), NFS_COPY, 5000));
+        assert!(dedup.is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+//! Named pipe (FIFO) log source
+//!
+//! Some integrations write their log to a FIFO instead of a file. A FIFO
+//! can't be seeked or rotated like a file, so rather than tailing it
+//! [`AsyncFifoReader`] reads lines as they arrive. When the writer closes
+//! its end the reader sees end-of-file and reopens the path, picking up
+//! the next writer (or a FIFO the writer recreated).
+
+use crate::models::LogEvent;
+use super::parser::{LineParser, LogParser};
+use super::stats::IngestionStats;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader as AsyncBufReader};
+use tokio::net::unix::pipe;
+use tokio::sync::mpsc;
+
+/// Pause after reopening, so platforms that report end-of-file until a
+/// writer connects don't spin
+const REOPEN_DELAY: Duration = Duration::from_millis(100);
+
+/// Async line reader over a named pipe
+pub struct AsyncFifoReader {
+    path: PathBuf,
+    parser: Box<dyn LogParser>,
+    stats: Arc<IngestionStats>,
+}
+
+impl AsyncFifoReader {
+    /// Create a reader for the FIFO at `path`
+    pub fn new(path: PathBuf) -> Self {
+        AsyncFifoReader {
+            path,
+            parser: Box::new(LineParser::default()),
+            stats: Arc::new(IngestionStats::new()),
+        }
+    }
+
+    /// Parse lines with a parser configured for this source
+    pub fn with_parser(mut self, parser: impl LogParser + 'static) -> Self {
+        self.parser = Box::new(parser);
+        self
+    }
+
+    /// Record parse outcomes into shared ingestion counters
+    pub fn with_stats(mut self, stats: Arc<IngestionStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    /// Run the reader, sending events through the channel
+    ///
+    /// Runs until the channel is closed; fails if the path can't be
+    /// opened or isn't a FIFO.
+    pub async fn run(
+        &mut self,
+        tx: mpsc::Sender<LogEvent>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        log::info!("FIFO reader started on {:?}", self.path);
+
+        let mut receiver = pipe::OpenOptions::new().open_receiver(&self.path)?;
+        loop {
+            let mut lines = AsyncBufReader::new(receiver).lines();
+            while let Some(line) = lines.next_line().await? {
+                let parsed = self.parser.parse(&line);
+                self.stats.record(&parsed);
+                if let Ok(event) = parsed {
+                    if tx.send(event).await.is_err() {
+                        log::info!("Channel closed, stopping FIFO reader");
+                        return Ok(());
+                    }
+                }
+            }
+
+            // The writer closed its end; wait for the next one
+            log::debug!("Writer closed {:?}, reopening", self.path);
+            if tx.is_closed() {
+                return Ok(());
+            }
+            // Open before closing the old end, so the pipe stays alive and
+            // nothing a new writer has already written is lost
+            receiver = pipe::OpenOptions::new().open_receiver(&self.path)?;
+            drop(lines);
+            tokio::time::sleep(REOPEN_DELAY).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn mkfifo(path: &std::path::Path) {
+        let status = std::process::Command::new("mkfifo").arg(path).status().unwrap();
+        assert!(status.success());
+    }
+
+    /// Open the FIFO as a writer (blocking until the reader is open),
+    /// write the lines and close it again
+    async fn write_lines(path: PathBuf, lines: &'static str) {
+        tokio::task::spawn_blocking(move || {
+            let mut writer = std::fs::OpenOptions::new().write(true).open(path).unwrap();
+            writer.write_all(lines.as_bytes()).unwrap();
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reads_events_across_writers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("auth.fifo");
+        mkfifo(&path);
+
+        let (tx, mut rx) = mpsc::channel(10);
+        let mut reader = AsyncFifoReader::new(path.clone());
+        let task = tokio::spawn(async move { reader.run(tx).await.unwrap() });
+
+        write_lines(
+            path.clone(),
+            "Jan 1 12:00:00 host sshd[1]: Accepted publickey for alice from 10.0.0.1 port 22\n\
+             Jan 1 12:00:01 host sshd[1]: Failed password for bob from 10.0.0.2 port 22\n",
+        )
+        .await;
+        assert_eq!(rx.recv().await.unwrap().user, "alice");
+        assert_eq!(rx.recv().await.unwrap().user, "bob");
+
+        // A new writer after the first closed the pipe is read too
+        write_lines(path, "Jan 1 12:00:02 host sshd[1]: Accepted password for carol from 10.0.0.3 port 22\n").await;
+        let event = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(event.user, "carol");
+        assert_eq!(event.event_type, "SSH_LOGIN");
+
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_regular_file_rejected() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let (tx, _rx) = mpsc::channel(10);
+        let mut reader = AsyncFifoReader::new(file.path().to_path_buf());
+        assert!(reader.run(tx).await.is_err());
+    }
+}
@@ -1,16 +1,18 @@
 use crate::models::LogEvent;
+use super::parser::{LineParser, LogParser};
+use super::stats::IngestionStats;
 use std::fs::File;
 use std::io::{BufRead, BufReader, Seek, SeekFrom};
 use std::path::PathBuf;
-use std::time::Duration;
-use std::net::IpAddr;
-use std::str::FromStr;
+use std::sync::Arc;
 
 /// Tail a log file and parse log events
 pub struct FileTailer {
     file_path: PathBuf,
     reader: Option<BufReader<File>>,
     file_position: u64,
+    parser: Box<dyn LogParser>,
+    stats: Arc<IngestionStats>,
 }
 
 impl FileTailer {
@@ -20,9 +22,23 @@ impl FileTailer {
             file_path,
             reader: None,
             file_position: 0,
+            parser: Box::new(LineParser::default()),
+            stats: Arc::new(IngestionStats::new()),
         }
     }
 
+    /// Parse lines with a parser configured for this source
+    pub fn with_parser(mut self, parser: impl LogParser + 'static) -> Self {
+        self.parser = Box::new(parser);
+        self
+    }
+
+    /// Record parse outcomes into shared ingestion counters
+    pub fn with_stats(mut self, stats: Arc<IngestionStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+
     /// Initialize the file reader
     pub fn initialize(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         let file = File::open(&self.file_path)?;
@@ -57,7 +73,9 @@ impl FileTailer {
             self.file_position += bytes_read as u64;
 
             // Try to parse the line as a log event
-            if let Ok(event) = Self::parse_log_line(&line) {
+            let parsed = self.parser.parse(&line);
+            self.stats.record(&parsed);
+            if let Ok(event) = parsed {
                 events.push(event);
             }
         }
@@ -65,57 +83,6 @@ impl FileTailer {
         Ok(events)
     }
 
-    /// Parse a log line into a LogEvent
-    /// This is a basic parser - in production, you'd want a more robust parser
-    /// that handles different log formats (syslog, auth.log, etc.)
-    fn parse_log_line(line: &str) -> Result<LogEvent, Box<dyn std::error::Error>> {
-        // Basic SSH log format parser (simplified)
-        // Example: "Jan 1 12:00:00 hostname sshd[1234]: Accepted publickey for user from 192.168.1.1"
-        
-        // Try to extract IP address
-        let ip_pattern = regex::Regex::new(r"\b(\d{1,3}\.\d{1,3}\.\d{1,3}\.\d{1,3})\b")?;
-        let ip_addr = if let Some(cap) = ip_pattern.find(line) {
-            IpAddr::from_str(cap.as_str())?
-        } else {
-            IpAddr::from_str("0.0.0.0")? // Default if not found
-        };
-
-        // Try to extract username (after "for")
-        let user = if let Some(pos) = line.find("for ") {
-            let after_for = &line[pos + 4..];
-            if let Some(end_pos) = after_for.find(' ') {
-                after_for[..end_pos].to_string()
-            } else if let Some(end_pos) = after_for.find(" from") {
-                after_for[..end_pos].to_string()
-            } else {
-                "unknown".to_string()
-            }
-        } else {
-            "unknown".to_string()
-        };
-
-        // Get current timestamp (in a real implementation, parse from log line)
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)?
-            .as_secs() as i64;
-
-        // Determine event type
-        let event_type = if line.contains("Accepted") || line.contains("Successful") {
-            "SSH_LOGIN".to_string()
-        } else if line.contains("Failed") || line.contains("Invalid") {
-            "SSH_FAILED".to_string()
-        } else {
-            "UNKNOWN".to_string()
-        };
-
-        Ok(LogEvent {
-            timestamp,
-            user,
-            ip_address: ip_addr,
-            event_type,
-        })
-    }
-
     /// Check if the file still exists and is readable
     pub fn is_valid(&self) -> bool {
         self.file_path.exists()
@@ -129,17 +96,62 @@ impl FileTailer {
 use tokio::fs::File as AsyncFile;
 use tokio::io::{AsyncBufReadExt, AsyncSeekExt, BufReader as AsyncBufReader};
 use tokio::sync::mpsc;
-use tokio::time::{sleep, Duration as TokioDuration};
+use tokio::time::{sleep, Duration as TokioDuration, Instant};
+
+/// How often the path is checked for rotation, by default
+pub const DEFAULT_ROTATION_CHECK_INTERVAL: TokioDuration = TokioDuration::from_secs(1);
 
 /// Async version of FileTailer for use with tokio
+///
+/// Follows the path across log rotation: while idle at end of file it
+/// periodically checks whether the path now names a different file (a
+/// new inode after logrotate renamed the old one) or one shorter than the
+/// position read so far (truncated in place), and if so reopens it from
+/// the start.
 pub struct AsyncFileTailer {
     file_path: PathBuf,
+    parser: Box<dyn LogParser>,
+    stats: Arc<IngestionStats>,
+    rotation_check_interval: TokioDuration,
 }
 
 impl AsyncFileTailer {
     /// Create a new async file tailer
     pub fn new(file_path: PathBuf) -> Self {
-        AsyncFileTailer { file_path }
+        AsyncFileTailer {
+            file_path,
+            parser: Box::new(LineParser::default()),
+            stats: Arc::new(IngestionStats::new()),
+            rotation_check_interval: DEFAULT_ROTATION_CHECK_INTERVAL,
+        }
+    }
+
+    /// Parse lines with a parser configured for this source
+    pub fn with_parser(mut self, parser: impl LogParser + 'static) -> Self {
+        self.parser = Box::new(parser);
+        self
+    }
+
+    /// Record parse outcomes into shared ingestion counters
+    pub fn with_stats(mut self, stats: Arc<IngestionStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    /// Check the path for rotation this often while idle
+    pub fn with_rotation_check_interval(mut self, interval: TokioDuration) -> Self {
+        self.rotation_check_interval = interval;
+        self
+    }
+
+    /// Whether the path names a different file than the one open, or one
+    /// shorter than what has been read
+    async fn rotated(&self, identity: Option<(u64, u64)>, position: u64) -> bool {
+        match tokio::fs::metadata(&self.file_path).await {
+            Ok(metadata) => file_identity(&metadata) != identity || metadata.len() < position,
+            // Renamed away and not recreated yet; keep the old file
+            Err(_) => false,
+        }
     }
 
     /// Run the file tailer, sending events through the channel
@@ -151,10 +163,12 @@ impl AsyncFileTailer {
         tx: mpsc::Sender<LogEvent>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let file = AsyncFile::open(&self.file_path).await?;
+        let mut identity = file_identity(&file.metadata().await?);
         let mut reader = AsyncBufReader::new(file);
 
         // Seek to end of file to start tailing
-        reader.seek(std::io::SeekFrom::End(0)).await?;
+        let mut position = reader.seek(std::io::SeekFrom::End(0)).await?;
+        let mut last_rotation_check = Instant::now();
 
         log::info!("Async file tailer started for {:?}", self.file_path);
 
@@ -163,12 +177,31 @@ impl AsyncFileTailer {
 
             match reader.read_line(&mut line).await {
                 Ok(0) => {
-                    // EOF - wait for more data
+                    // EOF - the old file is drained, so follow a rotation
+                    if last_rotation_check.elapsed() >= self.rotation_check_interval {
+                        last_rotation_check = Instant::now();
+                        if self.rotated(identity, position).await {
+                            match AsyncFile::open(&self.file_path).await {
+                                Ok(file) => {
+                                    log::info!("Log file {:?} rotated, reopening from the start", self.file_path);
+                                    identity = file_identity(&file.metadata().await?);
+                                    reader = AsyncBufReader::new(file);
+                                    position = 0;
+                                    continue;
+                                }
+                                Err(e) => log::warn!("Failed to reopen rotated log file {:?}: {}", self.file_path, e),
+                            }
+                        }
+                    }
+                    // Wait for more data
                     sleep(TokioDuration::from_millis(100)).await;
                 }
-                Ok(_) => {
+                Ok(bytes_read) => {
+                    position += bytes_read as u64;
                     // Parse the line and send the event
-                    if let Ok(event) = Self::parse_log_line(&line) {
+                    let parsed = self.parser.parse(&line);
+                    self.stats.record(&parsed);
+                    if let Ok(event) = parsed {
                         if tx.send(event).await.is_err() {
                             log::info!("Channel closed, stopping file tailer");
                             break;
@@ -184,65 +217,87 @@ impl AsyncFileTailer {
 
         Ok(())
     }
+}
 
-    /// Parse a log line into a LogEvent (same logic as sync version)
-    fn parse_log_line(line: &str) -> Result<LogEvent, Box<dyn std::error::Error + Send + Sync>> {
-        // Try to extract IP address
-        let ip_pattern = regex::Regex::new(r"\b(\d{1,3}\.\d{1,3}\.\d{1,3}\.\d{1,3})\b")?;
-        let ip_addr = if let Some(cap) = ip_pattern.find(line) {
-            IpAddr::from_str(cap.as_str())?
-        } else {
-            IpAddr::from_str("0.0.0.0")?
-        };
-
-        // Try to extract username (after "for")
-        let user = if let Some(pos) = line.find("for ") {
-            let after_for = &line[pos + 4..];
-            if let Some(end_pos) = after_for.find(' ') {
-                after_for[..end_pos].to_string()
-            } else if let Some(end_pos) = after_for.find(" from") {
-                after_for[..end_pos].to_string()
-            } else {
-                "unknown".to_string()
-            }
-        } else {
-            "unknown".to_string()
-        };
-
-        // Get current timestamp
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)?
-            .as_secs() as i64;
-
-        // Determine event type
-        let event_type = if line.contains("Accepted") || line.contains("Successful") {
-            "SSH_LOGIN".to_string()
-        } else if line.contains("Failed") || line.contains("Invalid") {
-            "SSH_FAILED".to_string()
-        } else {
-            "UNKNOWN".to_string()
-        };
-
-        Ok(LogEvent {
-            timestamp,
-            user,
-            ip_address: ip_addr,
-            event_type,
-        })
-    }
+/// Device and inode of a file, where the platform has them
+#[cfg(unix)]
+fn file_identity(metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_identity(_metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    None
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::input::EventClassifier;
 
     #[test]
     fn test_parse_log_line() {
         let line = "Jan 1 12:00:00 hostname sshd[1234]: Accepted publickey for alice from 192.168.1.100 port 12345";
-        let event = FileTailer::parse_log_line(line).unwrap();
+        let event = LineParser::default().parse(line).unwrap();
         assert_eq!(event.user, "alice");
         assert_eq!(event.ip_address.to_string(), "192.168.1.100");
         assert_eq!(event.event_type, "SSH_LOGIN");
     }
+
+    #[test]
+    fn test_parse_detailed_failure_types() {
+        let parser = LineParser::default().with_classifier(EventClassifier::default().with_detailed_failures(true));
+        let line = "Jan 1 12:00:00 hostname sshd[1234]: error: maximum authentication attempts exceeded for root from 203.0.113.5 port 22 ssh2 [preauth]";
+        let event = parser.parse(line).unwrap();
+        assert_eq!(event.user, "root");
+        assert_eq!(event.event_type, "SSH_FAILED_MAX_AUTH");
+        assert!(event.is_failed_login());
+
+        let line = "Jan 1 12:00:05 hostname sshd[1234]: Failed password for root from 203.0.113.5 port 22 ssh2";
+        let event = parser.parse(line).unwrap();
+        assert_eq!(event.event_type, "SSH_FAILED_PASSWORD");
+    }
+
+    fn append(path: &std::path::Path, lines: &str) {
+        use std::io::Write;
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path).unwrap();
+        file.write_all(lines.as_bytes()).unwrap();
+    }
+
+    async fn next_user(rx: &mut mpsc::Receiver<LogEvent>) -> String {
+        tokio::time::timeout(TokioDuration::from_secs(5), rx.recv()).await.unwrap().unwrap().user
+    }
+
+    #[tokio::test]
+    async fn test_follows_rotated_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("auth.log");
+        append(&path, "Jan 1 11:59:59 host sshd[1]: Accepted password for old from 10.0.0.9 port 22\n");
+
+        let (tx, mut rx) = mpsc::channel(10);
+        let mut tailer = AsyncFileTailer::new(path.clone()).with_rotation_check_interval(TokioDuration::from_millis(50));
+        let task = tokio::spawn(async move { tailer.run(tx).await.unwrap() });
+        sleep(TokioDuration::from_millis(200)).await;
+
+        append(&path, "Jan 1 12:00:00 host sshd[1]: Accepted publickey for alice from 10.0.0.1 port 22\n");
+        assert_eq!(next_user(&mut rx).await, "alice");
+
+        // logrotate renames the file and creates a new one; lines written
+        // to the old file before the switch are still read
+        std::fs::rename(&path, dir.path().join("auth.log.1")).unwrap();
+        append(&dir.path().join("auth.log.1"), "Jan 1 12:00:01 host sshd[1]: Failed password for bob from 10.0.0.2 port 22\n");
+        append(&path, "Jan 1 12:00:02 host sshd[1]: Accepted password for carol from 10.0.0.3 port 22\n");
+        assert_eq!(next_user(&mut rx).await, "bob");
+        assert_eq!(next_user(&mut rx).await, "carol");
+
+        // Truncation in place (copytruncate) is followed too
+        std::fs::write(&path, "").unwrap();
+        sleep(TokioDuration::from_millis(300)).await;
+        append(&path, "Jan 1 12:00:03 host sshd[1]: Accepted password for dave from 10.0.0.4 port 22\n");
+        assert_eq!(next_user(&mut rx).await, "dave");
+
+        task.abort();
+    }
 }
 
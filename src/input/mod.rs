@@ -1,10 +1,38 @@
+pub mod age;
+pub mod classify;
+pub mod clock;
+pub mod dedup;
+#[cfg(unix)]
+pub mod fifo_reader;
 pub mod file_tailer;
+pub mod normalize;
+pub mod parser;
+pub mod reorder;
+pub mod sampling;
+pub mod stats;
+pub mod stdin_reader;
 pub mod syslog_listener;
+pub mod tcp_syslog;
+pub mod timestamp;
 
+pub use age::EventAgeFilter;
+pub use classify::EventClassifier;
+pub use clock::{ClockGuard, ClockJump, ClockJumpSource, CLOCK_STEP};
+pub use dedup::EventDeduplicator;
 pub use file_tailer::FileTailer;
+pub use normalize::UsernameNormalizer;
+pub use parser::{LineParser, LogParser, NginxAccessParser, ParseError, SshdParser};
+pub use reorder::EventReorderBuffer;
+pub use sampling::EventSampler;
+pub use stats::{IngestionSnapshot, IngestionStats};
 pub use syslog_listener::SyslogListener;
+pub use timestamp::{parse_syslog_timestamp, TimestampRegistry};
 
 // Async versions
 pub use file_tailer::AsyncFileTailer;
+#[cfg(unix)]
+pub use fifo_reader::AsyncFifoReader;
 pub use syslog_listener::AsyncSyslogListener;
+pub use stdin_reader::AsyncStdinReader;
+pub use tcp_syslog::AsyncTcpSyslogListener;
 
@@ -0,0 +1,129 @@
+//! Username normalization
+//!
+//! Collapses different spellings of the same account (`ALICE`,
+//! `alice@example.com`, `EXAMPLE\alice`) into a single canonical form
+//! so all detection rules key on the same user.
+
+use crate::config::UsernameNormalizationConfig;
+use crate::models::LogEvent;
+
+/// Applies the configured username transformations to log events
+#[derive(Debug, Clone, Default)]
+pub struct UsernameNormalizer {
+    config: UsernameNormalizationConfig,
+}
+
+impl UsernameNormalizer {
+    /// Create a normalizer from configuration
+    pub fn new(config: UsernameNormalizationConfig) -> Self {
+        UsernameNormalizer { config }
+    }
+
+    /// Check if any transformation is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.config.lowercase || self.config.strip_domain || self.config.strip_domain_prefix
+    }
+
+    /// Normalize a single username
+    pub fn normalize(&self, user: &str) -> String {
+        let mut user = user.trim();
+
+        // DOMAIN\user -> user
+        if self.config.strip_domain_prefix {
+            if let Some(pos) = user.rfind('\\') {
+                user = &user[pos + 1..];
+            }
+        }
+
+        // user@example.com -> user
+        if self.config.strip_domain {
+            if let Some(pos) = user.find('@') {
+                if pos > 0 {
+                    user = &user[..pos];
+                }
+            }
+        }
+
+        if self.config.lowercase {
+            user.to_lowercase()
+        } else {
+            user.to_string()
+        }
+    }
+
+    /// Normalize the user field of an event in place
+    pub fn apply(&self, event: &mut LogEvent) {
+        if self.is_enabled() {
+            event.user = self.normalize(&event.user);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detection::LoginRateLimiter;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    fn all_enabled() -> UsernameNormalizer {
+        UsernameNormalizer::new(UsernameNormalizationConfig {
+            lowercase: true,
+            strip_domain: true,
+            strip_domain_prefix: true,
+        })
+    }
+
+    fn create_event(user: &str, timestamp: i64) -> LogEvent {
+        LogEvent {
+            timestamp,
+            user: user.to_string(),
+            ip_address: IpAddr::from_str("1.1.1.1").unwrap(),
+            event_type: "SSH_FAILED".to_string(),
+            host: None,
+        }
+    }
+
+    #[test]
+    fn test_representations_collapse() {
+        let normalizer = all_enabled();
+        for user in ["alice", "ALICE", "alice@example.com", "EXAMPLE\\alice", "Example\\Alice@corp"] {
+            assert_eq!(normalizer.normalize(user), "alice", "failed for {}", user);
+        }
+    }
+
+    #[test]
+    fn test_transformations_are_toggleable() {
+        let normalizer = UsernameNormalizer::new(UsernameNormalizationConfig {
+            lowercase: false,
+            strip_domain: true,
+            strip_domain_prefix: false,
+        });
+        assert_eq!(normalizer.normalize("Alice@example.com"), "Alice");
+        assert_eq!(normalizer.normalize("EXAMPLE\\Alice"), "EXAMPLE\\Alice");
+
+        let disabled = UsernameNormalizer::default();
+        let mut event = create_event("ALICE", 0);
+        disabled.apply(&mut event);
+        assert_eq!(event.user, "ALICE");
+    }
+
+    #[test]
+    fn test_rate_limit_accumulates_across_representations() {
+        let normalizer = all_enabled();
+        let mut limiter = LoginRateLimiter::with_config(300, 2, 100);
+
+        let mut reports = Vec::new();
+        for (i, user) in ["alice", "ALICE", "alice@example.com", "EXAMPLE\\alice"]
+            .iter()
+            .enumerate()
+        {
+            let mut event = create_event(user, 1700000000 + i as i64);
+            normalizer.apply(&mut event);
+            reports.extend(limiter.check_rate_limit(&event));
+        }
+
+        assert_eq!(limiter.get_user_attempt_count("alice"), 4);
+        assert!(reports.iter().any(|r| r.rule_name == "User Rate Limit Exceeded"));
+    }
+}
@@ -0,0 +1,804 @@
+//! Log line parsing
+//!
+//! A [`LogParser`] turns one line from an input source into a
+//! [`LogEvent`]. Every source gets its own parser, so a tailed JSON
+//! application log and BSD-syslog sshd messages can be read side by side
+//! with their own formats. [`LineParser`] is the parser the daemon builds
+//! from `input.log_format`; [`SshdParser`] and [`NginxAccessParser`] can
+//! also be used on their own, and any other format can be read by
+//! implementing the trait.
+//!
+//! - `text` lines are free-form auth log messages: the IP is the address
+//!   after the last "from " (sshd) or after "rhost=" (PAM), falling back
+//!   to the first IPv6, then IPv4, address anywhere in lines with neither;
+//!   the user is the word after "for " (or "for user "), and the event
+//!   type comes from the classifier.
+//!   A line without an address is rejected, unless it is a clock step or
+//!   lockout event, which name none. The host is read from a BSD or RFC
+//!   5424 syslog header when the line has one.
+//! - `json` lines are one object each, read through the configured keys.
+//! - `logfmt` lines are `key=value` pairs, read through the configured
+//!   keys; values may be double-quoted.
+//!
+//!   In both, an event type of `fail`/`failure` becomes `SSH_FAILED` and
+//!   `success`/`ok` `SSH_LOGIN`, so application results feed the rules.
+//! - `nginx_access` lines are web server access log entries (common or
+//!   combined log format): the IP is the client address and the user the
+//!   authenticated user if any. 401 and 403 responses are
+//!   `HTTP_AUTH_FAILED`, 2xx responses to an authenticated user
+//!   `HTTP_LOGIN`, and anything else an `HTTP_REQUEST`.
+//!
+//! In every format IPv4-mapped IPv6 addresses (`::ffff:192.0.2.1`) are
+//! read as IPv4, so a dual-stack listener's clients are tracked under the
+//! same address whichever source logged them. Lines without a usable
+//! timestamp are stamped with the current time.
+
+use crate::config::{FieldsConfig, LineFormat, SourceSpec};
+use crate::models::{LogEvent, UNKNOWN_USER};
+use super::classify::{
+    EventClassifier, ACCOUNT_LOCKED, ACCOUNT_UNLOCKED, HTTP_AUTH_FAILED, HTTP_LOGIN, HTTP_REQUEST, SSH_FAILED, SSH_LOGIN,
+};
+use super::clock::CLOCK_STEP;
+use super::timestamp::TimestampRegistry;
+use regex::Regex;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::net::{AddrParseError, IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use thiserror::Error;
+
+/// Result of parsing a single line
+pub type ParseResult = Result<LogEvent, ParseError>;
+
+/// Errors rejecting a line
+#[derive(Debug, Error)]
+pub enum ParseError {
+    #[error("line names no source address")]
+    NoAddress,
+
+    #[error("invalid address: {0}")]
+    Address(#[from] AddrParseError),
+
+    #[error("invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("{0}")]
+    Malformed(&'static str),
+}
+
+/// A log line format
+///
+/// Every input source reads its lines through one of these, so a format
+/// the built-in parsers don't cover only needs an implementation passed
+/// to the source's `with_parser`.
+pub trait LogParser: Send + Sync {
+    /// Parse one line into an event
+    fn parse(&self, line: &str) -> ParseResult;
+}
+
+/// Address used when a structured line has no source IP field
+const NO_IP: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+
+/// Text event types that name no source address
+const ADDRESSLESS_EVENTS: &[&str] = &[CLOCK_STEP, ACCOUNT_LOCKED, ACCOUNT_UNLOCKED];
+
+/// Structured event type values read as a failed login (case-insensitive)
+const FAILURE_RESULTS: &[&str] = &["fail", "failed", "failure"];
+
+/// Structured event type values read as a successful login (case-insensitive)
+const SUCCESS_RESULTS: &[&str] = &["success", "succeeded", "ok"];
+
+/// Keys a structured line's fields are read from
+struct FieldKeys<'a> {
+    timestamp: &'a str,
+    user: &'a str,
+    ip: &'a str,
+    event_type: &'a str,
+    message: &'a str,
+}
+
+const JSON_KEYS: FieldKeys<'static> = FieldKeys {
+    timestamp: "timestamp",
+    user: "user",
+    ip: "ip",
+    event_type: "event_type",
+    message: "message",
+};
+
+const LOGFMT_KEYS: FieldKeys<'static> = FieldKeys {
+    timestamp: "time",
+    user: "user",
+    ip: "src_ip",
+    event_type: "result",
+    message: "msg",
+};
+
+impl<'a> FieldKeys<'a> {
+    /// The configured keys, with the format's defaults for unset ones
+    fn new(fields: &'a FieldsConfig, defaults: &FieldKeys<'static>) -> Self {
+        FieldKeys {
+            timestamp: fields.timestamp.as_deref().unwrap_or(defaults.timestamp),
+            user: fields.user.as_deref().unwrap_or(defaults.user),
+            ip: fields.ip.as_deref().unwrap_or(defaults.ip),
+            event_type: fields.event_type.as_deref().unwrap_or(defaults.event_type),
+            message: fields.message.as_deref().unwrap_or(defaults.message),
+        }
+    }
+}
+
+fn ipv4_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"\b(\d{1,3}\.\d{1,3}\.\d{1,3}\.\d{1,3})\b").unwrap())
+}
+
+/// Runs of hex digits, dots and colons holding at least one colon: IPv6
+/// candidates, including bracketed (`[2001:db8::1]:22`) and IPv4-mapped
+/// forms. Timestamps and ports match too and are weeded out by parsing.
+fn ipv6_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"[0-9A-Fa-f.]*:[0-9A-Fa-f:.]*").unwrap())
+}
+
+/// Addresses at the positions sshd (`from <addr> port N`) and PAM
+/// (`rhost=<addr>`) log the source in, brackets stripped
+fn anchored_address() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"(\bfrom |\brhost=)\[?([0-9A-Fa-f:.]+)").unwrap())
+}
+
+/// Read IPv4-mapped IPv6 addresses as IPv4, so a client is tracked under
+/// one key whichever format or stack logged it
+fn canonical(result: ParseResult) -> ParseResult {
+    result.map(|mut event| {
+        event.ip_address = event.ip_address.to_canonical();
+        event
+    })
+}
+
+/// The source address in a free-form line
+///
+/// Usernames are chosen by whoever logs in, so an address-like username
+/// mustn't be mistaken for the source. sshd logs the source after the
+/// user (`Invalid user ::1 from 203.0.113.5 port 22`), so the last
+/// address after "from " wins; PAM logs `rhost=` before the user, so the
+/// first wins there. Only lines with neither are scanned as a whole: the
+/// first valid IPv6 address, else the first IPv4 one.
+fn find_ip(line: &str) -> Result<Option<IpAddr>, AddrParseError> {
+    let anchored: Vec<(bool, IpAddr)> = anchored_address()
+        .captures_iter(line)
+        .filter_map(|caps| {
+            let candidate = caps[2].trim_end_matches('.');
+            let ip = IpAddr::from_str(candidate).ok()?;
+            Some((&caps[1] == "from ", ip))
+        })
+        .collect();
+    let from_sshd = anchored.iter().rev().find(|(from, _)| *from);
+    let from_pam = anchored.iter().find(|(from, _)| !*from);
+    if let Some((_, ip)) = from_sshd.or(from_pam) {
+        return Ok(Some(*ip));
+    }
+
+    let ipv6 = ipv6_pattern()
+        .find_iter(line)
+        .find_map(|candidate| Ipv6Addr::from_str(candidate.as_str().trim_end_matches('.')).ok());
+    if let Some(ip) = ipv6 {
+        return Ok(Some(IpAddr::V6(ip)));
+    }
+    ipv4_pattern().find(line).map(|ip| IpAddr::from_str(ip.as_str())).transpose()
+}
+
+/// `client - user [time] "request" status` (common/combined log format)
+fn access_log_line() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r#"^(\S+) \S+ (\S+) \[[^\]]+\] "[^"]*" (\d{3}) "#).unwrap())
+}
+
+/// `<pri>1 timestamp host app procid msgid` (RFC 5424)
+fn rfc5424_header() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"^<\d{1,3}>\d{1,2} \S+ (\S+) ").unwrap())
+}
+
+/// `[<pri>]Jan  1 12:00:00 host tag[pid]:` (BSD), or the same with an
+/// RFC 3339 timestamp (rsyslog's high-precision format)
+fn bsd_header() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(r"^(?:<\d{1,3}>)?(?:[A-Z][a-z]{2}\s+\d{1,2} \d{2}:\d{2}:\d{2}|\d{4}-\d{2}-\d{2}T\S+) (\S+) [^\s\[\]:]+(?:\[[^\]]*\])?:").unwrap()
+    })
+}
+
+/// Host named in a line's syslog header
+///
+/// BSD headers only count when a tag (`sshd[1]:`) follows the host, so
+/// a header without a host, or a line that merely starts with a
+/// timestamp, yields none. RFC 5424's `-` means no host.
+fn syslog_host(line: &str) -> Option<String> {
+    let caps = rfc5424_header().captures(line).or_else(|| bsd_header().captures(line))?;
+    let host = &caps[1];
+    if host == "-" || host.ends_with(':') {
+        return None;
+    }
+    Some(host.to_string())
+}
+
+/// Parser for free-form auth log lines (sshd, PAM, time daemons, ...)
+#[derive(Debug, Clone)]
+pub struct SshdParser {
+    timestamps: TimestampRegistry,
+    classifier: EventClassifier,
+    extract_host: bool,
+}
+
+impl Default for SshdParser {
+    fn default() -> Self {
+        SshdParser {
+            timestamps: TimestampRegistry::default(),
+            classifier: EventClassifier::default(),
+            extract_host: true,
+        }
+    }
+}
+
+impl SshdParser {
+    /// Use a custom set of timestamp formats
+    pub fn with_timestamp_formats(mut self, timestamps: TimestampRegistry) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+
+    /// Use a custom event type classification
+    pub fn with_classifier(mut self, classifier: EventClassifier) -> Self {
+        self.classifier = classifier;
+        self
+    }
+
+    /// Whether to read the host from syslog headers
+    pub fn with_host_extraction(mut self, enabled: bool) -> Self {
+        self.extract_host = enabled;
+        self
+    }
+}
+
+impl LogParser for SshdParser {
+    fn parse(&self, line: &str) -> ParseResult {
+        canonical(self.parse_line(line))
+    }
+}
+
+impl SshdParser {
+    /// Example: "Jan 1 12:00:00 hostname sshd[1234]: Accepted publickey for user from 192.168.1.1"
+    fn parse_line(&self, line: &str) -> ParseResult {
+        let event_type = self.classifier.classify(line);
+        let ip_address = match find_ip(line)? {
+            Some(ip) => ip,
+            None if ADDRESSLESS_EVENTS.contains(&event_type.as_str()) => NO_IP,
+            None => return Err(ParseError::NoAddress),
+        };
+
+        // The username is the word after "for", or "for user" (pam_faillock)
+        let user = line
+            .find("for ")
+            .map(|pos| &line[pos + 4..])
+            .map(|after_for| after_for.strip_prefix("user ").unwrap_or(after_for))
+            .and_then(|after_for| after_for.split_whitespace().next())
+            .map(str::to_string)
+            .unwrap_or_else(|| UNKNOWN_USER.to_string());
+
+        let timestamp = self.timestamps.parse(line).unwrap_or_else(current_timestamp);
+        let host = if self.extract_host { syslog_host(line) } else { None };
+        Ok(LogEvent::new(timestamp, user, ip_address, event_type).with_host(host))
+    }
+}
+
+/// Parser for web server access log lines (common or combined format)
+#[derive(Debug, Clone, Default)]
+pub struct NginxAccessParser {
+    timestamps: TimestampRegistry,
+}
+
+impl NginxAccessParser {
+    /// Use a custom set of timestamp formats
+    pub fn with_timestamp_formats(mut self, timestamps: TimestampRegistry) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+}
+
+impl LogParser for NginxAccessParser {
+    fn parse(&self, line: &str) -> ParseResult {
+        canonical(self.parse_line(line))
+    }
+}
+
+impl NginxAccessParser {
+    /// Example: `203.0.113.5 - alice [10/Oct/2023:13:55:36 +0000] "GET /admin HTTP/1.1" 401 512 "-" "curl/8.0"`
+    fn parse_line(&self, line: &str) -> ParseResult {
+        let caps = access_log_line().captures(line).ok_or(ParseError::Malformed("not an access log line"))?;
+        let user = match &caps[2] {
+            "-" => UNKNOWN_USER,
+            user => user,
+        };
+        // Only the status says whether authentication was involved; most
+        // requests are page and asset loads that mustn't count as attempts
+        let event_type = match &caps[3] {
+            "401" | "403" => HTTP_AUTH_FAILED,
+            status if status.starts_with('2') && user != UNKNOWN_USER => HTTP_LOGIN,
+            _ => HTTP_REQUEST,
+        };
+
+        let timestamp = self.timestamps.parse(line).unwrap_or_else(current_timestamp);
+        Ok(LogEvent::new(timestamp, user, IpAddr::from_str(&caps[1])?, event_type))
+    }
+}
+
+/// Parser for the lines of one input source, in its configured format
+#[derive(Debug, Clone, Default)]
+pub struct LineParser {
+    format: LineFormat,
+    fields: FieldsConfig,
+    /// Text lines; its timestamps and classifier serve the structured
+    /// formats too
+    sshd: SshdParser,
+    access: NginxAccessParser,
+}
+
+impl LineParser {
+    /// Create a parser for a line format with the built-in timestamp
+    /// formats and default classification
+    pub fn new(format: LineFormat) -> Self {
+        LineParser {
+            format,
+            ..LineParser::default()
+        }
+    }
+
+    /// Create the parser for a configured source
+    ///
+    /// Fails if the source lists an unknown timestamp format.
+    pub fn from_source(source: &SourceSpec, classifier: EventClassifier) -> Result<Self, String> {
+        let timestamps = match source.timestamp_formats {
+            Some(ref names) => TimestampRegistry::from_names(names)?,
+            None => TimestampRegistry::with_builtins(),
+        };
+        Ok(LineParser::new(source.format)
+            .with_fields(source.fields.clone())
+            .with_timestamp_formats(timestamps)
+            .with_classifier(classifier))
+    }
+
+    /// Read JSON and logfmt lines through these keys
+    pub fn with_fields(mut self, fields: FieldsConfig) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    /// Use a custom set of timestamp formats
+    pub fn with_timestamp_formats(mut self, timestamps: TimestampRegistry) -> Self {
+        self.access = self.access.with_timestamp_formats(timestamps.clone());
+        self.sshd = self.sshd.with_timestamp_formats(timestamps);
+        self
+    }
+
+    /// Use a custom event type classification
+    pub fn with_classifier(mut self, classifier: EventClassifier) -> Self {
+        self.sshd = self.sshd.with_classifier(classifier);
+        self
+    }
+
+    /// Whether to read the host from syslog headers of text lines
+    pub fn with_host_extraction(mut self, enabled: bool) -> Self {
+        self.sshd = self.sshd.with_host_extraction(enabled);
+        self
+    }
+
+    pub fn format(&self) -> LineFormat {
+        self.format
+    }
+
+    /// Parse a JSON object line through the configured keys
+    fn parse_json(&self, line: &str) -> ParseResult {
+        let value: Value = serde_json::from_str(line.trim())?;
+        let object = value.as_object().ok_or(ParseError::Malformed("JSON log line is not an object"))?;
+        let fields = FieldKeys::new(&self.fields, &JSON_KEYS);
+        let text = |key: &str| object.get(key).and_then(Value::as_str).filter(|s| !s.is_empty());
+
+        let ip_address = match text(fields.ip) {
+            Some(ip) => IpAddr::from_str(ip)?,
+            None => NO_IP,
+        };
+        let timestamp = match object.get(fields.timestamp) {
+            Some(Value::Number(seconds)) => seconds.as_f64().map(|seconds| seconds as i64),
+            Some(Value::String(timestamp)) => self.sshd.timestamps.parse(timestamp),
+            _ => None,
+        };
+        let event_type = self.structured_event_type(text(fields.event_type), text(fields.message).unwrap_or(line));
+
+        let user = text(fields.user).unwrap_or(UNKNOWN_USER);
+        Ok(LogEvent::new(timestamp.unwrap_or_else(current_timestamp), user, ip_address, event_type))
+    }
+
+    /// Parse a logfmt line through the configured keys
+    fn parse_logfmt(&self, line: &str) -> ParseResult {
+        let pairs = split_logfmt(line);
+        if pairs.is_empty() {
+            return Err(ParseError::Malformed("logfmt line has no key=value pairs"));
+        }
+        let fields = FieldKeys::new(&self.fields, &LOGFMT_KEYS);
+        let text = |key: &str| pairs.get(key).map(String::as_str).filter(|s| !s.is_empty());
+
+        let ip_address = match text(fields.ip) {
+            Some(ip) => IpAddr::from_str(ip)?,
+            None => NO_IP,
+        };
+        let timestamp = text(fields.timestamp).and_then(|timestamp| match timestamp.parse::<i64>() {
+            Ok(seconds) => Some(seconds),
+            Err(_) => self.sshd.timestamps.parse(timestamp),
+        });
+        let event_type = self.structured_event_type(text(fields.event_type), text(fields.message).unwrap_or(line));
+
+        let user = text(fields.user).unwrap_or(UNKNOWN_USER);
+        Ok(LogEvent::new(timestamp.unwrap_or_else(current_timestamp), user, ip_address, event_type))
+    }
+}
+
+impl LineParser {
+    /// Event type of a structured line: its event type field, with
+    /// generic results mapped to the built-in types, else the message
+    /// classified like a text line
+    fn structured_event_type(&self, event_type: Option<&str>, message: &str) -> String {
+        let is = |results: &[&str], value: &str| results.iter().any(|result| value.eq_ignore_ascii_case(result));
+        match event_type {
+            Some(value) if is(FAILURE_RESULTS, value) => SSH_FAILED.to_string(),
+            Some(value) if is(SUCCESS_RESULTS, value) => SSH_LOGIN.to_string(),
+            Some(value) => value.to_string(),
+            None => self.sshd.classifier.classify(message),
+        }
+    }
+}
+
+impl LogParser for LineParser {
+    fn parse(&self, line: &str) -> ParseResult {
+        canonical(match self.format {
+            LineFormat::Text => self.sshd.parse_line(line),
+            LineFormat::Json => self.parse_json(line),
+            LineFormat::Logfmt => self.parse_logfmt(line),
+            LineFormat::NginxAccess => self.access.parse_line(line),
+        })
+    }
+}
+
+/// Split a logfmt line into its `key=value` pairs
+///
+/// Double-quoted values may contain spaces and `\"` / `\\` escapes. Bare
+/// words without `=` are skipped; a repeated key keeps its last value.
+fn split_logfmt(line: &str) -> HashMap<String, String> {
+    let mut pairs = HashMap::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return pairs;
+        }
+
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '=') {
+            key.push(c);
+        }
+        if chars.next_if_eq(&'=').is_none() {
+            continue;
+        }
+
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => value.extend(chars.next()),
+                    c => value.push(c),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                value.push(c);
+            }
+        }
+        if !key.is_empty() {
+            pairs.insert(key, value);
+        }
+    }
+}
+
+fn current_timestamp() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::AsyncStdinReader;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn test_json_fields() {
+        let fields = FieldsConfig {
+            user: Some("username".to_string()),
+            ip: Some("client_ip".to_string()),
+            ..FieldsConfig::default()
+        };
+        let parser = LineParser::new(LineFormat::Json).with_fields(fields);
+
+        let line = r#"{"timestamp": "2023-11-14T22:13:20Z", "username": "alice", "client_ip": "203.0.113.5", "event_type": "APP_LOGIN"}"#;
+        let event = parser.parse(line).unwrap();
+        assert_eq!(event.timestamp, 1700000000);
+        assert_eq!(event.user, "alice");
+        assert_eq!(event.ip_address.to_string(), "203.0.113.5");
+        assert_eq!(event.event_type, "APP_LOGIN");
+
+        // Without an event type the message is classified; numbers are epoch seconds
+        let line = r#"{"timestamp": 1700000000, "message": "Failed password for bob"}"#;
+        let event = parser.parse(line).unwrap();
+        assert_eq!(event.timestamp, 1700000000);
+        assert_eq!(event.user, UNKNOWN_USER);
+        assert_eq!(event.ip_address, NO_IP);
+        assert_eq!(event.event_type, "SSH_FAILED");
+
+        assert!(parser.parse("Jan 1 12:00:00 host sshd[1]: Accepted publickey for alice").is_err());
+        assert!(parser.parse(r#"["not", "an", "object"]"#).is_err());
+        assert!(parser.parse(r#"{"client_ip": "not-an-ip"}"#).is_err());
+    }
+
+    #[test]
+    fn test_logfmt_fields() {
+        let parser = LineParser::new(LineFormat::Logfmt);
+
+        let line = r#"time=2023-11-14T22:13:20Z user=alice src_ip=203.0.113.5 result=fail msg="Failed password for alice from 203.0.113.5""#;
+        let event = parser.parse(line).unwrap();
+        assert_eq!(event.timestamp, 1700000000);
+        assert_eq!(event.user, "alice");
+        assert_eq!(event.ip_address.to_string(), "203.0.113.5");
+        assert_eq!(event.event_type, SSH_FAILED);
+
+        // Generic results map to the built-in types, anything else is kept
+        let result = |value: &str| parser.parse(&format!("user=alice src_ip=203.0.113.5 result={}", value)).unwrap().event_type;
+        assert_eq!(result("Failure"), SSH_FAILED);
+        assert_eq!(result("success"), SSH_LOGIN);
+        assert_eq!(result("OK"), SSH_LOGIN);
+        assert_eq!(result("MFA_CHALLENGE"), "MFA_CHALLENGE");
+
+        // Keys can be renamed; unset ones keep the logfmt defaults
+        let parser = LineParser::new(LineFormat::Logfmt).with_fields(FieldsConfig {
+            ip: Some("remote".to_string()),
+            ..FieldsConfig::default()
+        });
+        let event = parser.parse("user=bob remote=198.51.100.2 result=failure").unwrap();
+        assert_eq!(event.ip_address.to_string(), "198.51.100.2");
+        assert_eq!(event.event_type, SSH_FAILED);
+
+        let parser = LineParser::new(LineFormat::Logfmt);
+
+        // Without a result the quoted message is classified; digits are epoch seconds
+        let line = r#"time=1700000000 level=info user="svc backup" msg="Accepted publickey for \"svc backup\"""#;
+        let event = parser.parse(line).unwrap();
+        assert_eq!(event.timestamp, 1700000000);
+        assert_eq!(event.user, "svc backup");
+        assert_eq!(event.ip_address, NO_IP);
+        assert_eq!(event.event_type, "SSH_LOGIN");
+
+        assert!(parser.parse("no pairs on this line").is_err());
+        assert!(parser.parse("src_ip=not-an-ip").is_err());
+    }
+
+    #[test]
+    fn test_access_log_lines() {
+        let parser = LineParser::new(LineFormat::NginxAccess);
+
+        let combined = r#"203.0.113.5 - alice [10/Oct/2023:13:55:36 +0000] "GET /admin HTTP/1.1" 401 512 "-" "curl/8.0""#;
+        let event = parser.parse(combined).unwrap();
+        assert_eq!(event.timestamp, 1696946136);
+        assert_eq!(event.user, "alice");
+        assert_eq!(event.ip_address.to_string(), "203.0.113.5");
+        assert_eq!(event.event_type, HTTP_AUTH_FAILED);
+
+        let authenticated = r#"203.0.113.5 - alice [10/Oct/2023:13:55:40 +0000] "GET /admin HTTP/1.1" 200 9 "-" "curl/8.0""#;
+        assert_eq!(parser.parse(authenticated).unwrap().event_type, HTTP_LOGIN);
+
+        // Common log format, anonymous, over IPv6
+        let common = r#"2001:db8::7 - - [10/Oct/2023:13:55:37 +0000] "POST /login HTTP/2.0" 200 31"#;
+        let event = parser.parse(common).unwrap();
+        assert_eq!(event.user, UNKNOWN_USER);
+        assert_eq!(event.ip_address.to_string(), "2001:db8::7");
+        assert_eq!(event.event_type, HTTP_REQUEST);
+
+        assert!(parser.parse("Jan 1 12:00:00 host sshd[1]: Accepted publickey for alice from 10.0.0.1").is_err());
+        assert!(parser.parse(r#"not-an-ip - - [10/Oct/2023:13:55:37 +0000] "GET / HTTP/1.1" 200 31"#).is_err());
+    }
+
+    /// A format the built-in parsers don't know: `user ip`
+    struct PairParser;
+
+    impl LogParser for PairParser {
+        fn parse(&self, line: &str) -> ParseResult {
+            let (user, ip) = line.trim().split_once(' ').ok_or(ParseError::Malformed("expected `user ip`"))?;
+            Ok(LogEvent {
+                timestamp: 1700000000,
+                user: user.to_string(),
+                ip_address: IpAddr::from_str(ip)?,
+                event_type: "APP_LOGIN".to_string(),
+                host: None,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_parser_on_a_source() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let input: &[u8] = b"alice 198.51.100.7\nnot-a-pair\n";
+        AsyncStdinReader::from_reader(input).with_parser(PairParser).run(tx).await.unwrap();
+
+        let event = rx.recv().await.unwrap();
+        assert_eq!(event.user, "alice");
+        assert_eq!(event.ip_address.to_string(), "198.51.100.7");
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[test]
+    fn test_split_logfmt() {
+        let pairs = split_logfmt(r#"  a=1 flag b="two words" c= d="esc \"q\" \\" a=3"#);
+        assert_eq!(pairs.len(), 4);
+        assert_eq!(pairs["a"], "3");
+        assert_eq!(pairs["b"], "two words");
+        assert_eq!(pairs["c"], "");
+        assert_eq!(pairs["d"], r#"esc "q" \"#);
+    }
+
+    #[tokio::test]
+    async fn test_sources_with_different_formats() {
+        let json_source = SourceSpec {
+            source_type: "file".to_string(),
+            file_path: None,
+            syslog_address: None,
+            format: LineFormat::Json,
+            timestamp_formats: Some(vec!["rfc3339".to_string()]),
+            fields: FieldsConfig::default(),
+        };
+        let syslog_source = SourceSpec {
+            source_type: "syslog".to_string(),
+            format: LineFormat::Text,
+            timestamp_formats: Some(vec!["%Y-%m-%d %H:%M:%S".to_string()]),
+            ..json_source.clone()
+        };
+
+        let json_input: &[u8] = b"{\"timestamp\": \"2023-11-14T22:13:20Z\", \"user\": \"alice\", \"ip\": \"198.51.100.7\", \"message\": \"Accepted password\"}\n";
+        let syslog_input: &[u8] = b"2023-11-14 22:15:00 host sshd[1]: Failed password for bob from 203.0.113.5 port 22\n";
+
+        let (tx, mut rx) = mpsc::channel(10);
+        for (source, input) in [(&json_source, json_input), (&syslog_source, syslog_input)] {
+            let parser = LineParser::from_source(source, EventClassifier::default()).unwrap();
+            AsyncStdinReader::from_reader(input).with_parser(parser).run(tx.clone()).await.unwrap();
+        }
+
+        let json_event = rx.recv().await.unwrap();
+        assert_eq!(json_event.timestamp, 1700000000);
+        assert_eq!(json_event.user, "alice");
+        assert_eq!(json_event.ip_address.to_string(), "198.51.100.7");
+        assert_eq!(json_event.event_type, "SSH_LOGIN");
+
+        let syslog_event = rx.recv().await.unwrap();
+        assert_eq!(syslog_event.timestamp, 1700000100);
+        assert_eq!(syslog_event.user, "bob");
+        assert_eq!(syslog_event.ip_address.to_string(), "203.0.113.5");
+        assert_eq!(syslog_event.event_type, "SSH_FAILED");
+    }
+
+    #[test]
+    fn test_unknown_timestamp_format_rejected() {
+        let source = SourceSpec {
+            source_type: "file".to_string(),
+            file_path: None,
+            syslog_address: None,
+            format: LineFormat::Text,
+            timestamp_formats: Some(vec!["nonsense".to_string()]),
+            fields: FieldsConfig::default(),
+        };
+        assert!(LineParser::from_source(&source, EventClassifier::default()).is_err());
+    }
+
+    #[test]
+    fn test_ipv6_addresses() {
+        let parser = LineParser::default();
+        let ip = |line: &str| parser.parse(line).unwrap().ip_address.to_string();
+
+        let sshd = "Jan  1 12:00:00 host sshd[1]: Failed password for alice from 2001:db8::1 port 22 ssh2";
+        assert_eq!(ip(sshd), "2001:db8::1");
+        let bracketed = "Jan  1 12:00:01 host app[2]: Accepted password for bob from [2001:db8:0:1::a]:2222";
+        assert_eq!(ip(bracketed), "2001:db8:0:1::a");
+        let loopback = "Jan  1 12:00:02 host sshd[1]: Accepted publickey for carol from ::1 port 22";
+        assert_eq!(ip(loopback), "::1");
+
+        // Mapped addresses are read as IPv4
+        let mapped = "Jan  1 12:00:03 host sshd[1]: Failed password for dave from ::ffff:192.0.2.7 port 22";
+        assert_eq!(ip(mapped), "192.0.2.7");
+
+        // IPv6 is preferred on mixed-stack lines; IPv4 still parses alone
+        let mixed = "Jan  1 12:00:04 proxy-10.0.0.5 app[3]: Accepted password for erin from 2001:db8::42 via 10.0.0.5";
+        assert_eq!(ip(mixed), "2001:db8::42");
+        let ipv4 = "2024-01-15T10:30:00.123+00:00 bastion sshd[99]: Failed password for frank from 203.0.113.5 port 22";
+        assert_eq!(ip(ipv4), "203.0.113.5");
+        let pam = "Jan  1 12:00:05 host sshd[1]: pam_unix(sshd:auth): authentication failure; logname= uid=0 rhost=198.51.100.9  user=grace";
+        assert_eq!(ip(pam), "198.51.100.9");
+        let unanchored = "Jan  1 12:00:06 host app[3]: login ok user=heidi client 2001:db8::9";
+        assert_eq!(ip(unanchored), "2001:db8::9");
+    }
+
+    #[test]
+    fn test_mapped_addresses_read_as_ipv4_in_every_format() {
+        let lines = [
+            (LineFormat::Text, "Jan  1 12:00:00 host sshd[1]: Failed password for alice from ::ffff:192.0.2.7 port 22"),
+            (LineFormat::Json, r#"{"user": "alice", "ip": "::ffff:192.0.2.7", "event_type": "fail"}"#),
+            (LineFormat::Logfmt, "user=alice src_ip=::ffff:192.0.2.7 result=fail"),
+            (
+                LineFormat::NginxAccess,
+                r#"::ffff:192.0.2.7 - alice [10/Oct/2023:13:55:36 +0000] "GET /admin HTTP/1.1" 401 512"#,
+            ),
+        ];
+        for (format, line) in lines {
+            let event = LineParser::new(format).parse(line).unwrap();
+            assert_eq!(event.ip_address.to_string(), "192.0.2.7", "{line}");
+        }
+
+        let sshd = SshdParser::default().parse(lines[0].1).unwrap();
+        assert_eq!(sshd.ip_address.to_string(), "192.0.2.7");
+        let access = NginxAccessParser::default().parse(lines[3].1).unwrap();
+        assert_eq!(access.ip_address.to_string(), "192.0.2.7");
+    }
+
+    #[test]
+    fn test_address_like_username_not_taken_as_source() {
+        let parser = LineParser::default();
+        let ip = |line: &str| parser.parse(line).unwrap().ip_address.to_string();
+
+        assert_eq!(ip("Jan  1 12:00:00 host sshd[1]: Failed password for invalid user ::1 from 203.0.113.5 port 22 ssh2"), "203.0.113.5");
+        assert_eq!(ip("Jan  1 12:00:00 host sshd[1]: Invalid user dead::beef from 203.0.113.5"), "203.0.113.5");
+        assert_eq!(ip("Jan  1 12:00:00 host sshd[1]: Invalid user x from 10.9.9.9 from 2001:db8::5 port 22"), "2001:db8::5");
+        assert_eq!(
+            ip("Jan  1 12:00:00 host sshd[1]: pam_unix(sshd:auth): authentication failure; rhost=203.0.113.5  user=rhost=10.0.0.1"),
+            "203.0.113.5"
+        );
+    }
+
+    #[test]
+    fn test_line_without_address_rejected() {
+        let parser = LineParser::default();
+        let error = parser.parse("Jan  1 12:00:00 host sshd[1]: Failed password for alice").unwrap_err();
+        assert!(matches!(error, ParseError::NoAddress));
+
+        // Clock steps and lockouts name no address
+        let classifier = EventClassifier::default().with_lockout_events(true);
+        let parser = LineParser::default().with_classifier(classifier);
+        let event = parser.parse("Jan  1 12:00:00 host pam_faillock[1]: Account locked for alice").unwrap();
+        assert_eq!(event.ip_address, NO_IP);
+    }
+
+    #[test]
+    fn test_syslog_host() {
+        let parser = LineParser::default();
+        let host = |line: &str| parser.parse(line).unwrap().host;
+
+        let bsd = "<34>Jan  1 12:00:00 web-01 sshd[1234]: Accepted publickey for alice from 192.168.1.100 port 22";
+        assert_eq!(host(bsd).as_deref(), Some("web-01"));
+        let rfc5424 = "<38>1 2024-01-15T10:30:00Z db-02.example.com sshd 1234 - - Failed password for bob from 203.0.113.5";
+        assert_eq!(host(rfc5424).as_deref(), Some("db-02.example.com"));
+        let high_precision = "2024-01-15T10:30:00.123+00:00 bastion sshd[99]: Accepted password for carol from 10.0.0.1";
+        assert_eq!(host(high_precision).as_deref(), Some("bastion"));
+
+        // Headers without a host, and lines without a header
+        assert_eq!(host("<38>1 2024-01-15T10:30:00Z - sshd - - Accepted password for bob from 10.0.0.1"), None);
+        assert_eq!(host("Jan  1 12:00:00 sshd[1234]: Accepted password for alice from 10.0.0.1 port 22"), None);
+        assert_eq!(host("2024-01-15T10:30:00Z Accepted password for alice from 10.0.0.1 port 22"), None);
+
+        let parser = LineParser::default().with_host_extraction(false);
+        assert_eq!(parser.parse(bsd).unwrap().host, None);
+    }
+}
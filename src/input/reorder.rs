@@ -0,0 +1,200 @@
+//! Event reordering
+//!
+//! Events from several sources (a file backlog merged with live syslog,
+//! say) reach detection in arrival order, which can be slightly out of
+//! timestamp order. Geo velocity and rate limits read that as time going
+//! backwards. With a delay set, [`EventReorderBuffer`] holds each event
+//! for that long and releases held events sorted by timestamp, trading a
+//! little latency for ordering. The buffer holds at most a fixed number
+//! of events; past that the earliest are released early.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use crate::models::LogEvent;
+
+/// Events held at most, by default
+pub const DEFAULT_MAX_REORDER_EVENTS: usize = 10_000;
+
+/// A held event, ordered by timestamp and then by arrival
+#[derive(Debug)]
+struct Held {
+    timestamp: i64,
+    sequence: u64,
+    received_at: i64,
+    event: LogEvent,
+}
+
+impl PartialEq for Held {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Held {}
+
+impl PartialOrd for Held {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Held {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.timestamp, self.sequence).cmp(&(other.timestamp, other.sequence))
+    }
+}
+
+/// Holds events for a short delay and releases them in timestamp order
+#[derive(Debug)]
+pub struct EventReorderBuffer {
+    delay_seconds: Option<i64>,
+    max_events: usize,
+    /// Held events, earliest timestamp first
+    held: BinaryHeap<Reverse<Held>>,
+    /// Arrival counter, keeping events with equal timestamps in order
+    sequence: u64,
+}
+
+impl EventReorderBuffer {
+    /// Create a buffer (events pass straight through when `delay_seconds` is None)
+    pub fn new(delay_seconds: Option<i64>) -> Self {
+        EventReorderBuffer {
+            delay_seconds,
+            max_events: DEFAULT_MAX_REORDER_EVENTS,
+            held: BinaryHeap::new(),
+            sequence: 0,
+        }
+    }
+
+    /// Limit the number of events held at once (at least one)
+    pub fn with_max_events(mut self, max_events: usize) -> Self {
+        self.max_events = max_events.max(1);
+        self
+    }
+
+    /// Whether a delay is set
+    pub fn is_enabled(&self) -> bool {
+        self.delay_seconds.is_some()
+    }
+
+    /// Add an event received at `now`, returning the events now ready
+    pub fn push(&mut self, event: LogEvent, now: i64) -> Vec<LogEvent> {
+        if !self.is_enabled() {
+            return vec![event];
+        }
+        self.sequence += 1;
+        self.held.push(Reverse(Held {
+            timestamp: event.timestamp,
+            sequence: self.sequence,
+            received_at: now,
+            event,
+        }));
+        self.release(now)
+    }
+
+    /// Events ready at `now`, earliest first
+    ///
+    /// The earliest held event is released once it has been held for the
+    /// delay, or early when the buffer is over its limit.
+    pub fn release(&mut self, now: i64) -> Vec<LogEvent> {
+        let Some(delay) = self.delay_seconds else {
+            return Vec::new();
+        };
+        let mut ready = Vec::new();
+        while let Some(Reverse(earliest)) = self.held.peek() {
+            if self.held.len() <= self.max_events && now < earliest.received_at + delay {
+                break;
+            }
+            if let Some(Reverse(held)) = self.held.pop() {
+                ready.push(held.event);
+            }
+        }
+        ready
+    }
+
+    /// Release every held event, earliest first (e.g. on shutdown)
+    pub fn flush(&mut self) -> Vec<LogEvent> {
+        std::mem::take(&mut self.held)
+            .into_sorted_vec()
+            .into_iter()
+            .rev()
+            .map(|Reverse(held)| held.event)
+            .collect()
+    }
+
+    /// Number of events currently held
+    pub fn len(&self) -> usize {
+        self.held.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.held.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    fn create_event(user: &str, timestamp: i64) -> LogEvent {
+        LogEvent {
+            timestamp,
+            user: user.to_string(),
+            ip_address: IpAddr::from_str("203.0.113.5").unwrap(),
+            event_type: "SSH_FAILED".to_string(),
+            host: None,
+        }
+    }
+
+    fn timestamps(events: &[LogEvent]) -> Vec<i64> {
+        events.iter().map(|event| event.timestamp).collect()
+    }
+
+    #[test]
+    fn test_out_of_order_events_reach_detection_in_order() {
+        let mut buffer = EventReorderBuffer::new(Some(2));
+
+        // A backlog event and a late syslog event arrive after newer ones
+        let mut released = Vec::new();
+        let arrivals = [(1010, 5000), (1000, 5000), (1012, 5001), (1005, 5001), (1011, 5003)];
+        for (timestamp, now) in arrivals {
+            released.extend(buffer.push(create_event("alice", timestamp), now));
+        }
+        released.extend(buffer.release(5010));
+        assert!(buffer.is_empty());
+        assert_eq!(timestamps(&released), vec![1000, 1005, 1010, 1011, 1012]);
+    }
+
+    #[test]
+    fn test_events_held_for_delay() {
+        let mut buffer = EventReorderBuffer::new(Some(2));
+        assert!(buffer.push(create_event("alice", 1000), 5000).is_empty());
+        assert!(buffer.push(create_event("bob", 999), 5001).is_empty());
+
+        // Released once the earliest has been held for the delay
+        assert_eq!(timestamps(&buffer.release(5002)), Vec::<i64>::new());
+        assert_eq!(timestamps(&buffer.release(5003)), vec![999, 1000]);
+    }
+
+    #[test]
+    fn test_bounded_and_flushed() {
+        let mut buffer = EventReorderBuffer::new(Some(60)).with_max_events(2);
+        assert!(buffer.push(create_event("alice", 1003), 5000).is_empty());
+        assert!(buffer.push(create_event("alice", 1001), 5000).is_empty());
+        assert_eq!(timestamps(&buffer.push(create_event("alice", 1002), 5000)), vec![1001]);
+        assert_eq!(buffer.len(), 2);
+
+        assert_eq!(timestamps(&buffer.flush()), vec![1002, 1003]);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_disabled_passes_through() {
+        let mut buffer = EventReorderBuffer::new(None);
+        assert!(!buffer.is_enabled());
+        assert_eq!(timestamps(&buffer.push(create_event("alice", 1000), 5000)), vec![1000]);
+        assert!(buffer.is_empty());
+    }
+}
@@ -0,0 +1,176 @@
+//! Event sampling for chatty, low-value event types
+//!
+//! Some sources log far more of certain event types than the rules need
+//! to see individually; all that matters is their rate. [`EventSampler`]
+//! lets through only 1 in N events of configured types, and
+//! [`EventSampler::scale_weights`] multiplies the rate limiter's weight
+//! for those types by N so windowed counts stay (approximately) right.
+//!
+//! Accuracy: a sampled count moves in steps of N and is placed at the
+//! timestamp of the kept event, so a window can be over- or under-counted
+//! by up to N - 1 events at its edges, and a burst shorter than N events
+//! may be missed or counted in full. Pick N well below the rate limits
+//! the type feeds into.
+//!
+//! Failed and successful logins are never sampled, whatever the
+//! configuration says: every one of them can trigger a rule on its own.
+//! Rates configured for them are dropped with a warning, so their rate
+//! limiter weights aren't scaled either.
+
+use crate::models::event::{is_failed_login_type, is_successful_login_type};
+use crate::models::LogEvent;
+use std::collections::HashMap;
+
+/// Whether events of a type must always be processed
+pub fn is_critical_type(event_type: &str) -> bool {
+    is_failed_login_type(event_type) || is_successful_login_type(event_type)
+}
+
+/// Whether an event must always be processed
+pub fn is_critical_event(event: &LogEvent) -> bool {
+    is_critical_type(&event.event_type)
+}
+
+/// Keeps 1 in N events of selected types
+#[derive(Debug, Default)]
+pub struct EventSampler {
+    /// Event type -> (N, events seen)
+    rates: HashMap<String, (u32, u64)>,
+    dropped: u64,
+}
+
+impl EventSampler {
+    /// Create a sampler from event type -> N
+    ///
+    /// Rates of 0 or 1 mean no sampling and are ignored, as are rates for
+    /// logins and failures.
+    pub fn new(rates: &HashMap<String, u32>) -> Self {
+        let mut critical: Vec<&str> = rates
+            .keys()
+            .map(String::as_str)
+            .filter(|event_type| is_critical_type(event_type))
+            .collect();
+        if !critical.is_empty() {
+            critical.sort_unstable();
+            log::warn!("Ignoring sample rates for {}: logins and failures are never sampled", critical.join(", "));
+        }
+        EventSampler {
+            rates: rates
+                .iter()
+                .filter(|(event_type, &n)| n > 1 && !is_critical_type(event_type))
+                .map(|(event_type, &n)| (event_type.clone(), (n, 0)))
+                .collect(),
+            dropped: 0,
+        }
+    }
+
+    /// Whether any event type is sampled
+    pub fn is_enabled(&self) -> bool {
+        !self.rates.is_empty()
+    }
+
+    /// Whether this event should be processed
+    ///
+    /// The first event of a sampled type is kept, then every Nth.
+    pub fn should_process(&mut self, event: &LogEvent) -> bool {
+        if is_critical_event(event) {
+            return true;
+        }
+        let Some((n, seen)) = self.rates.get_mut(&event.event_type) else {
+            return true;
+        };
+        let keep = *seen % u64::from(*n) == 0;
+        *seen += 1;
+        if !keep {
+            self.dropped += 1;
+        }
+        keep
+    }
+
+    /// Events dropped by sampling since the last call, resetting the count
+    pub fn take_dropped(&mut self) -> u64 {
+        std::mem::take(&mut self.dropped)
+    }
+
+    /// Rate limiter weights with sampled types scaled by their N
+    ///
+    /// Types without a configured weight count once, so they are scaled
+    /// from 1.
+    pub fn scale_weights(&self, weights: &HashMap<String, usize>) -> HashMap<String, usize> {
+        let mut scaled = weights.clone();
+        for (event_type, (n, _)) in &self.rates {
+            let weight = scaled.entry(event_type.clone()).or_insert(1);
+            *weight *= *n as usize;
+        }
+        scaled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detection::LoginRateLimiter;
+
+    fn event(event_type: &str, timestamp: i64) -> LogEvent {
+        LogEvent {
+            timestamp,
+            user: "alice".to_string(),
+            ip_address: "203.0.113.5".parse().unwrap(),
+            event_type: event_type.to_string(),
+            host: None,
+        }
+    }
+
+    #[test]
+    fn test_sampled_counts_are_scaled() {
+        let mut sampler = EventSampler::new(&HashMap::from([("SSH_DISCONNECT".to_string(), 10)]));
+        let weights = sampler.scale_weights(&HashMap::new());
+        assert_eq!(weights["SSH_DISCONNECT"], 10);
+
+        let mut limiter = LoginRateLimiter::with_config(300, 1000, 1000).with_event_weights(weights);
+        for i in 0..100 {
+            let event = event("SSH_DISCONNECT", 1700000000 + i);
+            if sampler.should_process(&event) {
+                limiter.check_rate_limit(&event);
+            }
+        }
+        assert_eq!(sampler.take_dropped(), 90);
+        assert_eq!(sampler.take_dropped(), 0);
+        assert_eq!(limiter.get_user_attempt_count("alice"), 100);
+    }
+
+    #[test]
+    fn test_configured_weight_is_scaled() {
+        let sampler = EventSampler::new(&HashMap::from([("SSH_DISCONNECT".to_string(), 5)]));
+        let weights = sampler.scale_weights(&HashMap::from([("SSH_DISCONNECT".to_string(), 2)]));
+        assert_eq!(weights["SSH_DISCONNECT"], 10);
+    }
+
+    #[test]
+    fn test_critical_types_never_dropped() {
+        let rates = HashMap::from([
+            ("SSH_FAILED".to_string(), 10),
+            ("SSH_FAILED_PASSWORD".to_string(), 10),
+            ("SSH_LOGIN".to_string(), 10),
+        ]);
+        let mut sampler = EventSampler::new(&rates);
+        assert!(!sampler.is_enabled());
+        for i in 0..50 {
+            for event_type in ["SSH_FAILED", "SSH_FAILED_PASSWORD", "SSH_LOGIN"] {
+                assert!(sampler.should_process(&event(event_type, 1700000000 + i)));
+            }
+        }
+        assert_eq!(sampler.take_dropped(), 0);
+
+        // Nor are their rate limiter weights scaled
+        let weights = sampler.scale_weights(&HashMap::from([("SSH_FAILED".to_string(), 2)]));
+        assert_eq!(weights, HashMap::from([("SSH_FAILED".to_string(), 2)]));
+    }
+
+    #[test]
+    fn test_unsampled_types_pass() {
+        let mut sampler = EventSampler::new(&HashMap::from([("SSH_DISCONNECT".to_string(), 1)]));
+        assert!(!sampler.is_enabled());
+        assert!((0..10).all(|i| sampler.should_process(&event("SSH_DISCONNECT", i))));
+    }
+}
@@ -0,0 +1,153 @@
+//! Ingestion counters
+//!
+//! Counts lines read, parsed and rejected by the input sources so that a
+//! log format change (which makes every line fail to parse) shows up as a
+//! spike in failures instead of detection silently going quiet.
+//!
+//! Lines that simply aren't auth events, such as cron or kernel messages
+//! in a shared syslog stream, are counted as skipped rather than failed,
+//! so normal mixed logs don't look like a broken format.
+
+use super::classify::UNKNOWN_EVENT;
+use super::parser::{ParseError, ParseResult};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Shared, lock-free ingestion counters
+#[derive(Debug, Default)]
+pub struct IngestionStats {
+    lines_read: AtomicU64,
+    lines_parsed: AtomicU64,
+    lines_skipped: AtomicU64,
+    parse_failures: AtomicU64,
+}
+
+/// Point-in-time copy of the ingestion counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct IngestionSnapshot {
+    pub lines_read: u64,
+    pub lines_parsed: u64,
+    /// Lines that weren't auth events
+    pub lines_skipped: u64,
+    pub parse_failures: u64,
+}
+
+impl IngestionStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a line that produced a recognised event
+    pub fn record_parsed(&self) {
+        self.lines_read.fetch_add(1, Ordering::Relaxed);
+        self.lines_parsed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a line that wasn't an auth event
+    pub fn record_skipped(&self) {
+        self.lines_read.fetch_add(1, Ordering::Relaxed);
+        self.lines_skipped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a line that failed to parse
+    pub fn record_failure(&self) {
+        self.lines_read.fetch_add(1, Ordering::Relaxed);
+        self.parse_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record the outcome of parsing one line
+    ///
+    /// Lines naming no source address or matching no known event type are
+    /// skipped; only lines a parser rejected as malformed count as
+    /// failures.
+    pub fn record(&self, result: &ParseResult) {
+        match result {
+            Ok(event) if event.event_type != UNKNOWN_EVENT => self.record_parsed(),
+            Ok(_) | Err(ParseError::NoAddress) => self.record_skipped(),
+            Err(_) => self.record_failure(),
+        }
+    }
+
+    pub fn snapshot(&self) -> IngestionSnapshot {
+        IngestionSnapshot {
+            lines_read: self.lines_read.load(Ordering::Relaxed),
+            lines_parsed: self.lines_parsed.load(Ordering::Relaxed),
+            lines_skipped: self.lines_skipped.load(Ordering::Relaxed),
+            parse_failures: self.parse_failures.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl IngestionSnapshot {
+    /// Counts accumulated since an earlier snapshot
+    pub fn since(&self, earlier: &IngestionSnapshot) -> IngestionSnapshot {
+        IngestionSnapshot {
+            lines_read: self.lines_read.saturating_sub(earlier.lines_read),
+            lines_parsed: self.lines_parsed.saturating_sub(earlier.lines_parsed),
+            lines_skipped: self.lines_skipped.saturating_sub(earlier.lines_skipped),
+            parse_failures: self.parse_failures.saturating_sub(earlier.parse_failures),
+        }
+    }
+
+    /// Lines that were auth events or failed to parse, i.e. not skipped
+    pub fn lines_considered(&self) -> u64 {
+        self.lines_parsed + self.parse_failures
+    }
+
+    /// Fraction of considered lines that failed to parse (0.0 when there
+    /// were none)
+    pub fn failure_ratio(&self) -> f64 {
+        let considered = self.lines_considered();
+        if considered == 0 {
+            0.0
+        } else {
+            self.parse_failures as f64 / considered as f64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::parser::{LineParser, LogParser};
+
+    #[test]
+    fn test_counters_and_ratio() {
+        let stats = IngestionStats::new();
+        stats.record_parsed();
+        let earlier = stats.snapshot();
+        stats.record_parsed();
+        stats.record_failure();
+        stats.record_failure();
+        stats.record_failure();
+
+        let total = stats.snapshot();
+        assert_eq!(total.lines_read, 5);
+        assert_eq!(total.parse_failures, 3);
+
+        let delta = total.since(&earlier);
+        assert_eq!(delta.lines_read, 4);
+        assert_eq!(delta.failure_ratio(), 0.75);
+    }
+
+    #[test]
+    fn test_non_auth_lines_skipped_not_failed() {
+        let stats = IngestionStats::new();
+        let parser = LineParser::default();
+        for line in [
+            "Jan  1 12:00:00 host sshd[1]: Accepted publickey for alice from 203.0.113.5 port 22",
+            "Jan  1 12:00:01 host CRON[2]: (root) CMD (run-parts /etc/cron.hourly)",
+            "Jan  1 12:00:02 host kernel: eth0 link up from 10.0.0.1",
+            "Jan  1 12:00:03 host sshd[1]: Failed password for bob from 999.0.113.5 port 22",
+        ] {
+            stats.record(&parser.parse(line));
+        }
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.lines_read, 4);
+        assert_eq!(snapshot.lines_parsed, 1);
+        assert_eq!(snapshot.lines_skipped, 2);
+        assert_eq!(snapshot.parse_failures, 1);
+        assert_eq!(snapshot.failure_ratio(), 0.5);
+    }
+}
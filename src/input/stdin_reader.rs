@@ -0,0 +1,131 @@
+//! Standard input log source
+//!
+//! Reads log lines from stdin for ad-hoc pipelines such as
+//! `journalctl -f | isds_daemon --stdin`.
+
+use crate::models::LogEvent;
+use super::parser::{LineParser, LogParser};
+use super::stats::IngestionStats;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader as AsyncBufReader, Stdin};
+use tokio::sync::mpsc;
+
+/// Async line reader over standard input (or any async reader)
+pub struct AsyncStdinReader<R = Stdin> {
+    reader: AsyncBufReader<R>,
+    parser: Box<dyn LogParser>,
+    stats: Arc<IngestionStats>,
+}
+
+impl AsyncStdinReader<Stdin> {
+    /// Create a reader over the process's standard input
+    pub fn new() -> Self {
+        Self::from_reader(tokio::io::stdin())
+    }
+}
+
+impl Default for AsyncStdinReader<Stdin> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncStdinReader<R> {
+    /// Create a reader over an arbitrary async source
+    pub fn from_reader(reader: R) -> Self {
+        AsyncStdinReader {
+            reader: AsyncBufReader::new(reader),
+            parser: Box::new(LineParser::default()),
+            stats: Arc::new(IngestionStats::new()),
+        }
+    }
+
+    /// Parse lines with a parser configured for this source
+    pub fn with_parser(mut self, parser: impl LogParser + 'static) -> Self {
+        self.parser = Box::new(parser);
+        self
+    }
+
+    /// Record parse outcomes into shared ingestion counters
+    pub fn with_stats(mut self, stats: Arc<IngestionStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    /// Run the reader, sending events through the channel
+    ///
+    /// Returns the number of events sent once EOF is reached or the
+    /// channel is closed.
+    pub async fn run(
+        &mut self,
+        tx: mpsc::Sender<LogEvent>,
+    ) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+        log::info!("Stdin reader started");
+
+        let mut sent = 0;
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line).await? == 0 {
+                log::info!("Reached end of stdin after {} event(s)", sent);
+                break;
+            }
+
+            let parsed = self.parser.parse(&line);
+            self.stats.record(&parsed);
+            if let Ok(event) = parsed {
+                if tx.send(event).await.is_err() {
+                    log::info!("Channel closed, stopping stdin reader");
+                    break;
+                }
+                sent += 1;
+            }
+        }
+
+        Ok(sent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reads_events_until_eof() {
+        let input: &[u8] = b"Jan 1 12:00:00 host sshd[1]: Accepted publickey for alice from 10.0.0.1 port 22\n\
+Jan 1 12:00:01 host sshd[1]: Failed password for bob from 10.0.0.2 port 22\n";
+        let (tx, mut rx) = mpsc::channel(10);
+
+        let mut reader = AsyncStdinReader::from_reader(input);
+        let sent = reader.run(tx).await.unwrap();
+        assert_eq!(sent, 2);
+
+        let first = rx.recv().await.unwrap();
+        assert_eq!(first.user, "alice");
+        assert_eq!(first.event_type, "SSH_LOGIN");
+        let second = rx.recv().await.unwrap();
+        assert_eq!(second.user, "bob");
+        assert_eq!(second.event_type, "SSH_FAILED");
+
+        // Sender is dropped at EOF so the channel closes
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_unparseable_line_counts_as_failure() {
+        let input: &[u8] = b"Jan 1 12:00:00 host sshd[1]: Accepted publickey for alice from 10.0.0.1 port 22
+garbage that is not an auth log line
+Jan 1 12:00:01 host sshd[1]: Failed password for bob from 300.0.0.1 port 22
+";
+        let (tx, _rx) = mpsc::channel(10);
+        let stats = Arc::new(IngestionStats::new());
+
+        let mut reader = AsyncStdinReader::from_reader(input).with_stats(stats.clone());
+        reader.run(tx).await.unwrap();
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.lines_read, 3);
+        assert_eq!(snapshot.lines_parsed, 1);
+        assert_eq!(snapshot.lines_skipped, 1);
+        assert_eq!(snapshot.parse_failures, 1);
+    }
+}
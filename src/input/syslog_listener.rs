@@ -1,11 +1,38 @@
 use crate::models::LogEvent;
-use std::net::UdpSocket;
+use super::parser::{LineParser, LogParser};
+use super::stats::IngestionStats;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::Arc;
 use std::time::Duration;
 
+/// Receive buffer size unless configured otherwise
+pub const DEFAULT_BUFFER_SIZE: usize = 8192;
+
+/// Largest UDP datagram; the buffer grows to this after a truncation
+pub const MAX_DATAGRAM_BYTES: usize = 65_535;
+
+/// Whether a datagram of `size` bytes filled the buffer, and so was
+/// probably truncated
+///
+/// A truncated message would parse into a corrupt event, so it is
+/// dropped with a warning, and the buffer grows to the largest datagram
+/// so later messages that long arrive whole.
+fn truncated(buffer: &mut Vec<u8>, size: usize, from: SocketAddr) -> bool {
+    if size < buffer.len() {
+        return false;
+    }
+    log::warn!("Dropping syslog message from {} truncated at {} bytes", from, size);
+    if buffer.len() < MAX_DATAGRAM_BYTES {
+        log::warn!("Growing syslog receive buffer to {} bytes", MAX_DATAGRAM_BYTES);
+        buffer.resize(MAX_DATAGRAM_BYTES, 0);
+    }
+    true
+}
+
 /// Syslog listener for receiving log events via UDP
 pub struct SyslogListener {
     socket: UdpSocket,
-    buffer: [u8; 1024],
+    buffer: Vec<u8>,
 }
 
 impl SyslogListener {
@@ -16,14 +43,26 @@ impl SyslogListener {
         
         Ok(SyslogListener {
             socket,
-            buffer: [0; 1024],
+            buffer: vec![0; DEFAULT_BUFFER_SIZE],
         })
     }
 
+    /// Receive messages of up to `size` bytes whole (capped at the
+    /// largest datagram)
+    pub fn with_buffer_size(mut self, size: usize) -> Self {
+        self.buffer = vec![0; size.clamp(1, MAX_DATAGRAM_BYTES)];
+        self
+    }
+
     /// Read a syslog message (non-blocking)
+    ///
+    /// Truncated messages are dropped, returning None.
     pub fn read_message(&mut self) -> Result<Option<String>, Box<dyn std::error::Error>> {
         match self.socket.recv_from(&mut self.buffer) {
-            Ok((size, _addr)) => {
+            Ok((size, addr)) => {
+                if truncated(&mut self.buffer, size, addr) {
+                    return Ok(None);
+                }
                 let message = String::from_utf8_lossy(&self.buffer[..size]).to_string();
                 Ok(Some(message))
             }
@@ -38,54 +77,9 @@ impl SyslogListener {
         }
     }
 
-    /// Parse a syslog message into a LogEvent
+    /// Parse a syslog message into a LogEvent using the built-in timestamp formats
     pub fn parse_syslog_message(message: &str) -> Result<LogEvent, Box<dyn std::error::Error>> {
-        // Basic syslog parser
-        // In production, you'd want a more robust parser
-        
-        use std::net::IpAddr;
-        use std::str::FromStr;
-
-        // Extract IP address
-        let ip_pattern = regex::Regex::new(r"\b(\d{1,3}\.\d{1,3}\.\d{1,3}\.\d{1,3})\b")?;
-        let ip_addr = if let Some(cap) = ip_pattern.find(message) {
-            IpAddr::from_str(cap.as_str())?
-        } else {
-            IpAddr::from_str("0.0.0.0")?
-        };
-
-        // Extract username
-        let user = if let Some(pos) = message.find("for ") {
-            let after_for = &message[pos + 4..];
-            if let Some(end_pos) = after_for.find(' ') {
-                after_for[..end_pos].to_string()
-            } else {
-                "unknown".to_string()
-            }
-        } else {
-            "unknown".to_string()
-        };
-
-        // Get timestamp
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)?
-            .as_secs() as i64;
-
-        // Determine event type
-        let event_type = if message.contains("Accepted") || message.contains("Successful") {
-            "SSH_LOGIN".to_string()
-        } else if message.contains("Failed") || message.contains("Invalid") {
-            "SSH_FAILED".to_string()
-        } else {
-            "UNKNOWN".to_string()
-        };
-
-        Ok(LogEvent {
-            timestamp,
-            user,
-            ip_address: ip_addr,
-            event_type,
-        })
+        Ok(LineParser::default().parse(message)?)
     }
 }
 
@@ -99,13 +93,45 @@ use tokio::sync::mpsc;
 /// Async version of SyslogListener for use with tokio
 pub struct AsyncSyslogListener {
     socket: AsyncUdpSocket,
+    parser: Box<dyn LogParser>,
+    stats: Arc<IngestionStats>,
+    buffer_size: usize,
 }
 
 impl AsyncSyslogListener {
     /// Create a new async syslog listener bound to the given address
     pub async fn new(address: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         let socket = AsyncUdpSocket::bind(address).await?;
-        Ok(AsyncSyslogListener { socket })
+        Ok(AsyncSyslogListener {
+            socket,
+            parser: Box::new(LineParser::default()),
+            stats: Arc::new(IngestionStats::new()),
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        })
+    }
+
+    /// Receive messages of up to `size` bytes whole (capped at the
+    /// largest datagram)
+    pub fn with_buffer_size(mut self, size: usize) -> Self {
+        self.buffer_size = size.clamp(1, MAX_DATAGRAM_BYTES);
+        self
+    }
+
+    /// Address the listener is bound to
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Parse lines with a parser configured for this source
+    pub fn with_parser(mut self, parser: impl LogParser + 'static) -> Self {
+        self.parser = Box::new(parser);
+        self
+    }
+
+    /// Record parse outcomes into shared ingestion counters
+    pub fn with_stats(mut self, stats: Arc<IngestionStats>) -> Self {
+        self.stats = stats;
+        self
     }
 
     /// Run the syslog listener, sending events through the channel
@@ -116,16 +142,22 @@ impl AsyncSyslogListener {
         &mut self,
         tx: mpsc::Sender<LogEvent>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let mut buf = [0u8; 1024];
+        let mut buf = vec![0u8; self.buffer_size];
 
         log::info!("Async syslog listener started");
 
         loop {
             match self.socket.recv_from(&mut buf).await {
-                Ok((size, _addr)) => {
+                Ok((size, addr)) => {
+                    if truncated(&mut buf, size, addr) {
+                        self.stats.record_failure();
+                        continue;
+                    }
                     let message = String::from_utf8_lossy(&buf[..size]);
+                    let parsed = self.parser.parse(&message);
+                    self.stats.record(&parsed);
 
-                    if let Ok(event) = SyslogListener::parse_syslog_message(&message) {
+                    if let Ok(event) = parsed {
                         if tx.send(event).await.is_err() {
                             log::info!("Channel closed, stopping syslog listener");
                             break;
@@ -152,6 +184,46 @@ mod tests {
         let event = SyslogListener::parse_syslog_message(message).unwrap();
         assert_eq!(event.user, "alice");
         assert_eq!(event.ip_address.to_string(), "192.168.1.100");
+        assert_eq!(event.host.as_deref(), Some("hostname"));
+    }
+
+    #[tokio::test]
+    async fn test_large_message_parsed_whole() {
+        let stats = Arc::new(IngestionStats::new());
+        let mut listener = AsyncSyslogListener::new("127.0.0.1:0").await.unwrap().with_stats(stats.clone());
+        let address = listener.local_addr().unwrap();
+        let (tx, mut rx) = mpsc::channel(10);
+        let task = tokio::spawn(async move { listener.run(tx).await.unwrap() });
+
+        let message = format!(
+            "<34>Jan 1 12:00:00 hostname sshd[1234]: Accepted publickey for alice from 192.168.1.100 port 22 ssh2: {}",
+            "A".repeat(4096)
+        );
+        assert!(message.len() > 4096);
+        let sender = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender.send_to(message.as_bytes(), address).unwrap();
+
+        let event = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(event.user, "alice");
+        assert_eq!(event.ip_address.to_string(), "192.168.1.100");
+        assert_eq!(stats.snapshot().parse_failures, 0);
+        task.abort();
+    }
+
+    #[test]
+    fn test_truncated_message_dropped_and_buffer_grown() {
+        let mut listener = SyslogListener::new("127.0.0.1:0").unwrap().with_buffer_size(64);
+        let address = listener.socket.local_addr().unwrap();
+        let message = "<34>Jan 1 12:00:00 hostname sshd[1234]: Accepted publickey for alice from 192.168.1.100";
+        let sender = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        // The first copy doesn't fit and is dropped rather than parsed
+        sender.send_to(message.as_bytes(), address).unwrap();
+        assert_eq!(listener.read_message().unwrap(), None);
+        assert_eq!(listener.buffer.len(), MAX_DATAGRAM_BYTES);
+
+        sender.send_to(message.as_bytes(), address).unwrap();
+        assert_eq!(listener.read_message().unwrap().as_deref(), Some(message));
     }
 }
 
@@ -0,0 +1,306 @@
+//! TCP syslog listener
+//!
+//! UDP syslog truncates long messages and can't reach senders that only
+//! speak TCP. [`AsyncTcpSyslogListener`] accepts TCP connections and reads
+//! RFC 6587 frames from each: octet-counted (`<length> <message>`) or
+//! newline-delimited, detected per frame. Every connection is read on its
+//! own task, so a slow or idle sender doesn't hold up the others. The
+//! number of open connections is capped, and a connection that sends
+//! nothing for the idle timeout is closed, so stalled senders can't
+//! exhaust tasks or file descriptors.
+
+use crate::models::LogEvent;
+use super::parser::{LineParser, LogParser};
+use super::stats::IngestionStats;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Semaphore};
+
+/// Longest frame accepted; a connection sending a longer one is closed
+pub const MAX_FRAME_BYTES: usize = 64 * 1024;
+
+/// Digits read looking for an octet count; a longer run of digits starts
+/// a newline-delimited frame
+const MAX_LENGTH_DIGITS: usize = 10;
+
+/// Default cap on open connections
+pub const DEFAULT_MAX_CONNECTIONS: usize = 256;
+
+/// Default time a connection may go without sending a frame
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Pause after a failed accept (e.g. out of file descriptors)
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Syslog listener for receiving log events over TCP
+pub struct AsyncTcpSyslogListener {
+    listener: TcpListener,
+    parser: Arc<dyn LogParser>,
+    stats: Arc<IngestionStats>,
+    /// Slots for open connections
+    connection_slots: Arc<Semaphore>,
+    idle_timeout: Duration,
+}
+
+impl AsyncTcpSyslogListener {
+    /// Create a listener bound to the given address
+    pub async fn new(address: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let listener = TcpListener::bind(address).await?;
+        Ok(AsyncTcpSyslogListener {
+            listener,
+            parser: Arc::new(LineParser::default()),
+            stats: Arc::new(IngestionStats::new()),
+            connection_slots: Arc::new(Semaphore::new(DEFAULT_MAX_CONNECTIONS)),
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+        })
+    }
+
+    /// Limit the number of open connections; further ones are closed
+    /// as soon as they are accepted
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.connection_slots = Arc::new(Semaphore::new(max_connections.max(1)));
+        self
+    }
+
+    /// Close connections that send nothing for this long
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Parse lines with a parser configured for this source
+    pub fn with_parser(mut self, parser: impl LogParser + 'static) -> Self {
+        self.parser = Arc::new(parser);
+        self
+    }
+
+    /// Record parse outcomes into shared ingestion counters
+    pub fn with_stats(mut self, stats: Arc<IngestionStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    /// Address the listener is bound to
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accept connections, sending their events through the channel
+    ///
+    /// Runs until the channel is closed.
+    pub async fn run(
+        &mut self,
+        tx: mpsc::Sender<LogEvent>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        log::info!("TCP syslog listener started on {}", self.local_addr()?);
+
+        loop {
+            tokio::select! {
+                _ = tx.closed() => {
+                    log::info!("Channel closed, stopping TCP syslog listener");
+                    return Ok(());
+                }
+                accepted = self.listener.accept() => match accepted {
+                    Ok((stream, peer)) => {
+                        let Ok(slot) = self.connection_slots.clone().try_acquire_owned() else {
+                            log::warn!("Rejecting syslog connection from {}: too many open connections", peer);
+                            continue;
+                        };
+                        log::debug!("Syslog connection from {}", peer);
+                        let parser = self.parser.clone();
+                        let stats = self.stats.clone();
+                        let idle_timeout = self.idle_timeout;
+                        let tx = tx.clone();
+                        tokio::spawn(async move {
+                            let _slot = slot;
+                            if let Err(e) = read_connection(stream, parser.as_ref(), &stats, idle_timeout, tx).await {
+                                log::warn!("Closing syslog connection from {}: {}", peer, e);
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        log::error!("Syslog accept error: {}", e);
+                        tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// Read frames from one connection until it closes or goes idle
+async fn read_connection(
+    stream: TcpStream,
+    parser: &dyn LogParser,
+    stats: &IngestionStats,
+    idle_timeout: Duration,
+    tx: mpsc::Sender<LogEvent>,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    loop {
+        let frame = tokio::time::timeout(idle_timeout, read_frame(&mut reader))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "idle timeout"))??;
+        let Some(frame) = frame else {
+            break;
+        };
+        if frame.is_empty() {
+            continue;
+        }
+        let parsed = parser.parse(&frame);
+        stats.record(&parsed);
+        if let Ok(event) = parsed {
+            if tx.send(event).await.is_err() {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Read the next frame, octet-counted or newline-delimited (None at end
+/// of stream)
+///
+/// The framing is only decided once the byte after any leading digits is
+/// seen, so a length split across reads isn't mistaken for the start of
+/// a newline-delimited frame.
+pub async fn read_frame<R: AsyncBufRead + Unpin>(reader: &mut R) -> io::Result<Option<String>> {
+    let mut digits = Vec::new();
+    let octet_counted = loop {
+        let Some(&byte) = reader.fill_buf().await?.first() else {
+            break false;
+        };
+        if byte.is_ascii_digit() && digits.len() < MAX_LENGTH_DIGITS {
+            digits.push(byte);
+            reader.consume(1);
+            continue;
+        }
+        break byte == b' ' && !digits.is_empty();
+    };
+
+    // Octet counting: `<length> <message>`
+    if octet_counted {
+        let length = std::str::from_utf8(&digits)
+            .ok()
+            .and_then(|digits| digits.parse::<usize>().ok())
+            .filter(|&length| length <= MAX_FRAME_BYTES)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "frame length out of range"))?;
+        reader.consume(1);
+        let mut frame = vec![0; length];
+        reader.read_exact(&mut frame).await?;
+        return Ok(Some(String::from_utf8_lossy(&frame).into_owned()));
+    }
+
+    // Newline-delimited, starting with any digits already read
+    let mut frame = digits;
+    let limit = (MAX_FRAME_BYTES + 1 - frame.len()) as u64;
+    let read = (&mut *reader).take(limit).read_until(b'\n', &mut frame).await?;
+    if read == 0 && frame.is_empty() {
+        return Ok(None);
+    }
+    if frame.len() > MAX_FRAME_BYTES {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too long"));
+    }
+    while frame.last().is_some_and(|b| *b == b'\n' || *b == b'\r') {
+        frame.pop();
+    }
+    Ok(Some(String::from_utf8_lossy(&frame).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_read_mixed_frames() {
+        let message = "<34>Jan 1 12:00:00 host sshd[1]: Accepted password for alice from 10.0.0.1 port 22";
+        let input = format!(
+            "{} {}<34>Jan 1 12:00:01 host sshd[1]: Failed password for bob from 10.0.0.2\r\n\
+             2024-01-15T10:30:00Z host sshd[1]: Failed password for carol from 10.0.0.3\n",
+            message.len(),
+            message
+        );
+        let mut reader = BufReader::new(input.as_bytes());
+
+        assert_eq!(read_frame(&mut reader).await.unwrap().unwrap(), message);
+        assert!(read_frame(&mut reader).await.unwrap().unwrap().ends_with("from 10.0.0.2"));
+        assert!(read_frame(&mut reader).await.unwrap().unwrap().starts_with("2024-01-15T10:30:00Z host"));
+        assert_eq!(read_frame(&mut reader).await.unwrap(), None);
+
+        let mut oversized = BufReader::new("99999999 <34>".as_bytes());
+        assert!(read_frame(&mut oversized).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_length_split_across_reads() {
+        // The second length straddles the end of the tiny buffer
+        let mut reader = BufReader::with_capacity(6, "3 abc10 0123456789".as_bytes());
+        assert_eq!(read_frame(&mut reader).await.unwrap().unwrap(), "abc");
+        assert_eq!(read_frame(&mut reader).await.unwrap().unwrap(), "0123456789");
+        assert_eq!(read_frame(&mut reader).await.unwrap(), None);
+
+        // Leading digits without a space are part of a plain line
+        let mut reader = BufReader::with_capacity(4, "2024-01-15 host sshd\n".as_bytes());
+        assert_eq!(read_frame(&mut reader).await.unwrap().unwrap(), "2024-01-15 host sshd");
+    }
+
+    #[tokio::test]
+    async fn test_connection_limit_and_idle_timeout() {
+        let listener = AsyncTcpSyslogListener::new("127.0.0.1:0").await.unwrap();
+        let mut listener = listener
+            .with_max_connections(1)
+            .with_idle_timeout(Duration::from_millis(200));
+        let address = listener.local_addr().unwrap();
+        let (tx, mut rx) = mpsc::channel(10);
+        let task = tokio::spawn(async move { listener.run(tx).await.unwrap() });
+
+        let mut idle = TcpStream::connect(address).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Over the limit: closed straight away
+        let mut rejected = TcpStream::connect(address).await.unwrap();
+        let mut buf = [0u8; 1];
+        let read = tokio::time::timeout(Duration::from_secs(5), rejected.read(&mut buf)).await.unwrap();
+        assert_eq!(read.unwrap(), 0);
+
+        // The idle connection is dropped, freeing its slot
+        let read = tokio::time::timeout(Duration::from_secs(5), idle.read(&mut buf)).await.unwrap();
+        assert_eq!(read.unwrap(), 0);
+        let mut next = TcpStream::connect(address).await.unwrap();
+        next.write_all(b"<34>Jan 1 12:00:01 host sshd[1]: Failed password for bob from 10.0.0.2 port 22\n")
+            .await
+            .unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(event.user, "bob");
+
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_slow_client_does_not_stall_others() {
+        let mut listener = AsyncTcpSyslogListener::new("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (tx, mut rx) = mpsc::channel(10);
+        let task = tokio::spawn(async move { listener.run(tx).await.unwrap() });
+
+        // A client that stops halfway through a frame
+        let mut slow = TcpStream::connect(address).await.unwrap();
+        slow.write_all(b"200 <34>Jan 1 12:00:00 host sshd[1]: Accepted").await.unwrap();
+
+        let mut fast = TcpStream::connect(address).await.unwrap();
+        fast.write_all(b"<34>Jan 1 12:00:01 host sshd[1]: Failed password for bob from 2001:db8::2 port 22\n")
+            .await
+            .unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(event.user, "bob");
+        assert_eq!(event.ip_address.to_string(), "2001:db8::2");
+        assert_eq!(event.host.as_deref(), Some("host"));
+
+        task.abort();
+    }
+}
@@ -0,0 +1,353 @@
+//! Timestamp format registry for log parsers
+//!
+//! Log sources stamp lines in many different ways (syslog, RFC 3339,
+//! Apache CLF, epoch seconds/millis). The registry holds an ordered list
+//! of named formats; the line parser tries each in turn and uses the first
+//! one that matches to produce the event's epoch timestamp.
+
+use chrono::{DateTime, Datelike, NaiveDateTime, TimeZone, Utc};
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Function that finds and parses a timestamp in a log line
+pub type TimestampParser = fn(&str) -> Option<i64>;
+
+/// Names of the built-in formats, in default lookup order
+pub const BUILTIN_FORMATS: &[&str] = &[
+    "rfc3339",
+    "iso8601",
+    "syslog",
+    "clf",
+    "epoch_millis",
+    "epoch_seconds",
+];
+
+#[derive(Clone)]
+enum FormatKind {
+    Builtin(TimestampParser),
+    /// strftime pattern matched at the start of the line
+    Pattern(String),
+}
+
+/// A named timestamp format
+#[derive(Clone)]
+pub struct TimestampFormat {
+    name: String,
+    kind: FormatKind,
+}
+
+impl TimestampFormat {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn parse(&self, line: &str) -> Option<i64> {
+        match &self.kind {
+            FormatKind::Builtin(parser) => parser(line),
+            FormatKind::Pattern(pattern) => parse_pattern(line, pattern),
+        }
+    }
+}
+
+impl std::fmt::Debug for TimestampFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TimestampFormat").field("name", &self.name).finish()
+    }
+}
+
+/// Ordered registry of timestamp formats
+#[derive(Debug, Clone)]
+pub struct TimestampRegistry {
+    formats: Vec<TimestampFormat>,
+}
+
+impl TimestampRegistry {
+    /// Create an empty registry
+    pub fn empty() -> Self {
+        TimestampRegistry { formats: Vec::new() }
+    }
+
+    /// Create a registry with all built-in formats in default order
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::empty();
+        for name in BUILTIN_FORMATS {
+            if let Some(parser) = builtin(name) {
+                registry.register(name, parser);
+            }
+        }
+        registry
+    }
+
+    /// Build a registry from a list of format names
+    ///
+    /// Each entry is either a built-in format name or, if it contains a
+    /// `%`, a strftime pattern expected at the start of the line.
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> Result<Self, String> {
+        let mut registry = Self::empty();
+        for name in names {
+            let name = name.as_ref();
+            if let Some(parser) = builtin(name) {
+                registry.register(name, parser);
+            } else if name.contains('%') {
+                registry.register_pattern(name, name);
+            } else {
+                return Err(format!("Unknown timestamp format: {}", name));
+            }
+        }
+        Ok(registry)
+    }
+
+    /// Append a format backed by a parse function
+    pub fn register(&mut self, name: &str, parser: TimestampParser) {
+        self.formats.push(TimestampFormat {
+            name: name.to_string(),
+            kind: FormatKind::Builtin(parser),
+        });
+    }
+
+    /// Append a format backed by a strftime pattern
+    pub fn register_pattern(&mut self, name: &str, pattern: &str) {
+        self.formats.push(TimestampFormat {
+            name: name.to_string(),
+            kind: FormatKind::Pattern(pattern.to_string()),
+        });
+    }
+
+    /// Registered formats in lookup order
+    pub fn formats(&self) -> &[TimestampFormat] {
+        &self.formats
+    }
+
+    /// Parse the timestamp of a log line using the first matching format
+    pub fn parse(&self, line: &str) -> Option<i64> {
+        self.formats.iter().find_map(|format| format.parse(line))
+    }
+}
+
+impl Default for TimestampRegistry {
+    fn default() -> Self {
+        Self::with_builtins()
+    }
+}
+
+/// Look up a built-in parser by name
+fn builtin(name: &str) -> Option<TimestampParser> {
+    match name {
+        "rfc3339" => Some(parse_rfc3339),
+        "iso8601" => Some(parse_iso8601),
+        "syslog" => Some(parse_syslog),
+        "clf" => Some(parse_clf),
+        "epoch_millis" => Some(parse_epoch_millis),
+        "epoch_seconds" => Some(parse_epoch_seconds),
+        _ => None,
+    }
+}
+
+fn regex(cell: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    cell.get_or_init(|| Regex::new(pattern).expect("valid timestamp regex"))
+}
+
+/// Strip a leading syslog priority such as `<34>`
+fn strip_priority(line: &str) -> &str {
+    static PRI: OnceLock<Regex> = OnceLock::new();
+    match regex(&PRI, r"^<\d{1,3}>(\d+ )?").find(line) {
+        Some(m) => &line[m.end()..],
+        None => line,
+    }
+}
+
+/// `2024-01-15T10:30:00Z`, `2024-01-15T10:30:00.123+02:00`
+fn parse_rfc3339(line: &str) -> Option<i64> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = regex(
+        &RE,
+        r"\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(\.\d+)?(Z|[+-]\d{2}:\d{2})",
+    );
+    let m = re.find(line)?;
+    DateTime::parse_from_rfc3339(m.as_str()).ok().map(|dt| dt.timestamp())
+}
+
+/// `2024-01-15 10:30:00` or `2024-01-15T10:30:00.123` (assumed UTC)
+fn parse_iso8601(line: &str) -> Option<i64> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = regex(&RE, r"(\d{4}-\d{2}-\d{2})[ T](\d{2}:\d{2}:\d{2})(\.\d+)?");
+    let caps = re.captures(line)?;
+    let text = format!("{} {}", &caps[1], &caps[2]);
+    NaiveDateTime::parse_from_str(&text, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .map(|dt| dt.and_utc().timestamp())
+}
+
+/// Timestamp of a syslog line: RFC 3339 (RFC 5424 headers) or the BSD
+/// `Jan  1 12:00:00` prefix, as epoch seconds
+///
+/// BSD timestamps carry no year; see [`parse_syslog`] for how it's chosen.
+/// Callers fall back to the current time when this gives None.
+pub fn parse_syslog_timestamp(line: &str) -> Option<i64> {
+    parse_rfc3339(line).or_else(|| parse_syslog(line))
+}
+
+/// `Jan  1 12:00:00` (BSD syslog / auth.log, no year, assumed UTC)
+///
+/// The year is taken from the current date; timestamps more than a day in
+/// the future are assumed to belong to the previous year (e.g. December
+/// lines read in January).
+fn parse_syslog(line: &str) -> Option<i64> {
+    parse_syslog_at(line, Utc::now())
+}
+
+/// [`parse_syslog`] as of `now`
+fn parse_syslog_at(line: &str, now: DateTime<Utc>) -> Option<i64> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = regex(&RE, r"^([A-Z][a-z]{2})\s+(\d{1,2}) (\d{2}:\d{2}:\d{2})\b");
+    let caps = re.captures(strip_priority(line))?;
+
+    let parse_in_year = |year: i32| {
+        let text = format!("{} {} {} {}", year, &caps[1], &caps[2], &caps[3]);
+        NaiveDateTime::parse_from_str(&text, "%Y %b %d %H:%M:%S")
+            .ok()
+            .map(|dt| dt.and_utc().timestamp())
+    };
+
+    let timestamp = parse_in_year(now.year())?;
+    if timestamp > now.timestamp() + 86400 {
+        parse_in_year(now.year() - 1)
+    } else {
+        Some(timestamp)
+    }
+}
+
+/// `[10/Oct/2000:13:55:36 -0700]` (Apache Common Log Format)
+fn parse_clf(line: &str) -> Option<i64> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = regex(&RE, r"\[(\d{2}/[A-Z][a-z]{2}/\d{4}:\d{2}:\d{2}:\d{2} [+-]\d{4})\]");
+    let caps = re.captures(line)?;
+    DateTime::parse_from_str(&caps[1], "%d/%b/%Y:%H:%M:%S %z")
+        .ok()
+        .map(|dt| dt.timestamp())
+}
+
+/// `1700000000123 ...` (13-digit epoch milliseconds at line start)
+fn parse_epoch_millis(line: &str) -> Option<i64> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = regex(&RE, r"^(\d{13})\b");
+    let caps = re.captures(strip_priority(line.trim_start()))?;
+    caps[1].parse::<i64>().ok().map(|ms| ms / 1000)
+}
+
+/// `1700000000 ...` or `1700000000.123 ...` (epoch seconds at line start)
+fn parse_epoch_seconds(line: &str) -> Option<i64> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = regex(&RE, r"^(\d{9,10})(\.\d+)?\b");
+    let caps = re.captures(strip_priority(line.trim_start()))?;
+    caps[1].parse::<i64>().ok()
+}
+
+/// Parse a strftime pattern at the start of the line
+fn parse_pattern(line: &str, pattern: &str) -> Option<i64> {
+    let text = strip_priority(line.trim_start());
+    if let Ok((dt, _)) = DateTime::parse_and_remainder(text, pattern) {
+        return Some(dt.timestamp());
+    }
+    NaiveDateTime::parse_and_remainder(text, pattern)
+        .ok()
+        .map(|(dt, _)| Utc.from_utc_datetime(&dt).timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rfc3339() {
+        let registry = TimestampRegistry::with_builtins();
+        assert_eq!(
+            registry.parse("2023-11-14T22:13:20Z host sshd[1]: Accepted"),
+            Some(1700000000)
+        );
+        assert_eq!(
+            registry.parse("<34>1 2023-11-15T00:13:20.5+02:00 host sshd - - Accepted"),
+            Some(1700000000)
+        );
+    }
+
+    #[test]
+    fn test_iso8601() {
+        let registry = TimestampRegistry::with_builtins();
+        assert_eq!(registry.parse("2023-11-14 22:13:20 login ok"), Some(1700000000));
+    }
+
+    #[test]
+    fn test_syslog() {
+        let registry = TimestampRegistry::with_builtins();
+        let parsed = registry
+            .parse("<34>Nov 14 22:13:20 host sshd[1234]: Accepted publickey for alice")
+            .unwrap();
+
+        let now = Utc::now();
+        let mut expected = Utc.with_ymd_and_hms(now.year(), 11, 14, 22, 13, 20).unwrap();
+        if expected.timestamp() > now.timestamp() + 86400 {
+            expected = Utc.with_ymd_and_hms(now.year() - 1, 11, 14, 22, 13, 20).unwrap();
+        }
+        assert_eq!(parsed, expected.timestamp());
+
+        // Single-digit days are space padded
+        assert!(registry.parse("Jan  1 00:00:01 host sshd[1]: Failed").is_some());
+    }
+
+    #[test]
+    fn test_syslog_year_rollover() {
+        let january = Utc.with_ymd_and_hms(2024, 1, 2, 8, 0, 0).unwrap();
+        let december = Utc.with_ymd_and_hms(2023, 12, 31, 23, 59, 59).unwrap();
+        assert_eq!(
+            parse_syslog_at("Dec 31 23:59:59 host sshd[1]: Accepted", january),
+            Some(december.timestamp())
+        );
+        // Lines from earlier this year, or up to a day ahead (clock skew), stay in it
+        let new_year = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 1).unwrap();
+        assert_eq!(parse_syslog_at("Jan  1 00:00:01 host", january), Some(new_year.timestamp()));
+        let tomorrow = Utc.with_ymd_and_hms(2024, 1, 3, 7, 0, 0).unwrap();
+        assert_eq!(parse_syslog_at("Jan  3 07:00:00 host", january), Some(tomorrow.timestamp()));
+    }
+
+    #[test]
+    fn test_parse_syslog_timestamp() {
+        assert_eq!(
+            parse_syslog_timestamp("<38>1 2023-11-14T22:13:20Z web-01 sshd 1 - - Accepted"),
+            Some(1700000000)
+        );
+        assert!(parse_syslog_timestamp("Nov 14 22:13:20 web-01 sshd[1]: Accepted").is_some());
+        assert_eq!(parse_syslog_timestamp("no timestamp here"), None);
+        assert_eq!(parse_syslog_timestamp("1700000000 epoch isn't syslog"), None);
+    }
+
+    #[test]
+    fn test_clf() {
+        let registry = TimestampRegistry::with_builtins();
+        assert_eq!(
+            registry.parse(r#"10.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET / HTTP/1.0" 200"#),
+            Some(971211336)
+        );
+    }
+
+    #[test]
+    fn test_epoch() {
+        let registry = TimestampRegistry::with_builtins();
+        assert_eq!(registry.parse("1700000000123 login alice"), Some(1700000000));
+        assert_eq!(registry.parse("1700000000.25 login alice"), Some(1700000000));
+    }
+
+    #[test]
+    fn test_custom_pattern_and_order() {
+        let registry = TimestampRegistry::from_names(&["%d.%m.%Y %H:%M:%S", "epoch_seconds"]).unwrap();
+        assert_eq!(registry.parse("14.11.2023 22:13:20 login"), Some(1700000000));
+        assert_eq!(registry.parse("1700000000 login"), Some(1700000000));
+        // RFC 3339 is not registered, so it isn't recognised
+        assert_eq!(registry.parse("2023-11-14T22:13:20Z login"), None);
+    }
+
+    #[test]
+    fn test_unknown_format_name() {
+        assert!(TimestampRegistry::from_names(&["nonsense"]).is_err());
+    }
+}
@@ -6,11 +6,15 @@ pub mod output;
 pub mod geolocation;
 pub mod persistence;
 pub mod alerting;
+pub mod processing;
+pub mod api;
+pub mod telemetry;
 
 // Re-export commonly used types
 pub use models::{LogEvent, AnomalyReport};
-pub use detection::{IdentityContext, GeoVelocityTracker, LoginRateLimiter, GeoLocation};
+pub use detection::{IdentityContext, GeoVelocityTracker, LoginRateLimiter, GeoLocation, DetectionEngine, Decision, Verdict};
 pub use geolocation::GeoIpService;
 pub use persistence::{StateStore, SqliteStateStore};
-pub use alerting::{AlertDispatcher, AlertQueue, AlertConfig};
+pub use alerting::{AlertDispatcher, AlertQueue};
+pub use config::AlertConfig;
 
@@ -1,5 +1,23 @@
+use std::collections::BTreeMap;
 use std::net::IpAddr;
 use serde::{Deserialize, Serialize};
+use crate::input::classify::{HTTP_AUTH_FAILED, HTTP_LOGIN, SSH_FAILED, SSH_FAILED_PREFIX, SSH_LOGIN};
+
+/// Username the parsers fall back to when none could be extracted
+pub const UNKNOWN_USER: &str = "unknown";
+
+/// Report metadata key holding the host that logged the triggering event
+pub const LOG_HOST_METADATA_KEY: &str = "log_host";
+
+/// Whether events of this type are failed authentications
+pub fn is_failed_login_type(event_type: &str) -> bool {
+    event_type == SSH_FAILED || event_type.starts_with(SSH_FAILED_PREFIX) || event_type == HTTP_AUTH_FAILED
+}
+
+/// Whether events of this type are successful logins
+pub fn is_successful_login_type(event_type: &str) -> bool {
+    event_type == SSH_LOGIN || event_type == HTTP_LOGIN
+}
 
 #[derive(Debug, Clone)]
 pub struct LogEvent {
@@ -7,15 +25,151 @@ pub struct LogEvent {
     pub user: String,
     pub ip_address: IpAddr,
     pub event_type: String, 
+    /// Host that logged the event, from the syslog header when present
+    pub host: Option<String>,
+}
+
+impl LogEvent {
+    /// An event with no logging host; set one with `with_host`
+    pub fn new(timestamp: i64, user: impl Into<String>, ip_address: IpAddr, event_type: impl Into<String>) -> Self {
+        LogEvent {
+            timestamp,
+            user: user.into(),
+            ip_address,
+            event_type: event_type.into(),
+            host: None,
+        }
+    }
+
+    /// Record the host that logged the event
+    pub fn with_host(mut self, host: Option<String>) -> Self {
+        self.host = host;
+        self
+    }
+
+    /// Whether the parser couldn't extract a username for this event
+    pub fn has_unknown_user(&self) -> bool {
+        self.user == UNKNOWN_USER
+    }
+
+    /// Whether this is a failed authentication, coarse (`SSH_FAILED`),
+    /// detailed (`SSH_FAILED_*`) or a rejected web request
+    pub fn is_failed_login(&self) -> bool {
+        is_failed_login_type(&self.event_type)
+    }
+
+    /// Whether this is a successful login, over SSH or to a web server
+    pub fn is_successful_login(&self) -> bool {
+        is_successful_login_type(&self.event_type)
+    }
+
+    /// Record the host that logged this event on a report raised for it
+    pub fn annotate_report(&self, report: &mut AnomalyReport) {
+        if let Some(host) = &self.host {
+            report.metadata.insert(LOG_HOST_METADATA_KEY.to_string(), host.clone());
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnomalyReport {
     pub severity: u8,
     pub rule_name: String,
     pub user: String,
     pub detected_ip: String,
     pub trusted_ip: String,
+    /// Time of the triggering event (from the log)
     pub timestamp: i64,
+    /// Wall-clock time when the anomaly was detected
+    #[serde(default)]
+    pub detected_at: i64,
     pub description: String,
-}
\ No newline at end of file
+    /// Enrichment attached after detection (e.g. `detected_hostname`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+    /// How certain the rule is (0.0-1.0), when it can estimate that
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+}
+
+impl AnomalyReport {
+    /// A report detected now, with no IPs, metadata or confidence
+    pub fn new(
+        severity: u8,
+        rule_name: impl Into<String>,
+        user: impl Into<String>,
+        timestamp: i64,
+        description: impl Into<String>,
+    ) -> Self {
+        AnomalyReport {
+            severity,
+            rule_name: rule_name.into(),
+            user: user.into(),
+            detected_ip: String::new(),
+            trusted_ip: String::new(),
+            timestamp,
+            detected_at: chrono::Utc::now().timestamp(),
+            description: description.into(),
+            metadata: BTreeMap::new(),
+            confidence: None,
+        }
+    }
+
+    /// Set the IP the anomaly was detected from
+    pub fn with_detected_ip(mut self, ip: impl Into<String>) -> Self {
+        self.detected_ip = ip.into();
+        self
+    }
+
+    /// Set the IP the user was trusted on before
+    pub fn with_trusted_ip(mut self, ip: impl Into<String>) -> Self {
+        self.trusted_ip = ip.into();
+        self
+    }
+
+    /// Set when the anomaly was detected (defaults to now)
+    pub fn with_detected_at(mut self, detected_at: i64) -> Self {
+        self.detected_at = detected_at;
+        self
+    }
+
+    /// Attach a metadata entry
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Set how certain the rule is (0.0-1.0)
+    pub fn with_confidence(mut self, confidence: Option<f64>) -> Self {
+        self.confidence = confidence;
+        self
+    }
+}
+
+/// What a lockout applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LockoutKind {
+    User,
+    Ip,
+}
+
+impl LockoutKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LockoutKind::User => "user",
+            LockoutKind::Ip => "ip",
+        }
+    }
+}
+
+/// A user or IP that is blocked until `expires_at`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lockout {
+    pub kind: LockoutKind,
+    /// Username or IP address, depending on `kind`
+    pub subject: String,
+    pub reason: String,
+    pub locked_at: i64,
+    pub expires_at: i64,
+}
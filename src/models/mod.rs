@@ -1,4 +1,4 @@
 pub mod event;
 
-pub use event::{LogEvent, AnomalyReport};
+pub use event::{LogEvent, AnomalyReport, Lockout, LockoutKind, UNKNOWN_USER, LOG_HOST_METADATA_KEY};
 
@@ -0,0 +1,103 @@
+//! Suppression audit log
+//!
+//! Events kept from detection by `process_event_types` or the
+//! `unknown_user` policy leave no trace in the report output, so an
+//! overly broad setting could hide an attack unnoticed. With an audit
+//! file configured, [`SuppressionAudit`] appends one JSON line per
+//! suppressed event, naming the setting that suppressed it, for later
+//! review.
+
+use super::OutputError;
+use crate::models::LogEvent;
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::{LineWriter, Write};
+use std::path::Path;
+
+/// One suppressed event
+#[derive(Serialize)]
+struct AuditRecord<'a> {
+    timestamp: i64,
+    user: &'a str,
+    ip: String,
+    event_type: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    host: Option<&'a str>,
+    reason: &'a str,
+}
+
+/// Appends suppressed events to an audit log
+pub struct SuppressionAudit {
+    writer: Box<dyn Write + Send>,
+}
+
+impl SuppressionAudit {
+    /// Append to the audit file at `path`, creating it if needed
+    pub fn open(path: &Path) -> Result<Self, OutputError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(SuppressionAudit::from_writer(Box::new(LineWriter::new(file))))
+    }
+
+    /// Write audit records to any writer
+    pub fn from_writer(writer: Box<dyn Write + Send>) -> Self {
+        SuppressionAudit { writer }
+    }
+
+    /// Record an event suppressed for `reason`
+    pub fn record(&mut self, event: &LogEvent, reason: &str) -> Result<(), OutputError> {
+        let record = AuditRecord {
+            timestamp: event.timestamp,
+            user: &event.user,
+            ip: event.ip_address.to_string(),
+            event_type: &event.event_type,
+            host: event.host.as_deref(),
+            reason,
+        };
+        serde_json::to_writer(&mut self.writer, &record)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    fn create_event(user: &str, event_type: &str) -> LogEvent {
+        LogEvent {
+            timestamp: 1700000000,
+            user: user.to_string(),
+            ip_address: IpAddr::from_str("203.0.113.5").unwrap(),
+            event_type: event_type.to_string(),
+            host: None,
+        }
+    }
+
+    #[test]
+    fn test_suppressed_event_recorded_with_reason() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("suppressed.jsonl");
+        let mut input = Config::default().input;
+        input.process_event_types = Some(vec!["SSH_LOGIN".to_string()]);
+
+        let mut audit = SuppressionAudit::open(&path).unwrap();
+        for event in [create_event("alice", "SSH_LOGIN"), create_event("bob", "SSH_FAILED_MAX_AUTH")] {
+            if let Some(reason) = input.suppression_reason(&event) {
+                audit.record(&event, &reason).unwrap();
+            }
+        }
+        drop(audit);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let record: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(record["user"], "bob");
+        assert_eq!(record["ip"], "203.0.113.5");
+        assert_eq!(record["event_type"], "SSH_FAILED_MAX_AUTH");
+        assert_eq!(record["reason"], "process_event_types [\"SSH_LOGIN\"] doesn't list SSH_FAILED_MAX_AUTH");
+    }
+}
@@ -0,0 +1,154 @@
+//! Elastic Common Schema (ECS) formatting for anomaly reports
+//!
+//! Each report becomes one JSON document using ECS field names, so it can
+//! be indexed into Elasticsearch without an ingest pipeline. Fields with
+//! no ECS equivalent (trusted IP, confidence, remaining metadata) go under
+//! the custom `odin` object.
+
+use crate::geolocation::event_geo::COUNTRY_METADATA_KEY;
+use crate::geolocation::reverse_dns::HOSTNAME_METADATA_KEY;
+use crate::models::AnomalyReport;
+use chrono::{DateTime, SecondsFormat};
+use serde_json::{json, Map, Value};
+use std::net::IpAddr;
+
+/// ECS version the documents conform to
+pub const ECS_VERSION: &str = "8.11.0";
+
+/// RFC 3339 UTC time for a Unix timestamp
+fn rfc3339(timestamp: i64) -> Option<String> {
+    DateTime::from_timestamp(timestamp, 0).map(|dt| dt.to_rfc3339_opts(SecondsFormat::Secs, true))
+}
+
+/// Render a report as an ECS document
+///
+/// - `@timestamp` is the time of the event that triggered the report and
+///   `event.created` the time it was detected.
+/// - `event.severity` is the report severity (1-10), `event.risk_score`
+///   the same on a 0-100 scale.
+/// - `source.ip` is the detected IP; it and `related.ip` only hold values
+///   that parse as addresses, since ECS maps them as `ip`.
+pub fn format_report(report: &AnomalyReport) -> Value {
+    let detected_ip = report.detected_ip.parse::<IpAddr>().ok();
+    let trusted_ip = report.trusted_ip.parse::<IpAddr>().ok();
+
+    let mut source = Map::new();
+    if let Some(ip) = detected_ip {
+        source.insert("ip".to_string(), json!(ip));
+    }
+    if let Some(hostname) = report.metadata.get(HOSTNAME_METADATA_KEY) {
+        source.insert("domain".to_string(), json!(hostname));
+    }
+    if let Some(country) = report.metadata.get(COUNTRY_METADATA_KEY) {
+        source.insert("geo".to_string(), json!({ "country_iso_code": country }));
+    }
+
+    let mut odin = Map::new();
+    if !report.trusted_ip.is_empty() {
+        odin.insert("trusted_ip".to_string(), json!(report.trusted_ip));
+    }
+    if let Some(confidence) = report.confidence {
+        odin.insert("confidence".to_string(), json!(confidence));
+    }
+    let metadata: Map<String, Value> = report
+        .metadata
+        .iter()
+        .filter(|(key, _)| key.as_str() != HOSTNAME_METADATA_KEY && key.as_str() != COUNTRY_METADATA_KEY)
+        .map(|(key, value)| (key.clone(), json!(value)))
+        .collect();
+    if !metadata.is_empty() {
+        odin.insert("metadata".to_string(), Value::Object(metadata));
+    }
+
+    let mut document = json!({
+        "@timestamp": rfc3339(report.timestamp),
+        "ecs": { "version": ECS_VERSION },
+        "message": report.description,
+        "event": {
+            "kind": "alert",
+            "category": ["authentication"],
+            "type": ["info"],
+            "module": "odin",
+            "dataset": "odin.anomaly",
+            "severity": report.severity,
+            "risk_score": f64::from(report.severity) * 10.0,
+            "created": rfc3339(report.detected_at),
+        },
+        "rule": { "name": report.rule_name },
+        "related": {
+            "ip": detected_ip.into_iter().chain(trusted_ip).collect::<Vec<_>>(),
+        },
+    });
+    if !report.user.is_empty() {
+        document["user"] = json!({ "name": report.user });
+        document["related"]["user"] = json!([report.user]);
+    }
+    if !source.is_empty() {
+        document["source"] = Value::Object(source);
+    }
+    if !odin.is_empty() {
+        document["odin"] = Value::Object(odin);
+    }
+    document
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_ecs_field_names() {
+        let mut metadata = BTreeMap::new();
+        metadata.insert(COUNTRY_METADATA_KEY.to_string(), "GB".to_string());
+        metadata.insert("distance_km".to_string(), "5570".to_string());
+        let report = AnomalyReport {
+            severity: 8,
+            rule_name: "Impossible Travel Velocity".to_string(),
+            user: "alice".to_string(),
+            detected_ip: "203.0.113.5".to_string(),
+            trusted_ip: "198.51.100.7".to_string(),
+            timestamp: 1700000000,
+            detected_at: 1700000042,
+            description: "Travel too fast".to_string(),
+            metadata,
+            confidence: Some(0.9),
+        };
+
+        let document = format_report(&report);
+        assert_eq!(document["@timestamp"], "2023-11-14T22:13:20Z");
+        assert_eq!(document["event"]["created"], "2023-11-14T22:14:02Z");
+        assert_eq!(document["event"]["severity"], 8);
+        assert_eq!(document["event"]["kind"], "alert");
+        assert_eq!(document["rule"]["name"], "Impossible Travel Velocity");
+        assert_eq!(document["user"]["name"], "alice");
+        assert_eq!(document["source"]["ip"], "203.0.113.5");
+        assert_eq!(document["source"]["geo"]["country_iso_code"], "GB");
+        assert_eq!(document["related"]["ip"], json!(["203.0.113.5", "198.51.100.7"]));
+        assert_eq!(document["message"], "Travel too fast");
+        assert_eq!(document["odin"]["trusted_ip"], "198.51.100.7");
+        assert_eq!(document["odin"]["metadata"], json!({ "distance_km": "5570" }));
+    }
+
+    #[test]
+    fn test_fields_without_values_omitted() {
+        let report = AnomalyReport {
+            severity: 5,
+            rule_name: "Alerts Suppressed".to_string(),
+            user: String::new(),
+            detected_ip: String::new(),
+            trusted_ip: String::new(),
+            timestamp: 1700000000,
+            detected_at: 1700000000,
+            description: "3 additional anomalies suppressed".to_string(),
+            metadata: BTreeMap::new(),
+            confidence: None,
+        };
+
+        let document = format_report(&report);
+        for field in ["user", "source", "odin"] {
+            assert!(document.get(field).is_none(), "unexpected {}", field);
+        }
+        assert_eq!(document["related"]["ip"], json!([]));
+    }
+}
@@ -1,101 +1,611 @@
+pub mod audit;
+pub mod ecs;
+pub mod serializer;
+pub mod sinks;
+pub mod syslog;
+
+pub use audit::SuppressionAudit;
+pub use serializer::ReportSerializer;
+pub use sinks::OutputSinks;
+pub use syslog::SyslogLevelMap;
+
 use crate::models::AnomalyReport;
-use std::fs::OpenOptions;
-use std::io::{Write, BufWriter};
-use std::path::PathBuf;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Initial delay before retrying to reopen a lost output file
+const DEFAULT_REOPEN_BACKOFF: Duration = Duration::from_secs(1);
+/// Upper bound for the reopen backoff
+const DEFAULT_MAX_REOPEN_BACKOFF: Duration = Duration::from_secs(60);
+/// How often to check that the output file is still at its path
+const DEFAULT_PATH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Errors that can occur while writing anomaly reports
+#[derive(Error, Debug)]
+pub enum OutputError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("Output file unavailable, next reopen attempt in {0:?}")]
+    Unavailable(Duration),
+
+    #[error("Fatal output error: {0}")]
+    Fatal(io::Error),
+
+    #[error("Invalid output configuration: {0}")]
+    InvalidConfig(String),
+}
+
+impl OutputError {
+    /// Whether the error cannot be fixed by retrying (e.g. permission denied)
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, OutputError::Fatal(_))
+    }
+}
 
 /// Output handler for anomaly reports
 pub struct OutputHandler {
     format: OutputFormat,
-    writer: Option<Box<dyn Write + Send>>,
+    serializer: Box<dyn ReportSerializer>,
+    writer: Option<BufWriter<File>>,
+    /// Output still buffered for a file that disappeared, written to the
+    /// reopened file before anything else
+    unflushed: Vec<u8>,
+    /// Path of the output file (None when writing to stdout)
+    file_path: Option<PathBuf>,
+    /// Replacement for stdout when there is no output file
+    console: Option<Box<dyn Write + Send>>,
+    /// Whether the serializer's header has gone to the console yet
+    console_header_written: bool,
+    /// Number of consecutive failed reopen attempts
+    reopen_attempts: u32,
+    /// Earliest time the next reopen may be attempted
+    next_reopen: Option<Instant>,
+    reopen_backoff: Duration,
+    max_reopen_backoff: Duration,
+    /// Minimum time between checks that the file is still at its path
+    path_check_interval: Duration,
+    last_path_check: Instant,
+    /// Minimum time between flushes (zero flushes every write)
+    flush_interval: Duration,
+    last_flush: Instant,
 }
 
 #[derive(Debug, Clone)]
 pub enum OutputFormat {
     Json,
     Jsonl,
+    Syslog,
     Console,
+    /// Aligned columns, one line per report
+    ConsoleCompact,
+    /// One Elastic Common Schema JSON document per line
+    Ecs,
 }
 
 impl OutputFormat {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Self {
         match s.to_lowercase().as_str() {
             "json" => OutputFormat::Json,
             "jsonl" => OutputFormat::Jsonl,
+            "syslog" => OutputFormat::Syslog,
             "console" => OutputFormat::Console,
+            "console-compact" => OutputFormat::ConsoleCompact,
+            "ecs" => OutputFormat::Ecs,
             _ => OutputFormat::Jsonl, // Default
         }
     }
+
+    /// Serializer for the format, with the default syslog levels
+    pub fn serializer(&self) -> Box<dyn ReportSerializer> {
+        match self {
+            OutputFormat::Json => Box::new(serializer::JsonSerializer),
+            OutputFormat::Jsonl => Box::new(serializer::JsonlSerializer),
+            OutputFormat::Syslog => Box::new(serializer::SyslogSerializer::new(SyslogLevelMap::default())),
+            OutputFormat::Console => Box::new(serializer::ConsoleSerializer),
+            OutputFormat::ConsoleCompact => Box::new(serializer::ConsoleCompactSerializer::for_stdout()),
+            OutputFormat::Ecs => Box::new(serializer::EcsSerializer),
+        }
+    }
 }
 
 impl OutputHandler {
     /// Create a new output handler
-    pub fn new(format: OutputFormat, file_path: Option<PathBuf>) -> Result<Self, Box<dyn std::error::Error>> {
-        let writer: Option<Box<dyn Write + Send>> = match (&format, file_path) {
-            (OutputFormat::Console, _) => None,
-            (_, Some(path)) => {
-                let file = OpenOptions::new()
-                    .create(true)
-                    .append(true)
-                    .open(path)?;
-                Some(Box::new(BufWriter::new(file)))
-            }
-            (_, None) => None,
+    pub fn new(format: OutputFormat, file_path: Option<PathBuf>) -> Result<Self, OutputError> {
+        let file_path = match format {
+            OutputFormat::Console | OutputFormat::ConsoleCompact => None,
+            _ => file_path,
+        };
+
+        let serializer = format.serializer();
+        let writer = match &file_path {
+            Some(path) => Some(Self::open_writer(path, serializer.as_ref())?),
+            None => None,
         };
 
         Ok(OutputHandler {
             format,
+            serializer,
             writer,
+            unflushed: Vec::new(),
+            file_path,
+            console: None,
+            console_header_written: false,
+            reopen_attempts: 0,
+            next_reopen: None,
+            reopen_backoff: DEFAULT_REOPEN_BACKOFF,
+            max_reopen_backoff: DEFAULT_MAX_REOPEN_BACKOFF,
+            path_check_interval: DEFAULT_PATH_CHECK_INTERVAL,
+            last_path_check: Instant::now(),
+            flush_interval: Duration::ZERO,
+            last_flush: Instant::now(),
         })
     }
 
+    /// Override the backoff used when reopening a lost output file
+    ///
+    /// The delay starts at `initial` and doubles after each failed attempt,
+    /// capped at `max`.
+    pub fn with_reopen_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.reopen_backoff = initial;
+        self.max_reopen_backoff = max;
+        self
+    }
+
+    /// Check at most once per `interval` that the output file is still at
+    /// its path, reopening it after it was rotated away or deleted
+    ///
+    /// Defaults to every 5 seconds; reports written in between go to the
+    /// old file.
+    pub fn with_path_check_interval(mut self, interval: Duration) -> Self {
+        self.path_check_interval = interval;
+        self
+    }
+
+    /// Send output that would go to stdout to another writer instead
+    pub fn with_console_writer(mut self, writer: Box<dyn Write + Send>) -> Self {
+        self.console = Some(writer);
+        self
+    }
+
+    /// Use a custom severity to syslog level mapping (syslog format only)
+    pub fn with_syslog_levels(mut self, levels: SyslogLevelMap) -> Self {
+        if matches!(self.format, OutputFormat::Syslog) {
+            self.serializer = Box::new(serializer::SyslogSerializer::new(levels));
+        }
+        self
+    }
+
+    /// Render reports with a custom serializer instead of the format's own
+    ///
+    /// Its header starts an empty output file, or goes to the console
+    /// before the first report. Fails if the output file can't be
+    /// reopened for the header.
+    pub fn with_serializer(mut self, serializer: Box<dyn ReportSerializer>) -> Result<Self, OutputError> {
+        self.serializer = serializer;
+        // Reopen so an empty file gets this serializer's header
+        if let Some(path) = &self.file_path {
+            let writer = Self::open_writer(path, self.serializer.as_ref()).map_err(Self::classify)?;
+            self.writer = Some(writer);
+        }
+        Ok(self)
+    }
+
+    /// Buffer writes and flush at most once per `interval`
+    ///
+    /// A zero interval (the default) flushes after every write. Callers
+    /// should call `flush_if_due` periodically and `flush` on shutdown.
+    pub fn with_flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = interval;
+        self
+    }
+
     /// Write an anomaly report
-    pub fn write_report(&mut self, report: &AnomalyReport) -> Result<(), Box<dyn std::error::Error>> {
-        match &self.format {
-            OutputFormat::Json => {
-                let json = serde_json::to_string_pretty(report)?;
-                self.write_output(&format!("{}\n", json))?;
+    pub fn write_report(&mut self, report: &AnomalyReport) -> Result<(), OutputError> {
+        let record = self.serializer.serialize(report)?;
+        self.write_output(&format!("{}\n", record))
+    }
+
+    fn write_output(&mut self, data: &str) -> Result<(), OutputError> {
+        let path = match &self.file_path {
+            Some(path) => path.clone(),
+            None => {
+                let header = match self.console_header_written {
+                    false => self.serializer.header().map(|header| format!("{}\n", header)),
+                    true => None,
+                };
+                let data = format!("{}{}", header.unwrap_or_default(), data);
+                match self.console.as_mut() {
+                    Some(console) => {
+                        console.write_all(data.as_bytes())?;
+                        console.flush()?;
+                    }
+                    None => {
+                        print!("{}", data);
+                        io::stdout().flush()?;
+                    }
+                }
+                self.console_header_written = true;
+                return Ok(());
             }
-            OutputFormat::Jsonl => {
-                let json = serde_json::to_string(report)?;
-                self.write_output(&format!("{}\n", json))?;
+        };
+
+        // An unlinked file still accepts writes, so check the path as well
+        if self.writer.is_some() && self.last_path_check.elapsed() >= self.path_check_interval {
+            self.last_path_check = Instant::now();
+            if !path.exists() {
+                log::warn!("Output file {:?} disappeared, reopening", path);
+                if let Some(writer) = self.writer.take() {
+                    // Dropping the writer would flush into the unlinked file
+                    let (_, buffered) = writer.into_parts();
+                    self.unflushed = buffered.unwrap_or_else(|panicked| panicked.into_inner());
+                }
             }
-            OutputFormat::Console => {
-                let output = format!(
-                    "[{}] {} - User: {}, IP: {} -> {}, Severity: {}\n",
-                    report.rule_name,
-                    report.description,
-                    report.user,
-                    report.trusted_ip,
-                    report.detected_ip,
-                    report.severity
-                );
-                self.write_output(&output)?;
+        }
+
+        let writer = match self.writer.take() {
+            Some(writer) => writer,
+            None => self.reopen(&path)?,
+        };
+        let writer = self.writer.insert(writer);
+
+        if !self.unflushed.is_empty() {
+            if let Err(e) = writer.write_all(&self.unflushed) {
+                self.writer = None;
+                return Err(Self::classify(e));
             }
+            log::info!("Moved {} buffered byte(s) to the reopened output file {:?}", self.unflushed.len(), path);
+            self.unflushed.clear();
+        }
+
+        if let Err(e) = writer.write_all(data.as_bytes()) {
+            // Drop the broken writer so the next write reopens the file
+            self.writer = None;
+            return Err(Self::classify(e));
+        }
+
+        self.flush_if_due()
+    }
+
+    /// Flush buffered output if the flush interval has elapsed
+    pub fn flush_if_due(&mut self) -> Result<(), OutputError> {
+        if self.last_flush.elapsed() < self.flush_interval {
+            return Ok(());
+        }
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+        self.last_flush = Instant::now();
+        if let Err(e) = writer.flush() {
+            self.writer = None;
+            return Err(Self::classify(e));
         }
         Ok(())
     }
 
-    fn write_output(&mut self, data: &str) -> Result<(), Box<dyn std::error::Error>> {
-        match &mut self.writer {
-            Some(writer) => {
-                writer.write_all(data.as_bytes())?;
-                writer.flush()?;
+    /// Recreate the parent directory and reopen the output file, honouring backoff
+    fn reopen(&mut self, path: &Path) -> Result<BufWriter<File>, OutputError> {
+        let now = Instant::now();
+        if let Some(next) = self.next_reopen {
+            if now < next {
+                return Err(OutputError::Unavailable(next - now));
             }
-            None => {
-                print!("{}", data);
-                use std::io::{self, Write};
-                io::stdout().flush()?;
+        }
+
+        let result = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => std::fs::create_dir_all(parent),
+            _ => Ok(()),
+        }
+        .and_then(|_| Self::open_writer(path, self.serializer.as_ref()));
+
+        match result {
+            Ok(writer) => {
+                if self.reopen_attempts > 0 {
+                    log::info!(
+                        "Output file {:?} reopened after {} failed attempt(s)",
+                        path,
+                        self.reopen_attempts
+                    );
+                }
+                self.reopen_attempts = 0;
+                self.next_reopen = None;
+                self.last_path_check = now;
+                Ok(writer)
+            }
+            Err(e) => {
+                let delay = self
+                    .reopen_backoff
+                    .saturating_mul(2u32.saturating_pow(self.reopen_attempts))
+                    .min(self.max_reopen_backoff);
+                self.reopen_attempts = self.reopen_attempts.saturating_add(1);
+                self.next_reopen = Some(now + delay);
+                Err(Self::classify(e))
             }
         }
-        Ok(())
+    }
+
+    fn open_file(path: &Path) -> io::Result<File> {
+        OpenOptions::new().create(true).append(true).open(path)
+    }
+
+    /// Open the output file for appending, starting an empty one with
+    /// the serializer's header
+    ///
+    /// The header is flushed right away so the buffer only ever holds
+    /// reports, which can be moved to a new file if this one disappears.
+    fn open_writer(path: &Path, serializer: &dyn ReportSerializer) -> io::Result<BufWriter<File>> {
+        let file = Self::open_file(path)?;
+        let empty = file.metadata()?.len() == 0;
+        let mut writer = BufWriter::new(file);
+        if let Some(header) = serializer.header().filter(|_| empty) {
+            writeln!(writer, "{}", header)?;
+            writer.flush()?;
+        }
+        Ok(writer)
+    }
+
+    /// Separate errors that retrying cannot fix from transient ones
+    fn classify(e: io::Error) -> OutputError {
+        match e.kind() {
+            io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem => {
+                OutputError::Fatal(e)
+            }
+            _ => OutputError::Io(e),
+        }
     }
 
     /// Flush any buffered output
-    pub fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn flush(&mut self) -> Result<(), OutputError> {
         if let Some(writer) = &mut self.writer {
             writer.flush()?;
         }
+        self.last_flush = Instant::now();
         Ok(())
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn create_test_report() -> AnomalyReport {
+        AnomalyReport {
+            severity: 8,
+            rule_name: "Test Rule".to_string(),
+            user: "alice".to_string(),
+            detected_ip: "1.2.3.4".to_string(),
+            trusted_ip: "5.6.7.8".to_string(),
+            timestamp: 1700000000,
+            detected_at: 1700000000,
+            description: "Test anomaly".to_string(),
+            metadata: BTreeMap::new(),
+            confidence: None,
+        }
+    }
+
+    #[test]
+    fn test_recovers_after_output_directory_removed() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("out");
+        std::fs::create_dir(&dir).unwrap();
+        let path = dir.join("anomalies.jsonl");
+
+        let mut handler = OutputHandler::new(OutputFormat::Jsonl, Some(path.clone()))
+            .unwrap()
+            .with_reopen_backoff(Duration::ZERO, Duration::ZERO)
+            .with_path_check_interval(Duration::ZERO);
+        handler.write_report(&create_test_report()).unwrap();
+
+        // Replace the directory with a plain file so it cannot be recreated
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::write(&dir, b"").unwrap();
+        let err = handler.write_report(&create_test_report()).unwrap_err();
+        assert!(!err.is_fatal());
+
+        // Restore the path; the next write should succeed
+        std::fs::remove_file(&dir).unwrap();
+        std::fs::create_dir(&dir).unwrap();
+        handler.write_report(&create_test_report()).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 1);
+    }
+
+    #[test]
+    fn test_reopen_respects_backoff() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("out");
+        std::fs::create_dir(&dir).unwrap();
+        let path = dir.join("anomalies.jsonl");
+
+        let mut handler = OutputHandler::new(OutputFormat::Jsonl, Some(path))
+            .unwrap()
+            .with_reopen_backoff(Duration::from_secs(60), Duration::from_secs(60))
+            .with_path_check_interval(Duration::ZERO);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::write(&dir, b"").unwrap();
+        assert!(handler.write_report(&create_test_report()).is_err());
+
+        // Still within the backoff window, even though the path is back
+        std::fs::remove_file(&dir).unwrap();
+        std::fs::create_dir(&dir).unwrap();
+        let err = handler.write_report(&create_test_report()).unwrap_err();
+        assert!(matches!(err, OutputError::Unavailable(_)));
+    }
+
+    #[test]
+    fn test_buffered_output_flushed_on_interval_and_shutdown() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("anomalies.jsonl");
+        let mut handler = OutputHandler::new(OutputFormat::Jsonl, Some(path.clone()))
+            .unwrap()
+            .with_flush_interval(Duration::from_millis(50));
+
+        for _ in 0..10 {
+            handler.write_report(&create_test_report()).unwrap();
+        }
+        // Still buffered
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 0);
+
+        std::thread::sleep(Duration::from_millis(60));
+        handler.flush_if_due().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 10);
+
+        for _ in 0..5 {
+            handler.write_report(&create_test_report()).unwrap();
+        }
+        handler.flush().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 15);
+    }
+
+    #[test]
+    fn test_buffered_output_moved_to_reopened_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("anomalies.jsonl");
+        let mut handler = OutputHandler::new(OutputFormat::Jsonl, Some(path.clone()))
+            .unwrap()
+            .with_flush_interval(Duration::from_secs(3600))
+            .with_path_check_interval(Duration::ZERO);
+        for _ in 0..3 {
+            handler.write_report(&create_test_report()).unwrap();
+        }
+
+        // The buffered reports follow the file to its new incarnation
+        std::fs::remove_file(&path).unwrap();
+        handler.write_report(&create_test_report()).unwrap();
+        handler.flush().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 4);
+    }
+
+    #[test]
+    fn test_missing_directory_is_recreated() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("out");
+        std::fs::create_dir(&dir).unwrap();
+        let path = dir.join("anomalies.jsonl");
+
+        let mut handler = OutputHandler::new(OutputFormat::Jsonl, Some(path.clone()))
+            .unwrap()
+            .with_path_check_interval(Duration::ZERO);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        handler.write_report(&create_test_report()).unwrap();
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn test_rotation_checked_on_interval() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("anomalies.jsonl");
+        let rotated = temp.path().join("anomalies.jsonl.1");
+        let mut handler = OutputHandler::new(OutputFormat::Jsonl, Some(path.clone()))
+            .unwrap()
+            .with_path_check_interval(Duration::from_millis(50));
+        handler.write_report(&create_test_report()).unwrap();
+
+        // Within the interval the rotated file keeps receiving reports
+        std::fs::rename(&path, &rotated).unwrap();
+        handler.write_report(&create_test_report()).unwrap();
+        assert!(!path.exists());
+
+        std::thread::sleep(Duration::from_millis(60));
+        handler.write_report(&create_test_report()).unwrap();
+        handler.flush().unwrap();
+        assert_eq!(std::fs::read_to_string(&rotated).unwrap().lines().count(), 2);
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 1);
+    }
+
+    /// Pipe-separated rule and user, with a column header
+    struct PipeSerializer;
+
+    impl ReportSerializer for PipeSerializer {
+        fn serialize(&self, report: &AnomalyReport) -> Result<String, OutputError> {
+            Ok(format!("{}|{}|{}", report.rule_name, report.user, report.severity))
+        }
+
+        fn header(&self) -> Option<String> {
+            Some("rule|user|severity".to_string())
+        }
+    }
+
+    #[test]
+    fn test_custom_serializer_header_written_once() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("anomalies.txt");
+        let new_handler = || {
+            OutputHandler::new(OutputFormat::Jsonl, Some(path.clone()))
+                .unwrap()
+                .with_serializer(Box::new(PipeSerializer))
+                .unwrap()
+        };
+
+        // The header starts a new file, and isn't repeated when appending to it
+        let mut handler = new_handler();
+        handler.write_report(&create_test_report()).unwrap();
+        handler.write_report(&create_test_report()).unwrap();
+        drop(handler);
+        let mut handler = new_handler();
+        handler.write_report(&create_test_report()).unwrap();
+        handler.flush().unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            contents.lines().collect::<Vec<_>>(),
+            vec!["rule|user|severity", "Test Rule|alice|8", "Test Rule|alice|8", "Test Rule|alice|8"]
+        );
+    }
+
+    #[test]
+    fn test_custom_serializer_header_on_console() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("console.txt");
+        let mut handler = OutputHandler::new(OutputFormat::Console, None)
+            .unwrap()
+            .with_console_writer(Box::new(File::create(&path).unwrap()))
+            .with_serializer(Box::new(PipeSerializer))
+            .unwrap();
+        handler.write_report(&create_test_report()).unwrap();
+        handler.write_report(&create_test_report()).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents, "rule|user|severity\nTest Rule|alice|8\nTest Rule|alice|8\n");
+    }
+
+    #[test]
+    fn test_custom_serializer_open_error_returned() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("anomalies.txt");
+        let handler = OutputHandler::new(OutputFormat::Jsonl, Some(path.clone())).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::create_dir(&path).unwrap();
+
+        assert!(handler.with_serializer(Box::new(PipeSerializer)).is_err());
+    }
+
+    #[test]
+    fn test_ecs_output_one_document_per_line() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("anomalies.ecs.jsonl");
+        let mut handler = OutputHandler::new(OutputFormat::from_str("ECS"), Some(path.clone())).unwrap();
+        handler.write_report(&create_test_report()).unwrap();
+        handler.write_report(&create_test_report()).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 2);
+        let document: serde_json::Value = serde_json::from_str(contents.lines().next().unwrap()).unwrap();
+        assert_eq!(document["@timestamp"], "2023-11-14T22:13:20Z");
+        assert_eq!(document["user"]["name"], "alice");
+        assert_eq!(document["source"]["ip"], "1.2.3.4");
+        assert_eq!(document["event"]["severity"], 8);
+        assert_eq!(document["rule"]["name"], "Test Rule");
+    }
+}
@@ -0,0 +1,222 @@
+//! Report serialization
+//!
+//! An [`OutputHandler`](super::OutputHandler) turns each report into text
+//! through a [`ReportSerializer`]. Every built-in format is one
+//! implementation; a new format only needs another, set with
+//! `OutputHandler::with_serializer`.
+
+use super::ecs;
+use super::{OutputError, SyslogLevelMap};
+use crate::models::AnomalyReport;
+
+/// Renders reports in one output format
+pub trait ReportSerializer: Send {
+    /// Render a report as one record, without a trailing newline
+    fn serialize(&self, report: &AnomalyReport) -> Result<String, OutputError>;
+
+    /// Line written once before the first record of a new output (e.g. a
+    /// CSV column header)
+    fn header(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Pretty-printed JSON, one document per report
+pub struct JsonSerializer;
+
+impl ReportSerializer for JsonSerializer {
+    fn serialize(&self, report: &AnomalyReport) -> Result<String, OutputError> {
+        Ok(serde_json::to_string_pretty(report)?)
+    }
+}
+
+/// Compact JSON, one report per line
+pub struct JsonlSerializer;
+
+impl ReportSerializer for JsonlSerializer {
+    fn serialize(&self, report: &AnomalyReport) -> Result<String, OutputError> {
+        Ok(serde_json::to_string(report)?)
+    }
+}
+
+/// RFC 5424 syslog lines
+pub struct SyslogSerializer {
+    levels: SyslogLevelMap,
+}
+
+impl SyslogSerializer {
+    pub fn new(levels: SyslogLevelMap) -> Self {
+        SyslogSerializer { levels }
+    }
+}
+
+impl ReportSerializer for SyslogSerializer {
+    fn serialize(&self, report: &AnomalyReport) -> Result<String, OutputError> {
+        Ok(self.levels.format_report(report))
+    }
+}
+
+/// Human-readable one-line summaries
+pub struct ConsoleSerializer;
+
+impl ReportSerializer for ConsoleSerializer {
+    fn serialize(&self, report: &AnomalyReport) -> Result<String, OutputError> {
+        let confidence = report
+            .confidence
+            .map(|c| format!(", Confidence: {:.0}%", c * 100.0))
+            .unwrap_or_default();
+        Ok(format!(
+            "[{}] {} - User: {}, IP: {} -> {}, Severity: {}{}",
+            report.rule_name,
+            report.description,
+            report.user,
+            report.trusted_ip,
+            report.detected_ip,
+            report.severity,
+            confidence
+        ))
+    }
+}
+
+/// Column widths of the compact console format, in characters
+const COMPACT_USER_WIDTH: usize = 16;
+const COMPACT_IP_WIDTH: usize = 24;
+const COMPACT_RULE_WIDTH: usize = 28;
+
+/// Dense, aligned columns for scanning many reports at a glance
+///
+/// Time, severity, user, IP and rule sit in fixed-width columns (longer
+/// values are cut short with `…`), followed by the description. With
+/// color on, the severity is red from 8, yellow from 5 and green below.
+pub struct ConsoleCompactSerializer {
+    color: bool,
+}
+
+impl ConsoleCompactSerializer {
+    pub fn new(color: bool) -> Self {
+        ConsoleCompactSerializer { color }
+    }
+
+    /// Color when stdout is a terminal and `NO_COLOR` isn't set
+    pub fn for_stdout() -> Self {
+        use std::io::IsTerminal;
+        ConsoleCompactSerializer::new(std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none())
+    }
+
+    fn severity(&self, severity: u8) -> String {
+        let column = format!("{:>3}", severity);
+        if !self.color {
+            return column;
+        }
+        let code = match severity {
+            8.. => "31",
+            5..=7 => "33",
+            _ => "32",
+        };
+        format!("\x1b[{}m{}\x1b[0m", code, column)
+    }
+}
+
+/// Pad or cut `value` to exactly `width` characters
+fn fit(value: &str, width: usize) -> String {
+    if value.chars().count() <= width {
+        return format!("{:<width$}", value);
+    }
+    let mut cut: String = value.chars().take(width - 1).collect();
+    cut.push('…');
+    cut
+}
+
+impl ReportSerializer for ConsoleCompactSerializer {
+    fn serialize(&self, report: &AnomalyReport) -> Result<String, OutputError> {
+        let time = chrono::DateTime::from_timestamp(report.timestamp, 0)
+            .map(|dt| dt.format("%H:%M:%S").to_string())
+            .unwrap_or_else(|| "--:--:--".to_string());
+        Ok(format!(
+            "{} {} {} {} {} {}",
+            time,
+            self.severity(report.severity),
+            fit(&report.user, COMPACT_USER_WIDTH),
+            fit(&report.detected_ip, COMPACT_IP_WIDTH),
+            fit(&report.rule_name, COMPACT_RULE_WIDTH),
+            report.description
+        ))
+    }
+
+    fn header(&self) -> Option<String> {
+        Some(format!(
+            "{:<8} {:>3} {} {} {} DESCRIPTION",
+            "TIME",
+            "SEV",
+            fit("USER", COMPACT_USER_WIDTH),
+            fit("IP", COMPACT_IP_WIDTH),
+            fit("RULE", COMPACT_RULE_WIDTH)
+        ))
+    }
+}
+
+/// Elastic Common Schema documents, one per line
+pub struct EcsSerializer;
+
+impl ReportSerializer for EcsSerializer {
+    fn serialize(&self, report: &AnomalyReport) -> Result<String, OutputError> {
+        Ok(ecs::format_report(report).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn create_report(user: &str, ip: &str, rule_name: &str, severity: u8) -> AnomalyReport {
+        AnomalyReport {
+            severity,
+            rule_name: rule_name.to_string(),
+            user: user.to_string(),
+            detected_ip: ip.to_string(),
+            trusted_ip: String::new(),
+            timestamp: 1700000000,
+            detected_at: 1700000000,
+            description: "Test anomaly".to_string(),
+            metadata: BTreeMap::new(),
+            confidence: None,
+        }
+    }
+
+    #[test]
+    fn test_compact_columns_aligned_and_truncated() {
+        let serializer = ConsoleCompactSerializer::new(false);
+        let short = serializer.serialize(&create_report("bob", "1.2.3.4", "IP Switch", 6)).unwrap();
+        let long = serializer.serialize(&create_report(
+            "a.very.long.username@corp.example.com",
+            "2001:db8:85a3:1234:5678:8a2e:370:7334",
+            "Successful Login From Attacking IP",
+            10,
+        ))
+        .unwrap();
+        let header = serializer.header().unwrap();
+
+        assert_eq!(
+            short,
+            "22:13:20   6 bob              1.2.3.4                  IP Switch                    Test anomaly"
+        );
+        assert_eq!(
+            long,
+            "22:13:20  10 a.very.long.use… 2001:db8:85a3:1234:5678… Successful Login From Attac… Test anomaly"
+        );
+        // Every column starts at the same character offset
+        let offset = |line: &str| line.chars().count() - line.split(' ').next_back().unwrap().chars().count();
+        assert_eq!(offset(&short), offset(&long));
+        assert_eq!(header.find("DESCRIPTION").unwrap(), short.find("Test anomaly").unwrap());
+    }
+
+    #[test]
+    fn test_compact_severity_colored() {
+        let serializer = ConsoleCompactSerializer::new(true);
+        let line = serializer.serialize(&create_report("bob", "1.2.3.4", "IP Switch", 9)).unwrap();
+        assert!(line.contains("\x1b[31m  9\x1b[0m"), "{:?}", line);
+        let line = serializer.serialize(&create_report("bob", "1.2.3.4", "IP Switch", 3)).unwrap();
+        assert!(line.contains("\x1b[32m  3\x1b[0m"), "{:?}", line);
+    }
+}
@@ -0,0 +1,170 @@
+//! Fan-out to several output sinks
+//!
+//! Each sink is an [`OutputHandler`] with its own format and destination,
+//! plus a filter deciding which reports it receives. A failing sink does
+//! not stop the others from being written.
+
+use super::{OutputError, OutputFormat, OutputHandler, SyslogLevelMap};
+use crate::config::{OutputConfig, SinkSpec};
+use crate::models::AnomalyReport;
+use std::time::Duration;
+
+/// A set of output handlers written to together
+pub struct OutputSinks {
+    sinks: Vec<(SinkSpec, OutputHandler)>,
+}
+
+impl OutputSinks {
+    /// Open every sink described by the output configuration
+    pub fn from_config(config: &OutputConfig) -> Result<Self, OutputError> {
+        let syslog_levels = SyslogLevelMap::new(&config.syslog_levels)?;
+        let flush_interval = Duration::from_millis(config.flush_interval_ms);
+
+        let mut sinks = Vec::new();
+        for spec in config.sink_specs() {
+            let handler = OutputHandler::new(OutputFormat::from_str(&spec.format), spec.file_path.clone())?
+                .with_syslog_levels(syslog_levels.clone())
+                .with_flush_interval(flush_interval);
+            sinks.push((spec, handler));
+        }
+        Ok(OutputSinks { sinks })
+    }
+
+    /// Build from already-configured handlers
+    pub fn from_handlers(sinks: Vec<(SinkSpec, OutputHandler)>) -> Self {
+        OutputSinks { sinks }
+    }
+
+    /// Number of sinks
+    pub fn len(&self) -> usize {
+        self.sinks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// Write a report to every sink whose filter accepts it
+    ///
+    /// All sinks are attempted; the first error encountered is returned.
+    pub fn write_report(&mut self, report: &AnomalyReport) -> Result<(), OutputError> {
+        self.for_each(|spec, handler| {
+            if spec.accepts(report) {
+                handler.write_report(report)
+            } else {
+                Ok(())
+            }
+        })
+    }
+
+    /// Flush every sink whose flush interval has elapsed
+    pub fn flush_if_due(&mut self) -> Result<(), OutputError> {
+        self.for_each(|_, handler| handler.flush_if_due())
+    }
+
+    /// Flush all buffered output
+    pub fn flush(&mut self) -> Result<(), OutputError> {
+        self.for_each(|_, handler| handler.flush())
+    }
+
+    fn for_each<F>(&mut self, mut op: F) -> Result<(), OutputError>
+    where
+        F: FnMut(&SinkSpec, &mut OutputHandler) -> Result<(), OutputError>,
+    {
+        let mut first_error = None;
+        for (spec, handler) in &mut self.sinks {
+            if let Err(e) = op(spec, handler) {
+                if first_error.is_some() {
+                    log::warn!("Output sink ({}) failed: {}", spec.format, e);
+                } else {
+                    first_error = Some(e);
+                }
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    /// Console writer that can be inspected after the handler takes it
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn create_report(severity: u8) -> AnomalyReport {
+        AnomalyReport {
+            severity,
+            rule_name: "Test Rule".to_string(),
+            user: "alice".to_string(),
+            detected_ip: "1.2.3.4".to_string(),
+            trusted_ip: "5.6.7.8".to_string(),
+            timestamp: 1700000000,
+            detected_at: 1700000000,
+            description: "Test anomaly".to_string(),
+            metadata: BTreeMap::new(),
+            confidence: None,
+        }
+    }
+
+    fn spec(format: &str, min_severity: u8) -> SinkSpec {
+        SinkSpec {
+            format: format.to_string(),
+            file_path: None,
+            min_severity,
+            rules: None,
+        }
+    }
+
+    #[test]
+    fn test_report_reaches_console_and_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("anomalies.jsonl");
+        let console = SharedBuffer::default();
+
+        let file_handler = OutputHandler::new(OutputFormat::Jsonl, Some(path.clone())).unwrap();
+        let console_handler = OutputHandler::new(OutputFormat::Console, None)
+            .unwrap()
+            .with_console_writer(Box::new(console.clone()));
+        let mut sinks = OutputSinks::from_handlers(vec![
+            (spec("jsonl", 0), file_handler),
+            // Only severe reports reach the console
+            (spec("console", 7), console_handler),
+        ]);
+
+        sinks.write_report(&create_report(8)).unwrap();
+        sinks.write_report(&create_report(3)).unwrap();
+        sinks.flush().unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+        let console_output = String::from_utf8(console.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(console_output.lines().count(), 1);
+        assert!(console_output.contains("[Test Rule] Test anomaly"));
+    }
+
+    #[test]
+    fn test_single_sink_shorthand() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut config = crate::config::Config::default().output;
+        config.format = "jsonl".to_string();
+        config.file_path = Some(temp.path().join("out.jsonl"));
+        assert_eq!(OutputSinks::from_config(&config).unwrap().len(), 1);
+
+        config.sinks = vec![spec("console", 0), spec("console", 5)];
+        assert_eq!(OutputSinks::from_config(&config).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,125 @@
+//! Syslog formatting for anomaly reports
+//!
+//! Reports are rendered as RFC 5424 messages whose PRI encodes the `auth`
+//! facility and a level derived from the report severity. The severity to
+//! level mapping is configurable since SIEMs interpret levels differently.
+
+use super::OutputError;
+use crate::config::SyslogLevelMapping;
+use crate::models::AnomalyReport;
+
+/// Syslog facility used for reports (4 = security/authorization)
+const FACILITY_AUTH: u8 = 4;
+/// Level used when no mapping entry matches (6 = informational)
+const DEFAULT_LEVEL: u8 = 6;
+
+/// Validated severity to syslog level mapping
+#[derive(Debug, Clone)]
+pub struct SyslogLevelMap {
+    /// Entries sorted by descending `min_severity`
+    entries: Vec<SyslogLevelMapping>,
+}
+
+impl SyslogLevelMap {
+    /// Build a mapping, rejecting syslog levels outside 0-7
+    pub fn new(entries: &[SyslogLevelMapping]) -> Result<Self, OutputError> {
+        if let Some(bad) = entries.iter().find(|e| e.level > 7) {
+            return Err(OutputError::InvalidConfig(format!(
+                "syslog level {} for severity {} is outside 0-7",
+                bad.level, bad.min_severity
+            )));
+        }
+        let mut entries = entries.to_vec();
+        entries.sort_by_key(|e| std::cmp::Reverse(e.min_severity));
+        Ok(SyslogLevelMap { entries })
+    }
+
+    /// Syslog level for a report severity
+    pub fn level_for(&self, severity: u8) -> u8 {
+        self.entries
+            .iter()
+            .find(|e| severity >= e.min_severity)
+            .map(|e| e.level)
+            .unwrap_or(DEFAULT_LEVEL)
+    }
+
+    /// PRI value (facility * 8 + level) for a report severity
+    pub fn pri(&self, severity: u8) -> u8 {
+        FACILITY_AUTH * 8 + self.level_for(severity)
+    }
+
+    /// Render a report as a single RFC 5424 line (without the newline)
+    pub fn format_report(&self, report: &AnomalyReport) -> String {
+        let timestamp = chrono::DateTime::from_timestamp(report.detected_at, 0)
+            .map(|dt| dt.to_rfc3339())
+            .unwrap_or_else(|| "-".to_string());
+        let confidence = report
+            .confidence
+            .map(|c| format!(" confidence={:.2}", c))
+            .unwrap_or_default();
+        format!(
+            "<{}>1 {} - odin - - - [{}] {} user={} ip={} trusted_ip={} severity={}{}",
+            self.pri(report.severity),
+            timestamp,
+            report.rule_name,
+            report.description,
+            report.user,
+            report.detected_ip,
+            report.trusted_ip,
+            report.severity,
+            confidence
+        )
+    }
+}
+
+impl Default for SyslogLevelMap {
+    fn default() -> Self {
+        let defaults = crate::config::Config::default().output.syslog_levels;
+        SyslogLevelMap::new(&defaults).expect("default syslog levels are valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn mapping(min_severity: u8, level: u8) -> SyslogLevelMapping {
+        SyslogLevelMapping { min_severity, level }
+    }
+
+    #[test]
+    fn test_severity_nine_uses_configured_level() {
+        let map = SyslogLevelMap::new(&[mapping(5, 4), mapping(9, 1)]).unwrap();
+        let report = AnomalyReport {
+            severity: 9,
+            rule_name: "Impossible Travel".to_string(),
+            user: "alice".to_string(),
+            detected_ip: "1.2.3.4".to_string(),
+            trusted_ip: "5.6.7.8".to_string(),
+            timestamp: 1700000000,
+            detected_at: 1700000000,
+            description: "test".to_string(),
+            metadata: BTreeMap::new(),
+            confidence: None,
+        };
+
+        // auth facility (4) * 8 + alert (1)
+        assert!(map.format_report(&report).starts_with("<33>1 2023-11-14T22:13:20+00:00 "));
+        assert_eq!(map.level_for(6), 4);
+        assert_eq!(map.level_for(2), DEFAULT_LEVEL);
+    }
+
+    #[test]
+    fn test_default_mapping() {
+        let map = SyslogLevelMap::default();
+        assert_eq!(map.level_for(10), 2);
+        assert_eq!(map.level_for(7), 3);
+        assert_eq!(map.level_for(1), 6);
+    }
+
+    #[test]
+    fn test_rejects_invalid_level() {
+        assert!(SyslogLevelMap::new(&[mapping(9, 8)]).is_err());
+    }
+}
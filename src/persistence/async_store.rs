@@ -0,0 +1,127 @@
+//! Running store operations off the async runtime
+//!
+//! [`StateStore`] is synchronous and the SQLite backend blocks on disk
+//! I/O, so calling it directly from async code stalls the executor thread
+//! and every task queued on it. Rather than a second, async trait that
+//! each backend would have to implement twice, the daemon goes through
+//! [`AsyncStateStore`], which runs calls on tokio's blocking thread pool.
+//! A natively async backend (Redis, Postgres) can still implement the
+//! sync trait on top of its own runtime handle.
+//!
+//! Detection rules call the store from inside their checks, under a lock;
+//! those are wrapped in [`run_blocking`] instead, which lets the runtime
+//! move other tasks off the current worker while the rule runs.
+
+use super::{PersistenceError, StateStore};
+use crate::models::AnomalyReport;
+use std::net::IpAddr;
+use std::sync::Arc;
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+/// Runs [`StateStore`] calls on the blocking thread pool
+#[derive(Clone)]
+pub struct AsyncStateStore {
+    inner: Arc<dyn StateStore>,
+}
+
+impl AsyncStateStore {
+    pub fn new(store: Arc<dyn StateStore>) -> Self {
+        AsyncStateStore { inner: store }
+    }
+
+    /// The wrapped synchronous store
+    pub fn inner(&self) -> &Arc<dyn StateStore> {
+        &self.inner
+    }
+
+    /// Run an arbitrary store operation on the blocking thread pool
+    pub async fn run<T, F>(&self, operation: F) -> Result<T, PersistenceError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn StateStore) -> Result<T, PersistenceError> + Send + 'static,
+    {
+        let store = self.inner.clone();
+        tokio::task::spawn_blocking(move || operation(store.as_ref())).await?
+    }
+
+    pub async fn store_anomaly_report(&self, report: AnomalyReport) -> Result<(), PersistenceError> {
+        self.run(move |store| store.store_anomaly_report(&report)).await
+    }
+
+    pub async fn get_user_last_ip(&self, user: &str) -> Result<Option<(IpAddr, i64)>, PersistenceError> {
+        let user = user.to_string();
+        self.run(move |store| store.get_user_last_ip(&user)).await
+    }
+
+    pub async fn prune_old_data(&self, before_timestamp: i64) -> Result<usize, PersistenceError> {
+        self.run(move |store| store.prune_old_data(before_timestamp)).await
+    }
+}
+
+/// Run blocking work (e.g. a rule that touches the store) from async code
+///
+/// On a multi-threaded runtime the current worker's other tasks are
+/// handed to another thread for the duration; elsewhere (current-thread
+/// runtime, no runtime) `work` simply runs inline.
+pub fn run_blocking<T>(work: impl FnOnce() -> T) -> T {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(work)
+        }
+        _ => work(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::SqliteStateStore;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Count ticks of a task running alongside `slow`
+    async fn ticks_during<F: std::future::Future>(slow: F) -> usize {
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let ticker = {
+            let ticks = ticks.clone();
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+        };
+        // Let the ticker start before the slow operation begins
+        tokio::task::yield_now().await;
+        slow.await;
+        ticker.abort();
+        ticks.load(Ordering::SeqCst)
+    }
+
+    #[tokio::test]
+    async fn test_slow_store_call_does_not_block_runtime() {
+        let store = AsyncStateStore::new(Arc::new(SqliteStateStore::in_memory().unwrap()));
+        let ticks = ticks_during(async {
+            let reports = store
+                .run(|store| {
+                    std::thread::sleep(Duration::from_millis(200));
+                    store.get_recent_reports(10)
+                })
+                .await
+                .unwrap();
+            assert!(reports.is_empty());
+        })
+        .await;
+        // A sleep on the (single) runtime thread would allow no ticks at all
+        assert!(ticks >= 5, "only {} ticks", ticks);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_run_blocking_hands_off_worker() {
+        let ticks = ticks_during(async {
+            run_blocking(|| std::thread::sleep(Duration::from_millis(200)));
+        })
+        .await;
+        assert!(ticks >= 5, "only {} ticks", ticks);
+    }
+}
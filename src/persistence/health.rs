@@ -0,0 +1,121 @@
+//! Store errors during detection
+//!
+//! Detection must not go blind because the database is flaky. Every rule
+//! backed by a store follows the same policy when a store call fails:
+//! the error is logged and counted here, and the rule carries on with its
+//! in-memory state for that operation. The event is never dropped.
+//!
+//! Falling back has predictable costs: a user or IP whose state was only
+//! in the store looks new, and writes that failed are missing after a
+//! restart. The first seen rule is the exception: there "looks new" is
+//! the alert itself, so a failed lookup treats the user as known.
+
+use super::PersistenceError;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counts store errors that detection fell back from
+#[derive(Debug, Default)]
+pub struct StoreHealth {
+    errors: AtomicU64,
+}
+
+impl StoreHealth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Log and count a failed store call
+    pub fn record(&self, operation: &str, error: &PersistenceError) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        log::warn!("Failed to {} in persistence, using in-memory state: {}", operation, error);
+    }
+
+    /// Store errors counted so far
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FirstSeenConfig;
+    use crate::detection::{FirstSeenDetector, GeoLocation, GeoVelocityTracker, IdentityContext, LoginRateLimiter};
+    use crate::models::LogEvent;
+    use crate::persistence::testing::FlakyStore;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    fn create_event(user: &str, ip: &str, event_type: &str, timestamp: i64) -> LogEvent {
+        LogEvent {
+            timestamp,
+            user: user.to_string(),
+            ip_address: IpAddr::from_str(ip).unwrap(),
+            event_type: event_type.to_string(),
+            host: None,
+        }
+    }
+
+    #[test]
+    fn test_ip_switch_and_travel_fall_back_to_memory() {
+        let store = Arc::new(FlakyStore::new());
+        let health = Arc::new(StoreHealth::new());
+        let mut context = IdentityContext::with_persistence(store.clone()).with_store_health(health.clone());
+        let mut tracker = GeoVelocityTracker::with_persistence(900.0, store.clone()).with_store_health(health.clone());
+        store.set_failing(true);
+
+        let nyc = GeoLocation { latitude: 40.7128, longitude: -74.0060 };
+        let london = GeoLocation { latitude: 51.5074, longitude: -0.1278 };
+        let first = create_event("alice", "198.51.100.7", "SSH_LOGIN", 1000);
+        let second = create_event("alice", "203.0.113.5", "SSH_LOGIN", 1600);
+
+        assert!(context.check_for_ip_switch(&first).is_none());
+        assert!(tracker.check_impossible_travel(&first, nyc).is_none());
+        let switch = context.check_for_ip_switch(&second).unwrap();
+        assert_eq!(switch.trusted_ip, "198.51.100.7");
+        let travel = tracker.check_impossible_travel(&second, london).unwrap();
+        assert_eq!(travel.rule_name, "Impossible Travel Velocity");
+
+        // Lookups and writes for both events failed
+        assert_eq!(health.errors(), 6);
+    }
+
+    #[test]
+    fn test_rate_limit_counts_from_memory() {
+        let store = Arc::new(FlakyStore::new());
+        let health = Arc::new(StoreHealth::new());
+        let mut limiter = LoginRateLimiter::with_persistence(300, 3, 100, store.clone()).with_store_health(health.clone());
+
+        // Two attempts reach the store, then it starts failing
+        for i in 0..2 {
+            assert!(limiter.check_rate_limit(&create_event("alice", "203.0.113.5", "SSH_FAILED", 1000 + i)).is_empty());
+        }
+        store.set_failing(true);
+        for i in 2..4 {
+            assert!(limiter.check_rate_limit(&create_event("alice", "203.0.113.5", "SSH_FAILED", 1000 + i)).is_empty());
+        }
+        let reports = limiter.check_rate_limit(&create_event("alice", "203.0.113.5", "SSH_FAILED", 1004));
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].rule_name, "User Rate Limit Exceeded");
+        assert_eq!(health.errors(), 3);
+    }
+
+    #[test]
+    fn test_first_seen_treats_store_error_as_known() {
+        let store = Arc::new(FlakyStore::new());
+        let health = Arc::new(StoreHealth::new());
+        let mut detector = FirstSeenDetector::new(&FirstSeenConfig::default())
+            .with_persistence(store.clone())
+            .with_store_health(health.clone());
+        store.set_failing(true);
+
+        assert!(detector.check_login(&create_event("alice", "203.0.113.5", "SSH_LOGIN", 1000)).is_none());
+        assert_eq!(health.errors(), 1);
+
+        // Not cached as known, so the store is asked again once it recovers
+        store.set_failing(false);
+        assert!(detector.check_login(&create_event("alice", "203.0.113.5", "SSH_LOGIN", 1100)).is_some());
+        assert!(detector.check_login(&create_event("alice", "203.0.113.5", "SSH_LOGIN", 1200)).is_none());
+    }
+}
@@ -0,0 +1,127 @@
+//! Offline database maintenance
+//!
+//! Lets operators prune and compact the state database on demand (e.g.
+//! from `isds_cli maintenance`) instead of waiting for the daemon's
+//! maintenance interval.
+
+use super::{PersistenceError, SqliteStateStore, StateStore};
+use std::path::Path;
+
+/// Result of a maintenance run
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaintenanceSummary {
+    /// Rows deleted by pruning
+    pub rows_removed: usize,
+    /// Database file size in bytes before maintenance
+    pub size_before: u64,
+    /// Database file size in bytes after maintenance
+    pub size_after: u64,
+}
+
+/// Prune data older than `prune_before` and optionally compact the file
+///
+/// The database must already exist; this never creates a new one.
+pub fn run_maintenance(
+    db_path: &Path,
+    prune_before: Option<i64>,
+    compact: bool,
+) -> Result<MaintenanceSummary, PersistenceError> {
+    let size_before = std::fs::metadata(db_path)?.len();
+    let store = SqliteStateStore::new(db_path)?;
+
+    let rows_removed = match prune_before {
+        Some(cutoff) => store.prune_old_data(cutoff)?,
+        None => 0,
+    };
+    if compact {
+        store.compact()?;
+    }
+    drop(store);
+
+    let size_after = std::fs::metadata(db_path)?.len();
+    Ok(MaintenanceSummary {
+        rows_removed,
+        size_before,
+        size_after,
+    })
+}
+
+/// Parse an age such as `90s`, `30m`, `12h`, `7d` or `2w` into seconds
+///
+/// A bare number is taken as seconds.
+pub fn parse_age(age: &str) -> Result<i64, String> {
+    let age = age.trim();
+    let split = age.find(|c: char| !c.is_ascii_digit()).unwrap_or(age.len());
+    let (number, unit) = age.split_at(split);
+    let number: i64 = number
+        .parse()
+        .map_err(|_| format!("Invalid age: {}", age))?;
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        _ => return Err(format!("Invalid age unit in {}, expected s, m, h, d or w", age)),
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("Age {} is too large", age))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("7d"), Ok(7 * 86400));
+        assert_eq!(parse_age("12h"), Ok(12 * 3600));
+        assert_eq!(parse_age("90"), Ok(90));
+        assert!(parse_age("7y").is_err());
+        assert!(parse_age("d").is_err());
+        assert_eq!(parse_age("9223372036854775807w"), Err("Age 9223372036854775807w is too large".to_string()));
+    }
+
+    #[test]
+    fn test_prune_populated_store() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("state.db");
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        {
+            let store = SqliteStateStore::new(&path).unwrap();
+            for ts in [1000, 2000, 3000, 9000, 9500] {
+                store.add_login_attempt("alice", &ip, ts, 1).unwrap();
+            }
+        }
+
+        let summary = run_maintenance(&path, Some(5000), true).unwrap();
+        assert_eq!(summary.rows_removed, 3);
+        assert!(summary.size_before > 0);
+        assert!(summary.size_after > 0);
+
+        let store = SqliteStateStore::new(&path).unwrap();
+        assert_eq!(store.get_user_attempt_count("alice", 0).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_missing_database_is_an_error() {
+        let temp = tempfile::tempdir().unwrap();
+        assert!(run_maintenance(&temp.path().join("missing.db"), Some(0), false).is_err());
+    }
+
+    #[test]
+    fn test_locked_database_is_reported() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("state.db");
+        drop(SqliteStateStore::new(&path).unwrap());
+
+        // Hold a write lock as a running daemon would mid-transaction
+        let holder = rusqlite::Connection::open(&path).unwrap();
+        holder.execute_batch("BEGIN EXCLUSIVE;").unwrap();
+
+        let err = run_maintenance(&path, Some(0), false).unwrap_err();
+        assert!(err.is_locked(), "unexpected error: {}", err);
+    }
+}
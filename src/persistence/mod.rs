@@ -3,12 +3,26 @@
 //! This module provides persistent storage for detection state,
 //! allowing the daemon to maintain context across restarts.
 
+pub mod async_store;
+pub mod health;
+pub mod maintenance;
+pub mod path_template;
+pub mod report_sinks;
+pub mod seed;
 pub mod sqlite_store;
+#[cfg(test)]
+pub(crate) mod testing;
 
+pub use async_store::{run_blocking, AsyncStateStore};
+pub use health::StoreHealth;
+pub use maintenance::{parse_age, run_maintenance, MaintenanceSummary};
+pub use path_template::{expand_database_path, is_templated};
+pub use report_sinks::ReportStores;
+pub use seed::{seed_baselines, Baselines, SeedSummary, UserBaseline};
 pub use sqlite_store::SqliteStateStore;
 
 use crate::detection::GeoLocation;
-use crate::models::AnomalyReport;
+use crate::models::{AnomalyReport, Lockout};
 use std::net::IpAddr;
 use thiserror::Error;
 
@@ -21,11 +35,47 @@ pub enum PersistenceError {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("Persistence task failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
+
     #[error("Invalid data in database: {0}")]
     InvalidData(String),
 
     #[error("Database not initialized")]
     NotInitialized,
+
+    #[error("Timed out after {0:?}")]
+    Timeout(std::time::Duration),
+}
+
+impl PersistenceError {
+    /// Whether the database is locked by another process (e.g. the daemon)
+    pub fn is_locked(&self) -> bool {
+        matches!(
+            self,
+            PersistenceError::Database(rusqlite::Error::SqliteFailure(e, _))
+                if matches!(
+                    e.code,
+                    rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked
+                )
+        )
+    }
+}
+
+/// An alert persisted after delivery failed
+#[derive(Debug, Clone)]
+pub struct PendingAlert {
+    /// Store-assigned identifier, used to remove the alert once delivered
+    pub id: i64,
+    /// Unix timestamp when the alert was first persisted
+    pub queued_at: i64,
+    pub report: AnomalyReport,
+    /// Channel the alert still has to be delivered to, or None for every
+    /// configured channel (alerts queued by older versions)
+    pub channel: Option<String>,
 }
 
 /// Trait for state persistence backends
@@ -51,17 +101,31 @@ pub trait StateStore: Send + Sync {
         timestamp: i64,
     ) -> Result<(), PersistenceError>;
 
+    /// Get the IPs remembered for a user, least recently seen first
+    fn get_user_known_ips(&self, user: &str) -> Result<Vec<IpAddr>, PersistenceError>;
+
+    /// Remember an IP for a user, keeping the `max_entries` most recently
+    /// seen
+    fn add_user_known_ip(
+        &self,
+        user: &str,
+        ip: &IpAddr,
+        timestamp: i64,
+        max_entries: usize,
+    ) -> Result<(), PersistenceError>;
+
     // =====================
     // User Location Tracking
     // =====================
 
     /// Get the last known location for a user
     ///
-    /// Returns the timestamp and geographic location of the last login
+    /// Returns the timestamp, geographic location and IP address of the
+    /// last login
     fn get_user_last_location(
         &self,
         user: &str,
-    ) -> Result<Option<(i64, GeoLocation)>, PersistenceError>;
+    ) -> Result<Option<(i64, GeoLocation, IpAddr)>, PersistenceError>;
 
     /// Add a location record for a user
     fn add_user_location(
@@ -72,16 +136,26 @@ pub trait StateStore: Send + Sync {
         ip: &IpAddr,
     ) -> Result<(), PersistenceError>;
 
+    // =====================
+    // Known Users
+    // =====================
+
+    /// Remember that a user has been seen, returning true the first time
+    ///
+    /// Known users are never pruned, so a user is only ever new once.
+    fn record_user_seen(&self, user: &str, timestamp: i64) -> Result<bool, PersistenceError>;
+
     // =====================
     // Login Attempt Tracking
     // =====================
 
-    /// Record a login attempt
+    /// Record a login attempt counting as `weight` attempts
     fn add_login_attempt(
         &self,
         user: &str,
         ip: &IpAddr,
         timestamp: i64,
+        weight: usize,
     ) -> Result<(), PersistenceError>;
 
     /// Get timestamps of login attempts for a user within a time window
@@ -94,27 +168,25 @@ pub trait StateStore: Send + Sync {
     /// Get timestamps of login attempts from an IP within a time window
     fn get_ip_attempts_in_window(
         &self,
-        ip: &str,
+        ip: &IpAddr,
         window_start: i64,
     ) -> Result<Vec<i64>, PersistenceError>;
 
-    /// Get count of login attempts for a user within a time window
+    /// Get count of login attempts for a user within a time window,
+    /// summing their weights
     fn get_user_attempt_count(
         &self,
         user: &str,
         window_start: i64,
-    ) -> Result<usize, PersistenceError> {
-        Ok(self.get_user_attempts_in_window(user, window_start)?.len())
-    }
+    ) -> Result<usize, PersistenceError>;
 
-    /// Get count of login attempts from an IP within a time window
+    /// Get count of login attempts from an IP within a time window,
+    /// summing their weights
     fn get_ip_attempt_count(
         &self,
-        ip: &str,
+        ip: &IpAddr,
         window_start: i64,
-    ) -> Result<usize, PersistenceError> {
-        Ok(self.get_ip_attempts_in_window(ip, window_start)?.len())
-    }
+    ) -> Result<usize, PersistenceError>;
 
     // =====================
     // Anomaly Report Storage
@@ -126,13 +198,62 @@ pub trait StateStore: Send + Sync {
     /// Get recent anomaly reports
     fn get_recent_reports(&self, limit: usize) -> Result<Vec<AnomalyReport>, PersistenceError>;
 
+    // =====================
+    // Lockouts
+    // =====================
+
+    /// Record a lockout, replacing any existing one for the same subject
+    fn add_lockout(&self, lockout: &Lockout) -> Result<(), PersistenceError>;
+
+    /// Get lockouts that have not expired at `now`
+    fn get_active_lockouts(&self, now: i64) -> Result<Vec<Lockout>, PersistenceError>;
+
+    // =====================
+    // Pending Alerts
+    // =====================
+
+    /// Persist an alert that couldn't be delivered to `channel`, keeping at
+    /// most `max_entries` (the oldest are dropped first)
+    fn enqueue_pending_alert(
+        &self,
+        report: &AnomalyReport,
+        channel: Option<&str>,
+        queued_at: i64,
+        max_entries: usize,
+    ) -> Result<(), PersistenceError>;
+
+    /// Get up to `limit` pending alerts, oldest first
+    fn get_pending_alerts(&self, limit: usize) -> Result<Vec<PendingAlert>, PersistenceError>;
+
+    /// Remove a pending alert after it was delivered
+    fn remove_pending_alert(&self, id: i64) -> Result<(), PersistenceError>;
+
+    /// Drop pending alerts queued before the specified timestamp
+    fn expire_pending_alerts(&self, before_timestamp: i64) -> Result<usize, PersistenceError>;
+
+    // =====================
+    // Report Suppression
+    // =====================
+
+    /// Record that reports for `key` below `severity` are suppressed until
+    /// `cooldown_until`, replacing any earlier cooldown
+    fn set_dedup_state(&self, key: &str, cooldown_until: i64, severity: u8) -> Result<(), PersistenceError>;
+
+    /// Get the cooldown of `key` as (cooldown_until, severity)
+    fn get_dedup_state(&self, key: &str) -> Result<Option<(i64, u8)>, PersistenceError>;
+
+    /// Remove cooldowns that ended at or before `now`
+    fn prune_dedup_state(&self, now: i64) -> Result<usize, PersistenceError>;
+
     // =====================
     // Maintenance
     // =====================
 
     /// Remove old data before the specified timestamp
     ///
-    /// This is used to prevent unbounded growth of the database
+    /// This is used to prevent unbounded growth of the database.
+    /// Tracking data is pruned by event timestamp, anomaly reports by
+    /// their `detected_at` time.
     fn prune_old_data(&self, before_timestamp: i64) -> Result<usize, PersistenceError>;
 
     /// Clear all data (useful for testing)
@@ -141,7 +262,5 @@ pub trait StateStore: Send + Sync {
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-
     // Tests are in sqlite_store.rs since they need an implementation
 }
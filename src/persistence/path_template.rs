@@ -0,0 +1,47 @@
+//! Date templating for database paths
+//!
+//! `database_path` may contain `{YYYY}`, `{MM}` and `{DD}` placeholders,
+//! e.g. `odin_{YYYY}{MM}{DD}.db`, so state can be partitioned into one
+//! file per day and old days removed by deleting files.
+
+use chrono::{Datelike, NaiveDate};
+use std::path::{Path, PathBuf};
+
+const PLACEHOLDERS: &[&str] = &["{YYYY}", "{MM}", "{DD}"];
+
+/// Whether the path contains any date placeholder
+pub fn is_templated(template: &Path) -> bool {
+    let text = template.to_string_lossy();
+    PLACEHOLDERS.iter().any(|p| text.contains(p))
+}
+
+/// Substitute the date placeholders in a database path template
+pub fn expand_database_path(template: &Path, date: NaiveDate) -> PathBuf {
+    let expanded = template
+        .to_string_lossy()
+        .replace("{YYYY}", &format!("{:04}", date.year()))
+        .replace("{MM}", &format!("{:02}", date.month()))
+        .replace("{DD}", &format!("{:02}", date.day()));
+    PathBuf::from(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_template() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 7).unwrap();
+        let path = expand_database_path(Path::new("/var/lib/odin/odin_{YYYY}{MM}{DD}.db"), date);
+        assert_eq!(path, PathBuf::from("/var/lib/odin/odin_20240307.db"));
+    }
+
+    #[test]
+    fn test_plain_path_unchanged() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 7).unwrap();
+        let template = Path::new("odin_state.db");
+        assert!(!is_templated(template));
+        assert_eq!(expand_database_path(template, date), PathBuf::from("odin_state.db"));
+        assert!(is_templated(Path::new("state/{YYYY}/{MM}.db")));
+    }
+}
@@ -0,0 +1,216 @@
+//! Storing reports in more than one database
+//!
+//! Some setups want anomaly reports kept both locally and in a second,
+//! central database (during a migration, or for redundancy) without
+//! duplicating the rest of the detection state. [`ReportStores`] writes
+//! each report to the primary store and to every report sink. Only
+//! reports go to the sinks; rules keep reading and writing the primary.
+//! Each target can skip reports below its own minimum severity; skipped
+//! reports still reach output and alerting.
+//!
+//! Each target fails independently: every write runs on its own blocking
+//! task under a shared deadline, so a slow sink delays report handling by
+//! at most the timeout, and an error or timeout on one is returned
+//! alongside its name without stopping the others. A write that timed out
+//! may still complete in the background. Sinks are never pruned by the
+//! daemon.
+
+use super::{PersistenceError, StateStore};
+use crate::models::AnomalyReport;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Name given to the primary store in failures
+pub const PRIMARY_STORE_NAME: &str = "primary";
+
+/// How long report handling waits for the stores by default
+const DEFAULT_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A store reports are written to
+#[derive(Clone)]
+struct ReportTarget {
+    name: String,
+    store: Arc<dyn StateStore>,
+    /// Reports below this severity aren't stored here
+    min_severity: u8,
+}
+
+/// The stores every anomaly report is written to
+#[derive(Clone)]
+pub struct ReportStores {
+    /// Primary first
+    targets: Vec<ReportTarget>,
+    /// How long to wait for the writes of one report
+    timeout: Duration,
+}
+
+impl Default for ReportStores {
+    fn default() -> Self {
+        ReportStores::new(None)
+    }
+}
+
+impl ReportStores {
+    /// Write reports to the primary store, if there is one
+    pub fn new(primary: Option<Arc<dyn StateStore>>) -> Self {
+        ReportStores {
+            targets: primary
+                .into_iter()
+                .map(|store| ReportTarget {
+                    name: PRIMARY_STORE_NAME.to_string(),
+                    store,
+                    min_severity: 0,
+                })
+                .collect(),
+            timeout: DEFAULT_WRITE_TIMEOUT,
+        }
+    }
+
+    /// Give up waiting for a store after `timeout` (default 5 seconds)
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Only store reports at or above this severity in the primary store
+    pub fn with_min_severity(mut self, severity: u8) -> Self {
+        if let Some(primary) = self.targets.iter_mut().find(|t| t.name == PRIMARY_STORE_NAME) {
+            primary.min_severity = severity;
+        }
+        self
+    }
+
+    /// Also write reports at or above `min_severity` to a sink
+    pub fn with_sink(mut self, name: impl Into<String>, store: Arc<dyn StateStore>, min_severity: u8) -> Self {
+        self.targets.push(ReportTarget {
+            name: name.into(),
+            store,
+            min_severity,
+        });
+        self
+    }
+
+    /// Whether reports are stored anywhere
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    /// Store a report in every target that takes its severity,
+    /// concurrently, returning the ones that failed or didn't finish
+    /// within the timeout
+    pub async fn store_anomaly_report(&self, report: &AnomalyReport) -> Vec<(String, PersistenceError)> {
+        let deadline = tokio::time::Instant::now() + self.timeout;
+        let writes: Vec<_> = self
+            .targets
+            .iter()
+            .filter(|target| report.severity >= target.min_severity)
+            .map(|target| {
+                let store = target.store.clone();
+                let report = report.clone();
+                (&target.name, tokio::task::spawn_blocking(move || store.store_anomaly_report(&report)))
+            })
+            .collect();
+
+        let mut failures = Vec::new();
+        for (name, write) in writes {
+            let result = match tokio::time::timeout_at(deadline, write).await {
+                Ok(Ok(result)) => result,
+                Ok(Err(e)) => Err(e.into()),
+                Err(_) => Err(PersistenceError::Timeout(self.timeout)),
+            };
+            if let Err(e) = result {
+                failures.push((name.clone(), e));
+            }
+        }
+        failures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FirstSeenConfig;
+    use crate::detection::FirstSeenDetector;
+    use crate::models::LogEvent;
+    use crate::persistence::testing::FlakyStore;
+    use crate::persistence::SqliteStateStore;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    fn store() -> Arc<dyn StateStore> {
+        Arc::new(SqliteStateStore::in_memory().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_reports_land_in_every_sink() {
+        let primary = store();
+        let local = store();
+        let central = store();
+        let stores = ReportStores::new(Some(primary.clone()))
+            .with_sink("local", local.clone(), 0)
+            .with_sink("central", central.clone(), 0);
+
+        // Rules keep their state in the primary store only
+        let mut detector = FirstSeenDetector::new(&FirstSeenConfig::default()).with_persistence(primary.clone());
+        let event = LogEvent {
+            timestamp: 1700000000,
+            user: "alice".to_string(),
+            ip_address: IpAddr::from_str("203.0.113.5").unwrap(),
+            event_type: "SSH_LOGIN".to_string(),
+            host: None,
+        };
+        let report = detector.check_login(&event).unwrap();
+        assert!(stores.store_anomaly_report(&report).await.is_empty());
+
+        for target in [&primary, &local, &central] {
+            let reports = target.get_recent_reports(10).unwrap();
+            assert_eq!(reports.len(), 1);
+            assert_eq!(reports[0].rule_name, "First Seen User");
+        }
+        assert!(!primary.record_user_seen("alice", 1700000000).unwrap());
+        assert!(local.record_user_seen("alice", 1700000000).unwrap());
+        assert!(central.record_user_seen("alice", 1700000000).unwrap());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_slow_sink_times_out_alone() {
+        let primary = store();
+        let slow = Arc::new(FlakyStore::new());
+        slow.set_delay(Duration::from_millis(500));
+        let stores = ReportStores::new(Some(primary.clone()))
+            .with_sink("slow", slow.clone(), 0)
+            .with_timeout(Duration::from_millis(100));
+
+        let started = std::time::Instant::now();
+        let failures = stores
+            .store_anomaly_report(&AnomalyReport::new(8, "Test Rule", "alice", 1700000000, "Test anomaly"))
+            .await;
+        assert!(started.elapsed() < Duration::from_millis(400));
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, "slow");
+        assert!(matches!(failures[0].1, PersistenceError::Timeout(_)));
+        assert_eq!(primary.get_recent_reports(10).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reports_below_min_severity_not_stored() {
+        let primary = store();
+        let central = store();
+        let stores = ReportStores::new(Some(primary.clone()))
+            .with_min_severity(6)
+            .with_sink("central", central.clone(), 8);
+
+        for severity in [5, 6, 8] {
+            let report = AnomalyReport::new(severity, "Off-Hours Login", "alice", 1700000000, "Test anomaly");
+            assert!(stores.store_anomaly_report(&report).await.is_empty());
+        }
+
+        let severities = |target: &Arc<dyn StateStore>| {
+            let mut severities: Vec<u8> = target.get_recent_reports(10).unwrap().iter().map(|r| r.severity).collect();
+            severities.sort();
+            severities
+        };
+        assert_eq!(severities(&primary), vec![6, 8]);
+        assert_eq!(severities(&central), vec![8]);
+    }
+}
@@ -8,6 +8,14 @@ CREATE TABLE IF NOT EXISTS user_last_ip (
     last_seen INTEGER NOT NULL
 );
 
+-- Each user's recently confirmed IPs, so returns to them aren't re-alerted
+CREATE TABLE IF NOT EXISTS user_known_ips (
+    user TEXT NOT NULL,
+    ip TEXT NOT NULL,
+    last_seen INTEGER NOT NULL,
+    PRIMARY KEY (user, ip)
+);
+
 -- User geographic locations for velocity tracking
 CREATE TABLE IF NOT EXISTS user_locations (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -21,12 +29,20 @@ CREATE TABLE IF NOT EXISTS user_locations (
 CREATE INDEX IF NOT EXISTS idx_user_locations_user ON user_locations(user);
 CREATE INDEX IF NOT EXISTS idx_user_locations_timestamp ON user_locations(timestamp);
 
+-- Every user ever seen, for first-seen detection (never pruned)
+CREATE TABLE IF NOT EXISTS known_users (
+    user TEXT PRIMARY KEY,
+    first_seen INTEGER NOT NULL
+);
+
 -- Login attempts for rate limiting
 CREATE TABLE IF NOT EXISTS login_attempts (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
     user TEXT NOT NULL,
     ip TEXT NOT NULL,
-    timestamp INTEGER NOT NULL
+    timestamp INTEGER NOT NULL,
+    -- Attempts this row counts as (weighted or sampled events)
+    weight INTEGER NOT NULL DEFAULT 1
 );
 
 CREATE INDEX IF NOT EXISTS idx_login_attempts_user ON login_attempts(user);
@@ -42,10 +58,44 @@ CREATE TABLE IF NOT EXISTS anomaly_reports (
     detected_ip TEXT NOT NULL,
     trusted_ip TEXT,
     timestamp INTEGER NOT NULL,
+    detected_at INTEGER,
     description TEXT NOT NULL,
+    confidence REAL,
     created_at INTEGER DEFAULT (strftime('%s', 'now'))
 );
 
 CREATE INDEX IF NOT EXISTS idx_anomaly_reports_timestamp ON anomaly_reports(timestamp);
 CREATE INDEX IF NOT EXISTS idx_anomaly_reports_user ON anomaly_reports(user);
 CREATE INDEX IF NOT EXISTS idx_anomaly_reports_severity ON anomaly_reports(severity);
+
+-- Active and recently expired lockouts of users/IPs
+CREATE TABLE IF NOT EXISTS lockouts (
+    kind TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    locked_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL,
+    PRIMARY KEY (kind, subject)
+);
+
+CREATE INDEX IF NOT EXISTS idx_lockouts_expires_at ON lockouts(expires_at);
+
+-- Alerts that could not be delivered, retried until delivered or expired
+CREATE TABLE IF NOT EXISTS pending_alerts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    report TEXT NOT NULL,
+    queued_at INTEGER NOT NULL,
+    -- Channel still owed the alert, NULL for every configured channel
+    channel TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_pending_alerts_queued_at ON pending_alerts(queued_at);
+
+-- Report suppression cooldowns per key, so throttling survives restarts
+CREATE TABLE IF NOT EXISTS dedup_state (
+    key TEXT PRIMARY KEY,
+    cooldown_until INTEGER NOT NULL,
+    severity INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_dedup_state_cooldown_until ON dedup_state(cooldown_until);
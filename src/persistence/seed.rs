@@ -0,0 +1,156 @@
+//! Seeding baselines from a file
+//!
+//! Lets operators pre-load each user's trusted IP and location on a new
+//! host, so detection compares the first logins against known baselines
+//! instead of treating every user as never seen before.
+//!
+//! The file is JSON:
+//!
+//! ```json
+//! {
+//!   "users": [
+//!     { "user": "alice", "trusted_ip": "203.0.113.5",
+//!       "location": { "latitude": 40.71, "longitude": -74.01 } },
+//!     { "user": "bob", "trusted_ip": "198.51.100.7" }
+//!   ]
+//! }
+//! ```
+//!
+//! `location` is optional. An optional top-level `seeded_at` (Unix
+//! seconds) sets the timestamp recorded for every baseline; it defaults
+//! to the time of seeding.
+
+use super::{PersistenceError, StateStore};
+use crate::detection::GeoLocation;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Baseline for a single user
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserBaseline {
+    pub user: String,
+    /// IP the IP switch rule treats as the user's trusted address
+    pub trusted_ip: IpAddr,
+    /// Location the geo-velocity rule measures travel from
+    #[serde(default)]
+    pub location: Option<GeoLocation>,
+}
+
+/// Contents of a baselines file
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Baselines {
+    /// Timestamp recorded for every baseline (defaults to now)
+    #[serde(default)]
+    pub seeded_at: Option<i64>,
+    pub users: Vec<UserBaseline>,
+}
+
+impl Baselines {
+    /// Read and parse a baselines file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, PersistenceError> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+}
+
+/// Result of seeding baselines
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SeedSummary {
+    /// Users whose baseline was written
+    pub seeded: usize,
+    /// Users skipped because they already had a stored IP
+    pub skipped: usize,
+}
+
+/// Write baselines into the store
+///
+/// Users that already have a stored IP keep it unless `overwrite` is set,
+/// so seeding an existing database never replaces learned state.
+pub fn seed_baselines(
+    store: &dyn StateStore,
+    baselines: &Baselines,
+    overwrite: bool,
+) -> Result<SeedSummary, PersistenceError> {
+    let seeded_at = baselines
+        .seeded_at
+        .unwrap_or_else(|| chrono::Utc::now().timestamp());
+
+    let mut summary = SeedSummary::default();
+    for baseline in &baselines.users {
+        if !overwrite && store.get_user_last_ip(&baseline.user)?.is_some() {
+            summary.skipped += 1;
+            continue;
+        }
+
+        store.set_user_last_ip(&baseline.user, &baseline.trusted_ip, seeded_at)?;
+        if let Some(location) = &baseline.location {
+            store.add_user_location(&baseline.user, seeded_at, location, &baseline.trusted_ip)?;
+        }
+        summary.seeded += 1;
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detection::IdentityContext;
+    use crate::models::LogEvent;
+    use crate::persistence::SqliteStateStore;
+    use std::sync::Arc;
+
+    const BASELINES: &str = r#"{
+        "seeded_at": 1700000000,
+        "users": [
+            { "user": "alice", "trusted_ip": "203.0.113.5",
+              "location": { "latitude": 40.71, "longitude": -74.01 } },
+            { "user": "bob", "trusted_ip": "198.51.100.7" }
+        ]
+    }"#;
+
+    #[test]
+    fn test_switch_detected_against_seeded_ip() {
+        let store = Arc::new(SqliteStateStore::in_memory().unwrap());
+        let baselines: Baselines = serde_json::from_str(BASELINES).unwrap();
+
+        let summary = seed_baselines(store.as_ref(), &baselines, false).unwrap();
+        assert_eq!(summary, SeedSummary { seeded: 2, skipped: 0 });
+        let (_, location, _) = store.get_user_last_location("alice").unwrap().unwrap();
+        assert_eq!(location.latitude, 40.71);
+
+        let mut context = IdentityContext::with_persistence(store.clone());
+        let event = LogEvent {
+            timestamp: 1700000100,
+            user: "alice".to_string(),
+            ip_address: "192.0.2.44".parse().unwrap(),
+            event_type: "SSH_LOGIN".to_string(),
+            host: None,
+        };
+        let report = context.check_for_ip_switch(&event).expect("switch from seeded IP");
+        assert_eq!(report.trusted_ip, "203.0.113.5");
+
+        // Logging in from the seeded IP is not a switch
+        let event = LogEvent {
+            user: "bob".to_string(),
+            ip_address: "198.51.100.7".parse().unwrap(),
+            ..event
+        };
+        assert!(context.check_for_ip_switch(&event).is_none());
+    }
+
+    #[test]
+    fn test_existing_users_kept_unless_overwrite() {
+        let store = SqliteStateStore::in_memory().unwrap();
+        let learned: IpAddr = "192.0.2.1".parse().unwrap();
+        store.set_user_last_ip("alice", &learned, 1700000000).unwrap();
+        let baselines: Baselines = serde_json::from_str(BASELINES).unwrap();
+
+        let summary = seed_baselines(&store, &baselines, false).unwrap();
+        assert_eq!(summary, SeedSummary { seeded: 1, skipped: 1 });
+        assert_eq!(store.get_user_last_ip("alice").unwrap().unwrap().0, learned);
+
+        seed_baselines(&store, &baselines, true).unwrap();
+        assert_eq!(store.get_user_last_ip("alice").unwrap().unwrap().0.to_string(), "203.0.113.5");
+    }
+}
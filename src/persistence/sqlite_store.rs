@@ -1,13 +1,35 @@
 //! SQLite implementation of the StateStore trait
 
-use super::{PersistenceError, StateStore};
+use super::{PendingAlert, PersistenceError, StateStore};
 use crate::detection::GeoLocation;
-use crate::models::AnomalyReport;
+use crate::models::{AnomalyReport, Lockout, LockoutKind};
 use rusqlite::{params, Connection};
+use std::collections::BTreeMap;
 use std::net::IpAddr;
 use std::path::Path;
 use std::str::FromStr;
-use std::sync::Mutex;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Coordinates closer than this (in degrees) are treated as the same place
+const LOCATION_DEDUP_EPSILON: f64 = 1e-6;
+
+/// Text an IP address is stored and looked up as
+///
+/// `IpAddr`'s display form is canonical, so every spelling of one address
+/// in the logs (`2001:db8::1`, `2001:0db8:0:0::1`) maps to the same key.
+/// IP columns only ever hold this form.
+fn ip_key(ip: &IpAddr) -> String {
+    ip.to_string()
+}
+
+/// A lockout's subject as stored: canonical for IP lockouts (from
+/// whatever form the locking system reported), verbatim for users
+fn lockout_subject(lockout: &Lockout) -> String {
+    match (lockout.kind, IpAddr::from_str(&lockout.subject)) {
+        (LockoutKind::Ip, Ok(ip)) => ip_key(&ip),
+        _ => lockout.subject.clone(),
+    }
+}
 
 /// SQLite-based state storage
 ///
@@ -15,6 +37,8 @@ use std::sync::Mutex;
 /// providing persistence across daemon restarts.
 pub struct SqliteStateStore {
     conn: Mutex<Connection>,
+    /// Refresh the latest location row instead of inserting a duplicate
+    dedup_locations: bool,
 }
 
 impl SqliteStateStore {
@@ -25,6 +49,7 @@ impl SqliteStateStore {
         let conn = Connection::open(db_path)?;
         let store = SqliteStateStore {
             conn: Mutex::new(conn),
+            dedup_locations: false,
         };
         store.initialize_schema()?;
         Ok(store)
@@ -35,18 +60,121 @@ impl SqliteStateStore {
         let conn = Connection::open_in_memory()?;
         let store = SqliteStateStore {
             conn: Mutex::new(conn),
+            dedup_locations: false,
         };
         store.initialize_schema()?;
         Ok(store)
     }
 
+    /// Skip storing a location identical to the user's most recent one,
+    /// updating that row's timestamp instead
+    pub fn with_location_dedup(mut self, enabled: bool) -> Self {
+        self.dedup_locations = enabled;
+        self
+    }
+
+    /// Switch to a different database file
+    ///
+    /// The new database is opened and initialized before the connection is
+    /// swapped under the lock, so concurrent operations either complete on
+    /// the old file or run on the new one. On error the old database stays
+    /// in use.
+    pub fn reopen<P: AsRef<Path>>(&self, db_path: P) -> Result<(), PersistenceError> {
+        let conn = Connection::open(db_path)?;
+        Self::initialize_connection(&conn)?;
+        *self.conn() = conn;
+        Ok(())
+    }
+
+    /// Rebuild the database file to reclaim space freed by deletions
+    pub fn compact(&self) -> Result<(), PersistenceError> {
+        let conn = self.conn();
+        conn.execute_batch("VACUUM;")?;
+        Ok(())
+    }
+
     /// Initialize the database schema
     fn initialize_schema(&self) -> Result<(), PersistenceError> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn();
+        Self::initialize_connection(&conn)
+    }
+
+    fn initialize_connection(conn: &Connection) -> Result<(), PersistenceError> {
         conn.execute_batch(include_str!("schema.sql"))?;
+        Self::migrate_schema(conn)?;
+        Ok(())
+    }
+
+    /// Bring databases created by older versions up to date
+    fn migrate_schema(conn: &Connection) -> Result<(), PersistenceError> {
+        if !Self::has_column(conn, "anomaly_reports", "detected_at")? {
+            // Older reports only have the event time; use it as the detection time
+            conn.execute_batch(
+                "ALTER TABLE anomaly_reports ADD COLUMN detected_at INTEGER;
+                 UPDATE anomaly_reports SET detected_at = timestamp;"
+            )?;
+        }
+        if !Self::has_column(conn, "anomaly_reports", "confidence")? {
+            conn.execute_batch("ALTER TABLE anomaly_reports ADD COLUMN confidence REAL;")?;
+        }
+        if !Self::has_column(conn, "pending_alerts", "channel")? {
+            // Older alerts are owed to every channel, which NULL means
+            conn.execute_batch("ALTER TABLE pending_alerts ADD COLUMN channel TEXT;")?;
+        }
+        if !Self::has_column(conn, "login_attempts", "weight")? {
+            // Older rows each recorded a single attempt
+            conn.execute_batch("ALTER TABLE login_attempts ADD COLUMN weight INTEGER NOT NULL DEFAULT 1;")?;
+        }
+        conn.execute_batch(
+            "CREATE INDEX IF NOT EXISTS idx_anomaly_reports_detected_at
+             ON anomaly_reports(detected_at);
+             CREATE INDEX IF NOT EXISTS idx_login_attempts_user_ip
+             ON login_attempts(user, ip, timestamp);"
+        )?;
         Ok(())
     }
 
+    /// Check whether a table has a given column
+    fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool, PersistenceError> {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+        let columns = stmt
+            .query_map([], |row| row.get::<_, String>(1))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(columns.iter().any(|c| c == column))
+    }
+
+    /// Lock the connection
+    ///
+    /// A panic while the lock was held (a rule caught by `run_rule`, say)
+    /// poisons the mutex. Every statement runs on its own, so the
+    /// connection is still usable and the poison is ignored rather than
+    /// failing every later call.
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Get timestamps of login attempts by a user from one IP within a
+    /// time window, served by the (user, ip) index
+    pub fn get_user_ip_attempts_in_window(
+        &self,
+        user: &str,
+        ip: &IpAddr,
+        window_start: i64,
+    ) -> Result<Vec<i64>, PersistenceError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT timestamp FROM login_attempts
+             WHERE user = ? AND ip = ? AND timestamp >= ?
+             ORDER BY timestamp DESC"
+        )?;
+
+        let timestamps = stmt
+            .query_map(params![user, ip_key(ip), window_start], |row| row.get(0))?
+            .collect::<Result<Vec<i64>, _>>()?;
+
+        Ok(timestamps)
+    }
+
     /// Helper to parse IP address from database string
     fn parse_ip(ip_str: &str) -> Result<IpAddr, PersistenceError> {
         IpAddr::from_str(ip_str)
@@ -56,7 +184,7 @@ impl SqliteStateStore {
 
 impl StateStore for SqliteStateStore {
     fn get_user_last_ip(&self, user: &str) -> Result<Option<(IpAddr, i64)>, PersistenceError> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn();
         let mut stmt = conn.prepare(
             "SELECT ip, last_seen FROM user_last_ip WHERE user = ?"
         )?;
@@ -83,10 +211,43 @@ impl StateStore for SqliteStateStore {
         ip: &IpAddr,
         timestamp: i64,
     ) -> Result<(), PersistenceError> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn();
         conn.execute(
             "INSERT OR REPLACE INTO user_last_ip (user, ip, last_seen) VALUES (?, ?, ?)",
-            params![user, ip.to_string(), timestamp],
+            params![user, ip_key(ip), timestamp],
+        )?;
+        Ok(())
+    }
+
+    fn get_user_known_ips(&self, user: &str) -> Result<Vec<IpAddr>, PersistenceError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT ip FROM user_known_ips WHERE user = ? ORDER BY last_seen ASC, rowid ASC"
+        )?;
+
+        let ips = stmt
+            .query_map(params![user], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        ips.iter().map(|ip| Self::parse_ip(ip)).collect()
+    }
+
+    fn add_user_known_ip(
+        &self,
+        user: &str,
+        ip: &IpAddr,
+        timestamp: i64,
+        max_entries: usize,
+    ) -> Result<(), PersistenceError> {
+        let conn = self.conn();
+        conn.execute(
+            "INSERT INTO user_known_ips (user, ip, last_seen) VALUES (?, ?, ?)
+             ON CONFLICT (user, ip) DO UPDATE SET last_seen = MAX(last_seen, excluded.last_seen)",
+            params![user, ip_key(ip), timestamp],
+        )?;
+        conn.execute(
+            "DELETE FROM user_known_ips WHERE user = ? AND ip NOT IN
+             (SELECT ip FROM user_known_ips WHERE user = ? ORDER BY last_seen DESC, rowid DESC LIMIT ?)",
+            params![user, user, max_entries],
         )?;
         Ok(())
     }
@@ -94,10 +255,10 @@ impl StateStore for SqliteStateStore {
     fn get_user_last_location(
         &self,
         user: &str,
-    ) -> Result<Option<(i64, GeoLocation)>, PersistenceError> {
-        let conn = self.conn.lock().unwrap();
+    ) -> Result<Option<(i64, GeoLocation, IpAddr)>, PersistenceError> {
+        let conn = self.conn();
         let mut stmt = conn.prepare(
-            "SELECT timestamp, latitude, longitude FROM user_locations
+            "SELECT timestamp, latitude, longitude, ip FROM user_locations
              WHERE user = ? ORDER BY timestamp DESC LIMIT 1"
         )?;
 
@@ -105,11 +266,17 @@ impl StateStore for SqliteStateStore {
             let timestamp: i64 = row.get(0)?;
             let latitude: f64 = row.get(1)?;
             let longitude: f64 = row.get(2)?;
-            Ok((timestamp, GeoLocation { latitude, longitude }))
+            let ip: String = row.get(3)?;
+            Ok((timestamp, latitude, longitude, ip))
         });
 
         match result {
-            Ok(data) => Ok(Some(data)),
+            Ok((timestamp, latitude, longitude, ip)) => {
+                let location = GeoLocation::new(latitude, longitude).map_err(|e| {
+                    PersistenceError::InvalidData(format!("stored location for user '{}': {}", user, e))
+                })?;
+                Ok(Some((timestamp, location, Self::parse_ip(&ip)?)))
+            }
             Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
             Err(e) => Err(e.into()),
         }
@@ -122,7 +289,32 @@ impl StateStore for SqliteStateStore {
         location: &GeoLocation,
         ip: &IpAddr,
     ) -> Result<(), PersistenceError> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn();
+
+        if self.dedup_locations {
+            let latest = conn.query_row(
+                "SELECT id, latitude, longitude FROM user_locations
+                 WHERE user = ? ORDER BY timestamp DESC, id DESC LIMIT 1",
+                params![user],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, f64>(1)?, row.get::<_, f64>(2)?)),
+            );
+            match latest {
+                Ok((id, latitude, longitude))
+                    if (latitude - location.latitude).abs() < LOCATION_DEDUP_EPSILON
+                        && (longitude - location.longitude).abs() < LOCATION_DEDUP_EPSILON =>
+                {
+                    conn.execute(
+                        "UPDATE user_locations SET timestamp = MAX(timestamp, ?),
+                         ip = CASE WHEN ? >= timestamp THEN ? ELSE ip END WHERE id = ?",
+                        params![timestamp, timestamp, ip_key(ip), id],
+                    )?;
+                    return Ok(());
+                }
+                Ok(_) | Err(rusqlite::Error::QueryReturnedNoRows) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
         conn.execute(
             "INSERT INTO user_locations (user, timestamp, latitude, longitude, ip)
              VALUES (?, ?, ?, ?, ?)",
@@ -131,22 +323,32 @@ impl StateStore for SqliteStateStore {
                 timestamp,
                 location.latitude,
                 location.longitude,
-                ip.to_string()
+                ip_key(ip)
             ],
         )?;
         Ok(())
     }
 
+    fn record_user_seen(&self, user: &str, timestamp: i64) -> Result<bool, PersistenceError> {
+        let conn = self.conn();
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO known_users (user, first_seen) VALUES (?, ?)",
+            params![user, timestamp],
+        )?;
+        Ok(inserted > 0)
+    }
+
     fn add_login_attempt(
         &self,
         user: &str,
         ip: &IpAddr,
         timestamp: i64,
+        weight: usize,
     ) -> Result<(), PersistenceError> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn();
         conn.execute(
-            "INSERT INTO login_attempts (user, ip, timestamp) VALUES (?, ?, ?)",
-            params![user, ip.to_string(), timestamp],
+            "INSERT INTO login_attempts (user, ip, timestamp, weight) VALUES (?, ?, ?, ?)",
+            params![user, ip_key(ip), timestamp, weight as i64],
         )?;
         Ok(())
     }
@@ -156,7 +358,7 @@ impl StateStore for SqliteStateStore {
         user: &str,
         window_start: i64,
     ) -> Result<Vec<i64>, PersistenceError> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn();
         let mut stmt = conn.prepare(
             "SELECT timestamp FROM login_attempts
              WHERE user = ? AND timestamp >= ?
@@ -172,10 +374,10 @@ impl StateStore for SqliteStateStore {
 
     fn get_ip_attempts_in_window(
         &self,
-        ip: &str,
+        ip: &IpAddr,
         window_start: i64,
     ) -> Result<Vec<i64>, PersistenceError> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn();
         let mut stmt = conn.prepare(
             "SELECT timestamp FROM login_attempts
              WHERE ip = ? AND timestamp >= ?
@@ -183,18 +385,48 @@ impl StateStore for SqliteStateStore {
         )?;
 
         let timestamps = stmt
-            .query_map(params![ip, window_start], |row| row.get(0))?
+            .query_map(params![ip_key(ip), window_start], |row| row.get(0))?
             .collect::<Result<Vec<i64>, _>>()?;
 
         Ok(timestamps)
     }
 
+    fn get_user_attempt_count(
+        &self,
+        user: &str,
+        window_start: i64,
+    ) -> Result<usize, PersistenceError> {
+        let conn = self.conn();
+        let count: i64 = conn.query_row(
+            "SELECT COALESCE(SUM(weight), 0) FROM login_attempts
+             WHERE user = ? AND timestamp >= ?",
+            params![user, window_start],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    fn get_ip_attempt_count(
+        &self,
+        ip: &IpAddr,
+        window_start: i64,
+    ) -> Result<usize, PersistenceError> {
+        let conn = self.conn();
+        let count: i64 = conn.query_row(
+            "SELECT COALESCE(SUM(weight), 0) FROM login_attempts
+             WHERE ip = ? AND timestamp >= ?",
+            params![ip_key(ip), window_start],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
     fn store_anomaly_report(&self, report: &AnomalyReport) -> Result<(), PersistenceError> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn();
         conn.execute(
             "INSERT INTO anomaly_reports
-             (severity, rule_name, user, detected_ip, trusted_ip, timestamp, description)
-             VALUES (?, ?, ?, ?, ?, ?, ?)",
+             (severity, rule_name, user, detected_ip, trusted_ip, timestamp, detected_at, description, confidence)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
             params![
                 report.severity,
                 report.rule_name,
@@ -202,16 +434,19 @@ impl StateStore for SqliteStateStore {
                 report.detected_ip,
                 report.trusted_ip,
                 report.timestamp,
-                report.description
+                report.detected_at,
+                report.description,
+                report.confidence
             ],
         )?;
         Ok(())
     }
 
     fn get_recent_reports(&self, limit: usize) -> Result<Vec<AnomalyReport>, PersistenceError> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn();
         let mut stmt = conn.prepare(
-            "SELECT severity, rule_name, user, detected_ip, trusted_ip, timestamp, description
+            "SELECT severity, rule_name, user, detected_ip, trusted_ip, timestamp, description,
+                    COALESCE(detected_at, timestamp), confidence
              FROM anomaly_reports
              ORDER BY created_at DESC
              LIMIT ?"
@@ -226,7 +461,10 @@ impl StateStore for SqliteStateStore {
                     detected_ip: row.get(3)?,
                     trusted_ip: row.get(4)?,
                     timestamp: row.get(5)?,
+                    detected_at: row.get(7)?,
                     description: row.get(6)?,
+                    metadata: BTreeMap::new(),
+                    confidence: row.get(8)?,
                 })
             })?
             .collect::<Result<Vec<_>, _>>()?;
@@ -234,8 +472,158 @@ impl StateStore for SqliteStateStore {
         Ok(reports)
     }
 
+    fn add_lockout(&self, lockout: &Lockout) -> Result<(), PersistenceError> {
+        let conn = self.conn();
+        conn.execute(
+            "INSERT OR REPLACE INTO lockouts (kind, subject, reason, locked_at, expires_at)
+             VALUES (?, ?, ?, ?, ?)",
+            params![
+                lockout.kind.as_str(),
+                lockout_subject(lockout),
+                lockout.reason,
+                lockout.locked_at,
+                lockout.expires_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn get_active_lockouts(&self, now: i64) -> Result<Vec<Lockout>, PersistenceError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT kind, subject, reason, locked_at, expires_at FROM lockouts
+             WHERE expires_at > ? ORDER BY locked_at"
+        )?;
+
+        let rows = stmt
+            .query_map(params![now], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, i64>(4)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(|(kind, subject, reason, locked_at, expires_at)| {
+                let kind = match kind.as_str() {
+                    "user" => LockoutKind::User,
+                    "ip" => LockoutKind::Ip,
+                    other => {
+                        return Err(PersistenceError::InvalidData(format!(
+                            "Invalid lockout kind: {}",
+                            other
+                        )))
+                    }
+                };
+                Ok(Lockout { kind, subject, reason, locked_at, expires_at })
+            })
+            .collect()
+    }
+
+    fn enqueue_pending_alert(
+        &self,
+        report: &AnomalyReport,
+        channel: Option<&str>,
+        queued_at: i64,
+        max_entries: usize,
+    ) -> Result<(), PersistenceError> {
+        let json = serde_json::to_string(report)?;
+        let conn = self.conn();
+        conn.execute(
+            "INSERT INTO pending_alerts (report, queued_at, channel) VALUES (?, ?, ?)",
+            params![json, queued_at, channel],
+        )?;
+        let dropped = conn.execute(
+            "DELETE FROM pending_alerts WHERE id NOT IN
+             (SELECT id FROM pending_alerts ORDER BY id DESC LIMIT ?)",
+            params![max_entries],
+        )?;
+        if dropped > 0 {
+            log::warn!("Pending alert queue full, dropped {} oldest alert(s)", dropped);
+        }
+        Ok(())
+    }
+
+    fn get_pending_alerts(&self, limit: usize) -> Result<Vec<PendingAlert>, PersistenceError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT id, queued_at, report, channel FROM pending_alerts ORDER BY id LIMIT ?"
+        )?;
+
+        let rows = stmt
+            .query_map(params![limit], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(|(id, queued_at, json, channel)| {
+                Ok(PendingAlert {
+                    id,
+                    queued_at,
+                    report: serde_json::from_str(&json)?,
+                    channel,
+                })
+            })
+            .collect()
+    }
+
+    fn remove_pending_alert(&self, id: i64) -> Result<(), PersistenceError> {
+        let conn = self.conn();
+        conn.execute("DELETE FROM pending_alerts WHERE id = ?", params![id])?;
+        Ok(())
+    }
+
+    fn expire_pending_alerts(&self, before_timestamp: i64) -> Result<usize, PersistenceError> {
+        let conn = self.conn();
+        let expired = conn.execute(
+            "DELETE FROM pending_alerts WHERE queued_at < ?",
+            params![before_timestamp],
+        )?;
+        Ok(expired)
+    }
+
+    fn set_dedup_state(&self, key: &str, cooldown_until: i64, severity: u8) -> Result<(), PersistenceError> {
+        let conn = self.conn();
+        conn.execute(
+            "INSERT OR REPLACE INTO dedup_state (key, cooldown_until, severity) VALUES (?, ?, ?)",
+            params![key, cooldown_until, severity],
+        )?;
+        Ok(())
+    }
+
+    fn get_dedup_state(&self, key: &str) -> Result<Option<(i64, u8)>, PersistenceError> {
+        let conn = self.conn();
+        let result = conn.query_row(
+            "SELECT cooldown_until, severity FROM dedup_state WHERE key = ?",
+            params![key],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        );
+
+        match result {
+            Ok(state) => Ok(Some(state)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn prune_dedup_state(&self, now: i64) -> Result<usize, PersistenceError> {
+        let conn = self.conn();
+        let pruned = conn.execute("DELETE FROM dedup_state WHERE cooldown_until <= ?", params![now])?;
+        Ok(pruned)
+    }
+
     fn prune_old_data(&self, before_timestamp: i64) -> Result<usize, PersistenceError> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn();
 
         let mut total_deleted = 0usize;
 
@@ -251,23 +639,35 @@ impl StateStore for SqliteStateStore {
             params![before_timestamp],
         )?;
 
-        // Keep anomaly reports longer (30 days instead of window)
+        // Keep anomaly reports longer (30 days instead of window). Retention
+        // is based on detection time so replayed historical events are kept.
         let report_cutoff = before_timestamp - (30 * 24 * 3600);
         total_deleted += conn.execute(
-            "DELETE FROM anomaly_reports WHERE timestamp < ?",
+            "DELETE FROM anomaly_reports WHERE COALESCE(detected_at, timestamp) < ?",
             params![report_cutoff],
         )?;
 
+        // Expired lockouts
+        total_deleted += conn.execute(
+            "DELETE FROM lockouts WHERE expires_at < ?",
+            params![before_timestamp],
+        )?;
+
         Ok(total_deleted)
     }
 
     fn clear_all(&self) -> Result<(), PersistenceError> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.conn();
         conn.execute_batch(
             "DELETE FROM user_last_ip;
+             DELETE FROM user_known_ips;
              DELETE FROM user_locations;
+             DELETE FROM known_users;
              DELETE FROM login_attempts;
-             DELETE FROM anomaly_reports;"
+             DELETE FROM anomaly_reports;
+             DELETE FROM lockouts;
+             DELETE FROM pending_alerts;
+             DELETE FROM dedup_state;"
         )?;
         Ok(())
     }
@@ -300,6 +700,69 @@ mod tests {
         assert_eq!(stored_timestamp, timestamp);
     }
 
+    #[test]
+    fn test_usable_after_panic_while_locked() {
+        let store = create_test_store();
+        let ip: IpAddr = "192.168.1.1".parse().unwrap();
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _conn = store.conn();
+            panic!("rule panicked mid-query");
+        }));
+        assert!(panicked.is_err());
+        assert!(store.conn.is_poisoned());
+
+        store.set_user_last_ip("alice", &ip, 1000).unwrap();
+        assert_eq!(store.get_user_last_ip("alice").unwrap().unwrap().0, ip);
+    }
+
+    #[test]
+    fn test_reopen_switches_database() {
+        let temp = tempfile::tempdir().unwrap();
+        let first = temp.path().join("odin_20240101.db");
+        let second = temp.path().join("odin_20240102.db");
+        let ip: IpAddr = "192.168.1.1".parse().unwrap();
+
+        let store = SqliteStateStore::new(&first).unwrap();
+        store.set_user_last_ip("alice", &ip, 1000).unwrap();
+
+        store.reopen(&second).unwrap();
+        assert!(store.get_user_last_ip("alice").unwrap().is_none());
+        store.set_user_last_ip("bob", &ip, 2000).unwrap();
+
+        // The first day's file still holds its own state
+        let old = SqliteStateStore::new(&first).unwrap();
+        assert!(old.get_user_last_ip("alice").unwrap().is_some());
+        assert!(old.get_user_last_ip("bob").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_active_lockouts_exclude_expired() {
+        let store = create_test_store();
+        let lockout = |kind, subject: &str, expires_at| Lockout {
+            kind,
+            subject: subject.to_string(),
+            reason: "test".to_string(),
+            locked_at: 1000,
+            expires_at,
+        };
+
+        store.add_lockout(&lockout(LockoutKind::User, "alice", 1500)).unwrap();
+        store.add_lockout(&lockout(LockoutKind::Ip, "10.0.0.1", 3000)).unwrap();
+        store.add_lockout(&lockout(LockoutKind::User, "bob", 2000)).unwrap();
+        store.add_lockout(&lockout(LockoutKind::Ip, "10.0.0.2", 900)).unwrap();
+
+        let active = store.get_active_lockouts(2000).unwrap();
+        let subjects: Vec<&str> = active.iter().map(|l| l.subject.as_str()).collect();
+        assert_eq!(subjects, vec!["10.0.0.1"]);
+        assert_eq!(active[0].kind, LockoutKind::Ip);
+
+        assert_eq!(store.get_active_lockouts(1200).unwrap().len(), 3);
+
+        // Re-locking a subject extends the existing lockout
+        store.add_lockout(&lockout(LockoutKind::User, "alice", 5000)).unwrap();
+        assert_eq!(store.get_active_lockouts(2000).unwrap().len(), 2);
+    }
+
     #[test]
     fn test_user_ip_update() {
         let store = create_test_store();
@@ -333,12 +796,69 @@ mod tests {
         store.add_user_location(user, timestamp, &location, &ip).unwrap();
 
         // Retrieve location
-        let (stored_ts, stored_loc) = store.get_user_last_location(user).unwrap().unwrap();
+        let (stored_ts, stored_loc, stored_ip) = store.get_user_last_location(user).unwrap().unwrap();
         assert_eq!(stored_ts, timestamp);
+        assert_eq!(stored_ip, ip);
         assert!((stored_loc.latitude - location.latitude).abs() < 0.0001);
         assert!((stored_loc.longitude - location.longitude).abs() < 0.0001);
     }
 
+    #[test]
+    fn test_corrupt_location_rejected_on_load() {
+        let store = create_test_store();
+        store
+            .conn
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO user_locations (user, timestamp, latitude, longitude, ip)
+                 VALUES ('alice', 1700000000, 200.0, 13.4, '8.8.8.8')",
+                [],
+            )
+            .unwrap();
+
+        let err = store.get_user_last_location("alice").unwrap_err();
+        assert!(matches!(err, PersistenceError::InvalidData(_)), "{}", err);
+    }
+
+    #[test]
+    fn test_location_dedup_updates_timestamp() {
+        let store = SqliteStateStore::in_memory().unwrap().with_location_dedup(true);
+        let location = GeoLocation {
+            latitude: 40.7128,
+            longitude: -74.0060,
+        };
+        let ip: IpAddr = "8.8.8.8".parse().unwrap();
+
+        for timestamp in [1000, 2000, 3000] {
+            store.add_user_location("alice", timestamp, &location, &ip).unwrap();
+        }
+
+        let rows: i64 = store
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM user_locations", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(rows, 1);
+        let (stored_ts, _, _) = store.get_user_last_location("alice").unwrap().unwrap();
+        assert_eq!(stored_ts, 3000);
+
+        // A different location is still recorded
+        let elsewhere = GeoLocation {
+            latitude: 51.5074,
+            longitude: -0.1278,
+        };
+        store.add_user_location("alice", 4000, &elsewhere, &ip).unwrap();
+        let rows: i64 = store
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM user_locations", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(rows, 2);
+    }
+
     #[test]
     fn test_login_attempts() {
         let store = create_test_store();
@@ -346,16 +866,85 @@ mod tests {
         let ip: IpAddr = "192.168.1.1".parse().unwrap();
 
         // Add attempts at different timestamps
-        store.add_login_attempt(user, &ip, 1000).unwrap();
-        store.add_login_attempt(user, &ip, 2000).unwrap();
-        store.add_login_attempt(user, &ip, 3000).unwrap();
+        store.add_login_attempt(user, &ip, 1000, 1).unwrap();
+        store.add_login_attempt(user, &ip, 2000, 1).unwrap();
+        store.add_login_attempt(user, &ip, 3000, 1).unwrap();
 
         // Get attempts in window
         let attempts = store.get_user_attempts_in_window(user, 1500).unwrap();
         assert_eq!(attempts.len(), 2); // 2000 and 3000
 
-        let ip_attempts = store.get_ip_attempts_in_window(&ip.to_string(), 1500).unwrap();
+        let ip_attempts = store.get_ip_attempts_in_window(&ip, 1500).unwrap();
         assert_eq!(ip_attempts.len(), 2);
+
+        // Counts sum each attempt's weight
+        store.add_login_attempt(user, &ip, 3500, 4).unwrap();
+        assert_eq!(store.get_user_attempt_count(user, 1500).unwrap(), 6);
+        assert_eq!(store.get_ip_attempt_count(&ip, 1500).unwrap(), 6);
+        assert_eq!(store.get_user_attempt_count("nobody", 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_ipv6_spellings_stored_canonically() {
+        let store = create_test_store();
+        let logged: IpAddr = "2001:0db8:0000:0000:0000:0000:0000:0001".parse().unwrap();
+        let queried: IpAddr = "2001:db8::1".parse().unwrap();
+        store.add_login_attempt("alice", &logged, 1000, 1).unwrap();
+        store.set_user_last_ip("alice", &logged, 1000).unwrap();
+
+        assert_eq!(store.get_ip_attempts_in_window(&queried, 0).unwrap(), vec![1000]);
+        assert_eq!(store.get_user_ip_attempts_in_window("alice", &queried, 0).unwrap(), vec![1000]);
+        assert_eq!(store.get_user_last_ip("alice").unwrap().unwrap().0, queried);
+        let conn = store.conn.lock().unwrap();
+        let stored: String = conn
+            .query_row("SELECT ip FROM login_attempts WHERE user = 'alice'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(stored, "2001:db8::1");
+        drop(conn);
+
+        // Re-locking an IP under another spelling updates the same lockout
+        for subject in ["2001:0DB8::0001", "2001:db8::1"] {
+            store
+                .add_lockout(&Lockout {
+                    kind: LockoutKind::Ip,
+                    subject: subject.to_string(),
+                    reason: "test".to_string(),
+                    locked_at: 1000,
+                    expires_at: 5000,
+                })
+                .unwrap();
+        }
+        let active = store.get_active_lockouts(2000).unwrap();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].subject, "2001:db8::1");
+    }
+
+    #[test]
+    fn test_user_ip_attempts_match_exact_pair() {
+        let store = create_test_store();
+        let ip: IpAddr = "192.168.1.1".parse().unwrap();
+        let other_ip: IpAddr = "192.168.1.2".parse().unwrap();
+        store.add_login_attempt("alice", &ip, 1000, 1).unwrap();
+        store.add_login_attempt("alice", &ip, 2000, 1).unwrap();
+        store.add_login_attempt("alice", &ip, 3000, 1).unwrap();
+        store.add_login_attempt("alice", &other_ip, 2500, 1).unwrap();
+        store.add_login_attempt("bob", &ip, 2500, 1).unwrap();
+
+        let attempts = store.get_user_ip_attempts_in_window("alice", &ip, 1500).unwrap();
+        assert_eq!(attempts, vec![3000, 2000]);
+        assert!(store.get_user_ip_attempts_in_window("carol", &ip, 0).unwrap().is_empty());
+
+        // Served by the composite index
+        let conn = store.conn.lock().unwrap();
+        let plan: String = conn
+            .query_row(
+                "EXPLAIN QUERY PLAN SELECT timestamp FROM login_attempts
+                 WHERE user = 'alice' AND ip = '192.168.1.1' AND timestamp >= 1500",
+                [],
+                |row| row.get(3),
+            )
+            .unwrap();
+        assert!(plan.contains("idx_login_attempts_user_ip"), "{}", plan);
     }
 
     #[test]
@@ -368,7 +957,10 @@ mod tests {
             detected_ip: "1.2.3.4".to_string(),
             trusted_ip: "5.6.7.8".to_string(),
             timestamp: 1700000000,
+            detected_at: 1700000000,
             description: "Test anomaly".to_string(),
+            metadata: BTreeMap::new(),
+            confidence: None,
         };
 
         store.store_anomaly_report(&report).unwrap();
@@ -379,6 +971,140 @@ mod tests {
         assert_eq!(reports[0].severity, 8);
     }
 
+    #[test]
+    fn test_anomaly_report_confidence_round_trips() {
+        let store = create_test_store();
+        let mut report = AnomalyReport {
+            severity: 8,
+            rule_name: "Test Rule".to_string(),
+            user: "testuser".to_string(),
+            detected_ip: "1.2.3.4".to_string(),
+            trusted_ip: String::new(),
+            timestamp: 1700000000,
+            detected_at: 1700000000,
+            description: "Test".to_string(),
+            metadata: BTreeMap::new(),
+            confidence: Some(0.25),
+        };
+        store.store_anomaly_report(&report).unwrap();
+        report.confidence = None;
+        store.store_anomaly_report(&report).unwrap();
+
+        let mut stored: Vec<_> = store.get_recent_reports(10).unwrap().into_iter().map(|r| r.confidence).collect();
+        stored.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(stored, vec![None, Some(0.25)]);
+    }
+
+    #[test]
+    fn test_anomaly_report_stores_both_timestamps() {
+        let store = create_test_store();
+        let report = AnomalyReport {
+            severity: 8,
+            rule_name: "Test Rule".to_string(),
+            user: "testuser".to_string(),
+            detected_ip: "1.2.3.4".to_string(),
+            trusted_ip: String::new(),
+            timestamp: 1600000000,
+            detected_at: 1700000000,
+            description: "Replayed event".to_string(),
+            metadata: BTreeMap::new(),
+            confidence: None,
+        };
+
+        store.store_anomaly_report(&report).unwrap();
+
+        let reports = store.get_recent_reports(10).unwrap();
+        assert_eq!(reports[0].timestamp, 1600000000);
+        assert_eq!(reports[0].detected_at, 1700000000);
+
+        // Retention uses detection time, so the old event time doesn't prune it
+        store.prune_old_data(1700000000).unwrap();
+        assert_eq!(store.get_recent_reports(10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_pending_alerts_bounded_and_expired() {
+        let store = create_test_store();
+        let mut report = AnomalyReport {
+            severity: 9,
+            rule_name: "Test Rule".to_string(),
+            user: "testuser".to_string(),
+            detected_ip: "1.2.3.4".to_string(),
+            trusted_ip: String::new(),
+            timestamp: 1700000000,
+            detected_at: 1700000000,
+            description: "Undelivered".to_string(),
+            metadata: BTreeMap::new(),
+            confidence: None,
+        };
+        report.metadata.insert("detected_hostname".to_string(), "host.example.com".to_string());
+
+        for queued_at in [1000, 2000, 3000] {
+            store.enqueue_pending_alert(&report, Some("slack"), queued_at, 2).unwrap();
+        }
+
+        // The oldest entry was dropped to stay within the bound
+        let pending = store.get_pending_alerts(10).unwrap();
+        assert_eq!(pending.iter().map(|p| p.queued_at).collect::<Vec<_>>(), vec![2000, 3000]);
+        assert_eq!(pending[0].report.metadata["detected_hostname"], "host.example.com");
+        assert_eq!(pending[0].channel.as_deref(), Some("slack"));
+
+        assert_eq!(store.expire_pending_alerts(2500).unwrap(), 1);
+        store.remove_pending_alert(pending[1].id).unwrap();
+        assert!(store.get_pending_alerts(10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_migrates_reports_without_detected_at() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE anomaly_reports (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                severity INTEGER NOT NULL,
+                rule_name TEXT NOT NULL,
+                user TEXT NOT NULL,
+                detected_ip TEXT NOT NULL,
+                trusted_ip TEXT,
+                timestamp INTEGER NOT NULL,
+                description TEXT NOT NULL,
+                created_at INTEGER DEFAULT (strftime('%s', 'now'))
+            );
+            INSERT INTO anomaly_reports
+                (severity, rule_name, user, detected_ip, trusted_ip, timestamp, description)
+                VALUES (8, 'Old Rule', 'bob', '1.1.1.1', '', 1650000000, 'old');
+            CREATE TABLE pending_alerts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                report TEXT NOT NULL,
+                queued_at INTEGER NOT NULL
+            );
+            CREATE TABLE login_attempts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user TEXT NOT NULL,
+                ip TEXT NOT NULL,
+                timestamp INTEGER NOT NULL
+            );
+            INSERT INTO login_attempts (user, ip, timestamp) VALUES ('bob', '1.1.1.1', 1650000000);"
+        ).unwrap();
+
+        let store = SqliteStateStore { conn: Mutex::new(conn), dedup_locations: false };
+        store.initialize_schema().unwrap();
+
+        let reports = store.get_recent_reports(10).unwrap();
+        assert_eq!(reports[0].detected_at, 1650000000);
+
+        // Alerts queued before the channel column existed are owed to all channels
+        let report = &reports[0];
+        store.conn.lock().unwrap().execute(
+            "INSERT INTO pending_alerts (report, queued_at) VALUES (?, 1650000000)",
+            params![serde_json::to_string(report).unwrap()],
+        ).unwrap();
+        assert_eq!(store.get_pending_alerts(10).unwrap()[0].channel, None);
+
+        // Attempts recorded before weights existed count once each
+        store.add_login_attempt("bob", &"1.1.1.1".parse().unwrap(), 1650000001, 3).unwrap();
+        assert_eq!(store.get_user_attempt_count("bob", 0).unwrap(), 4);
+    }
+
     #[test]
     fn test_prune_old_data() {
         let store = create_test_store();
@@ -390,11 +1116,11 @@ mod tests {
         };
 
         // Add old data
-        store.add_login_attempt(user, &ip, 1000).unwrap();
+        store.add_login_attempt(user, &ip, 1000, 1).unwrap();
         store.add_user_location(user, 1000, &location, &ip).unwrap();
 
         // Add new data
-        store.add_login_attempt(user, &ip, 5000).unwrap();
+        store.add_login_attempt(user, &ip, 5000, 1).unwrap();
         store.add_user_location(user, 5000, &location, &ip).unwrap();
 
         // Prune data older than 3000
@@ -407,6 +1133,35 @@ mod tests {
         assert_eq!(attempts[0], 5000);
     }
 
+    #[test]
+    fn test_user_known_ips_bounded() {
+        let store = create_test_store();
+        let ips: Vec<IpAddr> = ["1.1.1.1", "2.2.2.2", "3.3.3.3"].iter().map(|ip| ip.parse().unwrap()).collect();
+        assert!(store.get_user_known_ips("alice").unwrap().is_empty());
+
+        store.add_user_known_ip("alice", &ips[0], 1000, 2).unwrap();
+        store.add_user_known_ip("alice", &ips[1], 2000, 2).unwrap();
+        // Seeing the first IP again makes it the most recent
+        store.add_user_known_ip("alice", &ips[0], 3000, 2).unwrap();
+        assert_eq!(store.get_user_known_ips("alice").unwrap(), vec![ips[1], ips[0]]);
+
+        // A third IP pushes out the least recently seen one
+        store.add_user_known_ip("alice", &ips[2], 4000, 2).unwrap();
+        assert_eq!(store.get_user_known_ips("alice").unwrap(), vec![ips[0], ips[2]]);
+        assert!(store.get_user_known_ips("bob").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_known_users_survive_pruning() {
+        let store = create_test_store();
+        assert!(store.record_user_seen("alice", 1000).unwrap());
+        assert!(!store.record_user_seen("alice", 2000).unwrap());
+
+        store.prune_old_data(5000).unwrap();
+        assert!(!store.record_user_seen("alice", 6000).unwrap());
+        assert!(store.record_user_seen("bob", 6000).unwrap());
+    }
+
     #[test]
     fn test_clear_all() {
         let store = create_test_store();
@@ -414,7 +1169,7 @@ mod tests {
         let ip: IpAddr = "192.168.1.1".parse().unwrap();
 
         store.set_user_last_ip(user, &ip, 1000).unwrap();
-        store.add_login_attempt(user, &ip, 1000).unwrap();
+        store.add_login_attempt(user, &ip, 1000, 1).unwrap();
 
         store.clear_all().unwrap();
 
@@ -0,0 +1,184 @@
+//! Store error injection for tests
+
+use super::{PendingAlert, PersistenceError, SqliteStateStore, StateStore};
+use crate::detection::GeoLocation;
+use crate::models::{AnomalyReport, Lockout};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// In-memory store whose calls all fail while `set_failing(true)`, and
+/// all take at least `set_delay`
+pub struct FlakyStore {
+    inner: SqliteStateStore,
+    failing: AtomicBool,
+    delay_ms: AtomicU64,
+}
+
+impl FlakyStore {
+    pub fn new() -> Self {
+        FlakyStore {
+            inner: SqliteStateStore::in_memory().unwrap(),
+            failing: AtomicBool::new(false),
+            delay_ms: AtomicU64::new(0),
+        }
+    }
+
+    pub fn set_failing(&self, failing: bool) {
+        self.failing.store(failing, Ordering::Relaxed);
+    }
+
+    pub fn set_delay(&self, delay: Duration) {
+        self.delay_ms.store(delay.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn check(&self) -> Result<(), PersistenceError> {
+        let delay = self.delay_ms.load(Ordering::Relaxed);
+        if delay > 0 {
+            std::thread::sleep(Duration::from_millis(delay));
+        }
+        if self.failing.load(Ordering::Relaxed) {
+            return Err(PersistenceError::NotInitialized);
+        }
+        Ok(())
+    }
+}
+
+impl StateStore for FlakyStore {
+    fn get_user_last_ip(&self, user: &str) -> Result<Option<(IpAddr, i64)>, PersistenceError> {
+        self.check()?;
+        self.inner.get_user_last_ip(user)
+    }
+
+    fn set_user_last_ip(&self, user: &str, ip: &IpAddr, timestamp: i64) -> Result<(), PersistenceError> {
+        self.check()?;
+        self.inner.set_user_last_ip(user, ip, timestamp)
+    }
+
+    fn get_user_known_ips(&self, user: &str) -> Result<Vec<IpAddr>, PersistenceError> {
+        self.check()?;
+        self.inner.get_user_known_ips(user)
+    }
+
+    fn add_user_known_ip(&self, user: &str, ip: &IpAddr, timestamp: i64, max_entries: usize) -> Result<(), PersistenceError> {
+        self.check()?;
+        self.inner.add_user_known_ip(user, ip, timestamp, max_entries)
+    }
+
+    fn get_user_last_location(&self, user: &str) -> Result<Option<(i64, GeoLocation, IpAddr)>, PersistenceError> {
+        self.check()?;
+        self.inner.get_user_last_location(user)
+    }
+
+    fn add_user_location(
+        &self,
+        user: &str,
+        timestamp: i64,
+        location: &GeoLocation,
+        ip: &IpAddr,
+    ) -> Result<(), PersistenceError> {
+        self.check()?;
+        self.inner.add_user_location(user, timestamp, location, ip)
+    }
+
+    fn record_user_seen(&self, user: &str, timestamp: i64) -> Result<bool, PersistenceError> {
+        self.check()?;
+        self.inner.record_user_seen(user, timestamp)
+    }
+
+    fn add_login_attempt(&self, user: &str, ip: &IpAddr, timestamp: i64, weight: usize) -> Result<(), PersistenceError> {
+        self.check()?;
+        self.inner.add_login_attempt(user, ip, timestamp, weight)
+    }
+
+    fn get_user_attempts_in_window(&self, user: &str, window_start: i64) -> Result<Vec<i64>, PersistenceError> {
+        self.check()?;
+        self.inner.get_user_attempts_in_window(user, window_start)
+    }
+
+    fn get_ip_attempts_in_window(&self, ip: &IpAddr, window_start: i64) -> Result<Vec<i64>, PersistenceError> {
+        self.check()?;
+        self.inner.get_ip_attempts_in_window(ip, window_start)
+    }
+
+    fn get_user_attempt_count(&self, user: &str, window_start: i64) -> Result<usize, PersistenceError> {
+        self.check()?;
+        self.inner.get_user_attempt_count(user, window_start)
+    }
+
+    fn get_ip_attempt_count(&self, ip: &IpAddr, window_start: i64) -> Result<usize, PersistenceError> {
+        self.check()?;
+        self.inner.get_ip_attempt_count(ip, window_start)
+    }
+
+    fn store_anomaly_report(&self, report: &AnomalyReport) -> Result<(), PersistenceError> {
+        self.check()?;
+        self.inner.store_anomaly_report(report)
+    }
+
+    fn get_recent_reports(&self, limit: usize) -> Result<Vec<AnomalyReport>, PersistenceError> {
+        self.check()?;
+        self.inner.get_recent_reports(limit)
+    }
+
+    fn add_lockout(&self, lockout: &Lockout) -> Result<(), PersistenceError> {
+        self.check()?;
+        self.inner.add_lockout(lockout)
+    }
+
+    fn get_active_lockouts(&self, now: i64) -> Result<Vec<Lockout>, PersistenceError> {
+        self.check()?;
+        self.inner.get_active_lockouts(now)
+    }
+
+    fn enqueue_pending_alert(
+        &self,
+        report: &AnomalyReport,
+        channel: Option<&str>,
+        queued_at: i64,
+        max_entries: usize,
+    ) -> Result<(), PersistenceError> {
+        self.check()?;
+        self.inner.enqueue_pending_alert(report, channel, queued_at, max_entries)
+    }
+
+    fn get_pending_alerts(&self, limit: usize) -> Result<Vec<PendingAlert>, PersistenceError> {
+        self.check()?;
+        self.inner.get_pending_alerts(limit)
+    }
+
+    fn remove_pending_alert(&self, id: i64) -> Result<(), PersistenceError> {
+        self.check()?;
+        self.inner.remove_pending_alert(id)
+    }
+
+    fn expire_pending_alerts(&self, before_timestamp: i64) -> Result<usize, PersistenceError> {
+        self.check()?;
+        self.inner.expire_pending_alerts(before_timestamp)
+    }
+
+    fn set_dedup_state(&self, key: &str, cooldown_until: i64, severity: u8) -> Result<(), PersistenceError> {
+        self.check()?;
+        self.inner.set_dedup_state(key, cooldown_until, severity)
+    }
+
+    fn get_dedup_state(&self, key: &str) -> Result<Option<(i64, u8)>, PersistenceError> {
+        self.check()?;
+        self.inner.get_dedup_state(key)
+    }
+
+    fn prune_dedup_state(&self, now: i64) -> Result<usize, PersistenceError> {
+        self.check()?;
+        self.inner.prune_dedup_state(now)
+    }
+
+    fn prune_old_data(&self, before_timestamp: i64) -> Result<usize, PersistenceError> {
+        self.check()?;
+        self.inner.prune_old_data(before_timestamp)
+    }
+
+    fn clear_all(&self) -> Result<(), PersistenceError> {
+        self.check()?;
+        self.inner.clear_all()
+    }
+}
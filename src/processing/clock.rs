@@ -0,0 +1,202 @@
+//! Pipeline time source
+//!
+//! Cooldowns, burst merging and pruning all compare stored event times
+//! against "now", so "now" is event time too. When replaying old logs it
+//! comes from the events themselves: against the wall clock every
+//! replayed condition would look long expired, and pruning would throw
+//! away the history the replay is building up. When live it is the wall
+//! clock less the lag of the latest event, so logs that arrive late (a
+//! buffering forwarder, a host whose clock runs behind) keep the state
+//! their windows still cover, while time keeps moving once the stream
+//! goes quiet.
+
+use crate::config::ProcessingMode;
+use crate::models::LogEvent;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+/// Marks that no event has been observed yet
+const NO_EVENT: i64 = i64::MIN;
+
+/// Source of the current time for the processing pipeline
+#[derive(Debug)]
+pub struct PipelineClock {
+    mode: ProcessingMode,
+    /// Latest event timestamp observed (replay mode's "now")
+    latest_event: AtomicI64,
+    /// Seconds the latest event arrived behind the wall clock (live mode)
+    lag: AtomicI64,
+}
+
+impl PipelineClock {
+    pub fn new(mode: ProcessingMode) -> Self {
+        PipelineClock {
+            mode,
+            latest_event: AtomicI64::new(NO_EVENT),
+            lag: AtomicI64::new(0),
+        }
+    }
+
+    pub fn mode(&self) -> ProcessingMode {
+        self.mode
+    }
+
+    /// Advance the event-stream time; earlier timestamps never move it back
+    pub fn observe(&self, event: &LogEvent) {
+        let previous = self.latest_event.fetch_max(event.timestamp, Ordering::Relaxed);
+        if self.mode == ProcessingMode::Live && event.timestamp >= previous {
+            // Events stamped ahead of the wall clock don't move "now" forward
+            let lag = chrono::Utc::now().timestamp() - event.timestamp;
+            self.lag.store(lag.max(0), Ordering::Relaxed);
+        }
+    }
+
+    /// Current event time, or None in replay mode before any event was
+    /// observed
+    pub fn now(&self) -> Option<i64> {
+        match self.mode {
+            ProcessingMode::Live => Some(chrono::Utc::now().timestamp() - self.lag.load(Ordering::Relaxed)),
+            ProcessingMode::Replay => Some(self.latest_event.load(Ordering::Relaxed)).filter(|ts| *ts != NO_EVENT),
+        }
+    }
+
+    /// Time an event is considered received at
+    ///
+    /// In replay mode this is the event's own timestamp.
+    pub fn received_at(&self, event: &LogEvent) -> i64 {
+        match self.mode {
+            ProcessingMode::Live => chrono::Utc::now().timestamp(),
+            ProcessingMode::Replay => event.timestamp,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EscalationConfig;
+    use crate::detection::{LoginRateLimiter, SeverityEscalator};
+    use crate::input::classify::SSH_FAILED;
+    use crate::models::AnomalyReport;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    /// Mid-November 2023, long before any test run
+    const REPLAY_START: i64 = 1700000000;
+
+    fn create_event(user: &str, timestamp: i64) -> LogEvent {
+        LogEvent {
+            timestamp,
+            user: user.to_string(),
+            ip_address: IpAddr::from_str("203.0.113.5").unwrap(),
+            event_type: SSH_FAILED.to_string(),
+            host: None,
+        }
+    }
+
+    /// Limiter that reports when an exceeded limit clears
+    fn limiter() -> LoginRateLimiter {
+        LoginRateLimiter::with_config(300, 3, 100).with_alert_on_resolve(true)
+    }
+
+    /// Feed an attack through the limiter, observing each event on the clock
+    fn replay_attack(clock: &PipelineClock, limiter: &mut LoginRateLimiter) {
+        for i in 0..5 {
+            let event = create_event("alice", REPLAY_START + i);
+            clock.observe(&event);
+            limiter.check_rate_limit(&event);
+        }
+    }
+
+    #[test]
+    fn test_replay_now_follows_events() {
+        let clock = PipelineClock::new(ProcessingMode::Replay);
+        assert_eq!(clock.now(), None);
+
+        clock.observe(&create_event("alice", REPLAY_START + 10));
+        clock.observe(&create_event("bob", REPLAY_START));
+        assert_eq!(clock.now(), Some(REPLAY_START + 10));
+        assert_eq!(clock.received_at(&create_event("bob", REPLAY_START)), REPLAY_START);
+    }
+
+    #[test]
+    fn test_live_now_trails_late_events() {
+        let clock = PipelineClock::new(ProcessingMode::Live);
+        let wall = chrono::Utc::now().timestamp();
+        assert!((clock.now().unwrap() - wall).abs() <= 1);
+
+        // Logs arriving ten minutes late hold "now" ten minutes back
+        clock.observe(&create_event("alice", wall - 600));
+        assert!((clock.now().unwrap() - (wall - 600)).abs() <= 1);
+
+        // but an event stamped in the future doesn't move it past the wall clock
+        clock.observe(&create_event("bob", wall + 3600));
+        assert!((clock.now().unwrap() - wall).abs() <= 1);
+    }
+
+    #[test]
+    fn test_replay_cooldown_uses_event_time() {
+        let clock = PipelineClock::new(ProcessingMode::Replay);
+        let mut limiter = limiter();
+        replay_attack(&clock, &mut limiter);
+
+        // Periodic maintenance right after the attack: still inside the window
+        assert!(limiter.check_resolved(clock.now().unwrap()).is_empty());
+
+        // Only once the replayed timeline moves past the window does it clear
+        clock.observe(&create_event("bob", REPLAY_START + 400));
+        let cleared = limiter.check_resolved(clock.now().unwrap());
+        assert_eq!(cleared.len(), 1);
+        assert_eq!(cleared[0].rule_name, "Rate Limit Condition Cleared");
+        assert_eq!(cleared[0].timestamp, REPLAY_START + 400);
+    }
+
+    #[test]
+    fn test_live_cooldown_uses_event_time_of_late_logs() {
+        // Logs arriving long after they were written (a forwarder catching
+        // up) don't clear their conditions on the first maintenance tick
+        let clock = PipelineClock::new(ProcessingMode::Live);
+        let mut limiter = limiter();
+        replay_attack(&clock, &mut limiter);
+        assert!(limiter.check_resolved(clock.now().unwrap()).is_empty());
+
+        clock.observe(&create_event("bob", REPLAY_START + 400));
+        assert_eq!(limiter.check_resolved(clock.now().unwrap()).len(), 1);
+    }
+
+    #[test]
+    fn test_replay_burst_merge_uses_event_time() {
+        let clock = PipelineClock::new(ProcessingMode::Replay);
+        let mut limiter = limiter().with_merge_window(Some(60), None);
+        replay_attack(&clock, &mut limiter);
+
+        assert!(limiter.flush_bursts(clock.now().unwrap()).is_empty());
+        // The burst opened with the fifth attempt
+        clock.observe(&create_event("bob", REPLAY_START + 4 + 60));
+        assert_eq!(limiter.flush_bursts(clock.now().unwrap()).len(), 1);
+    }
+
+    #[test]
+    fn test_live_escalation_counts_repeats_of_late_logs() {
+        // Logs two hours late: against the wall clock the first report
+        // would be pruned before its repeat arrives
+        let clock = PipelineClock::new(ProcessingMode::Live);
+        let mut escalator = SeverityEscalator::new(&EscalationConfig {
+            window_seconds: 3600,
+            escalation_step: 1,
+            escalation_cap: 9,
+        });
+        let start = chrono::Utc::now().timestamp() - 7200;
+
+        let mut severities = Vec::new();
+        for i in 0..2 {
+            let event = create_event("alice", start + i * 600);
+            clock.observe(&event);
+            escalator.prune_stale(clock.now().unwrap());
+            let mut report =
+                AnomalyReport::new(6, "Login From Hosting Provider", "alice", event.timestamp, "Test anomaly");
+            escalator.apply(&mut report);
+            severities.push(report.severity);
+        }
+        assert_eq!(severities, vec![6, 7]);
+    }
+}
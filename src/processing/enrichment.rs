@@ -0,0 +1,511 @@
+//! Event enrichment pipeline
+//!
+//! Geolocation, ASN, reverse DNS and similar lookups add context about
+//! where an event came from. Rather than each rule looking that up on its
+//! own, an [`EnrichmentPipeline`] runs its stages once per event, in
+//! configured order, before detection. Every rule then reads the same
+//! [`EnrichedEvent`], and a stage can build on what earlier stages added.
+//!
+//! Stages are lazy: the built-in ones attach their lookup to the event,
+//! and the lookup runs the first time something reads the result, at most
+//! once per event. An event no rule asks about costs no database traversal.
+//! Each result is an [`Enrichment`], which tells "no stage provides this"
+//! apart from "looked up, not found".
+
+use crate::config::EnrichmentStage;
+use crate::geolocation::{AsnInfo, AsnLookup, GeoLookup, IpGeo, ReverseDnsEnricher};
+use crate::models::LogEvent;
+use crate::telemetry::EventTrace;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+/// Result of one kind of enrichment for an event
+#[derive(Debug, Clone, PartialEq)]
+pub enum Enrichment<T> {
+    /// No stage provides this data
+    NotRun,
+    /// Looked up, but the source doesn't know the address
+    NotFound,
+    /// The looked up data
+    Found(T),
+}
+
+impl<T> Enrichment<T> {
+    /// The data, if found
+    pub fn found(&self) -> Option<&T> {
+        match self {
+            Enrichment::Found(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl<T> From<Option<T>> for Enrichment<T> {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Enrichment::NotFound, Enrichment::Found)
+    }
+}
+
+/// A lookup deferred until its result is first read
+type Source<T> = Arc<dyn Fn() -> Enrichment<T> + Send + Sync>;
+
+/// One kind of enrichment: a deferred lookup and its result once run
+struct Lazy<T> {
+    source: Option<Source<T>>,
+    value: OnceLock<Enrichment<T>>,
+}
+
+impl<T> Lazy<T> {
+    fn new() -> Self {
+        Lazy {
+            source: None,
+            value: OnceLock::new(),
+        }
+    }
+
+    fn get(&self) -> &Enrichment<T> {
+        self.value
+            .get_or_init(|| self.source.as_ref().map_or(Enrichment::NotRun, |source| source()))
+    }
+
+    /// Defer to `source`, replacing anything set before
+    fn defer(&mut self, source: Source<T>) {
+        self.source = Some(source);
+        self.value = OnceLock::new();
+    }
+
+    fn set(&mut self, value: Enrichment<T>) {
+        self.source = None;
+        self.value = OnceLock::from(value);
+    }
+
+    fn is_provided(&self) -> bool {
+        self.source.is_some() || self.value.get().is_some()
+    }
+}
+
+impl<T: Clone> Clone for Lazy<T> {
+    fn clone(&self) -> Self {
+        Lazy {
+            source: self.source.clone(),
+            value: self.value.clone(),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Lazy<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.value.get() {
+            Some(value) => value.fmt(f),
+            None if self.source.is_some() => f.write_str("Pending"),
+            None => f.write_str("NotRun"),
+        }
+    }
+}
+
+/// A log event plus everything enrichment learned about it
+#[derive(Clone)]
+pub struct EnrichedEvent {
+    pub event: LogEvent,
+    geo: Lazy<IpGeo>,
+    asn: Lazy<AsnInfo>,
+    reverse_dns: Option<Arc<ReverseDnsEnricher>>,
+}
+
+impl fmt::Debug for EnrichedEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EnrichedEvent")
+            .field("event", &self.event)
+            .field("geo", &self.geo)
+            .field("asn", &self.asn)
+            .field("reverse_dns", &self.reverse_dns.is_some())
+            .finish()
+    }
+}
+
+impl EnrichedEvent {
+    /// Wrap an event with nothing enriched yet
+    pub fn new(event: LogEvent) -> Self {
+        EnrichedEvent {
+            event,
+            geo: Lazy::new(),
+            asn: Lazy::new(),
+            reverse_dns: None,
+        }
+    }
+
+    /// Geolocation of the source address, looked up on first call
+    ///
+    /// Once looked up it is `Found` even for an unknown address; the
+    /// [`IpGeo`] records what the database knew and whether it failed.
+    pub fn geo(&self) -> &Enrichment<IpGeo> {
+        self.geo.get()
+    }
+
+    /// Whether a stage provides geolocation (without looking it up)
+    pub fn has_geo(&self) -> bool {
+        self.geo.is_provided()
+    }
+
+    /// Set the geolocation
+    pub fn set_geo(&mut self, geo: Enrichment<IpGeo>) {
+        self.geo.set(geo);
+    }
+
+    /// Defer the geolocation to a lookup run on first read
+    pub fn defer_geo(&mut self, lookup: impl Fn() -> Enrichment<IpGeo> + Send + Sync + 'static) {
+        self.geo.defer(Arc::new(lookup));
+    }
+
+    /// Autonomous system of the source address, looked up on first call
+    pub fn asn(&self) -> &Enrichment<AsnInfo> {
+        self.asn.get()
+    }
+
+    /// Set the autonomous system
+    pub fn set_asn(&mut self, asn: Enrichment<AsnInfo>) {
+        self.asn.set(asn);
+    }
+
+    /// Defer the autonomous system to a lookup run on first read
+    pub fn defer_asn(&mut self, lookup: impl Fn() -> Enrichment<AsnInfo> + Send + Sync + 'static) {
+        self.asn.defer(Arc::new(lookup));
+    }
+
+    /// Hostname of the source address, resolved (and cached by the
+    /// resolver) on each call
+    pub async fn hostname(&self) -> Enrichment<String> {
+        match &self.reverse_dns {
+            Some(enricher) => enricher.resolve(self.event.ip_address).await.into(),
+            None => Enrichment::NotRun,
+        }
+    }
+
+    /// The reverse DNS enricher attached by its stage, for resolving
+    /// addresses in reports raised for this event
+    pub fn reverse_dns(&self) -> Option<&Arc<ReverseDnsEnricher>> {
+        self.reverse_dns.as_ref()
+    }
+}
+
+/// One enrichment stage
+pub trait Enricher: Send + Sync {
+    /// Short name of the stage, used to label its trace span
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Add this stage's data, or a deferred lookup of it, to the event
+    fn enrich(&self, event: &mut EnrichedEvent);
+}
+
+/// Looks up the source address's geolocation
+pub struct GeoEnricher {
+    lookup: Arc<dyn GeoLookup>,
+}
+
+impl GeoEnricher {
+    pub fn new(lookup: Arc<dyn GeoLookup>) -> Self {
+        GeoEnricher { lookup }
+    }
+}
+
+impl Enricher for GeoEnricher {
+    fn name(&self) -> &str {
+        "geo"
+    }
+
+    fn enrich(&self, event: &mut EnrichedEvent) {
+        let lookup = self.lookup.clone();
+        let ip = event.event.ip_address;
+        event.defer_geo(move || Enrichment::Found(lookup.lookup_geo(&ip)));
+    }
+}
+
+/// Looks up the source address's autonomous system
+pub struct AsnEnricher {
+    lookup: Arc<dyn AsnLookup>,
+}
+
+impl AsnEnricher {
+    pub fn new(lookup: Arc<dyn AsnLookup>) -> Self {
+        AsnEnricher { lookup }
+    }
+}
+
+impl Enricher for AsnEnricher {
+    fn name(&self) -> &str {
+        "asn"
+    }
+
+    fn enrich(&self, event: &mut EnrichedEvent) {
+        let lookup = self.lookup.clone();
+        let ip = event.event.ip_address;
+        event.defer_asn(move || lookup.lookup_asn(&ip).into());
+    }
+}
+
+/// Resolves hostnames (PTR records) of the source address and of the
+/// addresses in reports raised for the event
+///
+/// Lookups are asynchronous, so nothing is resolved during the pipeline;
+/// the stage attaches its resolver for [`EnrichedEvent::hostname`] and the
+/// report path.
+pub struct ReverseDnsStage {
+    enricher: Arc<ReverseDnsEnricher>,
+}
+
+impl ReverseDnsStage {
+    pub fn new(enricher: Arc<ReverseDnsEnricher>) -> Self {
+        ReverseDnsStage { enricher }
+    }
+}
+
+impl Enricher for ReverseDnsStage {
+    fn name(&self) -> &str {
+        "reverse_dns"
+    }
+
+    fn enrich(&self, event: &mut EnrichedEvent) {
+        event.reverse_dns = Some(self.enricher.clone());
+    }
+}
+
+/// Ordered enrichment stages run on every event before detection
+#[derive(Default)]
+pub struct EnrichmentPipeline {
+    stages: Vec<Box<dyn Enricher>>,
+    /// The reverse DNS stage's resolver, when configured
+    reverse_dns: Option<Arc<ReverseDnsEnricher>>,
+}
+
+impl EnrichmentPipeline {
+    /// Create a pipeline without stages
+    pub fn new() -> Self {
+        EnrichmentPipeline::default()
+    }
+
+    /// Build the configured stages from the available lookup backends
+    ///
+    /// Stages whose backend isn't available are skipped with a warning.
+    pub fn from_config(
+        stages: &[EnrichmentStage],
+        geo: Option<Arc<dyn GeoLookup>>,
+        asn: Option<Arc<dyn AsnLookup>>,
+        reverse_dns: Option<Arc<ReverseDnsEnricher>>,
+    ) -> Self {
+        let mut pipeline = EnrichmentPipeline::new();
+        for stage in stages {
+            match stage {
+                EnrichmentStage::Geo => match &geo {
+                    Some(lookup) => pipeline = pipeline.with_stage(GeoEnricher::new(lookup.clone())),
+                    None => log::warn!("Geo enrichment configured but no GeoIP database is loaded"),
+                },
+                EnrichmentStage::Asn => match &asn {
+                    Some(lookup) => pipeline = pipeline.with_stage(AsnEnricher::new(lookup.clone())),
+                    None => log::debug!("ASN enrichment skipped: no ASN database is configured"),
+                },
+                EnrichmentStage::ReverseDns => match &reverse_dns {
+                    Some(enricher) => pipeline = pipeline.with_reverse_dns(enricher.clone()),
+                    None => log::debug!("Reverse DNS enrichment skipped: detection.reverse_dns is disabled"),
+                },
+            }
+        }
+        pipeline
+    }
+
+    /// Append a stage; stages run in the order they were added
+    pub fn with_stage(mut self, stage: impl Enricher + 'static) -> Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    /// Append a reverse DNS stage resolving through `enricher`
+    pub fn with_reverse_dns(mut self, enricher: Arc<ReverseDnsEnricher>) -> Self {
+        self.reverse_dns = Some(enricher.clone());
+        self.with_stage(ReverseDnsStage::new(enricher))
+    }
+
+    /// The reverse DNS stage's resolver, if the pipeline has one
+    pub fn reverse_dns(&self) -> Option<&Arc<ReverseDnsEnricher>> {
+        self.reverse_dns.as_ref()
+    }
+
+    /// Number of stages
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    /// Run every stage on an event
+    pub fn run(&self, event: LogEvent) -> EnrichedEvent {
+        self.run_traced(event, &EventTrace::default())
+    }
+
+    /// Run every stage on an event, each in a child span of its trace
+    pub fn run_traced(&self, event: LogEvent, trace: &EventTrace) -> EnrichedEvent {
+        let mut enriched = EnrichedEvent::new(event);
+        for stage in &self.stages {
+            let _span = trace.span("enrich", stage.name());
+            stage.enrich(&mut enriched);
+        }
+        enriched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ReverseDnsConfig;
+    use crate::detection::GeoLocation;
+    use crate::geolocation::{CityInfo, LookupFuture, PtrLookup, ReverseResolver};
+    use std::net::IpAddr;
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Places every address in London
+    struct LondonLookup;
+
+    impl GeoLookup for LondonLookup {
+        fn lookup_geo(&self, _ip: &IpAddr) -> IpGeo {
+            IpGeo {
+                location: Some(GeoLocation { latitude: 51.5074, longitude: -0.1278 }),
+                city: Some(CityInfo {
+                    city_name: Some("London".to_string()),
+                    country_name: Some("United Kingdom".to_string()),
+                    country_code: Some("GB".to_string()),
+                    latitude: 51.5074,
+                    longitude: -0.1278,
+                    timezone: Some("Europe/London".to_string()),
+                    accuracy_radius: Some(20),
+                }),
+                failed: false,
+            }
+        }
+    }
+
+    /// Puts every address on one hosting AS, or none, and counts lookups
+    #[derive(Default)]
+    struct CountingAsnLookup {
+        unknown: bool,
+        lookups: AtomicUsize,
+    }
+
+    impl AsnLookup for CountingAsnLookup {
+        fn lookup_asn(&self, _ip: &IpAddr) -> Option<AsnInfo> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            (!self.unknown).then(|| AsnInfo {
+                number: 14061,
+                organization: Some("DIGITALOCEAN-ASN".to_string()),
+            })
+        }
+    }
+
+    /// Resolves every address to one hostname
+    struct FixedResolver;
+
+    impl ReverseResolver for FixedResolver {
+        fn reverse_lookup(&self, _ip: IpAddr) -> LookupFuture<'_> {
+            Box::pin(async { PtrLookup::Found("host.example.net".to_string()) })
+        }
+    }
+
+    /// Marks events from hosting networks, using the ASN stage's result
+    struct HostingFlag;
+
+    impl Enricher for HostingFlag {
+        fn enrich(&self, event: &mut EnrichedEvent) {
+            if event.asn().found().is_some() {
+                event.event.event_type = format!("{}_FROM_HOSTING", event.event.event_type);
+            }
+        }
+    }
+
+    fn create_event() -> LogEvent {
+        LogEvent {
+            timestamp: 1700000000,
+            user: "alice".to_string(),
+            ip_address: IpAddr::from_str("203.0.113.5").unwrap(),
+            event_type: "SSH_LOGIN".to_string(),
+            host: None,
+        }
+    }
+
+    #[test]
+    fn test_stages_accumulate() {
+        let pipeline = EnrichmentPipeline::new()
+            .with_stage(GeoEnricher::new(Arc::new(LondonLookup)))
+            .with_stage(AsnEnricher::new(Arc::new(CountingAsnLookup::default())));
+
+        let enriched = pipeline.run(create_event());
+        assert_eq!(enriched.event.user, "alice");
+        let geo = enriched.geo().found().unwrap();
+        assert_eq!(geo.city.as_ref().unwrap().country_code.as_deref(), Some("GB"));
+        assert_eq!(geo.location.unwrap().latitude, 51.5074);
+        assert_eq!(enriched.asn().found().unwrap().number, 14061);
+    }
+
+    #[test]
+    fn test_stages_run_in_order() {
+        // A stage sees what earlier stages added, not what later ones will
+        let asn_first = EnrichmentPipeline::new()
+            .with_stage(AsnEnricher::new(Arc::new(CountingAsnLookup::default())))
+            .with_stage(HostingFlag);
+        assert_eq!(asn_first.run(create_event()).event.event_type, "SSH_LOGIN_FROM_HOSTING");
+
+        let flag_first = EnrichmentPipeline::new()
+            .with_stage(HostingFlag)
+            .with_stage(AsnEnricher::new(Arc::new(CountingAsnLookup::default())));
+        assert_eq!(flag_first.run(create_event()).event.event_type, "SSH_LOGIN");
+    }
+
+    #[test]
+    fn test_lookups_are_lazy_and_run_once() {
+        let lookup = Arc::new(CountingAsnLookup {
+            unknown: true,
+            ..CountingAsnLookup::default()
+        });
+        let pipeline = EnrichmentPipeline::new().with_stage(AsnEnricher::new(lookup.clone()));
+
+        // Nothing reads the ASN: no lookup
+        let enriched = pipeline.run(create_event());
+        assert_eq!(lookup.lookups.load(Ordering::SeqCst), 0);
+
+        // A miss is remembered as not found rather than looked up again
+        assert_eq!(*enriched.asn(), Enrichment::NotFound);
+        assert_eq!(*enriched.asn(), Enrichment::NotFound);
+        assert_eq!(lookup.lookups.load(Ordering::SeqCst), 1);
+
+        // Without a stage the data was never looked for
+        assert_eq!(*EnrichedEvent::new(create_event()).asn(), Enrichment::NotRun);
+    }
+
+    #[test]
+    fn test_from_config_skips_missing_backends() {
+        let stages = [EnrichmentStage::Geo, EnrichmentStage::Asn, EnrichmentStage::ReverseDns];
+        let pipeline =
+            EnrichmentPipeline::from_config(&stages, None, Some(Arc::new(CountingAsnLookup::default())), None);
+        assert_eq!(pipeline.len(), 1);
+        assert!(pipeline.reverse_dns().is_none());
+
+        let enriched = pipeline.run(create_event());
+        assert!(!enriched.has_geo());
+        assert!(matches!(enriched.geo(), Enrichment::NotRun));
+        assert!(matches!(enriched.asn(), Enrichment::Found(_)));
+    }
+
+    #[tokio::test]
+    async fn test_reverse_dns_stage() {
+        let enricher = Arc::new(ReverseDnsEnricher::new(&ReverseDnsConfig::default(), Arc::new(FixedResolver)));
+        let pipeline =
+            EnrichmentPipeline::from_config(&[EnrichmentStage::ReverseDns], None, None, Some(enricher));
+        assert!(pipeline.reverse_dns().is_some());
+
+        let enriched = pipeline.run(create_event());
+        assert_eq!(enriched.hostname().await, Enrichment::Found("host.example.net".to_string()));
+        assert_eq!(EnrichedEvent::new(create_event()).hostname().await, Enrichment::NotRun);
+    }
+}
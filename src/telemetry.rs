@@ -0,0 +1,341 @@
+//! OpenTelemetry tracing of event processing
+//!
+//! With the `otel` feature built in and `[telemetry] enabled = true`, each
+//! processed event becomes a trace: a `process_event` root span with a
+//! child span per enrichment stage, per rule that ran and per report
+//! handled, exported over OTLP/HTTP to a collector. That shows where the
+//! time per event goes without sprinkling timers through the rules.
+//!
+//! Only `sample_ratio` of events are traced, and the user and source IP
+//! are recorded on the root span only with `record_identities` set.
+//!
+//! Without the feature, or with telemetry disabled, every type here is a
+//! no-op, so callers instrument unconditionally.
+
+use thiserror::Error;
+use crate::config::TelemetryConfig;
+use crate::models::LogEvent;
+
+#[cfg(feature = "otel")]
+use opentelemetry::{
+    trace::{Span as _, TraceContextExt, Tracer as _, TracerProvider as _},
+    Context, KeyValue,
+};
+#[cfg(feature = "otel")]
+use opentelemetry_sdk::trace::{Sampler, SdkTracer, SdkTracerProvider, Span};
+
+/// Name of the root span of each event
+pub const EVENT_SPAN_NAME: &str = "process_event";
+
+/// Errors setting up trace export
+#[derive(Debug, Error)]
+pub enum TelemetryError {
+    #[error("telemetry is enabled but this build lacks the otel feature")]
+    NotCompiled,
+
+    #[error("failed to create OTLP exporter: {0}")]
+    Exporter(String),
+}
+
+/// Starts a trace for each processed event
+#[derive(Clone, Default)]
+pub struct EventTracer {
+    #[cfg(feature = "otel")]
+    inner: Option<(SdkTracerProvider, SdkTracer)>,
+    /// Record user and source IP on root spans
+    record_identities: bool,
+}
+
+impl EventTracer {
+    /// A tracer that records nothing
+    pub fn disabled() -> Self {
+        EventTracer::default()
+    }
+
+    /// Export traces to the configured collector, if enabled
+    ///
+    /// Fails if telemetry is enabled in a build without the `otel` feature.
+    pub fn from_config(config: &TelemetryConfig) -> Result<Self, TelemetryError> {
+        if !config.enabled {
+            return Ok(EventTracer::disabled());
+        }
+        #[cfg(feature = "otel")]
+        {
+            use opentelemetry_otlp::WithExportConfig;
+
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_http()
+                .with_endpoint(config.otlp_endpoint.clone())
+                .build()
+                .map_err(|e| TelemetryError::Exporter(e.to_string()))?;
+            let resource = opentelemetry_sdk::Resource::builder()
+                .with_service_name(config.service_name.clone())
+                .build();
+            let provider = SdkTracerProvider::builder()
+                .with_batch_exporter(exporter)
+                .with_resource(resource)
+                .with_sampler(Sampler::TraceIdRatioBased(config.sample_ratio))
+                .build();
+            Ok(EventTracer::with_provider(provider).with_identities(config.record_identities))
+        }
+        #[cfg(not(feature = "otel"))]
+        {
+            Err(TelemetryError::NotCompiled)
+        }
+    }
+
+    /// Record spans through an already built provider
+    #[cfg(feature = "otel")]
+    pub fn with_provider(provider: SdkTracerProvider) -> Self {
+        let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+        EventTracer {
+            inner: Some((provider, tracer)),
+            record_identities: false,
+        }
+    }
+
+    /// Record each event's user and source IP on its root span
+    pub fn with_identities(mut self, enabled: bool) -> Self {
+        self.record_identities = enabled;
+        self
+    }
+
+    /// Whether spans are recorded
+    pub fn is_enabled(&self) -> bool {
+        #[cfg(feature = "otel")]
+        {
+            self.inner.is_some()
+        }
+        #[cfg(not(feature = "otel"))]
+        {
+            false
+        }
+    }
+
+    /// Start the root span of an event; it ends when the trace is dropped
+    pub fn trace_event(&self, event: &LogEvent) -> EventTrace {
+        #[cfg(feature = "otel")]
+        {
+            let cx = self.inner.as_ref().map(|(_, tracer)| {
+                let mut attributes = vec![KeyValue::new("event.type", event.event_type.clone())];
+                if self.record_identities {
+                    attributes.push(KeyValue::new("enduser.id", event.user.clone()));
+                    attributes.push(KeyValue::new("source.address", event.ip_address.to_string()));
+                }
+                let span = tracer.span_builder(EVENT_SPAN_NAME).with_attributes(attributes).start(tracer);
+                Context::new().with_span(span)
+            });
+            EventTrace {
+                tracer: self.inner.as_ref().map(|(_, tracer)| tracer.clone()),
+                cx,
+            }
+        }
+        #[cfg(not(feature = "otel"))]
+        {
+            let _ = (event, self.record_identities);
+            EventTrace::default()
+        }
+    }
+
+    /// Export pending spans and stop the exporter
+    pub fn shutdown(&self) {
+        #[cfg(feature = "otel")]
+        if let Some((provider, _)) = &self.inner {
+            if let Err(e) = provider.shutdown() {
+                log::warn!("Failed to shut down trace export: {}", e);
+            }
+        }
+    }
+}
+
+/// The trace of one event
+#[derive(Default)]
+pub struct EventTrace {
+    #[cfg(feature = "otel")]
+    tracer: Option<SdkTracer>,
+    #[cfg(feature = "otel")]
+    cx: Option<Context>,
+}
+
+impl EventTrace {
+    /// Start a child span named `<kind>.<name>`, e.g. `rule.ip_switch`; it
+    /// ends when dropped
+    pub fn span(&self, kind: &str, name: &str) -> StageSpan {
+        #[cfg(feature = "otel")]
+        {
+            let span = match (&self.tracer, &self.cx) {
+                (Some(tracer), Some(cx)) => Some(tracer.start_with_context(format!("{}.{}", kind, name), cx)),
+                _ => None,
+            };
+            StageSpan { span }
+        }
+        #[cfg(not(feature = "otel"))]
+        {
+            let _ = (kind, name);
+            StageSpan::default()
+        }
+    }
+}
+
+impl Drop for EventTrace {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(cx) = &self.cx {
+            cx.span().end();
+        }
+    }
+}
+
+/// A span for one step of processing an event
+#[derive(Default)]
+pub struct StageSpan {
+    #[cfg(feature = "otel")]
+    span: Option<Span>,
+}
+
+impl StageSpan {
+    /// Note how many reports the step raised
+    pub fn record_reports(&mut self, count: usize) {
+        #[cfg(feature = "otel")]
+        if let Some(span) = &mut self.span {
+            span.set_attribute(KeyValue::new("odin.reports", count as i64));
+        }
+        #[cfg(not(feature = "otel"))]
+        let _ = count;
+    }
+}
+
+#[cfg(all(test, feature = "otel"))]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::detection::DetectionEngine;
+    use crate::processing::{EnrichedEvent, Enricher, EnrichmentPipeline};
+    use opentelemetry::trace::SpanId;
+    use opentelemetry_sdk::error::OTelSdkResult;
+    use opentelemetry_sdk::trace::{SpanData, SpanExporter};
+    use std::net::IpAddr;
+    use std::str::FromStr;
+    use std::sync::{Arc, Mutex};
+
+    /// Keeps finished spans for inspection
+    #[derive(Debug, Clone, Default)]
+    struct CollectingExporter {
+        spans: Arc<Mutex<Vec<SpanData>>>,
+    }
+
+    impl CollectingExporter {
+        fn finished_spans(&self) -> Vec<SpanData> {
+            self.spans.lock().unwrap().clone()
+        }
+    }
+
+    impl SpanExporter for CollectingExporter {
+        async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+            self.spans.lock().unwrap().extend(batch);
+            Ok(())
+        }
+    }
+
+    struct NoopEnricher;
+
+    impl Enricher for NoopEnricher {
+        fn name(&self) -> &str {
+            "noop"
+        }
+
+        fn enrich(&self, _event: &mut EnrichedEvent) {}
+    }
+
+    fn create_tracer(sampler: Sampler) -> (EventTracer, CollectingExporter) {
+        let exporter = CollectingExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .with_sampler(sampler)
+            .build();
+        (EventTracer::with_provider(provider), exporter)
+    }
+
+    fn create_event() -> LogEvent {
+        LogEvent {
+            timestamp: 1700000000,
+            user: "alice".to_string(),
+            ip_address: IpAddr::from_str("203.0.113.5").unwrap(),
+            event_type: "SSH_LOGIN".to_string(),
+            host: None,
+        }
+    }
+
+    fn find<'a>(spans: &'a [SpanData], name: &str) -> &'a SpanData {
+        spans
+            .iter()
+            .find(|span| span.name == name)
+            .unwrap_or_else(|| panic!("no span named {}", name))
+    }
+
+    #[test]
+    fn test_event_spans_recorded() {
+        let (tracer, exporter) = create_tracer(Sampler::AlwaysOn);
+        let pipeline = EnrichmentPipeline::new().with_stage(NoopEnricher);
+        let mut engine = DetectionEngine::new(&Config::default().detection)
+            .unwrap()
+            .with_tracer(tracer.clone());
+
+        {
+            let trace = tracer.trace_event(&create_event());
+            pipeline.run_traced(create_event(), &trace);
+        }
+        engine.evaluate(&create_event());
+
+        let spans = exporter.finished_spans();
+        let roots: Vec<&SpanData> = spans.iter().filter(|span| span.name == EVENT_SPAN_NAME).collect();
+        assert_eq!(roots.len(), 2);
+        assert!(roots.iter().all(|root| root.parent_span_id == SpanId::INVALID));
+
+        // Stage and rule spans hang off their event's root
+        let stage = find(&spans, "enrich.noop");
+        assert_eq!(stage.parent_span_id, roots[0].span_context.span_id());
+        let rule = find(&spans, "rule.ip_switch");
+        assert_eq!(rule.parent_span_id, roots[1].span_context.span_id());
+        assert!(rule.attributes.iter().any(|kv| kv.key.as_str() == "odin.reports"));
+
+        // Disabled rules get no span
+        assert!(!spans.iter().any(|span| span.name == "rule.hosting_asn"));
+    }
+
+    #[test]
+    fn test_identities_recorded_only_when_enabled() {
+        let has_user = |exporter: &CollectingExporter| {
+            exporter
+                .finished_spans()
+                .iter()
+                .any(|span| span.attributes.iter().any(|kv| kv.key.as_str() == "enduser.id"))
+        };
+
+        let (tracer, exporter) = create_tracer(Sampler::AlwaysOn);
+        drop(tracer.trace_event(&create_event()));
+        assert!(!has_user(&exporter));
+
+        let (tracer, exporter) = create_tracer(Sampler::AlwaysOn);
+        drop(tracer.with_identities(true).trace_event(&create_event()));
+        assert!(has_user(&exporter));
+    }
+
+    #[test]
+    fn test_unsampled_events_export_nothing() {
+        let (tracer, exporter) = create_tracer(Sampler::TraceIdRatioBased(0.0));
+        let trace = tracer.trace_event(&create_event());
+        drop(trace.span("rule", "ip_switch"));
+        drop(trace);
+        assert!(exporter.finished_spans().is_empty());
+    }
+
+    #[test]
+    fn test_disabled_tracer_records_nothing() {
+        let tracer = EventTracer::from_config(&TelemetryConfig::default()).unwrap();
+        assert!(!tracer.is_enabled());
+        let trace = tracer.trace_event(&create_event());
+        let mut span = trace.span("rule", "ip_switch");
+        span.record_reports(1);
+    }
+}